@@ -0,0 +1,94 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Example usage of the C bindings of the light client.
+//
+// Reads a chain specification from the path passed as parameter, connects to this chain, and
+// prints the new best blocks as they are reported by the light client.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+#include "smoldot.h"
+
+static char *read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (!file)
+        return NULL;
+
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    fseek(file, 0, SEEK_SET);
+
+    char *content = malloc(len + 1);
+    if (content && fread(content, 1, len, file) != (size_t)len) {
+        free(content);
+        content = NULL;
+    }
+    if (content)
+        content[len] = '\0';
+
+    fclose(file);
+    return content;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "Usage: %s <chain-spec.json>\n", argv[0]);
+        return 1;
+    }
+
+    char *chain_spec = read_file(argv[1]);
+    if (!chain_spec) {
+        fprintf(stderr, "Failed to read %s\n", argv[1]);
+        return 1;
+    }
+
+    SmoldotClient *client = smoldot_client_new(3);
+
+    uint32_t chain_id;
+    char *error = smoldot_add_chain(client, chain_spec, NULL, NULL, 0, true, NULL, NULL, &chain_id);
+    free(chain_spec);
+    if (error) {
+        fprintf(stderr, "Failed to add chain: %s\n", error);
+        smoldot_string_free(error);
+        smoldot_client_destroy(client);
+        return 1;
+    }
+
+    if (smoldot_json_rpc_send(client, chain_id,
+            "{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"chain_subscribeNewHeads\",\"params\":[]}") != 0) {
+        fprintf(stderr, "Failed to send JSON-RPC request\n");
+        smoldot_remove_chain(client, chain_id);
+        smoldot_client_destroy(client);
+        return 1;
+    }
+
+    // For the sake of simplicity, this example polls the queue of responses periodically rather
+    // than passing a callback to `smoldot_add_chain`.
+    for (;;) {
+        char *response = smoldot_json_rpc_next_response(client, chain_id);
+        if (!response) {
+            usleep(100 * 1000);
+            continue;
+        }
+
+        printf("JSON-RPC response: %s\n", response);
+        smoldot_string_free(response);
+    }
+}
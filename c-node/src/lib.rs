@@ -0,0 +1,575 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! C bindings to the light client.
+//!
+//! This library exposes the light client of the `smoldot-light` crate through a C ABI, in order
+//! for it to be usable from languages other than Rust. Connections and background tasks are
+//! driven by the `async-std` runtime, using the
+//! [`AsyncStdTcpWebSocket`](smoldot_light::platform::async_std::AsyncStdTcpWebSocket)
+//! platform.
+//!
+//! A C header declaring the functions of this library is generated when building the library,
+//! and is written to `smoldot.h` in the output directory of the build script.
+//!
+//! # Usage
+//!
+//! - Call [`smoldot_client_new`] in order to initialize a client.
+//! - Call [`smoldot_add_chain`] in order to connect to a chain.
+//! - Send JSON-RPC requests using [`smoldot_json_rpc_send`], and retrieve the JSON-RPC
+//!   responses and notifications using [`smoldot_json_rpc_next_response`]. A callback can be
+//!   passed to [`smoldot_add_chain`] in order to be notified when responses are available.
+//! - Call [`smoldot_remove_chain`] then [`smoldot_client_destroy`] when done.
+//!
+//! Functions that accept a chain identifier never panic if this identifier is invalid. The
+//! behaviour in that situation is documented for each function.
+//!
+//! # Strings
+//!
+//! All the strings passed to the functions of this library must be NUL-terminated and UTF-8
+//! encoded.
+//!
+//! All the strings returned by the functions of this library must later be freed by calling
+//! [`smoldot_string_free`].
+//!
+//! # Threads
+//!
+//! The functions of this library can be called from any thread, and concurrently.
+//!
+//! The callback passed to [`smoldot_add_chain`] is called from a background thread. Its
+//! implementation isn't allowed to call any function of this library. Instead, it should for
+//! example notify another thread, which then calls [`smoldot_json_rpc_next_response`].
+//!
+//! Once [`smoldot_remove_chain`] or [`smoldot_client_destroy`] has returned, the callback of
+//! the concerned chains is guaranteed to not be called anymore, and the user data passed
+//! alongside with it can be freed.
+
+#![deny(rustdoc::broken_intra_doc_links)]
+#![deny(unused_crate_dependencies)]
+
+use smoldot_light::{platform::async_std::AsyncStdTcpWebSocket, HandleRpcError};
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_void, CStr, CString},
+    future::{self, Future},
+    pin::Pin,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+};
+
+/// Maximum number of JSON-RPC responses that are queued for each chain. Once this limit is
+/// reached, the responses are left within the JSON-RPC service, which stops processing
+/// requests, until [`smoldot_json_rpc_next_response`] is called.
+const MAX_QUEUED_JSON_RPC_RESPONSES: usize = 64;
+
+/// Light client. Holds a list of chains.
+///
+/// Created using [`smoldot_client_new`] and destroyed using [`smoldot_client_destroy`].
+pub struct SmoldotClient {
+    inner: Mutex<Client>,
+
+    /// Background tasks spawned by the client. Stopped when the client is destroyed.
+    background_tasks: Arc<BackgroundTasks>,
+}
+
+struct Client {
+    smoldot: smoldot_light::Client<AsyncStdTcpWebSocket>,
+
+    /// List of chains that have been added. Indices in this container are the identifiers
+    /// reported through the public API.
+    chains: slab::Slab<Chain>,
+}
+
+struct Chain {
+    smoldot_chain_id: smoldot_light::ChainId,
+
+    /// Queue of JSON-RPC responses of this chain, and background task that fills this queue.
+    /// `None` if the JSON-RPC service of this chain has been disabled.
+    json_rpc_responses: Option<(Arc<JsonRpcResponses>, async_std::task::JoinHandle<()>)>,
+}
+
+/// Keeps track of the background tasks spawned by the client, in order to be able to stop them
+/// when the client is destroyed.
+struct BackgroundTasks {
+    /// Set to `true` when the client is destroyed. Background tasks stop the next time they are
+    /// polled.
+    shutdown: AtomicBool,
+
+    /// Notified when [`BackgroundTasks::shutdown`] is set to `true`.
+    on_shutdown: event_listener::Event,
+
+    /// Number of background tasks that haven't been destroyed yet.
+    num_alive: AtomicUsize,
+
+    /// Notified whenever [`BackgroundTasks::num_alive`] is decreased.
+    on_task_destroyed: event_listener::Event,
+}
+
+impl BackgroundTasks {
+    /// Spawns a background task that stops when [`BackgroundTasks::shutdown_and_wait`] is
+    /// called.
+    fn spawn(self: &Arc<Self>, mut task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        /// Decreases [`BackgroundTasks::num_alive`] when destroyed.
+        struct AliveGuard(Arc<BackgroundTasks>);
+        impl Drop for AliveGuard {
+            fn drop(&mut self) {
+                self.0.num_alive.fetch_sub(1, Ordering::SeqCst);
+                self.0.on_task_destroyed.notify(usize::MAX);
+            }
+        }
+
+        // The counter is increased before spawning, so that a task spawned by another task
+        // can't be missed by `shutdown_and_wait`.
+        self.num_alive.fetch_add(1, Ordering::SeqCst);
+        let guard = AliveGuard(self.clone());
+
+        async_std::task::spawn(async move {
+            let mut on_shutdown = guard.0.on_shutdown.listen();
+            future::poll_fn(|cx| {
+                if guard.0.shutdown.load(Ordering::SeqCst) {
+                    return Poll::Ready(());
+                }
+                if task.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(());
+                }
+                Pin::new(&mut on_shutdown).poll(cx)
+            })
+            .await;
+
+            // The task must be destroyed before the guard.
+            drop(task);
+            drop(guard);
+        });
+    }
+
+    /// Stops all the background tasks, then blocks the current thread until all of them have
+    /// been destroyed.
+    fn shutdown_and_wait(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.on_shutdown.notify(usize::MAX);
+
+        loop {
+            let listener = self.on_task_destroyed.listen();
+            if self.num_alive.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            listener.wait();
+        }
+    }
+}
+
+/// JSON-RPC responses that have been pulled from the client, but not from the public API yet.
+struct JsonRpcResponses {
+    queue: Mutex<VecDeque<String>>,
+
+    /// Notified whenever an element is removed from [`JsonRpcResponses::queue`].
+    on_pop: event_listener::Event,
+}
+
+/// Function called when the queue of JSON-RPC responses of a chain becomes non-empty.
+///
+/// The first parameter is the user data that was passed to [`smoldot_add_chain`]. The second
+/// parameter is the identifier of the chain.
+///
+/// This function is called from a background thread, and isn't allowed to call any function of
+/// this library.
+pub type SmoldotJsonRpcResponsesCallback = Option<unsafe extern "C" fn(*mut c_void, u32)>;
+
+/// Wraps around the callback passed to [`smoldot_add_chain`] and its user data in order to send
+/// them to a background task.
+struct JsonRpcResponsesNotifier {
+    callback: SmoldotJsonRpcResponsesCallback,
+    user_data: *mut c_void,
+    chain_id: u32,
+}
+
+// The user of the library is responsible for ensuring that the callback and user data can be
+// used from any thread. See the documentation of `smoldot_add_chain`.
+unsafe impl Send for JsonRpcResponsesNotifier {}
+
+impl JsonRpcResponsesNotifier {
+    fn notify(&self) {
+        if let Some(callback) = self.callback {
+            unsafe { callback(self.user_data, self.chain_id) }
+        }
+    }
+}
+
+/// Initializes a new client.
+///
+/// `max_log_level` indicates which logs to print on the standard error output. 0 disables
+/// logging. 1 prints errors, 2 warnings, 3 information, 4 debug, and 5 everything.
+///
+/// > **Note**: Logging can only be initialized once per process. The value of `max_log_level`
+/// >           is ignored if a client has been created in the past.
+///
+/// The returned pointer must later be passed to [`smoldot_client_destroy`].
+#[no_mangle]
+pub extern "C" fn smoldot_client_new(max_log_level: u32) -> *mut SmoldotClient {
+    let _ = env_logger::Builder::new()
+        .filter_level(match max_log_level {
+            0 => log::LevelFilter::Off,
+            1 => log::LevelFilter::Error,
+            2 => log::LevelFilter::Warn,
+            3 => log::LevelFilter::Info,
+            4 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        })
+        .try_init();
+
+    let background_tasks = Arc::new(BackgroundTasks {
+        shutdown: AtomicBool::new(false),
+        on_shutdown: event_listener::Event::new(),
+        num_alive: AtomicUsize::new(0),
+        on_task_destroyed: event_listener::Event::new(),
+    });
+
+    let smoldot = smoldot_light::Client::new(smoldot_light::ClientConfig {
+        tasks_spawner: Box::new({
+            let background_tasks = background_tasks.clone();
+            move |_name, task| background_tasks.spawn(task)
+        }),
+        system_name: env!("CARGO_PKG_NAME").into(),
+        system_version: env!("CARGO_PKG_VERSION").into(),
+    });
+
+    Box::into_raw(Box::new(SmoldotClient {
+        inner: Mutex::new(Client {
+            smoldot,
+            chains: slab::Slab::with_capacity(8),
+        }),
+        background_tasks,
+    }))
+}
+
+/// Destroys a client previously created with [`smoldot_client_new`]. All its chains are removed.
+///
+/// This function blocks until all the background tasks of the client have stopped.
+///
+/// # Safety
+///
+/// `client` must have been returned by [`smoldot_client_new`], and must not be used anymore
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn smoldot_client_destroy(client: *mut SmoldotClient) {
+    let SmoldotClient {
+        inner,
+        background_tasks,
+    } = *Box::from_raw(client);
+    let Client {
+        mut smoldot,
+        chains,
+    } = inner.into_inner().unwrap();
+
+    for (_, chain) in chains {
+        remove_chain(&mut smoldot, chain);
+    }
+
+    drop(smoldot);
+    background_tasks.shutdown_and_wait();
+}
+
+/// Adds a chain to the client.
+///
+/// `chain_spec` is the JSON document containing the specification of the chain.
+/// `database_content` is either `NULL` or a string containing a database that was previously
+/// returned by the `chainHead_unstable_finalizedDatabase` JSON-RPC function.
+///
+/// If the chain is a parachain, `potential_relay_chains` must point to a list of
+/// `potential_relay_chains_len` identifiers of chains previously added to the same client, in
+/// which the relay chain is searched. `potential_relay_chains` can be `NULL` if
+/// `potential_relay_chains_len` is 0.
+///
+/// If `json_rpc_enabled` is `false`, no JSON-RPC service is started for this chain, and
+/// [`smoldot_json_rpc_send`] and [`smoldot_json_rpc_next_response`] must not be called with
+/// this chain.
+///
+/// If `json_rpc_responses_callback` isn't `NULL`, it is called with
+/// `json_rpc_responses_callback_user_data` every time the queue of JSON-RPC responses of the
+/// chain goes from empty to non-empty.
+///
+/// On success, writes the identifier of the newly-added chain to `chain_id_out` and returns
+/// `NULL`. On failure, returns an error message that must be freed with
+/// [`smoldot_string_free`].
+///
+/// # Safety
+///
+/// `client` must have been returned by [`smoldot_client_new`]. The strings must be valid
+/// NUL-terminated strings, and `potential_relay_chains` must point to
+/// `potential_relay_chains_len` elements. `chain_id_out` must point to a writable `uint32_t`.
+///
+/// The callback and its user data must be safe to use from any thread.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn smoldot_add_chain(
+    client: *mut SmoldotClient,
+    chain_spec: *const c_char,
+    database_content: *const c_char,
+    potential_relay_chains: *const u32,
+    potential_relay_chains_len: usize,
+    json_rpc_enabled: bool,
+    json_rpc_responses_callback: SmoldotJsonRpcResponsesCallback,
+    json_rpc_responses_callback_user_data: *mut c_void,
+    chain_id_out: *mut u32,
+) -> *mut c_char {
+    let mut client = (*client).inner.lock().unwrap();
+    let client = &mut *client;
+
+    let Ok(chain_spec) = CStr::from_ptr(chain_spec).to_str() else {
+        return error_string("Chain specification isn't valid UTF-8");
+    };
+
+    let database_content = if database_content.is_null() {
+        ""
+    } else {
+        match CStr::from_ptr(database_content).to_str() {
+            Ok(c) => c,
+            Err(_) => return error_string("Database content isn't valid UTF-8"),
+        }
+    };
+
+    // Identifiers that don't correspond to any chain are simply ignored.
+    let potential_relay_chains = if potential_relay_chains_len == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(potential_relay_chains, potential_relay_chains_len)
+            .iter()
+            .filter_map(|id| client.chains.get(usize::try_from(*id).ok()?))
+            .map(|chain| chain.smoldot_chain_id)
+            .collect::<Vec<_>>()
+    };
+
+    let chain_entry = client.chains.vacant_entry();
+    let chain_id = u32::try_from(chain_entry.key()).unwrap();
+
+    let smoldot_light::AddChainSuccess {
+        chain_id: smoldot_chain_id,
+        json_rpc_responses,
+    } = match client.smoldot.add_chain(smoldot_light::AddChainConfig {
+        user_data: (),
+        specification: chain_spec,
+        database_content,
+        disable_json_rpc: !json_rpc_enabled,
+        telemetry_node_name: None,
+        potential_relay_chains: potential_relay_chains.into_iter(),
+    }) {
+        Ok(success) => success,
+        Err(error) => return error_string(&error.to_string()),
+    };
+
+    // The JSON-RPC responses are pulled by a background task and pushed to a queue, in order to
+    // be able to notify the API user when a response is available.
+    let json_rpc_responses = json_rpc_responses.map(|mut json_rpc_responses| {
+        let responses = Arc::new(JsonRpcResponses {
+            queue: Mutex::new(VecDeque::with_capacity(MAX_QUEUED_JSON_RPC_RESPONSES)),
+            on_pop: event_listener::Event::new(),
+        });
+
+        let notifier = JsonRpcResponsesNotifier {
+            callback: json_rpc_responses_callback,
+            user_data: json_rpc_responses_callback_user_data,
+            chain_id,
+        };
+
+        let task = async_std::task::spawn({
+            let responses = responses.clone();
+            async move {
+                loop {
+                    // Wait for the queue to have some space, in order to not break the
+                    // back-pressure mechanism of the JSON-RPC service.
+                    loop {
+                        let listener = responses.on_pop.listen();
+                        if responses.queue.lock().unwrap().len() < MAX_QUEUED_JSON_RPC_RESPONSES {
+                            break;
+                        }
+                        listener.await;
+                    }
+
+                    // `None` is returned if the chain has been removed.
+                    let Some(response) = json_rpc_responses.next().await else {
+                        break;
+                    };
+
+                    let was_empty = {
+                        let mut queue = responses.queue.lock().unwrap();
+                        queue.push_back(response);
+                        queue.len() == 1
+                    };
+
+                    if was_empty {
+                        notifier.notify();
+                    }
+                }
+            }
+        });
+
+        (responses, task)
+    });
+
+    chain_entry.insert(Chain {
+        smoldot_chain_id,
+        json_rpc_responses,
+    });
+
+    *chain_id_out = chain_id;
+    ptr::null_mut()
+}
+
+/// Removes a chain previously added using [`smoldot_add_chain`].
+///
+/// The JSON-RPC responses of this chain that haven't been retrieved yet are discarded. Once
+/// this function returns, the JSON-RPC responses callback of this chain is guaranteed to not be
+/// called anymore.
+///
+/// Does nothing if `chain_id` isn't a valid chain.
+///
+/// # Safety
+///
+/// `client` must have been returned by [`smoldot_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn smoldot_remove_chain(client: *mut SmoldotClient, chain_id: u32) {
+    let mut client = (*client).inner.lock().unwrap();
+    let client = &mut *client;
+    let Some(chain) = usize::try_from(chain_id)
+        .ok()
+        .and_then(|id| client.chains.try_remove(id))
+    else {
+        return;
+    };
+    remove_chain(&mut client.smoldot, chain);
+}
+
+/// Enqueues a JSON-RPC request towards the given chain.
+///
+/// This function returns:
+/// - 0 on success.
+/// - 1 if the request couldn't be parsed as a valid JSON-RPC request.
+/// - 2 if the chain is currently overloaded with JSON-RPC requests and refuses to queue another
+///   one.
+/// - 3 if `chain_id` isn't a valid chain, or if the chain has been added with
+///   `json_rpc_enabled` equal to `false`.
+///
+/// # Safety
+///
+/// `client` must have been returned by [`smoldot_client_new`], and `request` must be a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn smoldot_json_rpc_send(
+    client: *mut SmoldotClient,
+    chain_id: u32,
+    request: *const c_char,
+) -> u32 {
+    let Ok(request) = CStr::from_ptr(request).to_str() else {
+        return 1;
+    };
+
+    let mut client = (*client).inner.lock().unwrap();
+    let client = &mut *client;
+    let Some(chain) = usize::try_from(chain_id)
+        .ok()
+        .and_then(|id| client.chains.get(id))
+    else {
+        return 3;
+    };
+    if chain.json_rpc_responses.is_none() {
+        return 3;
+    }
+    let smoldot_chain_id = chain.smoldot_chain_id;
+
+    match client.smoldot.json_rpc_request(request, smoldot_chain_id) {
+        Ok(()) => 0,
+        Err(HandleRpcError::MalformedJsonRpc(_)) => 1,
+        Err(HandleRpcError::Overloaded { .. }) => 2,
+    }
+}
+
+/// Removes the first JSON-RPC response or notification from the queue of the given chain and
+/// returns it.
+///
+/// Returns `NULL` if the queue is empty, if `chain_id` isn't a valid chain, or if the chain has
+/// been added with `json_rpc_enabled` equal to `false`. Otherwise, the returned string must be
+/// freed with [`smoldot_string_free`].
+///
+/// # Safety
+///
+/// `client` must have been returned by [`smoldot_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn smoldot_json_rpc_next_response(
+    client: *mut SmoldotClient,
+    chain_id: u32,
+) -> *mut c_char {
+    let client = (*client).inner.lock().unwrap();
+    let Some((responses, _)) = usize::try_from(chain_id)
+        .ok()
+        .and_then(|id| client.chains.get(id))
+        .and_then(|chain| chain.json_rpc_responses.as_ref())
+    else {
+        return ptr::null_mut();
+    };
+
+    let Some(response) = responses.queue.lock().unwrap().pop_front() else {
+        return ptr::null_mut();
+    };
+    responses.on_pop.notify(1);
+
+    // JSON documents never contain any NUL byte, as they're always escaped within strings.
+    CString::new(response).unwrap().into_raw()
+}
+
+/// Frees a string returned by one of the functions of this library. Does nothing if `string` is
+/// `NULL`.
+///
+/// # Safety
+///
+/// `string` must have been returned by one of the functions of this library, and must not be
+/// used anymore afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn smoldot_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Removes the chain from the client, then blocks the current thread until the background task
+/// pulling its JSON-RPC responses has stopped.
+fn remove_chain(smoldot: &mut smoldot_light::Client<AsyncStdTcpWebSocket>, chain: Chain) {
+    let () = smoldot.remove_chain(chain.smoldot_chain_id);
+
+    // The background task pulling the JSON-RPC responses might be waiting for the queue to have
+    // some space. Empty the queue in order for it to notice that the chain has been removed.
+    // Afterwards, wait for the task to end, as it might be in the middle of calling the
+    // callback. Since the callback isn't allowed to call any function of this library, this
+    // can't deadlock.
+    if let Some((responses, task)) = chain.json_rpc_responses {
+        responses.queue.lock().unwrap().clear();
+        responses.on_pop.notify(usize::MAX);
+        async_std::task::block_on(task);
+    }
+}
+
+fn error_string(message: &str) -> *mut c_char {
+    // Error messages can contain user-provided text, such as parts of the chain specification.
+    // NUL bytes are replaced, as they can't be represented in a C string, so that the
+    // conversion below never fails.
+    CString::new(message.replace('\0', "\u{fffd}"))
+        .unwrap()
+        .into_raw()
+}
@@ -0,0 +1,48 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Builds the example C program against the generated header and the library.
+
+#![cfg(unix)]
+
+use std::{env, path::Path, process::Command};
+
+#[test]
+fn c_example_builds() {
+    // Integration tests are found in `target/<profile>/deps`, while the library is found in
+    // `target/<profile>`.
+    let current_exe = env::current_exe().unwrap();
+    let library_dir = current_exe.parent().unwrap().parent().unwrap();
+    let output = env::temp_dir().join(format!("smoldot-c-example-{}", std::process::id()));
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/basic.c"))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg("-L")
+        .arg(library_dir)
+        .arg("-lsmoldot_light_c")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    let _ = std::fs::remove_file(&output);
+    assert!(status.success());
+}
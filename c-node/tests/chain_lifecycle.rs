@@ -0,0 +1,99 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Adds and removes chains through the C API.
+
+use smoldot_light_c::*;
+use std::{
+    ffi::{c_void, CString},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn invalid_chain_id() {
+    unsafe {
+        let client = smoldot_client_new(0);
+        let request =
+            CString::new(r#"{"id":1,"jsonrpc":"2.0","method":"system_name","params":[]}"#).unwrap();
+        assert_eq!(smoldot_json_rpc_send(client, 12, request.as_ptr()), 3);
+        assert!(smoldot_json_rpc_next_response(client, 12).is_null());
+        smoldot_remove_chain(client, 12);
+        smoldot_client_destroy(client);
+    }
+}
+
+#[test]
+fn callback_not_called_after_remove() {
+    unsafe extern "C" fn callback(user_data: *mut c_void, _: u32) {
+        (*(user_data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+    }
+
+    let num_calls = Box::new(AtomicUsize::new(0));
+
+    unsafe {
+        let client = smoldot_client_new(0);
+
+        let chain_spec = CString::new(include_str!(
+            "../../demo-chain-specs/substrate-node-template.json"
+        ))
+        .unwrap();
+        let mut chain_id = 0;
+        let error = smoldot_add_chain(
+            client,
+            chain_spec.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            0,
+            true,
+            Some(callback),
+            &*num_calls as *const AtomicUsize as *mut c_void,
+            &mut chain_id,
+        );
+        assert!(error.is_null());
+
+        let request =
+            CString::new(r#"{"id":1,"jsonrpc":"2.0","method":"system_name","params":[]}"#).unwrap();
+        assert_eq!(smoldot_json_rpc_send(client, chain_id, request.as_ptr()), 0);
+
+        while num_calls.load(Ordering::SeqCst) == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let response = smoldot_json_rpc_next_response(client, chain_id);
+        assert!(!response.is_null());
+        smoldot_string_free(response);
+
+        // Requests that are sent right before the chain is removed can't lead to the callback
+        // being called after the removal.
+        for _ in 0..16 {
+            smoldot_json_rpc_send(client, chain_id, request.as_ptr());
+        }
+        smoldot_remove_chain(client, chain_id);
+        let num_calls_after_remove = num_calls.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(num_calls.load(Ordering::SeqCst), num_calls_after_remove);
+
+        assert_eq!(smoldot_json_rpc_send(client, chain_id, request.as_ptr()), 3);
+        smoldot_client_destroy(client);
+    }
+
+    // The user data can be freed once the chain has been removed.
+    drop(num_calls);
+}
@@ -36,7 +36,7 @@ use smoldot::{
         PeerId,
     },
 };
-use std::{net::SocketAddr, path::PathBuf};
+use std::{borrow::Cow, fs, io, net::SocketAddr, path::PathBuf};
 
 // Note: the doc-comments applied to this struct and its field are visible when the binary is
 // started with `--help`.
@@ -57,6 +57,10 @@ pub enum CliOptionsCommand {
     /// Computes the 64 bits BLAKE2 hash of a string payload and prints the hexadecimal-encoded hash.
     #[command(name = "blake2-64bits-hash")]
     Blake264BitsHash(CliOptionsBlake264Hash),
+    /// Re-executes a block of the local database and prints the storage changes, logs, and
+    /// timings of the execution.
+    #[command(name = "replay-block")]
+    ReplayBlock(CliOptionsReplayBlock),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -76,15 +80,41 @@ pub struct CliOptionsRun {
     /// Ed25519 private key of network identity (as a seed phrase).
     #[arg(long, value_parser = decode_ed25519_private_key)]
     pub libp2p_key: Option<[u8; 32]>,
-    /// `Multiaddr` to listen on.
+    /// `Multiaddr` to listen on (for example `/ip4/0.0.0.0/tcp/30333`,
+    /// `/ip4/0.0.0.0/tcp/30334/ws`, or `/ip4/0.0.0.0/udp/30333/quic-v1`).
     #[arg(long, value_parser = decode_multiaddr)]
     pub listen_addr: Vec<Multiaddr>,
     /// `Multiaddr` of an additional node to try to connect to on startup.
     #[arg(long, value_parser = parse_bootnode)]
     pub additional_bootnode: Vec<Bootnode>,
+    /// `Multiaddr`, ending with `/p2p/...`, of a reserved peer. The node permanently tries to
+    /// stay connected to reserved peers. More reserved peers can be added at runtime through the
+    /// `system_addReservedPeer` JSON-RPC function.
+    #[arg(long, value_parser = parse_bootnode)]
+    pub reserved_peer: Vec<Bootnode>,
+    /// Only accept and maintain connections to reserved peers.
+    #[arg(long)]
+    pub reserved_only: bool,
     /// Bind point of the JSON-RPC server ("none" or `<ip>:<port>`).
     #[arg(long, default_value = "127.0.0.1:9944", value_parser = parse_json_rpc_address)]
     pub json_rpc_address: JsonRpcAddress,
+    /// Which JSON-RPC functions to expose: auto, safe, unsafe. Unsafe functions, such as
+    /// `system_addReservedPeer`, modify the state of the node or are expensive to answer. `auto`
+    /// only exposes them if the JSON-RPC server is bound to a loopback address.
+    #[arg(long, default_value = "auto")]
+    pub json_rpc_methods: JsonRpcMethods,
+    /// Maximum number of simultaneous connections to the JSON-RPC server.
+    #[arg(long, default_value = "64")]
+    pub json_rpc_max_clients: usize,
+    /// Maximum size, in bytes, of a JSON-RPC request, either sent through WebSocket or as the
+    /// body of an HTTP POST request.
+    #[arg(long, default_value = "1048576")]
+    pub json_rpc_max_request_size: usize,
+    /// Origin (for example `https://example.com`) of the web pages allowed to send HTTP requests
+    /// to the JSON-RPC server. Can be passed multiple times. Pass `*` to allow all origins. By
+    /// default, cross-origin requests are refused.
+    #[arg(long)]
+    pub json_rpc_cors_origin: Vec<String>,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     #[arg(long, value_parser = decode_sr25519_private_key)]
     // TODO: also automatically add the same keys through ed25519?
@@ -92,9 +122,28 @@ pub struct CliOptionsRun {
     /// Address of a Jaeger agent to send traces to (hint: port is typically 6831).
     #[arg(long)]
     pub jaeger: Option<SocketAddr>,
+    /// Directory where to write, for each block verified, the list of host functions called by
+    /// the runtime in the Chrome trace event format (`<hash>.json`) and in the folded stacks
+    /// format used by flame graph tools (`<hash>.folded`). A summary is also added to the Jaeger
+    /// span of the verification. Slows down the verification.
+    #[arg(long)]
+    pub runtime_profiling_directory: Option<PathBuf>,
+    /// Bind point of the Prometheus metrics server (`<ip>:<port>`). Metrics are served on the
+    /// `/metrics` path.
+    #[arg(long)]
+    pub prometheus_address: Option<SocketAddr>,
+    /// Name of the node reported to the telemetry servers. Defaults to the node's `PeerId`.
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Report information about the node to the telemetry servers of the chain specification.
+    #[arg(long)]
+    pub telemetry: bool,
     /// Do not load or store anything on disk.
     #[arg(long)]
     pub tmp: bool,
+    /// Syncing strategy when the database is empty: full, warp.
+    #[arg(long, default_value = "full")]
+    pub sync_mode: SyncMode,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -103,6 +152,41 @@ pub struct CliOptionsBlake264Hash {
     pub payload: String,
 }
 
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsReplayBlock {
+    /// Chain the block belongs to ("Polkadot", "Kusama", "Westend", or a file path).
+    #[arg(long, default_value = "polkadot")]
+    pub chain: CliChain,
+    /// Hash of the block of the database to replay. Unless `--state` is passed, its parent must
+    /// be the finalized block of the database.
+    #[arg(value_parser = decode_block_hash, required_unless_present = "header")]
+    pub block_hash: Option<[u8; 32]>,
+    /// Path to a file containing the SCALE-encoded header of the block to replay, as an
+    /// alternative to loading the block from the database.
+    #[arg(long, requires = "body", conflicts_with = "block_hash")]
+    pub header: Option<PathBuf>,
+    /// Path to a file containing the SCALE-encoded body (list of extrinsics) of the block to
+    /// replay.
+    #[arg(long, requires = "header")]
+    pub body: Option<PathBuf>,
+    /// Path to a JSON file containing the storage of the parent of the block, in the same format
+    /// as the `genesis.raw.top` field of chain specifications. Replaces loading the storage of
+    /// the finalized block from the database.
+    #[arg(long, requires = "parent_header")]
+    pub state: Option<PathBuf>,
+    /// Path to a file containing the SCALE-encoded header of the parent of the block. Must be
+    /// passed alongside with `--state`.
+    #[arg(long, requires = "state")]
+    pub parent_header: Option<PathBuf>,
+    /// Path to a Wasm runtime to use instead of the runtime of the parent block. The block is
+    /// then executed with both runtimes and the differences are reported.
+    #[arg(long)]
+    pub runtime: Option<PathBuf>,
+    /// Path to the database. Defaults to the database of the `run` subcommand.
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone)]
 pub enum CliChain {
     Polkadot,
@@ -111,6 +195,22 @@ pub enum CliChain {
     Custom(PathBuf),
 }
 
+impl CliChain {
+    /// Returns the JSON-encoded chain specification of the chain.
+    pub fn chain_spec_json(&self) -> io::Result<Cow<'static, [u8]>> {
+        Ok(match self {
+            CliChain::Polkadot => {
+                (&include_bytes!("../../demo-chain-specs/polkadot.json")[..]).into()
+            }
+            CliChain::Kusama => (&include_bytes!("../../demo-chain-specs/kusama.json")[..]).into(),
+            CliChain::Westend => {
+                (&include_bytes!("../../demo-chain-specs/westend.json")[..]).into()
+            }
+            CliChain::Custom(path) => fs::read(path)?.into(),
+        })
+    }
+}
+
 impl core::str::FromStr for CliChain {
     type Err = core::convert::Infallible;
 
@@ -166,6 +266,24 @@ pub enum Output {
     LogsJson,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum SyncMode {
+    /// Download and execute every block since the genesis.
+    Full,
+    /// Warp sync to the latest finalized block, then download its storage from the network.
+    Warp,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum JsonRpcMethods {
+    /// Expose unsafe functions only if the server is bound to a loopback address.
+    Auto,
+    /// Never expose unsafe functions.
+    Safe,
+    /// Always expose unsafe functions.
+    Unsafe,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonRpcAddress(pub Option<SocketAddr>);
 
@@ -209,3 +327,9 @@ fn decode_sr25519_private_key(phrase: &str) -> Result<[u8; 64], String> {
 fn decode_multiaddr(addr: &str) -> Result<Multiaddr, String> {
     addr.parse::<Multiaddr>().map_err(|err| err.to_string())
 }
+
+fn decode_block_hash(hash: &str) -> Result<[u8; 32], String> {
+    let hash = hash.strip_prefix("0x").unwrap_or(hash);
+    let bytes = hex::decode(hash).map_err(|err| err.to_string())?;
+    <[u8; 32]>::try_from(bytes).map_err(|_| "Block hash must be 32 bytes long".into())
+}
@@ -20,6 +20,7 @@
 #![deny(unused_crate_dependencies)]
 
 mod cli;
+mod replay_block;
 mod run;
 
 fn main() {
@@ -33,5 +34,11 @@ async fn async_main() {
             let hash = blake2_rfc::blake2b::blake2b(8, &[], opt.payload.as_bytes());
             println!("0x{}", hex::encode(hash));
         }
+        cli::CliOptionsCommand::ReplayBlock(opt) => {
+            if let Err(err) = replay_block::replay_block(opt) {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
     }
 }
@@ -0,0 +1,900 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implementation of the `replay-block` subcommand.
+//!
+//! The block to replay is either loaded from the local database or from files containing its
+//! header and body. It is re-executed on top of the storage of its parent, which is either the
+//! storage of the finalized block of the local database or a storage snapshot loaded from a file.
+//! Because the database only stores the storage of the finalized block, only children of the
+//! finalized block can be replayed without a snapshot.
+//!
+//! If a runtime is passed with `--runtime`, the block is executed a second time with this
+//! runtime. Since the runtime verifies at the end of the block execution that the state root it
+//! has calculated matches the one in the header, this second execution is performed by calling
+//! `Core_initialize_block`, `BlockBuilder_apply_extrinsic`, and `BlockBuilder_finalize_block`
+//! rather than `Core_execute_block`, and the storage keys whose value differs between the two
+//! executions are reported.
+
+use crate::cli;
+use smoldot::{
+    chain::chain_information,
+    database::full_sqlite,
+    executor::{self, host, runtime_host, storage_diff},
+    header,
+    trie::TrieEntryVersion,
+    verify::header_body,
+};
+use std::{
+    collections::BTreeMap,
+    fs, io, iter,
+    ops::Bound,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Error that can happen while replaying a block.
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Failed to read the chain specification.
+    #[display(fmt = "Failed to read chain specification: {_0}")]
+    ChainSpecRead(io::Error),
+    /// Failed to decode the chain specification.
+    #[display(fmt = "Failed to decode chain specification: {_0}")]
+    ChainSpecDecode(smoldot::chain_spec::ParseError),
+    /// No `--database` has been passed and the default location couldn't be determined.
+    #[display(fmt = "Failed to determine the location of the database. Please pass `--database`.")]
+    NoDatabasePath,
+    /// Failed to open the database.
+    #[display(fmt = "Failed to open database: {_0}")]
+    DatabaseOpen(full_sqlite::InternalError),
+    /// The database doesn't exist or is empty.
+    #[display(fmt = "Database at {} is empty", "_0.display()")]
+    DatabaseEmpty(PathBuf),
+    /// Error while accessing the database.
+    #[display(fmt = "Error while accessing the database: {_0}")]
+    DatabaseAccess(full_sqlite::AccessError),
+    /// Error while accessing the storage of the finalized block in the database.
+    #[display(fmt = "Error while accessing the finalized storage: {_0}")]
+    FinalizedAccess(full_sqlite::FinalizedAccessError),
+    /// The database contains a storage entry with an invalid trie entry version.
+    #[display(fmt = "Invalid trie entry version in the database: {_0}")]
+    InvalidEntryVersion(u8),
+    /// The block can't be found in the database.
+    #[display(fmt = "Block 0x{} not found in database", "hex::encode(_0)")]
+    UnknownBlock([u8; 32]),
+    /// Failed to read the header or body file of the block.
+    #[display(fmt = "Failed to read block: {_0}")]
+    BlockRead(io::Error),
+    /// The body file of the block isn't a valid SCALE-encoded list of extrinsics.
+    #[display(fmt = "Invalid block body file")]
+    InvalidBody,
+    /// The header of the block is invalid.
+    #[display(fmt = "Invalid block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// The parent of the block isn't the finalized block of the database.
+    #[display(
+        fmt = "The parent of the block isn't the finalized block of the database (0x{}). Only \
+        children of the finalized block can be replayed without passing `--state`.",
+        "hex::encode(_0)"
+    )]
+    ParentNotFinalized([u8; 32]),
+    /// Failed to read the storage snapshot or the header of the parent.
+    #[display(fmt = "Failed to read storage snapshot: {_0}")]
+    SnapshotRead(io::Error),
+    /// The storage snapshot isn't a JSON object of hexadecimal keys and values.
+    #[display(fmt = "Invalid storage snapshot: {_0}")]
+    InvalidSnapshot(String),
+    /// The header passed with `--parent-header` isn't the parent of the block.
+    #[display(fmt = "The header passed with `--parent-header` isn't the parent of the block")]
+    ParentHeaderMismatch,
+    /// Failed to determine the chain information of the parent from the storage snapshot.
+    #[display(fmt = "Failed to build chain information from storage snapshot: {_0}")]
+    ChainInformationBuild(chain_information::build::Error),
+    /// The storage of the parent doesn't contain any runtime.
+    #[display(fmt = "No `:code` found in the parent storage")]
+    MissingRuntimeCode,
+    /// Failed to read the runtime passed with `--runtime`.
+    #[display(fmt = "Failed to read runtime: {_0}")]
+    RuntimeRead(io::Error),
+    /// The `:heappages` value of the parent storage is invalid.
+    #[display(fmt = "Invalid `:heappages` in the parent storage: {_0}")]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Failed to compile the runtime.
+    #[display(fmt = "Failed to compile runtime: {_0}")]
+    RuntimeCompilation(host::NewErr),
+    /// The chain uses a consensus engine that isn't supported.
+    #[display(fmt = "Unsupported consensus engine")]
+    UnknownConsensus,
+    /// The block has failed to execute.
+    #[display(fmt = "Block execution failed: {_0}")]
+    Execution(header_body::Error),
+    /// The block has failed to execute with the runtime passed with `--runtime`.
+    #[display(fmt = "Block execution with the substitute runtime failed: {_0}")]
+    SubstituteExecution(SubstituteExecutionError),
+}
+
+/// Error that can happen while executing the block with the runtime passed with `--runtime`.
+#[derive(Debug, derive_more::Display)]
+pub enum SubstituteExecutionError {
+    /// Failed to start the call to a runtime function.
+    #[display(fmt = "Failed to call {function}: {error}")]
+    Start {
+        function: &'static str,
+        error: host::StartErr,
+    },
+    /// Error during the call to a runtime function.
+    #[display(fmt = "Error while calling {function}: {error}")]
+    Call {
+        function: &'static str,
+        error: runtime_host::ErrorDetail,
+    },
+    /// The runtime considers one of the extrinsics of the block as invalid.
+    #[display(fmt = "Extrinsic #{_0} is invalid")]
+    InvalidExtrinsic(usize),
+    /// `BlockBuilder_finalize_block` has returned an invalid header.
+    #[display(fmt = "Invalid header returned by BlockBuilder_finalize_block: {_0}")]
+    InvalidFinalizedHeader(header::Error),
+}
+
+/// Storage of the parent of the block to replay.
+type Storage = BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>;
+
+/// Runs the `replay-block` subcommand.
+pub fn replay_block(cli_options: cli::CliOptionsReplayBlock) -> Result<(), Error> {
+    let chain_spec = {
+        let json = cli_options
+            .chain
+            .chain_spec_json()
+            .map_err(Error::ChainSpecRead)?;
+        smoldot::chain_spec::ChainSpec::from_json_bytes(&json).map_err(Error::ChainSpecDecode)?
+    };
+    let block_number_bytes = usize::from(chain_spec.block_number_bytes());
+
+    // The database is only necessary if either the block or the parent storage are loaded
+    // from it.
+    let database = if cli_options.block_hash.is_some() || cli_options.state.is_none() {
+        Some(open_database(
+            &chain_spec,
+            cli_options.database,
+            block_number_bytes,
+        )?)
+    } else {
+        None
+    };
+
+    let (block_header, block_body) = match (
+        &cli_options.block_hash,
+        &cli_options.header,
+        &cli_options.body,
+    ) {
+        (Some(block_hash), _, _) => {
+            let database = database.as_ref().unwrap();
+            let header = database
+                .block_scale_encoded_header(block_hash)
+                .map_err(Error::DatabaseAccess)?
+                .ok_or(Error::UnknownBlock(*block_hash))?;
+            let body = database
+                .block_extrinsics(block_hash)
+                .map_err(Error::DatabaseAccess)?
+                .ok_or(Error::UnknownBlock(*block_hash))?
+                .collect::<Vec<_>>();
+            (header, body)
+        }
+        (None, Some(header), Some(body)) => {
+            let header = fs::read(header).map_err(Error::BlockRead)?;
+            let body = decode_body(&fs::read(body).map_err(Error::BlockRead)?)
+                .ok_or(Error::InvalidBody)?;
+            (header, body)
+        }
+        // Guaranteed by the CLI parser.
+        _ => unreachable!(),
+    };
+    let decoded_block_header =
+        header::decode(&block_header, block_number_bytes).map_err(Error::InvalidHeader)?;
+
+    let mut compilation_time = Duration::new(0, 0);
+
+    let (parent_header, parent_storage, parent_runtime, parent_chain_information) =
+        match (&cli_options.state, &cli_options.parent_header) {
+            (Some(state), Some(parent_header)) => {
+                let parent_header = fs::read(parent_header).map_err(Error::SnapshotRead)?;
+                if header::hash_from_scale_encoded_header(&parent_header)
+                    != *decoded_block_header.parent_hash
+                {
+                    return Err(Error::ParentHeaderMismatch);
+                }
+
+                let snapshot = decode_snapshot(&fs::read(state).map_err(Error::SnapshotRead)?)
+                    .map_err(Error::InvalidSnapshot)?;
+
+                let before_compilation = Instant::now();
+                let runtime = compile_runtime(
+                    snapshot
+                        .get(&b":code"[..])
+                        .ok_or(Error::MissingRuntimeCode)?,
+                    snapshot.get(&b":heappages"[..]).map(|v| &v[..]),
+                )?;
+                compilation_time += before_compilation.elapsed();
+
+                // The snapshot doesn't contain the version of each entry. We assume that all of
+                // them use the version of the runtime.
+                let state_version = runtime
+                    .runtime_version()
+                    .decode()
+                    .state_version
+                    .unwrap_or(TrieEntryVersion::V0);
+                let storage = snapshot
+                    .into_iter()
+                    .map(|(key, value)| (key, (value, state_version)))
+                    .collect::<Storage>();
+
+                let (chain_information, runtime) =
+                    build_chain_information(&parent_header, block_number_bytes, &storage, runtime)?;
+                (parent_header, storage, runtime, chain_information)
+            }
+            (None, None) => {
+                let database = database.as_ref().unwrap();
+                let finalized_block_hash = database
+                    .finalized_block_hash()
+                    .map_err(Error::DatabaseAccess)?;
+                if *decoded_block_header.parent_hash != finalized_block_hash {
+                    return Err(Error::ParentNotFinalized(finalized_block_hash));
+                }
+
+                // The entire storage of the parent is loaded in memory, in order to not have to
+                // hold a lock on the database during the execution.
+                let storage = database
+                    .finalized_block_storage_main_trie::<Vec<_>>(&finalized_block_hash)
+                    .map_err(Error::FinalizedAccess)?
+                    .into_iter()
+                    .map(|(key, value, version)| {
+                        let version = TrieEntryVersion::try_from(version)
+                            .map_err(|()| Error::InvalidEntryVersion(version))?;
+                        Ok((key, (value, version)))
+                    })
+                    .collect::<Result<Storage, Error>>()?;
+                let chain_information = database
+                    .to_chain_information(&finalized_block_hash)
+                    .map_err(Error::FinalizedAccess)?;
+                let parent_header = database
+                    .block_scale_encoded_header(&finalized_block_hash)
+                    .map_err(Error::DatabaseAccess)?
+                    .ok_or(Error::UnknownBlock(finalized_block_hash))?;
+
+                let before_compilation = Instant::now();
+                let runtime = compile_runtime(
+                    &storage
+                        .get(&b":code"[..])
+                        .ok_or(Error::MissingRuntimeCode)?
+                        .0,
+                    storage.get(&b":heappages"[..]).map(|(v, _)| &v[..]),
+                )?;
+                compilation_time += before_compilation.elapsed();
+
+                (parent_header, storage, runtime, chain_information)
+            }
+            // Guaranteed by the CLI parser.
+            _ => unreachable!(),
+        };
+
+    let substitute_runtime = match &cli_options.runtime {
+        Some(path) => {
+            let code = fs::read(path).map_err(Error::RuntimeRead)?;
+            let before_compilation = Instant::now();
+            let runtime = compile_runtime(
+                &code,
+                parent_storage.get(&b":heappages"[..]).map(|(v, _)| &v[..]),
+            )?;
+            compilation_time += before_compilation.elapsed();
+            Some(runtime)
+        }
+        None => None,
+    };
+
+    let parent_chain_information = parent_chain_information.as_ref();
+    let consensus = match &parent_chain_information.consensus {
+        chain_information::ChainInformationConsensusRef::Unknown => {
+            return Err(Error::UnknownConsensus)
+        }
+        chain_information::ChainInformationConsensusRef::Aura {
+            finalized_authorities_list,
+            slot_duration,
+        } => header_body::ConfigConsensus::Aura {
+            current_authorities: finalized_authorities_list.clone(),
+            slot_duration: *slot_duration,
+        },
+        chain_information::ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_block_epoch_information,
+            finalized_next_epoch_transition,
+        } => header_body::ConfigConsensus::Babe {
+            slots_per_epoch: *slots_per_epoch,
+            parent_block_epoch: finalized_block_epoch_information.clone(),
+            parent_block_next_epoch: finalized_next_epoch_transition.clone(),
+        },
+    };
+
+    let before_execution = Instant::now();
+
+    let mut verify = header_body::verify(header_body::Config {
+        parent_runtime,
+        parent_block_header: header::decode(&parent_header, block_number_bytes)
+            .map_err(Error::InvalidHeader)?,
+        consensus,
+        allow_unknown_consensus_engines: true,
+        now_from_unix_epoch: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::new(0, 0)),
+        block_header: decoded_block_header.clone(),
+        block_number_bytes,
+        block_body: block_body.iter(),
+        main_trie_root_calculation_cache: None,
+        max_log_level: 5,
+        host_functions_profiler: None,
+    });
+
+    // Time spent answering the storage requests of the runtime, included in the total execution
+    // time.
+    let mut storage_access_time = Duration::new(0, 0);
+    let mut num_storage_accesses = 0u64;
+
+    let success = loop {
+        let before_access = Instant::now();
+        verify = match verify {
+            header_body::Verify::Finished(Ok(success)) => break success,
+            header_body::Verify::Finished(Err((err, _))) => return Err(Error::Execution(err)),
+            header_body::Verify::RuntimeCompilation(rt) => {
+                // Compiling the new runtime isn't a storage access.
+                rt.build()
+            }
+            header_body::Verify::StorageGet(req) => {
+                let value = storage_get(&parent_storage, req.key().as_ref());
+                storage_access_time += before_access.elapsed();
+                num_storage_accesses += 1;
+                req.inject_value(value)
+            }
+            header_body::Verify::StoragePrefixKeys(req) => {
+                let keys = storage_prefix_keys(&parent_storage, req.prefix().as_ref());
+                storage_access_time += before_access.elapsed();
+                num_storage_accesses += 1;
+                req.inject_keys_ordered(keys.into_iter())
+            }
+            header_body::Verify::StorageNextKey(req) => {
+                let key = storage_next_key(&parent_storage, req.key().as_ref());
+                storage_access_time += before_access.elapsed();
+                num_storage_accesses += 1;
+                req.inject_key(key)
+            }
+        };
+    };
+
+    let execution_time = before_execution.elapsed();
+
+    println!("Storage changes:");
+    print_storage_changes(&success.storage_main_trie_changes);
+    println!("State trie version: {:?}", success.state_trie_version);
+    if success.new_runtime.is_some() {
+        println!("The block modifies the runtime");
+    }
+
+    println!("Logs:");
+    for line in success.logs.lines() {
+        println!("  {line}");
+    }
+
+    println!("Timings:");
+    println!("  Runtime compilation: {compilation_time:?}");
+    println!("  Execution (total): {execution_time:?}");
+    println!(
+        "  Including storage accesses ({num_storage_accesses} requests): {storage_access_time:?}"
+    );
+
+    let Some(substitute_runtime) = substitute_runtime else {
+        return Ok(());
+    };
+
+    let before_execution = Instant::now();
+    let substitute = execute_with_substitute_runtime(
+        substitute_runtime,
+        &parent_storage,
+        {
+            // The seal is added by the block author after the block has been built, and must
+            // not be passed to `Core_initialize_block`.
+            let mut unsealed_header = decoded_block_header.clone();
+            let _ = unsealed_header.digest.pop_seal();
+            unsealed_header
+                .scale_encoding(block_number_bytes)
+                .fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                })
+        },
+        &block_body,
+        block_number_bytes,
+    )
+    .map_err(Error::SubstituteExecution)?;
+    let execution_time = before_execution.elapsed();
+
+    println!();
+    println!("Storage changes with the substitute runtime:");
+    print_storage_changes(&substitute.storage_main_trie_changes);
+
+    println!("Logs with the substitute runtime:");
+    for line in substitute.logs.lines() {
+        println!("  {line}");
+    }
+
+    println!("Timings with the substitute runtime:");
+    println!("  Execution (total): {execution_time:?}");
+
+    if substitute.state_root == *decoded_block_header.state_root {
+        println!("The state root obtained with the substitute runtime matches the block header");
+    } else {
+        println!(
+            "State root mismatch: 0x{} in the block header, 0x{} with the substitute runtime",
+            hex::encode(decoded_block_header.state_root),
+            hex::encode(substitute.state_root)
+        );
+    }
+
+    let differences = storage_differences(
+        &parent_storage,
+        &success.storage_main_trie_changes,
+        &substitute.storage_main_trie_changes,
+    );
+    println!(
+        "Keys whose value differs between the two runtimes ({}):",
+        differences.len()
+    );
+    for (key, original, substitute) in differences {
+        println!("  0x{}", hex::encode(key));
+        println!("    original:   {}", format_value(original));
+        println!("    substitute: {}", format_value(substitute));
+    }
+
+    Ok(())
+}
+
+/// Opens the database of the given chain.
+fn open_database(
+    chain_spec: &smoldot::chain_spec::ChainSpec,
+    path: Option<PathBuf>,
+    block_number_bytes: usize,
+) -> Result<full_sqlite::SqliteFullDatabase, Error> {
+    let db_path = match path {
+        Some(path) => path,
+        None => directories::ProjectDirs::from("io", "smoldot", "smoldot")
+            .ok_or(Error::NoDatabasePath)?
+            .data_dir()
+            .join(chain_spec.id())
+            .join("database"),
+    };
+
+    match full_sqlite::open(full_sqlite::Config {
+        block_number_bytes,
+        ty: full_sqlite::ConfigTy::Disk(&db_path),
+    })
+    .map_err(Error::DatabaseOpen)?
+    {
+        full_sqlite::DatabaseOpen::Open(database) => Ok(database),
+        full_sqlite::DatabaseOpen::Empty(_) => Err(Error::DatabaseEmpty(db_path)),
+    }
+}
+
+/// Compiles the given runtime code.
+fn compile_runtime(code: &[u8], heap_pages: Option<&[u8]>) -> Result<host::HostVmPrototype, Error> {
+    let heap_pages =
+        executor::storage_heap_pages_to_value(heap_pages).map_err(Error::InvalidHeapPages)?;
+
+    host::HostVmPrototype::new(host::Config {
+        module: code,
+        heap_pages,
+        exec_hint: executor::vm::ExecHint::Oneshot,
+        resource_limits: Default::default(),
+        allow_unresolved_imports: true,
+    })
+    .map_err(Error::RuntimeCompilation)
+}
+
+/// Determines the chain information of the block whose header is passed as parameter by
+/// calling its runtime.
+fn build_chain_information(
+    scale_encoded_header: &[u8],
+    block_number_bytes: usize,
+    storage: &Storage,
+    runtime: host::HostVmPrototype,
+) -> Result<
+    (
+        chain_information::ValidChainInformation,
+        host::HostVmPrototype,
+    ),
+    Error,
+> {
+    let mut build =
+        chain_information::build::ChainInformationBuild::new(chain_information::build::Config {
+            finalized_block_header:
+                chain_information::build::ConfigFinalizedBlockHeader::NonGenesis {
+                    header: header::decode(scale_encoded_header, block_number_bytes)
+                        .map_err(Error::InvalidHeader)?
+                        .into(),
+                    known_finality: None,
+                },
+            runtime,
+        });
+
+    loop {
+        build = match build {
+            chain_information::build::ChainInformationBuild::Finished {
+                result: Ok(chain_information),
+                virtual_machine,
+            } => break Ok((chain_information, virtual_machine)),
+            chain_information::build::ChainInformationBuild::Finished {
+                result: Err(err), ..
+            } => break Err(Error::ChainInformationBuild(err)),
+            chain_information::build::ChainInformationBuild::InProgress(
+                chain_information::build::InProgress::StorageGet(get),
+            ) => {
+                let value = storage_get(storage, get.key().as_ref());
+                get.inject_value(value.map(|(value, _)| value))
+            }
+            chain_information::build::ChainInformationBuild::InProgress(
+                chain_information::build::InProgress::NextKey(nk),
+            ) => {
+                let key = storage_next_key(storage, nk.key().as_ref());
+                nk.inject_key(key)
+            }
+        };
+    }
+}
+
+/// Outcome of [`execute_with_substitute_runtime`].
+struct SubstituteSuccess {
+    storage_main_trie_changes: storage_diff::TrieDiff,
+    state_root: [u8; 32],
+    logs: String,
+}
+
+/// Executes the block by calling `Core_initialize_block`, then `BlockBuilder_apply_extrinsic`
+/// for each extrinsic, then `BlockBuilder_finalize_block`.
+///
+/// Contrary to `Core_execute_block`, this doesn't fail if the resulting state root doesn't
+/// match the one in the header of the block.
+fn execute_with_substitute_runtime(
+    runtime: host::HostVmPrototype,
+    parent_storage: &Storage,
+    scale_encoded_unsealed_header: Vec<u8>,
+    block_body: &[Vec<u8>],
+    block_number_bytes: usize,
+) -> Result<SubstituteSuccess, SubstituteExecutionError> {
+    let mut runtime = Some(runtime);
+    let mut storage_main_trie_changes = storage_diff::TrieDiff::empty();
+    let mut main_trie_root_calculation_cache = None;
+    let mut logs = String::new();
+
+    let calls =
+        iter::once((
+            "Core_initialize_block",
+            None,
+            &scale_encoded_unsealed_header[..],
+        ))
+        .chain(block_body.iter().enumerate().map(|(index, extrinsic)| {
+            ("BlockBuilder_apply_extrinsic", Some(index), &extrinsic[..])
+        }))
+        .chain(iter::once(("BlockBuilder_finalize_block", None, &[][..])));
+
+    let mut finalized_header = Vec::new();
+
+    for (function, extrinsic_index, parameter) in calls {
+        let mut call = runtime_host::run(runtime_host::Config {
+            virtual_machine: runtime.take().unwrap(),
+            function_to_call: function,
+            parameter: iter::once(parameter),
+            main_trie_root_calculation_cache: main_trie_root_calculation_cache.take(),
+            storage_main_trie_changes,
+            offchain_storage_changes: Default::default(),
+            storage_proof_recorder: None,
+            max_log_level: 5,
+            host_functions_profiler: None,
+        })
+        .map_err(|(error, _)| SubstituteExecutionError::Start { function, error })?;
+
+        let success = loop {
+            call = match call {
+                runtime_host::RuntimeHostVm::Finished(Ok(success)) => break success,
+                runtime_host::RuntimeHostVm::Finished(Err(err)) => {
+                    return Err(SubstituteExecutionError::Call {
+                        function,
+                        error: err.detail,
+                    })
+                }
+                runtime_host::RuntimeHostVm::StorageGet(req) => {
+                    let value = storage_get(parent_storage, req.key().as_ref());
+                    req.inject_value(value)
+                }
+                runtime_host::RuntimeHostVm::PrefixKeys(req) => {
+                    let keys = storage_prefix_keys(parent_storage, req.prefix().as_ref());
+                    req.inject_keys_ordered(keys.into_iter())
+                }
+                runtime_host::RuntimeHostVm::NextKey(req) => {
+                    let key = storage_next_key(parent_storage, req.key().as_ref());
+                    req.inject_key(key)
+                }
+                runtime_host::RuntimeHostVm::SignatureVerification(req) => req.verify_and_resume(),
+            };
+        };
+
+        // The output of `BlockBuilder_apply_extrinsic` is a `Result` whose error indicates that
+        // the extrinsic is invalid. Extrinsics that are valid but whose dispatch fails are
+        // included in blocks and return `Ok`.
+        if let Some(extrinsic_index) = extrinsic_index {
+            if success.virtual_machine.value().as_ref().first() != Some(&0) {
+                return Err(SubstituteExecutionError::InvalidExtrinsic(extrinsic_index));
+            }
+        } else if function == "BlockBuilder_finalize_block" {
+            finalized_header = success.virtual_machine.value().as_ref().to_vec();
+        }
+
+        storage_main_trie_changes = success.storage_main_trie_changes;
+        main_trie_root_calculation_cache = Some(success.main_trie_root_calculation_cache);
+        logs.push_str(&success.logs);
+        runtime = Some(success.virtual_machine.into_prototype());
+    }
+
+    let state_root = *header::decode(&finalized_header, block_number_bytes)
+        .map_err(SubstituteExecutionError::InvalidFinalizedHeader)?
+        .state_root;
+
+    Ok(SubstituteSuccess {
+        storage_main_trie_changes,
+        state_root,
+        logs,
+    })
+}
+
+fn storage_get<'a>(
+    storage: &'a Storage,
+    key: &[u8],
+) -> Option<(iter::Once<&'a [u8]>, TrieEntryVersion)> {
+    storage
+        .get(key)
+        .map(|(value, version)| (iter::once(&value[..]), *version))
+}
+
+fn storage_prefix_keys(storage: &Storage, prefix: &[u8]) -> Vec<Vec<u8>> {
+    storage
+        .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+fn storage_next_key(storage: &Storage, key: &[u8]) -> Option<Vec<u8>> {
+    storage
+        .range::<[u8], _>((Bound::Excluded(key), Bound::Unbounded))
+        .next()
+        .map(|(key, _)| key.clone())
+}
+
+fn print_storage_changes(changes: &storage_diff::TrieDiff) {
+    let mut changes = changes.diff_iter_unordered().collect::<Vec<_>>();
+    changes.sort_unstable_by_key(|(key, _, _)| *key);
+
+    println!("  ({} entries)", changes.len());
+    for (key, value, _) in changes {
+        println!("  0x{} => {}", hex::encode(key), format_value(value));
+    }
+}
+
+fn format_value(value: Option<&[u8]>) -> String {
+    match value {
+        Some(value) => format!("0x{}", hex::encode(value)),
+        None => "<none>".to_owned(),
+    }
+}
+
+/// Returns the list of keys whose value after applying `changes_a` on top of `parent_storage`
+/// differs from their value after applying `changes_b`, ordered by key. Each item contains the
+/// key, the value according to `changes_a`, and the value according to `changes_b`.
+fn storage_differences<'a>(
+    parent_storage: &'a Storage,
+    changes_a: &'a storage_diff::TrieDiff,
+    changes_b: &'a storage_diff::TrieDiff,
+) -> Vec<(&'a [u8], Option<&'a [u8]>, Option<&'a [u8]>)> {
+    let value_after = |changes: &'a storage_diff::TrieDiff, key: &[u8]| -> Option<&'a [u8]> {
+        match changes.diff_get(key) {
+            Some((value, _)) => value,
+            None => parent_storage.get(key).map(|(value, _)| &value[..]),
+        }
+    };
+
+    let mut keys = changes_a
+        .diff_iter_unordered()
+        .chain(changes_b.diff_iter_unordered())
+        .map(|(key, _, _)| key)
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let value_a = value_after(changes_a, key);
+            let value_b = value_after(changes_b, key);
+            if value_a != value_b {
+                Some((key, value_a, value_b))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Decodes a SCALE-encoded list of extrinsics. Returns `None` if the encoding is invalid.
+fn decode_body(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let num_extrinsics;
+    (num_extrinsics, bytes) = decode_scale_compact_usize(bytes)?;
+
+    let mut body = Vec::with_capacity(num_extrinsics.min(bytes.len()));
+    for _ in 0..num_extrinsics {
+        let extrinsic_len;
+        (extrinsic_len, bytes) = decode_scale_compact_usize(bytes)?;
+        if bytes.len() < extrinsic_len {
+            return None;
+        }
+        let (extrinsic, rest) = bytes.split_at(extrinsic_len);
+        body.push(extrinsic.to_vec());
+        bytes = rest;
+    }
+
+    if !bytes.is_empty() {
+        return None;
+    }
+
+    Some(body)
+}
+
+/// Decodes a SCALE-compact-encoded number at the start of `bytes`. Returns the number and the
+/// remaining bytes.
+fn decode_scale_compact_usize(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let first = *bytes.first()?;
+    let (num_bytes, value) = match first & 0b11 {
+        0b00 => (1, u64::from(first >> 2)),
+        0b01 => (
+            2,
+            u64::from(u16::from_le_bytes(<[u8; 2]>::try_from(bytes.get(..2)?).ok()?) >> 2),
+        ),
+        0b10 => (
+            4,
+            u64::from(u32::from_le_bytes(<[u8; 4]>::try_from(bytes.get(..4)?).ok()?) >> 2),
+        ),
+        _ => {
+            let len = usize::from(first >> 2) + 4;
+            if len > 8 {
+                return None;
+            }
+            let mut value = [0; 8];
+            value[..len].copy_from_slice(bytes.get(1..1 + len)?);
+            (1 + len, u64::from_le_bytes(value))
+        }
+    };
+
+    Some((usize::try_from(value).ok()?, &bytes[num_bytes..]))
+}
+
+/// Decodes a storage snapshot, in other words a JSON object whose keys and values are
+/// hexadecimal-encoded storage keys and values.
+fn decode_snapshot(json: &[u8]) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, String> {
+    let entries =
+        serde_json::from_slice::<BTreeMap<String, String>>(json).map_err(|err| err.to_string())?;
+
+    let decode_hex = |hex_str: &str| {
+        hex::decode(hex_str.strip_prefix("0x").unwrap_or(hex_str))
+            .map_err(|err| format!("{hex_str}: {err}"))
+    };
+
+    entries
+        .iter()
+        .map(|(key, value)| Ok((decode_hex(key)?, decode_hex(value)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use smoldot::{executor::storage_diff, trie::TrieEntryVersion};
+
+    #[test]
+    fn decode_body_basic() {
+        let body = super::decode_body(&[2 << 2, 2 << 2, 1, 2, 1 << 2, 3]).unwrap();
+        assert_eq!(body, vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn decode_body_empty() {
+        assert_eq!(super::decode_body(&[0]).unwrap(), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn decode_body_truncated() {
+        assert!(super::decode_body(&[2 << 2, 2 << 2, 1, 2]).is_none());
+        assert!(super::decode_body(&[1 << 2, 3 << 2, 1]).is_none());
+    }
+
+    #[test]
+    fn decode_body_trailing_bytes() {
+        assert!(super::decode_body(&[1 << 2, 1 << 2, 1, 0]).is_none());
+    }
+
+    #[test]
+    fn decode_body_large_compact() {
+        // Length of 100 encoded in the two-bytes mode: `(100 << 2) | 0b01`, little endian.
+        let mut encoded = vec![1 << 2, 0x91, 0x01];
+        encoded.extend((0..100).map(|_| 0xaa));
+        assert_eq!(super::decode_body(&encoded).unwrap(), vec![vec![0xaa; 100]]);
+    }
+
+    #[test]
+    fn decode_snapshot_basic() {
+        let snapshot = super::decode_snapshot(br#"{"0x0102": "0x03", "0x04": "0x"}"#).unwrap();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot.get(&[1, 2][..]).unwrap(), &[3]);
+        assert!(snapshot.get(&[4][..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_snapshot_invalid_hex() {
+        assert!(super::decode_snapshot(br#"{"0x0g": "0x03"}"#).is_err());
+    }
+
+    #[test]
+    fn storage_differences_basic() {
+        let mut parent = super::Storage::new();
+        parent.insert(b"unchanged".to_vec(), (b"a".to_vec(), TrieEntryVersion::V0));
+        parent.insert(b"same".to_vec(), (b"a".to_vec(), TrieEntryVersion::V0));
+        parent.insert(b"modified".to_vec(), (b"a".to_vec(), TrieEntryVersion::V0));
+
+        let mut changes_a = storage_diff::TrieDiff::empty();
+        changes_a.diff_insert(b"same".to_vec(), b"b".to_vec(), ());
+        changes_a.diff_insert(b"modified".to_vec(), b"b".to_vec(), ());
+        changes_a.diff_insert(b"only_a".to_vec(), b"b".to_vec(), ());
+
+        let mut changes_b = storage_diff::TrieDiff::empty();
+        changes_b.diff_insert(b"same".to_vec(), b"b".to_vec(), ());
+        changes_b.diff_insert_erase(b"modified".to_vec(), ());
+
+        let differences = super::storage_differences(&parent, &changes_a, &changes_b);
+        assert_eq!(
+            differences,
+            vec![
+                (&b"modified"[..], Some(&b"b"[..]), None),
+                (&b"only_a"[..], Some(&b"b"[..]), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn storage_differences_write_of_parent_value() {
+        let mut parent = super::Storage::new();
+        parent.insert(b"key".to_vec(), (b"a".to_vec(), TrieEntryVersion::V0));
+
+        // Writing the value that was already there isn't a difference.
+        let mut changes_a = storage_diff::TrieDiff::empty();
+        changes_a.diff_insert(b"key".to_vec(), b"a".to_vec(), ());
+        let changes_b = storage_diff::TrieDiff::empty();
+
+        assert!(super::storage_differences(&parent, &changes_a, &changes_b).is_empty());
+    }
+}
@@ -28,6 +28,7 @@ use smoldot::{
         connection, multiaddr,
         peer_id::{self, PeerId},
     },
+    telemetry,
 };
 use std::{
     borrow::Cow,
@@ -38,11 +39,24 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+mod authority_discovery_service;
 mod consensus_service;
 mod database_thread;
 mod jaeger_service;
 mod json_rpc_service;
 mod network_service;
+mod prometheus_service;
+mod telemetry_service;
+
+/// Limits applied when executing the runtime on behalf of an untrusted party, such as a
+/// JSON-RPC client or a peer of the network.
+// TODO: these values are arbitrary and should be adjusted after being measured on real runtimes
+const UNTRUSTED_RUNTIME_CALLS_RESOURCE_LIMITS: executor::vm::ResourceLimits =
+    executor::vm::ResourceLimits {
+        max_fuel: Some(20_000_000_000),
+        max_stack_depth: Some(1024),
+        max_memory_pages: Some(executor::vm::HeapPages::new(4096)),
+    };
 
 /// Runs the node using the given configuration. Catches `SIGINT` signals and stops if one is
 /// detected.
@@ -133,20 +147,10 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     );
 
     let chain_spec = {
-        let json: Cow<[u8]> = match &cli_options.chain {
-            cli::CliChain::Polkadot => {
-                (&include_bytes!("../../demo-chain-specs/polkadot.json")[..]).into()
-            }
-            cli::CliChain::Kusama => {
-                (&include_bytes!("../../demo-chain-specs/kusama.json")[..]).into()
-            }
-            cli::CliChain::Westend => {
-                (&include_bytes!("../../demo-chain-specs/westend.json")[..]).into()
-            }
-            cli::CliChain::Custom(path) => {
-                fs::read(path).expect("Failed to read chain specs").into()
-            }
-        };
+        let json = cli_options
+            .chain
+            .chain_spec_json()
+            .expect("Failed to read chain specs");
 
         smoldot::chain_spec::ChainSpec::from_json_bytes(&json)
             .expect("Failed to decode chain specs")
@@ -279,32 +283,31 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     // Determine which networking key to use.
     //
     // This is either passed as a CLI option, loaded from disk, or generated randomly.
-    let noise_key = if let Some(node_key) = cli_options.libp2p_key {
-        connection::NoiseKey::new(&node_key)
+    let libp2p_key = if let Some(node_key) = cli_options.libp2p_key {
+        node_key
     } else if let Some(dir) = base_storage_directory.as_ref() {
         let path = dir.join("libp2p_ed25519_secret_key.secret");
-        let noise_key = if path.exists() {
+        let libp2p_key = if path.exists() {
             let file_content =
                 fs::read_to_string(&path).expect("failed to read libp2p secret key file content");
             let hex_decoded =
                 hex::decode(file_content).expect("invalid libp2p secret key file content");
-            let actual_key =
-                <[u8; 32]>::try_from(hex_decoded).expect("invalid libp2p secret key file content");
-            connection::NoiseKey::new(&actual_key)
+            <[u8; 32]>::try_from(hex_decoded).expect("invalid libp2p secret key file content")
         } else {
             let actual_key: [u8; 32] = rand::random();
             fs::write(&path, hex::encode(actual_key))
                 .expect("failed to write libp2p secret key file");
-            connection::NoiseKey::new(&actual_key)
+            actual_key
         };
         // On Unix platforms, set the permission as 0o400 (only reading and by owner is permitted).
         // TODO: do something equivalent on Windows
         #[cfg(unix)]
         let _ = fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o400));
-        noise_key
+        libp2p_key
     } else {
-        connection::NoiseKey::new(&rand::random())
+        rand::random()
     };
+    let noise_key = connection::NoiseKey::new(&libp2p_key);
 
     let local_peer_id =
         peer_id::PublicKey::Ed25519(*noise_key.libp2p_public_ed25519_key()).into_peer_id();
@@ -330,6 +333,15 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                 fork_id: chain_spec.fork_id().map(|n| n.to_owned()),
                 block_number_bytes: usize::from(chain_spec.block_number_bytes()),
                 database: database.clone(),
+                routing_table_path: base_storage_directory
+                    .as_ref()
+                    .map(|d| d.join(chain_spec.id()).join("kademlia.json")),
+                reserved_only: cli_options.reserved_only,
+                reserved_peers: cli_options
+                    .reserved_peer
+                    .iter()
+                    .map(|peer| (peer.peer_id.clone(), peer.address.clone()))
+                    .collect(),
                 has_grandpa_protocol: matches!(
                     genesis_chain_information.as_ref().finality,
                     chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
@@ -382,6 +394,12 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                         fork_id: relay_chains_specs.fork_id().map(|n| n.to_owned()),
                         block_number_bytes: usize::from(relay_chains_specs.block_number_bytes()),
                         database: relay_chain_database.clone().unwrap(),
+                        routing_table_path: base_storage_directory
+                            .as_ref()
+                            .map(|d| d.join(relay_chains_specs.id()).join("kademlia.json")),
+                        // Reserved peers can only be configured for the main chain.
+                        reserved_only: false,
+                        reserved_peers: Vec::new(),
                         has_grandpa_protocol: matches!(
                             relay_genesis_chain_information.as_ref().unwrap().as_ref().finality,
                             chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
@@ -434,6 +452,7 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
             )
             .collect(),
             noise_key,
+            libp2p_key,
             tasks_executor: &mut |task| threads_pool.spawn_ok(task),
             jaeger_service: jaeger_service.clone(),
         })
@@ -462,15 +481,23 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         genesis_block_hash,
         network_events_receiver: network_events_receivers.next().unwrap(),
         network_service: (network_service.clone(), 0),
-        database,
+        database: database.clone(),
+        runtime_cache_directory: base_storage_directory
+            .as_ref()
+            .map(|path| path.join(chain_spec.id()).join("runtimes")),
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-        keystore,
+        warp_sync: matches!(cli_options.sync_mode, cli::SyncMode::Warp),
+        keystore: keystore.clone(),
         jaeger_service: jaeger_service.clone(),
+        runtime_profiling_directory: cli_options
+            .runtime_profiling_directory
+            .as_ref()
+            .map(|path| path.join(chain_spec.id())),
         slot_duration_author_ratio: 43691_u16,
     })
     .await;
 
-    let relay_chain_consensus_service = if let Some(relay_chain_database) = relay_chain_database {
+    let relay_chain_consensus_service = if let Some(relay_chain_database) = &relay_chain_database {
         Some(
             consensus_service::ConsensusService::new(consensus_service::Config {
                 tasks_executor: &mut |task| threads_pool.spawn_ok(task),
@@ -484,10 +511,15 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                     )),
                 network_events_receiver: network_events_receivers.next().unwrap(),
                 network_service: (network_service.clone(), 1),
-                database: relay_chain_database,
+                database: relay_chain_database.clone(),
+                runtime_cache_directory: base_storage_directory.as_ref().map(|path| {
+                    path.join(relay_chain_spec.as_ref().unwrap().id())
+                        .join("runtimes")
+                }),
                 block_number_bytes: usize::from(
                     relay_chain_spec.as_ref().unwrap().block_number_bytes(),
                 ),
+                warp_sync: matches!(cli_options.sync_mode, cli::SyncMode::Warp),
                 keystore: Arc::new(
                     keystore::Keystore::new(
                         base_storage_directory
@@ -499,6 +531,10 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                     .unwrap(),
                 ),
                 jaeger_service, // TODO: consider passing a different jaeger service with a different service name
+                runtime_profiling_directory: cli_options
+                    .runtime_profiling_directory
+                    .as_ref()
+                    .map(|path| path.join(relay_chain_spec.as_ref().unwrap().id())),
                 slot_duration_author_ratio: 43691_u16,
             })
             .await,
@@ -507,6 +543,105 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         None
     };
 
+    // Start the authority discovery services of the chain and of its relay chain.
+    // They only need to be kept alive in order to function.
+    let _authority_discovery_services = iter::once((database.clone(), 0))
+        .chain(relay_chain_database.map(|database| (database, 1)))
+        .map(|(database, chain_index)| {
+            authority_discovery_service::AuthorityDiscoveryService::new(
+                authority_discovery_service::Config {
+                    tasks_executor: &mut |task| threads_pool.spawn_ok(task),
+                    network_service: (network_service.clone(), chain_index),
+                    database,
+                    keystore: keystore.clone(),
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Start the telemetry service.
+    // Reports information about the node to the telemetry servers found in the chain
+    // specification.
+    let telemetry_service = {
+        let node_name = cli_options
+            .name
+            .clone()
+            .unwrap_or_else(|| local_peer_id.to_string());
+
+        // Telemetry is opt-in, as it sends information about the node to third-party servers.
+        let endpoints = if !cli_options.telemetry {
+            Vec::new()
+        } else {
+            chain_spec
+                .telemetry_endpoints_with_verbosity()
+                .filter_map(|(address, verbosity)| {
+                    match telemetry::parse_endpoint(address, verbosity) {
+                        Ok(endpoint) => Some(endpoint),
+                        Err(err) => {
+                            log::warn!(
+                                "telemetry-endpoint-parse-error; address={}; error={}",
+                                address,
+                                err
+                            );
+                            None
+                        }
+                    }
+                })
+                .collect()
+        };
+
+        telemetry_service::TelemetryService::new(telemetry_service::Config {
+            tasks_executor: &mut |task| threads_pool.spawn_ok(task),
+            endpoints,
+            system_connected: telemetry::SystemConnected {
+                chain: chain_spec.name(),
+                name: &node_name,
+                implementation: "smoldot-full-node",
+                version: env!("CARGO_PKG_VERSION"),
+                validator: None,
+                network_id: &local_peer_id.to_string(),
+                genesis_hash: &genesis_block_hash,
+                startup_time_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .and_then(|d| u64::try_from(d.as_millis()).ok()),
+                target_os: std::env::consts::OS,
+                target_arch: std::env::consts::ARCH,
+                target_env: if cfg!(target_env = "gnu") {
+                    "gnu"
+                } else if cfg!(target_env = "musl") {
+                    "musl"
+                } else if cfg!(target_env = "msvc") {
+                    "msvc"
+                } else {
+                    ""
+                },
+            },
+        })
+    };
+
+    // Start the Prometheus service.
+    // It only needs to be kept alive in order to function.
+    //
+    // Similar to the JSON-RPC service below, failing to bind the server is a fatal error.
+    let _prometheus_service = if let Some(bind_address) = cli_options.prometheus_address {
+        let result = prometheus_service::PrometheusService::new(prometheus_service::Config {
+            tasks_executor: &mut |task| threads_pool.spawn_ok(task),
+            bind_address,
+            network_service: network_service.clone(),
+            consensus_service: consensus_service.clone(),
+            database: database.clone(),
+        })
+        .await;
+
+        Some(match result {
+            Ok(service) => service,
+            Err(err) => panic!("failed to initialize Prometheus endpoint: {err}"),
+        })
+    } else {
+        None
+    };
+
     // Start the JSON-RPC service.
     // It only needs to be kept alive in order to function.
     //
@@ -518,6 +653,22 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         let result = json_rpc_service::JsonRpcService::new(json_rpc_service::Config {
             tasks_executor: { &mut move |task| threads_pool.spawn_ok(task) },
             bind_address,
+            max_clients: cli_options.json_rpc_max_clients,
+            max_request_size: cli_options.json_rpc_max_request_size,
+            allow_unsafe_methods: match cli_options.json_rpc_methods {
+                cli::JsonRpcMethods::Auto => bind_address.ip().is_loopback(),
+                cli::JsonRpcMethods::Safe => false,
+                cli::JsonRpcMethods::Unsafe => true,
+            },
+            cors_allowed_origins: cli_options.json_rpc_cors_origin.clone(),
+            local_listen_addresses: network_service
+                .listen_addresses()
+                .map(|addr| format!("{addr}/p2p/{local_peer_id}"))
+                .collect(),
+            database: database.clone(),
+            consensus_service: consensus_service.clone(),
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            network_service: (network_service.clone(), 0),
         })
         .await;
 
@@ -529,22 +680,6 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
         None
     };
 
-    /*let mut telemetry = {
-        let endpoints = chain_spec
-            .telemetry_endpoints()
-            .map(|addr| (addr.as_ref().to_owned(), 0))
-            .collect::<Vec<_>>();
-
-        smoldot::telemetry::init_telemetry(smoldot::telemetry::TelemetryConfig {
-            endpoints: smoldot::telemetry::TelemetryEndpoints::new(endpoints).unwrap(),
-            wasm_external_transport: None,
-            tasks_executor: {
-                let threads_pool = threads_pool.clone();
-                Box::new(move |task| threads_pool.spawn_obj_ok(From::from(task))) as Box<_>
-            },
-        })
-    };*/
-
     log::info!(
         "successful-initialization; local_peer_id={}; database_is_new={:?}; \
         finalized_block_hash={}; finalized_block_number={}",
@@ -587,6 +722,8 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
     );
 
     let mut network_known_best = None;
+    let mut telemetry_reported_best = None;
+    let mut telemetry_reported_finalized = None;
     let mut main_network_events_receiver = network_events_receivers.next().unwrap();
     debug_assert!(network_events_receivers.next().is_none());
 
@@ -649,42 +786,38 @@ pub async fn run(cli_options: cli::CliOptionsRun) {
                 }
             }
 
-            /*telemetry_event = telemetry.next_event().fuse() => {
-                telemetry.send(smoldot::telemetry::message::TelemetryMessage::SystemConnected(smoldot::telemetry::message::SystemConnected {
-                    chain: chain_spec.name().to_owned().into_boxed_str(),
-                    name: String::from("✨ Smoldot ✨").into_boxed_str(),  // TODO: node name
-                    implementation: String::from("Secret projet 🤫").into_boxed_str(),  // TODO:
-                    version: String::from(env!("CARGO_PKG_VERSION")).into_boxed_str(),
-                    validator: None,
-                    network_id: None, // TODO: Some(service.local_peer_id().to_base58().into_boxed_str()),
+            _ = telemetry_timer.next() => {
+                let sync_state = consensus_service.sync_state().await;
+
+                telemetry_service.send(telemetry::TelemetryMessage::SystemInterval(telemetry::SystemInterval {
+                    num_peers: u64::try_from(network_service.num_peers(0).await)
+                        .unwrap_or(u64::max_value()),
+                    num_transactions: None,
+                    bandwidth_upload: None,
+                    bandwidth_download: None,
+                    best_block_hash: &sync_state.best_block_hash,
+                    best_block_number: sync_state.best_block_number,
+                    finalized_block_hash: &sync_state.finalized_block_hash,
+                    finalized_block_number: sync_state.finalized_block_number,
                 }));
-            },*/
 
-            _ = telemetry_timer.next() => {
-                /*let sync_state = sync_state.lock().await.clone();
-
-                // Some of the fields below are set to `None` because there is no plan to
-                // implement reporting accurate metrics about the node.
-                telemetry.send(smoldot::telemetry::message::TelemetryMessage::SystemInterval(smoldot::telemetry::message::SystemInterval {
-                    stats: smoldot::telemetry::message::NodeStats {
-                        peers: network_state.num_network_connections.load(Ordering::Relaxed),
-                        txcount: 0,  // TODO:
-                    },
-                    memory: None,
-                    cpu: None,
-                    bandwidth_upload: Some(0.0), // TODO:
-                    bandwidth_download: Some(0.0), // TODO:
-                    finalized_height: Some(sync_state.finalized_block_number),
-                    finalized_hash: Some(sync_state.finalized_block_hash.into()),
-                    block: smoldot::telemetry::message::Block {
-                        hash: sync_state.best_block_hash.into(),
-                        height: sync_state.best_block_number,
-                    },
-                    used_state_cache_size: None,
-                    used_db_cache_size: None,
-                    disk_read_per_sec: None,
-                    disk_write_per_sec: None,
-                }));*/
+                // Blocks are reported to the telemetry servers only at each tick rather than
+                // every time they change, as the full node can import blocks very quickly
+                // while syncing.
+                if telemetry_reported_best != Some(sync_state.best_block_hash) {
+                    telemetry_reported_best = Some(sync_state.best_block_hash);
+                    telemetry_service.send(telemetry::TelemetryMessage::BlockImport(telemetry::BlockImport {
+                        block_hash: &sync_state.best_block_hash,
+                        block_number: sync_state.best_block_number,
+                    }));
+                }
+                if telemetry_reported_finalized != Some(sync_state.finalized_block_hash) {
+                    telemetry_reported_finalized = Some(sync_state.finalized_block_hash);
+                    telemetry_service.send(telemetry::TelemetryMessage::NotifyFinalized(telemetry::NotifyFinalized {
+                        block_hash: &sync_state.finalized_block_hash,
+                        block_number: sync_state.finalized_block_number,
+                    }));
+                }
             },
 
             _ = ctrlc_rx => {
@@ -753,6 +886,7 @@ async fn open_database(
                 )
                 .unwrap(),
                 exec_hint: executor::vm::ExecHint::Oneshot,
+                resource_limits: Default::default(),
                 allow_unresolved_imports: true,
             })
             .unwrap()
@@ -0,0 +1,433 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Authority discovery integration.
+//!
+//! The [`AuthorityDiscoveryService`] spawns a background task that regularly determines the list
+//! of authorities of a chain by calling the runtime of the finalized block. The list is passed
+//! to the [`network_service::NetworkService`], so that remotes can only store the records of
+//! these authorities in the local node.
+//!
+//! The background task then looks up in the Kademlia distributed hash table the records of the
+//! authorities whose addresses aren't known yet, and adds the addresses found to the network
+//! service. It also publishes a record for each authority discovery key of the keystore that
+//! belongs to the list of authorities.
+//!
+//! See the [`smoldot::network::authority_discovery`] module for more information.
+
+use crate::run::{database_thread, network_service};
+
+use futures::prelude::*;
+use futures_timer::Delay;
+use smoldot::{
+    database::full_sqlite,
+    executor::{self, host, runtime_host},
+    identity::keystore,
+    libp2p::{
+        multiaddr::{Multiaddr, ProtocolRef},
+        PeerId,
+    },
+    network::{authority_discovery, protocol},
+    trie::TrieEntryVersion,
+};
+use std::{iter, sync::Arc, time::Duration};
+
+/// Delay between the start of the service and the first round of discovery. Gives the network
+/// service some time to connect to peers.
+const FIRST_ROUND_DELAY: Duration = Duration::from_secs(30);
+
+/// Delay between two rounds of discovery.
+const ROUND_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of peers a record is requested from or published to. Matches the replication
+/// factor of the Kademlia distributed hash table.
+const MAX_TARGETS: usize = 20;
+
+/// Configuration for an [`AuthorityDiscoveryService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
+
+    /// Network service to use to access the distributed hash table, and index of the chain
+    /// within that service.
+    pub network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// Database of the chain. The list of authorities is determined by calling the runtime of
+    /// its finalized block.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Keystore containing the authority discovery keys to publish a record for.
+    pub keystore: Arc<keystore::Keystore>,
+}
+
+pub struct AuthorityDiscoveryService {
+    /// Handle connected to the background task. Makes it possible to abort it.
+    abort_handle: future::AbortHandle,
+}
+
+impl AuthorityDiscoveryService {
+    pub fn new(config: Config<'_>) -> Arc<Self> {
+        let (network_service, chain_index) = config.network_service;
+        let (task, abort_handle) = future::abortable(run_background(
+            network_service,
+            chain_index,
+            config.database,
+            config.keystore,
+        ));
+        (config.tasks_executor)(Box::pin(task.map(|_| ())));
+
+        Arc::new(AuthorityDiscoveryService { abort_handle })
+    }
+}
+
+impl Drop for AuthorityDiscoveryService {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+/// Background task of the service. Never ends.
+async fn run_background(
+    network_service: Arc<network_service::NetworkService>,
+    chain_index: usize,
+    database: Arc<database_thread::DatabaseThread>,
+    keystore: Arc<keystore::Keystore>,
+) {
+    let mut authority_discovery = authority_discovery::AuthorityDiscovery::new();
+    let mut next_round = Delay::new(FIRST_ROUND_DELAY);
+
+    loop {
+        next_round.await;
+        next_round = Delay::new(ROUND_INTERVAL);
+
+        let authorities = match finalized_block_authorities(&database).await {
+            Ok(authorities) => authorities,
+            Err(err) => {
+                // Chains that don't use authority discovery don't have the runtime function.
+                log::debug!(
+                    "authority-discovery-authorities-error; chain_index={}; error={}",
+                    chain_index,
+                    err
+                );
+                continue;
+            }
+        };
+
+        authority_discovery.set_authorities(authorities.iter().copied());
+        network_service
+            .set_authorities(chain_index, authorities)
+            .await;
+
+        // Publish the record of each of the local authority discovery keys.
+        let local_addresses = network_service
+            .listen_addresses()
+            .filter(|address| is_publishable(address))
+            .cloned()
+            .collect::<Vec<_>>();
+        for (namespace, public_key) in keystore.keys().await {
+            if namespace != keystore::KeyNamespace::AuthorityDiscovery
+                || !authority_discovery.is_authority(&public_key)
+            {
+                continue;
+            }
+
+            if local_addresses.is_empty() {
+                log::warn!(
+                    "authority-discovery-no-address; chain_index={}; the record of the local \
+                    authority can't be published because the node doesn't listen on any \
+                    specific address",
+                    chain_index
+                );
+                break;
+            }
+
+            let record = authority_discovery::build_authority_record(
+                network_service.local_peer_id(),
+                local_addresses.iter(),
+            );
+            let signature = match keystore
+                .sign(
+                    keystore::KeyNamespace::AuthorityDiscovery,
+                    &public_key,
+                    &record,
+                )
+                .await
+            {
+                Ok(signature) => signature,
+                Err(err) => {
+                    log::warn!(
+                        "authority-discovery-sign-error; chain_index={}; error={}",
+                        chain_index,
+                        err
+                    );
+                    continue;
+                }
+            };
+            let value = authority_discovery::build_signed_authority_record(&record, &signature);
+
+            let key = authority_discovery::dht_key(&public_key);
+            let num_stored = future::join_all(
+                targets(&network_service, chain_index, &key)
+                    .await
+                    .into_iter()
+                    .map(|target| {
+                        network_service.clone().kademlia_put_value_request(
+                            target,
+                            chain_index,
+                            &key,
+                            &value,
+                        )
+                    }),
+            )
+            .await
+            .into_iter()
+            .filter(|result| result.is_ok())
+            .count();
+
+            log::debug!(
+                "authority-discovery-published; chain_index={}; num_stored={}",
+                chain_index,
+                num_stored
+            );
+        }
+
+        // Look up the records of the authorities whose addresses aren't known yet.
+        let unresolved = authority_discovery
+            .unresolved_authorities()
+            .copied()
+            .collect::<Vec<_>>();
+        for authority in unresolved {
+            let key = authority_discovery::dht_key(&authority);
+            let responses = future::join_all(
+                targets(&network_service, chain_index, &key)
+                    .await
+                    .into_iter()
+                    .map(|target| {
+                        network_service.clone().kademlia_get_value_request(
+                            target,
+                            chain_index,
+                            &key,
+                        )
+                    }),
+            )
+            .await;
+
+            for response in responses {
+                let Ok(protocol::GetValueResponse {
+                    value: Some(value), ..
+                }) = response
+                else {
+                    continue;
+                };
+
+                match authority_discovery.inject_record(&authority, &value) {
+                    Ok(()) => {
+                        let (peer_id, addresses) =
+                            authority_discovery.authority_addresses(&authority).unwrap();
+                        log::debug!(
+                            "authority-discovery-resolved; chain_index={}; peer_id={}",
+                            chain_index,
+                            peer_id
+                        );
+                        network_service
+                            .discover(chain_index, peer_id.clone(), addresses.to_vec())
+                            .await;
+                        break;
+                    }
+                    Err(err) => {
+                        log::debug!(
+                            "authority-discovery-bad-record; chain_index={}; error={}",
+                            chain_index,
+                            err
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the list of peers to send a request concerning the given key to.
+///
+/// Requests can only be sent to peers we are connected to. The peers closest to the key are
+/// prioritized, and the list is completed with the other connected peers.
+async fn targets(
+    network_service: &network_service::NetworkService,
+    chain_index: usize,
+    key: &[u8],
+) -> Vec<PeerId> {
+    let closest_peers = network_service
+        .kademlia_lookup(chain_index, key.to_vec())
+        .await;
+    let connected = network_service.peers_list().await;
+
+    let mut targets = Vec::with_capacity(MAX_TARGETS);
+    for peer_id in closest_peers
+        .into_iter()
+        .map(|(peer_id, _)| peer_id)
+        .filter(|peer_id| connected.contains(peer_id))
+        .chain(connected.iter().cloned())
+    {
+        if targets.len() >= MAX_TARGETS {
+            break;
+        }
+        if !targets.contains(&peer_id) {
+            targets.push(peer_id);
+        }
+    }
+    targets
+}
+
+/// Returns `false` if the address is a wildcard address that remotes can't connect to.
+fn is_publishable(address: &Multiaddr) -> bool {
+    !address.iter().any(|protocol| match protocol {
+        ProtocolRef::Ip4(ip) => ip == [0; 4],
+        ProtocolRef::Ip6(ip) => ip == [0; 16],
+        _ => false,
+    })
+}
+
+/// Calls [`authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME`] on the finalized block of
+/// the database.
+async fn finalized_block_authorities(
+    database: &database_thread::DatabaseThread,
+) -> Result<Vec<[u8; 32]>, AuthoritiesError> {
+    database
+        .with_database(move |database| {
+            let block_hash = database.finalized_block_hash()?;
+
+            let storage_get = |key: &[u8]| {
+                database
+                    .finalized_block_storage_main_trie_get(&block_hash, key)?
+                    .map(|(value, version)| {
+                        TrieEntryVersion::try_from(version)
+                            .map(|version| (value, version))
+                            .map_err(|_| AuthoritiesError::InvalidTrieEntryVersion)
+                    })
+                    .transpose()
+            };
+
+            // TODO: the runtime is compiled again at every round; consider sharing it with the consensus service
+            let virtual_machine = {
+                let (code, _) = storage_get(b":code")?.ok_or(AuthoritiesError::MissingCode)?;
+                let heap_pages = executor::storage_heap_pages_to_value(
+                    storage_get(b":heappages")?
+                        .as_ref()
+                        .map(|(value, _)| &value[..]),
+                )
+                .map_err(AuthoritiesError::InvalidHeapPages)?;
+                host::HostVmPrototype::new(host::Config {
+                    module: code,
+                    heap_pages,
+                    exec_hint: executor::vm::ExecHint::Oneshot,
+                    resource_limits: Default::default(),
+                    allow_unresolved_imports: true,
+                })
+                .map_err(AuthoritiesError::RuntimeBuild)?
+            };
+
+            let mut call = runtime_host::run(runtime_host::Config {
+                virtual_machine,
+                function_to_call: authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME,
+                parameter: iter::empty::<&[u8]>(),
+                main_trie_root_calculation_cache: None,
+                storage_main_trie_changes: Default::default(),
+                offchain_storage_changes: Default::default(),
+                storage_proof_recorder: None,
+                max_log_level: 0,
+                host_functions_profiler: None,
+            })
+            .map_err(|(err, _)| AuthoritiesError::StartError(err))?;
+
+            loop {
+                call = match call {
+                    runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                        break authority_discovery::decode_authorities_output(
+                            success.virtual_machine.value().as_ref(),
+                        )
+                        .map_err(AuthoritiesError::Decode)
+                    }
+                    runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                        break Err(AuthoritiesError::RuntimeError(error.detail))
+                    }
+                    runtime_host::RuntimeHostVm::StorageGet(get) => {
+                        let value = storage_get(get.key().as_ref())?;
+                        get.inject_value(value.map(|(value, version)| (iter::once(value), version)))
+                    }
+                    runtime_host::RuntimeHostVm::PrefixKeys(prefix_keys) => {
+                        let keys = database.finalized_block_storage_main_trie_keys(
+                            &block_hash,
+                            prefix_keys.prefix().as_ref(),
+                        )?;
+                        prefix_keys.inject_keys_ordered(keys.into_iter())
+                    }
+                    runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                        let key = database.finalized_block_storage_main_trie_next_key(
+                            &block_hash,
+                            next_key.key().as_ref(),
+                        )?;
+                        next_key.inject_key(key)
+                    }
+                    runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                        sig.verify_and_resume()
+                    }
+                };
+            }
+        })
+        .await
+}
+
+/// Error potentially returned by [`finalized_block_authorities`].
+#[derive(Debug, derive_more::Display)]
+enum AuthoritiesError {
+    /// Error while accessing the database.
+    #[display(fmt = "{_0}")]
+    Database(full_sqlite::FinalizedAccessError),
+    /// The database contains an invalid trie entry version.
+    #[display(fmt = "Invalid trie entry version in the database")]
+    InvalidTrieEntryVersion,
+    /// No `:code` found in the storage of the finalized block.
+    #[display(fmt = "No `:code` found in the storage")]
+    MissingCode,
+    /// Error while parsing the `:heappages` storage value of the finalized block.
+    #[display(fmt = "Failed to parse `:heappages` storage value: {_0}")]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Error while compiling the runtime of the finalized block.
+    #[display(fmt = "Failed to compile the runtime: {_0}")]
+    RuntimeBuild(host::NewErr),
+    /// Error while starting the runtime call.
+    #[display(fmt = "{_0}")]
+    StartError(host::StartErr),
+    /// Error during the execution of the runtime call.
+    #[display(fmt = "{_0}")]
+    RuntimeError(runtime_host::ErrorDetail),
+    /// Failed to decode the output of the runtime call.
+    #[display(fmt = "{_0}")]
+    Decode(authority_discovery::DecodeAuthoritiesError),
+}
+
+impl From<full_sqlite::FinalizedAccessError> for AuthoritiesError {
+    fn from(err: full_sqlite::FinalizedAccessError) -> Self {
+        AuthoritiesError::Database(err)
+    }
+}
+
+impl From<full_sqlite::AccessError> for AuthoritiesError {
+    fn from(err: full_sqlite::AccessError) -> Self {
+        AuthoritiesError::Database(full_sqlite::FinalizedAccessError::Access(err))
+    }
+}
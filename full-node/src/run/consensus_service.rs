@@ -26,14 +26,21 @@
 
 use crate::run::{database_thread, jaeger_service, network_service};
 
+mod warp_sync;
+
 use core::{num::NonZeroU32, ops};
-use futures::{lock::Mutex, prelude::*};
+use futures::{
+    channel::{mpsc, oneshot},
+    lock::Mutex,
+    prelude::*,
+};
 use hashbrown::HashSet;
 use smoldot::{
     author,
     chain::chain_information,
     database::full_sqlite,
-    executor, header,
+    executor::{self, host, runtime_host, storage_diff},
+    header,
     identity::keystore,
     informant::HashDisplay,
     libp2p,
@@ -42,10 +49,12 @@ use smoldot::{
 };
 use std::{
     collections::BTreeMap,
-    iter,
+    fs, iter,
     num::NonZeroU64,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 /// Configuration for a [`ConsensusService`].
@@ -56,9 +65,18 @@ pub struct Config<'a> {
     /// Database to use to read and write information about the chain.
     pub database: Arc<database_thread::DatabaseThread>,
 
+    /// Directory where to store the runtimes compiled ahead of time, so that they don't need to
+    /// be compiled again when the node restarts. `None` to not cache them.
+    pub runtime_cache_directory: Option<PathBuf>,
+
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
+    /// If `true` and the finalized block of the database is the genesis block, the service warp
+    /// syncs to the latest finalized block of the chain and downloads its storage from the
+    /// network instead of verifying all the blocks since the genesis.
+    pub warp_sync: bool,
+
     /// Hash of the genesis block.
     ///
     /// > **Note**: At the time of writing of this comment, the value in this field is used only
@@ -79,6 +97,11 @@ pub struct Config<'a> {
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
 
+    /// If `Some`, the host functions called by the runtime are recorded when verifying blocks.
+    /// The recorded calls are written to this directory and summarized in the Jaeger span of
+    /// the verification.
+    pub runtime_profiling_directory: Option<PathBuf>,
+
     /// A node has the authorization to author a block during a slot.
     ///
     /// In order for the network to perform well, a block should be authored and propagated
@@ -109,12 +132,34 @@ pub struct SyncState {
     pub best_block_hash: [u8; 32],
     pub finalized_block_number: u64,
     pub finalized_block_hash: [u8; 32],
+    /// Syncing strategy currently in use.
+    pub status: SyncStatus,
+    /// Number of blocks that have been successfully verified since the service has started.
+    pub num_blocks_verified: u64,
+    /// Number of blocks whose verification has failed since the service has started.
+    pub num_blocks_verification_failures: u64,
+    /// Total time spent verifying blocks, both successfully and unsuccessfully, since the
+    /// service has started.
+    pub blocks_verification_duration: Duration,
+}
+
+/// See [`SyncState::status`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// Blocks are downloaded and verified one by one.
+    Sync,
+    /// The finality proofs of the chain are being downloaded in order to jump directly to the
+    /// head of the finalized chain.
+    WarpSync,
 }
 
 /// Background task that verifies blocks and emits requests.
 pub struct ConsensusService {
     /// State kept up-to-date with the background task.
     sync_state: Arc<Mutex<SyncState>>,
+
+    /// Channel to send messages to the background task.
+    to_background_tx: Mutex<mpsc::Sender<ToBackground>>,
 }
 
 impl ConsensusService {
@@ -205,82 +250,188 @@ impl ConsensusService {
             );
         }
 
+        // If the database only contains the genesis block, the storage of the finalized block
+        // is instead downloaded from the network within the background task.
+        let warp_sync = config.warp_sync && finalized_block_number == 0;
+
         let sync_state = Arc::new(Mutex::new(SyncState {
             best_block_number,
             best_block_hash,
             finalized_block_number,
             finalized_block_hash,
+            status: if warp_sync {
+                SyncStatus::WarpSync
+            } else {
+                SyncStatus::Sync
+            },
+            num_blocks_verified: 0,
+            num_blocks_verification_failures: 0,
+            blocks_verification_duration: Duration::new(0, 0),
         }));
 
+        // Builds the runtime of the finalized block.
+        // Assumed to always be valid, otherwise the block wouldn't have been saved in the
+        // database, hence the large number of unwraps here.
+        let finalized_runtime = if !warp_sync {
+            let (module, _) = finalized_block_storage.get(&b":code"[..]).unwrap();
+            let heap_pages = executor::storage_heap_pages_to_value(
+                finalized_block_storage
+                    .get(&b":heappages"[..])
+                    .map(|(v, _)| &v[..]),
+            )
+            .unwrap();
+            Some(
+                compile_runtime_cached(
+                    config.runtime_cache_directory.as_deref(),
+                    module,
+                    heap_pages,
+                )
+                .unwrap(),
+            )
+        } else {
+            None
+        };
+
+        let (to_background_tx, to_background_rx) = mpsc::channel(4);
+
+        // Spawn the thread that performs the runtime calls of `dry_run`. The thread stops when
+        // the background task, which holds the sender, ends.
+        let (to_dry_run_thread, dry_run_thread_rx) = mpsc::channel(4);
+        thread::Builder::new()
+            .name("dry-run".into())
+            .spawn(move || dry_run_thread(dry_run_thread_rx))
+            .unwrap();
+
         // Spawn the background task that synchronizes blocks and updates the database.
         (config.tasks_executor)({
-            let mut sync = all::AllSync::new(all::Config {
-                chain_information: finalized_chain_information,
-                block_number_bytes: config.block_number_bytes,
-                allow_unknown_consensus_engines: false,
-                sources_capacity: 32,
-                blocks_capacity: {
-                    // This is the maximum number of blocks between two consecutive justifications.
-                    1024
-                },
-                max_disjoint_headers: 1024,
-                max_requests_per_block: NonZeroU32::new(3).unwrap(),
-                download_ahead_blocks: {
-                    // Assuming a verification speed of 1k blocks/sec and a 99th download time
-                    // percentile of two second, the number of blocks to download ahead of time
-                    // in order to not block is 2000.
-                    // In practice, however, the verification speed and download speed depend on
-                    // the chain and the machine of the user.
-                    NonZeroU32::new(2000).unwrap()
-                },
-                full: Some(all::ConfigFull {
-                    finalized_runtime: {
-                        // Builds the runtime of the finalized block.
-                        // Assumed to always be valid, otherwise the block wouldn't have been saved in the
-                        // database, hence the large number of unwraps here.
-                        let (module, _) = finalized_block_storage.get(&b":code"[..]).unwrap();
-                        let heap_pages = executor::storage_heap_pages_to_value(
-                            finalized_block_storage
-                                .get(&b":heappages"[..])
-                                .map(|(v, _)| &v[..]),
-                        )
-                        .unwrap();
-                        executor::host::HostVmPrototype::new(executor::host::Config {
-                            module,
-                            heap_pages,
-                            exec_hint: executor::vm::ExecHint::CompileAheadOfTime, // TODO: probably should be decided by the optimisticsync
-                            allow_unresolved_imports: false,
+            let block_number_bytes = config.block_number_bytes;
+            let slot_duration_author_ratio = config.slot_duration_author_ratio;
+            let keystore = config.keystore;
+            let sync_state = sync_state.clone();
+            let (network_service, network_chain_index) = config.network_service;
+            let mut network_events_receiver = config.network_events_receiver;
+            let database = config.database;
+            let jaeger_service = config.jaeger_service;
+            let runtime_profiling_directory = config.runtime_profiling_directory;
+
+            Box::pin(async move {
+                let (
+                    finalized_chain_information,
+                    finalized_block_storage,
+                    finalized_runtime,
+                    best_block_number,
+                    best_block_hash,
+                    connected_peers,
+                ) = match finalized_runtime {
+                    Some(finalized_runtime) => (
+                        finalized_chain_information,
+                        finalized_block_storage,
+                        finalized_runtime,
+                        best_block_number,
+                        best_block_hash,
+                        Default::default(),
+                    ),
+                    None => {
+                        let outcome = warp_sync::warp_sync(warp_sync::Config {
+                            chain_information: finalized_chain_information,
+                            block_number_bytes,
+                            database: database.clone(),
+                            network_service: (network_service.clone(), network_chain_index),
+                            network_events_receiver: &mut network_events_receiver,
                         })
-                        .unwrap()
+                        .await;
+
+                        {
+                            let mut lock = sync_state.lock().await;
+                            lock.best_block_number = outcome.finalized_block_number;
+                            lock.best_block_hash = outcome.finalized_block_hash;
+                            lock.finalized_block_number = outcome.finalized_block_number;
+                            lock.finalized_block_hash = outcome.finalized_block_hash;
+                        }
+
+                        (
+                            outcome.chain_information,
+                            outcome.finalized_block_storage,
+                            outcome.finalized_runtime,
+                            outcome.finalized_block_number,
+                            outcome.finalized_block_hash,
+                            outcome.connected_peers,
+                        )
+                    }
+                };
+
+                let mut sync = all::AllSync::new(all::Config {
+                    chain_information: finalized_chain_information,
+                    block_number_bytes,
+                    allow_unknown_consensus_engines: false,
+                    sources_capacity: 32,
+                    blocks_capacity: {
+                        // This is the maximum number of blocks between two consecutive justifications.
+                        1024
                     },
-                }),
-            });
-
-            let block_author_sync_source =
-                sync.add_source(None, best_block_number, best_block_hash);
-
-            let background_sync = SyncBackground {
-                sync,
-                block_author_sync_source,
-                block_authoring: None,
-                authored_block: None,
-                slot_duration_author_ratio: config.slot_duration_author_ratio,
-                keystore: config.keystore,
-                finalized_block_storage,
-                sync_state: sync_state.clone(),
-                network_service: config.network_service.0,
-                network_chain_index: config.network_service.1,
-                from_network_service: config.network_events_receiver,
-                database: config.database,
-                peers_source_id_map: Default::default(),
-                block_requests_finished: stream::FuturesUnordered::new(),
-                jaeger_service: config.jaeger_service,
-            };
+                    max_disjoint_headers: 1024,
+                    max_requests_per_block: NonZeroU32::new(3).unwrap(),
+                    download_ahead_blocks: {
+                        // Assuming a verification speed of 1k blocks/sec and a 99th download time
+                        // percentile of two second, the number of blocks to download ahead of time
+                        // in order to not block is 2000.
+                        // In practice, however, the verification speed and download speed depend on
+                        // the chain and the machine of the user.
+                        NonZeroU32::new(2000).unwrap()
+                    },
+                    full: Some(all::ConfigFull { finalized_runtime }),
+                });
+
+                let block_author_sync_source =
+                    sync.add_source(None, best_block_number, best_block_hash);
 
-            Box::pin(background_sync.run())
+                // Peers that have connected during the warp sync are immediately added as sources.
+                let mut peers_source_id_map = hashbrown::HashMap::with_capacity_and_hasher(
+                    connected_peers.len(),
+                    Default::default(),
+                );
+                for (peer_id, (best_block_number, best_block_hash)) in connected_peers {
+                    let id = sync.add_source(
+                        Some(NetworkSourceInfo {
+                            peer_id: peer_id.clone(),
+                            is_disconnected: false,
+                        }),
+                        best_block_number,
+                        best_block_hash,
+                    );
+                    peers_source_id_map.insert(peer_id, id);
+                }
+
+                let background_sync = SyncBackground {
+                    sync,
+                    block_author_sync_source,
+                    block_authoring: None,
+                    authored_block: None,
+                    slot_duration_author_ratio,
+                    keystore,
+                    finalized_block_storage: Arc::new(finalized_block_storage),
+                    sync_state,
+                    network_service,
+                    network_chain_index,
+                    network_grandpa_set_id: None,
+                    from_network_service: network_events_receiver,
+                    from_foreground: to_background_rx,
+                    to_dry_run_thread,
+                    database,
+                    peers_source_id_map,
+                    block_requests_finished: stream::FuturesUnordered::new(),
+                    jaeger_service,
+                    runtime_profiling_directory,
+                };
+
+                background_sync.run().await
+            })
         });
 
-        Arc::new(ConsensusService { sync_state })
+        Arc::new(ConsensusService {
+            sync_state,
+            to_background_tx: Mutex::new(to_background_tx),
+        })
     }
 
     /// Returns a summary of the state of the service.
@@ -290,6 +441,269 @@ impl ConsensusService {
     pub async fn sync_state(&self) -> SyncState {
         self.sync_state.lock().await.clone()
     }
+
+    /// Applies the given extrinsic on top of a child of the given block, and returns the
+    /// SCALE-encoded `ApplyExtrinsicResult` returned by the runtime.
+    ///
+    /// The runtime function `Core_initialize_block` is first called with a header whose parent
+    /// is the given block and whose other fields are dummy values, then
+    /// `BlockBuilder_apply_extrinsic` is called on top of the storage modified by the former.
+    ///
+    /// The block must be either the current best block or the current finalized block. Pass
+    /// `None` in order to use the current best block.
+    ///
+    /// The runtime calls are performed on a dedicated thread, one at a time. An error is
+    /// returned if too many dry runs are already queued.
+    pub async fn dry_run(
+        &self,
+        block_hash: Option<[u8; 32]>,
+        scale_encoded_extrinsic: Vec<u8>,
+    ) -> Result<Vec<u8>, DryRunError> {
+        // The background task doesn't process messages while the warp sync is in progress.
+        if self.sync_state.lock().await.status == SyncStatus::WarpSync {
+            return Err(DryRunError::WarpSyncInProgress);
+        }
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.to_background_tx
+            .lock()
+            .await
+            .try_send(ToBackground::DryRun {
+                block_hash,
+                scale_encoded_extrinsic,
+                result_tx,
+            })
+            .map_err(|err| {
+                if err.is_full() {
+                    DryRunError::Busy
+                } else {
+                    DryRunError::Interrupted
+                }
+            })?;
+
+        // The sender is destroyed without sending anything if the background task or the
+        // dry-run thread has stopped.
+        result_rx.await.unwrap_or(Err(DryRunError::Interrupted))
+    }
+}
+
+/// Error potentially returned by [`ConsensusService::dry_run`].
+#[derive(Debug, derive_more::Display)]
+pub enum DryRunError {
+    /// The node is performing a warp sync and doesn't have access to the storage of the chain.
+    #[display(fmt = "Not available while the warp sync is in progress")]
+    WarpSyncInProgress,
+    /// Too many dry runs are already in progress.
+    #[display(fmt = "Too many dry runs in progress")]
+    Busy,
+    /// The consensus service has stopped before the dry run has finished.
+    #[display(fmt = "The dry run has been interrupted")]
+    Interrupted,
+    /// The requested block is neither the best block nor the finalized block.
+    #[display(fmt = "Only the best and finalized blocks are supported")]
+    UnsupportedBlock,
+    /// No `:code` found in the storage of the requested block.
+    #[display(fmt = "No `:code` found in the storage")]
+    MissingCode,
+    /// Error while parsing the `:heappages` storage value of the requested block.
+    #[display(fmt = "Failed to parse `:heappages` storage value: {_0}")]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Error while compiling the runtime of the requested block.
+    #[display(fmt = "Failed to compile the runtime: {_0}")]
+    RuntimeBuild(host::NewErr),
+    /// Error while starting a runtime call.
+    #[display(fmt = "{_0}")]
+    StartError(host::StartErr),
+    /// Error during the execution of a runtime call.
+    #[display(fmt = "{_0}")]
+    RuntimeError(runtime_host::ErrorDetail),
+    /// The runtime has tried to obtain the storage key following another on top of a
+    /// non-finalized block, which isn't supported at the moment.
+    #[display(fmt = "Getting the next key of a non-finalized block isn't supported")]
+    NextKeyForbidden,
+}
+
+/// Storage of a block, indexed by key, with the trie entry version of each value.
+type Storage = BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>;
+
+/// Message sent from the [`ConsensusService`] to the background task.
+enum ToBackground {
+    /// See [`ConsensusService::dry_run`].
+    DryRun {
+        block_hash: Option<[u8; 32]>,
+        scale_encoded_extrinsic: Vec<u8>,
+        result_tx: oneshot::Sender<Result<Vec<u8>, DryRunError>>,
+    },
+}
+
+/// Runtime calls of a [`ConsensusService::dry_run`], sent from the background task to the
+/// dry-run thread.
+struct DryRunJob {
+    /// Storage of the finalized block at the time when the dry run has been started.
+    finalized_block_storage: Arc<Storage>,
+    /// Changes made by the best block to [`DryRunJob::finalized_block_storage`], or `None` if
+    /// the calls are made on top of the finalized block.
+    best_block_storage_diff: Option<storage_diff::TrieDiff<TrieEntryVersion>>,
+    /// Parameter to pass to `Core_initialize_block`.
+    initialize_block_parameter: Vec<u8>,
+    /// Parameter to pass to `BlockBuilder_apply_extrinsic`.
+    scale_encoded_extrinsic: Vec<u8>,
+    /// Where to send back the outcome.
+    result_tx: oneshot::Sender<Result<Vec<u8>, DryRunError>>,
+}
+
+impl DryRunJob {
+    /// Returns the storage value at the given key on top of the block the calls are made on.
+    fn storage_get(&self, key: &[u8]) -> Option<(&[u8], TrieEntryVersion)> {
+        match self
+            .best_block_storage_diff
+            .as_ref()
+            .and_then(|diff| diff.diff_get(key))
+        {
+            Some((value, version)) => value.map(|value| (value, *version)),
+            None => self
+                .finalized_block_storage
+                .get(key)
+                .map(|(value, version)| (&value[..], *version)),
+        }
+    }
+}
+
+/// Performs the [`DryRunJob`]s received on the given channel one by one, until the channel is
+/// closed.
+///
+/// The extrinsics come from JSON-RPC clients and can't be trusted. The runtime is thus compiled
+/// again with resource limits, rather than reusing the runtime of the best block, in order to
+/// make sure that the calls can't run forever or exhaust the memory. The compiled runtime is
+/// kept and reused as long as the runtime code and heap pages don't change.
+fn dry_run_thread(mut jobs: mpsc::Receiver<DryRunJob>) {
+    // Runtime code and heap pages of the latest dry run, and the corresponding compiled runtime.
+    let mut runtime_cache: Option<(Vec<u8>, host::HeapPages, host::HostVmPrototype)> = None;
+
+    while let Some(job) = futures::executor::block_on(jobs.next()) {
+        // No need to perform the calls if the JSON-RPC request has been cancelled.
+        if job.result_tx.is_canceled() {
+            continue;
+        }
+
+        let result = dry_run(&mut runtime_cache, &job);
+        let _ = job.result_tx.send(result);
+    }
+}
+
+/// Performs the runtime calls of the given [`DryRunJob`]. See [`dry_run_thread`].
+fn dry_run(
+    runtime_cache: &mut Option<(Vec<u8>, host::HeapPages, host::HostVmPrototype)>,
+    job: &DryRunJob,
+) -> Result<Vec<u8>, DryRunError> {
+    let mut virtual_machine = {
+        let (code, _) = job.storage_get(b":code").ok_or(DryRunError::MissingCode)?;
+        let heap_pages =
+            executor::storage_heap_pages_to_value(job.storage_get(b":heappages").map(|(v, _)| v))
+                .map_err(DryRunError::InvalidHeapPages)?;
+
+        match runtime_cache {
+            Some((cached_code, cached_heap_pages, runtime))
+                if *cached_code == code && *cached_heap_pages == heap_pages =>
+            {
+                runtime.clone()
+            }
+            _ => {
+                let runtime = host::HostVmPrototype::new(host::Config {
+                    module: code,
+                    heap_pages,
+                    exec_hint: executor::vm::ExecHint::Untrusted,
+                    resource_limits: super::UNTRUSTED_RUNTIME_CALLS_RESOURCE_LIMITS,
+                    allow_unresolved_imports: true,
+                })
+                .map_err(DryRunError::RuntimeBuild)?;
+                *runtime_cache = Some((code.to_vec(), heap_pages, runtime.clone()));
+                runtime
+            }
+        }
+    };
+
+    // Storage changes performed by the previous calls, and output of the latest call.
+    let mut storage_main_trie_changes = Default::default();
+    let mut output = Vec::new();
+
+    for (function_to_call, parameter) in [
+        ("Core_initialize_block", &job.initialize_block_parameter[..]),
+        (
+            "BlockBuilder_apply_extrinsic",
+            &job.scale_encoded_extrinsic[..],
+        ),
+    ] {
+        let mut runtime_call = runtime_host::run(runtime_host::Config {
+            virtual_machine,
+            function_to_call,
+            parameter: iter::once(parameter),
+            main_trie_root_calculation_cache: None,
+            storage_main_trie_changes,
+            offchain_storage_changes: Default::default(),
+            storage_proof_recorder: None,
+            max_log_level: 0,
+            host_functions_profiler: None,
+        })
+        .map_err(|(err, _)| DryRunError::StartError(err))?;
+
+        let success = loop {
+            runtime_call = match runtime_call {
+                runtime_host::RuntimeHostVm::Finished(Ok(success)) => break success,
+                runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                    return Err(DryRunError::RuntimeError(error.detail))
+                }
+                runtime_host::RuntimeHostVm::StorageGet(get) => {
+                    let value = job.storage_get(get.key().as_ref());
+                    get.inject_value(value.map(|(val, vers)| (iter::once(val), vers)))
+                }
+                runtime_host::RuntimeHostVm::PrefixKeys(prefix_key) => {
+                    let in_finalized_ordered = job
+                        .finalized_block_storage
+                        .range::<[u8], _>((
+                            ops::Bound::Included(prefix_key.prefix().as_ref()),
+                            ops::Bound::Unbounded,
+                        ))
+                        .take_while(|(k, _)| k.starts_with(prefix_key.prefix().as_ref()))
+                        .map(|(k, _)| &k[..]);
+                    let keys = match &job.best_block_storage_diff {
+                        Some(diff) => diff
+                            .storage_prefix_keys_ordered(
+                                prefix_key.prefix().as_ref(),
+                                in_finalized_ordered,
+                            )
+                            .map(|k| k.as_ref().to_vec())
+                            .collect::<Vec<_>>(),
+                        None => in_finalized_ordered.map(|k| k.to_vec()).collect(),
+                    };
+                    prefix_key.inject_keys_ordered(keys.into_iter())
+                }
+                runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                    if job.best_block_storage_diff.is_some() {
+                        return Err(DryRunError::NextKeyForbidden);
+                    }
+
+                    let key = job
+                        .finalized_block_storage
+                        .range::<[u8], _>((
+                            ops::Bound::Excluded(next_key.key().as_ref()),
+                            ops::Bound::Unbounded,
+                        ))
+                        .next()
+                        .map(|(k, _)| k.clone());
+                    next_key.inject_key(key)
+                }
+                runtime_host::RuntimeHostVm::SignatureVerification(sig) => sig.verify_and_resume(),
+            };
+        };
+
+        output = success.virtual_machine.value().as_ref().to_vec();
+        storage_main_trie_changes = success.storage_main_trie_changes;
+        virtual_machine = success.virtual_machine.into_prototype();
+    }
+
+    // The output of the last call is the `ApplyExtrinsicResult`.
+    Ok(output)
 }
 
 struct SyncBackground {
@@ -344,7 +758,10 @@ struct SyncBackground {
     // While reading the storage from the database is an option, doing so considerably slows down
     /// the verification, and also makes it impossible to insert blocks in the database in
     /// parallel of this verification.
-    finalized_block_storage: BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>,
+    ///
+    /// Shared with the dry-run thread. If a dry run is in progress when a block is finalized,
+    /// the storage is copied before being modified.
+    finalized_block_storage: Arc<Storage>,
 
     sync_state: Arc<Mutex<SyncState>>,
 
@@ -356,10 +773,21 @@ struct SyncBackground {
     /// network service.
     network_chain_index: usize,
 
+    /// GrandPa authorities set id that was last passed to the
+    /// [`SyncBackground::network_service`], or `None` if none was passed yet.
+    network_grandpa_set_id: Option<u64>,
+
     /// Stream of events coming from the [`SyncBackground::network_service`]. Used to know what
     /// happens on the peer-to-peer network.
     from_network_service: stream::BoxStream<'static, network_service::Event>,
 
+    /// Messages sent by the [`ConsensusService`].
+    from_foreground: mpsc::Receiver<ToBackground>,
+
+    /// Channel to send the runtime calls of [`ConsensusService::dry_run`] to the thread that
+    /// performs them.
+    to_dry_run_thread: mpsc::Sender<DryRunJob>,
+
     /// For each networking peer, the identifier of the source in [`SyncBackground::sync`].
     /// This map is kept up-to-date with the "chain connections" of the network service. Whenever
     /// a connection is established with a peer, an entry is inserted in this map and a source is
@@ -388,6 +816,9 @@ struct SyncBackground {
 
     /// How to report events about blocks.
     jaeger_service: Arc<jaeger_service::JaegerService>,
+
+    /// See [`Config::runtime_profiling_directory`].
+    runtime_profiling_directory: Option<PathBuf>,
 }
 
 /// Information about a source in the sync state machine.
@@ -411,6 +842,39 @@ impl SyncBackground {
                 let mut lock = self.sync_state.lock().await;
                 lock.best_block_hash = self.sync.best_block_hash();
                 lock.best_block_number = self.sync.best_block_number();
+                lock.status = match self.sync.status() {
+                    all::Status::Sync => SyncStatus::Sync,
+                    all::Status::WarpSyncFragments { .. }
+                    | all::Status::WarpSyncChainInformation { .. } => SyncStatus::WarpSync,
+                };
+            }
+
+            // Keep the network service informed of the current GrandPa authorities, in order for
+            // it to be able to verify the votes gossiped by the other nodes.
+            let grandpa_authorities = match self.sync.as_chain_information().as_ref().finality {
+                chain_information::ChainInformationFinalityRef::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    finalized_triggered_authorities,
+                    ..
+                } if self.network_grandpa_set_id
+                    != Some(after_finalized_block_authorities_set_id) =>
+                {
+                    Some((
+                        after_finalized_block_authorities_set_id,
+                        finalized_triggered_authorities.to_vec(),
+                    ))
+                }
+                _ => None,
+            };
+            if let Some((set_id, authorities)) = grandpa_authorities {
+                self.network_service
+                    .set_grandpa_authorities(
+                        self.network_chain_index,
+                        set_id,
+                        authorities.iter().map(header::GrandpaAuthorityRef::from),
+                    )
+                    .await;
+                self.network_grandpa_set_id = Some(set_id);
             }
 
             // Creating the block authoring state and prepare a future that is ready when something
@@ -590,6 +1054,14 @@ impl SyncBackground {
                     }
                 },
 
+                message = self.from_foreground.select_next_some() => {
+                    match message {
+                        ToBackground::DryRun { block_hash, scale_encoded_extrinsic, result_tx } => {
+                            self.start_dry_run(block_hash, scale_encoded_extrinsic, result_tx);
+                        }
+                    }
+                },
+
                 (request_id, source_id, result) = self.block_requests_finished.select_next_some() => {
                     // `result` is an error if the block request got cancelled by the sync state
                     // machine.
@@ -626,6 +1098,72 @@ impl SyncBackground {
         }
     }
 
+    /// See [`ConsensusService::dry_run`].
+    ///
+    /// The storage of the finalized block and the changes made to it by the best block are held
+    /// by this background task. A snapshot of them is sent to the dry-run thread, which performs
+    /// the runtime calls.
+    fn start_dry_run(
+        &mut self,
+        block_hash: Option<[u8; 32]>,
+        scale_encoded_extrinsic: Vec<u8>,
+        result_tx: oneshot::Sender<Result<Vec<u8>, DryRunError>>,
+    ) {
+        let best_block_hash = self.sync.best_block_hash();
+        let finalized_block_hash = self
+            .sync
+            .finalized_block_header()
+            .hash(self.sync.block_number_bytes());
+        let block_hash = block_hash.unwrap_or(best_block_hash);
+
+        // Changes made by the best block on top of the finalized block, or `None` if the calls
+        // are made on top of the finalized block.
+        let (parent_number, best_block_storage_diff) = if block_hash == finalized_block_hash {
+            (self.sync.finalized_block_header().number, None)
+        } else if block_hash == best_block_hash {
+            match self.sync.best_block_storage() {
+                Some(storage) => (
+                    self.sync.best_block_number(),
+                    Some(storage.finalized_storage_diff().clone()),
+                ),
+                None => {
+                    let _ = result_tx.send(Err(DryRunError::UnsupportedBlock));
+                    return;
+                }
+            }
+        } else {
+            let _ = result_tx.send(Err(DryRunError::UnsupportedBlock));
+            return;
+        };
+
+        let initialize_block_parameter = header::HeaderRef {
+            parent_hash: &block_hash,
+            number: parent_number + 1,
+            extrinsics_root: &[0; 32],
+            state_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(self.sync.block_number_bytes());
+
+        let job = DryRunJob {
+            finalized_block_storage: self.finalized_block_storage.clone(),
+            best_block_storage_diff,
+            initialize_block_parameter,
+            scale_encoded_extrinsic,
+            result_tx,
+        };
+
+        // The dry-run thread only stops if it has panicked.
+        if let Err(err) = self.to_dry_run_thread.try_send(job) {
+            let error = if err.is_full() {
+                DryRunError::Busy
+            } else {
+                DryRunError::Interrupted
+            };
+            let _ = err.into_inner().result_tx.send(Err(error));
+        }
+    }
+
     /// Authors a block, then imports it and gossips it out.
     ///
     /// # Panic
@@ -1035,9 +1573,19 @@ impl SyncBackground {
                     let height_to_verify = verify.height();
                     let scale_encoded_header_to_verify = verify.scale_encoded_header().to_owned(); // TODO: copy :-/
 
-                    let _jaeger_span = self.jaeger_service.block_body_verify_span(&hash_to_verify);
+                    let mut jaeger_span =
+                        self.jaeger_service.block_body_verify_span(&hash_to_verify);
 
-                    let mut verify = verify.start(unix_time, ());
+                    let host_functions_profiler =
+                        self.runtime_profiling_directory.as_ref().map(|_| {
+                            runtime_host::HostFunctionsProfiler {
+                                profiler: host::profiler::Profiler::new(),
+                                clock: profiler_clock,
+                            }
+                        });
+
+                    let verification_start = Instant::now();
+                    let mut verify = verify.start(unix_time, (), host_functions_profiler);
                     // TODO: check this block against the chain spec's badBlocks
                     loop {
                         match verify {
@@ -1046,6 +1594,13 @@ impl SyncBackground {
                                 error,
                                 ..
                             } => {
+                                {
+                                    let mut lock = self.sync_state.lock().await;
+                                    lock.num_blocks_verification_failures += 1;
+                                    lock.blocks_verification_duration +=
+                                        verification_start.elapsed();
+                                }
+
                                 // Print a separate warning because it is important for the user
                                 // to be aware of the verification failure.
                                 // `error` is last because it's quite big.
@@ -1061,15 +1616,33 @@ impl SyncBackground {
                             all::BlockVerification::Success {
                                 is_new_best,
                                 sync: sync_out,
-                                ..
+                                host_functions_profiler,
                             } => {
                                 log::debug!(
                                     "block-verification-success; hash={}; height={}; is_new_best={:?}",
                                     HashDisplay(&hash_to_verify), height_to_verify, is_new_best
                                 );
 
+                                if let (Some(directory), Some(profiler)) =
+                                    (&self.runtime_profiling_directory, host_functions_profiler)
+                                {
+                                    report_host_functions_profile(
+                                        &mut jaeger_span,
+                                        directory,
+                                        &hash_to_verify,
+                                        &profiler.profiler,
+                                    );
+                                }
+
                                 // Processing has made a step forward.
 
+                                {
+                                    let mut lock = self.sync_state.lock().await;
+                                    lock.num_blocks_verified += 1;
+                                    lock.blocks_verification_duration +=
+                                        verification_start.elapsed();
+                                }
+
                                 if is_new_best {
                                     // Update the networking.
                                     let fut = self.network_service.set_local_best_block(
@@ -1237,7 +1810,7 @@ impl SyncBackground {
                                     .diff_iter_unordered()
                                 {
                                     if let Some(value) = value {
-                                        self.finalized_block_storage.insert(
+                                        Arc::make_mut(&mut self.finalized_block_storage).insert(
                                             key.to_owned(),
                                             (
                                                 value.to_owned(),
@@ -1245,7 +1818,9 @@ impl SyncBackground {
                                             ),
                                         );
                                     } else {
-                                        let _was_there = self.finalized_block_storage.remove(key);
+                                        let _was_there =
+                                            Arc::make_mut(&mut self.finalized_block_storage)
+                                                .remove(key);
                                         // TODO: if a block inserts a new value, then removes it in the next block, the key will remain in `finalized_block_storage`; either solve this or document this
                                         // assert!(_was_there.is_some());
                                     }
@@ -1359,6 +1934,21 @@ async fn database_blocks(
                     Err(full_sqlite::InsertError::Duplicate) => {} // TODO: this should be an error ; right now we silence them because non-finalized blocks aren't loaded from the database at startup, resulting in them being downloaded again
                     Err(err) => panic!("{}", err),
                 }
+
+                // Store the GrandPa justification, if any, in order to be able to later generate
+                // finality proofs.
+                if let Some((_, justification)) = block
+                    .justifications
+                    .iter()
+                    .find(|(engine_id, _)| engine_id == b"FRNK")
+                {
+                    database
+                        .set_block_justification(
+                            &block.header.hash(block_number_bytes),
+                            justification,
+                        )
+                        .unwrap();
+                }
             }
         })
         .await
@@ -1376,3 +1966,180 @@ async fn database_set_finalized(
         })
         .await
 }
+
+/// Compiles the given runtime ahead of time, or loads it from `cache_directory` if it has already
+/// been compiled before. The compiled runtime is then written to `cache_directory`.
+///
+/// The cache is keyed by the hash of the runtime code and by the configuration of the compiler.
+/// Additionally, the compiled runtime embeds the version of the compiler and the CPU features it
+/// has been compiled for, and loading it fails if they don't match. In that situation, the
+/// runtime is compiled again and the cache entry overwritten.
+fn compile_runtime_cached(
+    cache_directory: Option<&Path>,
+    module: &[u8],
+    heap_pages: executor::host::HeapPages,
+) -> Result<executor::host::HostVmPrototype, executor::host::NewErr> {
+    let config = || executor::host::Config {
+        module,
+        heap_pages,
+        exec_hint: executor::vm::ExecHint::CompileAheadOfTime, // TODO: probably should be decided by the optimisticsync
+        resource_limits: Default::default(),
+        allow_unresolved_imports: false,
+    };
+
+    // `None` if the platform doesn't support compiling ahead of time, in which case there is
+    // nothing to cache.
+    let cache_path = match (
+        cache_directory,
+        executor::vm::VirtualMachinePrototype::precompiled_engine_key(&Default::default()),
+    ) {
+        (Some(dir), Some(engine_key)) => {
+            let code_hash = blake2_rfc::blake2b::blake2b(32, &[], module);
+            Some(dir.join(format!(
+                "{}-{:016x}.bin",
+                hex::encode(code_hash.as_bytes()),
+                engine_key
+            )))
+        }
+        _ => None,
+    };
+
+    if let Some(cache_path) = &cache_path {
+        if let Ok(precompiled) = fs::read(cache_path) {
+            // The cache directory is only ever written by the node itself, and it is assumed that
+            // nobody tampers with its content.
+            let result = unsafe {
+                executor::host::HostVmPrototype::from_precompiled(config(), &precompiled)
+            };
+            match result {
+                Ok(runtime) => {
+                    // The modification time of the entry is used to determine which entries to
+                    // evict. See `evict_runtime_cache`.
+                    let _ = fs::File::options()
+                        .write(true)
+                        .open(cache_path)
+                        .and_then(|file| file.set_modified(SystemTime::now()));
+                    return Ok(runtime);
+                }
+                Err(err) => {
+                    log::debug!(
+                        "runtime-cache-load-failure; path={}; error={}",
+                        cache_path.display(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    let runtime = executor::host::HostVmPrototype::new(config())?;
+
+    if let (Some(cache_path), Some(precompiled)) = (&cache_path, runtime.serialize_precompiled()) {
+        // The compiled runtime is first written to a temporary file then renamed, so that a
+        // partially-written file is never loaded.
+        let tmp_path = cache_path.with_extension("tmp");
+        let result = fs::create_dir_all(cache_path.parent().unwrap())
+            .and_then(|()| fs::write(&tmp_path, &precompiled))
+            .and_then(|()| fs::rename(&tmp_path, cache_path));
+        if let Err(err) = result {
+            log::warn!(
+                "Failed to write compiled runtime to {}: {}",
+                cache_path.display(),
+                err
+            );
+        }
+
+        evict_runtime_cache(cache_path.parent().unwrap());
+    }
+
+    Ok(runtime)
+}
+
+/// Removes from the given cache directory of compiled runtimes all the entries but the most
+/// recently used ones.
+fn evict_runtime_cache(cache_directory: &Path) {
+    /// Maximum number of compiled runtimes to keep in the cache directory.
+    const MAX_ENTRIES: usize = 4;
+
+    let Ok(entries) = fs::read_dir(cache_directory) else {
+        return;
+    };
+
+    let mut entries = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "bin"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect::<Vec<_>>();
+    if entries.len() <= MAX_ENTRIES {
+        return;
+    }
+
+    // Most recently used entries first.
+    entries.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+    for (_, path) in entries.into_iter().skip(MAX_ENTRIES) {
+        if let Err(err) = fs::remove_file(&path) {
+            log::warn!(
+                "Failed to remove compiled runtime {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Clock passed to the host functions profiler. Returns the time elapsed since the first call.
+///
+/// A monotonic clock is used, as jumps of the wall clock would lead to invalid durations.
+fn profiler_clock() -> Duration {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed()
+}
+
+/// Writes the host functions calls recorded while verifying the given block to the given
+/// directory, and adds a summary of these calls to the Jaeger span of the verification.
+fn report_host_functions_profile(
+    jaeger_span: &mut mick_jaeger::Span,
+    directory: &Path,
+    block_hash: &[u8; 32],
+    profiler: &host::profiler::Profiler,
+) {
+    let calls = profiler.calls();
+    jaeger_span.add_int_tag(
+        "host-functions-calls",
+        i64::try_from(calls.len()).unwrap_or(i64::MAX),
+    );
+
+    // Total time spent in each host function, in microseconds.
+    let mut total_per_function = BTreeMap::<&'static str, u128>::new();
+    for call in calls {
+        *total_per_function.entry(call.name).or_insert(0) += call.duration.as_micros();
+    }
+    for (name, total) in total_per_function {
+        jaeger_span.add_int_tag(
+            &format!("host-function-{name}-us"),
+            i64::try_from(total).unwrap_or(i64::MAX),
+        );
+    }
+
+    let block_hash = hex::encode(block_hash);
+    let result = fs::create_dir_all(directory)
+        .and_then(|()| {
+            fs::write(
+                directory.join(format!("{block_hash}.json")),
+                profiler.chrome_trace(),
+            )
+        })
+        .and_then(|()| {
+            fs::write(
+                directory.join(format!("{block_hash}.folded")),
+                profiler.folded_stacks(&block_hash),
+            )
+        });
+    if let Err(err) = result {
+        log::warn!(
+            "Failed to write runtime profile to {}: {}",
+            directory.display(),
+            err
+        );
+    }
+}
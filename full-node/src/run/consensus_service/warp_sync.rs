@@ -0,0 +1,963 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Warp syncing of the chain and downloading of the storage of the finalized block.
+//!
+//! When the database only contains the genesis block, verifying every single block of the chain
+//! in order to reach the head of the chain takes a long time. Instead, the node can warp sync to
+//! the latest finalized block by downloading a GrandPa warp sync proof, then download the
+//! storage of this finalized block from the network, and resume normal syncing from there.
+//!
+//! Each response to a state request is verified against the state trie root of the block, and
+//! only the storage entries that the response proves to be contiguous are kept. Peers that send
+//! invalid responses, or responses that don't make the download progress, are banned.
+//!
+//! The default child tries are downloaded after the main trie and stored in the database as well.
+
+use crate::run::{database_thread, network_service};
+
+use core::{iter, num::NonZeroU32};
+use futures::prelude::*;
+use smoldot::{
+    chain::chain_information,
+    executor::host,
+    header,
+    informant::HashDisplay,
+    libp2p,
+    network::{self, protocol},
+    sync::all::{self, TrieEntryVersion},
+    trie::{compact_proof, proof_decode},
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Prefix of the keys of the main trie whose value is the root of a default child trie.
+const CHILD_STORAGE_DEFAULT_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Configuration for [`warp_sync`].
+pub(super) struct Config<'a> {
+    /// Chain information of the genesis block.
+    pub chain_information: chain_information::ValidChainInformation,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+
+    /// Database where to store the finalized block and its storage.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Access to the network, and index of the chain to sync from the point of view of the
+    /// network service.
+    pub network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// Receiver for events coming from the network. Events are consumed until the warp sync
+    /// is finished, after which the receiver can be used again.
+    pub network_events_receiver: &'a mut stream::BoxStream<'static, network_service::Event>,
+}
+
+/// Outcome of [`warp_sync`].
+pub(super) struct Outcome {
+    /// Chain information of the new finalized block.
+    pub chain_information: chain_information::ValidChainInformation,
+
+    /// Hash of the new finalized block.
+    pub finalized_block_hash: [u8; 32],
+
+    /// Height of the new finalized block.
+    pub finalized_block_number: u64,
+
+    /// Main trie of the storage of the new finalized block. Identical to what has been written
+    /// in the database.
+    pub finalized_block_storage: BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)>,
+
+    /// Runtime of the new finalized block.
+    pub finalized_runtime: host::HostVmPrototype,
+
+    /// List of peers that are connected at the end of the warp sync, with their best block
+    /// number and hash.
+    pub connected_peers: hashbrown::HashMap<libp2p::PeerId, (u64, [u8; 32]), fnv::FnvBuildHasher>,
+}
+
+/// Warp syncs to the latest finalized block of the chain, downloads its body and storage, and
+/// replaces the content of the database with it.
+///
+/// Errors are logged and the operation retried until it succeeds.
+pub(super) async fn warp_sync(config: Config<'_>) -> Outcome {
+    let mut network = Network {
+        block_number_bytes: config.block_number_bytes,
+        network_service: config.network_service.0,
+        network_chain_index: config.network_service.1,
+        network_events_receiver: config.network_events_receiver,
+        connected_peers: hashbrown::HashMap::with_capacity_and_hasher(32, Default::default()),
+        banned_peers: hashbrown::HashSet::with_capacity_and_hasher(8, Default::default()),
+        next_peer_index: 0,
+    };
+
+    log::info!("warp-sync-start");
+
+    let (chain_information, finalized_runtime) = network
+        .warp_sync_to_finalized(config.chain_information)
+        .await;
+
+    let finalized_block_header = chain_information.as_ref().finalized_block_header;
+    let finalized_block_hash = finalized_block_header.hash(config.block_number_bytes);
+    let finalized_block_number = finalized_block_header.number;
+    let state_root = *finalized_block_header.state_root;
+
+    log::info!(
+        "warp-sync-finished; finalized_block_number={}; finalized_block_hash={}",
+        finalized_block_number,
+        HashDisplay(&finalized_block_hash)
+    );
+
+    let (finalized_block_body, finalized_block_justification) = network
+        .download_body(
+            &finalized_block_hash,
+            finalized_block_header.extrinsics_root,
+        )
+        .await;
+
+    let state_version = finalized_runtime
+        .runtime_version()
+        .decode()
+        .state_version
+        .unwrap_or(TrieEntryVersion::V0);
+
+    let finalized_block_storage = network
+        .download_trie(&finalized_block_hash, None, &state_root, state_version)
+        .await;
+
+    // Child tries are found in the main trie under a specific prefix, with their root as value.
+    let mut finalized_block_child_tries = Vec::new();
+    for (key, (child_trie_root, _)) in finalized_block_storage
+        .range(CHILD_STORAGE_DEFAULT_PREFIX.to_vec()..)
+        .take_while(|(key, _)| key.starts_with(CHILD_STORAGE_DEFAULT_PREFIX))
+    {
+        let child_trie = &key[CHILD_STORAGE_DEFAULT_PREFIX.len()..];
+        let Ok(child_trie_root) = <[u8; 32]>::try_from(&child_trie_root[..]) else {
+            log::warn!(
+                "state-download-invalid-child-trie-root; child_trie={}",
+                hex::encode(child_trie)
+            );
+            continue;
+        };
+
+        let entries = network
+            .download_trie(
+                &finalized_block_hash,
+                Some(child_trie),
+                &child_trie_root,
+                state_version,
+            )
+            .await;
+        finalized_block_child_tries.extend(
+            entries
+                .into_iter()
+                .map(|(key, (value, version))| (child_trie.to_vec(), key, value, version)),
+        );
+    }
+
+    log::info!(
+        "state-download-finished; finalized_block_hash={}; num_entries={}; num_child_tries_entries={}",
+        HashDisplay(&finalized_block_hash),
+        finalized_block_storage.len(),
+        finalized_block_child_tries.len()
+    );
+
+    // The storage is moved to the database thread and given back afterwards rather than cloned,
+    // as it can be large.
+    let finalized_block_storage = config
+        .database
+        .with_database({
+            let chain_information = chain_information.clone();
+            move |database| {
+                database
+                    .reset(
+                        chain_information.as_ref(),
+                        finalized_block_body.iter().map(|ext| &ext[..]),
+                        finalized_block_justification,
+                        finalized_block_storage
+                            .iter()
+                            .map(|(k, (v, vers))| (&k[..], &v[..], u8::from(*vers))),
+                        finalized_block_child_tries
+                            .iter()
+                            .map(|(child_trie, k, v, vers)| {
+                                (&child_trie[..], &k[..], &v[..], u8::from(*vers))
+                            }),
+                    )
+                    .unwrap();
+                finalized_block_storage
+            }
+        })
+        .await;
+
+    Outcome {
+        chain_information,
+        finalized_block_hash,
+        finalized_block_number,
+        finalized_block_storage,
+        finalized_runtime,
+        connected_peers: network.connected_peers,
+    }
+}
+
+/// Access to the network during the warp sync.
+struct Network<'a> {
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::network_service`].
+    network_service: Arc<network_service::NetworkService>,
+
+    /// See [`Config::network_service`].
+    network_chain_index: usize,
+
+    /// See [`Config::network_events_receiver`].
+    network_events_receiver: &'a mut stream::BoxStream<'static, network_service::Event>,
+
+    /// List of peers that are currently connected, with their best block number and hash.
+    connected_peers: hashbrown::HashMap<libp2p::PeerId, (u64, [u8; 32]), fnv::FnvBuildHasher>,
+
+    /// List of peers of [`Network::connected_peers`] that have been banned because they sent an
+    /// invalid response. Requests are no longer sent to them until they disconnect.
+    banned_peers: hashbrown::HashSet<libp2p::PeerId, fnv::FnvBuildHasher>,
+
+    /// Used to cycle through [`Network::connected_peers`] when sending requests.
+    next_peer_index: usize,
+}
+
+/// Response to a request started by [`Network::warp_sync_to_finalized`].
+enum RequestOutcome {
+    GrandpaWarpSync(Result<network::service::EncodedGrandpaWarpSyncResponse, ()>),
+    StorageProof(Result<Vec<u8>, ()>),
+    CallProof(Result<Vec<u8>, ()>),
+}
+
+impl<'a> Network<'a> {
+    /// Updates [`Network::connected_peers`] following the given event.
+    fn on_network_event(&mut self, event: &network_service::Event) {
+        match event {
+            network_service::Event::Connected {
+                peer_id,
+                chain_index,
+                best_block_number,
+                best_block_hash,
+            } if *chain_index == self.network_chain_index => {
+                self.connected_peers
+                    .insert(peer_id.clone(), (*best_block_number, *best_block_hash));
+            }
+            network_service::Event::Disconnected {
+                peer_id,
+                chain_index,
+            } if *chain_index == self.network_chain_index => {
+                self.connected_peers.remove(peer_id);
+                self.banned_peers.remove(peer_id);
+            }
+            network_service::Event::BlockAnnounce {
+                chain_index,
+                peer_id,
+                header,
+                is_best: true,
+            } if *chain_index == self.network_chain_index => {
+                if let Some(best) = self.connected_peers.get_mut(peer_id) {
+                    *best = (header.number, header.hash(self.block_number_bytes));
+                }
+            }
+            _ => {
+                // Different chain index or non-best block.
+            }
+        }
+    }
+
+    /// Returns the next peer to send a request to, or `None` if no peer that isn't banned is
+    /// connected.
+    fn next_peer(&mut self) -> Option<libp2p::PeerId> {
+        let num_candidates = self.connected_peers.len() - self.banned_peers.len();
+        if num_candidates == 0 {
+            return None;
+        }
+
+        let index = self.next_peer_index % num_candidates;
+        self.next_peer_index = self.next_peer_index.wrapping_add(1);
+        self.connected_peers
+            .keys()
+            .filter(|peer_id| !self.banned_peers.contains(*peer_id))
+            .nth(index)
+            .cloned()
+    }
+
+    /// Bans the given peer, which has sent an invalid response, from the chain.
+    async fn ban_peer(&mut self, peer_id: libp2p::PeerId, reason: &str) {
+        self.network_service
+            .ban_peer(self.network_chain_index, peer_id.clone(), reason)
+            .await;
+        if self.connected_peers.contains_key(&peer_id) {
+            self.banned_peers.insert(peer_id);
+        }
+    }
+
+    /// Runs a GrandPa warp sync until the latest finalized block of the chain. Returns the chain
+    /// information and runtime of this finalized block.
+    async fn warp_sync_to_finalized(
+        &mut self,
+        chain_information: chain_information::ValidChainInformation,
+    ) -> (
+        chain_information::ValidChainInformation,
+        host::HostVmPrototype,
+    ) {
+        let mut sync = all::AllSync::<future::AbortHandle, libp2p::PeerId, ()>::new(all::Config {
+            chain_information,
+            block_number_bytes: self.block_number_bytes,
+            allow_unknown_consensus_engines: false,
+            sources_capacity: 32,
+            blocks_capacity: 1024,
+            max_disjoint_headers: 1024,
+            max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            download_ahead_blocks: NonZeroU32::new(2000).unwrap(),
+            full: None,
+        });
+
+        let mut peers_source_id_map =
+            hashbrown::HashMap::<_, _, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+                32,
+                Default::default(),
+            );
+
+        let mut pending_requests = stream::FuturesUnordered::<
+            future::BoxFuture<'static, (all::RequestId, Result<RequestOutcome, future::Aborted>)>,
+        >::new();
+
+        loop {
+            // Start the requests that the sync state machine desires, while enforcing a maximum
+            // of one request per source.
+            loop {
+                let Some((source_id, peer_id, request)) = sync
+                    .desired_requests()
+                    .find(|(source_id, _, request)| {
+                        // Blocks are downloaded only after the warp sync is finished.
+                        sync.source_num_ongoing_requests(*source_id) == 0
+                            && !matches!(request, all::DesiredRequest::BlocksRequest { .. })
+                    })
+                    .map(|(source_id, peer_id, request)| (source_id, peer_id.clone(), request))
+                else {
+                    break;
+                };
+
+                let request_future = match &request {
+                    all::DesiredRequest::GrandpaWarpSync {
+                        sync_start_block_hash,
+                    } => self
+                        .network_service
+                        .clone()
+                        .grandpa_warp_sync_request(
+                            peer_id,
+                            self.network_chain_index,
+                            *sync_start_block_hash,
+                        )
+                        .map(|result| RequestOutcome::GrandpaWarpSync(result.map_err(|_| ())))
+                        .boxed(),
+                    all::DesiredRequest::StorageGetMerkleProof {
+                        block_hash, keys, ..
+                    } => self
+                        .network_service
+                        .clone()
+                        .storage_proof_request(
+                            peer_id,
+                            self.network_chain_index,
+                            protocol::StorageProofRequestConfig {
+                                block_hash: *block_hash,
+                                keys: keys.clone().into_iter(),
+                            },
+                        )
+                        .map(|result| {
+                            RequestOutcome::StorageProof(
+                                result.map(|proof| proof.decode().to_vec()).map_err(|_| ()),
+                            )
+                        })
+                        .boxed(),
+                    all::DesiredRequest::RuntimeCallMerkleProof {
+                        block_hash,
+                        function_name,
+                        parameter_vectored,
+                    } => {
+                        let network_service = self.network_service.clone();
+                        let network_chain_index = self.network_chain_index;
+                        let block_hash = *block_hash;
+                        let function_name = function_name.clone();
+                        let parameter_vectored = parameter_vectored.clone();
+                        async move {
+                            let result = network_service
+                                .call_proof_request(
+                                    peer_id,
+                                    network_chain_index,
+                                    protocol::CallProofRequestConfig {
+                                        block_hash,
+                                        method: &function_name,
+                                        parameter_vectored: iter::once(parameter_vectored),
+                                    },
+                                )
+                                .await;
+                            RequestOutcome::CallProof(
+                                result.map(|proof| proof.decode().to_vec()).map_err(|_| ()),
+                            )
+                        }
+                        .boxed()
+                    }
+                    all::DesiredRequest::BlocksRequest { .. } => unreachable!(),
+                };
+
+                let (request_future, abort) = future::abortable(request_future);
+                let request_id = sync.add_request(source_id, request.into(), abort);
+                pending_requests.push(async move { (request_id, request_future.await) }.boxed());
+            }
+
+            // Verify the warp sync fragments that have been received.
+            loop {
+                match sync.process_one() {
+                    all::ProcessOne::AllSync(idle) => {
+                        sync = idle;
+                        break;
+                    }
+                    all::ProcessOne::VerifyWarpSyncFragment(verify) => {
+                        let sender_peer_id = verify.proof_sender().1.clone();
+                        let (updated, result) = verify.perform(rand::random());
+                        sync = updated;
+                        if let Err(err) = result {
+                            log::warn!(
+                                "warp-sync-fragment-error; peer_id={}; error={}",
+                                sender_peer_id,
+                                err
+                            );
+                            self.ban_peer(sender_peer_id, "invalid-warp-sync-fragment")
+                                .await;
+                        }
+                    }
+                    all::ProcessOne::WarpSyncError {
+                        sync: updated,
+                        error,
+                    } => {
+                        sync = updated;
+                        log::warn!("warp-sync-error; error={}", error);
+                    }
+                    all::ProcessOne::WarpSyncFinished {
+                        sync: finished,
+                        finalized_block_runtime,
+                        ..
+                    } => {
+                        return (
+                            finished.as_chain_information().into(),
+                            finalized_block_runtime,
+                        );
+                    }
+                    all::ProcessOne::VerifyHeader(_)
+                    | all::ProcessOne::VerifyFinalityProof(_)
+                    | all::ProcessOne::VerifyBodyHeader(_) => {
+                        // Blocks, headers, and justifications are never downloaded before the
+                        // warp sync is finished.
+                        unreachable!()
+                    }
+                }
+            }
+
+            futures::select! {
+                network_event = self.network_events_receiver.next().fuse() => {
+                    // We expect the network events channel to never shut down.
+                    let network_event = network_event.unwrap();
+                    self.on_network_event(&network_event);
+
+                    match network_event {
+                        network_service::Event::Connected { peer_id, chain_index, best_block_number, best_block_hash }
+                            if chain_index == self.network_chain_index =>
+                        {
+                            let id = sync.add_source(peer_id.clone(), best_block_number, best_block_hash);
+                            peers_source_id_map.insert(peer_id, id);
+                        }
+                        network_service::Event::Disconnected { peer_id, chain_index }
+                            if chain_index == self.network_chain_index =>
+                        {
+                            // Requests in progress are aborted, and their responses ignored.
+                            if let Some(id) = peers_source_id_map.remove(&peer_id) {
+                                let (_, requests) = sync.remove_source(id);
+                                for (_, abort) in requests {
+                                    abort.abort();
+                                }
+                            }
+                        }
+                        network_service::Event::BlockAnnounce { chain_index, peer_id, header, is_best }
+                            if chain_index == self.network_chain_index =>
+                        {
+                            if let Some(id) = peers_source_id_map.get(&peer_id) {
+                                let _ = sync.block_announce(*id, header.scale_encoding_vec(self.block_number_bytes), is_best);
+                            }
+                        }
+                        _ => {
+                            // Different chain index.
+                        }
+                    }
+                },
+
+                (request_id, result) = pending_requests.select_next_some() => {
+                    // `result` is an error if the request got aborted because its source has
+                    // disconnected.
+                    let Ok(result) = result else { continue };
+
+                    match result {
+                        RequestOutcome::GrandpaWarpSync(Ok(response)) => {
+                            let decoded = response.decode();
+                            let fragments = decoded
+                                .fragments
+                                .into_iter()
+                                .map(|fragment| all::WarpSyncFragment {
+                                    scale_encoded_header: fragment.scale_encoded_header.to_vec(),
+                                    scale_encoded_justification: fragment.scale_encoded_justification.to_vec(),
+                                })
+                                .collect();
+                            let _ = sync.grandpa_warp_sync_response_ok(request_id, fragments, decoded.is_finished);
+                        }
+                        RequestOutcome::GrandpaWarpSync(Err(())) => {
+                            let _ = sync.grandpa_warp_sync_response_err(request_id);
+                        }
+                        RequestOutcome::StorageProof(response) => {
+                            let _ = sync.storage_get_response(request_id, response);
+                        }
+                        RequestOutcome::CallProof(response) => {
+                            let _ = sync.call_proof_response(request_id, response);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// Downloads the body and GrandPa justification of the given block from the connected
+    /// peers.
+    ///
+    /// The body is verified against the given extrinsics root, found in the header of the block.
+    async fn download_body(
+        &mut self,
+        block_hash: &[u8; 32],
+        extrinsics_root: &[u8; 32],
+    ) -> (Vec<Vec<u8>>, Option<Vec<u8>>) {
+        let mut pending_request = stream::FuturesUnordered::new();
+
+        loop {
+            if pending_request.is_empty() {
+                if let Some(peer_id) = self.next_peer() {
+                    let request = self.network_service.clone().blocks_request(
+                        peer_id.clone(),
+                        self.network_chain_index,
+                        protocol::BlocksRequestConfig {
+                            start: protocol::BlocksRequestConfigStart::Hash(*block_hash),
+                            desired_count: NonZeroU32::new(1).unwrap(),
+                            direction: protocol::BlocksRequestDirection::Ascending,
+                            fields: protocol::BlocksRequestFields {
+                                header: false,
+                                body: true,
+                                justifications: true,
+                            },
+                        },
+                    );
+                    pending_request.push(request.map(move |result| (peer_id, result)));
+                }
+            }
+
+            let (peer_id, result) = futures::select! {
+                network_event = self.network_events_receiver.next().fuse() => {
+                    // We expect the network events channel to never shut down.
+                    self.on_network_event(&network_event.unwrap());
+                    continue;
+                },
+                result = pending_request.select_next_some() => result,
+            };
+
+            let block = match result {
+                Ok(blocks) => blocks.into_iter().next(),
+                Err(err) => {
+                    log::warn!(
+                        "finalized-block-download-error; peer_id={}; error={}",
+                        peer_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            // The response can't be trusted. The body must match the header of the block, which
+            // has already been verified.
+            let Some(protocol::BlockData {
+                body: Some(body),
+                justifications,
+                ..
+            }) = block
+            else {
+                log::warn!("finalized-block-download-missing-body; peer_id={}", peer_id);
+                self.ban_peer(peer_id, "missing-block-body").await;
+                continue;
+            };
+
+            if header::extrinsics_root(&body) != *extrinsics_root {
+                log::warn!("finalized-block-download-bad-body; peer_id={}", peer_id);
+                self.ban_peer(peer_id, "invalid-block-body").await;
+                continue;
+            }
+
+            let justification = justifications
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(engine_id, _)| engine_id == b"FRNK")
+                .map(|(_, justification)| justification);
+            return (body, justification);
+        }
+    }
+
+    /// Downloads the main trie, or the default child trie whose key is passed as parameter, of
+    /// the storage of the given block from the connected peers.
+    ///
+    /// Every response is verified against the given trie root, and only the entries that the
+    /// response proves to be contiguous are kept. The download is thus guaranteed to be
+    /// complete and correct when this function returns.
+    ///
+    /// `state_version` is the version of the trie entries whose version can't be determined from
+    /// the proofs.
+    async fn download_trie(
+        &mut self,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        trie_root: &[u8; 32],
+        state_version: TrieEntryVersion,
+    ) -> BTreeMap<Vec<u8>, (Vec<u8>, TrieEntryVersion)> {
+        let mut entries = BTreeMap::new();
+        let mut next_start_key = Vec::new();
+        let mut pending_request = stream::FuturesUnordered::new();
+
+        loop {
+            if pending_request.is_empty() {
+                if let Some(peer_id) = self.next_peer() {
+                    let network_service = self.network_service.clone();
+                    let network_chain_index = self.network_chain_index;
+                    let block_hash = *block_hash;
+                    let child_trie = child_trie.map(|child_trie| child_trie.to_vec());
+                    let start_key = next_start_key.clone();
+                    pending_request.push(async move {
+                        let result = network_service
+                            .state_request(
+                                peer_id.clone(),
+                                network_chain_index,
+                                block_hash,
+                                match &child_trie {
+                                    None => protocol::StateRequestStart::MainTrie(&start_key),
+                                    Some(child_trie) => {
+                                        protocol::StateRequestStart::ChildTrieDefault {
+                                            child_trie,
+                                            key: &start_key,
+                                        }
+                                    }
+                                },
+                            )
+                            .await;
+                        (peer_id, result)
+                    });
+                }
+            }
+
+            let (peer_id, result) = futures::select! {
+                network_event = self.network_events_receiver.next().fuse() => {
+                    // We expect the network events channel to never shut down.
+                    self.on_network_event(&network_event.unwrap());
+                    continue;
+                },
+                result = pending_request.select_next_some() => result,
+            };
+
+            let response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    log::warn!("state-request-error; peer_id={}; error={}", peer_id, err);
+                    continue;
+                }
+            };
+
+            let VerifiedStateResponse {
+                entries: new_entries,
+                is_complete,
+            } = match verify_state_response(
+                trie_root,
+                &next_start_key,
+                response.decode(),
+                state_version,
+            ) {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    log::warn!("state-response-error; peer_id={}; error={}", peer_id, err);
+                    self.ban_peer(peer_id, "invalid-state-response").await;
+                    continue;
+                }
+            };
+
+            let mut made_progress = false;
+            for (key, value) in new_entries {
+                next_start_key.clone_from(&key);
+                made_progress |= entries.insert(key, value).is_none();
+            }
+
+            if is_complete {
+                return entries;
+            }
+
+            // Every response is expected to contain at least one new entry. Otherwise, the peer
+            // could prevent the download from progressing.
+            if !made_progress {
+                log::warn!("state-response-no-progress; peer_id={}", peer_id);
+                self.ban_peer(peer_id, "state-response-no-progress").await;
+                continue;
+            }
+
+            log::debug!(
+                "state-download-progress; child_trie={}; num_entries={}; last_key={}",
+                child_trie.map_or_else(|| "none".to_owned(), hex::encode),
+                entries.len(),
+                hex::encode(&next_start_key)
+            );
+        }
+    }
+}
+
+/// Verifies the compact proof found in a state response against the root of the trie being
+/// downloaded.
+///
+/// On success, returns the storage entries whose key is superior or equal to `start_key` and
+/// that the proof guarantees to be contiguous.
+fn verify_state_response(
+    trie_root: &[u8; 32],
+    start_key: &[u8],
+    compact_proof: &[u8],
+    state_version: TrieEntryVersion,
+) -> Result<VerifiedStateResponse, StateResponseError> {
+    // A response can contain multiple tries: the main trie and child tries. Only the one being
+    // downloaded is relevant.
+    let trie = compact_proof::decode(compact_proof)
+        .map_err(StateResponseError::CompactProof)?
+        .into_iter()
+        .find(|trie| trie.trie_root_hash == *trie_root)
+        .ok_or(StateResponseError::TrieNotFound)?;
+
+    let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+        trie_root_hash: trie_root,
+        proof: &trie.proof,
+    })
+    .map_err(StateResponseError::Proof)?;
+
+    let (entries, is_complete) = decoded.iter_runtime_context_ordered_contiguous(start_key);
+    let entries = entries
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let proof_decode::StorageValue::Known { value, inline } = value else {
+                return None;
+            };
+
+            // Values that are hashed indicate version 1 of the trie, while values that are
+            // inline despite being longer than 32 bytes indicate version 0. Short values are
+            // always inline, in which case the version can't be known.
+            let version = if !inline {
+                TrieEntryVersion::V1
+            } else if value.len() >= 33 {
+                TrieEntryVersion::V0
+            } else {
+                state_version
+            };
+
+            Some((key, (value.to_vec(), version)))
+        })
+        .collect();
+
+    Ok(VerifiedStateResponse {
+        entries,
+        is_complete,
+    })
+}
+
+/// Outcome of [`verify_state_response`].
+struct VerifiedStateResponse {
+    /// Storage entries of the trie, ordered by key. The trie is guaranteed to not contain any
+    /// other entry between the first and the last key of this list.
+    entries: Vec<(Vec<u8>, (Vec<u8>, TrieEntryVersion))>,
+    /// `true` if the trie is guaranteed to not contain any entry after the last key of
+    /// [`VerifiedStateResponse::entries`].
+    is_complete: bool,
+}
+
+/// Error while processing a state response.
+#[derive(Debug, derive_more::Display)]
+enum StateResponseError {
+    /// Failed to decode the compact proof.
+    #[display(fmt = "Failed to decode compact proof: {_0}")]
+    CompactProof(compact_proof::Error),
+    /// The response doesn't contain the trie being downloaded.
+    TrieNotFound,
+    /// The proof is invalid.
+    #[display(fmt = "Invalid proof: {_0}")]
+    Proof(proof_decode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use smoldot::trie::{calculate_root, trie_node, Nibble, TrieEntryVersion};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn verify_state_response_keeps_contiguous_entries() {
+        // Trie made of a branch node at `0x6` with three leaves at `a`, `b` and `c`. The values
+        // are longer than 32 bytes so that the leaves aren't inlined in the branch node.
+        let mut storage = BTreeMap::new();
+        storage.insert(b"a".to_vec(), (vec![1; 40], TrieEntryVersion::V0));
+        storage.insert(b"b".to_vec(), (vec![2; 40], TrieEntryVersion::V0));
+        storage.insert(b"c".to_vec(), (vec![3; 40], TrieEntryVersion::V0));
+
+        let trie_root = {
+            let mut calculation = calculate_root::root_merkle_value(None);
+            loop {
+                match calculation {
+                    calculate_root::RootMerkleValueCalculation::Finished { hash, .. } => {
+                        break hash
+                    }
+                    calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                        calculation = keys.inject(storage.keys().map(|k| k.iter().copied()));
+                    }
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value_request) => {
+                        let key = value_request.key().collect::<Vec<u8>>();
+                        calculation =
+                            value_request.inject(storage.get(&key).map(|(val, v)| (val, *v)));
+                    }
+                }
+            }
+        };
+
+        let encode = |partial_key: &[u8],
+                      children: [Option<&[u8]>; 16],
+                      storage_value: trie_node::StorageValue| {
+            trie_node::encode_to_vec(trie_node::Decoded {
+                partial_key: partial_key
+                    .iter()
+                    .map(|n| Nibble::try_from(*n).unwrap())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                children,
+                storage_value,
+            })
+            .unwrap()
+        };
+
+        // Compact proofs are a SCALE-encoded list of entries. All the lengths used here are
+        // shorter than 2^14, in which case their SCALE-compact encoding is one or two bytes.
+        let compact_proof = |entries: &[Vec<u8>]| {
+            let encode_len = |len: usize| {
+                if len < 1 << 6 {
+                    vec![u8::try_from(len << 2).unwrap()]
+                } else {
+                    u16::try_from((len << 2) | 1)
+                        .unwrap()
+                        .to_le_bytes()
+                        .to_vec()
+                }
+            };
+
+            let mut proof = encode_len(entries.len());
+            for entry in entries {
+                proof.extend(encode_len(entry.len()));
+                proof.extend_from_slice(entry);
+            }
+            proof
+        };
+
+        let leaves = [1u8, 2, 3]
+            .map(|n| encode(&[], [None; 16], trie_node::StorageValue::Unhashed(&[n; 40])));
+
+        // The proof contains all the nodes. Children present in a compact proof are omitted from
+        // the node value of their parent.
+        let mut root_children = [None; 16];
+        for child in &mut root_children[1..=3] {
+            *child = Some(&[][..]);
+        }
+        let full_proof = compact_proof(&[
+            encode(&[6], root_children, trie_node::StorageValue::None),
+            leaves[0].clone(),
+            leaves[1].clone(),
+            leaves[2].clone(),
+        ]);
+
+        let super::VerifiedStateResponse {
+            entries,
+            is_complete,
+        } = super::verify_state_response(&trie_root, b"", &full_proof, TrieEntryVersion::V1)
+            .unwrap();
+        assert!(is_complete);
+        assert_eq!(entries, storage.clone().into_iter().collect::<Vec<_>>());
+
+        let super::VerifiedStateResponse {
+            entries,
+            is_complete,
+        } = super::verify_state_response(&trie_root, b"b", &full_proof, TrieEntryVersion::V1)
+            .unwrap();
+        assert!(is_complete);
+        assert_eq!(
+            entries.into_iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+
+        // The proof contains the root node and the leaf at `a`. The leaves at `b` and `c` are
+        // only referenced by their hash, and can thus not be known.
+        let leaves_hashes = leaves.clone().map(|leaf| {
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &leaf).as_bytes()).unwrap()
+        });
+        let mut root_children = [None; 16];
+        root_children[1] = Some(&[][..]);
+        root_children[2] = Some(&leaves_hashes[1][..]);
+        root_children[3] = Some(&leaves_hashes[2][..]);
+        let partial_proof = compact_proof(&[
+            encode(&[6], root_children, trie_node::StorageValue::None),
+            leaves[0].clone(),
+        ]);
+
+        let super::VerifiedStateResponse {
+            entries,
+            is_complete,
+        } = super::verify_state_response(&trie_root, b"", &partial_proof, TrieEntryVersion::V1)
+            .unwrap();
+        assert!(!is_complete);
+        assert_eq!(
+            entries.into_iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec![b"a".to_vec()]
+        );
+
+        // A response that starts in the unknown part of the trie doesn't make any progress.
+        let super::VerifiedStateResponse {
+            entries,
+            is_complete,
+        } = super::verify_state_response(&trie_root, b"b", &partial_proof, TrieEntryVersion::V1)
+            .unwrap();
+        assert!(!is_complete);
+        assert!(entries.is_empty());
+
+        // Proofs of a different trie are rejected.
+        assert!(matches!(
+            super::verify_state_response(&[0; 32], b"", &full_proof, TrieEntryVersion::V1),
+            Err(super::StateResponseError::TrieNotFound)
+        ));
+        assert!(matches!(
+            super::verify_state_response(&trie_root, b"", &[1, 2, 3], TrieEntryVersion::V1),
+            Err(super::StateResponseError::CompactProof(_))
+        ));
+    }
+}
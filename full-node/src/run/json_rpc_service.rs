@@ -15,17 +15,92 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use futures::{channel::oneshot, prelude::*};
-use smoldot::json_rpc::{self, methods, websocket_server};
-use std::{io, net::SocketAddr};
+use crate::run::{consensus_service, database_thread, network_service};
+
+use futures::{channel::oneshot, prelude::*, stream};
+use smoldot::{
+    finality::grandpa::finality_proof,
+    header,
+    json_rpc::{self, methods, websocket_server},
+    libp2p::{
+        multiaddr::{Multiaddr, ProtocolRef},
+        PeerId,
+    },
+    trie,
+};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum number of requests that a single batch can contain. Larger batches are refused as a
+/// whole.
+const MAX_REQUESTS_PER_BATCH: usize = 64;
+
+/// Maximum number of messages, either single calls or batches, that are processed in parallel.
+/// No new message is read from the clients while this limit is reached.
+const MAX_PARALLEL_MESSAGES: usize = 32;
+
+/// Maximum number of subscriptions that a single connection can have at the same time. Each
+/// subscription holds a queue of network events, and additional subscriptions are refused.
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 16;
+
+/// Maximum number of headers that a `grandpa_proveFinality` response can contain. Requests that
+/// would need more headers are refused, as they would need to load that many headers in memory.
+const MAX_FINALITY_PROOF_HEADERS: u64 = 1024;
+
+/// Maximum number of keys that a `state_getReadProof` request can contain. Larger requests are
+/// refused.
+const MAX_READ_PROOF_KEYS: usize = 64;
 
 /// Configuration for a [`JsonRpcService`].
 pub struct Config<'a> {
     /// Closure that spawns background tasks.
     pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
 
-    /// Where to bind the WebSocket server.
+    /// Where to bind the WebSocket server. Plain HTTP requests are also accepted on this address.
     pub bind_address: SocketAddr,
+
+    /// Maximum number of simultaneous connections to the server. Additional connections are
+    /// refused.
+    pub max_clients: usize,
+
+    /// Maximum size, in bytes, of a request. Applies both to WebSocket messages and to the body
+    /// of HTTP requests.
+    pub max_request_size: usize,
+
+    /// Origins of the web pages allowed to send HTTP requests to the server. See
+    /// [`websocket_server::Config::cors_allowed_origins`].
+    pub cors_allowed_origins: Vec<String>,
+
+    /// If `false`, the JSON-RPC functions that modify the state of the node, such as
+    /// `system_addReservedPeer`, or that are expensive to answer, such as `system_dryRun`, are
+    /// refused. Must be `false` if the server is reachable by untrusted clients.
+    pub allow_unsafe_methods: bool,
+
+    /// Addresses the networking of the node is listening on, including the `/p2p` suffix.
+    /// Reported through `system_localListenAddresses`.
+    pub local_listen_addresses: Vec<String>,
+
+    /// Database of the chain. Used to answer storage-related requests.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Consensus service of the chain. Used to perform runtime calls on top of the best and
+    /// finalized blocks, whose storage it holds in memory.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Number of bytes used to encode the block number in headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Network service of the node, and index of the chain within it. Used to report the state
+    /// of the networking and its events.
+    pub network_service: (Arc<network_service::NetworkService>, usize),
 }
 
 /// Running JSON-RPC service. Holds a server open for as long as it is alive.
@@ -40,9 +115,11 @@ impl JsonRpcService {
         let server = {
             let result = websocket_server::WsServer::new(websocket_server::Config {
                 bind_address: config.bind_address,
-                capacity: 1,
-                max_frame_size: 4096,
+                capacity: config.max_clients,
+                max_frame_size: config.max_request_size,
+                max_http_body_size: config.max_request_size,
                 send_buffer_len: 16384,
+                cors_allowed_origins: config.cors_allowed_origins,
             })
             .await;
 
@@ -61,6 +138,21 @@ impl JsonRpcService {
 
         let background = JsonRpcBackground {
             server,
+            max_clients: config.max_clients,
+            shared: Arc::new(Shared {
+                allow_unsafe_methods: config.allow_unsafe_methods,
+                local_listen_addresses: config.local_listen_addresses,
+                database: config.database,
+                consensus_service: config.consensus_service,
+                block_number_bytes: config.block_number_bytes,
+                network_service: config.network_service,
+                next_subscription_id: AtomicU64::new(0),
+            }),
+            connections: HashMap::new(),
+            next_connection_generation: 0,
+            network_events_subscriptions: HashMap::new(),
+            notifications: stream::SelectAll::new(),
+            messages_in_progress: stream::FuturesUnordered::new(),
             client_still_alive: client_still_alive.fuse(),
         };
 
@@ -86,59 +178,825 @@ struct JsonRpcBackground {
     /// State machine of the WebSocket server. Holds the TCP socket.
     server: websocket_server::WsServer<SocketAddr>,
 
+    /// See [`Config::max_clients`].
+    max_clients: usize,
+
+    /// State accessed by the messages being processed.
+    shared: Arc<Shared>,
+
+    /// List of connections currently open, with a generation number that is unique for each
+    /// connection. Because [`websocket_server::ConnectionId`]s can be reused, the generation is
+    /// used to detect that the connection a message has been received on has been closed while
+    /// the message was being processed.
+    ///
+    /// Also contains the number of subscriptions of each connection, including the ones being
+    /// created, which is compared against [`MAX_SUBSCRIPTIONS_PER_CONNECTION`].
+    connections: HashMap<websocket_server::ConnectionId, (u64, Arc<AtomicUsize>)>,
+
+    /// Generation number to assign to the next connection.
+    next_connection_generation: u64,
+
+    /// List of active `network_unstable_subscribeEvents` subscriptions, indexed by subscription
+    /// id, with the connection they belong to. Dropping the sender stops the subscription.
+    network_events_subscriptions:
+        HashMap<String, (websocket_server::ConnectionId, oneshot::Sender<()>)>,
+
+    /// Notifications generated by the active subscriptions, and the connection to send them to.
+    notifications:
+        stream::SelectAll<stream::BoxStream<'static, (websocket_server::ConnectionId, String)>>,
+
+    /// Messages being processed, with the connection they have been received on, the generation
+    /// of this connection, and whether the message is an HTTP request.
+    messages_in_progress: stream::FuturesUnordered<
+        future::BoxFuture<'static, (websocket_server::ConnectionId, u64, bool, Outcome)>,
+    >,
+
     /// As long as this channel is pending, the frontend of the JSON-RPC server is still alive.
     client_still_alive: future::Fuse<oneshot::Receiver<()>>,
 }
 
+/// State of the server that is accessed when processing messages. Messages are processed in
+/// parallel, and this state is thus shared between them.
+struct Shared {
+    /// See [`Config::allow_unsafe_methods`].
+    allow_unsafe_methods: bool,
+
+    /// See [`Config::local_listen_addresses`].
+    local_listen_addresses: Vec<String>,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::consensus_service`].
+    consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::network_service`].
+    network_service: (Arc<network_service::NetworkService>, usize),
+
+    /// Identifier to assign to the next subscription.
+    next_subscription_id: AtomicU64,
+}
+
+/// Outcome of processing a message or a single call.
+#[derive(Default)]
+struct Outcome {
+    /// Response to send back, if any.
+    response: Option<String>,
+
+    /// Subscriptions that have been created. Their notifications must only be sent after the
+    /// response, as the client can't know the subscription id beforehand.
+    new_subscriptions: Vec<NewSubscription>,
+
+    /// Identifiers of the subscriptions that the client has asked to stop.
+    unsubscriptions: Vec<String>,
+}
+
+/// Subscription created while processing a call. See [`Outcome::new_subscriptions`].
+struct NewSubscription {
+    /// Identifier of the subscription.
+    id: String,
+
+    /// Dropping this sender stops the subscription.
+    stop: oneshot::Sender<()>,
+
+    /// Notifications to send to the client.
+    notifications: stream::BoxStream<'static, String>,
+}
+
 impl JsonRpcBackground {
     async fn run(mut self) {
         loop {
+            // No new message is read from the clients if too many are being processed.
+            let can_read_messages = self.messages_in_progress.len() < MAX_PARALLEL_MESSAGES;
+            let server = &mut self.server;
+
             let event = futures::select! {
                 _ = &mut self.client_still_alive => return,
-                event = self.server.next_event().fuse() => event,
+                event = async move {
+                    if can_read_messages {
+                        server.next_event().await
+                    } else {
+                        future::pending().await
+                    }
+                }.fuse() => either::Left(either::Left(event)),
+                notification = self.notifications.select_next_some() => {
+                    either::Left(either::Right(notification))
+                },
+                processed = self.messages_in_progress.select_next_some() => {
+                    either::Right(processed)
+                },
+            };
+
+            let event = match event {
+                either::Left(either::Left(event)) => event,
+                either::Left(either::Right((connection_id, notification))) => {
+                    self.server.queue_send(connection_id, notification);
+                    continue;
+                }
+                either::Right((connection_id, generation, is_http, outcome)) => {
+                    self.on_message_processed(connection_id, generation, is_http, outcome);
+                    continue;
+                }
             };
 
-            let (connection_id, message) = match event {
+            let (connection_id, message, is_http) = match event {
                 websocket_server::Event::ConnectionOpen { address, .. } => {
+                    if self.server.len() >= self.max_clients {
+                        log::debug!("incoming-connection-refused; address={}", address);
+                        self.server.reject();
+                        continue;
+                    }
+
                     log::debug!("incoming-connection; address={}", address);
-                    self.server.accept(address);
+                    let connection_id = self.server.accept(address);
+                    self.connections.insert(
+                        connection_id,
+                        (
+                            self.next_connection_generation,
+                            Arc::new(AtomicUsize::new(0)),
+                        ),
+                    );
+                    self.next_connection_generation += 1;
                     continue;
                 }
                 websocket_server::Event::ConnectionError {
-                    user_data: address, ..
+                    connection_id,
+                    user_data: address,
                 } => {
                     log::debug!("connection-closed; address={}", address);
+                    self.connections.remove(&connection_id);
+                    // Dropping the senders stops the subscriptions of this connection.
+                    self.network_events_subscriptions
+                        .retain(|_, (subscription_connection, _)| {
+                            *subscription_connection != connection_id
+                        });
                     continue;
                 }
                 websocket_server::Event::TextFrame {
                     connection_id,
                     message,
                     ..
-                } => (connection_id, message),
+                } => (connection_id, message, false),
+                // HTTP requests are answered exactly like WebSocket messages, with the exception
+                // that the connection must be closed if there is no response to send back.
+                websocket_server::Event::HttpRequest {
+                    connection_id,
+                    body,
+                    ..
+                } => (connection_id, body, true),
             };
 
-            let (request_id, _method) = match methods::parse_json_call(&message) {
-                Ok(v) => v,
-                Err(error) => {
-                    log::debug!("bad-request; error={:?}; message={:?}", error, message);
-                    self.server.close(connection_id);
-                    continue;
+            let (generation, num_subscriptions) = self.connections.get(&connection_id).unwrap();
+            let generation = *generation;
+            let num_subscriptions = num_subscriptions.clone();
+            let shared = self.shared.clone();
+            self.messages_in_progress.push(
+                async move {
+                    // Subscriptions aren't possible over HTTP, as the connection is closed after
+                    // the response has been sent.
+                    let outcome = shared
+                        .handle_message(
+                            &message,
+                            if is_http {
+                                None
+                            } else {
+                                Some(&num_subscriptions)
+                            },
+                        )
+                        .await;
+                    (connection_id, generation, is_http, outcome)
                 }
-            };
+                .boxed(),
+            );
+        }
+    }
+
+    /// Called when a message received on the given connection has finished being processed.
+    fn on_message_processed(
+        &mut self,
+        connection_id: websocket_server::ConnectionId,
+        generation: u64,
+        is_http: bool,
+        outcome: Outcome,
+    ) {
+        // The connection might have been closed, and its identifier reused, in the meantime.
+        // Dropping `outcome` stops the subscriptions that have been created.
+        let num_subscriptions = match self.connections.get(&connection_id) {
+            Some((connection_generation, num_subscriptions))
+                if *connection_generation == generation =>
+            {
+                num_subscriptions.clone()
+            }
+            _ => return,
+        };
+
+        // A subscription can only be stopped by the connection it belongs to. Dropping the
+        // sender stops the stream of notifications.
+        for subscription in outcome.unsubscriptions {
+            if let Some((subscription_connection, _)) =
+                self.network_events_subscriptions.get(&subscription)
+            {
+                if *subscription_connection == connection_id {
+                    self.network_events_subscriptions.remove(&subscription);
+                    num_subscriptions.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        // No response is sent back to notifications or batches of notifications.
+        match outcome.response {
+            Some(response) => self.server.queue_send(connection_id, response),
+            None if is_http => {
+                self.server.close(connection_id);
+                self.connections.remove(&connection_id);
+            }
+            None => {}
+        }
+
+        // The notifications of the new subscriptions are only sent after the response.
+        for subscription in outcome.new_subscriptions {
+            self.network_events_subscriptions
+                .insert(subscription.id, (connection_id, subscription.stop));
+            self.notifications.push(
+                subscription
+                    .notifications
+                    .map(move |notification| (connection_id, notification))
+                    .boxed(),
+            );
+        }
+    }
+}
+
+impl Shared {
+    /// Processes a message sent by a client, which can be either a single call or a batch, and
+    /// returns its outcome.
+    ///
+    /// `num_subscriptions` is the number of subscriptions of the connection the message has been
+    /// received on, or `None` if subscriptions aren't supported.
+    async fn handle_message(
+        &self,
+        message: &str,
+        num_subscriptions: Option<&AtomicUsize>,
+    ) -> Outcome {
+        match json_rpc::parse::parse_request(message) {
+            Ok(json_rpc::parse::Request::Single(_)) => {
+                self.handle_call(message, num_subscriptions).await
+            }
+            Ok(json_rpc::parse::Request::Batch(calls)) if calls.len() > MAX_REQUESTS_PER_BATCH => {
+                log::debug!("batch-too-large; num_requests={}", calls.len());
+                Outcome {
+                    response: Some(json_rpc::parse::build_error_response(
+                        "null",
+                        json_rpc::parse::ErrorResponse::ServerError(
+                            -32000,
+                            "Too many requests in batch",
+                        ),
+                        None,
+                    )),
+                    ..Default::default()
+                }
+            }
+            Ok(json_rpc::parse::Request::Batch(calls)) => {
+                // The calls of the batch are processed in parallel, and the response is built
+                // once all of them have finished.
+                let outcomes = future::join_all(
+                    calls
+                        .into_iter()
+                        .map(|call| self.handle_call(call, num_subscriptions)),
+                )
+                .await;
 
-            log::debug!("request; request_id={:?}; method={:?}", request_id, _method);
+                let mut responses = Vec::with_capacity(outcomes.len());
+                let mut batch_outcome = Outcome::default();
+                for outcome in outcomes {
+                    responses.extend(outcome.response);
+                    batch_outcome
+                        .new_subscriptions
+                        .extend(outcome.new_subscriptions);
+                    batch_outcome
+                        .unsubscriptions
+                        .extend(outcome.unsubscriptions);
+                }
+                batch_outcome.response = json_rpc::parse::build_batch_response(responses);
+                batch_outcome
+            }
+            Err(error) => {
+                log::debug!("bad-request; error={}; message={:?}", error, message);
+                Outcome {
+                    response: Some(json_rpc::parse::build_error_response(
+                        "null",
+                        json_rpc::parse::ErrorResponse::ParseError,
+                        None,
+                    )),
+                    ..Default::default()
+                }
+            }
+        }
+    }
 
-            self.server.queue_send(
-                connection_id,
+    /// Processes a single JSON-RPC call and returns its outcome. No response is sent back if the
+    /// call is a notification.
+    ///
+    /// See [`Shared::handle_message`] for `num_subscriptions`.
+    async fn handle_call(
+        &self,
+        call_json: &str,
+        num_subscriptions: Option<&AtomicUsize>,
+    ) -> Outcome {
+        let (request_id, method) = match methods::parse_json_call(call_json) {
+            Ok(v) => v,
+            Err(methods::ParseError::Method { request_id, error }) => {
+                log::debug!("bad-request; request_id={}; error={}", request_id, error);
+                return Outcome {
+                    response: Some(error.to_json_error(request_id)),
+                    ..Default::default()
+                };
+            }
+            Err(methods::ParseError::UnknownNotification(method)) => {
+                log::debug!("unknown-notification; method={}", method);
+                return Outcome::default();
+            }
+            Err(methods::ParseError::JsonRpcParse(error)) => {
+                log::debug!("bad-request; error={}; message={:?}", error, call_json);
+                return Outcome {
+                    response: Some(json_rpc::parse::build_error_response(
+                        "null",
+                        json_rpc::parse::ErrorResponse::InvalidRequest,
+                        None,
+                    )),
+                    ..Default::default()
+                };
+            }
+        };
+
+        log::debug!("request; request_id={:?}; method={:?}", request_id, method);
+
+        let response = match method {
+            methods::MethodCall::system_localListenAddresses {} => {
+                methods::Response::system_localListenAddresses(self.local_listen_addresses.clone())
+                    .to_json_response(request_id)
+            }
+            methods::MethodCall::state_getReadProof { .. }
+            | methods::MethodCall::system_dryRun { .. }
+            | methods::MethodCall::system_addReservedPeer { .. }
+            | methods::MethodCall::system_removeReservedPeer { .. }
+                if !self.allow_unsafe_methods =>
+            {
                 json_rpc::parse::build_error_response(
                     request_id,
                     json_rpc::parse::ErrorResponse::ServerError(
                         -32000,
-                        "Not implemented in smoldot yet",
+                        "Unsafe JSON-RPC functions are disabled on this server",
                     ),
                     None,
+                )
+            }
+            methods::MethodCall::state_getReadProof { keys, at } => {
+                self.state_get_read_proof(request_id, keys, at).await
+            }
+            methods::MethodCall::system_dryRun { extrinsic, hash } => {
+                self.system_dry_run(request_id, extrinsic, hash).await
+            }
+            methods::MethodCall::system_addReservedPeer { peer } => {
+                self.system_add_reserved_peer(request_id, &peer).await
+            }
+            methods::MethodCall::system_removeReservedPeer { peer_id } => {
+                self.system_remove_reserved_peer(request_id, &peer_id).await
+            }
+            methods::MethodCall::system_networkState {} => {
+                self.system_network_state(request_id).await
+            }
+            methods::MethodCall::grandpa_roundState {} => {
+                self.grandpa_round_state(request_id).await
+            }
+            methods::MethodCall::grandpa_proveFinality { block_number } => {
+                self.grandpa_prove_finality(request_id, block_number).await
+            }
+            methods::MethodCall::network_unstable_subscribeEvents {} => {
+                return self
+                    .network_unstable_subscribe_events(request_id, num_subscriptions)
+                    .await;
+            }
+            methods::MethodCall::network_unstable_unsubscribeEvents { subscription } => {
+                // The subscription is stopped by the server once the call has been processed.
+                return Outcome {
+                    response: Some(
+                        methods::Response::network_unstable_unsubscribeEvents(())
+                            .to_json_response(request_id),
+                    ),
+                    unsubscriptions: vec![subscription.into_owned()],
+                    ..Default::default()
+                };
+            }
+            _ => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    "Not implemented in smoldot yet",
+                ),
+                None,
+            ),
+        };
+
+        Outcome {
+            response: Some(response),
+            ..Default::default()
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getReadProof`].
+    ///
+    /// Only the storage of the finalized block is available in the database, and thus only the
+    /// finalized block is supported.
+    ///
+    /// The database doesn't store the trie nodes, and building the proof requires going through
+    /// the entire storage of the finalized block. This function is thus considered unsafe.
+    async fn state_get_read_proof(
+        &self,
+        request_id: &str,
+        keys: Vec<methods::HexString>,
+        at: Option<methods::HashHexString>,
+    ) -> String {
+        if keys.len() > MAX_READ_PROOF_KEYS {
+            return json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::InvalidParams,
+                None,
+            );
+        }
+
+        let result = self
+            .database
+            .with_database(move |database| {
+                let finalized_block_hash = database
+                    .finalized_block_hash()
+                    .map_err(|err| err.to_string())?;
+                if matches!(at, Some(at) if at.0 != finalized_block_hash) {
+                    return Err("Only the finalized block is supported".to_owned());
+                }
+
+                // The proof is built while the entries are read from the database, in order to
+                // avoid loading the entire storage in memory.
+                let mut invalid_version = false;
+                let proof = database
+                    .with_finalized_block_storage_main_trie_ordered(
+                        &finalized_block_hash,
+                        |entries| {
+                            let entries = entries.map_while(|(key, value, version)| {
+                                match trie::TrieEntryVersion::try_from(version) {
+                                    Ok(version) => Some((key, value, version)),
+                                    Err(_) => {
+                                        invalid_version = true;
+                                        None
+                                    }
+                                }
+                            });
+                            trie::proof_encode::build_from_ordered_trie_entries(
+                                entries,
+                                keys.iter().map(|key| (&key.0, false)),
+                            )
+                            .build_to_vec()
+                        },
+                    )
+                    .map_err(|err| err.to_string())?;
+                if invalid_version {
+                    return Err("Corrupted database".to_owned());
+                }
+
+                Ok((finalized_block_hash, proof))
+            })
+            .await;
+
+        match result {
+            Ok((at, proof)) => methods::Response::state_getReadProof(methods::ReadProof {
+                at: methods::HashHexString(at),
+                // The proof has just been generated and its decoding can't fail.
+                proof: trie::proof_decode::decode_proof_entries(&proof)
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| methods::HexString(entry.to_vec()))
+                    .collect(),
+            })
+            .to_json_response(request_id),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                None,
+            ),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::system_dryRun`].
+    ///
+    /// Only the best and finalized blocks are supported, as the storage of the other blocks
+    /// isn't available.
+    async fn system_dry_run(
+        &self,
+        request_id: &str,
+        extrinsic: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) -> String {
+        let result = self
+            .consensus_service
+            .dry_run(hash.map(|hash| hash.0), extrinsic.0)
+            .await;
+
+        match result {
+            Ok(output) => methods::Response::system_dryRun(methods::HexString(output))
+                .to_json_response(request_id),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::system_addReservedPeer`].
+    async fn system_add_reserved_peer(&self, request_id: &str, peer: &str) -> String {
+        let mut address = match peer.parse::<Multiaddr>() {
+            Ok(address) => address,
+            Err(error) => {
+                return json_rpc::parse::build_error_response(
+                    request_id,
+                    json_rpc::parse::ErrorResponse::InvalidParams,
+                    Some(&serde_json::to_string(&error.to_string()).unwrap()),
+                )
+            }
+        };
+
+        let peer_id = match address.iter().last() {
+            Some(ProtocolRef::P2p(peer_id)) => PeerId::from_bytes(peer_id.to_vec()).ok(),
+            _ => None,
+        };
+        let Some(peer_id) = peer_id else {
+            return json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::InvalidParams,
+                Some(&serde_json::to_string("multiaddr doesn't end with /p2p").unwrap()),
+            );
+        };
+        address.pop();
+
+        let (network_service, chain_index) = &self.network_service;
+        network_service
+            .add_reserved_peer(*chain_index, peer_id, address)
+            .await;
+        methods::Response::system_addReservedPeer(()).to_json_response(request_id)
+    }
+
+    /// Handles a call to [`methods::MethodCall::system_removeReservedPeer`].
+    async fn system_remove_reserved_peer(&self, request_id: &str, peer_id: &str) -> String {
+        let Ok(peer_id) = peer_id.parse::<PeerId>() else {
+            return json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::InvalidParams,
+                Some(&serde_json::to_string("invalid peer id").unwrap()),
+            );
+        };
+
+        let (network_service, chain_index) = &self.network_service;
+        network_service
+            .remove_reserved_peer(*chain_index, &peer_id)
+            .await;
+        methods::Response::system_removeReservedPeer(()).to_json_response(request_id)
+    }
+
+    /// Handles a call to [`methods::MethodCall::system_networkState`].
+    async fn system_network_state(&self, request_id: &str) -> String {
+        let (network_service, chain_index) = &self.network_service;
+
+        methods::Response::system_networkState(methods::NetworkState {
+            peer_id: network_service.local_peer_id().to_string(),
+            listened_addresses: network_service
+                .listen_addresses()
+                .map(|addr| addr.to_string())
+                .collect(),
+            connected_peers: network_service
+                .peers_list()
+                .await
+                .into_iter()
+                .map(|peer_id| peer_id.to_string())
+                .collect(),
+            k_buckets: network_service
+                .routing_table(*chain_index)
+                .await
+                .into_iter()
+                .map(|(peer_id, addresses)| methods::NetworkStateNode {
+                    peer_id: peer_id.to_string(),
+                    addresses: addresses.iter().map(|addr| addr.to_string()).collect(),
+                })
+                .collect(),
+        })
+        .to_json_response(request_id)
+    }
+
+    /// Handles a call to [`methods::MethodCall::grandpa_roundState`].
+    async fn grandpa_round_state(&self, request_id: &str) -> String {
+        let (network_service, chain_index) = &self.network_service;
+
+        let Some(round_state) = network_service.grandpa_round_state(*chain_index).await else {
+            return json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    "GrandPa authorities aren't known",
                 ),
+                None,
             );
+        };
+
+        methods::Response::grandpa_roundState(methods::GrandpaRoundStates {
+            set_id: round_state.set_id(),
+            best: methods::GrandpaRoundState {
+                round: round_state.round_number(),
+                total_weight: round_state.total_weight(),
+                threshold_weight: round_state.threshold_weight(),
+                prevotes: methods::GrandpaRoundVotes {
+                    current_weight: round_state.prevotes_weight(),
+                    missing: round_state
+                        .missing_prevotes()
+                        .map(|public_key| methods::HexString(public_key.to_vec()))
+                        .collect(),
+                },
+                precommits: methods::GrandpaRoundVotes {
+                    current_weight: round_state.precommits_weight(),
+                    missing: round_state
+                        .missing_precommits()
+                        .map(|public_key| methods::HexString(public_key.to_vec()))
+                        .collect(),
+                },
+            },
+            // Only the latest round is tracked.
+            background: Vec::new(),
+        })
+        .to_json_response(request_id)
+    }
+
+    /// Handles a call to [`methods::MethodCall::grandpa_proveFinality`].
+    ///
+    /// The proof is built from the justification stored in the database that targets the
+    /// finalized block with the lowest number superior or equal to the requested block. `null` is
+    /// returned if the block isn't finalized, and an error if no such justification is available.
+    async fn grandpa_prove_finality(&self, request_id: &str, block_number: u64) -> String {
+        let block_number_bytes = self.block_number_bytes;
+        let result = self
+            .database
+            .with_database(move |database| -> Result<Option<Vec<u8>>, String> {
+                let finalized_block_hash = database
+                    .finalized_block_hash()
+                    .map_err(|err| err.to_string())?;
+                let finalized_block_number = header::decode(
+                    &database
+                        .block_scale_encoded_header(&finalized_block_hash)
+                        .map_err(|err| err.to_string())?
+                        .ok_or_else(|| "Corrupted database".to_owned())?,
+                    block_number_bytes,
+                )
+                .map_err(|_| "Corrupted database".to_owned())?
+                .number;
+
+                if block_number > finalized_block_number {
+                    return Ok(None);
+                }
+
+                // Justifications are only ever stored for finalized blocks.
+                let (justified_number, justified_hash, justification) = database
+                    .first_justified_block(block_number)
+                    .map_err(|err| err.to_string())?
+                    .filter(|(number, ..)| *number <= finalized_block_number)
+                    .ok_or_else(|| "No justification available for this block".to_owned())?;
+
+                if justified_number - block_number > MAX_FINALITY_PROOF_HEADERS {
+                    return Err(
+                        "Too many blocks between the requested block and the closest \
+                        justification"
+                            .to_owned(),
+                    );
+                }
+
+                // Collect the headers of the blocks between the requested block (exclusive) and
+                // the justified block (inclusive), by walking the chain backwards.
+                let mut scale_encoded_headers =
+                    Vec::with_capacity(usize::try_from(justified_number - block_number).unwrap());
+                let mut current_hash = justified_hash;
+                for _ in block_number..justified_number {
+                    let scale_encoded_header = database
+                        .block_scale_encoded_header(&current_hash)
+                        .map_err(|err| err.to_string())?
+                        .ok_or_else(|| "Corrupted database".to_owned())?;
+                    current_hash = *header::decode(&scale_encoded_header, block_number_bytes)
+                        .map_err(|_| "Corrupted database".to_owned())?
+                        .parent_hash;
+                    scale_encoded_headers.push(scale_encoded_header);
+                }
+                scale_encoded_headers.reverse();
+
+                Ok(Some(finality_proof::encode(
+                    &justified_hash,
+                    &justification,
+                    scale_encoded_headers.iter().map(|h| &h[..]),
+                )))
+            })
+            .await;
+
+        match result {
+            Ok(proof) => methods::Response::grandpa_proveFinality(proof.map(methods::HexString))
+                .to_json_response(request_id),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                None,
+            ),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::network_unstable_subscribeEvents`].
+    async fn network_unstable_subscribe_events(
+        &self,
+        request_id: &str,
+        num_subscriptions: Option<&AtomicUsize>,
+    ) -> Outcome {
+        let Some(num_subscriptions) = num_subscriptions else {
+            return Outcome {
+                response: Some(json_rpc::parse::build_error_response(
+                    request_id,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        "Subscriptions aren't supported over plain HTTP",
+                    ),
+                    None,
+                )),
+                ..Default::default()
+            };
+        };
+
+        // The slot is reserved now and released by the server when the client unsubscribes.
+        if num_subscriptions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |num| {
+                (num < MAX_SUBSCRIPTIONS_PER_CONNECTION).then_some(num + 1)
+            })
+            .is_err()
+        {
+            return Outcome {
+                response: Some(json_rpc::parse::build_error_response(
+                    request_id,
+                    json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        "Too many active subscriptions",
+                    ),
+                    None,
+                )),
+                ..Default::default()
+            };
+        }
+
+        let subscription_id = self
+            .next_subscription_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let chain_index = self.network_service.1;
+        let network_events = self.network_service.0.subscribe_network_events().await;
+
+        let notifications = {
+            let subscription_id = subscription_id.clone();
+            let mut filter = json_rpc::network_events::ChainFilter::new(chain_index);
+            network_events
+                .flat_map(move |event| {
+                    let when = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |since_epoch| {
+                            u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX)
+                        });
+                    stream::iter(filter.inject_event(event, when))
+                })
+                .map(move |event| {
+                    methods::ServerToClient::network_unstable_event {
+                        subscription: (&subscription_id).into(),
+                        result: event,
+                    }
+                    .to_json_call_object_parameters(None)
+                })
+                .take_until(stop_rx)
+                .boxed()
+        };
+
+        Outcome {
+            response: Some(
+                methods::Response::network_unstable_subscribeEvents((&subscription_id).into())
+                    .to_json_response(request_id),
+            ),
+            new_subscriptions: vec![NewSubscription {
+                id: subscription_id,
+                stop: stop_tx,
+                notifications,
+            }],
+            ..Default::default()
         }
     }
 }
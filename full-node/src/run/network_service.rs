@@ -29,7 +29,7 @@
 
 use crate::run::{database_thread, jaeger_service};
 
-use core::{cmp, mem, task::Poll, time::Duration};
+use core::{cmp, iter, mem, task::Poll, time::Duration};
 use futures::{
     channel::{mpsc, oneshot},
     lock::Mutex,
@@ -38,6 +38,8 @@ use futures::{
 use hashbrown::HashMap;
 use smoldot::{
     database::full_sqlite,
+    executor::{self, host, runtime_host},
+    finality::grandpa::round_state,
     header,
     informant::HashDisplay,
     libp2p::{
@@ -46,19 +48,26 @@ use smoldot::{
         peer_id::{self, PeerId},
         peers,
     },
-    network::{protocol, service},
+    network::{authority_discovery, kademlia, protocol, service},
+    trie::{self, TrieEntryVersion},
 };
 use std::{
-    io, iter,
-    net::{IpAddr, SocketAddr},
+    collections::BTreeMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     num::NonZeroUsize,
+    path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
     thread,
     time::Instant,
 };
 
+mod quic;
 mod tasks;
+mod webrtc;
+
+pub(crate) use tasks::tls_connector;
 
 /// Configuration for a [`NetworkService`].
 pub struct Config<'a> {
@@ -69,6 +78,12 @@ pub struct Config<'a> {
     pub num_events_receivers: usize,
 
     /// Addresses to listen for incoming connections.
+    ///
+    /// Supported formats are `/ip4/.../tcp/...` and `/ip6/.../tcp/...`, optionally followed
+    /// with `/ws` in order to accept WebSocket connections, `/ip4/.../udp/.../webrtc-direct`
+    /// and `/ip6/.../udp/.../webrtc-direct` in order to accept WebRTC connections, and
+    /// `/ip4/.../udp/.../quic-v1` and `/ip6/.../udp/.../quic-v1` in order to accept QUIC
+    /// connections.
     pub listen_addresses: Vec<Multiaddr>,
 
     /// List of block chains to be connected to.
@@ -79,6 +94,11 @@ pub struct Config<'a> {
     /// Signed using the actual libp2p key.
     pub noise_key: connection::NoiseKey,
 
+    /// Ed25519 private key of the libp2p identity of the local node, which
+    /// [`Config::noise_key`] has been derived from. Used to generate the certificate presented
+    /// during the TLS handshake of QUIC connections.
+    pub libp2p_key: [u8; 32],
+
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
 }
@@ -108,6 +128,18 @@ pub struct ChainConfig {
 
     /// If true, the chain uses the GrandPa networking protocol.
     pub has_grandpa_protocol: bool,
+
+    /// Path to the file where the Kademlia routing table is regularly saved, and loaded from at
+    /// initialization. `None` if the routing table shouldn't be persisted.
+    pub routing_table_path: Option<PathBuf>,
+
+    /// If true, only reserved peers are connected to. See
+    /// [`NetworkService::add_reserved_peer`].
+    pub reserved_only: bool,
+
+    /// List of node identities and addresses of the initial reserved peers of the chain. See
+    /// [`NetworkService::add_reserved_peer`].
+    pub reserved_peers: Vec<(PeerId, Multiaddr)>,
 }
 
 /// Event generated by the events reporters returned by [`NetworkService::new`].
@@ -131,10 +163,16 @@ pub enum Event {
     },
 }
 
+pub use smoldot::json_rpc::network_events::NetworkEvent;
+
 pub struct NetworkService {
     /// Actual network service.
     inner: Arc<Inner>,
 
+    /// Addresses the service is listening on. Contains the addresses that the sockets are
+    /// actually bound to rather than the ones passed through [`Config::listen_addresses`].
+    listen_addresses: Vec<Multiaddr>,
+
     /// Handles connected to all the background tasks of the network service. Makes it possible to
     /// abort everything.
     abort_handles: Vec<future::AbortHandle>,
@@ -154,6 +192,10 @@ struct Inner {
     /// Databases to use to read blocks from when answering requests.
     databases: Vec<Arc<database_thread::DatabaseThread>>,
 
+    /// For each chain, path where to save the Kademlia routing table.
+    /// See [`ChainConfig::routing_table_path`].
+    routing_table_paths: Vec<Option<PathBuf>>,
+
     /// Identity of the local node.
     local_peer_id: PeerId,
 
@@ -173,6 +215,11 @@ struct Guarded {
 
     conn_tasks_tx: mpsc::Sender<Pin<Box<dyn Future<Output = ()> + Send>>>,
 
+    /// QUIC endpoints used to accept incoming connections and to dial remotes. Contains at most
+    /// one endpoint dedicated to dialing per IP version, in case no listening endpoint exists
+    /// for this IP version.
+    quic_endpoints: Vec<quinn::Endpoint>,
+
     active_connections: HashMap<
         service::ConnectionId,
         mpsc::Sender<service::CoordinatorToConnection<Instant>>,
@@ -198,9 +245,110 @@ struct Guarded {
         fnv::FnvBuildHasher,
     >,
 
-    /// List of Kademlia discovery operations that have been started but not finished yet.
-    kademlia_discovery_operations:
-        HashMap<service::KademliaOperationId, usize, fnv::FnvBuildHasher>,
+    /// List of all GrandPa warp sync requests that have been started but not finished yet.
+    grandpa_warp_sync_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<
+            Result<service::EncodedGrandpaWarpSyncResponse, service::GrandpaWarpSyncRequestError>,
+        >,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all state requests that have been started but not finished yet.
+    state_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedStateResponse, service::StateRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all storage proof requests that have been started but not finished yet.
+    storage_proof_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, service::StorageProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all call proof requests that have been started but not finished yet.
+    call_proof_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, service::CallProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// For each chain, the Kademlia lookups in progress. The routing table used by these lookups
+    /// is the k-buckets of [`Guarded::network`].
+    kademlia: Vec<kademlia::Kademlia<Instant>>,
+
+    /// List of all Kademlia find node requests that have been started but not finished yet,
+    /// with the index of the chain and the request within [`Guarded::kademlia`].
+    kademlia_find_node_requests:
+        HashMap<service::OutRequestId, (usize, kademlia::StartRequest), fnv::FnvBuildHasher>,
+
+    /// List of Kademlia find node requests of [`Guarded::kademlia`] whose target the local node
+    /// is trying to connect to, with the index of the chain and when to give up. Each entry has
+    /// a corresponding call to [`service::ChainNetwork::add_dial_request`].
+    kademlia_pending_find_node_requests: Vec<(usize, kademlia::StartRequest, Instant)>,
+
+    /// List of all identify requests that have been started but not finished yet, with the
+    /// index of the chain whose k-buckets the addresses of the target must be inserted in.
+    identify_requests: HashMap<service::OutRequestId, (usize, PeerId), fnv::FnvBuildHasher>,
+
+    /// List of all Kademlia lookups started with [`NetworkService::kademlia_lookup`] that
+    /// haven't finished yet, indexed by chain index and lookup.
+    kademlia_lookups: HashMap<
+        (usize, kademlia::LookupId),
+        oneshot::Sender<kademlia::LookupFinished>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all Kademlia get value requests that have been started but not finished yet.
+    kademlia_get_value_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<protocol::GetValueResponse, service::KademliaGetValueError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all Kademlia put value requests that have been started but not finished yet.
+    kademlia_put_value_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<(), service::KademliaPutValueError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// For each chain, the Kademlia records that remotes have asked the local node to store.
+    kademlia_records: Vec<kademlia::record_store::RecordStore<Instant>>,
+
+    /// For each chain, the authorities set with [`NetworkService::set_authorities`]. Used to
+    /// verify the records that remotes ask the local node to store.
+    authority_discovery: Vec<authority_discovery::AuthorityDiscovery>,
+
+    /// Number of requests that have been performed so far, indexed by protocol name.
+    /// See [`NetworkService::requests_statistics`].
+    requests_statistics: BTreeMap<&'static str, RequestsStatistics>,
+
+    /// Senders of the subscriptions created with [`NetworkService::subscribe_network_events`].
+    network_events_senders: Vec<mpsc::Sender<NetworkEvent>>,
+
+    /// Identifier that the next connection or substream reported through a [`NetworkEvent`]
+    /// will have.
+    network_events_next_id: u32,
+
+    /// For each peer we're connected to, identifier of the connection as reported through
+    /// [`NetworkEvent`]s.
+    network_events_connections: HashMap<PeerId, u32, fnv::FnvBuildHasher>,
+
+    /// For each outgoing request in progress, identifier of its substream as reported through
+    /// [`NetworkEvent`]s.
+    network_events_requests: HashMap<service::OutRequestId, u32, fnv::FnvBuildHasher>,
+
+    /// For each peer and chain combination with a block announces substream, identifier of
+    /// this substream as reported through [`NetworkEvent`]s.
+    network_events_chain_substreams: HashMap<(PeerId, usize), u32, fnv::FnvBuildHasher>,
+
+    /// For each chain, state of the current GrandPa round as gathered from the votes and
+    /// neighbor packets received from the network. `None` if the authorities of the chain
+    /// haven't been provided through [`NetworkService::set_grandpa_authorities`] yet.
+    grandpa_round_states: Vec<Option<round_state::RoundState>>,
 }
 
 impl NetworkService {
@@ -235,11 +383,18 @@ impl NetworkService {
                     None
                 },
                 allow_inbound_block_requests: true,
+                allow_inbound_storage_and_call_proof_requests: true,
+                allow_inbound_kademlia_requests: true,
+                reserved_only: chain.reserved_only,
             });
 
             databases.push(chain.database.clone());
         }
 
+        let local_peer_id =
+            peer_id::PublicKey::Ed25519(*config.noise_key.libp2p_public_ed25519_key())
+                .into_peer_id();
+
         let mut network = service::ChainNetwork::new(service::Config {
             now: Instant::now(),
             chains,
@@ -251,11 +406,51 @@ impl NetworkService {
             randomness_seed: rand::random(),
         });
 
-        // Add the bootnodes to the inner state machine.
+        let mut kademlia = Vec::with_capacity(config.chains.len());
+        let mut routing_table_paths = Vec::with_capacity(config.chains.len());
+        let kademlia_records = (0..config.chains.len())
+            .map(|_| {
+                kademlia::record_store::RecordStore::new(kademlia::record_store::Config {
+                    max_records: 1024,
+                    max_value_size: 4096,
+                    record_ttl: Duration::from_secs(36 * 3600),
+                })
+            })
+            .collect::<Vec<_>>();
+        let authority_discovery = (0..config.chains.len())
+            .map(|_| authority_discovery::AuthorityDiscovery::new())
+            .collect::<Vec<_>>();
+        let grandpa_round_states = (0..config.chains.len()).map(|_| None).collect::<Vec<_>>();
+
+        // Add the bootnodes and the nodes of the saved routing table to the inner state machines.
         for (chain_index, chain) in config.chains.into_iter().enumerate() {
-            for (peer_id, addr) in chain.bootstrap_nodes {
-                network.discover(&Instant::now(), chain_index, peer_id, iter::once(addr));
+            kademlia.push(kademlia::Kademlia::new(kademlia::Config {
+                local_peer_id: local_peer_id.clone(),
+                parallelism: NonZeroUsize::new(3).unwrap(),
+                refresh_interval: Duration::from_secs(5 * 60),
+                randomness_seed: rand::random(),
+            }));
+
+            let saved_nodes = chain
+                .routing_table_path
+                .as_deref()
+                .map(load_routing_table)
+                .unwrap_or_default();
+
+            for (peer_id, addrs) in chain
+                .bootstrap_nodes
+                .into_iter()
+                .map(|(peer_id, addr)| (peer_id, vec![addr]))
+                .chain(saved_nodes)
+            {
+                network.discover(&Instant::now(), chain_index, peer_id, addrs);
             }
+
+            for (peer_id, addr) in chain.reserved_peers {
+                network.add_reserved_peer(chain_index, peer_id, addr);
+            }
+
+            routing_table_paths.push(chain.routing_table_path);
         }
 
         // A channel is used to communicate new tasks dedicated to handling connections.
@@ -270,17 +465,16 @@ impl NetworkService {
             let (messages_from_connections_tx, messages_from_connections_rx) = mpsc::channel(64);
 
             Arc::new(Inner {
-                local_peer_id: peer_id::PublicKey::Ed25519(
-                    *network.noise_key().libp2p_public_ed25519_key(),
-                )
-                .into_peer_id(),
+                local_peer_id,
                 wake_up_main_background_task: event_listener::Event::new(),
                 databases,
+                routing_table_paths,
                 guarded: Mutex::new(Guarded {
                     num_pending_out_attempts: 0,
                     messages_from_connections_tx,
                     messages_from_connections_rx,
                     conn_tasks_tx: conn_tasks_tx.clone(),
+                    quic_endpoints: Vec::new(),
                     network,
                     slots_assign_backoff: hashbrown::HashMap::with_capacity_and_hasher(
                         50, // TODO: ?
@@ -294,10 +488,62 @@ impl NetworkService {
                         50, // TODO: ?
                         Default::default(),
                     ),
-                    kademlia_discovery_operations: hashbrown::HashMap::with_capacity_and_hasher(
+                    grandpa_warp_sync_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        2,
+                        Default::default(),
+                    ),
+                    state_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        8,
+                        Default::default(),
+                    ),
+                    storage_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        8,
+                        Default::default(),
+                    ),
+                    call_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        8,
+                        Default::default(),
+                    ),
+                    kademlia,
+                    kademlia_find_node_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        16,
+                        Default::default(),
+                    ),
+                    kademlia_pending_find_node_requests: Vec::with_capacity(16),
+                    identify_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        16,
+                        Default::default(),
+                    ),
+                    kademlia_lookups: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    kademlia_get_value_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        4,
+                        Default::default(),
+                    ),
+                    kademlia_put_value_requests: hashbrown::HashMap::with_capacity_and_hasher(
                         4,
                         Default::default(),
                     ),
+                    kademlia_records,
+                    authority_discovery,
+                    requests_statistics: BTreeMap::new(),
+                    network_events_senders: Vec::new(),
+                    network_events_next_id: 0,
+                    network_events_connections: hashbrown::HashMap::with_capacity_and_hasher(
+                        100,
+                        Default::default(),
+                    ),
+                    network_events_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                        16,
+                        Default::default(),
+                    ),
+                    network_events_chain_substreams: hashbrown::HashMap::with_capacity_and_hasher(
+                        100,
+                        Default::default(),
+                    ),
+                    grandpa_round_states,
                 }),
                 jaeger_service: config.jaeger_service,
             })
@@ -313,25 +559,53 @@ impl NetworkService {
             abortable.map(|_| ())
         }));
 
-        // Spawn tasks dedicated to starting the Kademlia discovery queries.
+        // Spawn a task dedicated to regularly waking up the main task, so that Kademlia refresh
+        // lookups are started on time, and that Kademlia requests waiting for a connection to
+        // be established time out.
+        (config.tasks_executor)(Box::pin({
+            let inner = inner.clone();
+            let future = async move {
+                loop {
+                    futures_timer::Delay::new(Duration::from_secs(5)).await;
+                    inner.wake_up_main_background_task.notify(1);
+                }
+            };
+
+            let (abortable, abort_handle) = future::abortable(future);
+            abort_handles.push(abort_handle);
+            abortable.map(|_| ())
+        }));
+
+        // Spawn tasks dedicated to saving the Kademlia routing tables. A routing table is only
+        // written to disk if it has changed since the last time it has been saved.
         for chain_index in 0..inner.databases.len() {
+            let Some(path) = inner.routing_table_paths[chain_index].clone() else { continue };
             (config.tasks_executor)(Box::pin({
                 let inner = inner.clone();
                 let future = async move {
-                    let mut next_discovery = Duration::from_secs(1);
-
+                    let mut saved_routing_table = Vec::new();
                     loop {
-                        futures_timer::Delay::new(next_discovery).await;
-                        next_discovery = cmp::min(next_discovery * 2, Duration::from_secs(120));
+                        futures_timer::Delay::new(Duration::from_secs(5 * 60)).await;
+
+                        let mut routing_table = {
+                            let guarded = inner.guarded.lock().await;
+                            guarded
+                                .network
+                                .discovered_nodes(chain_index)
+                                .map(|(peer_id, addrs)| (peer_id.clone(), addrs.cloned().collect()))
+                                .collect::<Vec<(PeerId, Vec<Multiaddr>)>>()
+                        };
+                        // The order of the nodes and of their addresses changes over time and
+                        // doesn't matter.
+                        for (_, addrs) in &mut routing_table {
+                            addrs.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+                        }
+                        routing_table.sort_unstable_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
 
-                        let mut guarded = inner.guarded.lock().await;
-                        let operation_id = guarded
-                            .network
-                            .start_kademlia_discovery_round(Instant::now(), chain_index);
-                        let _prev_val = guarded
-                            .kademlia_discovery_operations
-                            .insert(operation_id, chain_index);
-                        debug_assert!(_prev_val.is_none());
+                        if routing_table != saved_routing_table {
+                            save_routing_table(&path, &routing_table);
+                            saved_routing_table = routing_table;
+                        }
                     }
                 };
 
@@ -343,38 +617,133 @@ impl NetworkService {
 
         // For each listening address in the configuration, create a background task dedicated to
         // listening on that address.
+        let mut listen_addresses = Vec::with_capacity(config.listen_addresses.len());
         for listen_address in config.listen_addresses {
+            // QUIC listeners use their own UDP socket, which is also used when dialing.
+            if let Some(addr) = quic::parse_listen_multiaddr(&listen_address) {
+                let endpoint = match std::net::UdpSocket::bind(addr)
+                    .and_then(|socket| quic::endpoint(socket, &config.libp2p_key, true))
+                {
+                    Ok(e) => e,
+                    Err(err) => return Err(InitError::ListenerIo(listen_address, err)),
+                };
+                let bound_addr = endpoint.local_addr().unwrap_or(addr);
+                listen_addresses.push(quic::socket_addr_to_multiaddr(bound_addr));
+                inner
+                    .guarded
+                    .lock()
+                    .await
+                    .quic_endpoints
+                    .push(endpoint.clone());
+
+                (config.tasks_executor)(Box::pin({
+                    let future =
+                        quic::listener_task(endpoint, inner.clone(), conn_tasks_tx.clone());
+                    let (abortable, abort_handle) = future::abortable(future);
+                    abort_handles.push(abort_handle);
+                    abortable.map(|_| ())
+                }));
+                continue;
+            }
+
+            // WebRTC listeners are handled separately, as all their connections share the same
+            // UDP socket.
+            if let Some(addr) = webrtc::parse_listen_multiaddr(&listen_address) {
+                let socket = match async_std::net::UdpSocket::bind(addr).await {
+                    Ok(s) => s,
+                    Err(err) => return Err(InitError::ListenerIo(listen_address, err)),
+                };
+                let bound_addr = socket.local_addr().unwrap_or(addr);
+
+                // Note that the certificate is generated anew at each start, meaning that the
+                // advertised address changes after each restart. The WebRTC library doesn't
+                // support loading an existing certificate, which prevents persisting it.
+                let certificate = webrtc::generate_certificate();
+                listen_addresses.push(webrtc::listen_multiaddr(bound_addr, &certificate));
+
+                (config.tasks_executor)(Box::pin({
+                    let future = webrtc::listener_task(
+                        Arc::new(socket),
+                        bound_addr,
+                        certificate,
+                        inner.clone(),
+                        conn_tasks_tx.clone(),
+                    );
+                    let (abortable, abort_handle) = future::abortable(future);
+                    abort_handles.push(abort_handle);
+                    abortable.map(|_| ())
+                }));
+                continue;
+            }
+
             // Try to parse the requested address and create the corresponding listening socket.
-            let tcp_listener: async_std::net::TcpListener = {
-                let addr = {
+            let (tcp_listener, is_websocket) = {
+                let (addr, is_websocket) = {
                     let mut iter = listen_address.iter();
                     let proto1 = iter.next();
                     let proto2 = iter.next();
                     let proto3 = iter.next();
-                    match (proto1, proto2, proto3) {
-                        (Some(ProtocolRef::Ip4(ip)), Some(ProtocolRef::Tcp(port)), None) => {
-                            Some(SocketAddr::from((ip, port)))
+                    let proto4 = iter.next();
+                    match (proto1, proto2, proto3, proto4) {
+                        (Some(ProtocolRef::Ip4(ip)), Some(ProtocolRef::Tcp(port)), None, None) => {
+                            (Some(SocketAddr::from((ip, port))), false)
                         }
-                        (Some(ProtocolRef::Ip6(ip)), Some(ProtocolRef::Tcp(port)), None) => {
-                            Some(SocketAddr::from((ip, port)))
+                        (Some(ProtocolRef::Ip6(ip)), Some(ProtocolRef::Tcp(port)), None, None) => {
+                            (Some(SocketAddr::from((ip, port))), false)
                         }
-                        _ => None,
+                        (
+                            Some(ProtocolRef::Ip4(ip)),
+                            Some(ProtocolRef::Tcp(port)),
+                            Some(ProtocolRef::Ws),
+                            None,
+                        ) => (Some(SocketAddr::from((ip, port))), true),
+                        (
+                            Some(ProtocolRef::Ip6(ip)),
+                            Some(ProtocolRef::Tcp(port)),
+                            Some(ProtocolRef::Ws),
+                            None,
+                        ) => (Some(SocketAddr::from((ip, port))), true),
+                        _ => (None, false),
                     }
                 };
 
+                // Note that secure WebSocket (`/wss`) isn't supported for listening, as it would
+                // require a TLS certificate. The common practice is to put a reverse proxy that
+                // handles TLS in front of a `/ws` listener.
                 if let Some(addr) = addr {
                     match async_std::net::TcpListener::bind(addr).await {
-                        Ok(l) => l,
+                        Ok(l) => (l, is_websocket),
                         Err(err) => {
                             return Err(InitError::ListenerIo(listen_address, err));
                         }
                     }
                 } else {
-                    // TODO: support WebSocket server
                     return Err(InitError::BadListenMultiaddr(listen_address));
                 }
             };
 
+            // Report the address that the socket is actually bound to, in case for example the
+            // requested port was 0.
+            match tcp_listener.local_addr() {
+                Ok(bound_addr) => listen_addresses.push(
+                    [
+                        match bound_addr.ip() {
+                            IpAddr::V4(ip) => ProtocolRef::Ip4(ip.octets()),
+                            IpAddr::V6(ip) => ProtocolRef::Ip6(ip.octets()),
+                        },
+                        ProtocolRef::Tcp(bound_addr.port()),
+                    ]
+                    .into_iter()
+                    .chain(if is_websocket {
+                        Some(ProtocolRef::Ws)
+                    } else {
+                        None
+                    })
+                    .collect::<Multiaddr>(),
+                ),
+                Err(_) => listen_addresses.push(listen_address.clone()),
+            }
+
             // Spawn a background task dedicated to this listener.
             (config.tasks_executor)(Box::pin({
                 let mut conn_tasks_tx = conn_tasks_tx.clone();
@@ -410,31 +779,21 @@ impl NetworkService {
                             ProtocolRef::Tcp(addr.port()),
                         ]
                         .into_iter()
+                        .chain(if is_websocket {
+                            Some(ProtocolRef::Ws)
+                        } else {
+                            None
+                        })
                         .collect::<Multiaddr>();
 
                         log::debug!("incoming-connection; multiaddr={}", multiaddr);
 
-                        let task = {
-                            let mut guarded = inner.guarded.lock().await;
-                            let (connection_id, connection_task) =
-                                guarded.network.add_single_stream_incoming_connection(
-                                    Instant::now(),
-                                    service::SingleStreamHandshakeKind::MultistreamSelectNoiseYamux,
-                                    multiaddr.clone(),
-                                );
-
-                            let (tx, rx) = mpsc::channel(16); // TODO: ?!
-                            guarded.active_connections.insert(connection_id, tx);
-
-                            tasks::established_connection_task(
-                                socket,
-                                inner.clone(),
-                                connection_id,
-                                connection_task,
-                                rx,
-                                guarded.messages_from_connections_tx.clone(),
-                            )
-                        };
+                        let task = tasks::incoming_connection_task(
+                            socket,
+                            multiaddr,
+                            is_websocket,
+                            inner.clone(),
+                        );
 
                         // Ignore errors, as it is possible for the destination task to have been
                         // aborted already.
@@ -448,6 +807,33 @@ impl NetworkService {
             }))
         }
 
+        // Create the QUIC endpoints used to dial remotes of an IP version for which no QUIC
+        // listening endpoint exists.
+        {
+            let mut guarded = inner.guarded.lock().await;
+            for unspecified in [
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            ] {
+                if guarded.quic_endpoints.iter().any(|endpoint| {
+                    endpoint
+                        .local_addr()
+                        .map_or(false, |addr| addr.is_ipv4() == unspecified.is_ipv4())
+                }) {
+                    continue;
+                }
+
+                // Errors are ignored, as the machine might for example not support IPv6.
+                // Dialing QUIC addresses of this IP version then fails.
+                match std::net::UdpSocket::bind((unspecified, 0))
+                    .and_then(|socket| quic::endpoint(socket, &config.libp2p_key, false))
+                {
+                    Ok(endpoint) => guarded.quic_endpoints.push(endpoint),
+                    Err(err) => log::debug!("quic-dial-endpoint-error; error={}", err),
+                }
+            }
+        }
+
         // Spawn task dedicated to processing connections.
         // A single task is responsible for all connections, thereby ensuring that the networking
         // won't use more than a single CPU core.
@@ -472,6 +858,7 @@ impl NetworkService {
         // Build the final network service.
         let network_service = Arc::new(NetworkService {
             inner,
+            listen_addresses,
             abort_handles: {
                 abort_handles.shrink_to_fit();
                 abort_handles
@@ -494,156 +881,466 @@ impl NetworkService {
         Ok((network_service, receivers))
     }
 
-    /// Returns the number of established TCP connections, both incoming and outgoing.
-    pub async fn num_established_connections(&self) -> usize {
-        self.inner
-            .guarded
-            .lock()
-            .await
-            .network
-            .num_established_connections()
+    /// Returns the list of addresses the service is listening on.
+    pub fn listen_addresses(&self) -> impl ExactSizeIterator<Item = &Multiaddr> {
+        self.listen_addresses.iter()
     }
 
-    /// Returns the number of peers we have a substream with.
-    pub async fn num_peers(&self, chain_index: usize) -> usize {
+    /// Returns the identity of the local node on the peer-to-peer network.
+    pub fn local_peer_id(&self) -> &PeerId {
+        &self.inner.local_peer_id
+    }
+
+    /// Returns the list of peers we have at least one established connection with.
+    pub async fn peers_list(&self) -> Vec<PeerId> {
         self.inner
             .guarded
             .lock()
             .await
             .network
-            .num_peers(chain_index)
+            .peers_list()
+            .cloned()
+            .collect()
     }
 
-    pub async fn set_local_best_block(
-        &self,
-        chain_index: usize,
-        best_hash: [u8; 32],
-        best_number: u64,
-    ) {
+    /// Returns the content of the Kademlia k-buckets of the given chain.
+    pub async fn routing_table(&self, chain_index: usize) -> Vec<(PeerId, Vec<Multiaddr>)> {
         self.inner
             .guarded
             .lock()
             .await
             .network
-            .set_local_best_block(chain_index, best_hash, best_number);
-        self.inner.wake_up_main_background_task.notify(1);
+            .discovered_nodes(chain_index)
+            .map(|(peer_id, addresses)| (peer_id.clone(), addresses.cloned().collect()))
+            .collect()
     }
 
-    pub async fn send_block_announce(
-        self: Arc<Self>,
-        target: &PeerId,
-        chain_index: usize,
-        scale_encoded_header: &[u8],
-        is_best: bool,
-    ) -> Result<(), QueueNotificationError> {
+    /// Adds the given addresses to the list of addresses of the given peer, so that the network
+    /// might connect to it later.
+    pub async fn discover(&self, chain_index: usize, peer_id: PeerId, addresses: Vec<Multiaddr>) {
         let mut guarded = self.inner.guarded.lock().await;
-
-        // The call to `send_block_announce` below panics if we have no active connection.
-        if !guarded
-            .network
-            .can_send_block_announces(target, chain_index)
-        {
-            return Err(QueueNotificationError::NoConnection);
-        }
-
-        let result = guarded
+        guarded
             .network
-            .send_block_announce(target, chain_index, scale_encoded_header, is_best)
-            .map_err(QueueNotificationError::Queue);
-
+            .discover(&Instant::now(), chain_index, peer_id, addresses);
         self.inner.wake_up_main_background_task.notify(1);
-        result
     }
 
-    /// Sends a blocks request to the given peer.
-    // TODO: more docs
-    // TODO: proper error type
-    pub async fn blocks_request(
-        self: Arc<Self>,
-        target: PeerId, // TODO: by value?
-        chain_index: usize,
-        config: protocol::BlocksRequestConfig,
-    ) -> Result<Vec<protocol::BlockData>, BlocksRequestError> {
-        log::debug!(
-            "blocks-request-start; perr_id={}; chain_index={}; start={}; desired_count={}; direction={}",
-            target, chain_index,
-            match &config.start {
-                protocol::BlocksRequestConfigStart::Hash(h) => either::Left(HashDisplay(h)),
-                protocol::BlocksRequestConfigStart::Number(n) => either::Right(n),
-            },
-            config.desired_count,
-            match config.direction {
-                protocol::BlocksRequestDirection::Ascending => "ascending",
-                protocol::BlocksRequestDirection::Descending => "descending",
-            },
-        );
+    /// Sets the list of authorities of the given chain, as returned by the
+    /// [`authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME`] runtime function.
+    ///
+    /// Remotes can only store in the local node the records of these authorities.
+    pub async fn set_authorities(&self, chain_index: usize, authorities: Vec<[u8; 32]>) {
+        self.inner.guarded.lock().await.authority_discovery[chain_index]
+            .set_authorities(authorities.into_iter());
+    }
 
-        // Setup a guard that will print a log message in case it is dropped silently.
-        // This lets us detect if the request is cancelled.
-        struct LogIfCancel(PeerId, usize);
-        impl Drop for LogIfCancel {
-            fn drop(&mut self) {
-                log::debug!(
-                    "blocks-request-ended; peer_id={}; chain_index={}; outcome=cancelled",
-                    self.0,
-                    self.1
-                );
-            }
-        }
-        let _log_if_cancel = LogIfCancel(target.clone(), chain_index);
+    /// Searches the Kademlia distributed hash table of the given chain for the nodes closest to
+    /// the given key, and returns them ordered by increasing distance.
+    pub async fn kademlia_lookup(
+        &self,
+        chain_index: usize,
+        key: Vec<u8>,
+    ) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+            let initial_candidates = guarded.network.closest_peers(chain_index, &key);
+            let lookup_id = guarded.kademlia[chain_index].start_lookup(key, initial_candidates);
+            let (tx, rx) = oneshot::channel();
+            guarded
+                .kademlia_lookups
+                .insert((chain_index, lookup_id), tx);
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
 
-        let _jaeger_span = self.inner.jaeger_service.outgoing_block_request_span(
-            &self.inner.local_peer_id,
-            &target,
-            config.desired_count.get(),
-            if let (1, protocol::BlocksRequestConfigStart::Hash(block_hash)) =
-                (config.desired_count.get(), &config.start)
-            {
-                Some(block_hash)
-            } else {
-                None
-            },
-        );
+        rx.await.unwrap().closest_peers
+    }
 
+    /// Sends a Kademlia get value request to the given peer.
+    pub async fn kademlia_get_value_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        key: &[u8],
+    ) -> Result<protocol::GetValueResponse, KademliaGetValueRequestError> {
         let rx = {
             let mut guarded = self.inner.guarded.lock().await;
 
-            // The call to `start_blocks_request` below panics if we have no active connection.
+            // The call to `start_kademlia_get_value` below panics if we have no active
+            // connection.
             if !guarded.network.can_start_requests(&target) {
-                return Err(BlocksRequestError::NoConnection);
+                return Err(KademliaGetValueRequestError::NoConnection);
             }
 
-            let (tx, rx) = oneshot::channel();
-
-            let request_id = guarded.network.start_blocks_request(
-                Instant::now(),
+            let request_id = match guarded.network.start_kademlia_get_value(
                 &target,
+                Instant::now(),
                 chain_index,
-                config,
-                Duration::from_secs(12),
-            );
+                key,
+            ) {
+                Ok(id) => id,
+                Err(service::StartRequestError::RequestTooLarge) => {
+                    return Err(KademliaGetValueRequestError::RequestTooLarge)
+                }
+            };
 
-            // TODO: somehow cancel the request if the `rx` is dropped?
-            guarded.blocks_requests.insert(request_id, tx);
+            let (tx, rx) = oneshot::channel();
+            guarded.kademlia_get_value_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "kademlia-get-value");
 
             self.inner.wake_up_main_background_task.notify(1);
             rx
         };
 
-        let result = rx.await.unwrap().map_err(BlocksRequestError::Request);
-
-        // Requet has finished. Print the log and prevent the cancellation message from being
-        // printed.
-        mem::forget(_log_if_cancel);
-        match &result {
-            Ok(success) => {
-                log::debug!(
-                    "blocks-request-ended; peer_id={}; chain_index={}; outcome=success; response_blocks={}",
-                    target, chain_index, success.len()
-                );
+        let result = rx
+            .await
+            .unwrap()
+            .map_err(KademliaGetValueRequestError::Request);
+        log::debug!(
+            "kademlia-get-value-request-ended; peer_id={}; chain_index={}; outcome={}",
+            target,
+            chain_index,
+            match &result {
+                Ok(response) if response.value.is_some() => "found",
+                Ok(_) => "not-found",
+                Err(_) => "failure",
             }
-            Err(err) => {
-                log::debug!(
+        );
+        result
+    }
+
+    /// Sends a Kademlia put value request to the given peer.
+    pub async fn kademlia_put_value_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), KademliaPutValueRequestError> {
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_kademlia_put_value` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(KademliaPutValueRequestError::NoConnection);
+            }
+
+            let request_id = match guarded.network.start_kademlia_put_value(
+                &target,
+                Instant::now(),
+                chain_index,
+                key,
+                value,
+            ) {
+                Ok(id) => id,
+                Err(service::StartRequestError::RequestTooLarge) => {
+                    return Err(KademliaPutValueRequestError::RequestTooLarge)
+                }
+            };
+
+            let (tx, rx) = oneshot::channel();
+            guarded.kademlia_put_value_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "kademlia-put-value");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx
+            .await
+            .unwrap()
+            .map_err(KademliaPutValueRequestError::Request);
+        log::debug!(
+            "kademlia-put-value-request-ended; peer_id={}; chain_index={}; success={:?}",
+            target,
+            chain_index,
+            result.is_ok()
+        );
+        result
+    }
+
+    /// Sets the GrandPa authorities of the given chain, against which the votes gossiped on the
+    /// network are verified.
+    ///
+    /// Has no effect if the authorities of the given set id have already been provided.
+    pub async fn set_grandpa_authorities<'a>(
+        &self,
+        chain_index: usize,
+        set_id: u64,
+        authorities: impl Iterator<Item = header::GrandpaAuthorityRef<'a>>,
+    ) {
+        let mut guarded = self.inner.guarded.lock().await;
+        let guarded = &mut *guarded;
+
+        if guarded.grandpa_round_states[chain_index]
+            .as_ref()
+            .map_or(false, |state| state.set_id() == set_id)
+        {
+            return;
+        }
+
+        guarded.grandpa_round_states[chain_index] =
+            Some(round_state::RoundState::new(round_state::Config {
+                block_number_bytes: guarded.network.block_number_bytes(chain_index),
+                set_id,
+                authorities,
+            }));
+    }
+
+    /// Returns the state of the current GrandPa round of the given chain, as gathered from the
+    /// network.
+    ///
+    /// Returns `None` if [`NetworkService::set_grandpa_authorities`] hasn't been called yet for
+    /// this chain.
+    pub async fn grandpa_round_state(&self, chain_index: usize) -> Option<round_state::RoundState> {
+        self.inner.guarded.lock().await.grandpa_round_states[chain_index].clone()
+    }
+
+    /// Returns a channel on which low-level [`NetworkEvent`]s are sent, for diagnostic purposes.
+    ///
+    /// Events that happened before this function is called aren't reported. Events are silently
+    /// discarded if the receiver doesn't process them quickly enough.
+    pub async fn subscribe_network_events(&self) -> mpsc::Receiver<NetworkEvent> {
+        let (tx, rx) = mpsc::channel(64);
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network_events_senders
+            .push(tx);
+        rx
+    }
+
+    /// Returns the number of established connections, both incoming and outgoing.
+    pub async fn num_established_connections(&self) -> usize {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network
+            .num_established_connections()
+    }
+
+    /// Returns the number of peers we have a substream with.
+    pub async fn num_peers(&self, chain_index: usize) -> usize {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network
+            .num_peers(chain_index)
+    }
+
+    /// Returns the number of requests that have been performed since the service has started,
+    /// for each protocol. Protocols for which no request has been performed are absent.
+    pub async fn requests_statistics(&self) -> Vec<(&'static str, RequestsStatistics)> {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .requests_statistics
+            .iter()
+            .map(|(protocol, stats)| (*protocol, stats.clone()))
+            .collect()
+    }
+
+    /// Adds a peer to the list of reserved peers of the given chain. The node permanently tries to
+    /// stay connected to reserved peers, and they don't count towards the limit to the number of
+    /// peers.
+    ///
+    /// Has no effect other than adding `address` to the known addresses of the peer if it is
+    /// already a reserved peer.
+    pub async fn add_reserved_peer(&self, chain_index: usize, peer_id: PeerId, address: Multiaddr) {
+        let mut guarded = self.inner.guarded.lock().await;
+
+        // Reserved peers must be connected to as soon as possible.
+        guarded
+            .slots_assign_backoff
+            .remove(&(peer_id.clone(), chain_index));
+
+        if guarded
+            .network
+            .add_reserved_peer(chain_index, peer_id.clone(), address)
+        {
+            guarded.report_network_event(NetworkEvent::SlotAssign {
+                chain_index,
+                peer_id,
+                slot_ty: service::SlotTy::Outbound,
+            });
+        }
+
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    /// Removes a peer from the list of reserved peers of the given chain. Does nothing if the
+    /// peer isn't a reserved peer.
+    ///
+    /// See [`NetworkService::add_reserved_peer`].
+    pub async fn remove_reserved_peer(&self, chain_index: usize, peer_id: &PeerId) {
+        let mut guarded = self.inner.guarded.lock().await;
+
+        if let Some(slot_ty) = guarded.network.remove_reserved_peer(chain_index, peer_id) {
+            guarded.report_network_event(NetworkEvent::SlotUnassign {
+                chain_index,
+                peer_id: peer_id.clone(),
+                slot_ty,
+            });
+        }
+
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    /// Closes the slot of the given peer for the given chain, and prevents this peer from being
+    /// assigned a slot again for a certain amount of time.
+    ///
+    /// This should be called when the peer has misbehaved, for example by sending an invalid
+    /// proof. The `reason` is used for logging purposes.
+    pub async fn ban_peer(&self, chain_index: usize, peer_id: PeerId, reason: &str) {
+        let mut guarded = self.inner.guarded.lock().await;
+
+        log::debug!(
+            "slot-ban; peer_id={}; chain_index={}; reason={}",
+            peer_id,
+            chain_index,
+            reason
+        );
+
+        guarded.unassign_slot_and_ban(chain_index, peer_id);
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    pub async fn set_local_best_block(
+        &self,
+        chain_index: usize,
+        best_hash: [u8; 32],
+        best_number: u64,
+    ) {
+        self.inner
+            .guarded
+            .lock()
+            .await
+            .network
+            .set_local_best_block(chain_index, best_hash, best_number);
+        self.inner.wake_up_main_background_task.notify(1);
+    }
+
+    pub async fn send_block_announce(
+        self: Arc<Self>,
+        target: &PeerId,
+        chain_index: usize,
+        scale_encoded_header: &[u8],
+        is_best: bool,
+    ) -> Result<(), QueueNotificationError> {
+        let mut guarded = self.inner.guarded.lock().await;
+
+        // The call to `send_block_announce` below panics if we have no active connection.
+        if !guarded
+            .network
+            .can_send_block_announces(target, chain_index)
+        {
+            return Err(QueueNotificationError::NoConnection);
+        }
+
+        let result = guarded
+            .network
+            .send_block_announce(target, chain_index, scale_encoded_header, is_best)
+            .map_err(QueueNotificationError::Queue);
+
+        self.inner.wake_up_main_background_task.notify(1);
+        result
+    }
+
+    /// Sends a blocks request to the given peer.
+    // TODO: more docs
+    // TODO: proper error type
+    pub async fn blocks_request(
+        self: Arc<Self>,
+        target: PeerId, // TODO: by value?
+        chain_index: usize,
+        config: protocol::BlocksRequestConfig,
+    ) -> Result<Vec<protocol::BlockData>, BlocksRequestError> {
+        log::debug!(
+            "blocks-request-start; perr_id={}; chain_index={}; start={}; desired_count={}; direction={}",
+            target, chain_index,
+            match &config.start {
+                protocol::BlocksRequestConfigStart::Hash(h) => either::Left(HashDisplay(h)),
+                protocol::BlocksRequestConfigStart::Number(n) => either::Right(n),
+            },
+            config.desired_count,
+            match config.direction {
+                protocol::BlocksRequestDirection::Ascending => "ascending",
+                protocol::BlocksRequestDirection::Descending => "descending",
+            },
+        );
+
+        // Setup a guard that will print a log message in case it is dropped silently.
+        // This lets us detect if the request is cancelled.
+        struct LogIfCancel(PeerId, usize);
+        impl Drop for LogIfCancel {
+            fn drop(&mut self) {
+                log::debug!(
+                    "blocks-request-ended; peer_id={}; chain_index={}; outcome=cancelled",
+                    self.0,
+                    self.1
+                );
+            }
+        }
+        let _log_if_cancel = LogIfCancel(target.clone(), chain_index);
+
+        let _jaeger_span = self.inner.jaeger_service.outgoing_block_request_span(
+            &self.inner.local_peer_id,
+            &target,
+            config.desired_count.get(),
+            if let (1, protocol::BlocksRequestConfigStart::Hash(block_hash)) =
+                (config.desired_count.get(), &config.start)
+            {
+                Some(block_hash)
+            } else {
+                None
+            },
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_blocks_request` below panics if we have no active connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(BlocksRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_blocks_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                config,
+                Duration::from_secs(12),
+            );
+
+            // TODO: somehow cancel the request if the `rx` is dropped?
+            guarded.blocks_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "blocks");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(BlocksRequestError::Request);
+
+        // Requet has finished. Print the log and prevent the cancellation message from being
+        // printed.
+        mem::forget(_log_if_cancel);
+        match &result {
+            Ok(success) => {
+                log::debug!(
+                    "blocks-request-ended; peer_id={}; chain_index={}; outcome=success; response_blocks={}",
+                    target, chain_index, success.len()
+                );
+            }
+            Err(err) => {
+                log::debug!(
                     "blocks-request-ended; peer_id={}; chain_index={}; outcome=failure; error={}",
                     target,
                     chain_index,
@@ -654,6 +1351,278 @@ impl NetworkService {
 
         result
     }
+
+    /// Sends a GrandPa warp sync request to the given peer.
+    pub async fn grandpa_warp_sync_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        begin_hash: [u8; 32],
+    ) -> Result<service::EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError> {
+        log::debug!(
+            "warp-sync-request-start; peer_id={}; chain_index={}; begin={}",
+            target,
+            chain_index,
+            HashDisplay(&begin_hash)
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_grandpa_warp_sync_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(GrandpaWarpSyncRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_grandpa_warp_sync_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                begin_hash,
+                Duration::from_secs(20),
+            );
+
+            guarded.grandpa_warp_sync_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "grandpa-warp-sync");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx
+            .await
+            .unwrap()
+            .map_err(GrandpaWarpSyncRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                let decoded = response.decode();
+                log::debug!(
+                    "warp-sync-request-ended; peer_id={}; chain_index={}; outcome=success; num_fragments={}; finished={:?}",
+                    target, chain_index, decoded.fragments.len(), decoded.is_finished
+                );
+            }
+            Err(err) => {
+                log::debug!(
+                    "warp-sync-request-ended; peer_id={}; chain_index={}; outcome=failure; error={}",
+                    target,
+                    chain_index,
+                    err
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Sends a state request to the given peer.
+    ///
+    /// The response is a compact proof of a portion of the storage of the given block, starting
+    /// at the given key of the main trie or of a child trie. It isn't verified by this function.
+    pub async fn state_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        block_hash: [u8; 32],
+        start_key: protocol::StateRequestStart<'_>,
+    ) -> Result<service::EncodedStateResponse, StateRequestError> {
+        log::debug!(
+            "state-request-start; peer_id={}; chain_index={}; block={}; child_trie={}; start_key={}",
+            target,
+            chain_index,
+            HashDisplay(&block_hash),
+            match &start_key {
+                protocol::StateRequestStart::MainTrie(_) => "none".to_owned(),
+                protocol::StateRequestStart::ChildTrieDefault { child_trie, .. } =>
+                    hex::encode(child_trie),
+            },
+            match &start_key {
+                protocol::StateRequestStart::MainTrie(key)
+                | protocol::StateRequestStart::ChildTrieDefault { key, .. } => hex::encode(key),
+            }
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_state_request` below panics if we have no active connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(StateRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = guarded.network.start_state_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                &block_hash,
+                start_key,
+                Duration::from_secs(20),
+            );
+
+            guarded.state_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "state");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(StateRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                log::debug!(
+                    "state-request-ended; peer_id={}; chain_index={}; outcome=success; proof_size={}",
+                    target, chain_index, response.decode().len()
+                );
+            }
+            Err(err) => {
+                log::debug!(
+                    "state-request-ended; peer_id={}; chain_index={}; outcome=failure; error={}",
+                    target,
+                    chain_index,
+                    err
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Sends a storage proof request to the given peer.
+    pub async fn storage_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        config: protocol::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
+    ) -> Result<service::EncodedMerkleProof, StorageProofRequestError> {
+        log::debug!(
+            "storage-proof-request-start; peer_id={}; chain_index={}; block={}",
+            target,
+            chain_index,
+            HashDisplay(&config.block_hash)
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_storage_proof_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(StorageProofRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = match guarded.network.start_storage_proof_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                config,
+                Duration::from_secs(12),
+            ) {
+                Ok(id) => id,
+                Err(service::StartRequestError::RequestTooLarge) => {
+                    return Err(StorageProofRequestError::RequestTooLarge)
+                }
+            };
+
+            guarded.storage_proof_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "storage-proof");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(StorageProofRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                log::debug!(
+                    "storage-proof-request-ended; peer_id={}; chain_index={}; outcome=success; proof_size={}",
+                    target, chain_index, response.decode().len()
+                );
+            }
+            Err(err) => {
+                log::debug!(
+                    "storage-proof-request-ended; peer_id={}; chain_index={}; outcome=failure; error={}",
+                    target, chain_index, err
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Sends a call proof request to the given peer.
+    pub async fn call_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_index: usize,
+        config: protocol::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
+    ) -> Result<service::EncodedMerkleProof, CallProofRequestError> {
+        log::debug!(
+            "call-proof-request-start; peer_id={}; chain_index={}; block={}; function={}",
+            target,
+            chain_index,
+            HashDisplay(&config.block_hash),
+            config.method
+        );
+
+        let rx = {
+            let mut guarded = self.inner.guarded.lock().await;
+
+            // The call to `start_call_proof_request` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(CallProofRequestError::NoConnection);
+            }
+
+            let (tx, rx) = oneshot::channel();
+
+            let request_id = match guarded.network.start_call_proof_request(
+                Instant::now(),
+                &target,
+                chain_index,
+                config,
+                Duration::from_secs(12),
+            ) {
+                Ok(id) => id,
+                Err(service::StartRequestError::RequestTooLarge) => {
+                    return Err(CallProofRequestError::RequestTooLarge)
+                }
+            };
+
+            guarded.call_proof_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "call-proof");
+
+            self.inner.wake_up_main_background_task.notify(1);
+            rx
+        };
+
+        let result = rx.await.unwrap().map_err(CallProofRequestError::Request);
+
+        match &result {
+            Ok(response) => {
+                log::debug!(
+                    "call-proof-request-ended; peer_id={}; chain_index={}; outcome=success; proof_size={}",
+                    target, chain_index, response.decode().len()
+                );
+            }
+            Err(err) => {
+                log::debug!(
+                    "call-proof-request-ended; peer_id={}; chain_index={}; outcome=failure; error={}",
+                    target, chain_index, err
+                );
+            }
+        }
+
+        result
+    }
 }
 
 impl Drop for NetworkService {
@@ -664,6 +1633,18 @@ impl Drop for NetworkService {
     }
 }
 
+/// Number of requests of a certain protocol that have been performed.
+/// See [`NetworkService::requests_statistics`].
+#[derive(Debug, Clone, Default)]
+pub struct RequestsStatistics {
+    /// Number of outgoing requests that have succeeded.
+    pub outgoing_success: u64,
+    /// Number of outgoing requests that have failed, including timeouts.
+    pub outgoing_failure: u64,
+    /// Number of incoming requests that have been received.
+    pub incoming: u64,
+}
+
 /// Error when initializing the network service.
 #[derive(Debug, derive_more::Display)]
 pub enum InitError {
@@ -685,6 +1666,74 @@ pub enum BlocksRequestError {
     Request(service::BlocksRequestError),
 }
 
+/// Error returned by [`NetworkService::grandpa_warp_sync_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum GrandpaWarpSyncRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::GrandpaWarpSyncRequestError),
+}
+
+/// Error returned by [`NetworkService::state_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StateRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::StateRequestError),
+}
+
+/// Error returned by [`NetworkService::storage_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StorageProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Storage proof request is too large and can't be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::StorageProofRequestError),
+}
+
+/// Error returned by [`NetworkService::call_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum CallProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Call proof request is too large and can't be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::CallProofRequestError),
+}
+
+/// Error returned by [`NetworkService::kademlia_get_value_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaGetValueRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Request is too large and can't be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::KademliaGetValueError),
+}
+
+/// Error returned by [`NetworkService::kademlia_put_value_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaPutValueRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Request is too large and can't be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::KademliaPutValueError),
+}
+
 /// Error returned by [`NetworkService::send_block_announce`].
 #[derive(Debug, derive_more::Display)]
 pub enum QueueNotificationError {
@@ -734,12 +1783,24 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
             match inner_event {
                 service::Event::Connected(peer_id) => {
                     log::debug!("connected; peer_id={}", peer_id);
+                    let connection_id = guarded.next_network_event_id();
+                    guarded
+                        .network_events_connections
+                        .insert(peer_id.clone(), connection_id);
+                    guarded.report_network_event(NetworkEvent::Connected {
+                        connection_id,
+                        peer_id,
+                    });
                 }
                 service::Event::Disconnected {
                     peer_id,
                     chain_indices,
                 } => {
                     log::debug!("disconnected; peer_id={}", peer_id);
+                    if let Some(connection_id) = guarded.network_events_connections.remove(&peer_id)
+                    {
+                        guarded.report_network_event(NetworkEvent::Disconnected { connection_id });
+                    }
                     if !chain_indices.is_empty() {
                         debug_assert_eq!(chain_indices.len(), 1); // TODO: not implemented
                         break Event::Disconnected {
@@ -796,6 +1857,7 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                 service::Event::ChainConnected {
                     peer_id,
                     chain_index,
+                    slot_ty,
                     best_number,
                     best_hash,
                     ..
@@ -807,6 +1869,35 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         best_number,
                         HashDisplay(&best_hash),
                     );
+                    // The addresses of peers that have connected to the local node are unknown,
+                    // and are requested in order to insert these peers in the k-buckets.
+                    if matches!(slot_ty, service::SlotTy::Inbound)
+                        && guarded.network.can_start_requests(&peer_id)
+                    {
+                        let request_id = guarded
+                            .network
+                            .start_identify_request(&peer_id, Instant::now());
+                        guarded
+                            .identify_requests
+                            .insert(request_id, (chain_index, peer_id.clone()));
+                        guarded.report_request_start(&peer_id, chain_index, request_id, "identify");
+                    }
+                    if let Some(connection_id) =
+                        guarded.network_events_connections.get(&peer_id).copied()
+                    {
+                        let substream_id = guarded.next_network_event_id();
+                        guarded
+                            .network_events_chain_substreams
+                            .insert((peer_id.clone(), chain_index), substream_id);
+                        guarded.report_network_event(NetworkEvent::SubstreamOpen {
+                            connection_id,
+                            substream_id,
+                            chain_index,
+                            protocol_name: "block-announces",
+                        });
+                        guarded
+                            .report_network_event(NetworkEvent::SubstreamAccept { substream_id });
+                    }
                     break Event::Connected {
                         peer_id,
                         chain_index,
@@ -817,7 +1908,7 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                 service::Event::ChainDisconnected {
                     peer_id,
                     chain_index,
-                    ..
+                    unassigned_slot_ty,
                 } => {
                     log::debug!(
                         "chain-disconnected; peer_id={}; chain_index={}",
@@ -825,6 +1916,20 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         chain_index
                     );
 
+                    if let Some(substream_id) = guarded
+                        .network_events_chain_substreams
+                        .remove(&(peer_id.clone(), chain_index))
+                    {
+                        guarded.report_network_event(NetworkEvent::SubstreamStop {
+                            substream_id,
+                            reason: "closed".into(),
+                        });
+                    }
+                    guarded.report_network_event(NetworkEvent::SlotUnassign {
+                        chain_index,
+                        peer_id: peer_id.clone(),
+                        slot_ty: unassigned_slot_ty,
+                    });
                     guarded.unassign_slot_and_ban(chain_index, peer_id.clone());
                     inner.wake_up_main_background_task.notify(1);
 
@@ -837,35 +1942,212 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                     chain_index,
                     peer_id,
                     error,
-                    ..
+                    unassigned_slot_ty,
+                } => {
+                    log::debug!(
+                        "chain-connect-attempt-failed; peer_id={}; chain_index={}; error={}",
+                        peer_id,
+                        chain_index,
+                        error
+                    );
+
+                    guarded.report_network_event(NetworkEvent::SlotUnassign {
+                        chain_index,
+                        peer_id: peer_id.clone(),
+                        slot_ty: unassigned_slot_ty,
+                    });
+
+                    guarded.unassign_slot_and_ban(chain_index, peer_id);
+                    inner.wake_up_main_background_task.notify(1);
+                }
+                service::Event::InboundSlotAssigned {
+                    peer_id,
+                    chain_index,
+                } => {
+                    // TODO: log this
+                    guarded.report_network_event(NetworkEvent::SlotAssign {
+                        chain_index,
+                        peer_id,
+                        slot_ty: service::SlotTy::Inbound,
+                    });
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::Blocks(response),
+                } => {
+                    guarded.report_outgoing_request("blocks", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .blocks_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::GrandpaWarpSync(response),
+                } => {
+                    guarded.report_outgoing_request("grandpa-warp-sync", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .grandpa_warp_sync_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::State(response),
+                } => {
+                    guarded.report_outgoing_request("state", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .state_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::StorageProof(response),
+                } => {
+                    guarded.report_outgoing_request("storage-proof", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .storage_proof_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::CallProof(response),
+                } => {
+                    guarded.report_outgoing_request("call-proof", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .call_proof_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::KademliaFindNode(response),
+                } => {
+                    guarded.report_outgoing_request("kademlia-find-node", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let (chain_index, request) = guarded
+                        .kademlia_find_node_requests
+                        .remove(&request_id)
+                        .unwrap();
+                    let response = match response {
+                        Ok(closer_peers) => {
+                            // The node has answered, and is thus inserted in the k-buckets.
+                            guarded.network.discover(
+                                &Instant::now(),
+                                chain_index,
+                                request.target,
+                                request.addresses,
+                            );
+                            Ok(closer_peers)
+                        }
+                        Err(error) => {
+                            log::debug!(
+                                "kademlia-find-node-error; chain_index={}; error={}",
+                                chain_index,
+                                error
+                            );
+                            Err(())
+                        }
+                    };
+                    guarded.kademlia[chain_index].inject_response(request.request_id, response);
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::Identify(response),
+                } => {
+                    guarded.report_outgoing_request("identify", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let (chain_index, peer_id) =
+                        guarded.identify_requests.remove(&request_id).unwrap();
+                    match response {
+                        Ok(response) => {
+                            let listen_addrs = response.decode().listen_addrs.collect::<Vec<_>>();
+                            log::debug!(
+                                "identify-response; peer_id={}; chain_index={}; num_listen_addrs={}",
+                                peer_id,
+                                chain_index,
+                                listen_addrs.len()
+                            );
+                            guarded.network.discover(
+                                &Instant::now(),
+                                chain_index,
+                                peer_id,
+                                listen_addrs,
+                            );
+                        }
+                        Err(error) => {
+                            log::debug!(
+                                "identify-error; peer_id={}; chain_index={}; error={}",
+                                peer_id,
+                                chain_index,
+                                error
+                            );
+                        }
+                    }
+                }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::KademliaGetValue(response),
                 } => {
-                    log::debug!(
-                        "chain-connect-attempt-failed; peer_id={}; chain_index={}; error={}",
-                        peer_id,
-                        chain_index,
-                        error
+                    guarded.report_outgoing_request("kademlia-get-value", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
                     );
-
-                    guarded.unassign_slot_and_ban(chain_index, peer_id);
-                    inner.wake_up_main_background_task.notify(1);
-                }
-                service::Event::InboundSlotAssigned { .. } => {
-                    // TODO: log this
+                    let _ = guarded
+                        .kademlia_get_value_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
                 }
                 service::Event::RequestResult {
                     request_id,
-                    response: service::RequestResult::Blocks(response),
+                    response: service::RequestResult::KademliaPutValue(response),
                 } => {
+                    guarded.report_outgoing_request("kademlia-put-value", response.is_ok());
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
                     let _ = guarded
-                        .blocks_requests
+                        .kademlia_put_value_requests
                         .remove(&request_id)
                         .unwrap()
                         .send(response);
                 }
-                service::Event::RequestResult { .. } => {
-                    // We never start a request of any other kind.
-                    unreachable!()
-                }
                 service::Event::RequestInCancel { .. } => {
                     // Requests are answered immediately, and thus cancelling events can't happen.
                     unreachable!()
@@ -874,31 +2156,88 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                     operation_id,
                     result,
                 } => {
-                    let chain_index = guarded
-                        .kademlia_discovery_operations
-                        .remove(&operation_id)
-                        .unwrap();
-                    match result {
-                        Ok(nodes) => {
-                            log::debug!("discovered; nodes={:?}", nodes);
-                            for (peer_id, addrs) in nodes {
-                                guarded.network.discover(
-                                    &Instant::now(),
-                                    chain_index,
-                                    peer_id,
-                                    addrs,
-                                );
-                            }
-                        }
-                        Err(error) => {
-                            log::debug!("discovery-error; error={}", error);
-                        }
-                    }
+                    // Discovery is performed through `Guarded::kademlia`, and discovery rounds
+                    // of the network state machine are never started. As this event doesn't
+                    // indicate which chain it concerns, it is simply ignored.
+                    log::debug!(
+                        "kademlia-discovery-result-ignored; operation_id={:?}; success={}",
+                        operation_id,
+                        result.is_ok()
+                    );
+                }
+                service::Event::KademliaFindNodeRequestIn {
+                    peer_id,
+                    chain_index,
+                    key,
+                    request_id,
+                } => {
+                    guarded.report_incoming_request("kademlia-find-node");
+                    log::debug!(
+                        "incoming-kademlia-find-node-request; peer_id={}; chain_index={}",
+                        peer_id,
+                        chain_index
+                    );
+                    let closer_peers = guarded.network.closest_peers(chain_index, &key);
+                    guarded
+                        .network
+                        .respond_kademlia_find_node(request_id, &closer_peers);
+                }
+                service::Event::KademliaGetValueRequestIn {
+                    peer_id,
+                    chain_index,
+                    key,
+                    request_id,
+                } => {
+                    guarded.report_incoming_request("kademlia-get-value");
+                    let closer_peers = guarded.network.closest_peers(chain_index, &key);
+                    let value = guarded.kademlia_records[chain_index]
+                        .get(&Instant::now(), &key)
+                        .map(|value| value.to_vec());
+                    log::debug!(
+                        "incoming-kademlia-get-value-request; peer_id={}; chain_index={}; found={}",
+                        peer_id,
+                        chain_index,
+                        value.is_some()
+                    );
+                    guarded.network.respond_kademlia_get_value(
+                        request_id,
+                        value.as_deref(),
+                        &closer_peers,
+                    );
+                }
+                service::Event::KademliaPutValueRequestIn {
+                    peer_id,
+                    chain_index,
+                    key,
+                    value,
+                    request_id,
+                } => {
+                    guarded.report_incoming_request("kademlia-put-value");
+                    // Only the records of the current authorities are stored, as storing any
+                    // record would let remotes use the local node as a free storage space.
+                    let result = match guarded.authority_discovery[chain_index]
+                        .verify_record(&key, &value)
+                    {
+                        Ok(_) => guarded.kademlia_records[chain_index]
+                            .put(&Instant::now(), key, value)
+                            .map_err(|err| err.to_string()),
+                        Err(err) => Err(err.to_string()),
+                    };
+                    log::debug!(
+                        "incoming-kademlia-put-value-request; peer_id={}; chain_index={}; result={:?}",
+                        peer_id,
+                        chain_index,
+                        result
+                    );
+                    guarded
+                        .network
+                        .respond_kademlia_put_value(request_id, result.is_ok());
                 }
                 service::Event::IdentifyRequestIn {
                     peer_id,
                     request_id,
                 } => {
+                    guarded.report_incoming_request("identify");
                     log::debug!("identify-request; peer_id={}", peer_id);
                     guarded.network.respond_identify(request_id, "smoldot");
                 }
@@ -908,6 +2247,7 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                     config,
                     request_id,
                 } => {
+                    guarded.report_incoming_request("blocks");
                     log::debug!(
                         "incoming-blocks-request; peer_id={}; chain_index={}",
                         peer_id,
@@ -944,6 +2284,75 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         },
                     );
                 }
+                service::Event::StorageProofRequestIn {
+                    peer_id,
+                    chain_index,
+                    block_hash,
+                    keys,
+                    request_id,
+                } => {
+                    guarded.report_incoming_request("storage-proof");
+                    log::debug!(
+                        "incoming-storage-proof-request; peer_id={}; chain_index={}; block_hash={}",
+                        peer_id,
+                        chain_index,
+                        HashDisplay(&block_hash)
+                    );
+
+                    // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
+                    let response =
+                        storage_proof_response(&inner.databases[chain_index], block_hash, keys)
+                            .await;
+                    guarded.network.respond_storage_proof(
+                        request_id,
+                        match &response {
+                            Ok(proof) => Some(proof),
+                            Err(error) => {
+                                log::debug!(
+                                    "incoming-storage-proof-request-error; error={}",
+                                    error
+                                );
+                                None
+                            }
+                        },
+                    );
+                }
+                service::Event::CallProofRequestIn {
+                    peer_id,
+                    chain_index,
+                    block_hash,
+                    method,
+                    parameter,
+                    request_id,
+                } => {
+                    guarded.report_incoming_request("call-proof");
+                    log::debug!(
+                        "incoming-call-proof-request; peer_id={}; chain_index={}; block_hash={}; method={}",
+                        peer_id,
+                        chain_index,
+                        HashDisplay(&block_hash),
+                        method
+                    );
+
+                    // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
+                    let response = call_proof_response(
+                        &inner.databases[chain_index],
+                        block_hash,
+                        method,
+                        parameter,
+                    )
+                    .await;
+                    guarded.network.respond_call_proof(
+                        request_id,
+                        match &response {
+                            Ok(proof) => Some(proof),
+                            Err(error) => {
+                                log::debug!("incoming-call-proof-request-error; error={}", error);
+                                None
+                            }
+                        },
+                    );
+                }
                 service::Event::GrandpaNeighborPacket {
                     chain_index,
                     peer_id,
@@ -970,6 +2379,36 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                         HashDisplay(message.decode().message.target_hash),
                     );
                 }
+                service::Event::GrandpaVoteMessage {
+                    chain_index,
+                    peer_id,
+                    message,
+                } => {
+                    // Note that the round number reported by neighbor packets isn't taken into
+                    // account, as it could be set to any value by a malicious peer. Only the
+                    // votes, whose signatures are verified, make the round advance.
+                    let (vote, round_state) = match (
+                        message.as_round_state_vote(),
+                        &mut guarded.grandpa_round_states[chain_index],
+                    ) {
+                        (Some(vote), Some(round_state)) => (vote, round_state),
+                        _ => continue,
+                    };
+
+                    let result = round_state.inject_vote(vote.clone());
+                    log::debug!(
+                        "grandpa-vote-message; peer_id={}; chain_index={}; round_number={}; set_id={}; target_hash={}; outcome={}",
+                        peer_id,
+                        chain_index,
+                        vote.round_number,
+                        vote.set_id,
+                        HashDisplay(vote.target_hash),
+                        match result {
+                            Ok(()) => "ok".to_owned(),
+                            Err(error) => error.to_string(),
+                        }
+                    );
+                }
                 service::Event::ProtocolError { peer_id, error } => {
                     log::warn!("protocol-error; peer_id={}; error={}", peer_id, error);
                     for chain_index in 0..guarded.network.num_chains() {
@@ -1003,6 +2442,75 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
         guarded = inner.guarded.lock().await;
     }
 
+    // Start the Kademlia requests whose target the local node has finished connecting to, and
+    // give up on the ones whose target couldn't be reached in time.
+    {
+        let now = Instant::now();
+        for (chain_index, request, timeout) in
+            mem::take(&mut guarded.kademlia_pending_find_node_requests)
+        {
+            if guarded.network.can_start_requests(&request.target) {
+                guarded.network.remove_dial_request(&request.target);
+                guarded.start_kademlia_find_node(chain_index, request, now);
+            } else if timeout <= now {
+                guarded.network.remove_dial_request(&request.target);
+                guarded.kademlia[chain_index].inject_response(request.request_id, Err(()));
+            } else {
+                guarded
+                    .kademlia_pending_find_node_requests
+                    .push((chain_index, request, timeout));
+            }
+        }
+    }
+
+    // Start the Kademlia requests of the lookups in progress.
+    for chain_index in 0..guarded.kademlia.len() {
+        let now = Instant::now();
+
+        // Refresh the k-buckets if necessary.
+        for key in guarded.kademlia[chain_index].refresh_keys(&now) {
+            let initial_candidates = guarded.network.closest_peers(chain_index, &key);
+            guarded.kademlia[chain_index].start_lookup(key, initial_candidates);
+        }
+
+        while let Some(action) = guarded.kademlia[chain_index].next_action() {
+            match action {
+                kademlia::Action::StartRequest(request) => {
+                    if guarded.network.can_start_requests(&request.target) {
+                        guarded.start_kademlia_find_node(chain_index, request, now);
+                    } else if guarded
+                        .network
+                        .add_dial_request(request.target.clone(), request.addresses.clone())
+                    {
+                        // Requests can only be sent to nodes we're connected to. The request is
+                        // sent once the connection is established.
+                        guarded.kademlia_pending_find_node_requests.push((
+                            chain_index,
+                            request,
+                            now + Duration::from_secs(15),
+                        ));
+                    } else {
+                        // No address is known for this node.
+                        guarded.kademlia[chain_index].inject_response(request.request_id, Err(()));
+                    }
+                }
+                kademlia::Action::LookupFinished(lookup) => {
+                    log::debug!(
+                        "kademlia-lookup-finished; chain_index={}; num_found={}",
+                        chain_index,
+                        lookup.closest_peers.len()
+                    );
+                    if let Some(sender) = guarded
+                        .kademlia_lookups
+                        .remove(&(chain_index, lookup.lookup_id))
+                    {
+                        let _ = sender.send(lookup);
+                    }
+                }
+            }
+        }
+    }
+
     // TODO: doc
     for chain_index in 0..guarded.network.num_chains() {
         let now = Instant::now();
@@ -1032,7 +2540,14 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
                 peer_to_assign,
                 chain_index
             );
-            guarded.network.assign_out_slot(chain_index, peer_to_assign);
+            guarded
+                .network
+                .assign_out_slot(chain_index, peer_to_assign.clone());
+            guarded.report_network_event(NetworkEvent::SlotAssign {
+                chain_index,
+                peer_id: peer_to_assign,
+                slot_ty: service::SlotTy::Outbound,
+            });
         }
     }
 
@@ -1053,11 +2568,22 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
         guarded.num_pending_out_attempts += 1;
 
         // Perform the connection process in a separate task.
-        let task = tasks::opening_connection_task(
-            start_connect,
-            inner.clone(),
-            guarded.messages_from_connections_tx.clone(),
-        );
+        let task = if let Some(target) = quic::parse_dial_multiaddr(&start_connect.multiaddr) {
+            quic::opening_connection_task(
+                start_connect,
+                target,
+                inner.clone(),
+                guarded.messages_from_connections_tx.clone(),
+            )
+            .boxed()
+        } else {
+            tasks::opening_connection_task(
+                start_connect,
+                inner.clone(),
+                guarded.messages_from_connections_tx.clone(),
+            )
+            .boxed()
+        };
 
         // Sending the new task might fail in case a shutdown is happening, in which case
         // we don't really care about the state of anything anymore.
@@ -1085,10 +2611,66 @@ async fn update_round(inner: &Arc<Inner>, event_senders: &mut [mpsc::Sender<Even
 }
 
 impl Guarded {
+    /// Sends the given Kademlia find node request of [`Guarded::kademlia`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if no request can be sent to the target of the request.
+    ///
+    fn start_kademlia_find_node(
+        &mut self,
+        chain_index: usize,
+        request: kademlia::StartRequest,
+        now: Instant,
+    ) {
+        let request_id =
+            self.network
+                .start_kademlia_find_node(&request.target, now, chain_index, &request.key);
+        self.report_request_start(
+            &request.target,
+            chain_index,
+            request_id,
+            "kademlia-find-node",
+        );
+        let _prev_value = self
+            .kademlia_find_node_requests
+            .insert(request_id, (chain_index, request));
+        debug_assert!(_prev_value.is_none());
+    }
+
+    fn report_outgoing_request(&mut self, protocol: &'static str, success: bool) {
+        let stats = self.requests_statistics.entry(protocol).or_default();
+        if success {
+            stats.outgoing_success += 1;
+        } else {
+            stats.outgoing_failure += 1;
+        }
+    }
+
+    fn report_incoming_request(&mut self, protocol: &'static str) {
+        self.requests_statistics
+            .entry(protocol)
+            .or_default()
+            .incoming += 1;
+    }
+
     fn unassign_slot_and_ban(&mut self, chain_index: usize, peer_id: PeerId) {
-        self.network.unassign_slot(chain_index, &peer_id);
+        if let Some(slot_ty) = self.network.unassign_slot(chain_index, &peer_id) {
+            self.report_network_event(NetworkEvent::SlotUnassign {
+                chain_index,
+                peer_id: peer_id.clone(),
+                slot_ty,
+            });
+        }
 
-        let new_expiration = Instant::now() + Duration::from_secs(20); // TODO: arbitrary constant
+        // Reserved peers are reconnected to quickly. They are still briefly banned in order to
+        // not continuously try to connect to a reserved peer that is unreachable.
+        let ban_duration = if self.network.is_reserved_peer(chain_index, &peer_id) {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(20) // TODO: arbitrary constant
+        };
+        let new_expiration = Instant::now() + ban_duration;
         match self.slots_assign_backoff.entry((peer_id, chain_index)) {
             hashbrown::hash_map::Entry::Occupied(e) if *e.get() < new_expiration => {
                 *e.into_mut() = new_expiration;
@@ -1099,6 +2681,139 @@ impl Guarded {
             }
         }
     }
+
+    /// Sends the given event to the subscriptions created with
+    /// [`NetworkService::subscribe_network_events`].
+    fn report_network_event(&mut self, event: NetworkEvent) {
+        // Closed subscriptions are cleaned up here.
+        self.network_events_senders
+            .retain(|sender| !sender.is_closed());
+
+        for sender in &mut self.network_events_senders {
+            // Subscriptions that don't process their events quickly enough miss them.
+            let _ = sender.try_send(event.clone());
+        }
+    }
+
+    /// Returns a new identifier for a connection or substream reported through a
+    /// [`NetworkEvent`].
+    fn next_network_event_id(&mut self) -> u32 {
+        let id = self.network_events_next_id;
+        self.network_events_next_id = self.network_events_next_id.wrapping_add(1);
+        id
+    }
+
+    /// Reports through a [`NetworkEvent`] that the given outgoing request has been started.
+    fn report_request_start(
+        &mut self,
+        target: &PeerId,
+        chain_index: usize,
+        request_id: service::OutRequestId,
+        protocol_name: &'static str,
+    ) {
+        let Some(connection_id) = self.network_events_connections.get(target).copied() else {
+            return;
+        };
+
+        let substream_id = self.next_network_event_id();
+        self.network_events_requests
+            .insert(request_id, substream_id);
+        self.report_network_event(NetworkEvent::SubstreamOpen {
+            connection_id,
+            substream_id,
+            chain_index,
+            protocol_name,
+        });
+    }
+
+    /// Reports through a [`NetworkEvent`] that the given outgoing request has finished, either
+    /// successfully if `error` is `None`, or with the given error.
+    fn report_request_end(&mut self, request_id: service::OutRequestId, error: Option<String>) {
+        let Some(substream_id) = self.network_events_requests.remove(&request_id) else {
+            return;
+        };
+
+        self.report_network_event(match error {
+            None => NetworkEvent::SubstreamAccept { substream_id },
+            Some(reason) => NetworkEvent::SubstreamStop {
+                substream_id,
+                reason,
+            },
+        });
+    }
+}
+
+/// Loads the Kademlia routing table saved by [`save_routing_table`].
+///
+/// Returns an empty list if the file doesn't exist or is invalid.
+fn load_routing_table(path: &Path) -> Vec<(PeerId, Vec<Multiaddr>)> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            log::warn!(
+                "Failed to read Kademlia routing table from {}: {}",
+                path.display(),
+                err
+            );
+            return Vec::new();
+        }
+    };
+
+    let decoded = match serde_json::from_slice::<BTreeMap<String, Vec<String>>>(&content) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            log::warn!(
+                "Failed to decode Kademlia routing table from {}: {}",
+                path.display(),
+                err
+            );
+            return Vec::new();
+        }
+    };
+
+    decoded
+        .into_iter()
+        .filter_map(|(peer_id, addrs)| {
+            let peer_id = peer_id.parse::<PeerId>().ok()?;
+            let addrs = addrs
+                .into_iter()
+                .filter_map(|addr| addr.parse::<Multiaddr>().ok())
+                .collect();
+            Some((peer_id, addrs))
+        })
+        .collect()
+}
+
+/// Saves the given Kademlia routing table to `path`, so that it can later be loaded with
+/// [`load_routing_table`].
+///
+/// The content of the file is a JSON object whose keys are the peer IDs and values the list of
+/// addresses of each peer.
+fn save_routing_table(path: &Path, routing_table: &[(PeerId, Vec<Multiaddr>)]) {
+    let encoded = routing_table
+        .iter()
+        .map(|(peer_id, addrs)| {
+            (
+                peer_id.to_base58(),
+                addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // The routing table is first written to a temporary file then renamed, so that a
+    // partially-written file is never loaded.
+    let tmp_path = path.with_extension("tmp");
+    let result = fs::create_dir_all(path.parent().unwrap())
+        .and_then(|()| fs::write(&tmp_path, serde_json::to_vec(&encoded).unwrap()))
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if let Err(err) = result {
+        log::warn!(
+            "Failed to write Kademlia routing table to {}: {}",
+            path.display(),
+            err
+        );
+    }
 }
 
 /// Builds the response to a block request by reading from the given database.
@@ -1179,3 +2894,204 @@ async fn blocks_request_response(
         })
         .await
 }
+
+/// Builds the proof of the storage values of `keys` in the storage of the given block.
+///
+/// Only the storage of the finalized block is available in the database, and thus only the
+/// finalized block is supported.
+async fn storage_proof_response(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    keys: Vec<Vec<u8>>,
+) -> Result<Vec<u8>, ProofRequestError> {
+    database
+        .with_database(move |database| {
+            if database.finalized_block_hash()? != block_hash {
+                return Err(ProofRequestError::UnsupportedBlock);
+            }
+
+            build_finalized_storage_proof(database, &block_hash, |entries| {
+                trie::proof_encode::build_from_ordered_trie_entries(
+                    entries,
+                    keys.iter().map(|key| (key, false)),
+                )
+                .build_to_vec()
+            })
+        })
+        .await
+}
+
+/// Performs a runtime call on top of the given block, and builds the proof of all the storage
+/// accesses performed by this call.
+///
+/// Only the storage of the finalized block is available in the database, and thus only the
+/// finalized block is supported.
+async fn call_proof_response(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    method: String,
+    parameter: Vec<u8>,
+) -> Result<Vec<u8>, ProofRequestError> {
+    database
+        .with_database(move |database| {
+            if database.finalized_block_hash()? != block_hash {
+                return Err(ProofRequestError::UnsupportedBlock);
+            }
+
+            let storage_get = |key: &[u8]| {
+                database
+                    .finalized_block_storage_main_trie_get(&block_hash, key)?
+                    .map(|(value, version)| {
+                        TrieEntryVersion::try_from(version)
+                            .map(|version| (value, version))
+                            .map_err(|_| ProofRequestError::InvalidTrieEntryVersion)
+                    })
+                    .transpose()
+            };
+
+            // TODO: the runtime is compiled again for every request; consider caching it
+            // The method and parameter come from a peer of the network, and the call is thus
+            // executed with resource limits.
+            let virtual_machine = {
+                let (code, _) = storage_get(b":code")?.ok_or(ProofRequestError::MissingCode)?;
+                let heap_pages = executor::storage_heap_pages_to_value(
+                    storage_get(b":heappages")?
+                        .as_ref()
+                        .map(|(value, _)| &value[..]),
+                )
+                .map_err(ProofRequestError::InvalidHeapPages)?;
+                host::HostVmPrototype::new(host::Config {
+                    module: code,
+                    heap_pages,
+                    exec_hint: executor::vm::ExecHint::Untrusted,
+                    resource_limits: super::UNTRUSTED_RUNTIME_CALLS_RESOURCE_LIMITS,
+                    allow_unresolved_imports: true,
+                })
+                .map_err(ProofRequestError::RuntimeBuild)?
+            };
+
+            let mut call = runtime_host::run(runtime_host::Config {
+                virtual_machine,
+                function_to_call: &method,
+                parameter: iter::once(&parameter),
+                main_trie_root_calculation_cache: None,
+                storage_main_trie_changes: Default::default(),
+                offchain_storage_changes: Default::default(),
+                storage_proof_recorder: Some(runtime_host::StorageProofRecorder::new()),
+                max_log_level: 0,
+                host_functions_profiler: None,
+            })
+            .map_err(|(err, _)| ProofRequestError::StartError(err))?;
+
+            let recorder = loop {
+                call = match call {
+                    runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                        break success.storage_proof_recorder.unwrap()
+                    }
+                    runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                        return Err(ProofRequestError::RuntimeError(error.detail))
+                    }
+                    runtime_host::RuntimeHostVm::StorageGet(get) => {
+                        let value = storage_get(get.key().as_ref())?;
+                        get.inject_value(value.map(|(value, version)| (iter::once(value), version)))
+                    }
+                    runtime_host::RuntimeHostVm::PrefixKeys(prefix_keys) => {
+                        let keys = database.finalized_block_storage_main_trie_keys(
+                            &block_hash,
+                            prefix_keys.prefix().as_ref(),
+                        )?;
+                        prefix_keys.inject_keys_ordered(keys.into_iter())
+                    }
+                    runtime_host::RuntimeHostVm::NextKey(next_key) => {
+                        let key = database.finalized_block_storage_main_trie_next_key(
+                            &block_hash,
+                            next_key.key().as_ref(),
+                        )?;
+                        next_key.inject_key(key)
+                    }
+                    runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                        sig.verify_and_resume()
+                    }
+                };
+            };
+
+            build_finalized_storage_proof(database, &block_hash, |entries| {
+                recorder.build_proof(entries)
+            })
+        })
+        .await
+}
+
+/// Calls `build` with the ordered entries of the storage of the finalized block and returns
+/// its output.
+fn build_finalized_storage_proof(
+    database: &full_sqlite::SqliteFullDatabase,
+    finalized_block_hash: &[u8; 32],
+    build: impl FnOnce(&mut dyn Iterator<Item = (Vec<u8>, Vec<u8>, TrieEntryVersion)>) -> Vec<u8>,
+) -> Result<Vec<u8>, ProofRequestError> {
+    // The proof is built while the entries are read from the database, in order to avoid
+    // loading the entire storage in memory.
+    let mut invalid_version = false;
+    let proof = database.with_finalized_block_storage_main_trie_ordered(
+        finalized_block_hash,
+        |entries| {
+            let mut entries = entries.map_while(|(key, value, version)| {
+                match TrieEntryVersion::try_from(version) {
+                    Ok(version) => Some((key, value, version)),
+                    Err(_) => {
+                        invalid_version = true;
+                        None
+                    }
+                }
+            });
+            build(&mut entries)
+        },
+    )?;
+
+    if invalid_version {
+        return Err(ProofRequestError::InvalidTrieEntryVersion);
+    }
+
+    Ok(proof)
+}
+
+/// Error potentially returned when answering a storage proof request or a call proof request.
+#[derive(Debug, derive_more::Display)]
+enum ProofRequestError {
+    /// Error while accessing the database.
+    #[display(fmt = "{_0}")]
+    Database(full_sqlite::FinalizedAccessError),
+    /// The requested block isn't the finalized block.
+    #[display(fmt = "Only the finalized block is supported")]
+    UnsupportedBlock,
+    /// The database contains an invalid trie entry version.
+    #[display(fmt = "Invalid trie entry version in the database")]
+    InvalidTrieEntryVersion,
+    /// No `:code` found in the storage of the finalized block.
+    #[display(fmt = "No `:code` found in the storage")]
+    MissingCode,
+    /// Error while parsing the `:heappages` storage value of the finalized block.
+    #[display(fmt = "Failed to parse `:heappages` storage value: {_0}")]
+    InvalidHeapPages(executor::InvalidHeapPagesError),
+    /// Error while compiling the runtime of the finalized block.
+    #[display(fmt = "Failed to compile the runtime: {_0}")]
+    RuntimeBuild(host::NewErr),
+    /// Error while starting the runtime call.
+    #[display(fmt = "{_0}")]
+    StartError(host::StartErr),
+    /// Error during the execution of the runtime call.
+    #[display(fmt = "{_0}")]
+    RuntimeError(runtime_host::ErrorDetail),
+}
+
+impl From<full_sqlite::FinalizedAccessError> for ProofRequestError {
+    fn from(err: full_sqlite::FinalizedAccessError) -> Self {
+        ProofRequestError::Database(err)
+    }
+}
+
+impl From<full_sqlite::AccessError> for ProofRequestError {
+    fn from(err: full_sqlite::AccessError) -> Self {
+        ProofRequestError::Database(full_sqlite::FinalizedAccessError::Access(err))
+    }
+}
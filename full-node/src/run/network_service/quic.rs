@@ -0,0 +1,599 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! QUIC connections.
+//!
+//! See <https://github.com/libp2p/specs/blob/master/quic/README.md>.
+//!
+//! QUIC connections go through QUIC endpoints, each bound to a UDP socket. One endpoint is
+//! created for each QUIC listening address, and these endpoints are also used to dial remotes.
+//! If no endpoint exists for a certain IP version, an endpoint dedicated to dialing is created.
+//!
+//! Remotes are authenticated by the libp2p TLS handshake (see
+//! [`smoldot::libp2p::connection::tls`]), after which the connection is reported to the network
+//! state machine as a multi-stream connection. Each bidirectional QUIC stream maps to a
+//! substream.
+
+use super::Inner;
+
+use futures::{channel::mpsc, prelude::*};
+use futures_timer::Delay;
+use smoldot::{
+    libp2p::{
+        async_std_connection::{quic, with_buffers},
+        collection::SubstreamFate,
+        connection::tls,
+        multiaddr::{Multiaddr, ProtocolRef},
+        read_write::ReadWrite,
+    },
+    network::service,
+};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// Returns the address to bind to if the given multiaddress is a QUIC listening address, or
+/// `None` otherwise.
+pub(super) fn parse_listen_multiaddr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    match (iter.next(), iter.next(), iter.next(), iter.next()) {
+        (
+            Some(ProtocolRef::Ip4(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::QuicV1),
+            None,
+        ) => Some(SocketAddr::from((ip, port))),
+        (
+            Some(ProtocolRef::Ip6(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::QuicV1),
+            None,
+        ) => Some(SocketAddr::from((ip, port))),
+        _ => None,
+    }
+}
+
+/// Returns the target to connect to if the given multiaddress is a QUIC address, or `None`
+/// otherwise.
+pub(super) fn parse_dial_multiaddr(
+    addr: &Multiaddr,
+) -> Option<either::Either<SocketAddr, (String, u16)>> {
+    let mut iter = addr.iter();
+    match (iter.next(), iter.next(), iter.next(), iter.next()) {
+        (
+            Some(ProtocolRef::Ip4(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::QuicV1),
+            None,
+        ) => Some(either::Left(SocketAddr::from((ip, port)))),
+        (
+            Some(ProtocolRef::Ip6(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::QuicV1),
+            None,
+        ) => Some(either::Left(SocketAddr::from((ip, port)))),
+        // TODO: we don't care about the differences between Dns, Dns4, and Dns6
+        (
+            Some(ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::QuicV1),
+            None,
+        ) => Some(either::Right((addr.to_string(), port))),
+        _ => None,
+    }
+}
+
+/// Builds the multiaddress of the given socket address.
+pub(super) fn socket_addr_to_multiaddr(addr: SocketAddr) -> Multiaddr {
+    [
+        match addr.ip() {
+            IpAddr::V4(ip) => ProtocolRef::Ip4(ip.octets()),
+            IpAddr::V6(ip) => ProtocolRef::Ip6(ip.octets()),
+        },
+        ProtocolRef::Udp(addr.port()),
+        ProtocolRef::QuicV1,
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Builds a QUIC endpoint on top of the given UDP socket.
+///
+/// If `accept_incoming` is `false`, the endpoint refuses all incoming connections.
+pub(super) fn endpoint(
+    socket: std::net::UdpSocket,
+    libp2p_key: &[u8; 32],
+    accept_incoming: bool,
+) -> Result<quinn::Endpoint, io::Error> {
+    let certificate = tls::generate_certificate(libp2p_key, &rand::random(), rand::random());
+
+    let server_config = if accept_incoming {
+        Some(quic::server_config(&certificate).map_err(io::Error::other)?)
+    } else {
+        None
+    };
+
+    let client_config = quic::client_config(&certificate).map_err(io::Error::other)?;
+
+    let mut endpoint = quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        server_config,
+        socket,
+        Arc::new(quinn::AsyncStdRuntime),
+    )?;
+    endpoint.set_default_client_config(client_config);
+    Ok(endpoint)
+}
+
+/// Asynchronous task accepting the incoming connections of the given QUIC endpoint.
+pub(super) async fn listener_task(
+    endpoint: quinn::Endpoint,
+    inner: Arc<Inner>,
+    mut conn_tasks_tx: mpsc::Sender<Pin<Box<dyn Future<Output = ()> + Send>>>,
+) {
+    while let Some(connecting) = endpoint.accept().await {
+        let task = incoming_connection_task(connecting, inner.clone());
+
+        // Ignore errors, as it is possible for the destination task to have been aborted
+        // already.
+        let _ = conn_tasks_tx.send(Box::pin(task)).await;
+    }
+}
+
+/// Asynchronous task managing a specific outgoing connection, including the dialing process.
+pub(super) async fn opening_connection_task(
+    start_connect: service::StartConnect<Instant>,
+    target: either::Either<SocketAddr, (String, u16)>,
+    inner: Arc<Inner>,
+    connection_to_coordinator: mpsc::Sender<(
+        service::ConnectionId,
+        service::ConnectionToCoordinator,
+    )>,
+) {
+    let connect = async {
+        let target = match target {
+            either::Left(addr) => addr,
+            either::Right((host, port)) => {
+                async_std::net::ToSocketAddrs::to_socket_addrs(&(&host[..], port))
+                    .await?
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?
+            }
+        };
+
+        let endpoint = inner
+            .guarded
+            .lock()
+            .await
+            .quic_endpoints
+            .iter()
+            .find(|endpoint| {
+                matches!(endpoint.local_addr(), Ok(addr) if addr.is_ipv4() == target.is_ipv4())
+            })
+            .cloned()
+            .ok_or_else(|| io::Error::other("no QUIC endpoint available"))?;
+
+        // The server name is irrelevant, as it isn't verified.
+        let connection = endpoint
+            .connect(target, "libp2p")
+            .map_err(io::Error::other)?
+            .await
+            .map_err(io::Error::other)?;
+
+        quic::remote_peer_id(&connection)
+            .map(|peer_id| (connection, peer_id))
+            .ok_or_else(|| io::Error::other("invalid certificate"))
+    };
+
+    let timeout = Delay::new(
+        start_connect
+            .timeout
+            .saturating_duration_since(Instant::now()),
+    );
+
+    // The `connect` future borrows `inner` and must be destroyed before the connection task
+    // starts, hence the block.
+    let outcome = {
+        futures::pin_mut!(connect);
+        match future::select(connect, timeout).await {
+            future::Either::Left((result, _)) => Some(result),
+            future::Either::Right(((), _)) => None,
+        }
+    };
+
+    let (connection, remote_peer_id) = match outcome {
+        Some(Ok(connection)) => connection,
+        Some(Err(error)) => {
+            log::debug!(
+                "quic-connection-error; address={}; error={}",
+                start_connect.multiaddr,
+                error
+            );
+            let mut guarded = inner.guarded.lock().await;
+            guarded.num_pending_out_attempts -= 1;
+            guarded.network.pending_outcome_err(start_connect.id, true);
+            for chain_index in 0..guarded.network.num_chains() {
+                guarded.unassign_slot_and_ban(chain_index, start_connect.expected_peer_id.clone());
+            }
+            inner.wake_up_main_background_task.notify(1);
+            return;
+        }
+        None => {
+            let mut guarded = inner.guarded.lock().await;
+            guarded.num_pending_out_attempts -= 1;
+            guarded.network.pending_outcome_err(start_connect.id, false);
+            for chain_index in 0..guarded.network.num_chains() {
+                guarded.unassign_slot_and_ban(chain_index, start_connect.expected_peer_id.clone());
+            }
+            inner.wake_up_main_background_task.notify(1);
+            return;
+        }
+    };
+
+    // Inform the underlying network state machine that this dialing attempt
+    // has succeeded.
+    let mut guarded = inner.guarded.lock().await;
+    guarded.num_pending_out_attempts -= 1;
+    let (connection_id, connection_task) = guarded.network.pending_outcome_ok_multi_stream(
+        start_connect.id,
+        service::MultiStreamHandshakeKind::Quic { remote_peer_id },
+    );
+    inner.wake_up_main_background_task.notify(1);
+
+    let (tx, rx) = mpsc::channel(16); // TODO: ?!
+    guarded.active_connections.insert(connection_id, tx);
+
+    // Now run the connection.
+    drop(guarded);
+    connection_task_run(
+        connection,
+        inner,
+        connection_id,
+        connection_task,
+        rx,
+        connection_to_coordinator,
+    )
+    .await;
+}
+
+/// Asynchronous task managing a connection that has been accepted by a listener.
+///
+/// The QUIC handshake is performed within the task of the connection, in order to not block the
+/// listener while it is in progress.
+async fn incoming_connection_task(connecting: quinn::Connecting, inner: Arc<Inner>) {
+    let multiaddr = socket_addr_to_multiaddr(connecting.remote_address());
+    log::debug!("incoming-connection; multiaddr={}", multiaddr);
+
+    let timeout = Delay::new(Duration::from_secs(10));
+    futures::pin_mut!(connecting);
+    let connection = match future::select(connecting, timeout).await {
+        future::Either::Left((Ok(connection), _)) => connection,
+        future::Either::Left((Err(error), _)) => {
+            log::debug!(
+                "quic-handshake-error; multiaddr={}; error={}",
+                multiaddr,
+                error
+            );
+            return;
+        }
+        future::Either::Right(((), _)) => {
+            log::debug!("quic-handshake-timeout; multiaddr={}", multiaddr);
+            return;
+        }
+    };
+
+    let Some(remote_peer_id) = quic::remote_peer_id(&connection) else {
+        log::debug!(
+            "quic-handshake-error; multiaddr={}; error=invalid certificate",
+            multiaddr
+        );
+        return;
+    };
+
+    let mut guarded = inner.guarded.lock().await;
+    let (connection_id, connection_task) = guarded.network.add_multi_stream_incoming_connection(
+        Instant::now(),
+        service::MultiStreamHandshakeKind::Quic { remote_peer_id },
+        multiaddr,
+    );
+
+    let (tx, rx) = mpsc::channel(16); // TODO: ?!
+    guarded.active_connections.insert(connection_id, tx);
+    let connection_to_coordinator = guarded.messages_from_connections_tx.clone();
+
+    // Now run the connection.
+    drop(guarded);
+    connection_task_run(
+        connection,
+        inner,
+        connection_id,
+        connection_task,
+        rx,
+        connection_to_coordinator,
+    )
+    .await;
+}
+
+/// Asynchronous task managing a specific established connection.
+async fn connection_task_run(
+    connection: quinn::Connection,
+    inner: Arc<Inner>,
+    connection_id: service::ConnectionId,
+    mut connection_task: service::MultiStreamConnectionTask<Instant, u64>,
+    coordinator_to_connection: mpsc::Receiver<service::CoordinatorToConnection<Instant>>,
+    mut connection_to_coordinator: mpsc::Sender<(
+        service::ConnectionId,
+        service::ConnectionToCoordinator,
+    )>,
+) {
+    // We need to use `peek()` on this future later down this function.
+    let mut coordinator_to_connection = coordinator_to_connection.peekable();
+
+    // List of all the substreams that are currently open, indexed by an identifier attributed
+    // locally.
+    let mut open_substreams = hashbrown::HashMap::<
+        u64,
+        with_buffers::WithBuffers<quic::QuicStream>,
+        fnv::FnvBuildHasher,
+    >::default();
+    let mut next_substream_id = 0u64;
+
+    // Outbound substreams that are being opened.
+    let mut pending_outbound_substreams = stream::FuturesUnordered::new();
+
+    // Substreams whose writing side has been closed, and whose data still has to be sent out
+    // before they can be dropped.
+    let mut closing_substreams = stream::FuturesUnordered::new();
+
+    // Future that is ready when the remote opens a new substream.
+    let mut inbound_substream = accept_bi(&connection).fuse();
+
+    // `true` if the connection is dead, either because of an error or because the remote has
+    // closed it, and the connection task has been notified.
+    let mut reset_reported = false;
+
+    loop {
+        // Inject in the connection task the messages coming from the coordinator, if any.
+        while let Some(Some(message)) = coordinator_to_connection.next().now_or_never() {
+            connection_task.inject_coordinator_message(message);
+        }
+
+        // Start opening new outbound substreams, if needed.
+        if !reset_reported {
+            for _ in 0..(connection_task.desired_outbound_substreams() as usize)
+                .saturating_sub(pending_outbound_substreams.len())
+            {
+                let connection = connection.clone();
+                pending_outbound_substreams.push(async move { connection.open_bi().await });
+            }
+        }
+
+        let now = Instant::now();
+
+        // When reading/writing substreams, the substream can ask to be woken up after a certain
+        // time. This variable stores the earliest time when we should be waking up.
+        let mut wake_up_after = None;
+
+        // Perform a read-write on all substreams.
+        // TODO: trying to read/write every single substream every single time is suboptimal
+        for substream_id in open_substreams.keys().copied().collect::<Vec<_>>() {
+            loop {
+                let substream = open_substreams.get_mut(&substream_id).unwrap();
+
+                let (read_buffer, write_buffer) = match substream.buffers() {
+                    Ok(b) => b,
+                    Err(error) => {
+                        log::debug!("quic-substream-error; error={}", error);
+                        connection_task.reset_substream(&substream_id);
+                        open_substreams.remove(&substream_id);
+                        break;
+                    }
+                };
+
+                let outgoing_buffer_was_closed = write_buffer.is_none();
+
+                let mut read_write = ReadWrite {
+                    now,
+                    incoming_buffer: read_buffer.map(|b| b.0),
+                    outgoing_buffer: write_buffer,
+                    read_bytes: 0,
+                    written_bytes: 0,
+                    wake_up_after,
+                };
+
+                let substream_fate =
+                    connection_task.substream_read_write(&substream_id, &mut read_write);
+
+                // We need to destroy `read_write` in order to un-borrow `substream`.
+                let read_bytes = read_write.read_bytes;
+                let written_bytes = read_write.written_bytes;
+                wake_up_after = read_write.wake_up_after.take();
+                let outgoing_buffer_now_closed = read_write.outgoing_buffer.is_none();
+
+                if outgoing_buffer_now_closed && !outgoing_buffer_was_closed {
+                    substream.close();
+                }
+
+                substream.advance(read_bytes, written_bytes);
+
+                if matches!(substream_fate, SubstreamFate::Reset) {
+                    // If the writing side has been closed, the data that is still buffered must
+                    // be sent out before the stream is dropped. Otherwise, dropping the stream
+                    // resets it.
+                    let substream = open_substreams.remove(&substream_id).unwrap();
+                    if substream.is_closed() {
+                        closing_substreams.push(flush_close(substream));
+                    }
+                    break;
+                }
+
+                if read_bytes == 0 && written_bytes == 0 {
+                    break;
+                }
+            }
+        }
+
+        // Try pull message to send to the coordinator.
+
+        // Calling this method takes ownership of the task and returns that task if it has
+        // more work to do. If `None` is returned, then the entire task is gone and the
+        // connection must be abruptly closed, which is what happens when we return from
+        // this function.
+        let (mut task_update, message) = connection_task.pull_message_to_coordinator();
+
+        // If `task_update` is `None`, the connection task is going to die as soon as the
+        // message reaches the coordinator. Before returning, we need to do a bit of clean up
+        // by removing the task from the list of active connections.
+        // This is done before the message is sent to the coordinator, in order to be sure
+        // that the connection id is still attributed to the current task, and not to a new
+        // connection that the coordinator has assigned after receiving the message.
+        if task_update.is_none() {
+            let mut guarded = inner.guarded.lock().await;
+            let _was_in = guarded.active_connections.remove(&connection_id);
+            debug_assert!(_was_in.is_some());
+        }
+
+        let has_message = message.is_some();
+        if let Some(message) = message {
+            // Sending this message might take a long time (in case the coordinator is busy),
+            // but this is intentional and serves as a back-pressure mechanism.
+            // However, it is important to continue processing the messages coming from the
+            // coordinator, otherwise this could result in a deadlock.
+            loop {
+                futures::select! {
+                    _ = future::poll_fn(|cx| connection_to_coordinator.poll_ready(cx)).fuse() => break,
+                    message = coordinator_to_connection.next() => {
+                        if let Some(message) = message {
+                            if let Some(task_update) = &mut task_update {
+                                task_update.inject_coordinator_message(message);
+                            }
+                        } else {
+                            return;
+                        }
+                    }
+                }
+            }
+            let result = connection_to_coordinator.try_send((connection_id, message));
+            inner.wake_up_main_background_task.notify(1);
+            if result.is_err() {
+                return;
+            }
+        }
+
+        if let Some(task_update) = task_update {
+            connection_task = task_update;
+        } else {
+            return;
+        }
+
+        if has_message {
+            continue;
+        }
+
+        // Starting from here, we block the current task until more processing needs to happen.
+
+        // Future ready when the timeout indicated by the connection state machine is reached.
+        let poll_after = if let Some(wake_up) = wake_up_after {
+            if wake_up > now {
+                future::Either::Left(Delay::new(wake_up - now))
+            } else {
+                // "Wake up" immediately.
+                continue;
+            }
+        } else {
+            future::Either::Right(future::pending())
+        }
+        .fuse();
+        futures::pin_mut!(poll_after);
+
+        // Future that is woken up when new data is ready on any of the substreams.
+        let substreams_ready = future::poll_fn(|cx| {
+            for substream in open_substreams.values_mut() {
+                let process = Pin::new(substream).process();
+                futures::pin_mut!(process);
+                if process.poll(cx).is_ready() {
+                    return Poll::Ready(());
+                }
+            }
+            Poll::Pending
+        })
+        .fuse();
+        futures::pin_mut!(substreams_ready);
+
+        // Future that is woken up when a new message is coming from the coordinator.
+        let message_from_coordinator = Pin::new(&mut coordinator_to_connection).peek().fuse();
+        futures::pin_mut!(message_from_coordinator);
+
+        let new_substream = futures::select! {
+            _ = message_from_coordinator => None,
+            () = poll_after => None,
+            () = substreams_ready => None,
+            () = closing_substreams.select_next_some() => None,
+            result = pending_outbound_substreams.select_next_some() => Some((result, true)),
+            result = &mut inbound_substream => Some((result, false)),
+        };
+
+        match new_substream {
+            None => {}
+            Some((Ok((send, recv)), outbound)) if !reset_reported => {
+                let substream_id = next_substream_id;
+                next_substream_id += 1;
+                connection_task.add_substream(substream_id, outbound);
+                open_substreams.insert(
+                    substream_id,
+                    with_buffers::WithBuffers::new(quic::QuicStream::new(send, recv)),
+                );
+                if !outbound {
+                    inbound_substream = accept_bi(&connection).fuse();
+                }
+            }
+            Some((Err(error), _)) if !reset_reported => {
+                // Failing to open or accept a stream means that the connection is dead.
+                log::debug!("quic-connection-error; error={}", error);
+                connection_task.reset();
+                open_substreams.clear();
+                reset_reported = true;
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Returns a future that accepts the next bidirectional stream opened by the remote.
+fn accept_bi(
+    connection: &quinn::Connection,
+) -> future::BoxFuture<
+    'static,
+    Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>,
+> {
+    let connection = connection.clone();
+    Box::pin(async move { connection.accept_bi().await })
+}
+
+/// Sends out the data buffered within the substream, then closes its writing side.
+async fn flush_close(mut substream: with_buffers::WithBuffers<quic::QuicStream>) {
+    // The writing side is reported as closed only after it has actually been closed, which
+    // happens after all the buffered data has been sent out.
+    while matches!(substream.buffers(), Ok((_, Some(_)))) {
+        Pin::new(&mut substream).process().await;
+    }
+}
@@ -19,6 +19,7 @@ use super::Inner;
 
 use core::time::Duration;
 use futures::{channel::mpsc, prelude::*};
+use futures_rustls::rustls;
 use futures_timer::Delay;
 use smoldot::{
     libp2p::{
@@ -130,6 +131,65 @@ pub(super) async fn opening_connection_task(
     .await;
 }
 
+/// Asynchronous task managing a connection that has just been accepted by a listener, including
+/// the WebSocket handshake if `is_websocket` is `true`.
+///
+/// The WebSocket handshake is performed within the task of the connection, in order to not block
+/// the listener while it is in progress.
+pub(super) async fn incoming_connection_task(
+    socket: async_std::net::TcpStream,
+    multiaddr: Multiaddr,
+    is_websocket: bool,
+    inner: Arc<Inner>,
+) {
+    let socket = if is_websocket {
+        let handshake = websocket::websocket_server_handshake(socket);
+        let timeout = Delay::new(Duration::from_secs(10));
+        futures::pin_mut!(handshake);
+
+        match future::select(handshake, timeout).await {
+            future::Either::Left((Ok(socket), _)) => future::Either::Right(socket),
+            future::Either::Left((Err(error), _)) => {
+                log::debug!(
+                    "websocket-handshake-error; multiaddr={}; error={}",
+                    multiaddr,
+                    error
+                );
+                return;
+            }
+            future::Either::Right(((), _)) => {
+                log::debug!("websocket-handshake-timeout; multiaddr={}", multiaddr);
+                return;
+            }
+        }
+    } else {
+        future::Either::Left(socket)
+    };
+
+    let mut guarded = inner.guarded.lock().await;
+    let (connection_id, connection_task) = guarded.network.add_single_stream_incoming_connection(
+        Instant::now(),
+        service::SingleStreamHandshakeKind::MultistreamSelectNoiseYamux,
+        multiaddr,
+    );
+
+    let (tx, rx) = mpsc::channel(16);
+    guarded.active_connections.insert(connection_id, tx);
+    let connection_to_coordinator = guarded.messages_from_connections_tx.clone();
+
+    // Now run the connection.
+    drop(guarded);
+    established_connection_task(
+        socket,
+        inner,
+        connection_id,
+        connection_task,
+        rx,
+        connection_to_coordinator,
+    )
+    .await;
+}
+
 /// Asynchronous task managing a specific connection.
 pub(super) async fn established_connection_task(
     socket: impl AsyncRead + AsyncWrite + Unpin,
@@ -330,10 +390,8 @@ fn multiaddr_to_socket(
         return Err(());
     }
 
-    // TODO: doesn't support WebSocket secure connections
-
     // Ensure ahead of time that the multiaddress is supported.
-    let (addr, host_if_websocket) = match (&proto1, &proto2, &proto3) {
+    let (addr, websocket) = match (&proto1, &proto2, &proto3) {
         (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), None) => (
             either::Left(SocketAddr::new(IpAddr::V4((*ip).into()), *port)),
             None,
@@ -344,11 +402,27 @@ fn multiaddr_to_socket(
         ),
         (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
             let addr = SocketAddr::new(IpAddr::V4((*ip).into()), *port);
-            (either::Left(addr), Some(addr.to_string()))
+            (either::Left(addr), Some((addr.to_string(), None)))
         }
         (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
             let addr = SocketAddr::new(IpAddr::V6((*ip).into()), *port);
-            (either::Left(addr), Some(addr.to_string()))
+            (either::Left(addr), Some((addr.to_string(), None)))
+        }
+        (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Wss)) => {
+            let addr = SocketAddr::new(IpAddr::V4((*ip).into()), *port);
+            let server_name = rustls::ServerName::IpAddress(addr.ip());
+            (
+                either::Left(addr),
+                Some((addr.to_string(), Some(server_name))),
+            )
+        }
+        (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Wss)) => {
+            let addr = SocketAddr::new(IpAddr::V6((*ip).into()), *port);
+            let server_name = rustls::ServerName::IpAddress(addr.ip());
+            (
+                either::Left(addr),
+                Some((addr.to_string(), Some(server_name))),
+            )
         }
 
         // TODO: we don't care about the differences between Dns, Dns4, and Dns6
@@ -363,8 +437,20 @@ fn multiaddr_to_socket(
             Some(ProtocolRef::Ws),
         ) => (
             either::Right((addr.to_string(), *port)),
-            Some(format!("{}:{}", addr, *port)),
+            Some((format!("{}:{}", addr, *port), None)),
         ),
+        (
+            ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+            ProtocolRef::Tcp(port),
+            Some(ProtocolRef::Wss),
+        ) => {
+            let server_name =
+                rustls::ServerName::try_from(addr.to_string().as_str()).map_err(|_| ())?;
+            (
+                either::Right((addr.to_string(), *port)),
+                Some((format!("{}:{}", addr, *port), Some(server_name))),
+            )
+        }
 
         _ => return Err(()),
     };
@@ -388,18 +474,48 @@ fn multiaddr_to_socket(
             let _ = tcp_socket.set_nodelay(true);
         }
 
-        match (tcp_socket, host_if_websocket) {
-            (Ok(tcp_socket), Some(host)) => {
+        match (tcp_socket, websocket) {
+            (Ok(tcp_socket), Some((host, None))) => {
                 websocket::websocket_client_handshake(websocket::Config {
                     tcp_socket,
                     host: &host,
                     url: "/",
                 })
                 .await
-                .map(future::Either::Right)
+                .map(|socket| future::Either::Right(future::Either::Left(socket)))
+            }
+            (Ok(tcp_socket), Some((host, Some(server_name)))) => {
+                let tls_socket = tls_connector().connect(server_name, tcp_socket).await?;
+                websocket::websocket_client_handshake(websocket::Config {
+                    tcp_socket: tls_socket,
+                    host: &host,
+                    url: "/",
+                })
+                .await
+                .map(|socket| future::Either::Right(future::Either::Right(socket)))
             }
             (Ok(tcp_socket), None) => Ok(future::Either::Left(tcp_socket)),
             (Err(err), _) => Err(err),
         }
     })
 }
+
+/// Builds a TLS connector that verifies the certificates of servers against the list of root
+/// certificates authorities trusted by Mozilla.
+pub(crate) fn tls_connector() -> futures_rustls::TlsConnector {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    futures_rustls::TlsConnector::from(Arc::new(config))
+}
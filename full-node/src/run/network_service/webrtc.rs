@@ -0,0 +1,885 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Listener for WebRTC-direct connections.
+//!
+//! See <https://github.com/libp2p/specs/blob/master/webrtc/webrtc-direct.md>.
+//!
+//! Contrary to TCP, all the WebRTC connections share the same UDP socket. The listener task
+//! receives all the UDP datagrams and dispatches them to the task of the connection they belong
+//! to, based on their source address.
+//!
+//! The remote always initiates the connection by sending a STUN binding request. According to
+//! the libp2p specification, the ICE username fragment and password are identical on both sides
+//! and are chosen by the remote. The listener extracts the username fragment from the STUN
+//! request, which is enough to build the local WebRTC state machine without any SDP exchange.
+//!
+//! Once the DTLS handshake has finished, the connection is reported to the network state machine
+//! as a multi-stream connection. Each SCTP data channel maps to a substream, and the data channel
+//! whose identifier is 0 is used for the Noise handshake.
+
+use super::Inner;
+
+use futures::{channel::mpsc, prelude::*};
+use futures_timer::Delay;
+use smoldot::{
+    libp2p::{
+        collection::SubstreamFate,
+        multiaddr::{Multiaddr, ProtocolRef},
+        read_write::ReadWrite,
+    },
+    network::service,
+};
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    str,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use str0m::{
+    change::{DtlsCert, Fingerprint, IceCreds},
+    channel::{ChannelConfig, ChannelId, Reliability},
+    net::{Protocol, Receive},
+    Candidate, Event, IceConnectionState, Input, Output, Rtc,
+};
+
+/// Prefix that the ICE username fragment chosen by the remote must start with, according to the
+/// libp2p WebRTC specification.
+const UFRAG_PREFIX: &str = "libp2p+webrtc+v1/";
+
+/// Maximum duration between the first STUN request of a remote and the end of the DTLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum size of a message sent on a data channel, as defined by the libp2p WebRTC
+/// specification.
+const MAX_MESSAGE_SIZE: usize = 16384;
+
+/// Maximum number of connections whose DTLS handshake is in progress. STUN binding requests
+/// coming from new remotes are ignored when this limit is reached.
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// Parses a multiaddress of the form `/ip4/.../udp/.../webrtc-direct` or
+/// `/ip6/.../udp/.../webrtc-direct`. Returns `None` if the multiaddress isn't of this form.
+pub(super) fn parse_listen_multiaddr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    match (iter.next(), iter.next(), iter.next(), iter.next()) {
+        (
+            Some(ProtocolRef::Ip4(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::WebRtcDirect),
+            None,
+        ) => Some(SocketAddr::from((ip, port))),
+        (
+            Some(ProtocolRef::Ip6(ip)),
+            Some(ProtocolRef::Udp(port)),
+            Some(ProtocolRef::WebRtcDirect),
+            None,
+        ) => Some(SocketAddr::from((ip, port))),
+        _ => None,
+    }
+}
+
+/// Generates a new self-signed certificate to use for the DTLS layer of WebRTC connections.
+pub(super) fn generate_certificate() -> DtlsCert {
+    DtlsCert::new()
+}
+
+/// Builds the multiaddress that remotes must use in order to connect to a WebRTC listener bound
+/// to the given address and using the given certificate.
+pub(super) fn listen_multiaddr(bound_addr: SocketAddr, certificate: &DtlsCert) -> Multiaddr {
+    [
+        match bound_addr.ip() {
+            IpAddr::V4(ip) => ProtocolRef::Ip4(ip.octets()),
+            IpAddr::V6(ip) => ProtocolRef::Ip6(ip.octets()),
+        },
+        ProtocolRef::Udp(bound_addr.port()),
+        ProtocolRef::WebRtcDirect,
+        ProtocolRef::Certhash(Cow::Owned(fingerprint_multihash(
+            &certificate.fingerprint(),
+        ))),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Converts a SHA-256 certificate fingerprint into its multihash representation.
+fn fingerprint_multihash(fingerprint: &Fingerprint) -> Vec<u8> {
+    // The DTLS layer always uses SHA-256 fingerprints, as required by the libp2p specification.
+    debug_assert_eq!(fingerprint.hash_func, "sha-256");
+    debug_assert_eq!(fingerprint.bytes.len(), 32);
+
+    let mut out = Vec::with_capacity(2 + fingerprint.bytes.len());
+    out.push(0x12); // SHA-256 multihash code.
+    out.push(32);
+    out.extend_from_slice(&fingerprint.bytes);
+    out
+}
+
+/// Message sent by a connection task to the listener task.
+enum ConnectionTaskEvent {
+    /// The DTLS handshake of the connection with the given remote has finished.
+    HandshakeFinished(SocketAddr),
+    /// The task of the connection with the given remote has finished.
+    Closed(SocketAddr),
+}
+
+/// Asynchronous task receiving all the UDP datagrams of a WebRTC listener and dispatching them
+/// to the task of the connection they belong to.
+///
+/// If `bound_addr` is an unspecified address, the local address of each connection is the
+/// address of the interface the operating system uses to reach the remote.
+pub(super) async fn listener_task(
+    socket: Arc<async_std::net::UdpSocket>,
+    bound_addr: SocketAddr,
+    certificate: DtlsCert,
+    inner: Arc<Inner>,
+    mut conn_tasks_tx: mpsc::Sender<Pin<Box<dyn Future<Output = ()> + Send>>>,
+) {
+    // For each remote address, the channel to send the datagrams to the corresponding task, and
+    // whether its handshake is still in progress.
+    let mut connections = hashbrown::HashMap::<
+        SocketAddr,
+        (mpsc::Sender<Vec<u8>>, bool),
+        fnv::FnvBuildHasher,
+    >::default();
+    let mut num_pending_handshakes = 0;
+
+    // Connection tasks report through this channel when their handshake has finished and when
+    // they have finished.
+    let (connection_events_tx, mut connection_events_rx) = mpsc::unbounded();
+
+    let mut buffer = vec![0; 65536];
+
+    loop {
+        let (num_read, remote_addr) = futures::select! {
+            result = socket.recv_from(&mut buffer).fuse() => match result {
+                Ok(v) => v,
+                Err(_) => {
+                    // A wait is added in order to avoid having a busy-loop failing to receive
+                    // datagrams.
+                    Delay::new(Duration::from_secs(2)).await;
+                    continue;
+                }
+            },
+            event = connection_events_rx.select_next_some() => {
+                match event {
+                    ConnectionTaskEvent::HandshakeFinished(remote_addr) => {
+                        if let Some((_, handshaking @ true)) = connections.get_mut(&remote_addr) {
+                            *handshaking = false;
+                            num_pending_handshakes -= 1;
+                        }
+                    }
+                    ConnectionTaskEvent::Closed(remote_addr) => {
+                        if let Some((_, true)) = connections.remove(&remote_addr) {
+                            num_pending_handshakes -= 1;
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+
+        let datagram = &buffer[..num_read];
+
+        if let Some((packets_tx, _)) = connections.get_mut(&remote_addr) {
+            // Datagrams are silently dropped if the connection task is too busy to process them,
+            // which is similar to what happens if the socket buffer is full.
+            let _ = packets_tx.try_send(datagram.to_vec());
+            continue;
+        }
+
+        // Datagrams coming from unknown remotes that aren't a STUN binding request with a valid
+        // username are ignored.
+        let Some(ufrag) = stun_binding_request_ufrag(datagram) else {
+            continue;
+        };
+        if !ufrag.starts_with(UFRAG_PREFIX) {
+            continue;
+        }
+
+        if num_pending_handshakes >= MAX_PENDING_HANDSHAKES {
+            log::debug!(
+                "webrtc-handshake-refused; remote_addr={}; reason=too-many-pending-handshakes",
+                remote_addr
+            );
+            continue;
+        }
+
+        let local_addr = if bound_addr.ip().is_unspecified() {
+            match local_addr_towards(remote_addr, bound_addr.port()) {
+                Ok(addr) => addr,
+                Err(error) => {
+                    log::debug!(
+                        "webrtc-setup-error; remote_addr={}; error={}",
+                        remote_addr,
+                        error
+                    );
+                    continue;
+                }
+            }
+        } else {
+            bound_addr
+        };
+
+        let rtc = match build_rtc(&certificate, local_addr, remote_addr, ufrag) {
+            Ok(rtc) => rtc,
+            Err(error) => {
+                log::debug!(
+                    "webrtc-setup-error; remote_addr={}; error={}",
+                    remote_addr,
+                    error
+                );
+                continue;
+            }
+        };
+
+        log::debug!("incoming-webrtc-connection; remote_addr={}", remote_addr);
+
+        let (mut packets_tx, packets_rx) = mpsc::channel(64);
+        let _ = packets_tx.try_send(datagram.to_vec());
+        connections.insert(remote_addr, (packets_tx, true));
+        num_pending_handshakes += 1;
+
+        let task = {
+            let socket = socket.clone();
+            let inner = inner.clone();
+            let connection_events_tx = connection_events_tx.clone();
+            async move {
+                connection_task(
+                    rtc,
+                    socket,
+                    local_addr,
+                    remote_addr,
+                    packets_rx,
+                    connection_events_tx.clone(),
+                    inner,
+                )
+                .await;
+                let _ =
+                    connection_events_tx.unbounded_send(ConnectionTaskEvent::Closed(remote_addr));
+            }
+        };
+
+        // Ignore errors, as it is possible for the destination task to have been aborted
+        // already.
+        let _ = conn_tasks_tx.send(task.boxed()).await;
+    }
+}
+
+/// Returns the address of the local interface that the operating system uses in order to reach
+/// the given remote, combined with the given port.
+fn local_addr_towards(remote_addr: SocketAddr, port: u16) -> std::io::Result<SocketAddr> {
+    // Connecting a UDP socket doesn't send anything on the network, but makes the operating
+    // system choose the local interface.
+    let unspecified = match remote_addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = std::net::UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+    socket.connect(remote_addr)?;
+    Ok(SocketAddr::new(socket.local_addr()?.ip(), port))
+}
+
+/// Builds the WebRTC state machine of a new incoming connection.
+fn build_rtc(
+    certificate: &DtlsCert,
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    ufrag: &str,
+) -> Result<Rtc, str0m::RtcError> {
+    let mut rtc = Rtc::builder()
+        .set_ice_lite(true)
+        .set_dtls_cert(certificate.clone())
+        // The certificate of the remote isn't known in advance, and is instead authenticated
+        // through the Noise handshake.
+        .set_fingerprint_verification(false)
+        .build();
+
+    rtc.add_local_candidate(Candidate::host(local_addr, "udp")?);
+    rtc.add_remote_candidate(Candidate::host(remote_addr, "udp")?);
+
+    let mut api = rtc.direct_api();
+    // The ICE username fragment and password are identical, and are the same on both sides.
+    let credentials = IceCreds {
+        ufrag: ufrag.to_owned(),
+        pass: ufrag.to_owned(),
+    };
+    api.set_ice_controlling(false);
+    api.set_local_ice_credentials(credentials.clone());
+    api.set_remote_ice_credentials(credentials);
+    // The remote is the DTLS client and the SCTP client.
+    api.start_dtls(false)?;
+    api.start_sctp(false);
+
+    Ok(rtc)
+}
+
+/// Asynchronous task managing a specific WebRTC connection.
+async fn connection_task(
+    mut rtc: Rtc,
+    socket: Arc<async_std::net::UdpSocket>,
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    packets_rx: mpsc::Receiver<Vec<u8>>,
+    connection_events_tx: mpsc::UnboundedSender<ConnectionTaskEvent>,
+    inner: Arc<Inner>,
+) {
+    let handshake_deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let local_tls_certificate_multihash =
+        fingerprint_multihash(&rtc.direct_api().local_dtls_fingerprint());
+
+    // The data channel used for the Noise handshake is negotiated in advance by both sides.
+    let handshake_channel = rtc.direct_api().create_data_channel(ChannelConfig {
+        label: String::new(),
+        ordered: true,
+        reliability: Reliability::Reliable,
+        negotiated: Some(0),
+        protocol: String::new(),
+    });
+
+    // Networking state machine of the connection, plus the channels to communicate with the
+    // coordinator. `None` until the DTLS handshake has finished.
+    let mut established = None::<(
+        service::ConnectionId,
+        service::MultiStreamConnectionTask<Instant, ChannelId>,
+        stream::Peekable<mpsc::Receiver<service::CoordinatorToConnection<Instant>>>,
+        mpsc::Sender<(service::ConnectionId, service::ConnectionToCoordinator)>,
+    )>;
+
+    // List of all currently open substreams, with the data received on each of them and not
+    // processed yet.
+    let mut open_substreams =
+        hashbrown::HashMap::<ChannelId, Vec<u8>, fnv::FnvBuildHasher>::default();
+    // Data channels opened locally and whose opening hasn't been reported by `rtc` yet.
+    let mut pending_outbound_substreams =
+        hashbrown::HashSet::<ChannelId, fnv::FnvBuildHasher>::default();
+
+    // `true` if the WebRTC connection is dead, either because of an error or because the remote
+    // has closed it.
+    let mut rtc_dead = false;
+    // `true` if the connection task has been notified that the connection is dead.
+    let mut reset_reported = false;
+
+    let mut packets_rx = packets_rx.fuse();
+    let mut write_buffer = vec![0; MAX_MESSAGE_SIZE];
+
+    loop {
+        // Process everything that the WebRTC state machine has to output.
+        let mut rtc_wake_up = None;
+        while !rtc_dead {
+            let output = match rtc.poll_output() {
+                Ok(output) => output,
+                Err(error) => {
+                    log::debug!(
+                        "webrtc-connection-error; remote_addr={}; error={}",
+                        remote_addr,
+                        error
+                    );
+                    rtc_dead = true;
+                    break;
+                }
+            };
+
+            match output {
+                Output::Timeout(when) => {
+                    rtc_wake_up = Some(when);
+                    break;
+                }
+                Output::Transmit(transmit) => {
+                    // Errors are ignored, as they are equivalent to the datagram being lost.
+                    let _ = socket
+                        .send_to(&transmit.contents, transmit.destination)
+                        .await;
+                }
+                Output::Event(Event::Connected) if established.is_none() => {
+                    let remote_tls_certificate_multihash =
+                        match rtc.direct_api().remote_dtls_fingerprint() {
+                            Some(fingerprint) => fingerprint_multihash(&fingerprint),
+                            None => {
+                                rtc_dead = true;
+                                break;
+                            }
+                        };
+
+                    let multiaddr = [
+                        match remote_addr.ip() {
+                            IpAddr::V4(ip) => ProtocolRef::Ip4(ip.octets()),
+                            IpAddr::V6(ip) => ProtocolRef::Ip6(ip.octets()),
+                        },
+                        ProtocolRef::Udp(remote_addr.port()),
+                        ProtocolRef::WebRtcDirect,
+                        ProtocolRef::Certhash(Cow::Owned(remote_tls_certificate_multihash.clone())),
+                    ]
+                    .into_iter()
+                    .collect::<Multiaddr>();
+
+                    let mut guarded = inner.guarded.lock().await;
+                    let (connection_id, mut connection_task) =
+                        guarded.network.add_multi_stream_incoming_connection(
+                            Instant::now(),
+                            service::MultiStreamHandshakeKind::WebRtc {
+                                local_tls_certificate_multihash: local_tls_certificate_multihash
+                                    .clone(),
+                                remote_tls_certificate_multihash,
+                            },
+                            multiaddr,
+                        );
+
+                    let (tx, rx) = mpsc::channel(16);
+                    guarded.active_connections.insert(connection_id, tx);
+                    let connection_to_coordinator = guarded.messages_from_connections_tx.clone();
+                    drop(guarded);
+
+                    let _ = connection_events_tx
+                        .unbounded_send(ConnectionTaskEvent::HandshakeFinished(remote_addr));
+
+                    // The handshake data channel is the first outbound substream that the
+                    // connection task wants to open.
+                    debug_assert_eq!(connection_task.desired_outbound_substreams(), 1);
+                    connection_task.add_substream(handshake_channel, true);
+                    open_substreams.insert(handshake_channel, Vec::new());
+
+                    established = Some((
+                        connection_id,
+                        connection_task,
+                        rx.peekable(),
+                        connection_to_coordinator,
+                    ));
+                }
+                Output::Event(Event::IceConnectionStateChange(
+                    IceConnectionState::Disconnected,
+                )) => {
+                    rtc_dead = true;
+                }
+                Output::Event(Event::ChannelOpen(channel_id, _)) => {
+                    let Some((_, connection_task, ..)) = &mut established else {
+                        continue;
+                    };
+                    if open_substreams.contains_key(&channel_id) {
+                        continue;
+                    }
+                    let outbound = pending_outbound_substreams.remove(&channel_id);
+                    connection_task.add_substream(channel_id, outbound);
+                    open_substreams.insert(channel_id, Vec::new());
+                }
+                Output::Event(Event::ChannelData(data)) => {
+                    if let Some(read_buffer) = open_substreams.get_mut(&data.id) {
+                        read_buffer.extend_from_slice(&data.data);
+                    }
+                }
+                Output::Event(Event::ChannelClose(channel_id)) => {
+                    pending_outbound_substreams.remove(&channel_id);
+                    if open_substreams.remove(&channel_id).is_some() {
+                        if let Some((_, connection_task, ..)) = &mut established {
+                            connection_task.reset_substream(&channel_id);
+                        }
+                    }
+                }
+                Output::Event(_) => {}
+            }
+        }
+
+        if !rtc.is_alive() {
+            rtc_dead = true;
+        }
+
+        let Some((_, connection_task, coordinator_to_connection, _)) = &mut established else {
+            // The connection hasn't been reported to the coordinator yet, meaning that it can
+            // simply be discarded.
+            if rtc_dead || Instant::now() >= handshake_deadline {
+                log::debug!("webrtc-handshake-failed; remote_addr={}", remote_addr);
+                return;
+            }
+
+            // Wait for either a datagram or the WebRTC timer.
+            let wake_up = rtc_wake_up.map_or(handshake_deadline, |w| w.min(handshake_deadline));
+            let timer = Delay::new(wake_up.saturating_duration_since(Instant::now())).fuse();
+            futures::pin_mut!(timer);
+            futures::select! {
+                packet = packets_rx.next() => match packet {
+                    Some(packet) => inject_datagram(&mut rtc, local_addr, remote_addr, &packet),
+                    None => return,
+                },
+                () = timer => {
+                    let _ = rtc.handle_input(Input::Timeout(Instant::now()));
+                }
+            }
+            continue;
+        };
+
+        if rtc_dead && !reset_reported {
+            connection_task.reset();
+            open_substreams.clear();
+            reset_reported = true;
+        }
+
+        // Start opening new outbound substreams, if needed.
+        if !rtc_dead {
+            for _ in 0..(connection_task.desired_outbound_substreams() as usize)
+                .saturating_sub(pending_outbound_substreams.len())
+            {
+                let channel_id = rtc.direct_api().create_data_channel(ChannelConfig {
+                    label: String::new(),
+                    ordered: true,
+                    reliability: Reliability::Reliable,
+                    negotiated: None,
+                    protocol: String::new(),
+                });
+                pending_outbound_substreams.insert(channel_id);
+            }
+        }
+
+        // Inject in the connection task the messages coming from the coordinator, if any.
+        while let Some(Some(message)) = coordinator_to_connection.next().now_or_never() {
+            connection_task.inject_coordinator_message(message);
+        }
+
+        let now = Instant::now();
+
+        // When reading/writing substreams, the substream can ask to be woken up after a certain
+        // time. This variable stores the earliest time when we should be waking up.
+        let mut wake_up_after = None;
+
+        // Perform a read-write on all substreams.
+        // TODO: trying to read/write every single substream every single time is suboptimal
+        for channel_id in open_substreams.keys().cloned().collect::<Vec<_>>() {
+            loop {
+                let read_buffer = open_substreams.get_mut(&channel_id).unwrap();
+
+                let mut read_write = ReadWrite {
+                    now,
+                    incoming_buffer: Some(&read_buffer[..]),
+                    outgoing_buffer: Some((&mut write_buffer[..], &mut [])),
+                    read_bytes: 0,
+                    written_bytes: 0,
+                    wake_up_after,
+                };
+
+                let substream_fate =
+                    connection_task.substream_read_write(&channel_id, &mut read_write);
+
+                // Because the `read_write` object borrows the buffers, we need to drop it before
+                // we can modify them.
+                let read_bytes = read_write.read_bytes;
+                let written_bytes = read_write.written_bytes;
+                wake_up_after = read_write.wake_up_after.take();
+                drop(read_write);
+
+                read_buffer.drain(..read_bytes);
+
+                let write_failed = written_bytes != 0
+                    && match rtc.channel(channel_id) {
+                        Some(mut channel) => {
+                            channel.write(true, &write_buffer[..written_bytes]).is_err()
+                        }
+                        None => true,
+                    };
+
+                if write_failed {
+                    connection_task.reset_substream(&channel_id);
+                    open_substreams.remove(&channel_id);
+                    rtc.direct_api().close_data_channel(channel_id);
+                    break;
+                }
+
+                // If the `connection_task` requires this substream to be killed, we close the
+                // data channel.
+                if matches!(substream_fate, SubstreamFate::Reset) {
+                    open_substreams.remove(&channel_id);
+                    rtc.direct_api().close_data_channel(channel_id);
+                    break;
+                }
+
+                if read_bytes == 0 && written_bytes == 0 {
+                    break;
+                }
+            }
+        }
+
+        // Try pull message to send to the coordinator.
+
+        // Calling this method takes ownership of the task and returns that task if it has
+        // more work to do. If `None` is returned, then the entire task is gone and the
+        // connection must be abruptly closed, which is what happens when we return from
+        // this function.
+        let (
+            connection_id,
+            connection_task,
+            mut coordinator_to_connection,
+            mut connection_to_coordinator,
+        ) = established.take().unwrap();
+        let (mut task_update, message) = connection_task.pull_message_to_coordinator();
+
+        // If `task_update` is `None`, the connection task is going to die as soon as the
+        // message reaches the coordinator. Before returning, we need to do a bit of clean up
+        // by removing the task from the list of active connections.
+        // This is done before the message is sent to the coordinator, in order to be sure
+        // that the connection id is still attributed to the current task, and not to a new
+        // connection that the coordinator has assigned after receiving the message.
+        if task_update.is_none() {
+            let mut guarded = inner.guarded.lock().await;
+            let _was_in = guarded.active_connections.remove(&connection_id);
+            debug_assert!(_was_in.is_some());
+        }
+
+        let has_message = message.is_some();
+        if let Some(message) = message {
+            // Sending this message might take a long time (in case the coordinator is busy),
+            // but this is intentional and serves as a back-pressure mechanism.
+            // However, it is important to continue processing the messages coming from the
+            // coordinator, otherwise this could result in a deadlock.
+            loop {
+                futures::select! {
+                    _ = future::poll_fn(|cx| connection_to_coordinator.poll_ready(cx)).fuse() => break,
+                    message = coordinator_to_connection.next() => {
+                        if let Some(message) = message {
+                            if let Some(task_update) = &mut task_update {
+                                task_update.inject_coordinator_message(message);
+                            }
+                        } else {
+                            return;
+                        }
+                    }
+                }
+            }
+            let result = connection_to_coordinator.try_send((connection_id, message));
+            inner.wake_up_main_background_task.notify(1);
+            if result.is_err() {
+                return;
+            }
+        }
+
+        let Some(connection_task) = task_update else {
+            return;
+        };
+        established = Some((
+            connection_id,
+            connection_task,
+            coordinator_to_connection,
+            connection_to_coordinator,
+        ));
+
+        if has_message {
+            continue;
+        }
+
+        // Starting from here, we block the current task until more processing needs to happen.
+        let wake_up = match (wake_up_after, rtc_wake_up) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let poll_after = if let Some(wake_up) = wake_up {
+            if wake_up > now {
+                future::Either::Left(Delay::new(wake_up - now))
+            } else {
+                // "Wake up" immediately.
+                if !rtc_dead {
+                    let _ = rtc.handle_input(Input::Timeout(Instant::now()));
+                }
+                continue;
+            }
+        } else {
+            future::Either::Right(future::pending())
+        }
+        .fuse();
+        futures::pin_mut!(poll_after);
+
+        let (_, _, coordinator_to_connection, _) = established.as_mut().unwrap();
+        let message_from_coordinator = Pin::new(coordinator_to_connection).peek().fuse();
+        futures::pin_mut!(message_from_coordinator);
+
+        futures::select! {
+            packet = packets_rx.next() => match packet {
+                Some(packet) if !rtc_dead => inject_datagram(&mut rtc, local_addr, remote_addr, &packet),
+                _ => {}
+            },
+            _ = message_from_coordinator => {},
+            () = poll_after => {
+                if !rtc_dead {
+                    let _ = rtc.handle_input(Input::Timeout(Instant::now()));
+                }
+            }
+        }
+    }
+}
+
+/// Injects a UDP datagram received from the remote into the WebRTC state machine.
+fn inject_datagram(rtc: &mut Rtc, local_addr: SocketAddr, remote_addr: SocketAddr, packet: &[u8]) {
+    let Ok(receive) = Receive::new(Protocol::Udp, remote_addr, local_addr, packet) else {
+        return;
+    };
+    // Errors are ignored, as they are equivalent to the datagram being lost.
+    let _ = rtc.handle_input(Input::Receive(Instant::now(), receive));
+}
+
+/// Parses the given datagram as a STUN binding request, and returns the username fragment of
+/// the local node as indicated by the `USERNAME` attribute.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc8489> and <https://www.rfc-editor.org/rfc/rfc8445>.
+fn stun_binding_request_ufrag(datagram: &[u8]) -> Option<&str> {
+    const BINDING_REQUEST: u16 = 0x0001;
+    const MAGIC_COOKIE: u32 = 0x2112a442;
+    const ATTRIBUTE_USERNAME: u16 = 0x0006;
+
+    let header = datagram.get(..20)?;
+    let message_ty = u16::from_be_bytes(<[u8; 2]>::try_from(&header[0..2]).unwrap());
+    let message_len = usize::from(u16::from_be_bytes(
+        <[u8; 2]>::try_from(&header[2..4]).unwrap(),
+    ));
+    let magic_cookie = u32::from_be_bytes(<[u8; 4]>::try_from(&header[4..8]).unwrap());
+    if message_ty != BINDING_REQUEST || magic_cookie != MAGIC_COOKIE {
+        return None;
+    }
+
+    let mut attributes = datagram.get(20..20 + message_len)?;
+    while attributes.len() >= 4 {
+        let attribute_ty = u16::from_be_bytes(<[u8; 2]>::try_from(&attributes[0..2]).unwrap());
+        let attribute_len = usize::from(u16::from_be_bytes(
+            <[u8; 2]>::try_from(&attributes[2..4]).unwrap(),
+        ));
+        let value = attributes.get(4..4 + attribute_len)?;
+
+        if attribute_ty == ATTRIBUTE_USERNAME {
+            // The username is of the form `<local ufrag>:<remote ufrag>`.
+            let username = str::from_utf8(value).ok()?;
+            return username.split(':').next();
+        }
+
+        // Attributes are padded to a multiple of 4 bytes.
+        let padded_len = (attribute_len + 3) & !3;
+        attributes = attributes.get(4 + padded_len..)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use smoldot::libp2p::multiaddr::{Multiaddr, ProtocolRef};
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    /// Builds a STUN binding request containing the given attributes.
+    fn stun_request(message_ty: u16, attributes: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut attributes_bytes = Vec::new();
+        for (ty, value) in attributes {
+            attributes_bytes.extend_from_slice(&ty.to_be_bytes());
+            attributes_bytes.extend_from_slice(&u16::try_from(value.len()).unwrap().to_be_bytes());
+            attributes_bytes.extend_from_slice(value);
+            while attributes_bytes.len() % 4 != 0 {
+                attributes_bytes.push(0);
+            }
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&message_ty.to_be_bytes());
+        out.extend_from_slice(&u16::try_from(attributes_bytes.len()).unwrap().to_be_bytes());
+        out.extend_from_slice(&0x2112a442u32.to_be_bytes());
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&attributes_bytes);
+        out
+    }
+
+    #[test]
+    fn stun_ufrag_basic() {
+        let request = stun_request(
+            0x0001,
+            &[
+                (0x0024, &[0, 0, 0, 1]),
+                (0x0006, b"libp2p+webrtc+v1/abc:libp2p+webrtc+v1/abc"),
+            ],
+        );
+        assert_eq!(
+            super::stun_binding_request_ufrag(&request),
+            Some("libp2p+webrtc+v1/abc")
+        );
+    }
+
+    #[test]
+    fn stun_ufrag_padded_attribute() {
+        // The first attribute has a length that isn't a multiple of 4.
+        let request = stun_request(0x0001, &[(0x8022, b"abcde"), (0x0006, b"foo:bar")]);
+        assert_eq!(super::stun_binding_request_ufrag(&request), Some("foo"));
+    }
+
+    #[test]
+    fn stun_ufrag_no_username() {
+        let request = stun_request(0x0001, &[(0x0024, &[0, 0, 0, 1])]);
+        assert_eq!(super::stun_binding_request_ufrag(&request), None);
+    }
+
+    #[test]
+    fn stun_ufrag_not_binding_request() {
+        let request = stun_request(0x0101, &[(0x0006, b"foo:bar")]);
+        assert_eq!(super::stun_binding_request_ufrag(&request), None);
+    }
+
+    #[test]
+    fn stun_ufrag_truncated() {
+        let request = stun_request(0x0001, &[(0x0006, b"foo:bar")]);
+        assert_eq!(
+            super::stun_binding_request_ufrag(&request[..request.len() - 1]),
+            None
+        );
+        assert_eq!(super::stun_binding_request_ufrag(&request[..10]), None);
+    }
+
+    #[test]
+    fn parse_listen_multiaddr() {
+        let addr = "/ip4/0.0.0.0/udp/30333/webrtc-direct"
+            .parse::<Multiaddr>()
+            .unwrap();
+        assert_eq!(
+            super::parse_listen_multiaddr(&addr),
+            Some(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 30333)))
+        );
+
+        let addr = "/ip4/127.0.0.1/tcp/30333".parse::<Multiaddr>().unwrap();
+        assert_eq!(super::parse_listen_multiaddr(&addr), None);
+    }
+
+    #[test]
+    fn listen_multiaddr_contains_certhash() {
+        let certificate = super::generate_certificate();
+        let addr =
+            super::listen_multiaddr(SocketAddr::from((Ipv4Addr::LOCALHOST, 30333)), &certificate);
+
+        let mut iter = addr.iter();
+        assert!(matches!(
+            iter.next(),
+            Some(ProtocolRef::Ip4([127, 0, 0, 1]))
+        ));
+        assert!(matches!(iter.next(), Some(ProtocolRef::Udp(30333))));
+        assert!(matches!(iter.next(), Some(ProtocolRef::WebRtcDirect)));
+        match iter.next() {
+            Some(ProtocolRef::Certhash(multihash)) => {
+                assert_eq!(&multihash[..2], &[0x12, 32]);
+                assert_eq!(&multihash[2..], &certificate.fingerprint().bytes[..]);
+            }
+            _ => panic!(),
+        }
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn local_addr_towards_loopback() {
+        let addr = super::local_addr_towards(SocketAddr::from((Ipv4Addr::LOCALHOST, 1234)), 30333)
+            .unwrap();
+        assert_eq!(addr, SocketAddr::from((Ipv4Addr::LOCALHOST, 30333)));
+    }
+}
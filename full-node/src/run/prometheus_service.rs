@@ -0,0 +1,353 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Prometheus integration.
+//!
+//! Runs a minimal HTTP server that answers `GET /metrics` requests with the current values of
+//! the metrics of the node, in the Prometheus text exposition format.
+//! See <https://prometheus.io/docs/instrumenting/exposition_formats/>.
+//!
+//! The metrics are gathered from the other services at the moment when the request arrives.
+
+use crate::run::{consensus_service, database_thread, network_service};
+
+use futures::{channel::oneshot, prelude::*};
+use futures_timer::Delay;
+use std::{fmt::Write as _, io, net::SocketAddr, sync::Arc, time::Duration};
+
+/// Configuration for a [`PrometheusService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
+
+    /// Where to bind the HTTP server.
+    pub bind_address: SocketAddr,
+
+    /// Network service to report the metrics of. Only the chain whose index is 0 is reported.
+    pub network_service: Arc<network_service::NetworkService>,
+
+    /// Consensus service to report the metrics of.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Database of the chain. Used to report its size.
+    pub database: Arc<database_thread::DatabaseThread>,
+}
+
+/// Running Prometheus service. Holds a server open for as long as it is alive.
+pub struct PrometheusService {
+    /// As long as this value is alive, the background server continues running.
+    _server_keep_alive: oneshot::Sender<()>,
+}
+
+impl PrometheusService {
+    /// Initializes a new [`PrometheusService`].
+    pub async fn new(config: Config<'_>) -> Result<Self, InitError> {
+        let listener = match async_std::net::TcpListener::bind(config.bind_address).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                return Err(InitError::ListenError {
+                    bind_address: config.bind_address,
+                    error,
+                })
+            }
+        };
+
+        let (_server_keep_alive, client_still_alive) = oneshot::channel();
+
+        let sources = Arc::new(Sources {
+            network_service: config.network_service,
+            consensus_service: config.consensus_service,
+            database: config.database,
+        });
+
+        let mut client_still_alive = client_still_alive.fuse();
+        let mut connections = stream::FuturesUnordered::new();
+
+        (config.tasks_executor)(Box::pin(async move {
+            loop {
+                futures::select! {
+                    _ = client_still_alive => return,
+                    accept = listener.accept().fuse() => {
+                        let (socket, address) = match accept {
+                            Ok(v) => v,
+                            Err(error) => {
+                                log::debug!("prometheus-accept-error; error={}", error);
+                                continue;
+                            }
+                        };
+
+                        let sources = sources.clone();
+                        connections.push(async move {
+                            // Connections are given a fixed amount of time to send their request
+                            // and receive the response, in order to not accumulate idle sockets.
+                            let handle = handle_connection(socket, &sources);
+                            let timeout = Delay::new(Duration::from_secs(10));
+                            futures::pin_mut!(handle, timeout);
+                            match future::select(handle, timeout).await {
+                                future::Either::Left((Ok(()), _)) => {}
+                                future::Either::Left((Err(error), _)) => {
+                                    log::debug!(
+                                        "prometheus-connection-error; address={}; error={}",
+                                        address,
+                                        error
+                                    );
+                                }
+                                future::Either::Right(((), _)) => {
+                                    log::debug!(
+                                        "prometheus-connection-timeout; address={}",
+                                        address
+                                    );
+                                }
+                            }
+                        });
+                    },
+                    () = connections.select_next_some() => {},
+                }
+            }
+        }));
+
+        Ok(PrometheusService { _server_keep_alive })
+    }
+}
+
+/// Error potentially returned by [`PrometheusService::new`].
+#[derive(Debug, derive_more::Display)]
+pub enum InitError {
+    /// Failed to listen on the server address.
+    #[display(fmt = "Failed to listen on TCP address {bind_address}: {error}")]
+    ListenError {
+        /// Address that was attempted.
+        bind_address: SocketAddr,
+        /// Error returned by the operating system.
+        error: io::Error,
+    },
+}
+
+/// Services whose metrics are reported.
+struct Sources {
+    network_service: Arc<network_service::NetworkService>,
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    database: Arc<database_thread::DatabaseThread>,
+}
+
+/// Reads an HTTP request from the given socket and sends back the response.
+async fn handle_connection(
+    mut socket: async_std::net::TcpStream,
+    sources: &Sources,
+) -> Result<(), io::Error> {
+    // Read until the end of the HTTP headers. The body of the request, if any, is ignored.
+    let mut request = Vec::with_capacity(1024);
+    loop {
+        if request.len() >= 8192 {
+            return write_response(&mut socket, "413 Payload Too Large", "").await;
+        }
+
+        let mut buffer = [0; 1024];
+        let num_read = socket.read(&mut buffer).await?;
+        if num_read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        request.extend_from_slice(&buffer[..num_read]);
+
+        if request.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let request_line = request
+        .split(|b| *b == b'\r')
+        .next()
+        .unwrap_or(&request[..]);
+    let mut request_line = request_line.split(|b| *b == b' ');
+
+    match (request_line.next(), request_line.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            let body = gather_metrics(sources).await;
+            write_response(&mut socket, "200 OK", &body).await
+        }
+        (Some(b"GET"), _) => write_response(&mut socket, "404 Not Found", "").await,
+        _ => write_response(&mut socket, "405 Method Not Allowed", "").await,
+    }
+}
+
+async fn write_response(
+    socket: &mut async_std::net::TcpStream,
+    status: &str,
+    body: &str,
+) -> Result<(), io::Error> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: text/plain; version=0.0.4\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.flush().await
+}
+
+/// Builds the body of the response to a `GET /metrics` request.
+async fn gather_metrics(sources: &Sources) -> String {
+    let sync_state = sources.consensus_service.sync_state().await;
+    let num_peers = sources.network_service.num_peers(0).await;
+    let num_connections = sources.network_service.num_established_connections().await;
+    let requests_statistics = sources.network_service.requests_statistics().await;
+    let database_size = sources
+        .database
+        .with_database(|database| database.size_bytes())
+        .await;
+
+    let mut out = String::with_capacity(4096);
+
+    write_metric(
+        &mut out,
+        "smoldot_peers",
+        "gauge",
+        "Number of peers the node has a substream with.",
+        [("", num_peers.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_network_connections",
+        "gauge",
+        "Number of established connections, both incoming and outgoing.",
+        [("", num_connections.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_best_block_number",
+        "gauge",
+        "Height of the current best block.",
+        [("", sync_state.best_block_number.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_finalized_block_number",
+        "gauge",
+        "Height of the current finalized block.",
+        [("", sync_state.finalized_block_number.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_sync_status",
+        "gauge",
+        "Syncing strategy currently in use. The value is 1 for the active strategy and 0 otherwise.",
+        [
+            (
+                r#"status="sync""#,
+                u8::from(sync_state.status == consensus_service::SyncStatus::Sync).to_string(),
+            ),
+            (
+                r#"status="warp_sync""#,
+                u8::from(sync_state.status == consensus_service::SyncStatus::WarpSync).to_string(),
+            ),
+        ],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_blocks_verified_total",
+        "counter",
+        "Number of blocks that have been successfully verified.",
+        [("", sync_state.num_blocks_verified.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_blocks_verification_failures_total",
+        "counter",
+        "Number of blocks whose verification has failed.",
+        [("", sync_state.num_blocks_verification_failures.to_string())],
+    );
+    write_metric(
+        &mut out,
+        "smoldot_block_verification_seconds",
+        "summary",
+        "Time spent verifying blocks.",
+        [
+            (
+                "_sum",
+                sync_state
+                    .blocks_verification_duration
+                    .as_secs_f64()
+                    .to_string(),
+            ),
+            (
+                "_count",
+                (sync_state.num_blocks_verified + sync_state.num_blocks_verification_failures)
+                    .to_string(),
+            ),
+        ],
+    );
+
+    match database_size {
+        Ok(size) => write_metric(
+            &mut out,
+            "smoldot_database_size_bytes",
+            "gauge",
+            "Size of the database on disk.",
+            [("", size.to_string())],
+        ),
+        Err(error) => log::warn!("prometheus-database-size-error; error={}", error),
+    }
+
+    write_metric(
+        &mut out,
+        "smoldot_network_requests_total",
+        "counter",
+        "Number of networking requests, by protocol, direction, and outcome.",
+        requests_statistics.iter().flat_map(|(protocol, stats)| {
+            [
+                (
+                    format!(r#"protocol="{protocol}",direction="out",outcome="success""#),
+                    stats.outgoing_success.to_string(),
+                ),
+                (
+                    format!(r#"protocol="{protocol}",direction="out",outcome="failure""#),
+                    stats.outgoing_failure.to_string(),
+                ),
+                (
+                    format!(r#"protocol="{protocol}",direction="in""#),
+                    stats.incoming.to_string(),
+                ),
+            ]
+        }),
+    );
+
+    out
+}
+
+/// Appends a metric to `out`.
+///
+/// Each sample is a tuple of a suffix and of a value. If the suffix doesn't start with `_`, it
+/// is considered as a list of labels and is put between braces.
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    ty: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (impl AsRef<str>, String)>,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {ty}");
+    for (suffix, value) in samples {
+        let suffix = suffix.as_ref();
+        if suffix.is_empty() || suffix.starts_with('_') {
+            let _ = writeln!(out, "{name}{suffix} {value}");
+        } else {
+            let _ = writeln!(out, "{name}{{{suffix}}} {value}");
+        }
+    }
+}
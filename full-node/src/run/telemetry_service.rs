@@ -0,0 +1,224 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Telemetry integration.
+//!
+//! Connects to the telemetry servers passed through the configuration and sends them the
+//! messages passed to [`TelemetryService::send`]. See the [`smoldot::telemetry`] module for
+//! more information.
+//!
+//! Each telemetry server is handled by a separate background task. In case of disconnection,
+//! the task tries to reconnect after a delay. Messages that are sent while the connection to a
+//! server isn't established, or while the connection is too slow, are silently discarded.
+
+use crate::run::network_service;
+
+use futures::{channel::mpsc, prelude::*};
+use futures_rustls::rustls;
+use futures_timer::Delay;
+use smoldot::{libp2p::websocket, telemetry};
+use std::{
+    io,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Identifier of the node passed when encoding telemetry messages. Since only one node is
+/// reported per connection, the value doesn't matter.
+const NODE_ID: u64 = 1;
+
+/// Configuration for a [`TelemetryService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(future::BoxFuture<'static, ()>),
+
+    /// List of telemetry servers to report to.
+    ///
+    /// If this is empty, the service will still be created but do nothing.
+    pub endpoints: Vec<telemetry::TelemetryEndpoint>,
+
+    /// Message to send to each telemetry server right after the connection has been established.
+    pub system_connected: telemetry::SystemConnected<'a>,
+}
+
+pub struct TelemetryService {
+    /// List of telemetry servers and channels connected to the background task of each of them.
+    endpoints: Vec<(telemetry::TelemetryEndpoint, Mutex<mpsc::Sender<String>>)>,
+}
+
+impl TelemetryService {
+    pub fn new(config: Config<'_>) -> Arc<Self> {
+        let system_connected =
+            telemetry::TelemetryMessage::SystemConnected(config.system_connected).encode(NODE_ID);
+
+        let mut endpoints = Vec::with_capacity(config.endpoints.len());
+        for endpoint in config.endpoints {
+            // The size of the channel is arbitrary. Messages are discarded if it is full.
+            let (tx, rx) = mpsc::channel(16);
+            (config.tasks_executor)(Box::pin(run_endpoint(
+                endpoint.clone(),
+                system_connected.clone(),
+                rx,
+            )));
+            endpoints.push((endpoint, Mutex::new(tx)));
+        }
+
+        Arc::new(TelemetryService { endpoints })
+    }
+
+    /// Sends the given message to all the telemetry servers whose verbosity is high enough.
+    ///
+    /// The message is silently discarded for servers that aren't connected at the moment.
+    pub fn send(&self, message: telemetry::TelemetryMessage) {
+        let mut encoded = None;
+
+        for (endpoint, sender) in &self.endpoints {
+            if !endpoint.accepts(&message) {
+                continue;
+            }
+
+            let encoded = encoded.get_or_insert_with(|| message.encode(NODE_ID));
+            let _ = sender.lock().unwrap().try_send(encoded.clone());
+        }
+    }
+}
+
+/// Background task connected to a specific telemetry server. Ends when the [`TelemetryService`]
+/// is destroyed.
+async fn run_endpoint(
+    endpoint: telemetry::TelemetryEndpoint,
+    system_connected: String,
+    mut messages: mpsc::Receiver<String>,
+) {
+    loop {
+        let connect = connect(&endpoint);
+        let timeout = Delay::new(Duration::from_secs(20));
+        futures::pin_mut!(connect, timeout);
+
+        match future::select(connect, timeout).await {
+            future::Either::Left((Ok(mut socket), _)) => {
+                log::debug!(
+                    "telemetry-connected; host={}; port={}",
+                    endpoint.host,
+                    endpoint.port
+                );
+
+                // Discard the messages that have been queued while disconnected, as they are
+                // out of date.
+                while let Ok(Some(_)) = messages.try_next() {}
+
+                let mut next_message = Some(system_connected.clone());
+                loop {
+                    let message = match next_message.take() {
+                        Some(m) => m,
+                        None => match messages.next().await {
+                            Some(m) => m,
+                            None => return,
+                        },
+                    };
+
+                    // Each call to `write_all` on the WebSocket connection translates into one
+                    // WebSocket frame.
+                    let result = async {
+                        socket.write_all(message.as_bytes()).await?;
+                        socket.flush().await
+                    }
+                    .await;
+
+                    if let Err(err) = result {
+                        log::debug!(
+                            "telemetry-disconnected; host={}; port={}; error={}",
+                            endpoint.host,
+                            endpoint.port,
+                            err
+                        );
+                        break;
+                    }
+                }
+            }
+            future::Either::Left((Err(err), _)) => {
+                log::debug!(
+                    "telemetry-connection-error; host={}; port={}; error={}",
+                    endpoint.host,
+                    endpoint.port,
+                    err
+                );
+            }
+            future::Either::Right(((), _)) => {
+                log::debug!(
+                    "telemetry-connection-timeout; host={}; port={}",
+                    endpoint.host,
+                    endpoint.port
+                );
+            }
+        }
+
+        // Wait a bit before trying to reconnect, while still discarding the messages that are
+        // being sent. The task ends if the service has been destroyed.
+        let reconnect = Delay::new(Duration::from_secs(10));
+        futures::pin_mut!(reconnect);
+        loop {
+            match future::select(reconnect.as_mut(), messages.next()).await {
+                future::Either::Left(((), _)) => break,
+                future::Either::Right((Some(_), _)) => {}
+                future::Either::Right((None, _)) => return,
+            }
+        }
+    }
+}
+
+/// Opens a TCP connection to the given telemetry server, then negotiates TLS if necessary and
+/// the WebSocket protocol.
+async fn connect(
+    endpoint: &telemetry::TelemetryEndpoint,
+) -> Result<impl AsyncWrite + Unpin, io::Error> {
+    let tcp_socket =
+        async_std::net::TcpStream::connect((&endpoint.host[..], endpoint.port)).await?;
+    // Disable the Nagle algorithm, as each message is flushed immediately anyway.
+    let _ = tcp_socket.set_nodelay(true);
+
+    let host = endpoint.host_header();
+
+    if endpoint.secure {
+        let server_name = match endpoint.host.parse::<IpAddr>() {
+            Ok(ip) => rustls::ServerName::IpAddress(ip),
+            Err(_) => rustls::ServerName::try_from(&endpoint.host[..])
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        };
+
+        let tls_socket = network_service::tls_connector()
+            .connect(server_name, tcp_socket)
+            .await?;
+
+        websocket::websocket_client_handshake(websocket::Config {
+            tcp_socket: tls_socket,
+            host: &host,
+            url: &endpoint.path,
+        })
+        .await
+        .map(future::Either::Right)
+    } else {
+        websocket::websocket_client_handshake(websocket::Config {
+            tcp_socket,
+            host: &host,
+            url: &endpoint.path,
+        })
+        .await
+        .map(future::Either::Left)
+    }
+}
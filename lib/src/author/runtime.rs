@@ -193,7 +193,9 @@ pub fn build_block(config: Config) -> BlockBuild {
         main_trie_root_calculation_cache: config.main_trie_root_calculation_cache,
         storage_main_trie_changes: Default::default(),
         offchain_storage_changes: Default::default(),
+        storage_proof_recorder: None,
         max_log_level: config.max_log_level,
+        host_functions_profiler: None,
     });
 
     let vm = match init_result {
@@ -332,7 +334,9 @@ impl BlockBuild {
                         ),
                         storage_main_trie_changes: success.storage_main_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
+                        storage_proof_recorder: None,
                         max_log_level: shared.max_log_level,
+                        host_functions_profiler: None,
                     });
 
                     inner = Inner::Runtime(match init_result {
@@ -528,7 +532,9 @@ impl InherentExtrinsics {
             main_trie_root_calculation_cache: Some(self.main_trie_root_calculation_cache),
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_proof_recorder: None,
             max_log_level: self.shared.max_log_level,
+            host_functions_profiler: None,
         });
 
         let vm = match init_result {
@@ -562,7 +568,9 @@ impl ApplyExtrinsic {
             main_trie_root_calculation_cache: Some(self.main_trie_root_calculation_cache),
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_proof_recorder: None,
             max_log_level: self.shared.max_log_level,
+            host_functions_profiler: None,
         });
 
         self.shared.stage = Stage::ApplyExtrinsic(extrinsic);
@@ -586,7 +594,9 @@ impl ApplyExtrinsic {
             main_trie_root_calculation_cache: Some(self.main_trie_root_calculation_cache),
             storage_main_trie_changes: self.storage_main_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_proof_recorder: None,
             max_log_level: self.shared.max_log_level,
+            host_functions_profiler: None,
         });
 
         let vm = match init_result {
@@ -22,7 +22,7 @@
 
 use crate::{
     chain::{chain_information, fork_tree},
-    executor::{host, storage_diff},
+    executor::{host, runtime_host, storage_diff},
     header,
     trie::calculate_root,
     verify,
@@ -602,6 +602,7 @@ impl<T> VerifyContext<T> {
                     state_trie_version: success.state_trie_version,
                     offchain_storage_changes: success.offchain_storage_changes,
                     main_trie_root_calculation_cache: success.main_trie_root_calculation_cache,
+                    host_functions_profiler: success.host_functions_profiler,
                     insert: BodyInsert {
                         context: self,
                         is_new_best,
@@ -737,11 +738,15 @@ impl<T> BodyVerifyRuntimeRequired<T> {
     ///
     /// While `main_trie_root_calculation_cache` is optional, providing a value will considerably
     /// speed up the calculation.
+    ///
+    /// If `host_functions_profiler` is `Some`, the host functions called by the runtime are
+    /// recorded and returned in [`BodyVerifyStep2::Finished::host_functions_profiler`].
     pub fn resume(
         self,
         parent_runtime: host::HostVmPrototype,
         block_body: impl ExactSizeIterator<Item = impl AsRef<[u8]> + Clone> + Clone,
         main_trie_root_calculation_cache: Option<calculate_root::CalculationCache>,
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
     ) -> BodyVerifyStep2<T> {
         let parent_block_header = if let Some(parent_tree_index) = self.context.parent_tree_index {
             &self
@@ -810,6 +815,7 @@ impl<T> BodyVerifyRuntimeRequired<T> {
             block_body,
             main_trie_root_calculation_cache,
             max_log_level: 0,
+            host_functions_profiler,
         });
 
         self.context.with_body_verify(process)
@@ -856,6 +862,9 @@ pub enum BodyVerifyStep2<T> {
         /// Pass this value to [`BodyVerifyRuntimeRequired::resume`] when verifying a children of
         /// this block in order to considerably speed up the verification.
         main_trie_root_calculation_cache: calculate_root::CalculationCache,
+        /// Value that was passed to [`BodyVerifyRuntimeRequired::resume`], containing the host
+        /// functions called during the verification.
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
         /// Use to insert the block in the chain.
         insert: BodyInsert<T>,
     },
@@ -109,6 +109,7 @@ impl ChainSpec {
             module: &wasm_code,
             heap_pages,
             exec_hint: executor::vm::ExecHint::Oneshot,
+            resource_limits: Default::default(),
             allow_unresolved_imports: true,
         })
         .map_err(FromGenesisStorageError::VmInitialization)?;
@@ -262,6 +263,20 @@ impl ChainSpec {
             .flat_map(|ep| ep.iter().map(|e| &e.0))
     }
 
+    /// Returns the list of default telemetry servers of the chain, alongside with the maximum
+    /// verbosity of the messages to send to each of them.
+    ///
+    /// See [`crate::telemetry::parse_endpoint`] in order to parse the addresses.
+    pub fn telemetry_endpoints_with_verbosity(
+        &'_ self,
+    ) -> impl Iterator<Item = (&'_ str, u8)> + '_ {
+        self.client_spec
+            .telemetry_endpoints
+            .as_ref()
+            .into_iter()
+            .flat_map(|ep| ep.iter().map(|e| (&e.0[..], e.1)))
+    }
+
     /// Returns the network protocol id that uniquely identifies a chain. Used to prevent nodes
     /// from different blockchain networks from accidentally connecting to each other.
     ///
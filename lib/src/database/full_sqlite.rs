@@ -115,6 +115,35 @@ impl SqliteFullDatabase {
         finalized_hash(&database)
     }
 
+    /// Returns the size, in bytes, of the main file of the database.
+    ///
+    /// This doesn't include the size of the write-ahead log.
+    pub fn size_bytes(&self) -> Result<u64, AccessError> {
+        let database = self.database.lock();
+
+        let mut statement = database
+            .prepare(
+                r#"SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+
+        // This statement always returns exactly one row.
+        statement
+            .next()
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+
+        let value = statement
+            .read::<i64>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)?;
+        Ok(u64::try_from(value).unwrap_or(0))
+    }
+
     /// Returns the SCALE-encoded header of the given block, or `None` if the block is unknown.
     ///
     /// > **Note**: If this method is called twice times in a row with the same block hash, it
@@ -177,6 +206,80 @@ impl SqliteFullDatabase {
         Ok(Some(out.into_iter()))
     }
 
+    /// Returns the SCALE-encoded GrandPa justification of the given block, or `None` if the
+    /// block is unknown or doesn't have any justification stored.
+    pub fn block_justification(
+        &self,
+        block_hash: &[u8; 32],
+    ) -> Result<Option<Vec<u8>>, AccessError> {
+        let connection = self.database.lock();
+
+        let mut statement = connection
+            .prepare(r#"SELECT justification FROM blocks WHERE hash = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?
+            .bind(1, &block_hash[..])
+            .unwrap();
+
+        if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+            return Ok(None);
+        }
+
+        let value = statement
+            .read::<Option<Vec<u8>>>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?;
+        Ok(value)
+    }
+
+    /// Returns the number, hash, and SCALE-encoded GrandPa justification of the block with the
+    /// lowest number that is superior or equal to `block_number` and that has a justification
+    /// stored, or `None` if there isn't any.
+    pub fn first_justified_block(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<(u64, [u8; 32], Vec<u8>)>, AccessError> {
+        let block_number = match i64::try_from(block_number) {
+            Ok(n) => n,
+            Err(_) => return Ok(None),
+        };
+
+        let connection = self.database.lock();
+
+        let mut statement = connection
+            .prepare(
+                r#"SELECT number, hash, justification FROM blocks
+                WHERE number >= ? AND justification IS NOT NULL
+                ORDER BY number ASC LIMIT 1"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?
+            .bind(1, block_number)
+            .unwrap();
+
+        if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+            return Ok(None);
+        }
+
+        let number = statement
+            .read::<i64>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?;
+        let number = u64::try_from(number)
+            .map_err(|_| AccessError::Corrupted(CorruptedError::InvalidNumber))?;
+        let hash = statement
+            .read::<Vec<u8>>(1)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?;
+        let hash = <[u8; 32]>::try_from(&hash[..])
+            .map_err(|_| AccessError::Corrupted(CorruptedError::InvalidBlockHashLen))?;
+        let justification = statement
+            .read::<Vec<u8>>(2)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?;
+        Ok(Some((number, hash, justification)))
+    }
+
     /// Returns the hashes of the blocks given a block number.
     pub fn block_hash_by_number(
         &self,
@@ -406,6 +509,29 @@ impl SqliteFullDatabase {
         Ok(())
     }
 
+    /// Stores the SCALE-encoded GrandPa justification of the given block, overwriting the
+    /// previous one if any.
+    ///
+    /// Does nothing if the block isn't in the database.
+    pub fn set_block_justification(
+        &self,
+        block_hash: &[u8; 32],
+        justification: &[u8],
+    ) -> Result<(), AccessError> {
+        let connection = self.database.lock();
+
+        let mut statement = connection
+            .prepare(r#"UPDATE blocks SET justification = ? WHERE hash = ?"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)?
+            .bind(1, justification)
+            .unwrap()
+            .bind(2, &block_hash[..])
+            .unwrap();
+        statement.next().unwrap();
+        Ok(())
+    }
+
     /// Changes the finalized block to the given one.
     ///
     /// The block must have been previously inserted using [`SqliteFullDatabase::insert`], otherwise
@@ -669,6 +795,104 @@ impl SqliteFullDatabase {
         Ok(())
     }
 
+    /// Discards all the blocks and the finalized storage of the database, and replaces them with
+    /// the given finalized block and its storage.
+    ///
+    /// The genesis block is kept in the database, as it identifies the chain the database belongs
+    /// to.
+    ///
+    /// The storage entries of the main trie consist in a key, a value, and a trie entry version.
+    /// The storage entries of the child tries consist in the key of the child trie (without the
+    /// `:child_storage:default:` prefix), a key, a value, and a trie entry version.
+    ///
+    /// > **Note**: The changes that blocks perform on child tries aren't tracked yet. The child
+    /// >           tries passed to this function are therefore not updated when blocks are
+    /// >           finalized afterwards.
+    ///
+    /// This is typically used after having downloaded the storage of a recent finalized block
+    /// from the network, in order to avoid having to execute all the blocks since the genesis.
+    pub fn reset<'a>(
+        &self,
+        chain_information: impl Into<chain_information::ChainInformationRef<'a>>,
+        finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+        finalized_block_justification: Option<Vec<u8>>,
+        finalized_block_storage_main_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8], u8)>,
+        finalized_block_storage_child_tries_entries: impl Iterator<
+            Item = (&'a [u8], &'a [u8], &'a [u8], u8),
+        >,
+    ) -> Result<(), AccessError> {
+        let chain_information = chain_information.into();
+        let connection = self.database.lock();
+
+        let finalized_block_hash = chain_information
+            .finalized_block_header
+            .hash(self.block_number_bytes);
+
+        connection
+            .execute(
+                r#"
+            DELETE FROM non_finalized_changes;
+            DELETE FROM finalized_storage_main_trie;
+            DELETE FROM finalized_storage_child_tries;
+            DELETE FROM grandpa_triggered_authorities;
+            DELETE FROM grandpa_scheduled_authorities;
+            DELETE FROM aura_finalized_authorities;
+            DELETE FROM meta;
+            DELETE FROM blocks_body WHERE hash IN (SELECT hash FROM blocks WHERE number != 0);
+            DELETE FROM blocks WHERE number != 0;
+            "#,
+            )
+            .unwrap();
+
+        // If the new finalized block is the genesis block, it is inserted again below.
+        if chain_information.finalized_block_header.number == 0 {
+            for query in [
+                "DELETE FROM blocks_body WHERE hash = ?",
+                "DELETE FROM blocks WHERE hash = ?",
+            ] {
+                connection
+                    .prepare(query)
+                    .unwrap()
+                    .bind(1, &finalized_block_hash[..])
+                    .unwrap()
+                    .next()
+                    .unwrap();
+            }
+        }
+
+        open::insert_chain_information(
+            &connection,
+            self.block_number_bytes,
+            chain_information,
+            finalized_block_body,
+            finalized_block_justification,
+            finalized_block_storage_main_trie_entries,
+        )?;
+
+        {
+            let mut statement = connection
+                .prepare("INSERT INTO finalized_storage_child_tries(child_trie, key, value, trie_entry_version) VALUES(?, ?, ?, ?)")
+                .unwrap();
+            for (child_trie, key, value, trie_entry_version) in
+                finalized_block_storage_child_tries_entries
+            {
+                statement = statement
+                    .bind(1, child_trie)
+                    .unwrap()
+                    .bind(2, key)
+                    .unwrap()
+                    .bind(3, value)
+                    .unwrap()
+                    .bind(4, i64::from(trie_entry_version))
+                    .unwrap();
+                statement.next().unwrap();
+                statement = statement.reset().unwrap();
+            }
+        }
+
+        flush(&connection)
+    }
+
     /// Returns all the keys and values in the storage of the finalized block.
     ///
     /// In order to avoid race conditions, the known finalized block hash must be passed as
@@ -718,6 +942,61 @@ impl SqliteFullDatabase {
         Ok(out)
     }
 
+    /// Calls `f` with an iterator to all the keys and values in the storage of the finalized
+    /// block, ordered by ascending key.
+    ///
+    /// Contrary to [`SqliteFullDatabase::finalized_block_storage_main_trie`], the entries are
+    /// read from the database as they are yielded by the iterator, and never all loaded in
+    /// memory at the same time. The database is locked while `f` executes.
+    ///
+    /// In order to avoid race conditions, the known finalized block hash must be passed as
+    /// parameter. If the finalized block in the database doesn't match the hash passed as
+    /// parameter, most likely because it has been updated in a parallel thread, a
+    /// [`FinalizedAccessError::Obsolete`] error is returned.
+    ///
+    /// The iterator yields tuples of keys, values, and trie entry version. If the database is
+    /// corrupted, the iterator stops early and an error is returned.
+    pub fn with_finalized_block_storage_main_trie_ordered<T>(
+        &self,
+        finalized_block_hash: &[u8; 32],
+        f: impl FnOnce(&mut dyn Iterator<Item = (Vec<u8>, Vec<u8>, u8)>) -> T,
+    ) -> Result<T, FinalizedAccessError> {
+        let connection = self.database.lock();
+
+        if finalized_hash(&connection)? != *finalized_block_hash {
+            return Err(FinalizedAccessError::Obsolete);
+        }
+
+        let mut statement = connection
+            .prepare(r#"SELECT key, value, trie_entry_version FROM finalized_storage_main_trie ORDER BY key ASC"#)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+
+        let mut error = None;
+        let out = f(&mut iter::from_fn(|| {
+            if error.is_some() || !matches!(statement.next().unwrap(), sqlite::State::Row) {
+                return None;
+            }
+
+            let key = statement.read::<Vec<u8>>(0).unwrap();
+            let value = statement.read::<Vec<u8>>(1).unwrap();
+            match u8::try_from(statement.read::<i64>(2).unwrap()) {
+                Ok(trie_entry_version) => Some((key, value, trie_entry_version)),
+                Err(_) => {
+                    error = Some(CorruptedError::InvalidTrieEntryVersion);
+                    None
+                }
+            }
+        }));
+
+        match error {
+            None => Ok(out),
+            Some(err) => Err(FinalizedAccessError::Access(AccessError::Corrupted(err))),
+        }
+    }
+
     /// Returns the value associated to a key in the storage of the finalized block, and the trie
     /// entry version.
     ///
@@ -766,6 +1045,62 @@ impl SqliteFullDatabase {
         Ok(Some((value, trie_entry_version)))
     }
 
+    /// Returns the value associated to a key in the given default child trie of the storage of
+    /// the finalized block, and the trie entry version.
+    ///
+    /// `child_trie` is the key of the child trie, without the `:child_storage:default:` prefix.
+    ///
+    /// > **Note**: Child tries are only available if the database has been filled with
+    /// >           [`SqliteFullDatabase::reset`]. See also the documentation of this function.
+    ///
+    /// In order to avoid race conditions, the known finalized block hash must be passed as
+    /// parameter. If the finalized block in the database doesn't match the hash passed as
+    /// parameter, most likely because it has been updated in a parallel thread, a
+    /// [`FinalizedAccessError::Obsolete`] error is returned.
+    pub fn finalized_block_storage_child_trie_get(
+        &self,
+        finalized_block_hash: &[u8; 32],
+        child_trie: &[u8],
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, u8)>, FinalizedAccessError> {
+        let connection = self.database.lock();
+
+        if finalized_hash(&connection)? != *finalized_block_hash {
+            return Err(FinalizedAccessError::Obsolete);
+        }
+
+        let mut statement = connection
+            .prepare(
+                r#"SELECT value, trie_entry_version FROM finalized_storage_child_tries WHERE child_trie = ? AND key = ?"#,
+            )
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?
+            .bind(1, child_trie)
+            .unwrap()
+            .bind(2, key)
+            .unwrap();
+
+        if !matches!(statement.next().unwrap(), sqlite::State::Row) {
+            return Ok(None);
+        }
+
+        let value = statement
+            .read::<Vec<u8>>(0)
+            .map_err(InternalError)
+            .map_err(CorruptedError::Internal)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+
+        let trie_entry_version = u8::try_from(statement.read::<i64>(1).unwrap())
+            .map_err(|_| CorruptedError::InvalidTrieEntryVersion)
+            .map_err(AccessError::Corrupted)
+            .map_err(FinalizedAccessError::Access)?;
+
+        Ok(Some((value, trie_entry_version)))
+    }
+
     /// Returns the key in the storage of the finalized block that immediately follows the key
     /// passed as parameter.
     ///
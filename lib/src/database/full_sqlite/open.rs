@@ -145,6 +145,21 @@ CREATE TABLE IF NOT EXISTS finalized_storage_main_trie(
     trie_entry_version INTEGER NOT NULL
 );
 
+/*
+Storage of the default child tries at the highest block that is considered finalized.
+`child_trie` is the key of the child trie without the `:child_storage:default:` prefix.
+Only filled when the storage of the finalized block has been downloaded from the network. The
+changes that blocks perform on child tries aren't tracked yet, and this table isn't updated when
+new blocks get finalized.
+*/
+CREATE TABLE IF NOT EXISTS finalized_storage_child_tries(
+    child_trie BLOB NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB NOT NULL,
+    trie_entry_version INTEGER NOT NULL,
+    PRIMARY KEY(child_trie, key)
+);
+
 /*
 For non-finalized blocks (i.e. blocks that descend from the finalized block), contains changes
 that this block performs on the storage.
@@ -279,109 +294,152 @@ impl DatabaseEmpty {
         finalized_block_storage_main_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8])> + Clone,
         finalized_block_state_version: u8,
     ) -> Result<SqliteFullDatabase, AccessError> {
-        let chain_information = chain_information.into();
-
-        let finalized_block_hash = chain_information
-            .finalized_block_header
-            .hash(self.block_number_bytes);
-
-        let scale_encoded_finalized_block_header = chain_information
-            .finalized_block_header
-            .scale_encoding(self.block_number_bytes)
-            .fold(Vec::new(), |mut a, b| {
-                a.extend_from_slice(b.as_ref());
-                a
-            });
-
-        {
-            let mut statement = self
-                .database
-                .prepare("INSERT INTO finalized_storage_main_trie(key, value, trie_entry_version) VALUES(?, ?, ?)")
+        insert_chain_information(
+            &self.database,
+            self.block_number_bytes,
+            chain_information.into(),
+            finalized_block_body,
+            finalized_block_justification,
+            finalized_block_storage_main_trie_entries
+                .map(|(key, value)| (key, value, finalized_block_state_version)),
+        )?;
+
+        super::flush(&self.database)?;
+
+        Ok(SqliteFullDatabase {
+            database: parking_lot::Mutex::new(self.database),
+            block_number_bytes: self.block_number_bytes,
+        })
+    }
+}
+
+/// Inserts the given [`chain_information::ChainInformationRef`], finalized block body,
+/// justification, and storage in the database.
+///
+/// Storage entries consist in a key, a value, and a trie entry version.
+///
+/// The tables that this function writes to are expected to be empty.
+pub(super) fn insert_chain_information<'a>(
+    database: &sqlite::Connection,
+    block_number_bytes: usize,
+    chain_information: chain_information::ChainInformationRef<'a>,
+    finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+    finalized_block_justification: Option<Vec<u8>>,
+    finalized_block_storage_main_trie_entries: impl Iterator<Item = (&'a [u8], &'a [u8], u8)>,
+) -> Result<(), AccessError> {
+    let finalized_block_hash = chain_information
+        .finalized_block_header
+        .hash(block_number_bytes);
+
+    let scale_encoded_finalized_block_header = chain_information
+        .finalized_block_header
+        .scale_encoding(block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+    {
+        let mut statement = database
+            .prepare("INSERT INTO finalized_storage_main_trie(key, value, trie_entry_version) VALUES(?, ?, ?)")
+            .unwrap();
+        for (key, value, trie_entry_version) in finalized_block_storage_main_trie_entries {
+            statement = statement
+                .bind(1, key)
+                .unwrap()
+                .bind(2, value)
+                .unwrap()
+                .bind(3, i64::from(trie_entry_version))
                 .unwrap();
-            for (key, value) in finalized_block_storage_main_trie_entries {
-                statement = statement
-                    .bind(1, key)
-                    .unwrap()
-                    .bind(2, value)
-                    .unwrap()
-                    .bind(3, i64::from(finalized_block_state_version))
-                    .unwrap();
-                statement.next().unwrap();
-                statement = statement.reset().unwrap();
-            }
+            statement.next().unwrap();
+            statement = statement.reset().unwrap();
         }
+    }
 
-        {
-            let mut statement = self
-                .database
-                .prepare(
-                    "INSERT INTO blocks(hash, number, header, justification) VALUES(?, ?, ?, ?)",
-                )
-                .unwrap()
+    {
+        let mut statement = database
+            .prepare("INSERT INTO blocks(hash, number, header, justification) VALUES(?, ?, ?, ?)")
+            .unwrap()
+            .bind(1, &finalized_block_hash[..])
+            .unwrap()
+            .bind(
+                2,
+                i64::try_from(chain_information.finalized_block_header.number).unwrap(),
+            )
+            .unwrap()
+            .bind(3, &scale_encoded_finalized_block_header[..])
+            .unwrap();
+        if let Some(finalized_block_justification) = &finalized_block_justification {
+            statement = statement
+                .bind(4, &finalized_block_justification[..])
+                .unwrap();
+        } else {
+            statement = statement.bind(4, ()).unwrap();
+        }
+        statement.next().unwrap();
+    }
+
+    {
+        let mut statement = database
+            .prepare("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
+            .unwrap();
+        for (index, item) in finalized_block_body.enumerate() {
+            statement = statement
                 .bind(1, &finalized_block_hash[..])
                 .unwrap()
-                .bind(
-                    2,
-                    i64::try_from(chain_information.finalized_block_header.number).unwrap(),
-                )
+                .bind(2, i64::try_from(index).unwrap())
                 .unwrap()
-                .bind(3, &scale_encoded_finalized_block_header[..])
+                .bind(3, item)
                 .unwrap();
-            if let Some(finalized_block_justification) = &finalized_block_justification {
-                statement = statement
-                    .bind(4, &finalized_block_justification[..])
-                    .unwrap();
-            } else {
-                statement = statement.bind(4, ()).unwrap();
-            }
             statement.next().unwrap();
+            statement = statement.reset().unwrap();
         }
+    }
+
+    super::meta_set_blob(database, "best", &finalized_block_hash[..]).unwrap();
+    super::meta_set_number(
+        database,
+        "finalized",
+        chain_information.finalized_block_header.number,
+    )
+    .unwrap();
+
+    match &chain_information.finality {
+        chain_information::ChainInformationFinalityRef::Outsourced => {}
+        chain_information::ChainInformationFinalityRef::Grandpa {
+            finalized_triggered_authorities,
+            after_finalized_block_authorities_set_id,
+            finalized_scheduled_change,
+        } => {
+            super::meta_set_number(
+                database,
+                "grandpa_authorities_set_id",
+                *after_finalized_block_authorities_set_id,
+            )
+            .unwrap();
 
-        {
-            let mut statement = self
-                .database
-                .prepare("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
+            let mut statement = database
+            .prepare("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                 .unwrap();
-            for (index, item) in finalized_block_body.enumerate() {
+            for (index, item) in finalized_triggered_authorities.iter().enumerate() {
                 statement = statement
-                    .bind(1, &finalized_block_hash[..])
+                    .bind(1, i64::try_from(index).unwrap())
                     .unwrap()
-                    .bind(2, i64::try_from(index).unwrap())
+                    .bind(2, &item.public_key[..])
                     .unwrap()
-                    .bind(3, item)
+                    .bind(3, i64::from_ne_bytes(item.weight.get().to_ne_bytes()))
                     .unwrap();
                 statement.next().unwrap();
                 statement = statement.reset().unwrap();
             }
-        }
 
-        super::meta_set_blob(&self.database, "best", &finalized_block_hash[..]).unwrap();
-        super::meta_set_number(
-            &self.database,
-            "finalized",
-            chain_information.finalized_block_header.number,
-        )
-        .unwrap();
-
-        match &chain_information.finality {
-            chain_information::ChainInformationFinalityRef::Outsourced => {}
-            chain_information::ChainInformationFinalityRef::Grandpa {
-                finalized_triggered_authorities,
-                after_finalized_block_authorities_set_id,
-                finalized_scheduled_change,
-            } => {
-                super::meta_set_number(
-                    &self.database,
-                    "grandpa_authorities_set_id",
-                    *after_finalized_block_authorities_set_id,
-                )
-                .unwrap();
+            if let Some((height, list)) = finalized_scheduled_change {
+                super::meta_set_number(database, "grandpa_scheduled_target", *height).unwrap();
 
-                let mut statement = self
-                    .database
-                    .prepare("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
+                let mut statement = database
+            .prepare("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                     .unwrap();
-                for (index, item) in finalized_triggered_authorities.iter().enumerate() {
+                for (index, item) in list.iter().enumerate() {
                     statement = statement
                         .bind(1, i64::try_from(index).unwrap())
                         .unwrap()
@@ -392,84 +450,55 @@ impl DatabaseEmpty {
                     statement.next().unwrap();
                     statement = statement.reset().unwrap();
                 }
-
-                if let Some((height, list)) = finalized_scheduled_change {
-                    super::meta_set_number(&self.database, "grandpa_scheduled_target", *height)
-                        .unwrap();
-
-                    let mut statement = self
-                        .database
-                        .prepare("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
-                        .unwrap();
-                    for (index, item) in list.iter().enumerate() {
-                        statement = statement
-                            .bind(1, i64::try_from(index).unwrap())
-                            .unwrap()
-                            .bind(2, &item.public_key[..])
-                            .unwrap()
-                            .bind(3, i64::from_ne_bytes(item.weight.get().to_ne_bytes()))
-                            .unwrap();
-                        statement.next().unwrap();
-                        statement = statement.reset().unwrap();
-                    }
-                }
             }
         }
+    }
 
-        match &chain_information.consensus {
-            chain_information::ChainInformationConsensusRef::Unknown => {}
-            chain_information::ChainInformationConsensusRef::Aura {
-                finalized_authorities_list,
-                slot_duration,
-            } => {
-                super::meta_set_number(&self.database, "aura_slot_duration", slot_duration.get())
-                    .unwrap();
+    match &chain_information.consensus {
+        chain_information::ChainInformationConsensusRef::Unknown => {}
+        chain_information::ChainInformationConsensusRef::Aura {
+            finalized_authorities_list,
+            slot_duration,
+        } => {
+            super::meta_set_number(database, "aura_slot_duration", slot_duration.get()).unwrap();
 
-                let mut statement = self
-                    .database
-                    .prepare("INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)")
+            let mut statement = database
+                .prepare("INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)")
+                .unwrap();
+            for (index, item) in finalized_authorities_list.clone().enumerate() {
+                statement = statement
+                    .bind(1, i64::try_from(index).unwrap())
+                    .unwrap()
+                    .bind(2, &item.public_key[..])
                     .unwrap();
-                for (index, item) in finalized_authorities_list.clone().enumerate() {
-                    statement = statement
-                        .bind(1, i64::try_from(index).unwrap())
-                        .unwrap()
-                        .bind(2, &item.public_key[..])
-                        .unwrap();
-                    statement.next().unwrap();
-                    statement = statement.reset().unwrap();
-                }
+                statement.next().unwrap();
+                statement = statement.reset().unwrap();
             }
-            chain_information::ChainInformationConsensusRef::Babe {
-                slots_per_epoch,
-                finalized_next_epoch_transition,
-                finalized_block_epoch_information,
-            } => {
-                super::meta_set_number(
-                    &self.database,
-                    "babe_slots_per_epoch",
-                    slots_per_epoch.get(),
-                )
+        }
+        chain_information::ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_next_epoch_transition,
+            finalized_block_epoch_information,
+        } => {
+            super::meta_set_number(database, "babe_slots_per_epoch", slots_per_epoch.get())
                 .unwrap();
+            super::meta_set_blob(
+                database,
+                "babe_finalized_next_epoch",
+                &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+            )
+            .unwrap();
+
+            if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
                 super::meta_set_blob(
-                    &self.database,
-                    "babe_finalized_next_epoch",
-                    &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+                    database,
+                    "babe_finalized_epoch",
+                    &encode_babe_epoch_information(finalized_block_epoch_information.clone())[..],
                 )
                 .unwrap();
-
-                if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
-                    super::meta_set_blob(&self.database, "babe_finalized_epoch", &encode_babe_epoch_information(
-            finalized_block_epoch_information.clone(),
-        )[..]).unwrap();
-                }
             }
         }
-
-        super::flush(&self.database)?;
-
-        Ok(SqliteFullDatabase {
-            database: parking_lot::Mutex::new(self.database),
-            block_number_bytes: self.block_number_bytes,
-        })
     }
+
+    Ok(())
 }
@@ -153,6 +153,7 @@
 //!         module: &wasm_binary_code,
 //!         heap_pages: HeapPages::from(2048),
 //!         exec_hint: smoldot::executor::vm::ExecHint::Oneshot,
+//!         resource_limits: Default::default(),
 //!         allow_unresolved_imports: false
 //!     }).unwrap();
 //!     prototype.run_no_param("Core_version").unwrap().into()
@@ -204,6 +205,7 @@ use core::{cmp, fmt, hash::Hasher as _, iter, str};
 use sha2::Digest as _;
 use tiny_keccak::Hasher as _;
 
+pub mod profiler;
 pub mod runtime_version;
 
 pub use runtime_version::{CoreVersion, CoreVersionError, CoreVersionRef};
@@ -211,7 +213,7 @@ pub use trie::TrieEntryVersion;
 pub use vm::HeapPages;
 pub use zstd::Error as ModuleFormatError;
 
-mod tests;
+pub(super) mod tests;
 mod zstd;
 
 /// Configuration for [`HostVmPrototype::new`].
@@ -229,6 +231,13 @@ pub struct Config<TModule> {
     /// Hint used by the implementation to decide which kind of virtual machine to use.
     pub exec_hint: vm::ExecHint,
 
+    /// Limits to the resources that the Wasm code can use during each call. Use
+    /// `Default::default()` for no limit.
+    ///
+    /// If the memory size limit is lower than the number of pages required by the runtime plus
+    /// [`Config::heap_pages`], a [`NewErr::MemoryMaxSizeTooLow`] error is returned.
+    pub resource_limits: vm::ResourceLimits,
+
     /// If `true`, no [`vm::NewErr::UnresolvedFunctionImport`] error will be returned if the
     /// module trying to import functions that aren't recognized by the implementation. Instead,
     /// a [`Error::UnresolvedFunctionCalled`] error will be generated if the module tries to call
@@ -273,6 +282,45 @@ pub struct HostVmPrototype {
 impl HostVmPrototype {
     /// Creates a new [`HostVmPrototype`]. Parses and potentially JITs the module.
     pub fn new(config: Config<impl AsRef<[u8]>>) -> Result<Self, NewErr> {
+        Self::new_inner(config, None)
+    }
+
+    /// Creates a new [`HostVmPrototype`] from a module previously compiled ahead of time, as
+    /// returned by [`HostVmPrototype::serialize_precompiled`].
+    ///
+    /// The Wasm code must still be passed through [`Config::module`], as it contains information
+    /// about the runtime. [`Config::exec_hint`] is ignored.
+    ///
+    /// Returns [`vm::NewErr::InvalidPrecompiled`] if the precompiled module has been generated
+    /// with an incompatible compiler version or configuration, in which case the module should
+    /// be compiled again with [`HostVmPrototype::new`].
+    ///
+    /// # Safety
+    ///
+    /// See [`vm::VirtualMachinePrototype::from_precompiled`].
+    pub unsafe fn from_precompiled(
+        config: Config<impl AsRef<[u8]>>,
+        precompiled: &[u8],
+    ) -> Result<Self, NewErr> {
+        Self::new_inner(config, Some(precompiled))
+    }
+
+    /// Serializes the machine code generated when compiling the Wasm code, so that it can later
+    /// be passed to [`HostVmPrototype::from_precompiled`].
+    ///
+    /// Returns `None` if the Wasm code is interpreted rather than compiled ahead of time.
+    pub fn serialize_precompiled(&self) -> Option<Vec<u8>> {
+        self.vm_proto.serialize_precompiled()
+    }
+
+    /// Implementation of [`HostVmPrototype::new`] and [`HostVmPrototype::from_precompiled`].
+    ///
+    /// If `precompiled` is `Some`, the safety requirements of
+    /// [`vm::VirtualMachinePrototype::from_precompiled`] must be upheld by the caller.
+    fn new_inner(
+        config: Config<impl AsRef<[u8]>>,
+        precompiled: Option<&[u8]>,
+    ) -> Result<Self, NewErr> {
         // TODO: configurable maximum allowed size? a uniform value is important for consensus
         let module = zstd::zstd_decode_if_necessary(config.module.as_ref(), 50 * 1024 * 1024)
             .map_err(NewErr::BadFormat)?;
@@ -286,30 +334,42 @@ impl HostVmPrototype {
         // array.
         let (mut vm_proto, registered_functions) = {
             let mut registered_functions = Vec::new();
-            let vm_proto = vm::VirtualMachinePrototype::new(
-                module,
-                config.exec_hint,
-                // This closure is called back for each function that the runtime imports.
-                |mod_name, f_name, signature| {
-                    if mod_name != "env" {
+            // This closure is called back for each function that the runtime imports.
+            let symbols = |mod_name: &str, f_name: &str, signature: &vm::Signature| {
+                if mod_name != "env" {
+                    return Err(());
+                }
+
+                let id = registered_functions.len();
+                registered_functions.push(match HostFunction::by_name(f_name) {
+                    Some(f) if f.signature() == *signature => FunctionImport::Resolved(f),
+                    Some(_) | None if !config.allow_unresolved_imports => {
+                        // TODO: return a better error if there is a signature mismatch
                         return Err(());
                     }
-
-                    let id = registered_functions.len();
-                    registered_functions.push(match HostFunction::by_name(f_name) {
-                        Some(f) if f.signature() == *signature => FunctionImport::Resolved(f),
-                        Some(_) | None if !config.allow_unresolved_imports => {
-                            // TODO: return a better error if there is a signature mismatch
-                            return Err(());
-                        }
-                        Some(_) | None => FunctionImport::Unresolved {
-                            name: f_name.to_owned(),
-                            module: mod_name.to_owned(),
-                        },
-                    });
-                    Ok(id)
+                    Some(_) | None => FunctionImport::Unresolved {
+                        name: f_name.to_owned(),
+                        module: mod_name.to_owned(),
+                    },
+                });
+                Ok(id)
+            };
+            let vm_proto = match precompiled {
+                // The safety requirements are upheld by the caller of this function.
+                Some(precompiled) => unsafe {
+                    vm::VirtualMachinePrototype::from_precompiled(
+                        precompiled,
+                        config.resource_limits,
+                        symbols,
+                    )?
                 },
-            )?;
+                None => vm::VirtualMachinePrototype::new(
+                    module,
+                    config.exec_hint,
+                    config.resource_limits,
+                    symbols,
+                )?,
+            };
             registered_functions.shrink_to_fit();
             (vm_proto, registered_functions)
         };
@@ -497,6 +557,11 @@ impl HostVmPrototype {
                 registered_functions: self.registered_functions,
                 storage_transaction_depth: 0,
                 allocator,
+                pending_host_call: None,
+                current_host_function: None,
+                num_host_function_calls: 0,
+                memory_bytes_read: 0,
+                memory_bytes_written: 0,
             },
         })
     }
@@ -603,6 +668,74 @@ impl HostVm {
             HostVm::LogEmit(inner) => inner.inner.into_prototype(),
         }
     }
+
+    /// Returns statistics about the host functions that the runtime has called since the start
+    /// of the execution.
+    ///
+    /// Returns `None` if the execution has failed.
+    ///
+    /// See also [`ReadyToRun::run_step`].
+    pub fn host_functions_stats(&self) -> Option<HostFunctionsStats> {
+        let inner = match self {
+            HostVm::ReadyToRun(inner) => &inner.inner,
+            HostVm::Finished(inner) => &inner.inner,
+            HostVm::Error { .. } => return None,
+            HostVm::ExternalStorageGet(inner) => &inner.inner,
+            HostVm::ExternalStorageSet(inner) => &inner.inner,
+            HostVm::ExternalStorageAppend(inner) => &inner.inner,
+            HostVm::ExternalStorageClearPrefix(inner) => &inner.inner,
+            HostVm::ExternalStorageRoot(inner) => &inner.inner,
+            HostVm::ExternalStorageNextKey(inner) => &inner.inner,
+            HostVm::ExternalStorageNextChildTrie(inner) => &inner.inner,
+            HostVm::ExternalOffchainStorageSet(inner) => &inner.inner,
+            HostVm::SignatureVerification(inner) => &inner.inner,
+            HostVm::CallRuntimeVersion(inner) => &inner.inner,
+            HostVm::StartStorageTransaction(inner) => &inner.inner,
+            HostVm::EndStorageTransaction { resume, .. } => &resume.inner,
+            HostVm::GetMaxLogLevel(inner) => &inner.inner,
+            HostVm::LogEmit(inner) => &inner.inner,
+        };
+
+        // A `ReadyToRun` is within a host function only if the host function call hasn't been
+        // processed yet. All the other variants (except for `Finished`) are always generated
+        // while a host function is being called.
+        let current_call = match self {
+            HostVm::ReadyToRun(inner) if inner.inner.pending_host_call.is_none() => None,
+            HostVm::Finished(_) => None,
+            _ => inner.current_host_function.map(|f| f.name()),
+        };
+
+        Some(HostFunctionsStats {
+            num_calls: inner.num_host_function_calls,
+            current_call,
+            memory_bytes_read: inner.memory_bytes_read,
+            memory_bytes_written: inner.memory_bytes_written,
+        })
+    }
+}
+
+/// Statistics about the host functions called by the runtime. See
+/// [`HostVm::host_functions_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFunctionsStats {
+    /// Number of host function calls that the runtime has started since the start of the
+    /// execution.
+    pub num_calls: u64,
+
+    /// Name of the host function that the runtime is currently calling, if any. The host
+    /// function call is considered finished when the execution of the runtime resumes.
+    pub current_call: Option<&'static str>,
+
+    /// Total number of bytes of memory of the runtime that have been read by host functions
+    /// since the start of the execution.
+    ///
+    /// This only includes the parameters read when the host function is called, and not for
+    /// example the keys and values accessed through [`ExternalStorageGet::key`] or similar.
+    pub memory_bytes_read: u64,
+
+    /// Total number of bytes of memory of the runtime that have been written by host functions
+    /// since the start of the execution.
+    pub memory_bytes_written: u64,
 }
 
 /// Virtual machine is ready to run.
@@ -617,14 +750,33 @@ impl ReadyToRun {
     /// > **Note**: This is when the actual CPU-heavy computation happens.
     pub fn run(mut self) -> HostVm {
         loop {
-            match self.run_once() {
+            match self.run_once(false) {
                 HostVm::ReadyToRun(r) => self = r,
                 other => return other,
             }
         }
     }
 
-    fn run_once(mut self) -> HostVm {
+    /// Same as [`ReadyToRun::run`], but returns [`HostVm::ReadyToRun`] right after the runtime
+    /// has called a host function, before this host function is processed, and after each host
+    /// function that is processed without requiring any action from the API user.
+    ///
+    /// Combined with [`HostVm::host_functions_stats`], this makes it possible to measure the
+    /// time spent within each host function.
+    pub fn run_step(self) -> HostVm {
+        self.run_once(true)
+    }
+
+    fn run_once(mut self, stop_before_host_function: bool) -> HostVm {
+        // If a host function call has been paused by `run_step`, resume processing it.
+        if let Some((id, params)) = self.inner.pending_host_call.take() {
+            return self.run_host_function(id, params);
+        }
+
+        // The execution of the runtime is resumed, meaning that the previous host function call,
+        // if any, is over.
+        self.inner.current_host_function = None;
+
         // `vm::ExecOutcome::Interrupted` is by far the variant that requires the most
         // handling code. As such, special-case all other variants before.
         let (id, params) = match self.inner.vm.run(self.resume_value) {
@@ -688,10 +840,15 @@ impl ReadyToRun {
             Ok(vm::ExecOutcome::Finished {
                 return_value: Err(err),
             }) => {
+                let error = match err.resource_exhausted() {
+                    Some(resource) => Error::ResourceExhausted(resource),
+                    None => Error::Trap(err),
+                };
+
                 return HostVm::Error {
-                    error: Error::Trap(err),
+                    error,
                     prototype: self.inner.into_prototype(),
-                }
+                };
             }
 
             Err(vm::RunErr::BadValueTy { .. }) => {
@@ -707,6 +864,24 @@ impl ReadyToRun {
             }
         };
 
+        self.inner.num_host_function_calls += 1;
+        self.inner.current_host_function = match self.inner.registered_functions.get(id) {
+            Some(FunctionImport::Resolved(f)) => Some(*f),
+            _ => None,
+        };
+
+        if stop_before_host_function {
+            self.inner.pending_host_call = Some((id, params));
+            self.resume_value = None;
+            return HostVm::ReadyToRun(self);
+        }
+
+        self.run_host_function(id, params)
+    }
+
+    /// Processes a call to a host function. `id` and `params` are the values found in
+    /// [`vm::ExecOutcome::Interrupted`].
+    fn run_host_function(mut self, id: usize, params: Vec<vm::WasmValue>) -> HostVm {
         // The Wasm code has called an host_fn. The `id` is a value that we passed
         // at initialization, and corresponds to an index in `registered_functions`.
         let host_fn = match self.inner.registered_functions.get_mut(id) {
@@ -736,6 +911,7 @@ impl ReadyToRun {
                 let len = u32::try_from(val >> 32).unwrap();
                 let ptr = u32::try_from(val & 0xffffffff).unwrap();
 
+                self.inner.memory_bytes_read += u64::from(len);
                 let result = self.inner.vm.read_memory(ptr, len);
                 match result {
                     Ok(v) => v,
@@ -792,6 +968,7 @@ impl ReadyToRun {
                     _ => unreachable!(),
                 };
 
+                self.inner.memory_bytes_read += $size as u64;
                 let result = self.inner.vm.read_memory(ptr, $size);
                 match result {
                     Ok(v) => *<&[u8; $size]>::try_from(v.as_ref()).unwrap(),
@@ -3464,6 +3641,24 @@ struct Inner {
 
     /// Memory allocator in order to answer the calls to `malloc` and `free`.
     allocator: allocator::FreeingBumpHeapAllocator,
+
+    /// Host function call that has been started by the runtime but not processed yet, because
+    /// [`ReadyToRun::run_step`] was used. Contains the same values as
+    /// [`vm::ExecOutcome::Interrupted`].
+    pending_host_call: Option<(usize, Vec<vm::WasmValue>)>,
+
+    /// Host function that the runtime is currently calling. Set back to `None` when the
+    /// execution of the runtime resumes.
+    current_host_function: Option<HostFunction>,
+
+    /// See [`HostFunctionsStats::num_calls`].
+    num_host_function_calls: u64,
+
+    /// See [`HostFunctionsStats::memory_bytes_read`].
+    memory_bytes_read: u64,
+
+    /// See [`HostFunctionsStats::memory_bytes_written`].
+    memory_bytes_written: u64,
 }
 
 impl Inner {
@@ -3499,6 +3694,7 @@ impl Inner {
             }
         };
 
+        self.memory_bytes_written += u64::from(data_len);
         let mut ptr_iter = dest_ptr;
         for chunk in data {
             let chunk = chunk.as_ref();
@@ -3548,6 +3744,7 @@ impl Inner {
             }
         };
 
+        self.memory_bytes_written += u64::from(data_len);
         let mut ptr_iter = dest_ptr;
         for chunk in data {
             let chunk = chunk.as_ref();
@@ -3765,6 +3962,9 @@ pub enum Error {
         /// Name of the function being called.
         function: &'static str,
     },
+    /// One of the limits passed through [`Config::resource_limits`] has been reached.
+    #[display(fmt = "Resource limit reached: {_0}")]
+    ResourceExhausted(vm::ResourceExhausted),
 }
 
 /// Writing and reading keys the main trie under this prefix obey weird rules.
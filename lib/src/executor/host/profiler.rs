@@ -0,0 +1,345 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Recording of the host functions called by a runtime.
+//!
+//! The [`Profiler`] records, for each host function called by the runtime, its name, the moment
+//! when it has been called, its duration, the number of bytes it has read from and written to
+//! the memory of the runtime, and the storage keys it has accessed.
+//!
+//! # Usage
+//!
+//! In order to profile an execution, call [`ReadyToRun::run_step`](super::ReadyToRun::run_step)
+//! instead of [`ReadyToRun::run`](super::ReadyToRun::run), and call [`Profiler::observe`] with
+//! every [`HostVm`] that is obtained, including the ones obtained when resuming the execution
+//! after an action from the API user.
+//!
+//! When the execution is driven through the [`runtime_host`](crate::executor::runtime_host)
+//! module, this is done automatically by passing the profiler through
+//! [`runtime_host::Config::host_functions_profiler`](crate::executor::runtime_host::Config::host_functions_profiler).
+//!
+//! Because this module doesn't have access to a clock, the current time must be passed by the
+//! API user. It is expressed as a `Duration` since an arbitrary moment that must be the same for
+//! the entire execution, for example the start of the process.
+//!
+//! The calls that have been recorded can then be exported in the Chrome trace event format using
+//! [`Profiler::chrome_trace`], which can be opened in `chrome://tracing` or
+//! <https://ui.perfetto.dev>, or in the "folded stacks" format using [`Profiler::folded_stacks`],
+//! which can be passed to most flame graph generation tools.
+
+use super::{HostVm, StorageKey};
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt::Write as _, time::Duration};
+use hashbrown::HashMap;
+
+/// Records the host functions called by a runtime. See [the module-level documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// List of calls that have finished.
+    calls: Vec<HostFunctionCall>,
+
+    /// Call currently in progress, if any.
+    current: Option<CurrentCall>,
+}
+
+#[derive(Debug, Clone)]
+struct CurrentCall {
+    /// Value of [`super::HostFunctionsStats::num_calls`] when the call has started.
+    call_index: u64,
+    /// Value of [`super::HostFunctionsStats::memory_bytes_read`] when the call has started.
+    memory_bytes_read_start: u64,
+    /// Value of [`super::HostFunctionsStats::memory_bytes_written`] when the call has started.
+    memory_bytes_written_start: u64,
+    /// Number of bytes read in addition to the ones reported by
+    /// [`super::HostFunctionsStats::memory_bytes_read`].
+    extra_bytes_read: u64,
+    /// Call being built.
+    call: HostFunctionCall,
+}
+
+/// Host function call recorded by the [`Profiler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFunctionCall {
+    /// Name of the host function, for example `ext_storage_get_version_1`.
+    pub name: &'static str,
+    /// Moment when the host function has been called, as passed to [`Profiler::observe`].
+    pub start: Duration,
+    /// Duration of the call, including the time it took for the API user to answer a request
+    /// such as a storage access.
+    pub duration: Duration,
+    /// Number of bytes that the host function has read from the memory of the runtime.
+    pub bytes_read: u64,
+    /// Number of bytes that the host function has written to the memory of the runtime.
+    pub bytes_written: u64,
+    /// List of storage keys (or prefixes, in the case of a prefix clearing) that the host
+    /// function has accessed. Keys within child tries are reported without the child trie they
+    /// belong to.
+    pub storage_keys: Vec<Vec<u8>>,
+}
+
+impl Profiler {
+    /// Initializes a new empty [`Profiler`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the profiler with the latest state of the virtual machine.
+    ///
+    /// Must be called with every [`HostVm`] that is obtained during the execution.
+    pub fn observe(&mut self, vm: &HostVm, now: Duration) {
+        let stats = match vm.host_functions_stats() {
+            Some(stats) => stats,
+            None => {
+                // The execution has failed. Finish the current call, if any, without changing
+                // the number of bytes.
+                if let Some(current) = self.current.take() {
+                    let mut call = current.call;
+                    call.duration = now.saturating_sub(call.start);
+                    call.bytes_read = current.extra_bytes_read;
+                    self.calls.push(call);
+                }
+                return;
+            }
+        };
+
+        // Finish the current call if it is over.
+        if self.current.as_ref().map_or(false, |current| {
+            stats.current_call.is_none() || current.call_index != stats.num_calls
+        }) {
+            let current = self.current.take().unwrap();
+            let mut call = current.call;
+            call.duration = now.saturating_sub(call.start);
+            call.bytes_read = stats
+                .memory_bytes_read
+                .saturating_sub(current.memory_bytes_read_start)
+                + current.extra_bytes_read;
+            call.bytes_written = stats
+                .memory_bytes_written
+                .saturating_sub(current.memory_bytes_written_start);
+            self.calls.push(call);
+        }
+
+        // Start a new call if necessary.
+        if let (Some(name), None) = (stats.current_call, &self.current) {
+            self.current = Some(CurrentCall {
+                call_index: stats.num_calls,
+                memory_bytes_read_start: stats.memory_bytes_read,
+                memory_bytes_written_start: stats.memory_bytes_written,
+                extra_bytes_read: 0,
+                call: HostFunctionCall {
+                    name,
+                    start: now,
+                    duration: Duration::new(0, 0),
+                    bytes_read: 0,
+                    bytes_written: 0,
+                    storage_keys: Vec::new(),
+                },
+            });
+        }
+
+        // Record the storage keys accessed by the call.
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        let (key, value_len) = match vm {
+            HostVm::ExternalStorageGet(req) => (storage_key_to_vec(req.key()), 0),
+            HostVm::ExternalStorageSet(req) => (
+                storage_key_to_vec(req.key()),
+                req.value().map_or(0, |v| v.as_ref().len()),
+            ),
+            HostVm::ExternalStorageAppend(req) => {
+                (storage_key_to_vec(req.key()), req.value().as_ref().len())
+            }
+            HostVm::ExternalStorageClearPrefix(req) => (storage_key_to_vec(req.prefix()), 0),
+            HostVm::ExternalStorageNextKey(req) => (storage_key_to_vec(req.key()), 0),
+            _ => return,
+        };
+        current.extra_bytes_read += u64::try_from(key.len() + value_len).unwrap();
+        current.call.storage_keys.push(key);
+    }
+
+    /// Returns the list of host function calls that have finished, in chronological order.
+    pub fn calls(&self) -> &[HostFunctionCall] {
+        &self.calls
+    }
+
+    /// Returns the recorded calls in the Chrome trace event format, as a JSON document.
+    ///
+    /// Each host function call is represented with a "complete" event. The timestamps are in
+    /// microseconds, as required by the format.
+    pub fn chrome_trace(&self) -> String {
+        let mut out = String::with_capacity(64 + self.calls.len() * 128);
+        out.push_str("{\"traceEvents\":[");
+
+        for (index, call) in self.calls.iter().enumerate() {
+            if index != 0 {
+                out.push(',');
+            }
+
+            // Host function names only contain ASCII alphanumeric characters and underscores,
+            // and storage keys are hexadecimal-encoded. As such, no escaping is necessary.
+            let _ = write!(
+                out,
+                "{{\"name\":\"{}\",\"cat\":\"host\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\
+                \"ts\":{},\"dur\":{},\"args\":{{\"bytes_read\":{},\"bytes_written\":{},\
+                \"storage_keys\":[",
+                call.name,
+                call.start.as_micros(),
+                call.duration.as_micros(),
+                call.bytes_read,
+                call.bytes_written,
+            );
+
+            for (key_index, key) in call.storage_keys.iter().enumerate() {
+                if key_index != 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "\"0x{}\"", hex::encode(key));
+            }
+
+            out.push_str("]}}");
+        }
+
+        out.push_str("]}");
+        out
+    }
+
+    /// Returns the recorded calls in the "folded stacks" format, where each line contains a
+    /// stack of frames separated with `;`, followed with a space and a weight.
+    ///
+    /// The stack of each line consists in `root` followed with the name of the host function.
+    /// The weight is the total time spent in this host function, in microseconds. Lines are
+    /// ordered alphabetically.
+    pub fn folded_stacks(&self, root: &str) -> String {
+        let mut total_per_function =
+            HashMap::<&'static str, u128, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+                self.calls.len(),
+                Default::default(),
+            );
+        for call in &self.calls {
+            *total_per_function.entry(call.name).or_insert(0) += call.duration.as_micros();
+        }
+
+        let mut total_per_function = total_per_function.into_iter().collect::<Vec<_>>();
+        total_per_function.sort_unstable_by_key(|(name, _)| *name);
+
+        let mut out = String::new();
+        for (name, total) in total_per_function {
+            let _ = writeln!(out, "{root};{name} {total}");
+        }
+        out
+    }
+}
+
+/// Returns the key within its trie. The child trie, if any, is ignored.
+fn storage_key_to_vec(key: StorageKey<impl AsRef<[u8]>>) -> Vec<u8> {
+    match key {
+        StorageKey::MainTrie { key } | StorageKey::ChildTrieDefault { key, .. } => {
+            key.as_ref().to_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiler;
+    use crate::executor::{
+        host::{self, HostVm, HostVmPrototype},
+        vm::ExecHint,
+    };
+    use core::time::Duration;
+
+    #[test]
+    fn exports() {
+        // Runtime that reads `a` then sets `b` to `def`. Keys and values are found in the data
+        // segment.
+        let module_bytes = host::tests::with_core_version_custom_sections(
+            wat::parse_str(
+                r#"
+        (module
+            (import "env" "memory" (memory 1))
+            (import "env" "ext_storage_get_version_1" (func $get (param i64) (result i64)))
+            (import "env" "ext_storage_set_version_1" (func $set (param i64 i64)))
+            (data (i32.const 0) "abdef")
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (func (export "test") (param i32 i32) (result i64)
+                (drop (call $get (i64.const 4294967296)))
+                (call $set (i64.const 4294967297) (i64.const 12884901890))
+                (i64.const 0))
+        )
+        "#,
+            )
+            .unwrap(),
+        );
+
+        let proto = HostVmPrototype::new(host::Config {
+            module: &module_bytes,
+            heap_pages: host::HeapPages::new(1024),
+            exec_hint: ExecHint::Oneshot,
+            resource_limits: Default::default(),
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        // The time advances by one microsecond every time the virtual machine is observed.
+        let mut profiler = Profiler::new();
+        let mut now = Duration::new(0, 0);
+        let mut vm = HostVm::from(proto.run_no_param("test").unwrap());
+        loop {
+            now += Duration::from_micros(1);
+            profiler.observe(&vm, now);
+
+            vm = match vm {
+                HostVm::ReadyToRun(r) => r.run_step(),
+                HostVm::ExternalStorageGet(req) => req.resume_full_value(None),
+                HostVm::ExternalStorageSet(req) => req.resume(),
+                HostVm::Finished(_) => break,
+                HostVm::Error { error, .. } => panic!("{error:?}"),
+                _ => unreachable!(),
+            };
+        }
+
+        let calls = profiler.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "ext_storage_get_version_1");
+        assert_eq!(calls[0].storage_keys, vec![b"a".to_vec()]);
+        assert_eq!(calls[1].name, "ext_storage_set_version_1");
+        assert_eq!(calls[1].storage_keys, vec![b"b".to_vec()]);
+        // The key and the value.
+        assert_eq!(calls[1].bytes_read, 4);
+
+        assert_eq!(
+            profiler.folded_stacks("block"),
+            format!(
+                "block;ext_storage_get_version_1 {}\nblock;ext_storage_set_version_1 {}\n",
+                calls[0].duration.as_micros(),
+                calls[1].duration.as_micros()
+            )
+        );
+
+        let trace = profiler.chrome_trace();
+        let decoded = serde_json::from_str::<serde_json::Value>(&trace).unwrap();
+        let events = decoded["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "ext_storage_get_version_1");
+        assert_eq!(events[0]["ts"], calls[0].start.as_micros() as u64);
+        assert_eq!(events[0]["dur"], calls[0].duration.as_micros() as u64);
+        assert_eq!(events[0]["args"]["storage_keys"][0], "0x61");
+        assert_eq!(events[1]["args"]["storage_keys"][0], "0x62");
+    }
+}
@@ -35,7 +35,7 @@ following compilation options:
 
 /// Adds to the provided Wasm bytecode the custom sections containing the runtime version and
 /// runtime APIs, that the module wants to find in the Wasm module.
-fn with_core_version_custom_sections(mut wasm: Vec<u8>) -> Vec<u8> {
+pub(in crate::executor) fn with_core_version_custom_sections(mut wasm: Vec<u8>) -> Vec<u8> {
     let spec_name = "foo".to_string();
     let impl_name = "bar".to_string();
     let authoring_version = 0;
@@ -93,6 +93,7 @@ fn basic_core_version() {
             module: &include_bytes!("./westend-runtime-v9300.wasm")[..],
             heap_pages: HeapPages::new(2048),
             exec_hint,
+            resource_limits: Default::default(),
             allow_unresolved_imports: true,
         })
         .unwrap();
@@ -110,6 +111,43 @@ fn basic_core_version() {
     }
 }
 
+#[test]
+fn core_version_step_by_step() {
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            module: &include_bytes!("./westend-runtime-v9300.wasm")[..],
+            heap_pages: HeapPages::new(2048),
+            exec_hint,
+            resource_limits: Default::default(),
+            allow_unresolved_imports: true,
+        })
+        .unwrap();
+
+        let mut profiler = super::profiler::Profiler::new();
+        let mut now = core::time::Duration::new(0, 0);
+
+        let mut vm = HostVm::from(proto.run_no_param("Core_version").unwrap());
+        loop {
+            now += core::time::Duration::from_micros(1);
+            profiler.observe(&vm, now);
+
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run_step(),
+                HostVm::Error { error, .. } => panic!("{error:?}"),
+                HostVm::Finished(_) => break,
+                HostVm::GetMaxLogLevel(r) => vm = r.resume(0),
+                _ => unreachable!(),
+            }
+        }
+
+        assert!(!profiler.calls().is_empty());
+        assert!(profiler
+            .calls()
+            .iter()
+            .all(|call| call.duration >= core::time::Duration::from_micros(1)));
+    }
+}
+
 #[test]
 fn min_requirements() {
     // This module showcases minimum requirements in order for a Wasm module to be accepted.
@@ -131,6 +169,7 @@ fn min_requirements() {
 
     for exec_hint in ExecHint::available_engines() {
         HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
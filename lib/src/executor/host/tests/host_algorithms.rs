@@ -212,6 +212,7 @@ macro_rules! gen_test {
 
             for exec_hint in ExecHint::available_engines() {
                 let proto = HostVmPrototype::new(Config {
+                    resource_limits: Default::default(),
                     allow_unresolved_imports: false,
                     exec_hint,
                     heap_pages: HeapPages::new(1024),
@@ -26,6 +26,7 @@ fn invalid_wasm() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -43,6 +44,7 @@ fn invalid_zstd() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -69,6 +71,7 @@ fn valid_zstd() {
 
     for exec_hint in ExecHint::available_engines() {
         HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -93,6 +96,7 @@ fn no_heap_base() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -120,6 +124,7 @@ fn memory_max_size_too_low() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -148,6 +153,7 @@ fn unresolved_host_functions_setting() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: true,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -158,6 +164,7 @@ fn unresolved_host_functions_setting() {
         }
 
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -188,6 +195,7 @@ fn host_function_bad_signature() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: true,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -198,6 +206,7 @@ fn host_function_bad_signature() {
         }
 
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
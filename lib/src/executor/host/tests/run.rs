@@ -36,6 +36,7 @@ fn function_to_run_doesnt_exist() {
 
     for exec_hint in ExecHint::available_engines() {
         let host_vm = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -67,6 +68,7 @@ fn function_to_run_invalid_params() {
 
     for exec_hint in ExecHint::available_engines() {
         let host_vm = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -196,6 +198,7 @@ fn input_provided_correctly() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -218,6 +221,7 @@ fn input_provided_correctly() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -245,6 +249,7 @@ fn input_provided_correctly() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -351,6 +356,7 @@ fn large_input_provided_correctly() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -414,6 +420,7 @@ fn return_value_works() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -468,6 +475,7 @@ fn bad_return_value() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -527,6 +535,7 @@ fn returned_ptr_out_of_range() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -586,6 +595,7 @@ fn returned_size_out_of_range() {
 
     for exec_hint in ExecHint::available_engines() {
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -652,6 +662,7 @@ fn unresolved_host_function_called() {
 
     for exec_hint in ExecHint::available_engines() {
         match HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -662,6 +673,7 @@ fn unresolved_host_function_called() {
         }
 
         let proto = HostVmPrototype::new(Config {
+            resource_limits: Default::default(),
             allow_unresolved_imports: true,
             exec_hint,
             heap_pages: HeapPages::new(1024),
@@ -34,6 +34,22 @@
 //!
 //! These additional features considerably reduces the number of externals concepts to plug to
 //! the virtual machine.
+//!
+//! # Storage proofs
+//!
+//! If [`Config::storage_proof_recorder`] is `Some`, the execution keeps track of all the storage
+//! keys that it accesses. Once the execution has finished, the [`StorageProofRecorder`] can
+//! generate a Merkle proof containing all the trie nodes necessary to re-execute the same call
+//! while knowing only the state root. This can be used, for example, to answer call proof
+//! requests or to build the proof of validity of a parachain block.
+//!
+//! # Profiling
+//!
+//! If [`Config::host_functions_profiler`] is `Some`, the host functions called by the runtime are
+//! recorded using a [`host::profiler::Profiler`], which is then returned in
+//! [`Success::host_functions_profiler`]. The time spent answering the requests of the execution,
+//! such as [`RuntimeHostVm::StorageGet`], is counted as part of the host function that has
+//! generated the request.
 
 // TODO: more docs
 
@@ -43,8 +59,8 @@ use crate::{
     util,
 };
 
-use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
-use core::fmt;
+use alloc::{borrow::ToOwned as _, collections::BTreeSet, string::String, vec::Vec};
+use core::{fmt, time::Duration};
 use hashbrown::HashSet;
 
 pub use trie::TrieEntryVersion;
@@ -73,6 +89,18 @@ pub struct Config<'a, TParams> {
     /// execution will be pushed over the value in this field.
     pub offchain_storage_changes: storage_diff::TrieDiff,
 
+    /// Initial state of [`Success::storage_proof_recorder`]. If `None`, the storage accesses
+    /// aren't recorded.
+    ///
+    /// Passing the recorder of a previous call makes it possible to generate a single proof
+    /// covering multiple calls, such as all the calls necessary to execute a block.
+    ///
+    /// > **Note**: The storage values read while calculating the trie root are recorded as well.
+    /// >           If the runtime calculates the trie root and
+    /// >           [`Config::main_trie_root_calculation_cache`] is `None`, the entire storage is
+    /// >           read and the proof thus contains the entire trie.
+    pub storage_proof_recorder: Option<StorageProofRecorder>,
+
     /// Maximum log level of the runtime.
     ///
     /// > **Note**: This value is opaque from the point of the view of the client, and the runtime
@@ -80,6 +108,25 @@ pub struct Config<'a, TParams> {
     /// >           "off", `1` for "error", `2` for "warn", `3` for "info", `4` for "debug",
     /// >           and `5` for "trace".
     pub max_log_level: u32,
+
+    /// Initial state of [`Success::host_functions_profiler`]. If `None`, the host functions
+    /// calls aren't recorded.
+    ///
+    /// Passing the profiler of a previous call makes it possible to record multiple calls, such
+    /// as all the calls necessary to execute a block, in the same profiler.
+    pub host_functions_profiler: Option<HostFunctionsProfiler>,
+}
+
+/// Profiler of the host functions called during the execution, alongside with the way to obtain
+/// the current time. See [`Config::host_functions_profiler`].
+#[derive(Debug, Clone)]
+pub struct HostFunctionsProfiler {
+    /// Profiler the host function calls are recorded into.
+    pub profiler: host::profiler::Profiler,
+
+    /// Function that returns the current time, as passed to
+    /// [`host::profiler::Profiler::observe`]. Must always return durations since the same moment.
+    pub clock: fn() -> Duration,
 }
 
 /// Start running the WebAssembly virtual machine.
@@ -102,12 +149,14 @@ pub fn run(
         state_trie_version,
         main_trie_transaction: Vec::new(),
         offchain_storage_changes: config.offchain_storage_changes,
+        storage_proof_recorder: config.storage_proof_recorder,
         main_trie_root_calculation_cache: Some(
             config.main_trie_root_calculation_cache.unwrap_or_default(),
         ),
         root_calculation: None,
         logs: String::new(),
         max_log_level: config.max_log_level,
+        host_functions_profiler: config.host_functions_profiler,
     }
     .run())
 }
@@ -125,10 +174,16 @@ pub struct Success {
     pub state_trie_version: TrieEntryVersion,
     /// List of changes to the off-chain storage that this block performs.
     pub offchain_storage_changes: storage_diff::TrieDiff,
+    /// Storage accesses recorded during the execution, if [`Config::storage_proof_recorder`]
+    /// was `Some`.
+    pub storage_proof_recorder: Option<StorageProofRecorder>,
     /// Cache used for calculating the main trie root.
     pub main_trie_root_calculation_cache: calculate_root::CalculationCache,
     /// Concatenation of all the log messages printed by the runtime.
     pub logs: String,
+    /// Host function calls recorded during the execution, if
+    /// [`Config::host_functions_profiler`] was `Some`.
+    pub host_functions_profiler: Option<HostFunctionsProfiler>,
 }
 
 /// Function execution has succeeded. Contains the return value of the call.
@@ -274,12 +329,22 @@ impl StorageGet {
 
         match self.inner.vm {
             host::HostVm::ExternalStorageGet(req) => {
+                if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                    if let host::StorageKey::MainTrie { key } = req.key() {
+                        recorder.record_read(key.as_ref());
+                    }
+                }
+
                 // TODO: should actually report the offset and max_size in the API
                 self.inner.vm = req.resume_full_value(value.as_ref().map(|(v, _)| &v[..]));
             }
             host::HostVm::ExternalStorageAppend(req) => {
                 match req.key() {
                     host::StorageKey::MainTrie { key } => {
+                        if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                            recorder.record_read(key.as_ref());
+                        }
+
                         // TODO: could be less overhead?
                         let mut value = value.map(|(v, _)| v).unwrap_or_default();
                         append_to_storage_value(&mut value, req.value().as_ref());
@@ -296,6 +361,12 @@ impl StorageGet {
                 if let calculate_root::RootMerkleValueCalculation::StorageValue(value_request) =
                     self.inner.root_calculation.take().unwrap()
                 {
+                    // The value of the key is part of the calculated trie root, and thus of
+                    // the output of the execution.
+                    if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                        recorder.record_read(&value_request.key().collect::<Vec<_>>());
+                    }
+
                     self.inner.root_calculation = Some(value_request.inject(value));
                 } else {
                     // We only create a `StorageGet` if the state is `StorageValue`.
@@ -353,10 +424,22 @@ impl PrefixKeys {
                     _ => unreachable!(),
                 };
 
+                if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                    recorder.record_read(&prefix);
+                }
+
+                let storage_proof_recorder = &mut self.inner.storage_proof_recorder;
                 let mut after_overlay = self
                     .inner
                     .main_trie_changes
-                    .storage_prefix_keys_ordered(&prefix, keys)
+                    .storage_prefix_keys_ordered(
+                        &prefix,
+                        keys.inspect(|key| {
+                            if let Some(recorder) = storage_proof_recorder.as_mut() {
+                                recorder.record_read(key.as_ref());
+                            }
+                        }),
+                    )
                     .peekable();
 
                 let mut keys_to_remove = Vec::new(); // TODO: capacity?
@@ -385,6 +468,9 @@ impl PrefixKeys {
                 drop(after_overlay);
 
                 for key in keys_to_remove {
+                    if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                        recorder.record_write(&key);
+                    }
                     self.inner
                         .main_trie_root_calculation_cache
                         .as_mut()
@@ -402,6 +488,10 @@ impl PrefixKeys {
                 if let calculate_root::RootMerkleValueCalculation::AllKeys(all_keys) =
                     self.inner.root_calculation.take().unwrap()
                 {
+                    // The list of keys is intentionally not recorded, as doing so would require
+                    // including the entire trie in the proof. The verifier calculates the trie
+                    // root from the nodes found in the proof instead, and the values that this
+                    // calculation requires are recorded in `StorageGet::inject_value`.
                     // TODO: overhead
                     let mut list = keys
                         .filter(|v| {
@@ -483,6 +573,15 @@ impl NextKey {
                     } else {
                         req_key.as_ref()
                     };
+                    if let Some(recorder) = &mut self.inner.storage_proof_recorder {
+                        // Proving the key that follows `requested_key` consists in proving both
+                        // `requested_key` and the next key, as a proof contains all the nodes
+                        // along the path to these two keys.
+                        recorder.record_read(requested_key);
+                        if let Some(key) = key {
+                            recorder.record_read(key);
+                        }
+                    }
                     self.inner
                         .main_trie_changes
                         .storage_next_key(requested_key, key)
@@ -620,6 +719,9 @@ struct Inner {
     /// Pending changes to the off-chain storage that this execution performs.
     offchain_storage_changes: storage_diff::TrieDiff,
 
+    /// Value provided by [`Config::storage_proof_recorder`], updated as the execution progresses.
+    storage_proof_recorder: Option<StorageProofRecorder>,
+
     /// Cache passed by the user. Always `Some` except when we are currently calculating the trie
     /// state root.
     main_trie_root_calculation_cache: Option<calculate_root::CalculationCache>,
@@ -632,13 +734,24 @@ struct Inner {
 
     /// Value provided by [`Config::max_log_level`].
     max_log_level: u32,
+
+    /// Value provided by [`Config::host_functions_profiler`], updated as the execution
+    /// progresses.
+    host_functions_profiler: Option<HostFunctionsProfiler>,
 }
 
 impl Inner {
     /// Continues the execution.
     fn run(mut self) -> RuntimeHostVm {
         loop {
+            if let Some(profiler) = &mut self.host_functions_profiler {
+                profiler.profiler.observe(&self.vm, (profiler.clock)());
+            }
+
             match self.vm {
+                host::HostVm::ReadyToRun(r) if self.host_functions_profiler.is_some() => {
+                    self.vm = r.run_step()
+                }
                 host::HostVm::ReadyToRun(r) => self.vm = r.run(),
 
                 host::HostVm::Error { error, prototype } => {
@@ -657,10 +770,12 @@ impl Inner {
                         storage_main_trie_changes: self.main_trie_changes,
                         state_trie_version: self.state_trie_version,
                         offchain_storage_changes: self.offchain_storage_changes,
+                        storage_proof_recorder: self.storage_proof_recorder,
                         main_trie_root_calculation_cache: self
                             .main_trie_root_calculation_cache
                             .unwrap(),
                         logs: self.logs,
+                        host_functions_profiler: self.host_functions_profiler,
                     }));
                 }
 
@@ -689,6 +804,9 @@ impl Inner {
                 host::HostVm::ExternalStorageSet(req) => {
                     // TODO: this is a dummy implementation and child tries are not implemented properly
                     if let host::StorageKey::MainTrie { key } = req.key() {
+                        if let Some(recorder) = &mut self.storage_proof_recorder {
+                            recorder.record_write(key.as_ref());
+                        }
                         self.main_trie_root_calculation_cache
                             .as_mut()
                             .unwrap()
@@ -715,6 +833,10 @@ impl Inner {
                         }
                     };
 
+                    if let Some(recorder) = &mut self.storage_proof_recorder {
+                        recorder.record_write(key.as_ref());
+                    }
+
                     self.main_trie_root_calculation_cache
                         .as_mut()
                         .unwrap()
@@ -856,6 +978,7 @@ impl Inner {
                         module: req.wasm_code(),
                         heap_pages: executor::DEFAULT_HEAP_PAGES,
                         exec_hint: vm::ExecHint::Oneshot,
+                        resource_limits: Default::default(),
                         allow_unresolved_imports: false, // TODO: what is a correct value here?
                     }) {
                         Ok(w) => w,
@@ -938,6 +1061,87 @@ impl Inner {
     }
 }
 
+/// Keeps track of the storage keys accessed by one or more runtime calls, in order to later
+/// generate a Merkle proof of these accesses.
+///
+/// See [`Config::storage_proof_recorder`].
+#[derive(Debug, Clone, Default)]
+pub struct StorageProofRecorder {
+    /// Keys of the main trie whose storage value, or lack of storage value, the execution depends
+    /// upon.
+    read_keys: BTreeSet<Vec<u8>>,
+
+    /// Keys of the main trie that the execution has modified. The trie root calculation after
+    /// these modifications requires, in addition to the path to these keys, the children of the
+    /// nodes that might be merged with their parent.
+    written_keys: BTreeSet<Vec<u8>>,
+}
+
+impl StorageProofRecorder {
+    /// Initializes a new empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the list of keys of the main trie that have been read during the execution, in
+    /// lexicographic order.
+    ///
+    /// Reads that have been answered by the changes performed by the execution itself aren't
+    /// included, as they don't depend on the initial state of the storage.
+    pub fn read_keys(&'_ self) -> impl Iterator<Item = &'_ [u8]> + '_ {
+        self.read_keys.iter().map(|k| &k[..])
+    }
+
+    /// Returns the list of keys of the main trie that have been modified during the execution,
+    /// in lexicographic order.
+    pub fn written_keys(&'_ self) -> impl Iterator<Item = &'_ [u8]> + '_ {
+        self.written_keys.iter().map(|k| &k[..])
+    }
+
+    /// Builds the Merkle proof of all the storage accesses that have been recorded.
+    ///
+    /// `main_trie_entries` must contain all the entries of the main trie of the storage at the
+    /// start of the execution, ordered by ascending key. In other words,
+    /// [`Config::storage_main_trie_changes`] must **not** be applied on top of it.
+    ///
+    /// Calculating the trie nodes of the proof requires visiting every entry of the trie, but the
+    /// entries are visited in a single pass and aren't all kept in memory at the same time. See
+    /// [`trie::proof_encode::build_from_ordered_trie_entries`].
+    ///
+    /// The returned proof uses the same format as [`trie::proof_encode::ProofBuilder::build`],
+    /// and contains all the trie nodes necessary in order to re-execute the call while knowing
+    /// only the state root.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the keys of `main_trie_entries` aren't strictly increasing.
+    ///
+    pub fn build_proof(
+        &self,
+        main_trie_entries: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>, TrieEntryVersion)>,
+    ) -> Vec<u8> {
+        let keys_to_prove = self
+            .read_keys
+            .iter()
+            .map(|k| (&k[..], false))
+            .chain(self.written_keys.iter().map(|k| (&k[..], true)));
+        trie::proof_encode::build_from_ordered_trie_entries(main_trie_entries, keys_to_prove)
+            .build_to_vec()
+    }
+
+    fn record_read(&mut self, key: &[u8]) {
+        if !self.read_keys.contains(key) {
+            self.read_keys.insert(key.to_vec());
+        }
+    }
+
+    fn record_write(&mut self, key: &[u8]) {
+        if !self.written_keys.contains(key) {
+            self.written_keys.insert(key.to_vec());
+        }
+    }
+}
+
 /// Performs the action described by [`host::HostVm::ExternalStorageAppend`] on an
 /// encoded storage value.
 fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
@@ -981,3 +1185,256 @@ fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
     value[..new_len_encoded_size].copy_from_slice(new_len_encoded.as_ref());
     value.extend_from_slice(to_add);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{host, vm::ExecHint};
+    use crate::trie::{self, calculate_root, proof_decode};
+    use alloc::collections::BTreeMap;
+    use core::{
+        iter,
+        ops::Bound,
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+
+    #[test]
+    fn storage_proof_recorder_proves_call() {
+        // Runtime that reads `a`, sets `b` to `def`, removes `c`, then returns the new trie
+        // root. Keys and values are found in the data segment.
+        let module_bytes = host::tests::with_core_version_custom_sections(
+            wat::parse_str(
+                r#"
+        (module
+            (import "env" "memory" (memory 1))
+            (import "env" "ext_storage_get_version_1" (func $get (param i64) (result i64)))
+            (import "env" "ext_storage_set_version_1" (func $set (param i64 i64)))
+            (import "env" "ext_storage_clear_version_1" (func $clear (param i64)))
+            (import "env" "ext_storage_root_version_1" (func $root (result i64)))
+            (data (i32.const 0) "abcdef")
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (func (export "test") (param i32 i32) (result i64)
+                (drop (call $get (i64.const 4294967296)))
+                (call $set (i64.const 4294967297) (i64.const 12884901891))
+                (call $clear (i64.const 4294967298))
+                (call $root))
+        )
+        "#,
+            )
+            .unwrap(),
+        );
+
+        let storage = [
+            (&b"a"[..], &b"hello"[..]),
+            (&b"b"[..], &b"world"[..]),
+            (&b"c"[..], &b"to be removed"[..]),
+            (&b"ca"[..], &b"child of a removed node"[..]),
+            // Values longer than 32 bytes, so that the node values aren't inlined.
+            (
+                &b"z"[..],
+                &b"unrelated storage value longer than 32 bytes"[..],
+            ),
+            (
+                &b"zz"[..],
+                &b"another unrelated storage value longer than 32 bytes"[..],
+            ),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+        .collect::<BTreeMap<_, _>>();
+
+        // Calculate the root of the initial storage and the cache that corresponds to it, as
+        // would be the case when executing a call on top of an already-verified block.
+        let (initial_root, cache) = {
+            let mut calculation = calculate_root::root_merkle_value(None);
+            loop {
+                match calculation {
+                    calculate_root::RootMerkleValueCalculation::Finished { hash, cache } => {
+                        break (hash, cache)
+                    }
+                    calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                        calculation = keys.inject(storage.keys().map(|k| k.iter().copied()));
+                    }
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value) => {
+                        let key = value.key().collect::<Vec<_>>();
+                        let stored = storage.get(&key);
+                        calculation = value.inject(stored.map(|v| (v, trie::TrieEntryVersion::V0)));
+                    }
+                }
+            }
+        };
+
+        let mut execution = super::run(super::Config {
+            virtual_machine: host::HostVmPrototype::new(host::Config {
+                module: &module_bytes,
+                heap_pages: host::HeapPages::new(1024),
+                exec_hint: ExecHint::Oneshot,
+                resource_limits: Default::default(),
+                allow_unresolved_imports: false,
+            })
+            .unwrap(),
+            function_to_call: "test",
+            parameter: iter::empty::<Vec<u8>>(),
+            main_trie_root_calculation_cache: Some(cache),
+            storage_main_trie_changes: Default::default(),
+            offchain_storage_changes: Default::default(),
+            storage_proof_recorder: Some(super::StorageProofRecorder::new()),
+            max_log_level: 0,
+            host_functions_profiler: None,
+        })
+        .unwrap();
+
+        let success = loop {
+            execution = match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => break success,
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{:?}", err.detail),
+                super::RuntimeHostVm::StorageGet(req) => {
+                    let value = storage.get(req.key().as_ref()).cloned();
+                    req.inject_value(value.map(|v| (iter::once(v), trie::TrieEntryVersion::V0)))
+                }
+                super::RuntimeHostVm::PrefixKeys(req) => {
+                    let keys = storage
+                        .keys()
+                        .filter(|k| k.starts_with(req.prefix().as_ref()))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    req.inject_keys_ordered(keys.into_iter())
+                }
+                super::RuntimeHostVm::NextKey(req) => {
+                    let key = storage
+                        .range::<[u8], _>((Bound::Excluded(req.key().as_ref()), Bound::Unbounded))
+                        .next()
+                        .map(|(k, _)| k.clone());
+                    req.inject_key(key)
+                }
+                super::RuntimeHostVm::SignatureVerification(req) => req.verify_and_resume(),
+            };
+        };
+
+        // The root returned by the runtime must match the storage after the changes.
+        let mut final_storage = storage.clone();
+        for (key, value, ()) in success.storage_main_trie_changes.diff_iter_unordered() {
+            match value {
+                Some(value) => final_storage.insert(key.to_vec(), value.to_vec()),
+                None => final_storage.remove(key),
+            };
+        }
+        let final_entries = final_storage.into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            success.virtual_machine.value().as_ref(),
+            &trie::trie_root(trie::TrieEntryVersion::V0, &final_entries)[..]
+        );
+
+        let recorder = success.storage_proof_recorder.unwrap();
+        assert!(recorder.read_keys().any(|k| k == b"a"));
+        assert_eq!(
+            recorder.written_keys().collect::<Vec<_>>(),
+            vec![&b"b"[..], &b"c"[..]]
+        );
+
+        let proof = recorder.build_proof(
+            storage
+                .iter()
+                .map(|(k, v)| (k, v, trie::TrieEntryVersion::V0)),
+        );
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &initial_root,
+            proof,
+        })
+        .unwrap();
+
+        // The proof contains the values of all the keys that have been read and written.
+        for key in recorder.read_keys().chain(recorder.written_keys()) {
+            assert_eq!(
+                decoded
+                    .storage_value(key)
+                    .unwrap()
+                    .map(|(value, _)| value.to_vec()),
+                storage.get(key).cloned()
+            );
+        }
+
+        // Removing `c` merges it with its only child `ca`, whose node must thus be in the proof.
+        assert_eq!(
+            decoded.storage_value(b"ca").unwrap().unwrap().0,
+            b"child of a removed node"
+        );
+
+        // Nodes unrelated to the execution aren't in the proof.
+        assert!(decoded.storage_value(b"zz").is_none());
+    }
+
+    #[test]
+    fn host_functions_profiler() {
+        // Runtime that reads `a` then sets `b` to `def`. Keys and values are found in the data
+        // segment.
+        let module_bytes = host::tests::with_core_version_custom_sections(
+            wat::parse_str(
+                r#"
+        (module
+            (import "env" "memory" (memory 1))
+            (import "env" "ext_storage_get_version_1" (func $get (param i64) (result i64)))
+            (import "env" "ext_storage_set_version_1" (func $set (param i64 i64)))
+            (data (i32.const 0) "abdef")
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (func (export "test") (param i32 i32) (result i64)
+                (drop (call $get (i64.const 4294967296)))
+                (call $set (i64.const 4294967297) (i64.const 12884901890))
+                (i64.const 0))
+        )
+        "#,
+            )
+            .unwrap(),
+        );
+
+        // Clock that advances by one microsecond every time it is called.
+        fn clock() -> Duration {
+            static NOW: AtomicU64 = AtomicU64::new(0);
+            Duration::from_micros(NOW.fetch_add(1, Ordering::Relaxed))
+        }
+
+        let mut execution = super::run(super::Config {
+            virtual_machine: host::HostVmPrototype::new(host::Config {
+                module: &module_bytes,
+                heap_pages: host::HeapPages::new(1024),
+                exec_hint: ExecHint::Oneshot,
+                resource_limits: Default::default(),
+                allow_unresolved_imports: false,
+            })
+            .unwrap(),
+            function_to_call: "test",
+            parameter: iter::empty::<Vec<u8>>(),
+            main_trie_root_calculation_cache: None,
+            storage_main_trie_changes: Default::default(),
+            offchain_storage_changes: Default::default(),
+            storage_proof_recorder: None,
+            max_log_level: 0,
+            host_functions_profiler: Some(super::HostFunctionsProfiler {
+                profiler: host::profiler::Profiler::new(),
+                clock,
+            }),
+        })
+        .unwrap();
+
+        let success = loop {
+            execution = match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => break success,
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{:?}", err.detail),
+                super::RuntimeHostVm::StorageGet(req) => {
+                    assert_eq!(req.key().as_ref(), b"a");
+                    req.inject_value(None::<(iter::Empty<Vec<u8>>, _)>)
+                }
+                _ => unreachable!(),
+            };
+        };
+
+        let profiler = success.host_functions_profiler.unwrap().profiler;
+        let calls = profiler.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "ext_storage_get_version_1");
+        assert_eq!(calls[0].storage_keys, vec![b"a".to_vec()]);
+        assert_eq!(calls[1].name, "ext_storage_set_version_1");
+        assert_eq!(calls[1].storage_keys, vec![b"b".to_vec()]);
+        assert!(calls[0].start + calls[0].duration <= calls[1].start);
+    }
+}
@@ -52,6 +52,14 @@
 //!
 //! The first variant used to be the default model when compiling to WebAssembly, but the second
 //! variant (importing memory objects) is preferred nowadays.
+//!
+//! # Resource limits
+//!
+//! When the WebAssembly code is untrusted, it is possible to pass [`ResourceLimits`] to
+//! [`VirtualMachinePrototype::new`] in order to bound the amount of work that a single execution
+//! can perform. If the fuel or stack depth limit is reached, the execution finishes with a
+//! [`Trap`] whose [`Trap::resource_exhausted`] function returns `Some`. If the memory size limit
+//! is reached, the memory simply can't grow any further.
 
 mod interpreter;
 #[cfg(all(target_arch = "x86_64", feature = "std"))]
@@ -90,32 +98,142 @@ impl VirtualMachinePrototype {
     pub fn new(
         module_bytes: impl AsRef<[u8]>,
         exec_hint: ExecHint,
+        resource_limits: ResourceLimits,
         symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
     ) -> Result<Self, NewErr> {
+        // Only the interpreter is capable of counting the number of nested function calls.
+        // Executing the Wasm code with the JIT would make it possible to enforce
+        // [`ResourceLimits::max_stack_depth`] only approximately.
+        let exec_hint = if resource_limits.max_stack_depth.is_some() {
+            ExecHint::ForceWasmi
+        } else {
+            exec_hint
+        };
+
         Ok(VirtualMachinePrototype {
             inner: match exec_hint {
                 #[cfg(all(target_arch = "x86_64", feature = "std"))]
                 ExecHint::CompileAheadOfTime => VirtualMachinePrototypeInner::Jit(
-                    jit::JitPrototype::new(module_bytes, symbols)?,
+                    jit::JitPrototype::new(module_bytes, &resource_limits, symbols)?,
                 ),
                 #[cfg(not(all(target_arch = "x86_64", feature = "std")))]
                 ExecHint::CompileAheadOfTime => VirtualMachinePrototypeInner::Interpreter(
-                    interpreter::InterpreterPrototype::new(module_bytes, symbols)?,
+                    interpreter::InterpreterPrototype::new(
+                        module_bytes,
+                        &resource_limits,
+                        symbols,
+                    )?,
                 ),
                 ExecHint::Oneshot | ExecHint::Untrusted | ExecHint::ForceWasmi => {
                     VirtualMachinePrototypeInner::Interpreter(
-                        interpreter::InterpreterPrototype::new(module_bytes, symbols)?,
+                        interpreter::InterpreterPrototype::new(
+                            module_bytes,
+                            &resource_limits,
+                            symbols,
+                        )?,
                     )
                 }
 
                 #[cfg(all(target_arch = "x86_64", feature = "std"))]
                 ExecHint::ForceWasmtime => VirtualMachinePrototypeInner::Jit(
-                    jit::JitPrototype::new(module_bytes, symbols)?,
+                    jit::JitPrototype::new(module_bytes, &resource_limits, symbols)?,
                 ),
             },
         })
     }
 
+    /// Creates a new [`VirtualMachinePrototype`] from the output of
+    /// [`VirtualMachinePrototype::serialize_precompiled`].
+    ///
+    /// This skips the compilation of the Wasm code, which can take a long time for large
+    /// modules. The precompiled module embeds information about the version of the compiler, its
+    /// configuration, and the CPU features it was compiled for. An error is returned if they
+    /// don't match the current environment, in which case the caller is expected to compile the
+    /// module again using [`VirtualMachinePrototype::new`].
+    ///
+    /// See [`VirtualMachinePrototype::new`] for an explanation of the other parameters.
+    ///
+    /// Always returns [`NewErr::InvalidPrecompiled`] if the platform doesn't support compiling
+    /// Wasm code ahead of time, or if [`ResourceLimits::max_stack_depth`] is `Some`.
+    ///
+    /// # Safety
+    ///
+    /// The precompiled module contains native machine code that is executed as-is. The content of
+    /// `precompiled` must have been generated by [`VirtualMachinePrototype::serialize_precompiled`]
+    /// and must not have been tampered with.
+    pub unsafe fn from_precompiled(
+        precompiled: &[u8],
+        resource_limits: ResourceLimits,
+        symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
+    ) -> Result<Self, NewErr> {
+        // See the comment in [`VirtualMachinePrototype::new`].
+        if resource_limits.max_stack_depth.is_some() {
+            return Err(NewErr::InvalidPrecompiled(
+                "Stack depth limits require interpreting the Wasm code".into(),
+            ));
+        }
+
+        #[cfg(all(target_arch = "x86_64", feature = "std"))]
+        {
+            Ok(VirtualMachinePrototype {
+                inner: VirtualMachinePrototypeInner::Jit(jit::JitPrototype::from_precompiled(
+                    precompiled,
+                    &resource_limits,
+                    symbols,
+                )?),
+            })
+        }
+
+        #[cfg(not(all(target_arch = "x86_64", feature = "std")))]
+        {
+            let _ = (precompiled, resource_limits, symbols);
+            Err(NewErr::InvalidPrecompiled(
+                "Precompiled modules aren't supported on this platform".into(),
+            ))
+        }
+    }
+
+    /// Returns an identifier of the configuration of the compiler that
+    /// [`VirtualMachinePrototype::new`] would use given these resource limits.
+    ///
+    /// Two precompiled modules generated with the same identifier and from the same Wasm code are
+    /// interchangeable. This can be used as part of the key of a cache of precompiled modules.
+    ///
+    /// The identifier doesn't depend on the version of the compiler or on the CPU features, as
+    /// [`VirtualMachinePrototype::from_precompiled`] already returns an error if they don't
+    /// match.
+    ///
+    /// Returns `None` if the platform doesn't support compiling Wasm code ahead of time, or if
+    /// [`ResourceLimits::max_stack_depth`] is `Some`.
+    pub fn precompiled_engine_key(resource_limits: &ResourceLimits) -> Option<u64> {
+        if resource_limits.max_stack_depth.is_some() {
+            return None;
+        }
+
+        #[cfg(all(target_arch = "x86_64", feature = "std"))]
+        {
+            Some(jit::JitPrototype::precompiled_engine_key(resource_limits))
+        }
+
+        #[cfg(not(all(target_arch = "x86_64", feature = "std")))]
+        {
+            let _ = resource_limits;
+            None
+        }
+    }
+
+    /// Serializes the machine code generated when compiling the Wasm code, so that it can later
+    /// be passed to [`VirtualMachinePrototype::from_precompiled`].
+    ///
+    /// Returns `None` if the Wasm code is interpreted rather than compiled ahead of time.
+    pub fn serialize_precompiled(&self) -> Option<Vec<u8>> {
+        match &self.inner {
+            #[cfg(all(target_arch = "x86_64", feature = "std"))]
+            VirtualMachinePrototypeInner::Jit(inner) => inner.serialize_precompiled(),
+            VirtualMachinePrototypeInner::Interpreter(_) => None,
+        }
+    }
+
     /// Returns the value of a global that the module exports.
     ///
     /// The global variable must be a `u32`, otherwise an error is returned.
@@ -129,6 +247,9 @@ impl VirtualMachinePrototype {
 
     /// Returns the maximum number of pages that the memory can have.
     ///
+    /// This takes into account both the limit declared by the Wasm module and
+    /// [`ResourceLimits::max_memory_pages`].
+    ///
     /// `None` if there is no limit.
     pub fn memory_max_pages(&self) -> Option<HeapPages> {
         match &self.inner {
@@ -441,6 +562,54 @@ impl ExecHint {
     }
 }
 
+/// Limits to the resources that a single execution of a function can use.
+///
+/// See [`VirtualMachinePrototype::new`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Maximum amount of fuel that a single execution can consume. `None` if unlimited.
+    ///
+    /// Each Wasm instruction that is executed consumes one or more units of fuel. The exact cost
+    /// of each instruction depends on the execution engine, and the amount of fuel necessary for
+    /// a specific execution might thus differ between `wasmi` and `wasmtime`. However, for a
+    /// given engine, the amount of fuel consumed is deterministic.
+    ///
+    /// Calls to host functions don't consume any fuel, and the time spent executing host
+    /// functions must be bounded separately.
+    pub max_fuel: Option<u64>,
+
+    /// Maximum number of nested Wasm function calls. `None` for the default limit of the
+    /// execution engine.
+    ///
+    /// > **Note**: Only the interpreter is capable of counting nested function calls. If this
+    /// >           field is `Some`, the Wasm code is always interpreted, no matter the value of
+    /// >           the [`ExecHint`].
+    pub max_stack_depth: Option<u32>,
+
+    /// Maximum number of pages that the memory can reach by growing. `None` if the only limit is
+    /// the maximum declared by the Wasm module.
+    ///
+    /// Once the limit is reached, the `memory.grow` instruction fails in the same way as if the
+    /// Wasm module had declared this maximum itself.
+    ///
+    /// If the initial size of the memory is above this limit, [`NewErr::MemoryLimitTooLow`] is
+    /// returned at initialization. If the memory is defined by the module rather than imported,
+    /// the interpreter can only enforce this limit if the module declares a maximum that is
+    /// lower or equal, and returns [`NewErr::MemoryLimitUnenforceable`] otherwise.
+    pub max_memory_pages: Option<HeapPages>,
+}
+
+/// Resource whose limit has been reached. See [`Trap::resource_exhausted`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum ResourceExhausted {
+    /// [`ResourceLimits::max_fuel`] has been reached.
+    #[display(fmt = "Out of fuel")]
+    Fuel,
+    /// [`ResourceLimits::max_stack_depth`] has been reached.
+    #[display(fmt = "Stack depth limit reached")]
+    StackDepth,
+}
+
 /// Number of heap pages available to the Wasm code.
 ///
 /// Each page is `64kiB`.
@@ -763,8 +932,20 @@ pub enum ExecOutcome {
 
 /// Opaque error that happened during execution, such as an `unreachable` instruction.
 #[derive(Debug, derive_more::Display, Clone)]
-#[display(fmt = "{_0}")]
-pub struct Trap(String);
+#[display(fmt = "{message}")]
+pub struct Trap {
+    /// Message describing the error.
+    message: String,
+    /// If the trap is caused by a limit of [`ResourceLimits`] being reached, contains which one.
+    resource_exhausted: Option<ResourceExhausted>,
+}
+
+impl Trap {
+    /// Returns `Some` if the trap has been caused by one of the [`ResourceLimits`] being reached.
+    pub fn resource_exhausted(&self) -> Option<ResourceExhausted> {
+        self.resource_exhausted
+    }
+}
 
 /// Error that can happen when initializing a [`VirtualMachinePrototype`].
 #[derive(Debug, derive_more::Display, Clone)]
@@ -800,6 +981,20 @@ pub enum NewErr {
     CouldntAllocateMemory,
     /// The Wasm module requires importing a global or a table, which isn't supported.
     ImportTypeNotSupported,
+    /// The initial size of the memory of the Wasm module is above
+    /// [`ResourceLimits::max_memory_pages`].
+    MemoryLimitTooLow,
+    /// The Wasm module defines its own memory without declaring a maximum size lower or equal
+    /// to [`ResourceLimits::max_memory_pages`], and the execution engine is unable to enforce
+    /// this limit.
+    MemoryLimitUnenforceable,
+    /// Failed to load a precompiled module passed to [`VirtualMachinePrototype::from_precompiled`].
+    /// This typically happens if the module was compiled with a different version or
+    /// configuration of the compiler.
+    ///
+    /// Contains an opaque error message.
+    #[display(fmt = "Invalid precompiled module: {_0}")]
+    InvalidPrecompiled(String),
     /// Other error. This error is unfortunately necessary due to the underlying implementation
     /// returning an opaque error without explaining what can error.
     // TODO: remove as too imprecise?
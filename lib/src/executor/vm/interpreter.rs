@@ -18,12 +18,12 @@
 //! Implements the API documented [in the parent module](..).
 
 use super::{
-    ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, RunErr, Signature, StartErr,
-    Trap, ValueType, WasmValue,
+    ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, ResourceExhausted,
+    ResourceLimits, RunErr, Signature, StartErr, Trap, ValueType, WasmValue,
 };
 
 use alloc::{borrow::ToOwned as _, string::ToString as _, sync::Arc, vec::Vec};
-use core::fmt;
+use core::{cmp, fmt};
 
 /// See [`super::VirtualMachinePrototype`].
 pub struct InterpreterPrototype {
@@ -46,15 +46,35 @@ struct BaseComponents {
     /// For each import of the module, either `None` if not a function, or `Some` containing the
     /// `usize` of that function.
     resolved_imports: Vec<Option<usize>>,
+
+    /// Amount of fuel to add to each newly-created store. `None` if fuel metering is disabled.
+    max_fuel: Option<u64>,
+
+    /// Value of [`ResourceLimits::max_memory_pages`]. Applied to the type of the imported memory.
+    max_memory_pages: Option<HeapPages>,
 }
 
 impl InterpreterPrototype {
     /// See [`super::VirtualMachinePrototype::new`].
     pub fn new(
         module_bytes: impl AsRef<[u8]>,
+        resource_limits: &ResourceLimits,
         mut symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
     ) -> Result<Self, NewErr> {
-        let engine = wasmi::Engine::default(); // TODO: investigate config
+        let engine = {
+            let mut config = wasmi::Config::default(); // TODO: investigate config
+            config.consume_fuel(resource_limits.max_fuel.is_some());
+            if let Some(max_stack_depth) = resource_limits.max_stack_depth {
+                let max_stack_depth = usize::try_from(max_stack_depth).unwrap_or(usize::MAX);
+                // Only the maximum recursion depth is configured. The other values are the
+                // default ones of `wasmi`.
+                let stack_limits = wasmi::StackLimits::new(128, 1024 * 128, max_stack_depth)
+                    .map_err(|err| NewErr::Other(err.to_string()))?;
+                config.set_stack_limits(stack_limits);
+            }
+            wasmi::Engine::new(&config)
+        };
+
         let module = wasmi::Module::new(&engine, module_bytes.as_ref())
             .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
 
@@ -82,21 +102,52 @@ impl InterpreterPrototype {
 
                     resolved_imports.push(Some(function_index));
                 }
-                wasmi::ExternType::Memory(_) => resolved_imports.push(None),
+                wasmi::ExternType::Memory(memory_type) => {
+                    if let Some(max_memory_pages) = resource_limits.max_memory_pages {
+                        if u32::from(memory_type.initial_pages()) > max_memory_pages.0 {
+                            return Err(NewErr::MemoryLimitTooLow);
+                        }
+                    }
+                    resolved_imports.push(None)
+                }
                 wasmi::ExternType::Global(_) | wasmi::ExternType::Table(_) => {
                     return Err(NewErr::ImportTypeNotSupported)
                 }
             }
         }
 
+        // `wasmi` doesn't provide any way to limit the growth of a memory other than through its
+        // type. While the type of an imported memory can be adjusted, the type of a memory
+        // defined by the module can't, and the limit must already be enforced by the module.
+        if let Some(max_memory_pages) = resource_limits.max_memory_pages {
+            for export in module.exports() {
+                if let wasmi::ExternType::Memory(memory_type) = export.ty() {
+                    if u32::from(memory_type.initial_pages()) > max_memory_pages.0 {
+                        return Err(NewErr::MemoryLimitTooLow);
+                    }
+                    match memory_type.maximum_pages() {
+                        Some(max) if u32::from(max) <= max_memory_pages.0 => {}
+                        _ => return Err(NewErr::MemoryLimitUnenforceable),
+                    }
+                }
+            }
+        }
+
         Self::from_base_components(BaseComponents {
             module: Arc::new(module),
             resolved_imports,
+            max_fuel: resource_limits.max_fuel,
+            max_memory_pages: resource_limits.max_memory_pages,
         })
     }
 
     fn from_base_components(base_components: BaseComponents) -> Result<Self, NewErr> {
         let mut store = wasmi::Store::new(base_components.module.engine(), ());
+        if let Some(max_fuel) = base_components.max_fuel {
+            // Can only fail if fuel metering is disabled in the engine's configuration, which
+            // is never the case if `max_fuel` is `Some`.
+            store.add_fuel(max_fuel).unwrap();
+        }
 
         let mut linker = wasmi::Linker::<()>::new(&base_components.module.engine());
         let mut import_memory = None;
@@ -139,7 +190,22 @@ impl InterpreterPrototype {
                     // import has a unique name, this block can't be reached more than once.
                     debug_assert!(import_memory.is_none());
 
-                    let memory = wasmi::Memory::new(&mut store, *memory_type)
+                    // The maximum size of the memory is lowered in order to enforce
+                    // [`ResourceLimits::max_memory_pages`]. When the limit is reached,
+                    // `memory.grow` returns `-1`, as it would for any other maximum.
+                    let memory_type = match base_components.max_memory_pages {
+                        Some(max_memory_pages) => wasmi::MemoryType::new(
+                            u32::from(memory_type.initial_pages()),
+                            Some(match memory_type.maximum_pages() {
+                                Some(max) => cmp::min(u32::from(max), max_memory_pages.0),
+                                None => max_memory_pages.0,
+                            }),
+                        )
+                        .map_err(|_| NewErr::CouldntAllocateMemory)?,
+                        None => *memory_type,
+                    };
+
+                    let memory = wasmi::Memory::new(&mut store, memory_type)
                         .map_err(|_| NewErr::CouldntAllocateMemory)?;
                     import_memory = Some(memory);
 
@@ -225,6 +291,8 @@ impl Clone for InterpreterPrototype {
         InterpreterPrototype::from_base_components(BaseComponents {
             module: self.base_components.module.clone(),
             resolved_imports: self.base_components.resolved_imports.clone(),
+            max_fuel: self.base_components.max_fuel,
+            max_memory_pages: self.base_components.max_memory_pages,
         })
         .unwrap()
     }
@@ -508,9 +576,25 @@ impl Interpreter {
                 self.execution = Some(Execution::Started(next));
                 Ok(outcome)
             }
-            Err(err) => Ok(ExecOutcome::Finished {
-                return_value: Err(Trap(err.to_string())),
-            }),
+            Err(err) => {
+                let resource_exhausted = match &err {
+                    wasmi::Error::Trap(trap) => match trap.trap_code() {
+                        Some(wasmi::core::TrapCode::OutOfFuel) => Some(ResourceExhausted::Fuel),
+                        Some(wasmi::core::TrapCode::StackOverflow) => {
+                            Some(ResourceExhausted::StackDepth)
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                Ok(ExecOutcome::Finished {
+                    return_value: Err(Trap {
+                        message: err.to_string(),
+                        resource_exhausted,
+                    }),
+                })
+            }
         }
     }
 
@@ -18,13 +18,15 @@
 //! Implements the API documented [in the parent module](..).
 
 use super::{
-    ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, RunErr, Signature, StartErr,
-    Trap, ValueType, WasmValue,
+    ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, ResourceExhausted,
+    ResourceLimits, RunErr, Signature, StartErr, Trap, ValueType, WasmValue,
 };
 
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::{
-    fmt, future, mem,
+    cmp, fmt, future,
+    hash::{Hash as _, Hasher as _},
+    mem,
     pin::Pin,
     slice,
     task::{Context, Poll, Waker},
@@ -39,7 +41,7 @@ pub struct JitPrototype {
     /// Base components that can be used to recreate a prototype later if desired.
     base_components: BaseComponents,
 
-    store: wasmtime::Store<()>,
+    store: wasmtime::Store<wasmtime::StoreLimits>,
 
     /// Instantiated Wasm VM.
     instance: wasmtime::Instance,
@@ -60,14 +62,67 @@ struct BaseComponents {
     /// For each import of the module, either `None` if not a function, or `Some` containing the
     /// `usize` of that function.
     resolved_imports: Vec<Option<usize>>,
+
+    /// Amount of fuel to add to each newly-created store. `None` if fuel metering is disabled.
+    max_fuel: Option<u64>,
+
+    /// Value of [`ResourceLimits::max_memory_pages`]. Applied to the type of the imported memory
+    /// and enforced by the store for the memory defined by the module.
+    max_memory_pages: Option<HeapPages>,
 }
 
 impl JitPrototype {
     /// See [`super::VirtualMachinePrototype::new`].
     pub fn new(
         module_bytes: impl AsRef<[u8]>,
-        mut symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
+        resource_limits: &ResourceLimits,
+        symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
+    ) -> Result<Self, NewErr> {
+        let engine = Self::engine(resource_limits)?;
+        let module = wasmtime::Module::from_binary(&engine, module_bytes.as_ref())
+            .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
+        Self::from_module(module, resource_limits, symbols)
+    }
+
+    /// See [`super::VirtualMachinePrototype::from_precompiled`].
+    ///
+    /// # Safety
+    ///
+    /// See [`super::VirtualMachinePrototype::from_precompiled`].
+    pub unsafe fn from_precompiled(
+        precompiled: &[u8],
+        resource_limits: &ResourceLimits,
+        symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
     ) -> Result<Self, NewErr> {
+        let engine = Self::engine(resource_limits)?;
+        // `deserialize` verifies that the precompiled module was generated by the same version
+        // of `wasmtime`, with the same configuration, and for the same CPU features.
+        let module = wasmtime::Module::deserialize(&engine, precompiled)
+            .map_err(|err| NewErr::InvalidPrecompiled(err.to_string()))?;
+        Self::from_module(module, resource_limits, symbols)
+    }
+
+    /// See [`super::VirtualMachinePrototype::precompiled_engine_key`].
+    pub fn precompiled_engine_key(resource_limits: &ResourceLimits) -> u64 {
+        // The key is derived from the inputs of [`JitPrototype::engine`] rather than from the
+        // engine itself, as building an engine is expensive. The version of `wasmtime` and the
+        // CPU features aren't part of the key, as `Module::deserialize` already verifies them.
+        // Increase this value whenever the configuration built by `engine` is modified.
+        const ENGINE_CONFIG_VERSION: u32 = 1;
+
+        let mut hasher = fnv::FnvHasher::default();
+        ENGINE_CONFIG_VERSION.hash(&mut hasher);
+        resource_limits.max_fuel.is_some().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// See [`super::VirtualMachinePrototype::serialize_precompiled`].
+    pub fn serialize_precompiled(&self) -> Option<Vec<u8>> {
+        self.base_components.module.serialize().ok()
+    }
+
+    /// Builds the [`wasmtime::Engine`] used to compile modules.
+    fn engine(resource_limits: &ResourceLimits) -> Result<wasmtime::Engine, NewErr> {
         let mut config = wasmtime::Config::new();
         config.cranelift_nan_canonicalization(true);
         config.cranelift_opt_level(wasmtime::OptLevel::Speed);
@@ -78,12 +133,15 @@ impl JitPrototype {
         // environment variables whatsoever. Whether to use `Enable` or `Disable` below isn't
         // very important, so long as it is not `Environment`.
         config.wasm_backtrace_details(wasmtime::WasmBacktraceDetails::Enable);
-        let engine =
-            wasmtime::Engine::new(&config).map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
-
-        let module = wasmtime::Module::from_binary(&engine, module_bytes.as_ref())
-            .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
+        config.consume_fuel(resource_limits.max_fuel.is_some());
+        wasmtime::Engine::new(&config).map_err(|err| NewErr::InvalidWasm(err.to_string()))
+    }
 
+    fn from_module(
+        module: wasmtime::Module,
+        resource_limits: &ResourceLimits,
+        mut symbols: impl FnMut(&str, &str, &Signature) -> Result<usize, ()>,
+    ) -> Result<Self, NewErr> {
         // Building the list of imports that the Wasm VM is able to use.
         let resolved_imports = {
             let mut imports = Vec::with_capacity(module.imports().len());
@@ -113,7 +171,10 @@ impl JitPrototype {
                     wasmtime::ExternType::Global(_) | wasmtime::ExternType::Table(_) => {
                         return Err(NewErr::ImportTypeNotSupported);
                     }
-                    wasmtime::ExternType::Memory(_) => {
+                    wasmtime::ExternType::Memory(m) => {
+                        if max_memory_pages_exceeded(&m, resource_limits) {
+                            return Err(NewErr::MemoryLimitTooLow);
+                        }
                         imports.push(None);
                     }
                 };
@@ -121,14 +182,41 @@ impl JitPrototype {
             imports
         };
 
+        for export in module.exports() {
+            if let wasmtime::ExternType::Memory(m) = export.ty() {
+                if max_memory_pages_exceeded(&m, resource_limits) {
+                    return Err(NewErr::MemoryLimitTooLow);
+                }
+            }
+        }
+
         Self::from_base_components(BaseComponents {
             module,
             resolved_imports,
+            max_fuel: resource_limits.max_fuel,
+            max_memory_pages: resource_limits.max_memory_pages,
         })
     }
 
     fn from_base_components(base_components: BaseComponents) -> Result<Self, NewErr> {
-        let mut store = wasmtime::Store::new(base_components.module.engine(), ());
+        // The store refuses to grow any memory beyond [`ResourceLimits::max_memory_pages`], in
+        // which case `memory.grow` returns `-1`.
+        let store_limits = match base_components.max_memory_pages {
+            Some(max_memory_pages) => wasmtime::StoreLimitsBuilder::new()
+                .memory_size(
+                    usize::try_from(u64::from(max_memory_pages.0) * 64 * 1024)
+                        .unwrap_or(usize::MAX),
+                )
+                .build(),
+            None => wasmtime::StoreLimits::default(),
+        };
+        let mut store = wasmtime::Store::new(base_components.module.engine(), store_limits);
+        store.limiter(|store_limits| store_limits);
+        if let Some(max_fuel) = base_components.max_fuel {
+            // Can only fail if fuel metering is disabled in the engine's configuration, which
+            // is never the case if `max_fuel` is `Some`.
+            store.add_fuel(max_fuel).unwrap();
+        }
 
         let mut imported_memory = None;
         let shared = Arc::new(Mutex::new(Shared::ExecutingStart));
@@ -259,6 +347,22 @@ impl JitPrototype {
                         // Considering that the memory can only be "env":"memory", and that each
                         // import has a unique name, this block can't be reached more than once.
                         debug_assert!(imported_memory.is_none());
+                        // The maximum size of the imported memory is also lowered, so that
+                        // [`JitPrototype::memory_max_pages`] reports the limit.
+                        let m = match base_components.max_memory_pages {
+                            Some(max_memory_pages) => wasmtime::MemoryType::new(
+                                u32::try_from(m.minimum()).unwrap_or(u32::MAX),
+                                Some(match m.maximum() {
+                                    Some(max) => cmp::min(
+                                        u32::try_from(max).unwrap_or(u32::MAX),
+                                        max_memory_pages.0,
+                                    ),
+                                    None => max_memory_pages.0,
+                                }),
+                            ),
+                            None => m,
+                        };
+
                         imported_memory = Some(
                             wasmtime::Memory::new(&mut store, m)
                                 .map_err(|_| NewErr::CouldntAllocateMemory)?,
@@ -330,11 +434,16 @@ impl JitPrototype {
 
     /// See [`super::VirtualMachinePrototype::memory_max_pages`].
     pub fn memory_max_pages(&self) -> Option<HeapPages> {
-        let num = self.memory.ty(&self.store).maximum()?;
-        match u32::try_from(num) {
-            Ok(n) => Some(HeapPages::new(n)),
-            // If `num` doesn't fit in a `u32`, we return `None` to mean "infinite".
-            Err(_) => None,
+        let declared = match self.memory.ty(&self.store).maximum() {
+            // If `num` doesn't fit in a `u32`, we use `None` to mean "infinite".
+            Some(num) => u32::try_from(num).ok().map(HeapPages::new),
+            None => None,
+        };
+
+        // The memory defined by the module is limited by the store rather than by its type.
+        match (declared, self.base_components.max_memory_pages) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b) => a.or(b),
         }
     }
 
@@ -354,11 +463,25 @@ impl Clone for JitPrototype {
         JitPrototype::from_base_components(BaseComponents {
             module: self.base_components.module.clone(),
             resolved_imports: self.base_components.resolved_imports.clone(),
+            max_fuel: self.base_components.max_fuel,
+            max_memory_pages: self.base_components.max_memory_pages,
         })
         .unwrap()
     }
 }
 
+/// Returns `true` if the initial size of a memory of the given type is above
+/// [`ResourceLimits::max_memory_pages`].
+fn max_memory_pages_exceeded(
+    memory_type: &wasmtime::MemoryType,
+    resource_limits: &ResourceLimits,
+) -> bool {
+    match resource_limits.max_memory_pages {
+        Some(max_memory_pages) => memory_type.minimum() > u64::from(max_memory_pages.0),
+        None => false,
+    }
+}
+
 impl fmt::Debug for JitPrototype {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("JitPrototype").finish()
@@ -585,7 +708,7 @@ enum JitInner {
 
     /// Execution has not started yet.
     NotStarted {
-        store: wasmtime::Store<()>,
+        store: wasmtime::Store<wasmtime::StoreLimits>,
         function_to_call: wasmtime::Func,
         params: Vec<wasmtime::Val>,
     },
@@ -595,7 +718,7 @@ enum JitInner {
             Box<
                 dyn future::Future<
                         Output = (
-                            wasmtime::Store<()>,
+                            wasmtime::Store<wasmtime::StoreLimits>,
                             Result<Option<WasmValue>, wasmtime::Error>,
                         ),
                     > + Send,
@@ -603,7 +726,7 @@ enum JitInner {
         >,
     ),
     /// Execution has finished because the future has returned `Poll::Ready` in the past.
-    Done(wasmtime::Store<()>),
+    Done(wasmtime::Store<wasmtime::StoreLimits>),
 }
 
 impl Jit {
@@ -735,8 +858,16 @@ impl Jit {
             }
             Poll::Ready((store, Err(err))) => {
                 self.inner = JitInner::Done(store);
+                let resource_exhausted = match err.downcast_ref::<wasmtime::Trap>() {
+                    Some(wasmtime::Trap::OutOfFuel) => Some(ResourceExhausted::Fuel),
+                    _ => None,
+                };
+
                 Ok(ExecOutcome::Finished {
-                    return_value: Err(Trap(err.to_string())),
+                    return_value: Err(Trap {
+                        message: err.to_string(),
+                        resource_exhausted,
+                    }),
                 })
             }
             Poll::Pending => {
@@ -34,6 +34,7 @@ fn basic_seems_to_work() {
         let prototype = super::VirtualMachinePrototype::new(
             &include_bytes!("./test-polkadot-runtime-v9160.wasm")[..],
             exec_hint,
+            Default::default(),
             |_, _, _| Ok(0),
         )
         .unwrap();
@@ -73,7 +74,13 @@ fn out_of_memory_access() {
             0x06, 0x01, 0x00, 0x41, 0x03, 0x0b, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(super::VirtualMachinePrototype::new(
+            input,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0)
+        )
+        .is_err());
     }
 }
 
@@ -85,7 +92,13 @@ fn has_start_function() {
             0x02, 0x09, 0x01, 0x01, 0x71, 0x03, 0x69, 0x6d, 0x70, 0x00, 0x00, 0x08, 0x01, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(super::VirtualMachinePrototype::new(
+            input,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0)
+        )
+        .is_err());
     }
 }
 
@@ -98,7 +111,13 @@ fn unsupported_type() {
             0x00, 0x00,
         ];
 
-        assert!(super::VirtualMachinePrototype::new(input, exec_hint, |_, _, _| Ok(0)).is_err());
+        assert!(super::VirtualMachinePrototype::new(
+            input,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0)
+        )
+        .is_err());
     }
 }
 
@@ -120,8 +139,13 @@ fn basic_host_function_return_value_works() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -164,7 +188,12 @@ fn no_memory() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::NoMemory)
         ));
     }
@@ -187,7 +216,12 @@ fn memory_misnamed() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::MemoryNotNamedMemory)
         ));
     }
@@ -214,7 +248,12 @@ fn two_memories() {
         // multi-memory Wasm proposal isn't finalized yet. Even once finalized, we want to deny
         // this feature in smoldot.
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::InvalidWasm(_) | super::NewErr::TwoMemories)
         ));
     }
@@ -236,7 +275,13 @@ fn exported_memory_works() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
     }
 }
 
@@ -257,7 +302,12 @@ fn unresolved_function() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Err(())),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Err(())
+            ),
             Err(super::NewErr::UnresolvedFunctionImport { .. })
         ));
     }
@@ -278,7 +328,12 @@ fn unsupported_signature() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::UnresolvedFunctionImport { .. })
         ));
     }
@@ -299,7 +354,12 @@ fn unsupported_import_type() {
 
     for exec_hint in super::ExecHint::available_engines() {
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::ImportTypeNotSupported)
         ));
     }
@@ -321,7 +381,12 @@ fn start_function_forbidden() {
     for exec_hint in super::ExecHint::available_engines() {
         // TODO: `Ok(_)` shouldn't be accepted, but wasmtime doesn't really make it possible to detect the start function at the moment
         assert!(matches!(
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)),
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                Default::default(),
+                |_, _, _| Ok(0)
+            ),
             Err(super::NewErr::StartFunctionNotSupported) | Ok(_)
         ));
     }
@@ -340,8 +405,13 @@ fn max_memory_pages() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert_eq!(
             prototype.memory_max_pages().unwrap(),
             super::HeapPages::new(4096)
@@ -362,8 +432,13 @@ fn get_global() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let mut prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let mut prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert_eq!(prototype.global_value("test").unwrap(), 12);
     }
 }
@@ -381,8 +456,13 @@ fn call_non_existing_function() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert!(matches!(
             prototype.prepare().start("doesntexist", &[]),
             Err((super::StartErr::FunctionNotFound, _))
@@ -403,8 +483,13 @@ fn call_signature_not_supported() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             Err((super::StartErr::SignatureNotSupported, _))
@@ -425,8 +510,13 @@ fn bad_params_types() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             Err((super::StartErr::InvalidParameters, _))
@@ -447,8 +537,13 @@ fn try_to_call_global() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert!(matches!(
             prototype.prepare().start("hello", &[]),
             // TODO: wasmi doesn't properly detect NotAFunction at the moment
@@ -476,8 +571,13 @@ fn wrong_type_provided_initially() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -504,8 +604,13 @@ fn wrong_type_returned_by_host_function_call() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -530,8 +635,13 @@ fn memory_min_specified_in_wasm() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         let interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
     }
@@ -550,8 +660,13 @@ fn memory_grow_works() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         let mut interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
         interpreter.grow_memory(super::HeapPages::new(3)).unwrap();
@@ -572,8 +687,13 @@ fn memory_grow_detects_limit() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         let mut interpreter = prototype.prepare().start("hello", &[]).unwrap();
         assert_eq!(interpreter.memory_size(), super::HeapPages::new(16));
         assert!(interpreter.grow_memory(super::HeapPages::new(10)).is_err());
@@ -596,8 +716,13 @@ fn memory_grow_detects_limit_within_host_function() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
 
@@ -643,8 +768,13 @@ fn globals_reinitialized_after_reset() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let mut prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let mut prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
         assert_eq!(prototype.global_value("myglob").unwrap(), 5);
 
         let mut vm = prototype.prepare().start("hello", &[]).unwrap();
@@ -673,8 +803,13 @@ fn memory_zeroed_after_reset() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare();
         vm.write_memory(11, &[5, 6]).unwrap();
@@ -717,8 +852,13 @@ fn memory_zeroed_after_prepare() {
     .unwrap();
 
     for exec_hint in super::ExecHint::available_engines() {
-        let prototype =
-            super::VirtualMachinePrototype::new(&module_bytes, exec_hint, |_, _, _| Ok(0)).unwrap();
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
 
         let mut vm = prototype.prepare();
         assert_eq!(vm.read_memory(11, 2).unwrap().as_ref(), &[0, 0]);
@@ -729,4 +869,274 @@ fn memory_zeroed_after_prepare() {
     }
 }
 
+#[test]
+fn fuel_exhausted() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 0 4096))
+            (func (export "hello")
+                (loop $loop
+                    (br $loop)))
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            super::ResourceLimits {
+                max_fuel: Some(100_000),
+                ..Default::default()
+            },
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
+
+        let mut vm = prototype.prepare().start("hello", &[]).unwrap();
+        match vm.run(None) {
+            Ok(super::ExecOutcome::Finished {
+                return_value: Err(trap),
+            }) => {
+                assert_eq!(
+                    trap.resource_exhausted(),
+                    Some(super::ResourceExhausted::Fuel)
+                );
+            }
+            _ => panic!(),
+        }
+    }
+}
+
+#[test]
+fn stack_depth_exhausted() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 0 4096))
+            (func $recurse (export "hello") (param i32) (result i32)
+                (if (result i32) (i32.eqz (local.get 0))
+                    (then (i32.const 0))
+                    (else (call $recurse (i32.sub (local.get 0) (i32.const 1))))))
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            super::ResourceLimits {
+                max_stack_depth: Some(64),
+                ..Default::default()
+            },
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
+
+        // Recursing below the limit succeeds.
+        let mut vm = prototype
+            .clone()
+            .prepare()
+            .start("hello", &[super::WasmValue::I32(32)])
+            .unwrap();
+        assert!(matches!(
+            vm.run(None),
+            Ok(super::ExecOutcome::Finished {
+                return_value: Ok(Some(super::WasmValue::I32(0)))
+            })
+        ));
+
+        let mut vm = prototype
+            .prepare()
+            .start("hello", &[super::WasmValue::I32(128)])
+            .unwrap();
+        match vm.run(None) {
+            Ok(super::ExecOutcome::Finished {
+                return_value: Err(trap),
+            }) => {
+                assert_eq!(
+                    trap.resource_exhausted(),
+                    Some(super::ResourceExhausted::StackDepth)
+                );
+            }
+            _ => panic!(),
+        }
+    }
+}
+
+#[test]
+fn memory_limit_enforced_by_memory_grow() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 2 4096))
+            (func (export "hello") (param i32) (result i32)
+                (memory.grow (local.get 0)))
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            super::ResourceLimits {
+                max_memory_pages: Some(super::HeapPages::new(8)),
+                ..Default::default()
+            },
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
+        assert_eq!(prototype.memory_max_pages(), Some(super::HeapPages::new(8)));
+
+        // Growing up to the limit succeeds and returns the previous size.
+        let mut vm = prototype
+            .prepare()
+            .start("hello", &[super::WasmValue::I32(6)])
+            .unwrap();
+        assert!(matches!(
+            vm.run(None),
+            Ok(super::ExecOutcome::Finished {
+                return_value: Ok(Some(super::WasmValue::I32(2)))
+            })
+        ));
+
+        // Growing beyond the limit fails without trapping.
+        let mut vm = vm
+            .into_prototype()
+            .prepare()
+            .start("hello", &[super::WasmValue::I32(7)])
+            .unwrap();
+        assert!(matches!(
+            vm.run(None),
+            Ok(super::ExecOutcome::Finished {
+                return_value: Ok(Some(super::WasmValue::I32(-1)))
+            })
+        ));
+    }
+}
+
+#[test]
+fn memory_limit_module_defined_memory() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (memory (export "memory") 2)
+            (func (export "hello") (param i32) (result i32)
+                (memory.grow (local.get 0)))
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let result = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            super::ResourceLimits {
+                max_memory_pages: Some(super::HeapPages::new(8)),
+                ..Default::default()
+            },
+            |_, _, _| Ok(0),
+        );
+
+        // The interpreter can't enforce a limit on a memory without a declared maximum.
+        let prototype = match result {
+            Ok(prototype) => prototype,
+            Err(super::NewErr::MemoryLimitUnenforceable) => continue,
+            Err(_) => panic!(),
+        };
+        assert_eq!(prototype.memory_max_pages(), Some(super::HeapPages::new(8)));
+
+        let mut vm = prototype
+            .prepare()
+            .start("hello", &[super::WasmValue::I32(7)])
+            .unwrap();
+        assert!(matches!(
+            vm.run(None),
+            Ok(super::ExecOutcome::Finished {
+                return_value: Ok(Some(super::WasmValue::I32(-1)))
+            })
+        ));
+    }
+}
+
+#[test]
+fn memory_limit_below_initial_size() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 16 4096))
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        assert!(matches!(
+            super::VirtualMachinePrototype::new(
+                &module_bytes,
+                exec_hint,
+                super::ResourceLimits {
+                    max_memory_pages: Some(super::HeapPages::new(8)),
+                    ..Default::default()
+                },
+                |_, _, _| Ok(0)
+            ),
+            Err(super::NewErr::MemoryLimitTooLow)
+        ));
+    }
+}
+
+#[test]
+fn precompiled_round_trip() {
+    let module_bytes = wat::parse_str(
+        r#"
+        (module
+            (import "env" "memory" (memory $mem 0 4096))
+            (func (export "hello") (result i32)
+                i32.const 42)
+        )
+        "#,
+    )
+    .unwrap();
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let prototype = super::VirtualMachinePrototype::new(
+            &module_bytes,
+            exec_hint,
+            Default::default(),
+            |_, _, _| Ok(0),
+        )
+        .unwrap();
+
+        // Only the engines that compile ahead of time support precompiled modules.
+        let Some(precompiled) = prototype.serialize_precompiled() else {
+            continue;
+        };
+
+        let prototype = unsafe {
+            super::VirtualMachinePrototype::from_precompiled(
+                &precompiled,
+                Default::default(),
+                |_, _, _| Ok(0),
+            )
+        }
+        .unwrap();
+
+        let mut vm = prototype.prepare().start("hello", &[]).unwrap();
+        assert!(matches!(
+            vm.run(None),
+            Ok(super::ExecOutcome::Finished {
+                return_value: Ok(Some(super::WasmValue::I32(42)))
+            })
+        ));
+    }
+}
+
 // TODO: test for memory reads and writes, including within host functions
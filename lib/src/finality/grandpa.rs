@@ -16,4 +16,6 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 pub mod commit;
+pub mod finality_proof;
+pub mod round_state;
 pub mod warp_sync;
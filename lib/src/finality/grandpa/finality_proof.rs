@@ -0,0 +1,61 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Finality proofs.
+//!
+//! A finality proof proves that a certain block is finalized. It consists of a GrandPa
+//! justification targeting either this block or one of its descendants, plus the headers of the
+//! blocks between the block to prove and the block targeted by the justification.
+//!
+//! This is the format returned by the `grandpa_proveFinality` JSON-RPC function.
+
+use crate::util;
+
+use alloc::vec::Vec;
+
+/// Builds the SCALE encoding of a finality proof.
+///
+/// `justified_block_hash` must be the hash of the block targeted by `justification`, and
+/// `scale_encoded_headers` the SCALE-encoded headers of the blocks whose finality is proven, in
+/// increasing order and up to and including the block targeted by the justification.
+pub fn encode<'a>(
+    justified_block_hash: &[u8; 32],
+    justification: &[u8],
+    scale_encoded_headers: impl ExactSizeIterator<Item = &'a [u8]>,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(32 + 5 + justification.len() + 5);
+    out.extend_from_slice(justified_block_hash);
+    out.extend_from_slice(util::encode_scale_compact_usize(justification.len()).as_ref());
+    out.extend_from_slice(justification);
+    out.extend_from_slice(util::encode_scale_compact_usize(scale_encoded_headers.len()).as_ref());
+    for header in scale_encoded_headers {
+        out.extend_from_slice(header);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn encode_basic() {
+        let encoded = super::encode(&[0xaa; 32], &[1, 2, 3], [&[4, 5][..], &[6][..]].into_iter());
+
+        let mut expected = vec![0xaa; 32];
+        expected.extend_from_slice(&[3 << 2, 1, 2, 3, 2 << 2, 4, 5, 6]);
+        assert_eq!(encoded, expected);
+    }
+}
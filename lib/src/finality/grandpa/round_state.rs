@@ -0,0 +1,398 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of the votes of the latest GrandPa round.
+//!
+//! GrandPa finalizes blocks in rounds. During each round, the authorities first emit *prevotes*,
+//! then *precommits*, and these votes are gossiped on the peer-to-peer network. A block is
+//! finalized once the authorities that have precommitted for it represent at least the
+//! threshold weight of the authorities set, which is more than two thirds of its total weight.
+//!
+//! The [`RoundState`] struct holds which authorities have emitted a prevote and a precommit
+//! during the latest known round of an authorities set. Votes are injected using
+//! [`RoundState::inject_vote`], which verifies their signature.
+//!
+//! > **Note**: This module is meant to be used for diagnostic purposes. The votes aren't used
+//! >           to finalize blocks.
+
+use crate::header;
+
+use alloc::vec::Vec;
+use core::{cmp, iter, mem, num::NonZeroU64};
+
+/// Configuration for a new [`RoundState`].
+#[derive(Debug)]
+pub struct Config<TAuth> {
+    /// Number of bytes used to encode the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Identifier of the authorities set whose votes are tracked.
+    pub set_id: u64,
+
+    /// List of authorities of the set whose votes are tracked.
+    pub authorities: TAuth,
+}
+
+/// Votes of the latest known round of a GrandPa authorities set.
+#[derive(Debug, Clone)]
+pub struct RoundState {
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::set_id`].
+    set_id: u64,
+
+    /// Number of the latest known round.
+    round_number: u64,
+
+    /// List of authorities of the set, and whether they have voted during
+    /// [`RoundState::round_number`].
+    authorities: Vec<Authority>,
+}
+
+#[derive(Debug, Clone)]
+struct Authority {
+    public_key: [u8; 32],
+    weight: NonZeroU64,
+    prevoted: bool,
+    precommitted: bool,
+}
+
+impl RoundState {
+    /// Initializes a new [`RoundState`]. The current round is the first round of the set, and no
+    /// vote has been received yet.
+    pub fn new<'a>(config: Config<impl Iterator<Item = header::GrandpaAuthorityRef<'a>>>) -> Self {
+        RoundState {
+            block_number_bytes: config.block_number_bytes,
+            set_id: config.set_id,
+            round_number: 1,
+            authorities: config
+                .authorities
+                .map(|authority| Authority {
+                    public_key: *authority.public_key,
+                    weight: authority.weight,
+                    prevoted: false,
+                    precommitted: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the value that was passed as [`Config::set_id`].
+    pub fn set_id(&self) -> u64 {
+        self.set_id
+    }
+
+    /// Returns the number of the latest known round.
+    pub fn round_number(&self) -> u64 {
+        self.round_number
+    }
+
+    /// Switches to the given round if it is more recent than the current one, and forgets all
+    /// the votes of the current round. Does nothing if the round isn't more recent.
+    ///
+    /// This is typically called when a peer reports, through a neighbor packet, that it is at a
+    /// more recent round.
+    pub fn set_round_number(&mut self, round_number: u64) {
+        if round_number <= self.round_number {
+            return;
+        }
+
+        self.round_number = round_number;
+        for authority in &mut self.authorities {
+            authority.prevoted = false;
+            authority.precommitted = false;
+        }
+    }
+
+    /// Verifies the given vote and, if it is valid, adds it to the state.
+    ///
+    /// If the vote concerns a more recent round than the current one, the state switches to this
+    /// round. See [`RoundState::set_round_number`].
+    pub fn inject_vote(&mut self, vote: Vote) -> Result<(), InjectVoteError> {
+        if vote.set_id != self.set_id {
+            return Err(InjectVoteError::SetIdMismatch);
+        }
+
+        if vote.round_number < self.round_number {
+            return Err(InjectVoteError::ObsoleteRound);
+        }
+
+        let authority_index = self
+            .authorities
+            .iter()
+            .position(|a| a.public_key == *vote.authority_public_key)
+            .ok_or(InjectVoteError::NotAuthority)?;
+
+        let mut msg = Vec::with_capacity(1 + 32 + self.block_number_bytes + 8 + 8);
+        msg.push(match vote.ty {
+            VoteTy::Prevote => 0u8,
+            VoteTy::Precommit => 1u8,
+        });
+        msg.extend_from_slice(&vote.target_hash[..]);
+        // The message contains the little endian block number, padded with 0s if necessary.
+        msg.extend_from_slice(
+            &vote.target_number.to_le_bytes()[..cmp::min(
+                mem::size_of_val(&vote.target_number),
+                self.block_number_bytes,
+            )],
+        );
+        msg.extend(iter::repeat_n(
+            0,
+            self.block_number_bytes
+                .saturating_sub(mem::size_of_val(&vote.target_number)),
+        ));
+        msg.extend_from_slice(&u64::to_le_bytes(vote.round_number)[..]);
+        msg.extend_from_slice(&u64::to_le_bytes(vote.set_id)[..]);
+
+        ed25519_zebra::VerificationKey::try_from(*vote.authority_public_key)
+            .and_then(|key| key.verify(&ed25519_zebra::Signature::from(*vote.signature), &msg))
+            .map_err(|_| InjectVoteError::BadSignature)?;
+
+        // The state is modified only after the vote has been verified.
+        self.set_round_number(vote.round_number);
+        let authority = &mut self.authorities[authority_index];
+        match vote.ty {
+            VoteTy::Prevote => authority.prevoted = true,
+            VoteTy::Precommit => authority.precommitted = true,
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sum of the weights of all the authorities of the set.
+    pub fn total_weight(&self) -> u64 {
+        self.authorities
+            .iter()
+            .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()))
+    }
+
+    /// Returns the weight that the votes must reach in order for the round to make progress.
+    pub fn threshold_weight(&self) -> u64 {
+        let total_weight = self.total_weight();
+        let faulty_weight = total_weight.saturating_sub(1) / 3;
+        total_weight - faulty_weight
+    }
+
+    /// Returns the sum of the weights of the authorities that have emitted a prevote during the
+    /// current round.
+    pub fn prevotes_weight(&self) -> u64 {
+        self.authorities
+            .iter()
+            .filter(|a| a.prevoted)
+            .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()))
+    }
+
+    /// Returns the sum of the weights of the authorities that have emitted a precommit during
+    /// the current round.
+    pub fn precommits_weight(&self) -> u64 {
+        self.authorities
+            .iter()
+            .filter(|a| a.precommitted)
+            .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()))
+    }
+
+    /// Returns the public keys of the authorities that haven't emitted a prevote during the
+    /// current round.
+    pub fn missing_prevotes(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        self.authorities
+            .iter()
+            .filter(|a| !a.prevoted)
+            .map(|a| &a.public_key)
+    }
+
+    /// Returns the public keys of the authorities that haven't emitted a precommit during the
+    /// current round.
+    pub fn missing_precommits(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        self.authorities
+            .iter()
+            .filter(|a| !a.precommitted)
+            .map(|a| &a.public_key)
+    }
+}
+
+/// Vote emitted by an authority. See [`RoundState::inject_vote`].
+#[derive(Debug, Clone)]
+pub struct Vote<'a> {
+    /// Identifier of the authorities set the vote belongs to.
+    pub set_id: u64,
+    /// Round the vote belongs to.
+    pub round_number: u64,
+    /// Whether the vote is a prevote or a precommit.
+    pub ty: VoteTy,
+    /// Hash of the block concerned by the vote.
+    pub target_hash: &'a [u8; 32],
+    /// Height of the block concerned by the vote.
+    pub target_number: u64,
+    /// Ed25519 signature made with [`Vote::authority_public_key`].
+    pub signature: &'a [u8; 64],
+    /// Authority that emitted the vote.
+    pub authority_public_key: &'a [u8; 32],
+}
+
+/// Type of a [`Vote`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VoteTy {
+    Prevote,
+    Precommit,
+}
+
+/// Error potentially returned by [`RoundState::inject_vote`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum InjectVoteError {
+    /// The vote concerns a different authorities set.
+    #[display(fmt = "Vote concerns a different authorities set")]
+    SetIdMismatch,
+    /// The vote concerns a round older than the current one.
+    #[display(fmt = "Vote concerns an obsolete round")]
+    ObsoleteRound,
+    /// The vote has been emitted by a public key that isn't part of the authorities set.
+    #[display(fmt = "Vote emitted by a non-authority")]
+    NotAuthority,
+    /// The signature of the vote is invalid.
+    #[display(fmt = "Invalid vote signature")]
+    BadSignature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, InjectVoteError, RoundState, Vote, VoteTy};
+    use crate::header;
+    use core::num::NonZeroU64;
+
+    fn sign(key: &ed25519_zebra::SigningKey, ty: u8, round_number: u64, set_id: u64) -> [u8; 64] {
+        let mut msg = vec![ty];
+        msg.extend_from_slice(&[0xaa; 32]);
+        msg.extend_from_slice(&5u32.to_le_bytes());
+        msg.extend_from_slice(&round_number.to_le_bytes());
+        msg.extend_from_slice(&set_id.to_le_bytes());
+        key.sign(&msg).into()
+    }
+
+    fn round_state(keys: &[ed25519_zebra::SigningKey]) -> RoundState {
+        let public_keys = keys
+            .iter()
+            .map(|k| <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(k)))
+            .collect::<Vec<_>>();
+        RoundState::new(Config {
+            block_number_bytes: 4,
+            set_id: 3,
+            authorities: public_keys
+                .iter()
+                .map(|public_key| header::GrandpaAuthorityRef {
+                    public_key,
+                    weight: NonZeroU64::new(1).unwrap(),
+                }),
+        })
+    }
+
+    #[test]
+    fn votes_weights() {
+        let keys = (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+        let mut state = round_state(&keys);
+        assert_eq!(state.total_weight(), 4);
+        assert_eq!(state.threshold_weight(), 3);
+
+        let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(&keys[0]));
+        let signature = sign(&keys[0], 0, 1, 3);
+        state
+            .inject_vote(Vote {
+                set_id: 3,
+                round_number: 1,
+                ty: VoteTy::Prevote,
+                target_hash: &[0xaa; 32],
+                target_number: 5,
+                signature: &signature,
+                authority_public_key: &public_key,
+            })
+            .unwrap();
+
+        assert_eq!(state.prevotes_weight(), 1);
+        assert_eq!(state.precommits_weight(), 0);
+        assert_eq!(state.missing_prevotes().count(), 3);
+        assert_eq!(state.missing_precommits().count(), 4);
+    }
+
+    #[test]
+    fn new_round_resets_votes() {
+        let keys = (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+        let mut state = round_state(&keys);
+
+        for (round_number, key) in [(1, &keys[0]), (2, &keys[1])] {
+            let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key));
+            let signature = sign(key, 1, round_number, 3);
+            state
+                .inject_vote(Vote {
+                    set_id: 3,
+                    round_number,
+                    ty: VoteTy::Precommit,
+                    target_hash: &[0xaa; 32],
+                    target_number: 5,
+                    signature: &signature,
+                    authority_public_key: &public_key,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(state.round_number(), 2);
+        assert_eq!(state.precommits_weight(), 1);
+
+        let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(&keys[2]));
+        let signature = sign(&keys[2], 1, 1, 3);
+        assert!(matches!(
+            state.inject_vote(Vote {
+                set_id: 3,
+                round_number: 1,
+                ty: VoteTy::Precommit,
+                target_hash: &[0xaa; 32],
+                target_number: 5,
+                signature: &signature,
+                authority_public_key: &public_key,
+            }),
+            Err(InjectVoteError::ObsoleteRound)
+        ));
+    }
+
+    #[test]
+    fn bad_signature_refused() {
+        let keys = (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+        let mut state = round_state(&keys);
+
+        // Signature of a prevote used for a precommit.
+        let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(&keys[0]));
+        let signature = sign(&keys[0], 0, 1, 3);
+        assert!(matches!(
+            state.inject_vote(Vote {
+                set_id: 3,
+                round_number: 1,
+                ty: VoteTy::Precommit,
+                target_hash: &[0xaa; 32],
+                target_number: 5,
+                signature: &signature,
+                authority_public_key: &public_key,
+            }),
+            Err(InjectVoteError::BadSignature)
+        ));
+        assert_eq!(state.precommits_weight(), 0);
+    }
+}
@@ -62,6 +62,7 @@
 // TODO: write docs about usage ^
 
 pub mod methods;
+pub mod network_events;
 pub mod parse;
 pub mod payment_info;
 pub mod requests_subscriptions;
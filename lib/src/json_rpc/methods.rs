@@ -385,7 +385,11 @@ define_methods! {
     childstate_getStorage() -> (), // TODO:
     childstate_getStorageHash() -> (), // TODO:
     childstate_getStorageSize() -> (), // TODO:
-    grandpa_roundState() -> (), // TODO:
+    /// Returns a SCALE-encoded finality proof of the given block, made of a GrandPa
+    /// justification of one of its descendants and of the headers between the two blocks.
+    grandpa_proveFinality(block_number: u64) -> Option<HexString>,
+    /// Returns the state of the current GrandPa round, as gathered from the gossip.
+    grandpa_roundState() -> GrandpaRoundStates,
     offchain_localStorageGet() -> (), // TODO:
     offchain_localStorageSet() -> (), // TODO:
     payment_queryInfo(extrinsic: HexString, hash: Option<HashHexString>) -> RuntimeDispatchInfo,
@@ -396,33 +400,35 @@ define_methods! {
     state_getKeysPaged(prefix: Option<HexString>, count: u32, start_key: Option<HexString>, hash: Option<HashHexString>) -> Vec<HexString> [state_getKeysPagedAt],
     state_getMetadata(hash: Option<HashHexString>) -> HexString,
     state_getPairs() -> (), // TODO:
-    state_getReadProof() -> (), // TODO:
+    state_getReadProof(keys: Vec<HexString>, at: Option<HashHexString>) -> ReadProof,
     state_getRuntimeVersion(at: Option<HashHexString>) -> RuntimeVersion<'a> [chain_getRuntimeVersion],
     state_getStorage(key: HexString, hash: Option<HashHexString>) -> HexString [state_getStorageAt],
     state_getStorageHash() -> () [state_getStorageHashAt], // TODO:
     state_getStorageSize() -> () [state_getStorageSizeAt], // TODO:
-    state_queryStorage() -> (), // TODO:
+    state_queryStorage(keys: Vec<HexString>, #[rename = "fromBlock"] from_block: HashHexString, #[rename = "toBlock"] to_block: Option<HashHexString>) -> Vec<StorageChangeSet>,
     state_queryStorageAt(keys: Vec<HexString>, at: Option<HashHexString>) -> Vec<StorageChangeSet>, // TODO:
     state_subscribeRuntimeVersion() -> Cow<'a, str> [chain_subscribeRuntimeVersion],
     state_subscribeStorage(list: Vec<HexString>) -> Cow<'a, str>,
     state_unsubscribeRuntimeVersion(subscription: Cow<'a, str>) -> bool [chain_unsubscribeRuntimeVersion],
     state_unsubscribeStorage(subscription: Cow<'a, str>) -> bool,
     system_accountNextIndex(account: AccountId) -> u64,
-    system_addReservedPeer() -> (), // TODO:
+    /// Adds a peer, as a multiaddress ending with `/p2p/<peer id>`, to the reserved peers.
+    system_addReservedPeer(peer: Cow<'a, str>) -> (),
     system_chain() -> Cow<'a, str>,
     system_chainType() -> Cow<'a, str>,
-    system_dryRun() -> () [system_dryRunAt], // TODO:
+    system_dryRun(extrinsic: HexString, hash: Option<HashHexString>) -> HexString [system_dryRunAt],
     system_health() -> SystemHealth,
     system_localListenAddresses() -> Vec<String>,
     /// Returns the Base58 encoding of the network identity of the node on the peer-to-peer network.
     system_localPeerId() -> Cow<'a, str>,
     /// Returns, as an opaque string, the name of the client serving these JSON-RPC requests.
     system_name() -> Cow<'a, str>,
-    system_networkState() -> (), // TODO:
+    system_networkState() -> NetworkState,
     system_nodeRoles() -> Cow<'a, [NodeRole]>,
     system_peers() -> Vec<SystemPeer>,
     system_properties() -> Box<serde_json::value::RawValue>,
-    system_removeReservedPeer() -> (), // TODO:
+    /// Removes a peer, designated by the Base58 encoding of its identity, from the reserved peers.
+    system_removeReservedPeer(peer_id: Cow<'a, str>) -> (),
     /// Returns, as an opaque string, the version of the client serving these JSON-RPC requests.
     system_version() -> Cow<'a, str>,
 
@@ -939,6 +945,33 @@ pub struct HeaderDigest {
     pub logs: Vec<HexString>,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GrandpaRoundStates {
+    #[serde(rename = "setId")]
+    pub set_id: u64,
+    pub best: GrandpaRoundState,
+    pub background: Vec<GrandpaRoundState>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GrandpaRoundState {
+    pub round: u64,
+    #[serde(rename = "totalWeight")]
+    pub total_weight: u64,
+    #[serde(rename = "thresholdWeight")]
+    pub threshold_weight: u64,
+    pub prevotes: GrandpaRoundVotes,
+    pub precommits: GrandpaRoundVotes,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GrandpaRoundVotes {
+    #[serde(rename = "currentWeight")]
+    pub current_weight: u64,
+    /// Public keys of the authorities that haven't voted yet.
+    pub missing: Vec<HexString>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NetworkConfig {
     #[serde(rename = "totalAttempts")]
@@ -1026,6 +1059,12 @@ pub enum DispatchClass {
     Mandatory,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReadProof {
+    pub at: HashHexString,
+    pub proof: Vec<HexString>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StorageChangeSet {
     pub block: HashHexString,
@@ -1039,6 +1078,27 @@ pub struct SystemHealth {
     pub should_have_peers: bool,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkState {
+    #[serde(rename = "peerId")]
+    pub peer_id: String,
+    #[serde(rename = "listenedAddresses")]
+    pub listened_addresses: Vec<String>,
+    /// List of peers the node has at least one established connection with.
+    #[serde(rename = "connectedPeers")]
+    pub connected_peers: Vec<String>,
+    /// Content of the Kademlia k-buckets of the chain.
+    #[serde(rename = "kBuckets")]
+    pub k_buckets: Vec<NetworkStateNode>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetworkStateNode {
+    #[serde(rename = "peerId")]
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SystemPeer {
     #[serde(rename = "peerId")]
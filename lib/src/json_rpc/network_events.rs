@@ -0,0 +1,446 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conversion of low-level networking events into `network_unstable_event` JSON-RPC
+//! notifications.
+//!
+//! A network service reports [`NetworkEvent`]s about all the chains it is connected to. A
+//! JSON-RPC service, however, is tied to a single chain, and must only report the events that
+//! concern this chain. This is the role of the [`ChainFilter`].
+//!
+//! Connections aren't specific to a chain. The [`ChainFilter`] reports a connection only once it
+//! becomes relevant to the chain, in other words when the peer it is connected to is assigned a
+//! slot of this chain or when a substream of this chain is opened on it. Substreams are only
+//! reported if they concern the chain.
+
+use super::methods;
+use crate::{libp2p::PeerId, network::service::SlotTy};
+
+use alloc::{string::String, string::ToString as _, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+/// Low-level event about the networking.
+///
+/// Connections and substreams are designated by an identifier that is unique among all the
+/// events of a network service.
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    /// A connection with the given peer has been established and its handshake has finished.
+    Connected { connection_id: u32, peer_id: PeerId },
+    /// The connection with the given identifier has been closed.
+    Disconnected { connection_id: u32 },
+    /// An outgoing substream concerning the given chain has been opened on the given connection.
+    SubstreamOpen {
+        connection_id: u32,
+        substream_id: u32,
+        chain_index: usize,
+        /// Short name of the protocol of the substream, such as `blocks` or `block-announces`.
+        protocol_name: &'static str,
+    },
+    /// The remote has accepted the given substream. For request substreams, this means that a
+    /// response has been successfully received.
+    SubstreamAccept { substream_id: u32 },
+    /// The given substream has been closed or has failed.
+    SubstreamStop { substream_id: u32, reason: String },
+    /// A slot of the given chain has been assigned to the given peer.
+    SlotAssign {
+        chain_index: usize,
+        peer_id: PeerId,
+        slot_ty: SlotTy,
+    },
+    /// The slot of the given chain of the given peer has been unassigned.
+    SlotUnassign {
+        chain_index: usize,
+        peer_id: PeerId,
+        slot_ty: SlotTy,
+    },
+}
+
+/// Filters the [`NetworkEvent`]s that concern a specific chain and converts them into their
+/// JSON-RPC equivalent.
+///
+/// See [the module-level documentation](self).
+pub struct ChainFilter {
+    /// Index of the chain whose events are reported.
+    chain_index: usize,
+
+    /// All the connections that are currently open, and whether they have been reported.
+    connections: HashMap<u32, (PeerId, bool), fnv::FnvBuildHasher>,
+
+    /// Peers that currently have a slot of the chain, and the number of slots they have.
+    peers_with_slot: HashMap<PeerId, usize, fnv::FnvBuildHasher>,
+
+    /// Substreams that have been reported and haven't been closed yet.
+    substreams: HashSet<u32, fnv::FnvBuildHasher>,
+}
+
+impl ChainFilter {
+    /// Initializes a new filter that reports the events of the given chain.
+    ///
+    /// The `chain_index` corresponds to the `chain_index` fields found in [`NetworkEvent`].
+    pub fn new(chain_index: usize) -> Self {
+        ChainFilter {
+            chain_index,
+            connections: HashMap::with_capacity_and_hasher(16, Default::default()),
+            peers_with_slot: HashMap::with_capacity_and_hasher(16, Default::default()),
+            substreams: HashSet::with_capacity_and_hasher(16, Default::default()),
+        }
+    }
+
+    /// Injects an event generated by the network service and returns the JSON-RPC events to
+    /// report, which might be empty if the event doesn't concern the chain.
+    ///
+    /// `when` is the number of milliseconds since the UNIX epoch at which the event happened.
+    pub fn inject_event(
+        &mut self,
+        event: NetworkEvent,
+        when: u64,
+    ) -> Vec<methods::NetworkEvent<'static>> {
+        let mut out = Vec::new();
+
+        match event {
+            NetworkEvent::Connected {
+                connection_id,
+                peer_id,
+            } => {
+                let relevant = self.peers_with_slot.contains_key(&peer_id);
+                if relevant {
+                    out.push(methods::NetworkEvent::HandshakeFinished {
+                        when,
+                        connection_id,
+                        peer_id: peer_id.to_string().into(),
+                    });
+                }
+                self.connections.insert(connection_id, (peer_id, relevant));
+            }
+            NetworkEvent::Disconnected { connection_id } => {
+                if let Some((_, true)) = self.connections.remove(&connection_id) {
+                    out.push(methods::NetworkEvent::Stop {
+                        when,
+                        connection_id,
+                        reason: "disconnected".into(),
+                    });
+                }
+            }
+            NetworkEvent::SubstreamOpen {
+                connection_id,
+                substream_id,
+                chain_index,
+                protocol_name,
+            } => {
+                if chain_index == self.chain_index {
+                    self.report_connection(connection_id, when, &mut out);
+                    self.substreams.insert(substream_id);
+                    out.push(methods::NetworkEvent::SubstreamOutOpen {
+                        when,
+                        connection_id,
+                        substream_id,
+                        protocol_name: protocol_name.into(),
+                    });
+                }
+            }
+            NetworkEvent::SubstreamAccept { substream_id } => {
+                if self.substreams.contains(&substream_id) {
+                    out.push(methods::NetworkEvent::SubstreamOutAccept { when, substream_id });
+                }
+            }
+            NetworkEvent::SubstreamStop {
+                substream_id,
+                reason,
+            } => {
+                if self.substreams.remove(&substream_id) {
+                    out.push(methods::NetworkEvent::SubstreamOutStop {
+                        when,
+                        substream_id,
+                        reason: reason.into(),
+                    });
+                }
+            }
+            NetworkEvent::SlotAssign {
+                chain_index,
+                peer_id,
+                slot_ty,
+            } => {
+                if chain_index == self.chain_index {
+                    *self.peers_with_slot.entry(peer_id.clone()).or_insert(0) += 1;
+
+                    let peer_id_str = peer_id.to_string();
+                    out.push(match slot_ty {
+                        SlotTy::Inbound => methods::NetworkEvent::InSlotAssign {
+                            when,
+                            peer_id: peer_id_str.into(),
+                        },
+                        SlotTy::Outbound => methods::NetworkEvent::OutSlotAssign {
+                            when,
+                            peer_id: peer_id_str.into(),
+                        },
+                    });
+
+                    // The peer might already be connected, in which case its connection is now
+                    // relevant to the chain.
+                    let connection_ids = self
+                        .connections
+                        .iter()
+                        .filter(|(_, (p, reported))| !*reported && *p == peer_id)
+                        .map(|(id, _)| *id)
+                        .collect::<Vec<_>>();
+                    for connection_id in connection_ids {
+                        self.report_connection(connection_id, when, &mut out);
+                    }
+                }
+            }
+            NetworkEvent::SlotUnassign {
+                chain_index,
+                peer_id,
+                slot_ty,
+            } => {
+                if chain_index == self.chain_index {
+                    if let hashbrown::hash_map::Entry::Occupied(mut entry) =
+                        self.peers_with_slot.entry(peer_id.clone())
+                    {
+                        *entry.get_mut() -= 1;
+                        if *entry.get() == 0 {
+                            entry.remove();
+                        }
+                    }
+
+                    let peer_id = peer_id.to_string().into();
+                    out.push(match slot_ty {
+                        SlotTy::Inbound => methods::NetworkEvent::InSlotUnassign { when, peer_id },
+                        SlotTy::Outbound => {
+                            methods::NetworkEvent::OutSlotUnassign { when, peer_id }
+                        }
+                    });
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Pushes a `HandshakeFinished` event to `out` if the given connection hasn't been reported
+    /// yet.
+    fn report_connection(
+        &mut self,
+        connection_id: u32,
+        when: u64,
+        out: &mut Vec<methods::NetworkEvent<'static>>,
+    ) {
+        if let Some((peer_id, reported @ false)) = self.connections.get_mut(&connection_id) {
+            *reported = true;
+            out.push(methods::NetworkEvent::HandshakeFinished {
+                when,
+                connection_id,
+                peer_id: peer_id.to_string().into(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainFilter, NetworkEvent};
+    use crate::{
+        json_rpc::methods,
+        libp2p::peer_id::{PeerId, PublicKey},
+        network::service::SlotTy,
+    };
+
+    fn peer(n: u8) -> PeerId {
+        PeerId::from_public_key(&PublicKey::Ed25519([n; 32]))
+    }
+
+    #[test]
+    fn connection_reported_once_slot_assigned() {
+        let mut filter = ChainFilter::new(0);
+
+        assert!(filter
+            .inject_event(
+                NetworkEvent::Connected {
+                    connection_id: 5,
+                    peer_id: peer(1),
+                },
+                10,
+            )
+            .is_empty());
+
+        let events = filter.inject_event(
+            NetworkEvent::SlotAssign {
+                chain_index: 0,
+                peer_id: peer(1),
+                slot_ty: SlotTy::Outbound,
+            },
+            11,
+        );
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            methods::NetworkEvent::OutSlotAssign { when: 11, .. }
+        ));
+        assert!(matches!(
+            events[1],
+            methods::NetworkEvent::HandshakeFinished {
+                when: 11,
+                connection_id: 5,
+                ..
+            }
+        ));
+
+        let events = filter.inject_event(NetworkEvent::Disconnected { connection_id: 5 }, 12);
+        assert!(matches!(
+            events[..],
+            [methods::NetworkEvent::Stop {
+                connection_id: 5,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn connection_with_slot_reported_immediately() {
+        let mut filter = ChainFilter::new(0);
+
+        filter.inject_event(
+            NetworkEvent::SlotAssign {
+                chain_index: 0,
+                peer_id: peer(1),
+                slot_ty: SlotTy::Inbound,
+            },
+            1,
+        );
+
+        let events = filter.inject_event(
+            NetworkEvent::Connected {
+                connection_id: 0,
+                peer_id: peer(1),
+            },
+            2,
+        );
+        assert!(matches!(
+            events[..],
+            [methods::NetworkEvent::HandshakeFinished {
+                connection_id: 0,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn other_chains_filtered_out() {
+        let mut filter = ChainFilter::new(0);
+
+        assert!(filter
+            .inject_event(
+                NetworkEvent::Connected {
+                    connection_id: 0,
+                    peer_id: peer(1),
+                },
+                1,
+            )
+            .is_empty());
+        assert!(filter
+            .inject_event(
+                NetworkEvent::SlotAssign {
+                    chain_index: 1,
+                    peer_id: peer(1),
+                    slot_ty: SlotTy::Outbound,
+                },
+                2,
+            )
+            .is_empty());
+        assert!(filter
+            .inject_event(
+                NetworkEvent::SubstreamOpen {
+                    connection_id: 0,
+                    substream_id: 1,
+                    chain_index: 1,
+                    protocol_name: "blocks",
+                },
+                3,
+            )
+            .is_empty());
+        assert!(filter
+            .inject_event(NetworkEvent::SubstreamAccept { substream_id: 1 }, 4)
+            .is_empty());
+        assert!(filter
+            .inject_event(NetworkEvent::Disconnected { connection_id: 0 }, 5)
+            .is_empty());
+    }
+
+    #[test]
+    fn substream_reports_its_connection() {
+        let mut filter = ChainFilter::new(2);
+
+        filter.inject_event(
+            NetworkEvent::Connected {
+                connection_id: 0,
+                peer_id: peer(1),
+            },
+            1,
+        );
+
+        let events = filter.inject_event(
+            NetworkEvent::SubstreamOpen {
+                connection_id: 0,
+                substream_id: 1,
+                chain_index: 2,
+                protocol_name: "blocks",
+            },
+            2,
+        );
+        assert!(matches!(
+            events[..],
+            [
+                methods::NetworkEvent::HandshakeFinished {
+                    connection_id: 0,
+                    ..
+                },
+                methods::NetworkEvent::SubstreamOutOpen {
+                    connection_id: 0,
+                    substream_id: 1,
+                    ..
+                }
+            ]
+        ));
+
+        let events = filter.inject_event(
+            NetworkEvent::SubstreamStop {
+                substream_id: 1,
+                reason: "timeout".into(),
+            },
+            3,
+        );
+        assert!(matches!(
+            events[..],
+            [methods::NetworkEvent::SubstreamOutStop {
+                substream_id: 1,
+                ..
+            }]
+        ));
+
+        // A substream is only stopped once.
+        assert!(filter
+            .inject_event(
+                NetworkEvent::SubstreamStop {
+                    substream_id: 1,
+                    reason: "timeout".into(),
+                },
+                4,
+            )
+            .is_empty());
+    }
+}
@@ -17,7 +17,7 @@
 
 //! Parse JSON-RPC method calls and notifications, and build responses messages.
 
-use alloc::{borrow::Cow, string::String};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
 /// Parses a JSON-encoded RPC method call or notification.
 pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
@@ -45,6 +45,58 @@ pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
     })
 }
 
+/// Parses a JSON-encoded request, which can be either a single method call or notification, or a
+/// batch of calls.
+///
+/// A batch is a JSON array of calls. The elements of the batch aren't parsed by this function,
+/// and must be parsed individually using [`parse_call`]. This makes it possible to generate an
+/// individual error response for each invalid element of the batch, as required by the JSON-RPC
+/// specification.
+///
+/// An empty batch is considered as invalid.
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let request = parse::parse_request(
+///     r#"[{"jsonrpc":"2.0","id":1,"method":"foo"},{"jsonrpc":"2.0","id":2,"method":"bar"}]"#
+/// ).unwrap();
+///
+/// match request {
+///     parse::Request::Batch(calls) => {
+///         assert_eq!(calls.len(), 2);
+///         assert_eq!(parse::parse_call(calls[1]).unwrap().method, "bar");
+///     }
+///     parse::Request::Single(_) => unreachable!(),
+/// }
+/// ```
+pub fn parse_request(request_json: &str) -> Result<Request, ParseError> {
+    if !request_json.trim_start().starts_with('[') {
+        return parse_call(request_json).map(Request::Single);
+    }
+
+    let elements: Vec<&serde_json::value::RawValue> =
+        serde_json::from_str(request_json).map_err(ParseError)?;
+    if elements.is_empty() {
+        return Err(ParseError(serde::de::Error::custom("empty batch")));
+    }
+
+    Ok(Request::Batch(
+        elements.into_iter().map(|e| e.get()).collect(),
+    ))
+}
+
+/// Decoded JSON-RPC request. See [`parse_request`].
+#[derive(Debug)]
+pub enum Request<'a> {
+    /// Request consists in a single call.
+    Single(Call<'a>),
+    /// Request consists in a batch of calls. Contains the JSON-formatted unparsed elements of
+    /// the batch. Never empty.
+    Batch(Vec<&'a str>),
+}
+
 /// Builds a JSON call.
 ///
 /// `method` must be the name of the method to call. `params_json` must be the JSON-formatted
@@ -107,6 +159,50 @@ pub fn build_success_response(id_json: &str, result_json: &str) -> String {
     .unwrap()
 }
 
+/// Builds the JSON response to a batch of calls from the individual responses.
+///
+/// Returns `None` if the list of responses is empty, which can happen if the batch only
+/// contained notifications. In that situation, nothing must be sent back to the client.
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let response = parse::build_batch_response([
+///     parse::build_success_response("1", "true"),
+///     parse::build_success_response("2", "false"),
+/// ]);
+///
+/// assert_eq!(
+///     response.unwrap(),
+///     r#"[{"jsonrpc":"2.0","id":1,"result":true},{"jsonrpc":"2.0","id":2,"result":false}]"#
+/// );
+/// ```
+///
+/// # Panic
+///
+/// The responses aren't validated, but passing strings that aren't valid JSON will lead to an
+/// invalid response.
+///
+pub fn build_batch_response(
+    responses: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Option<String> {
+    let mut out = String::from("[");
+    for response in responses {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        out.push_str(response.as_ref());
+    }
+
+    if out.len() == 1 {
+        return None;
+    }
+
+    out.push(']');
+    Some(out)
+}
+
 /// Builds a JSON response.
 ///
 /// `id_json` must be the JSON-formatted identifier of the request, found in [`Call::id_json`].
@@ -381,6 +477,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_request_single() {
+        let request = super::parse_request(r#"  {"jsonrpc":"2.0","id":5,"method":"foo"}"#).unwrap();
+        assert!(matches!(request, super::Request::Single(call) if call.method == "foo"));
+    }
+
+    #[test]
+    fn parse_request_batch() {
+        let request = super::parse_request(
+            r#" [{"jsonrpc":"2.0","id":5,"method":"foo"}, 12, {"jsonrpc":"2.0","method":"bar"}]"#,
+        )
+        .unwrap();
+        let elements = match request {
+            super::Request::Batch(elements) => elements,
+            super::Request::Single(_) => panic!(),
+        };
+        assert_eq!(elements.len(), 3);
+        assert_eq!(super::parse_call(elements[0]).unwrap().method, "foo");
+        assert!(super::parse_call(elements[1]).is_err());
+        assert!(super::parse_call(elements[2]).unwrap().id_json.is_none());
+    }
+
+    #[test]
+    fn parse_request_empty_batch() {
+        assert!(super::parse_request("[]").is_err());
+        assert!(super::parse_request("[").is_err());
+    }
+
+    #[test]
+    fn build_empty_batch_response() {
+        assert!(super::build_batch_response(Vec::<String>::new()).is_none());
+    }
+
     #[test]
     fn parse_bad_id() {
         assert!(
@@ -128,7 +128,7 @@ pub struct ClientId(u64, Weak<dyn Any + Send + Sync>);
 #[derive(Clone)]
 pub struct RequestId(u64, Weak<dyn Any + Send + Sync>);
 
-#[derive(Debug, Clone, derive_more::From)]
+#[derive(Debug, Copy, Clone, derive_more::From)]
 pub enum ClientOrRequestIdRef<'a> {
     ClientId(&'a ClientId),
     RequestId(&'a RequestId),
@@ -173,7 +173,8 @@ pub struct RequestsSubscriptions<TSubMsg> {
     /// using [`RequestsSubscriptions::set_max_clients`], in which case it would be impossible
     /// to update the size of this list.
     // TODO: what about entries of obsolete clients clogging the queue? how do we deal with this?
-    unpulled_requests: crossbeam_queue::SegQueue<(String, Weak<ClientInner<TSubMsg>>)>,
+    unpulled_requests:
+        crossbeam_queue::SegQueue<(String, Weak<ClientInner<TSubMsg>>, Option<BatchSlot>)>,
 
     /// Event notified whenever an element is pushed to [`RequestsSubscriptions::unpulled_requests`].
     new_unpulled_request: event_listener::Event,
@@ -187,6 +188,11 @@ pub struct RequestsSubscriptions<TSubMsg> {
     /// Matches the values found in [`ClientInnerGuarded::pending_requests`].
     next_request_id: atomic::Atomic<u64>,
 
+    /// Next identifier to assign to the next batch of requests.
+    ///
+    /// Matches the values found in [`ClientInnerGuarded::pending_batches`].
+    next_batch_id: atomic::Atomic<u64>,
+
     /// Next identifier to assign to the next subscription.
     ///
     /// Matches the values found in TODO.
@@ -228,6 +234,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             subscriptions_tasks: executor::TasksQueue::new(),
             new_unpulled_request: event_listener::Event::new(),
             next_request_id: atomic::Atomic::new(0),
+            next_batch_id: atomic::Atomic::new(0),
             next_subscription_id: atomic::Atomic::new(0),
             max_clients: AtomicUsize::new(max_clients),
             max_requests_per_client,
@@ -284,10 +291,14 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             dead: AtomicBool::new(false),
             total_requests_in_fly: AtomicUsize::new(0),
             guarded: Mutex::new(ClientInnerGuarded {
-                pending_requests: hashbrown::HashSet::with_capacity_and_hasher(
+                pending_requests: hashbrown::HashMap::with_capacity_and_hasher(
                     self.max_requests_per_client,
                     Default::default(),
                 ),
+                pending_batches: hashbrown::HashMap::with_capacity_and_hasher(
+                    0,
+                    Default::default(),
+                ),
                 responses_send_back: VecDeque::with_capacity(self.max_requests_per_client),
                 notification_messages: BTreeMap::new(),
                 responses_send_back_pushed_or_dead: event_listener::Event::new(),
@@ -349,7 +360,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         let guarded_lock = removed.guarded.lock().await;
         let requests_list = guarded_lock
             .pending_requests
-            .iter()
+            .keys()
             .map(|n| RequestId(*n, client.1.clone()))
             .collect();
         let subscriptions_list = guarded_lock
@@ -413,6 +424,16 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
                             .notify_additional(1);
                         return message;
                     }
+                    Some(ResponseSendBack::BatchResponse(message, num_requests)) => {
+                        let _old_val = client
+                            .total_requests_in_fly
+                            .fetch_sub(num_requests, Ordering::Release);
+                        debug_assert!(_old_val >= num_requests); // Check for underflows
+                        client
+                            .total_requests_in_fly_dec_or_dead
+                            .notify_additional(num_requests);
+                        return message;
+                    }
                     Some(ResponseSendBack::SubscriptionMessage(sub_id, index)) => {
                         let message = guarded_lock
                             .notification_messages
@@ -530,7 +551,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         // Note that it is possible for `client.dead` to have become true in the meanwhile, but
         // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
         self.unpulled_requests
-            .push((request, Arc::downgrade(&client)));
+            .push((request, Arc::downgrade(&client), None));
         self.new_unpulled_request.notify_additional(1);
     }
 
@@ -582,11 +603,93 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         // Note that it is possible for `client.dead` to have become true in the meanwhile, but
         // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
         self.unpulled_requests
-            .push((request, Arc::downgrade(&client)));
+            .push((request, Arc::downgrade(&client), None));
         self.new_unpulled_request.notify_additional(1);
         Ok(())
     }
 
+    /// Similar to [`RequestsSubscriptions::try_queue_client_request`], but queues multiple
+    /// requests at once as a batch.
+    ///
+    /// Each request in the batch is later individually returned by
+    /// [`RequestsSubscriptions::next_request`] and must be individually answered with
+    /// [`RequestsSubscriptions::respond`]. However, rather than being sent back separately, the
+    /// responses are held until all the requests of the batch have been answered, then sent back
+    /// as a single JSON array in the same order as the requests.
+    ///
+    /// Each request of the batch counts separately towards the limit of requests per client.
+    /// In other words, the batch is refused if queuing all of its requests would exceed the
+    /// limit, and a batch containing more requests than the limit can never be queued.
+    ///
+    /// The requests of the batch should only contain requests that expect a response. In other
+    /// words, JSON-RPC notifications must be filtered out beforehand.
+    ///
+    /// Has no effect if `requests` is empty.
+    ///
+    /// Returns `Ok` and silently discards the requests if the [`ClientId`] is stale or invalid.
+    pub fn try_queue_client_batch(
+        &self,
+        client: &ClientId,
+        requests: Vec<String>,
+    ) -> Result<(), TryQueueClientBatchError> {
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        if requests.len() > self.max_requests_per_client {
+            return Err(TryQueueClientBatchError::TooLarge { requests });
+        }
+
+        let client = match client
+            .1
+            .upgrade()
+            .and_then(|c| Arc::downcast::<ClientInner<TSubMsg>>(c).ok())
+        {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        if client.dead.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // Try increase `total_requests_in_fly` by the size of the batch, capping at a maximum of
+        // `max_requests_per_client`.
+        let num_requests = requests.len();
+        let failed_to_increase = client
+            .total_requests_in_fly
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |old_value| {
+                // Considering that `num_requests <= max`, this subtraction never underflows.
+                if old_value > self.max_requests_per_client - num_requests {
+                    return None;
+                }
+
+                Some(old_value + num_requests)
+            })
+            .is_err();
+        if failed_to_increase {
+            return Err(TryQueueClientBatchError::QueueFull { requests });
+        }
+
+        // We can now insert the requests.
+        // As explained in `try_queue_client_request`, it is not a problem if `client.dead` has
+        // become true in the meanwhile.
+        let batch_id = self.next_batch_id.fetch_add(1, Ordering::Relaxed);
+        for (index, request) in requests.into_iter().enumerate() {
+            self.unpulled_requests.push((
+                request,
+                Arc::downgrade(&client),
+                Some(BatchSlot {
+                    batch_id,
+                    index,
+                    batch_len: num_requests,
+                }),
+            ));
+        }
+        self.new_unpulled_request.notify_additional(num_requests);
+        Ok(())
+    }
+
     /// Waits until a request has been queued using
     /// [`RequestsSubscriptions::queue_client_request`] and returns it, alongside with an
     /// identifier to later pass back when answering the request.
@@ -597,7 +700,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
     pub async fn next_request(&self) -> (String, RequestId) {
         // Try to pull a request from the queue. If there is none, wait for
         // `new_unpulled_request`.
-        let (request_message, client) = loop {
+        let (request_message, client, batch_slot) = loop {
             // Because `new_unpulled_request` is notified *after* new items are pushed to the queue,
             // we *must* check the queue after calling `new_unpulled_request.listen()` and before
             // sleeping.
@@ -611,7 +714,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             // - Try pull from queue again (mandatory to prevent race conditions).
             // - Actually wait for the notification, and jump back to step 1.
             let mut sleep_until = None;
-            let (request_message, client) = loop {
+            let (request_message, client, batch_slot) = loop {
                 if let Some(item) = self.unpulled_requests.pop() {
                     break item;
                 }
@@ -627,7 +730,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             // if not throw away the entry and pull another one.
             if let Some(client) = client.upgrade() {
                 if !client.dead.load(Ordering::Relaxed) {
-                    break (request_message, client);
+                    break (request_message, client, batch_slot);
                 }
             }
         };
@@ -639,8 +742,23 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         {
             // TODO: future cancellation issue /!\
             let mut lock = client.guarded.lock().await;
-            let _was_inserted = lock.pending_requests.insert(request_id_num);
-            debug_assert!(_was_inserted);
+
+            // The first request of a batch to be pulled creates the entry of the batch.
+            let batch = if let Some(batch_slot) = batch_slot {
+                lock.pending_batches
+                    .entry(batch_slot.batch_id)
+                    .or_insert_with(|| PendingBatch {
+                        responses: (0..batch_slot.batch_len).map(|_| None).collect(),
+                        num_missing: batch_slot.batch_len,
+                        held_notifications: Vec::new(),
+                    });
+                Some((batch_slot.batch_id, batch_slot.index))
+            } else {
+                None
+            };
+
+            let _was_inserted = lock.pending_requests.insert(request_id_num, batch);
+            debug_assert!(_was_inserted.is_none());
         }
 
         // Success.
@@ -665,13 +783,60 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
 
         {
             let mut lock = client.guarded.lock().await;
-            if !lock.pending_requests.remove(&request.0) {
-                // The request ID is invalid.
-                return;
-            }
+            let batch = match lock.pending_requests.remove(&request.0) {
+                Some(batch) => batch,
+                None => {
+                    // The request ID is invalid.
+                    return;
+                }
+            };
+
+            let message = match batch {
+                None => ResponseSendBack::Response(response),
+                Some((batch_id, index)) => {
+                    // Responses to requests that belong to a batch are held until all the
+                    // requests of the batch have been answered.
+                    let pending_batch = lock.pending_batches.get_mut(&batch_id).unwrap();
+                    debug_assert!(pending_batch.responses[index].is_none());
+                    pending_batch.responses[index] = Some(response);
+                    pending_batch.num_missing -= 1;
+                    if pending_batch.num_missing != 0 {
+                        return;
+                    }
 
-            lock.responses_send_back
-                .push_back(ResponseSendBack::Response(response));
+                    let pending_batch = lock.pending_batches.remove(&batch_id).unwrap();
+                    let num_requests = pending_batch.responses.len();
+                    let message = crate::json_rpc::parse::build_batch_response(
+                        pending_batch.responses.into_iter().map(|r| r.unwrap()),
+                    )
+                    .unwrap(); // Batches are never empty.
+
+                    // Shrink `pending_batches` in order to potentially reclaim memory after a
+                    // spike in number of batches.
+                    if lock.pending_batches.is_empty() {
+                        lock.pending_batches.shrink_to_fit();
+                    }
+
+                    // The notifications of the subscriptions started by the batch are sent
+                    // right after its response.
+                    let num_held = pending_batch.held_notifications.len();
+                    lock.responses_send_back
+                        .push_back(ResponseSendBack::BatchResponse(message, num_requests));
+                    lock.responses_send_back.extend(
+                        pending_batch
+                            .held_notifications
+                            .into_iter()
+                            .map(|(sub_id, index)| {
+                                ResponseSendBack::SubscriptionMessage(sub_id, index)
+                            }),
+                    );
+                    lock.responses_send_back_pushed_or_dead
+                        .notify_additional(1 + num_held);
+                    return;
+                }
+            };
+
+            lock.responses_send_back.push_back(message);
             lock.responses_send_back_pushed_or_dead.notify_additional(1);
         }
     }
@@ -734,8 +899,8 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         let subscription_id_num = self.next_subscription_id.fetch_add(1, Ordering::Relaxed);
         let subscription_id_string = subscription_id_num.to_string();
 
+        let client_or_request = client_or_request.into();
         let client_arc = match client_or_request
-            .into()
             .client_weak()
             .upgrade()
             .and_then(|c| Arc::downcast::<ClientInner<TSubMsg>>(c).ok())
@@ -769,10 +934,21 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
             return Err(StartSubscriptionError::LimitReached);
         }
 
+        // If the subscription is started by a request that belongs to a batch, its notifications
+        // must not reach the client before the response to the batch.
+        let batch_id = match client_or_request {
+            ClientOrRequestIdRef::RequestId(request) => lock
+                .pending_requests
+                .get(&request.0)
+                .and_then(|batch| batch.map(|(batch_id, _)| batch_id)),
+            ClientOrRequestIdRef::ClientId(_) => None,
+        };
+
         let _prev_value = lock.active_subscriptions.insert(
             subscription_id_num,
             Subscription {
                 notifications_capacity,
+                batch_id,
                 messages_tx,
             },
         );
@@ -911,12 +1087,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
 
         // Add an entry in `responses_send_back`, or skip this step if not necessary.
         if previous_message.is_none() {
-            lock.responses_send_back
-                .push_back(ResponseSendBack::SubscriptionMessage(
-                    subscription_as_num,
-                    index,
-                ));
-            lock.responses_send_back_pushed_or_dead.notify_additional(1);
+            lock.queue_notification(subscription_as_num, index);
         }
     }
 
@@ -1053,12 +1224,7 @@ impl<TSubMsg: Send + Sync + 'static> RequestsSubscriptions<TSubMsg> {
         debug_assert!(_previous_message.is_none());
 
         // Add an entry in `responses_send_back`.
-        lock.responses_send_back
-            .push_back(ResponseSendBack::SubscriptionMessage(
-                subscription_as_num,
-                index,
-            ));
-        lock.responses_send_back_pushed_or_dead.notify_additional(1);
+        lock.queue_notification(subscription_as_num, index);
 
         Ok(())
     }
@@ -1203,6 +1369,23 @@ pub struct TryQueueClientRequestError {
     pub request: String,
 }
 
+/// Error returned by [`RequestsSubscriptions::try_queue_client_batch`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum TryQueueClientBatchError {
+    /// Batch contains more requests than the maximum number of requests per client.
+    #[display(fmt = "Batch contains too many requests")]
+    TooLarge {
+        /// Original requests, passed as parameter to the function.
+        requests: Vec<String>,
+    },
+    /// Not enough room in the queue of unpulled requests for all the requests of the batch.
+    #[display(fmt = "Queue of unpulled requests full")]
+    QueueFull {
+        /// Original requests, passed as parameter to the function.
+        requests: Vec<String>,
+    },
+}
+
 /// Error returned by [`RequestsSubscriptions::add_client`] and
 /// [`RequestsSubscriptions::add_client_mut`].
 #[derive(Debug, derive_more::Display, Clone)]
@@ -1256,8 +1439,18 @@ struct ClientInnerGuarded<TSubMsg> {
     /// List of requests that have been pulled by [`RequestsSubscriptions::next_request`] and
     /// waiting to be responded.
     ///
+    /// For requests that belong to a batch, contains the identifier of the batch within
+    /// [`ClientInnerGuarded::pending_batches`] and the index of the request within the batch.
+    ///
     /// A FNV hasher is used because the keys of this map are allocated locally.
-    pending_requests: hashbrown::HashSet<u64, fnv::FnvBuildHasher>,
+    pending_requests: hashbrown::HashMap<u64, Option<(u64, usize)>, fnv::FnvBuildHasher>,
+
+    /// List of batches of which at least one request has been pulled by
+    /// [`RequestsSubscriptions::next_request`], and that are waiting for some of their requests
+    /// to be responded.
+    ///
+    /// A FNV hasher is used because the keys of this map are allocated locally.
+    pending_batches: hashbrown::HashMap<u64, PendingBatch, fnv::FnvBuildHasher>,
 
     /// Queue of responses to regular requests to send back to the client.
     ///
@@ -1280,7 +1473,7 @@ struct ClientInnerGuarded<TSubMsg> {
     /// List of notification messages to send back to the client.
     ///
     /// Each entry in this map also always has a corresponding entry in
-    /// [`ClientInnerGuarded::responses_send_back`].
+    /// [`ClientInnerGuarded::responses_send_back`] or in [`PendingBatch::held_notifications`].
     notification_messages: BTreeMap<(u64, usize), String>,
 
     /// Every time an entry is removed from [`ClientInnerGuarded::notification_messages`], one
@@ -1308,20 +1501,78 @@ struct ClientInnerGuarded<TSubMsg> {
     num_inactive_alive_subscriptions: usize,
 }
 
+impl<TSubMsg> ClientInnerGuarded<TSubMsg> {
+    /// Adds an entry in [`ClientInnerGuarded::responses_send_back`] for the given notification,
+    /// which must have been inserted in [`ClientInnerGuarded::notification_messages`], or holds
+    /// it back if the subscription was started by a batch that hasn't been responded yet.
+    fn queue_notification(&mut self, subscription: u64, index: usize) {
+        debug_assert!(self
+            .notification_messages
+            .contains_key(&(subscription, index)));
+
+        if let Some(pending_batch) = self
+            .active_subscriptions
+            .get(&subscription)
+            .and_then(|sub| sub.batch_id)
+            .and_then(|batch_id| self.pending_batches.get_mut(&batch_id))
+        {
+            pending_batch.held_notifications.push((subscription, index));
+            return;
+        }
+
+        self.responses_send_back
+            .push_back(ResponseSendBack::SubscriptionMessage(subscription, index));
+        self.responses_send_back_pushed_or_dead.notify_additional(1);
+    }
+}
+
 enum ResponseSendBack {
     /// Message to send back is a response to a request. Pulling out this message decrements
     /// [`ClientInner::total_requests_in_fly`].
     Response(String),
 
+    /// Message to send back is the response to a batch of requests. Pulling out this message
+    /// decrements [`ClientInner::total_requests_in_fly`] by the given number of requests.
+    BatchResponse(String, usize),
+
     /// Message to send back is a subscription notification. It can be found in
     /// [`ClientInnerGuarded::notification_messages`] at the given key.
     SubscriptionMessage(u64, usize),
 }
 
+/// Position of a request within a batch. See [`RequestsSubscriptions::try_queue_client_batch`].
+struct BatchSlot {
+    /// Identifier of the batch. Will be used as key in [`ClientInnerGuarded::pending_batches`].
+    batch_id: u64,
+    /// Index of the request within the batch.
+    index: usize,
+    /// Total number of requests within the batch.
+    batch_len: usize,
+}
+
+/// See [`ClientInnerGuarded::pending_batches`].
+struct PendingBatch {
+    /// Responses to the requests of the batch, in the same order as the requests. `None` for
+    /// requests that haven't been answered yet.
+    responses: Vec<Option<String>>,
+    /// Number of `None` in [`PendingBatch::responses`].
+    num_missing: usize,
+    /// Notifications of subscriptions started by requests of this batch, in the order in which
+    /// they have been queued. They are moved to [`ClientInnerGuarded::responses_send_back`] right
+    /// after the response to the batch, so that the client never receives a notification about
+    /// a subscription whose identifier it doesn't know yet. Keys of
+    /// [`ClientInnerGuarded::notification_messages`].
+    held_notifications: Vec<(u64, usize)>,
+}
+
 struct Subscription<TSubMsg> {
     /// Maximum number of notifications towards the client queued at any given time.
     notifications_capacity: usize,
 
+    /// If the subscription has been started by a request that belongs to a batch, identifier of
+    /// this batch. See [`PendingBatch::held_notifications`].
+    batch_id: Option<u64>,
+
     /// Sender for messages towards the subscription task. Connected to the receiver that was
     /// provided to the API user when the subscription was created.
     messages_tx: mpsc::Sender<(TSubMsg, oneshot::Sender<()>)>,
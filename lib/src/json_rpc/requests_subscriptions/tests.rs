@@ -19,6 +19,7 @@
 
 use super::{Config, RequestsSubscriptions};
 use core::num::NonZeroU32;
+use futures::FutureExt as _;
 
 #[test]
 fn clients_limit_adjustement() {
@@ -41,3 +42,87 @@ fn clients_limit_adjustement() {
         assert!(req_sub.add_client().await.is_err());
     });
 }
+
+#[test]
+fn batch_responses_reassembled() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 1,
+            max_requests_per_client: NonZeroU32::new(3).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client = req_sub.add_client().await.unwrap();
+
+        assert!(req_sub
+            .try_queue_client_batch(&client, vec!["a".to_owned(); 4])
+            .is_err());
+        req_sub
+            .try_queue_client_batch(&client, vec!["a".to_owned(), "b".to_owned()])
+            .unwrap();
+        assert!(req_sub
+            .try_queue_client_batch(&client, vec!["c".to_owned(), "d".to_owned()])
+            .is_err());
+
+        let (request1, request1_id) = req_sub.next_request().await;
+        let (request2, request2_id) = req_sub.next_request().await;
+        assert_eq!(request1, "a");
+        assert_eq!(request2, "b");
+
+        req_sub.respond(&request2_id, "2".to_owned()).await;
+        req_sub.respond(&request1_id, "1".to_owned()).await;
+
+        assert_eq!(req_sub.next_response(&client).await, "[1,2]");
+
+        // All the requests of the batch are no longer counted as in fly.
+        req_sub
+            .try_queue_client_batch(&client, vec!["c".to_owned(); 3])
+            .unwrap();
+    });
+}
+
+#[test]
+fn batch_notifications_after_response() {
+    futures::executor::block_on(async move {
+        let req_sub = RequestsSubscriptions::<()>::new(Config {
+            max_clients: 1,
+            max_requests_per_client: NonZeroU32::new(3).unwrap(),
+            max_subscriptions_per_client: 5,
+        });
+
+        let client = req_sub.add_client().await.unwrap();
+
+        req_sub
+            .try_queue_client_batch(&client, vec!["sub".to_owned(), "other".to_owned()])
+            .unwrap();
+
+        let (_, request1_id) = req_sub.next_request().await;
+        let (_, request2_id) = req_sub.next_request().await;
+
+        let (subscription_id, _, subscription_start) =
+            req_sub.start_subscription(&request1_id, 4).await.unwrap();
+        subscription_start.start(async {});
+
+        req_sub.respond(&request1_id, "1".to_owned()).await;
+        req_sub
+            .push_notification(&request1_id, &subscription_id, "notif1".to_owned())
+            .await;
+        req_sub
+            .push_notification(&request1_id, &subscription_id, "notif2".to_owned())
+            .await;
+
+        // The notifications are held as long as the batch hasn't been responded.
+        assert!(req_sub.next_response(&client).now_or_never().is_none());
+
+        req_sub.respond(&request2_id, "2".to_owned()).await;
+        assert_eq!(req_sub.next_response(&client).await, "[1,2]");
+        assert_eq!(req_sub.next_response(&client).await, "notif1");
+        assert_eq!(req_sub.next_response(&client).await, "notif2");
+
+        // Once the batch has been responded, notifications are no longer held.
+        req_sub
+            .push_notification(&client, &subscription_id, "notif3".to_owned())
+            .await;
+        assert_eq!(req_sub.next_response(&client).await, "notif3");
+    });
+}
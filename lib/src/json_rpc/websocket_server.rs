@@ -20,6 +20,16 @@
 //! Only handles text frames from the WebSocket protocol. While adding support for binary frames
 //! isn't difficult, it is out of scope of the use-case of this code.
 //!
+//! In addition to WebSocket connections, the server also accepts plain HTTP `POST` requests on
+//! the same port. The body of such a request is reported similarly to a WebSocket text frame, and
+//! the first message sent back on the connection is used as the body of the HTTP response, after
+//! which the connection is closed. Cross-origin requests are refused, unless their origin is
+//! found in [`Config::cors_allowed_origins`], in which case CORS pre-flight (`OPTIONS`) requests
+//! are automatically answered.
+//!
+//! Connections that haven't finished the WebSocket handshake or sent the entire HTTP request
+//! within a few seconds are closed.
+//!
 //! # Usage
 //!
 //! Call [`WsServer::new`], passing a [`Config`], in order to create a listening TCP socket
@@ -54,6 +64,10 @@
 //! Use [`WsServer::queue_send`] to send a text frame to a client. The message is buffered and
 //! will be progressively delivered when the client is ready to receive it.
 //!
+//! When [`Event::HttpRequest`] is returned, the API user is expected to call
+//! [`WsServer::queue_send`] exactly once in order to send back the response. Alternatively,
+//! calling [`WsServer::close`] sends back an empty `204 No Content` response.
+//!
 //! # Example
 //!
 //! ```
@@ -63,8 +77,10 @@
 //! let mut server = WsServer::new(Config {
 //!     bind_address: "127.0.0.1:0".parse().unwrap(),
 //!     max_frame_size: 1024 * 1024,
+//!     max_http_body_size: 1024 * 1024,
 //!     send_buffer_len: 32,
 //!     capacity: 32,
+//!     cors_allowed_origins: Vec::new(),
 //! })
 //! .await
 //! .unwrap();
@@ -89,6 +105,12 @@
 //!             server.queue_send(connection_id, "hello back!".to_string());
 //!         },
 //!
+//!         // Received an HTTP request. The connection is closed after the response is sent.
+//!         Event::HttpRequest { body, connection_id, .. } => {
+//!             println!("Received HTTP request: {:?}", body);
+//!             server.queue_send(connection_id, "hello back!".to_string());
+//!         },
+//!
 //!         // Connection has been closed.
 //!         Event::ConnectionError { .. } => {},
 //!     }
@@ -117,10 +139,20 @@
 mod tests;
 
 use async_std::net::{TcpListener, TcpStream};
-use core::{fmt, ops, str};
+use core::{
+    cmp, fmt, ops,
+    pin::Pin,
+    str,
+    task::{Context, Poll},
+};
 use futures::{channel::mpsc, prelude::*};
 use soketto::handshake::{server::Response, Server};
-use std::{io, net::SocketAddr};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 /// Configuration for a [`WsServer`].
 pub struct Config {
@@ -134,12 +166,24 @@ pub struct Config {
     /// occupy a lot of memory.
     pub max_frame_size: usize,
 
+    /// Maximum size, in bytes, of the body of an HTTP `POST` request.
+    ///
+    /// Requests whose body is larger than this value are answered with an HTTP error.
+    pub max_http_body_size: usize,
+
     /// Number of pending messages to buffer up for sending before the socket is considered
     /// unresponsive.
     pub send_buffer_len: usize,
 
     /// Pre-allocated capacity for the list of connections.
     pub capacity: usize,
+
+    /// List of values of the `Origin` header of HTTP requests for which cross-origin requests
+    /// are allowed. Contains `*` in order to allow all origins.
+    ///
+    /// If the origin of an HTTP request isn't in this list, the CORS headers are absent from the
+    /// response, and web browsers will refuse to let the web page read it.
+    pub cors_allowed_origins: Vec<String>,
 }
 
 /// Identifier for a connection with regard to a [`WsServer`].
@@ -153,25 +197,41 @@ pub struct WsServer<T> {
     /// Value passed through [`Config::max_frame_size`].
     max_frame_size: usize,
 
+    /// Value passed through [`Config::max_http_body_size`].
+    max_http_body_size: usize,
+
     /// Value passed through [`Config::send_buffer_len`].
     send_buffer_len: usize,
 
+    /// Value passed through [`Config::cors_allowed_origins`].
+    cors_allowed_origins: Arc<Vec<String>>,
+
     /// Endpoint for incoming TCP sockets.
     listener: TcpListener,
 
     /// Pending incoming connection to accept. Accepted by calling [`WsServer::accept`].
     pending_incoming: Option<TcpStream>,
 
-    /// List of TCP connections that are currently negotiating the WebSocket handshake.
+    /// List of TCP connections that are currently negotiating the WebSocket handshake or
+    /// receiving the body of an HTTP request.
     ///
     /// The output can be an error if the handshake fails.
-    negotiating: stream::FuturesUnordered<future::BoxFuture<'static, NegotiatingConnection>>,
+    ///
+    /// The WebSocket library isn't `Sync`, and neither are these futures. They are wrapped
+    /// within a `Mutex` in order for the [`WsServer`] to be `Sync`, so that it can be borrowed
+    /// across `await` points from a `Send` future. The `Mutex` is never locked, and only ever
+    /// accessed through `get_mut`.
+    negotiating: Mutex<stream::FuturesUnordered<future::BoxFuture<'static, NegotiatingConnection>>>,
 
     /// List of streams of incoming messages for all connections.
-    incoming_messages: stream::SelectAll<stream::BoxStream<'static, IncomingMessage>>,
+    ///
+    /// See [`WsServer::negotiating`] regarding the `Mutex`.
+    incoming_messages: Mutex<stream::SelectAll<stream::BoxStream<'static, IncomingMessage>>>,
 
     /// Tasks dedicated to sending messages on connections. One per healthy connection.
-    sending_tasks: stream::FuturesUnordered<future::BoxFuture<'static, (ConnectionId, u64)>>,
+    ///
+    /// See [`WsServer::negotiating`] regarding the `Mutex`.
+    sending_tasks: Mutex<stream::FuturesUnordered<future::BoxFuture<'static, (ConnectionId, u64)>>>,
 
     /// List of connections that are either negotiating or open.
     connections: slab::Slab<Connection<T>>,
@@ -180,7 +240,9 @@ pub struct WsServer<T> {
     next_unique_id: u64,
 
     /// Tasks dedicated to closing sockets that have been rejected.
-    rejected_sockets: stream::FuturesUnordered<future::BoxFuture<'static, ()>>,
+    ///
+    /// See [`WsServer::negotiating`] regarding the `Mutex`.
+    rejected_sockets: Mutex<stream::FuturesUnordered<future::BoxFuture<'static, ()>>>,
 }
 
 struct Connection<T> {
@@ -208,8 +270,24 @@ struct NegotiatingConnection {
     /// Unique identifier for this connection. See [`Connection::unique_id`].
     unique_id: u64,
 
-    /// Outcome of the negotiation. Can be `Err` if a problem happened.
-    outcome: Result<Server<'static, TcpStream>, ()>,
+    /// Outcome of the negotiation. Can be `Err` if a problem happened or if the connection has
+    /// already been answered.
+    outcome: Result<NegotiationOutcome, ()>,
+}
+
+enum NegotiationOutcome {
+    /// The WebSocket handshake has been successfully performed.
+    WebSocket(Server<'static, PrefixedSocket>),
+
+    /// The connection is a plain HTTP `POST` request whose body has been fully received.
+    Http {
+        /// Socket onto which to send back the response.
+        socket: TcpStream,
+        /// Body of the request.
+        body: String,
+        /// Value of the `Access-Control-Allow-Origin` header to put in the response, if any.
+        cors_origin: Option<String>,
+    },
 }
 
 struct IncomingMessage {
@@ -232,15 +310,17 @@ impl<T> WsServer<T> {
 
         Ok(WsServer {
             max_frame_size: config.max_frame_size,
+            max_http_body_size: config.max_http_body_size,
             send_buffer_len: config.send_buffer_len,
+            cors_allowed_origins: Arc::new(config.cors_allowed_origins),
             listener,
             pending_incoming: None,
-            negotiating: stream::FuturesUnordered::new(),
-            incoming_messages: stream::SelectAll::new(),
-            sending_tasks: stream::FuturesUnordered::new(),
+            negotiating: Mutex::new(stream::FuturesUnordered::new()),
+            incoming_messages: Mutex::new(stream::SelectAll::new()),
+            sending_tasks: Mutex::new(stream::FuturesUnordered::new()),
             connections: slab::Slab::with_capacity(config.capacity),
             next_unique_id: 0,
-            rejected_sockets: stream::FuturesUnordered::new(),
+            rejected_sockets: Mutex::new(stream::FuturesUnordered::new()),
         })
     }
 
@@ -277,45 +357,24 @@ impl<T> WsServer<T> {
             }
         }));
 
-        self.negotiating.push(Box::pin(async move {
-            let mut server = Server::new(pending_incoming);
-
-            let websocket_key = match server.receive_request().await {
-                Ok(req) => req.key(),
-                Err(_) => {
-                    return NegotiatingConnection {
-                        connection_id,
-                        unique_id,
-                        outcome: Err(()),
-                    }
-                }
-            };
-
-            match server
-                .send_response(&{
-                    Response::Accept {
-                        key: websocket_key,
-                        protocol: None,
-                    }
-                })
-                .await
-            {
-                Ok(()) => {}
-                Err(_) => {
-                    return NegotiatingConnection {
-                        connection_id,
-                        unique_id,
-                        outcome: Err(()),
-                    }
+        let max_http_body_size = self.max_http_body_size;
+        let cors_allowed_origins = self.cors_allowed_origins.clone();
+        self.negotiating
+            .get_mut()
+            .unwrap()
+            .push(Box::pin(async move {
+                let outcome = async_std::future::timeout(
+                    NEGOTIATION_TIMEOUT,
+                    negotiate(pending_incoming, max_http_body_size, &cors_allowed_origins),
+                )
+                .await;
+
+                NegotiatingConnection {
+                    connection_id,
+                    unique_id,
+                    outcome: outcome.unwrap_or(Err(())),
                 }
-            };
-
-            NegotiatingConnection {
-                connection_id,
-                unique_id,
-                outcome: Ok(server),
-            }
-        }));
+            }));
 
         connection_id
     }
@@ -397,7 +456,7 @@ impl<T> WsServer<T> {
                     return Event::ConnectionOpen { address };
                 },
 
-                negotiation = self.negotiating.select_next_some() => {
+                negotiation = self.negotiating.get_mut().unwrap().select_next_some() => {
                     // Make sure that what is in `self.connections` matches the outcome of the
                     // negotiation. Otherwise, it means that the connection is already closed.
                     if !self.connections.contains(negotiation.connection_id.0) {
@@ -408,7 +467,51 @@ impl<T> WsServer<T> {
                     }
 
                     let server = match negotiation.outcome {
-                        Ok(s) => s,
+                        Ok(NegotiationOutcome::WebSocket(s)) => s,
+                        Ok(NegotiationOutcome::Http {
+                            mut socket,
+                            body,
+                            cors_origin,
+                        }) => {
+                            // Spawn a task dedicated to sending back the response. Only the first
+                            // message queued is sent back. If the connection is closed by the API
+                            // user before any message has been queued, an empty response is sent.
+                            let mut send_rx = self.connections[negotiation.connection_id.0]
+                                .send_rx
+                                .take()
+                                .unwrap();
+                            self.sending_tasks.get_mut().unwrap().push(Box::pin(async move {
+                                let cors_origin = cors_origin.as_deref();
+                                let _ = match send_rx.next().await {
+                                    Some(response) => {
+                                        write_http_response(
+                                            &mut socket,
+                                            "200 OK",
+                                            cors_origin,
+                                            &response,
+                                        )
+                                        .await
+                                    }
+                                    None => {
+                                        write_http_response(
+                                            &mut socket,
+                                            "204 No Content",
+                                            cors_origin,
+                                            "",
+                                        )
+                                        .await
+                                    }
+                                };
+                                (negotiation.connection_id, negotiation.unique_id)
+                            }));
+
+                            return Event::HttpRequest {
+                                connection_id: negotiation.connection_id,
+                                user_data: &mut self.connections[negotiation.connection_id.0]
+                                    .user_data,
+                                body,
+                            };
+                        }
                         Err(()) => return Event::ConnectionError {
                             connection_id: negotiation.connection_id,
                             user_data: self.connections.remove(negotiation.connection_id.0).user_data,
@@ -423,7 +526,7 @@ impl<T> WsServer<T> {
                     };
 
                     // Spawn a task dedicated to receiving messages from the socket.
-                    self.incoming_messages.push({
+                    self.incoming_messages.get_mut().unwrap().push({
                         // Turn `receiver` into a stream of received packets.
                         let socket_packets = stream::unfold((receiver, Vec::new()), move |(mut receiver, mut buf)| async {
                             buf.clear();
@@ -442,7 +545,7 @@ impl<T> WsServer<T> {
                     });
 
                     // Spawn a task dedicated to sending the messages buffered to be sent.
-                    self.sending_tasks.push({
+                    self.sending_tasks.get_mut().unwrap().push({
                         let mut send_rx = self.connections[negotiation.connection_id.0].send_rx.take().unwrap();
                         Box::pin(async move {
                             while let Some(message) = send_rx.next().await {
@@ -458,7 +561,7 @@ impl<T> WsServer<T> {
                     });
                 },
 
-                incoming = self.incoming_messages.select_next_some() => {
+                incoming = self.incoming_messages.get_mut().unwrap().select_next_some() => {
                     // Make sure that what is in `self.connections` matches the message. Otherwise,
                     // it means that the connection is already closed.
                     if !self.connections.contains(incoming.connection_id.0) {
@@ -483,7 +586,11 @@ impl<T> WsServer<T> {
                     }
                 },
 
-                (connection_id, unique_id) = self.sending_tasks.select_next_some() => {
+                (connection_id, unique_id) = self
+                    .sending_tasks
+                    .get_mut()
+                    .unwrap()
+                    .select_next_some() => {
                     // Make sure that what is in `self.connections` matches the message. Otherwise,
                     // it means that the connection is already closed.
                     if !self.connections.contains(connection_id.0) {
@@ -499,7 +606,7 @@ impl<T> WsServer<T> {
                     }
                 },
 
-                _ = self.rejected_sockets.select_next_some() => {
+                _ = self.rejected_sockets.get_mut().unwrap().select_next_some() => {
                 }
             }
         }
@@ -568,4 +675,261 @@ pub enum Event<'a, T> {
         /// nothing must be assumed about the validity of this message.
         message: String,
     },
+
+    /// A connection is a plain HTTP `POST` request, and its body has been received.
+    ///
+    /// The response must be sent back by calling [`WsServer::queue_send`] once. The connection
+    /// is then closed, which generates an [`Event::ConnectionError`]. Calling [`WsServer::close`]
+    /// instead sends back an empty response.
+    HttpRequest {
+        /// Identifier of the connection that sent the request.
+        connection_id: ConnectionId,
+        /// User data associated with the connection.
+        user_data: &'a mut T,
+        /// Body of the request. Its content is entirely decided by the client, and nothing must
+        /// be assumed about the validity of this body.
+        body: String,
+    },
+}
+
+/// Maximum size, in bytes, of the request line and headers of an HTTP request.
+const MAX_HTTP_HEADERS_SIZE: usize = 8192;
+
+/// Maximum duration of the WebSocket handshake or of the reception of an HTTP request, after
+/// which the connection is closed.
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reads the HTTP request at the start of the given socket, then either performs the WebSocket
+/// handshake or reads the body of the HTTP `POST` request.
+///
+/// Requests that are neither WebSocket upgrades nor `POST` requests are directly answered, in
+/// which case `Err` is returned.
+///
+/// See [`Config::cors_allowed_origins`] for `cors_allowed_origins`.
+async fn negotiate(
+    mut socket: TcpStream,
+    max_http_body_size: usize,
+    cors_allowed_origins: &[String],
+) -> Result<NegotiationOutcome, ()> {
+    // Read until the end of the HTTP headers.
+    let mut buffer = Vec::with_capacity(1024);
+    let headers_end = loop {
+        if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+
+        if buffer.len() >= MAX_HTTP_HEADERS_SIZE {
+            let _ =
+                write_http_response(&mut socket, "431 Request Header Fields Too Large", None, "")
+                    .await;
+            return Err(());
+        }
+
+        let mut read_buffer = [0; 1024];
+        let num_read = socket.read(&mut read_buffer).await.map_err(|_| ())?;
+        if num_read == 0 {
+            return Err(());
+        }
+        buffer.extend_from_slice(&read_buffer[..num_read]);
+    };
+
+    let (method, is_websocket_upgrade, content_length, cors_origin) = {
+        let Ok(head) = str::from_utf8(&buffer[..headers_end]) else {
+            let _ = write_http_response(&mut socket, "400 Bad Request", None, "").await;
+            return Err(());
+        };
+
+        let mut lines = head.split("\r\n");
+        let method = lines
+            .next()
+            .and_then(|line| line.split(' ').next())
+            .unwrap_or("")
+            .to_owned();
+
+        let mut is_websocket_upgrade = false;
+        let mut content_length = None;
+        let mut origin = None;
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("upgrade") {
+                is_websocket_upgrade = value.eq_ignore_ascii_case("websocket");
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.parse::<usize>().ok());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value);
+            }
+        }
+
+        // The origin is echoed back in the response if it is allowed, as the CORS headers
+        // can't contain a list of origins.
+        let cors_origin = origin
+            .filter(|origin| {
+                cors_allowed_origins
+                    .iter()
+                    .any(|allowed| allowed == "*" || allowed == origin)
+            })
+            .map(|origin| origin.to_owned());
+
+        (method, is_websocket_upgrade, content_length, cors_origin)
+    };
+    let cors_origin = cors_origin.as_deref();
+
+    if is_websocket_upgrade {
+        // The handshake request has already been partially or entirely read from the socket,
+        // and needs to be passed to the WebSocket library as well.
+        let mut server = Server::new(PrefixedSocket {
+            prefix: buffer,
+            prefix_offset: 0,
+            socket,
+        });
+
+        let websocket_key = match server.receive_request().await {
+            Ok(req) => req.key(),
+            Err(_) => return Err(()),
+        };
+
+        server
+            .send_response(&{
+                Response::Accept {
+                    key: websocket_key,
+                    protocol: None,
+                }
+            })
+            .await
+            .map_err(|_| ())?;
+
+        return Ok(NegotiationOutcome::WebSocket(server));
+    }
+
+    match &method[..] {
+        "POST" => {}
+        "OPTIONS" if cors_origin.is_some() => {
+            // CORS pre-flight request.
+            let _ = write_http_response(&mut socket, "204 No Content", cors_origin, "").await;
+            return Err(());
+        }
+        _ => {
+            let _ =
+                write_http_response(&mut socket, "405 Method Not Allowed", cors_origin, "").await;
+            return Err(());
+        }
+    }
+
+    let content_length = match content_length {
+        Some(Some(len)) if len <= max_http_body_size => len,
+        Some(Some(_)) => {
+            let _ =
+                write_http_response(&mut socket, "413 Payload Too Large", cors_origin, "").await;
+            return Err(());
+        }
+        Some(None) => {
+            let _ = write_http_response(&mut socket, "400 Bad Request", cors_origin, "").await;
+            return Err(());
+        }
+        None => {
+            let _ = write_http_response(&mut socket, "411 Length Required", cors_origin, "").await;
+            return Err(());
+        }
+    };
+
+    // Part of the body might already have been read alongside with the headers. Any data past
+    // the body is ignored, as only one request per connection is supported.
+    let mut body = buffer.split_off(headers_end);
+    let already_read = cmp::min(body.len(), content_length);
+    body.resize(content_length, 0);
+    socket
+        .read_exact(&mut body[already_read..])
+        .await
+        .map_err(|_| ())?;
+
+    let Ok(body) = String::from_utf8(body) else {
+        let _ = write_http_response(&mut socket, "400 Bad Request", cors_origin, "").await;
+        return Err(());
+    };
+
+    Ok(NegotiationOutcome::Http {
+        socket,
+        body,
+        cors_origin: cors_origin.map(|origin| origin.to_owned()),
+    })
+}
+
+/// Writes an HTTP response on the given socket, then closes the writing side of the socket.
+///
+/// If `cors_origin` is `Some`, the response contains CORS headers allowing this origin.
+async fn write_http_response(
+    socket: &mut TcpStream,
+    status: &str,
+    cors_origin: Option<&str>,
+    body: &str,
+) -> Result<(), io::Error> {
+    let cors_headers = match cors_origin {
+        Some(origin) => format!(
+            "Access-Control-Allow-Origin: {origin}\r\n\
+            Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+            Access-Control-Allow-Headers: Content-Type\r\n\
+            Vary: Origin\r\n"
+        ),
+        None => String::new(),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: application/json; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        {cors_headers}\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.close().await
+}
+
+/// Wraps around a [`TcpStream`]. Reading from it first yields the content of a buffer of data
+/// that has already been read from the socket, then the data of the socket.
+struct PrefixedSocket {
+    prefix: Vec<u8>,
+    /// Number of bytes at the start of [`PrefixedSocket::prefix`] that have already been read.
+    prefix_offset: usize,
+    socket: TcpStream,
+}
+
+impl AsyncRead for PrefixedSocket {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if this.prefix_offset < this.prefix.len() {
+            let num_bytes = cmp::min(buf.len(), this.prefix.len() - this.prefix_offset);
+            buf[..num_bytes]
+                .copy_from_slice(&this.prefix[this.prefix_offset..this.prefix_offset + num_bytes]);
+            this.prefix_offset += num_bytes;
+            return Poll::Ready(Ok(num_bytes));
+        }
+
+        Pin::new(&mut this.socket).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PrefixedSocket {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_close(cx)
+    }
 }
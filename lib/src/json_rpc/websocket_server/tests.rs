@@ -17,7 +17,10 @@
 
 use super::{Config, Event, WsServer};
 
-use futures::io::{BufReader, BufWriter};
+use futures::{
+    io::{BufReader, BufWriter},
+    prelude::*,
+};
 
 #[test]
 fn basic_works() {
@@ -25,8 +28,10 @@ fn basic_works() {
         let mut server: WsServer<i32> = WsServer::new(Config {
             bind_address: "127.0.0.1:0".parse().unwrap(),
             max_frame_size: 1024 * 1024,
+            max_http_body_size: 1024 * 1024,
             send_buffer_len: 32,
             capacity: 32,
+            cors_allowed_origins: Vec::new(),
         })
         .await
         .unwrap();
@@ -97,3 +102,175 @@ fn basic_works() {
         client_task.await;
     });
 }
+
+#[test]
+fn http_post_works() {
+    async_std::task::block_on(async move {
+        let mut server: WsServer<()> = WsServer::new(Config {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            max_frame_size: 1024 * 1024,
+            max_http_body_size: 1024,
+            send_buffer_len: 32,
+            capacity: 32,
+            cors_allowed_origins: Vec::new(),
+        })
+        .await
+        .unwrap();
+
+        let server_addr = server.local_addr().unwrap();
+
+        let client_task = async_std::task::spawn(async move {
+            let mut socket = async_std::net::TcpStream::connect(server_addr)
+                .await
+                .unwrap();
+            socket
+                .write_all(
+                    b"POST / HTTP/1.1\r\nHost: example.com\r\n\
+                    Content-Type: application/json\r\nContent-Length: 12\r\n\r\nhello world!",
+                )
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.ends_with("\r\n\r\nhello back"));
+        });
+
+        let id = match server.next_event().await {
+            Event::ConnectionOpen { .. } => server.accept(()),
+            _ => panic!(),
+        };
+
+        match server.next_event().await {
+            Event::HttpRequest {
+                connection_id,
+                body,
+                ..
+            } => {
+                assert_eq!(connection_id, id);
+                assert_eq!(body, "hello world!");
+            }
+            _ => panic!(),
+        };
+
+        server.queue_send(id, "hello back".to_owned());
+
+        match server.next_event().await {
+            Event::ConnectionError { connection_id, .. } => assert_eq!(connection_id, id),
+            _ => panic!(),
+        };
+
+        client_task.await;
+    });
+}
+
+#[test]
+fn http_body_too_large() {
+    async_std::task::block_on(async move {
+        let mut server: WsServer<()> = WsServer::new(Config {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            max_frame_size: 1024 * 1024,
+            max_http_body_size: 4,
+            send_buffer_len: 32,
+            capacity: 32,
+            cors_allowed_origins: Vec::new(),
+        })
+        .await
+        .unwrap();
+
+        let server_addr = server.local_addr().unwrap();
+
+        let client_task = async_std::task::spawn(async move {
+            let mut socket = async_std::net::TcpStream::connect(server_addr)
+                .await
+                .unwrap();
+            socket
+                .write_all(b"POST / HTTP/1.1\r\nContent-Length: 12\r\n\r\nhello world!")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 413 "));
+        });
+
+        match server.next_event().await {
+            Event::ConnectionOpen { .. } => server.accept(()),
+            _ => panic!(),
+        };
+
+        assert!(matches!(
+            server.next_event().await,
+            Event::ConnectionError { .. }
+        ));
+
+        client_task.await;
+    });
+}
+
+#[test]
+fn http_cors_only_for_allowed_origins() {
+    async_std::task::block_on(async move {
+        let mut server: WsServer<()> = WsServer::new(Config {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            max_frame_size: 1024 * 1024,
+            max_http_body_size: 1024,
+            send_buffer_len: 32,
+            capacity: 32,
+            cors_allowed_origins: vec!["https://example.com".to_owned()],
+        })
+        .await
+        .unwrap();
+
+        let server_addr = server.local_addr().unwrap();
+
+        let client_task = async_std::task::spawn(async move {
+            let mut socket = async_std::net::TcpStream::connect(server_addr)
+                .await
+                .unwrap();
+            socket
+                .write_all(b"OPTIONS / HTTP/1.1\r\nOrigin: https://example.com\r\n\r\n")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 204 "));
+            assert!(response.contains("\r\nAccess-Control-Allow-Origin: https://example.com\r\n"));
+
+            let mut socket = async_std::net::TcpStream::connect(server_addr)
+                .await
+                .unwrap();
+            socket
+                .write_all(b"OPTIONS / HTTP/1.1\r\nOrigin: https://attacker.example\r\n\r\n")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 405 "));
+            assert!(!response.contains("Access-Control-Allow-Origin"));
+        });
+
+        for _ in 0..2 {
+            match server.next_event().await {
+                Event::ConnectionOpen { .. } => server.accept(()),
+                _ => panic!(),
+            };
+
+            assert!(matches!(
+                server.next_event().await,
+                Event::ConnectionError { .. }
+            ));
+        }
+
+        client_task.await;
+    });
+}
+
+#[test]
+fn is_send_and_sync() {
+    fn is_send_and_sync<T: Send + Sync>() {}
+    is_send_and_sync::<WsServer<()>>();
+}
@@ -181,7 +181,8 @@
 //! documentation.
 //! - A JSON-RPC client, in order to put a convenient-to-use UI on top of the client. See the
 //! [`json_rpc`] module.
-//! - TODO: telemetry
+//! - The capacity to report information about the node to telemetry servers. See the
+//! [`telemetry`] module.
 //!
 
 // The library part of `smoldot` should as pure as possible and shouldn't rely on any environment
@@ -209,6 +210,7 @@ pub mod json_rpc;
 pub mod libp2p;
 pub mod network;
 pub mod sync;
+pub mod telemetry;
 pub mod transactions;
 pub mod trie;
 pub mod verify;
@@ -18,4 +18,5 @@
 #![cfg(feature = "std")]
 #![cfg_attr(docsrs, doc(cfg(feature = "std")))]
 
+pub mod quic;
 pub mod with_buffers;
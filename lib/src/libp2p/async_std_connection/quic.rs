@@ -0,0 +1,428 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "quic")]
+#![cfg_attr(docsrs, doc(cfg(feature = "quic")))]
+
+//! Glue between the `quinn` QUIC library and the libp2p TLS handshake.
+//!
+//! See <https://github.com/libp2p/specs/blob/master/quic/README.md>.
+//!
+//! The configurations returned by [`client_config`] and [`server_config`] present the given
+//! certificate to the remote and verify the certificate of the remote using
+//! [`CertificateVerifier`]. Once a connection is established, the identity of the remote can be
+//! obtained with [`remote_peer_id`]. Each bidirectional QUIC stream can then be wrapped within a
+//! [`QuicStream`].
+
+use crate::libp2p::{connection::tls, PeerId};
+
+use alloc::sync::Arc;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::io::{AsyncRead, AsyncWrite};
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Builds the configuration of the client side of QUIC connections.
+///
+/// The certificate is presented to the remote during the TLS handshake, and should be generated
+/// with [`tls::generate_certificate`] from the libp2p key of the local node.
+pub fn client_config(
+    certificate: &tls::GeneratedCertificate,
+) -> Result<quinn::ClientConfig, rustls::Error> {
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_custom_certificate_verifier(Arc::new(CertificateVerifier))
+        .with_client_auth_cert(
+            vec![rustls::Certificate(certificate.certificate_der.clone())],
+            rustls::PrivateKey(certificate.private_key_pkcs8_der.clone()),
+        )?;
+    crypto.alpn_protocols = vec![tls::ALPN_PROTOCOL.to_vec()];
+    Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+}
+
+/// Builds the configuration of the server side of QUIC connections.
+///
+/// See [`client_config`].
+pub fn server_config(
+    certificate: &tls::GeneratedCertificate,
+) -> Result<quinn::ServerConfig, rustls::Error> {
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_client_cert_verifier(Arc::new(CertificateVerifier))
+        .with_single_cert(
+            vec![rustls::Certificate(certificate.certificate_der.clone())],
+            rustls::PrivateKey(certificate.private_key_pkcs8_der.clone()),
+        )?;
+    crypto.alpn_protocols = vec![tls::ALPN_PROTOCOL.to_vec()];
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Returns the identity of the remote of the given connection, as found in the certificate it
+/// has presented during the TLS handshake.
+///
+/// Returns `None` if the certificate is invalid, which can't happen if the connection has been
+/// established using [`client_config`] or [`server_config`].
+pub fn remote_peer_id(connection: &quinn::Connection) -> Option<PeerId> {
+    // The certificate has already been verified by the `CertificateVerifier`. It is verified
+    // again here in order to extract the identity of the remote.
+    let certificates = connection
+        .peer_identity()?
+        .downcast::<Vec<rustls::Certificate>>()
+        .ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    let certificate = tls::verify_certificate(&certificates.first()?.0, now).ok()?;
+    Some(certificate.into_peer_id())
+}
+
+/// Bidirectional QUIC stream.
+///
+/// Dropping a `quinn` send stream gracefully finishes it. Instead, the stream is reset when the
+/// `QuicStream` is dropped, unless its writing side has been successfully closed with
+/// [`AsyncWrite::poll_close`] beforehand.
+pub struct QuicStream {
+    send: quinn::SendStream,
+    recv: quinn::RecvStream,
+    /// `true` if the writing side has been closed gracefully.
+    finished: bool,
+}
+
+impl QuicStream {
+    /// Builds a new [`QuicStream`] from the two halves of a bidirectional QUIC stream.
+    pub fn new(send: quinn::SendStream, recv: quinn::RecvStream) -> Self {
+        QuicStream {
+            send,
+            recv,
+            finished: false,
+        }
+    }
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncRead::poll_read(Pin::new(&mut self.recv), cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.send), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.send), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let result = futures::ready!(AsyncWrite::poll_close(Pin::new(&mut self.send), cx));
+        if result.is_ok() {
+            self.finished = true;
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl Drop for QuicStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Errors are ignored, as they indicate that the stream is already closed.
+            let _ = self.send.reset(quinn::VarInt::from_u32(0));
+        }
+    }
+}
+
+/// Implementation of the certificate verification of the libp2p TLS handshake. Used both for the
+/// certificates of servers and of clients.
+pub struct CertificateVerifier;
+
+impl CertificateVerifier {
+    fn verify_certificate(
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        now: SystemTime,
+    ) -> Result<tls::VerifiedCertificate, rustls::Error> {
+        // The libp2p specification requires exactly one certificate to be presented.
+        if !intermediates.is_empty() {
+            return Err(rustls::Error::General(
+                "unexpected intermediate certificates".into(),
+            ));
+        }
+
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| rustls::Error::FailedToGetCurrentTime)?;
+        tls::verify_certificate(&end_entity.0, now)
+            .map_err(|err| rustls::Error::General(err.to_string()))
+    }
+
+    fn verify_signature(
+        message: &[u8],
+        certificate: &rustls::Certificate,
+        signature: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
+        let scheme = match signature.scheme {
+            rustls::SignatureScheme::ED25519 => tls::SignatureScheme::Ed25519,
+            rustls::SignatureScheme::ECDSA_NISTP256_SHA256 => {
+                tls::SignatureScheme::EcdsaSecp256r1Sha256
+            }
+            _ => {
+                return Err(rustls::Error::PeerIncompatible(
+                    rustls::PeerIncompatible::NoSignatureSchemesInCommon,
+                ))
+            }
+        };
+
+        Self::verify_certificate(certificate, &[], SystemTime::now())?
+            .verify_signature(scheme, message, signature.signature())
+            .map_err(|_| {
+                rustls::Error::InvalidCertificate(rustls::CertificateError::BadSignature)
+            })?;
+        Ok(rustls::client::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes() -> Vec<rustls::SignatureScheme> {
+        vec![
+            rustls::SignatureScheme::ED25519,
+            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
+        ]
+    }
+}
+
+impl rustls::client::ServerCertVerifier for CertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Self::verify_certificate(end_entity, intermediates, now)?;
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::Certificate,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
+        // Only TLS 1.3 is allowed.
+        Err(rustls::Error::PeerIncompatible(
+            rustls::PeerIncompatible::Tls13RequiredForQuic,
+        ))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::Certificate,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
+        Self::verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        Self::supported_verify_schemes()
+    }
+}
+
+impl rustls::server::ClientCertVerifier for CertificateVerifier {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        true
+    }
+
+    fn client_auth_root_subjects(&self) -> &[rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        now: SystemTime,
+    ) -> Result<rustls::server::ClientCertVerified, rustls::Error> {
+        Self::verify_certificate(end_entity, intermediates, now)?;
+        Ok(rustls::server::ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::Certificate,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
+        // Only TLS 1.3 is allowed.
+        Err(rustls::Error::PeerIncompatible(
+            rustls::PeerIncompatible::Tls13RequiredForQuic,
+        ))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::Certificate,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
+        Self::verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        Self::supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{
+        connection::{tls, NoiseKey},
+        peer_id::PublicKey,
+    };
+    use futures::prelude::*;
+    use std::{
+        net::{Ipv4Addr, SocketAddr, UdpSocket},
+        sync::Arc,
+        time::SystemTime,
+    };
+
+    fn endpoint(certificate: &tls::GeneratedCertificate, accept_incoming: bool) -> quinn::Endpoint {
+        let mut endpoint = quinn::Endpoint::new(
+            quinn::EndpointConfig::default(),
+            if accept_incoming {
+                Some(super::server_config(certificate).unwrap())
+            } else {
+                None
+            },
+            UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap(),
+            Arc::new(quinn::AsyncStdRuntime),
+        )
+        .unwrap();
+        endpoint.set_default_client_config(super::client_config(certificate).unwrap());
+        endpoint
+    }
+
+    #[test]
+    fn verifier_accepts_generated_certificate() {
+        let certificate = tls::generate_certificate(&[1; 32], &[2; 32], [3; 8]);
+        let verified = super::CertificateVerifier::verify_certificate(
+            &rustls::Certificate(certificate.certificate_der),
+            &[],
+            SystemTime::now(),
+        )
+        .unwrap();
+        assert_eq!(
+            verified.into_peer_id(),
+            PublicKey::Ed25519(*NoiseKey::new(&[1; 32]).libp2p_public_ed25519_key()).into_peer_id()
+        );
+    }
+
+    #[test]
+    fn verifier_rejects_intermediates() {
+        let certificate = tls::generate_certificate(&[1; 32], &[2; 32], [3; 8]);
+        let certificate = rustls::Certificate(certificate.certificate_der);
+        assert!(super::CertificateVerifier::verify_certificate(
+            &certificate,
+            &[certificate.clone()],
+            SystemTime::now(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn verifier_rejects_garbage() {
+        assert!(super::CertificateVerifier::verify_certificate(
+            &rustls::Certificate(vec![1, 2, 3, 4]),
+            &[],
+            SystemTime::now(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn connection_and_stream() {
+        async_std::task::block_on(async {
+            let server_key = [1; 32];
+            let client_key = [4; 32];
+            let server = endpoint(
+                &tls::generate_certificate(&server_key, &[2; 32], [3; 8]),
+                true,
+            );
+            let client = endpoint(
+                &tls::generate_certificate(&client_key, &[5; 32], [6; 8]),
+                false,
+            );
+            let server_addr = server.local_addr().unwrap();
+
+            let server_task = async {
+                let connection = server.accept().await.unwrap().await.unwrap();
+                let (send, recv) = connection.accept_bi().await.unwrap();
+                let mut stream = super::QuicStream::new(send, recv);
+                let mut buffer = [0; 5];
+                stream.read_exact(&mut buffer).await.unwrap();
+                stream.write_all(&buffer).await.unwrap();
+                stream.close().await.unwrap();
+                (super::remote_peer_id(&connection).unwrap(), connection)
+            };
+
+            let client_task = async {
+                // The server name is irrelevant, as it isn't verified.
+                let connection = client
+                    .connect(server_addr, "libp2p")
+                    .unwrap()
+                    .await
+                    .unwrap();
+                let (send, recv) = connection.open_bi().await.unwrap();
+                let mut stream = super::QuicStream::new(send, recv);
+                stream.write_all(b"hello").await.unwrap();
+                let mut response = Vec::new();
+                stream.read_to_end(&mut response).await.unwrap();
+                assert_eq!(response, b"hello");
+                (super::remote_peer_id(&connection).unwrap(), connection)
+            };
+
+            let ((client_seen_by_server, _c1), (server_seen_by_client, _c2)) =
+                future::join(server_task, client_task).await;
+
+            let peer_id = |key: [u8; 32]| {
+                PublicKey::Ed25519(*NoiseKey::new(&key).libp2p_public_ed25519_key()).into_peer_id()
+            };
+            assert_eq!(client_seen_by_server, peer_id(client_key));
+            assert_eq!(server_seen_by_client, peer_id(server_key));
+        });
+    }
+}
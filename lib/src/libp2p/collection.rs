@@ -97,6 +97,19 @@ pub enum MultiStreamHandshakeKind {
         /// Multihash encoding of the TLS certificate used by the remote node at the DTLS layer.
         remote_tls_certificate_multihash: Vec<u8>,
     },
+
+    /// The connection is a QUIC connection.
+    ///
+    /// The identity of the remote has already been authenticated during the libp2p TLS
+    /// handshake (see the [`super::connection::tls`] module). No additional handshake is
+    /// performed, and each QUIC stream directly corresponds to a substream.
+    ///
+    /// Contrary to WebRTC, the reading and writing sides of substreams can be closed.
+    Quic {
+        /// Identity of the remote, as found in the certificate it has presented during the TLS
+        /// handshake.
+        remote_peer_id: PeerId,
+    },
 }
 
 /// Configuration for a [`Network`].
@@ -149,65 +149,90 @@ where
         request_response_protocols: Arc<[ConfigRequestResponse]>,
         ping_protocol: Arc<str>,
     ) -> Self {
-        // In the WebRTC handshake, the Noise prologue must be set to `"libp2p-webrtc-noise:"`
-        // followed with the multihash-encoded fingerprints of the initiator's certificate
-        // and the receiver's certificate.
-        // See <https://github.com/libp2p/specs/pull/412>.
-        let noise_prologue = {
-            let MultiStreamHandshakeKind::WebRtc {
+        let established_config = established::Config {
+            notifications_protocols: notification_protocols
+                .iter()
+                .map(|net| established::ConfigNotifications {
+                    name: net.config.protocol_name.clone(), // TODO: clone :-/
+                    max_handshake_size: net.config.max_handshake_size,
+                    max_notification_size: net.config.max_notification_size,
+                })
+                .collect(),
+            request_protocols: request_response_protocols.to_vec(), // TODO: overhead
+            max_inbound_substreams,
+            randomness_seed,
+            ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
+            ping_interval: Duration::from_secs(20),   // TODO: hardcoded
+            ping_timeout: Duration::from_secs(10),    // TODO: hardcoded
+            first_out_ping: now + Duration::from_secs(2), // TODO: hardcoded
+        };
+
+        match handshake_kind {
+            MultiStreamHandshakeKind::WebRtc {
                 local_tls_certificate_multihash,
                 remote_tls_certificate_multihash,
-            } = handshake_kind;
-            const PREFIX: &[u8] = b"libp2p-webrtc-noise:";
-            let mut out = Vec::with_capacity(
-                PREFIX.len()
-                    + local_tls_certificate_multihash.len()
-                    + remote_tls_certificate_multihash.len(),
-            );
-            out.extend_from_slice(PREFIX);
-            if is_initiator {
-                out.extend_from_slice(&local_tls_certificate_multihash);
-                out.extend_from_slice(&remote_tls_certificate_multihash);
-            } else {
-                out.extend_from_slice(&remote_tls_certificate_multihash);
-                out.extend_from_slice(&local_tls_certificate_multihash);
-            }
-            out
-        };
+            } => {
+                // In the WebRTC handshake, the Noise prologue must be set to
+                // `"libp2p-webrtc-noise:"` followed with the multihash-encoded fingerprints of
+                // the initiator's certificate and the receiver's certificate.
+                // See <https://github.com/libp2p/specs/pull/412>.
+                let noise_prologue = {
+                    const PREFIX: &[u8] = b"libp2p-webrtc-noise:";
+                    let mut out = Vec::with_capacity(
+                        PREFIX.len()
+                            + local_tls_certificate_multihash.len()
+                            + remote_tls_certificate_multihash.len(),
+                    );
+                    out.extend_from_slice(PREFIX);
+                    if is_initiator {
+                        out.extend_from_slice(&local_tls_certificate_multihash);
+                        out.extend_from_slice(&remote_tls_certificate_multihash);
+                    } else {
+                        out.extend_from_slice(&remote_tls_certificate_multihash);
+                        out.extend_from_slice(&local_tls_certificate_multihash);
+                    }
+                    out
+                };
 
-        MultiStreamConnectionTask {
-            connection: MultiStreamConnectionTaskInner::Handshake {
-                handshake: Some(noise::HandshakeInProgress::new(noise::Config {
-                    key: &noise_key,
-                    // It's the "server" that initiates the Noise handshake.
-                    is_initiator: !is_initiator,
-                    prologue: &noise_prologue,
-                })),
-                opened_substream: None,
-                handshake_read_buffer: Vec::new(),
-                handshake_read_buffer_partial_read: 0,
-                extra_open_substreams: hashbrown::HashMap::with_capacity_and_hasher(
-                    0,
-                    Default::default(),
-                ),
-                established: Some(established::MultiStream::webrtc(established::Config {
-                    notifications_protocols: notification_protocols
-                        .iter()
-                        .map(|net| established::ConfigNotifications {
-                            name: net.config.protocol_name.clone(), // TODO: clone :-/
-                            max_handshake_size: net.config.max_handshake_size,
-                            max_notification_size: net.config.max_notification_size,
-                        })
-                        .collect(),
-                    request_protocols: request_response_protocols.to_vec(), // TODO: overhead
-                    max_inbound_substreams,
-                    randomness_seed,
-                    ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
-                    ping_interval: Duration::from_secs(20),   // TODO: hardcoded
-                    ping_timeout: Duration::from_secs(10),    // TODO: hardcoded
-                    first_out_ping: now + Duration::from_secs(2), // TODO: hardcoded
-                })),
-            },
+                MultiStreamConnectionTask {
+                    connection: MultiStreamConnectionTaskInner::Handshake {
+                        handshake: Some(noise::HandshakeInProgress::new(noise::Config {
+                            key: &noise_key,
+                            // It's the "server" that initiates the Noise handshake.
+                            is_initiator: !is_initiator,
+                            prologue: &noise_prologue,
+                        })),
+                        opened_substream: None,
+                        handshake_read_buffer: Vec::new(),
+                        handshake_read_buffer_partial_read: 0,
+                        extra_open_substreams: hashbrown::HashMap::with_capacity_and_hasher(
+                            0,
+                            Default::default(),
+                        ),
+                        established: Some(established::MultiStream::webrtc(established_config)),
+                    },
+                }
+            }
+            MultiStreamHandshakeKind::Quic { remote_peer_id } => {
+                // The remote has already been authenticated by the TLS handshake, meaning that
+                // the connection is immediately established.
+                MultiStreamConnectionTask {
+                    connection: MultiStreamConnectionTaskInner::Established {
+                        established: established::MultiStream::quic(established_config),
+                        handshake_substream: None,
+                        handshake_finished_message_to_send: Some(remote_peer_id),
+                        outbound_substreams_map: hashbrown::HashMap::with_capacity_and_hasher(
+                            0,
+                            Default::default(),
+                        ),
+                        outbound_substreams_reverse: hashbrown::HashMap::with_capacity_and_hasher(
+                            0,
+                            Default::default(),
+                        ),
+                        notifications_in_open_cancel_acknowledgments: VecDeque::with_capacity(4),
+                    },
+                }
+            }
         }
     }
 
@@ -780,7 +805,8 @@ where
     /// writing side of the substream was still open, then the user should reset that substream.
     ///
     /// In the case of a WebRTC connection, the [`ReadWrite::incoming_buffer`] and
-    /// [`ReadWrite::outgoing_buffer`] must always be `Some`.
+    /// [`ReadWrite::outgoing_buffer`] must always be `Some`. In the case of a QUIC connection,
+    /// they can be `None` if the corresponding side of the QUIC stream has been closed.
     ///
     /// # Panic
     ///
@@ -795,8 +821,14 @@ where
     ) -> SubstreamFate {
         // In WebRTC, the reading and writing sides are never closed.
         // Note that the `established::MultiStream` state machine also performs this check, but
-        // we do it here again because we're not necessarily in the ̀`established` state.
-        assert!(read_write.incoming_buffer.is_some() && read_write.outgoing_buffer.is_some());
+        // we do it here again because we're not necessarily in the ̀`established` state. Only
+        // WebRTC connections go through the handshake state.
+        if matches!(
+            self.connection,
+            MultiStreamConnectionTaskInner::Handshake { .. }
+        ) {
+            assert!(read_write.incoming_buffer.is_some() && read_write.outgoing_buffer.is_some());
+        }
 
         match &mut self.connection {
             MultiStreamConnectionTaskInner::Handshake {
@@ -87,6 +87,11 @@ pub struct MultiStream<TNow, TSubId, TRqUd, TNotifUd> {
     ping_interval: Duration,
     /// See [`Config::ping_timeout`].
     ping_timeout: Duration,
+
+    /// If `true`, the data of each substream is wrapped within Protobuf frames as defined in the
+    /// WebRTC specification, and the reading and writing sides of substreams are never closed.
+    /// If `false`, the data of each substream is sent and received as-is, like in QUIC.
+    webrtc_framing: bool,
 }
 
 struct Substream<TNow, TRqUd, TNotifUd> {
@@ -113,8 +118,20 @@ where
     TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
     TSubId: Clone + PartialEq + Eq + Hash,
 {
-    /// Creates a new connection from the given configuration.
+    /// Creates a new WebRTC connection from the given configuration.
     pub fn webrtc(config: Config<TNow>) -> MultiStream<TNow, TSubId, TRqUd, TNotifUd> {
+        Self::new(config, true)
+    }
+
+    /// Creates a new QUIC connection from the given configuration.
+    pub fn quic(config: Config<TNow>) -> MultiStream<TNow, TSubId, TRqUd, TNotifUd> {
+        Self::new(config, false)
+    }
+
+    fn new(
+        config: Config<TNow>,
+        webrtc_framing: bool,
+    ) -> MultiStream<TNow, TSubId, TRqUd, TNotifUd> {
         // TODO: check conflicts between protocol names?
 
         // We expect at maximum one parallel request per protocol, plus one substream per direction
@@ -154,6 +171,7 @@ where
             ping_protocol: config.ping_protocol,
             ping_interval: config.ping_interval,
             ping_timeout: config.ping_timeout,
+            webrtc_framing,
         }
     }
 
@@ -281,7 +299,8 @@ where
     /// [`MultiStream::pull_event`] to empty the queue of events between calls to this method.
     ///
     /// In the case of a WebRTC connection, the [`ReadWrite::incoming_buffer`] and
-    /// [`ReadWrite::outgoing_buffer`] must always be `Some`.
+    /// [`ReadWrite::outgoing_buffer`] must always be `Some`. In the case of a QUIC connection,
+    /// they can be `None` if the corresponding side of the QUIC stream has been closed.
     ///
     /// # Panic
     ///
@@ -297,7 +316,9 @@ where
         let mut substream = self.in_substreams.get_mut(substream_id).unwrap();
 
         // In WebRTC, the reading and writing side is never closed.
-        assert!(read_write.incoming_buffer.is_some() && read_write.outgoing_buffer.is_some());
+        if self.webrtc_framing {
+            assert!(read_write.incoming_buffer.is_some() && read_write.outgoing_buffer.is_some());
+        }
 
         // Reading/writing the ping substream is used to queue new outgoing pings.
         if Some(substream_id) == self.ping_substream.as_ref() {
@@ -322,218 +343,240 @@ where
                 return SubstreamFate::Continue;
             }
 
-            // In the situation where there's not enough space in the outgoing buffer to write an
-            // outgoing Protobuf frame, we just return immediately.
-            // This is necessary because calling `substream.read_write` can generate a write
-            // close message.
-            // TODO: this is error-prone, as we have no guarantee that the outgoing buffer will ever be > 6 bytes, for example in principle the API user could decide to use only a write buffer of 2 bytes, although that would be a very stupid thing to do
-            if read_write.outgoing_buffer_available() < 6 {
-                return SubstreamFate::Continue;
-            }
-
             // If this flag is still `false` at the end of the loop, we break out of it.
             let mut continue_looping = false;
 
-            // The incoming data is not directly the data of the substream. Instead, everything
-            // is wrapped within a Protobuf frame. For this reason, we first transfer the data to
-            // a buffer.
-            //
-            // According to the libp2p WebRTC spec, a frame and its length prefix must not be
-            // larger than 16kiB, meaning that the read buffer never has to exceed this size.
-            // TODO: this is very suboptimal; improve
-            if let Some(incoming_buffer) = read_write.incoming_buffer {
-                // TODO: reset the substream if `remote_writing_side_closed`
-                let max_to_transfer =
-                    cmp::min(incoming_buffer.len(), 16384 - substream.read_buffer.len());
-                substream
-                    .read_buffer
-                    .extend_from_slice(&incoming_buffer[..max_to_transfer]);
-                debug_assert!(substream.read_buffer.len() <= 16384);
-                if max_to_transfer != incoming_buffer.len() {
-                    continue_looping = true;
-                }
-                read_write.advance_read(max_to_transfer);
-            }
+            let event = if !self.webrtc_framing {
+                // Without framing, the substream state machine directly reads from and writes to
+                // the underlying stream.
+                let read_bytes_before = read_write.read_bytes;
+                let written_bytes_before = read_write.written_bytes;
 
-            // Try to parse the content of `self.read_buffer`.
-            // If the content of `self.read_buffer` is an incomplete frame, the flags will be
-            // `None` and the message will be `&[]`.
-            let (protobuf_frame_size, flags, message_within_frame) = {
-                let mut parser = nom::combinator::complete::<_, _, nom::error::Error<&[u8]>, _>(
-                    nom::combinator::map_parser(
-                        nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
-                        protobuf::message_decode! {
-                            #[optional] flags = 1 => protobuf::enum_tag_decode,
-                            #[optional] message = 2 => protobuf::bytes_tag_decode,
-                        },
-                    ),
-                );
-
-                match nom::Finish::finish(parser(&substream.read_buffer)) {
-                    Ok((rest, framed_message)) => {
-                        let protobuf_frame_size = substream.read_buffer.len() - rest.len();
-                        (
-                            protobuf_frame_size,
-                            framed_message.flags,
-                            framed_message.message.unwrap_or(&[][..]),
-                        )
-                    }
-                    Err(err) if err.code == nom::error::ErrorKind::Eof => {
-                        // TODO: reset the substream if incoming_buffer is full, as it means that the frame is too large, and remove the debug_assert below
-                        debug_assert!(substream.read_buffer.len() < 16384);
-                        (0, None, &[][..])
-                    }
-                    Err(_) => {
-                        // Message decoding error.
-                        // TODO: no, must ask the state machine to reset
-                        return SubstreamFate::Reset;
-                    }
-                }
-            };
+                let (substream_update, event) =
+                    substream.inner.take().unwrap().read_write(read_write);
+                substream.inner = substream_update;
 
-            let event = if protobuf_frame_size != 0
-                && message_within_frame.len() <= substream.read_buffer_partial_read
-            {
-                // If the substream state machine has already processed all the data within
-                // `read_buffer`, process the flags of the current protobuf frame, discard that
-                // protobuf frame, and loop again.
-                continue_looping = true;
-
-                // Discard the data.
-                substream.read_buffer_partial_read = 0;
-                substream.read_buffer = substream
-                    .read_buffer
-                    .split_at(protobuf_frame_size)
-                    .1
-                    .to_vec();
-
-                // Process the flags.
-                // Note that the `STOP_SENDING` flag is ignored.
-
-                // If the remote has sent a `FIN` or `RESET_STREAM` flag, mark the remote writing
-                // side as closed.
-                if flags.map_or(false, |f| f == 0 || f == 2) {
-                    substream.remote_writing_side_closed = true;
+                // Continue looping as the substream might have more data to read or write.
+                if read_write.read_bytes != read_bytes_before
+                    || read_write.written_bytes != written_bytes_before
+                {
+                    continue_looping = true;
                 }
 
-                // If the remote has sent a `RESET_STREAM` flag, also reset the substream.
-                if flags.map_or(false, |f| f == 2) {
-                    substream.inner.take().unwrap().reset()
-                } else {
-                    None
-                }
+                event
             } else {
-                // We allocate a buffer where the substream state machine will temporarily write
-                // out its data. The size of the buffer is capped in order to prevent the substream
-                // from generating data that wouldn't fit in a single protobuf frame.
-                let mut intermediary_write_buffer =
-                    vec![
-                        0;
-                        cmp::min(read_write.outgoing_buffer_available(), 16384).saturating_sub(10)
-                    ]; // TODO: this -10 calculation is hacky because we need to account for the variable length prefixes everywhere
-
-                let mut sub_read_write = ReadWrite {
-                    now: read_write.now.clone(),
-                    incoming_buffer: if substream.remote_writing_side_closed {
-                        None
-                    } else {
-                        Some(&message_within_frame[substream.read_buffer_partial_read..])
-                    },
-                    outgoing_buffer: if substream.local_writing_side_closed {
-                        None
-                    } else {
-                        Some((&mut intermediary_write_buffer, &mut []))
-                    },
-                    read_bytes: 0,
-                    written_bytes: 0,
-                    wake_up_after: None,
-                };
-
-                let (substream_update, event) = substream
-                    .inner
-                    .take()
-                    .unwrap()
-                    .read_write(&mut sub_read_write);
-
-                substream.inner = substream_update;
-                substream.read_buffer_partial_read += sub_read_write.read_bytes;
-                if let Some(wake_up_after) = &sub_read_write.wake_up_after {
-                    read_write.wake_up_after(wake_up_after)
+                // In the situation where there's not enough space in the outgoing buffer to write an
+                // outgoing Protobuf frame, we just return immediately.
+                // This is necessary because calling `substream.read_write` can generate a write
+                // close message.
+                // TODO: this is error-prone, as we have no guarantee that the outgoing buffer will ever be > 6 bytes, for example in principle the API user could decide to use only a write buffer of 2 bytes, although that would be a very stupid thing to do
+                if read_write.outgoing_buffer_available() < 6 {
+                    return SubstreamFate::Continue;
                 }
 
-                // Continue looping as the substream might have more data to read or write.
-                if sub_read_write.read_bytes != 0 || sub_read_write.written_bytes != 0 {
-                    continue_looping = true;
+                // The incoming data is not directly the data of the substream. Instead, everything
+                // is wrapped within a Protobuf frame. For this reason, we first transfer the data to
+                // a buffer.
+                //
+                // According to the libp2p WebRTC spec, a frame and its length prefix must not be
+                // larger than 16kiB, meaning that the read buffer never has to exceed this size.
+                // TODO: this is very suboptimal; improve
+                if let Some(incoming_buffer) = read_write.incoming_buffer {
+                    // TODO: reset the substream if `remote_writing_side_closed`
+                    let max_to_transfer =
+                        cmp::min(incoming_buffer.len(), 16384 - substream.read_buffer.len());
+                    substream
+                        .read_buffer
+                        .extend_from_slice(&incoming_buffer[..max_to_transfer]);
+                    debug_assert!(substream.read_buffer.len() <= 16384);
+                    if max_to_transfer != incoming_buffer.len() {
+                        continue_looping = true;
+                    }
+                    read_write.advance_read(max_to_transfer);
                 }
 
-                // Determine whether we should send a message on that substream with a specific
-                // flag.
-                let flag_to_write_out = if substream.inner.is_none()
-                    && (!substream.remote_writing_side_closed
-                        || sub_read_write.outgoing_buffer.is_some())
-                {
-                    // Send a `RESET_STREAM` if the state machine has reset while a side was still
-                    // open.
-                    Some(2)
-                } else if !substream.local_writing_side_closed
-                    && sub_read_write.outgoing_buffer.is_none()
-                {
-                    // Send a `FIN` if the state machine has closed the writing side while it
-                    // wasn't closed before.
-                    substream.local_writing_side_closed = true;
-                    Some(0)
-                } else {
-                    None
+                // Try to parse the content of `self.read_buffer`.
+                // If the content of `self.read_buffer` is an incomplete frame, the flags will be
+                // `None` and the message will be `&[]`.
+                let (protobuf_frame_size, flags, message_within_frame) = {
+                    let mut parser = nom::combinator::complete::<_, _, nom::error::Error<&[u8]>, _>(
+                        nom::combinator::map_parser(
+                            nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
+                            protobuf::message_decode! {
+                                #[optional] flags = 1 => protobuf::enum_tag_decode,
+                                #[optional] message = 2 => protobuf::bytes_tag_decode,
+                            },
+                        ),
+                    );
+
+                    match nom::Finish::finish(parser(&substream.read_buffer)) {
+                        Ok((rest, framed_message)) => {
+                            let protobuf_frame_size = substream.read_buffer.len() - rest.len();
+                            (
+                                protobuf_frame_size,
+                                framed_message.flags,
+                                framed_message.message.unwrap_or(&[][..]),
+                            )
+                        }
+                        Err(err) if err.code == nom::error::ErrorKind::Eof => {
+                            // TODO: reset the substream if incoming_buffer is full, as it means that the frame is too large, and remove the debug_assert below
+                            debug_assert!(substream.read_buffer.len() < 16384);
+                            (0, None, &[][..])
+                        }
+                        Err(_) => {
+                            // Message decoding error.
+                            // TODO: no, must ask the state machine to reset
+                            return SubstreamFate::Reset;
+                        }
+                    }
                 };
 
-                // Send out message.
-                if flag_to_write_out.is_some() || sub_read_write.written_bytes != 0 {
-                    let written_bytes = sub_read_write.written_bytes;
-                    drop(sub_read_write);
+                if protobuf_frame_size != 0
+                    && message_within_frame.len() <= substream.read_buffer_partial_read
+                {
+                    // If the substream state machine has already processed all the data within
+                    // `read_buffer`, process the flags of the current protobuf frame, discard that
+                    // protobuf frame, and loop again.
+                    continue_looping = true;
 
-                    debug_assert!(written_bytes <= intermediary_write_buffer.len());
+                    // Discard the data.
+                    substream.read_buffer_partial_read = 0;
+                    substream.read_buffer = substream
+                        .read_buffer
+                        .split_at(protobuf_frame_size)
+                        .1
+                        .to_vec();
+
+                    // Process the flags.
+                    // Note that the `STOP_SENDING` flag is ignored.
+
+                    // If the remote has sent a `FIN` or `RESET_STREAM` flag, mark the remote writing
+                    // side as closed.
+                    if flags.map_or(false, |f| f == 0 || f == 2) {
+                        substream.remote_writing_side_closed = true;
+                    }
 
-                    let protobuf_frame = {
-                        let flag_out = flag_to_write_out
-                            .into_iter()
-                            .flat_map(|f| protobuf::enum_tag_encode(1, f));
-                        let message_out = if written_bytes != 0 {
-                            Some(&intermediary_write_buffer[..written_bytes])
+                    // If the remote has sent a `RESET_STREAM` flag, also reset the substream.
+                    if flags.map_or(false, |f| f == 2) {
+                        substream.inner.take().unwrap().reset()
+                    } else {
+                        None
+                    }
+                } else {
+                    // We allocate a buffer where the substream state machine will temporarily write
+                    // out its data. The size of the buffer is capped in order to prevent the substream
+                    // from generating data that wouldn't fit in a single protobuf frame.
+                    let mut intermediary_write_buffer =
+                        vec![
+                            0;
+                            cmp::min(read_write.outgoing_buffer_available(), 16384)
+                                .saturating_sub(10)
+                        ]; // TODO: this -10 calculation is hacky because we need to account for the variable length prefixes everywhere
+
+                    let mut sub_read_write = ReadWrite {
+                        now: read_write.now.clone(),
+                        incoming_buffer: if substream.remote_writing_side_closed {
+                            None
                         } else {
+                            Some(&message_within_frame[substream.read_buffer_partial_read..])
+                        },
+                        outgoing_buffer: if substream.local_writing_side_closed {
                             None
-                        }
-                        .into_iter()
-                        .flat_map(|m| protobuf::bytes_tag_encode(2, m));
-                        flag_out
-                            .map(either::Left)
-                            .chain(message_out.map(either::Right))
+                        } else {
+                            Some((&mut intermediary_write_buffer, &mut []))
+                        },
+                        read_bytes: 0,
+                        written_bytes: 0,
+                        wake_up_after: None,
                     };
 
-                    let protobuf_frame_len = protobuf_frame.clone().fold(0, |mut l, b| {
-                        l += AsRef::<[u8]>::as_ref(&b).len();
-                        l
-                    });
-
-                    // The spec mentions that a frame plus its length prefix shouldn't exceed
-                    // 16kiB. This is normally ensured by forbidding the substream from writing
-                    // more data than would fit in 16kiB.
-                    debug_assert!(protobuf_frame_len <= 16384);
-                    debug_assert!(
-                        util::leb128::encode_usize(protobuf_frame_len).count() + protobuf_frame_len
-                            <= 16384
-                    );
-                    for byte in util::leb128::encode_usize(protobuf_frame_len) {
-                        read_write.write_out(&[byte]);
+                    let (substream_update, event) = substream
+                        .inner
+                        .take()
+                        .unwrap()
+                        .read_write(&mut sub_read_write);
+
+                    substream.inner = substream_update;
+                    substream.read_buffer_partial_read += sub_read_write.read_bytes;
+                    if let Some(wake_up_after) = &sub_read_write.wake_up_after {
+                        read_write.wake_up_after(wake_up_after)
                     }
-                    for buffer in protobuf_frame {
-                        read_write.write_out(AsRef::<[u8]>::as_ref(&buffer));
+
+                    // Continue looping as the substream might have more data to read or write.
+                    if sub_read_write.read_bytes != 0 || sub_read_write.written_bytes != 0 {
+                        continue_looping = true;
                     }
 
-                    // We continue looping because the substream might have more data to send.
-                    continue_looping = true;
-                }
+                    // Determine whether we should send a message on that substream with a specific
+                    // flag.
+                    let flag_to_write_out = if substream.inner.is_none()
+                        && (!substream.remote_writing_side_closed
+                            || sub_read_write.outgoing_buffer.is_some())
+                    {
+                        // Send a `RESET_STREAM` if the state machine has reset while a side was still
+                        // open.
+                        Some(2)
+                    } else if !substream.local_writing_side_closed
+                        && sub_read_write.outgoing_buffer.is_none()
+                    {
+                        // Send a `FIN` if the state machine has closed the writing side while it
+                        // wasn't closed before.
+                        substream.local_writing_side_closed = true;
+                        Some(0)
+                    } else {
+                        None
+                    };
 
-                event
+                    // Send out message.
+                    if flag_to_write_out.is_some() || sub_read_write.written_bytes != 0 {
+                        let written_bytes = sub_read_write.written_bytes;
+                        drop(sub_read_write);
+
+                        debug_assert!(written_bytes <= intermediary_write_buffer.len());
+
+                        let protobuf_frame = {
+                            let flag_out = flag_to_write_out
+                                .into_iter()
+                                .flat_map(|f| protobuf::enum_tag_encode(1, f));
+                            let message_out = if written_bytes != 0 {
+                                Some(&intermediary_write_buffer[..written_bytes])
+                            } else {
+                                None
+                            }
+                            .into_iter()
+                            .flat_map(|m| protobuf::bytes_tag_encode(2, m));
+                            flag_out
+                                .map(either::Left)
+                                .chain(message_out.map(either::Right))
+                        };
+
+                        let protobuf_frame_len = protobuf_frame.clone().fold(0, |mut l, b| {
+                            l += AsRef::<[u8]>::as_ref(&b).len();
+                            l
+                        });
+
+                        // The spec mentions that a frame plus its length prefix shouldn't exceed
+                        // 16kiB. This is normally ensured by forbidding the substream from writing
+                        // more data than would fit in 16kiB.
+                        debug_assert!(protobuf_frame_len <= 16384);
+                        debug_assert!(
+                            util::leb128::encode_usize(protobuf_frame_len).count()
+                                + protobuf_frame_len
+                                <= 16384
+                        );
+                        for byte in util::leb128::encode_usize(protobuf_frame_len) {
+                            read_write.write_out(&[byte]);
+                        }
+                        for buffer in protobuf_frame {
+                            read_write.write_out(AsRef::<[u8]>::as_ref(&buffer));
+                        }
+
+                        // We continue looping because the substream might have more data to send.
+                        continue_looping = true;
+                    }
+
+                    event
+                }
             };
 
             match event {
@@ -591,7 +634,7 @@ where
             }
 
             // WebRTC never closes the writing side.
-            debug_assert!(read_write.outgoing_buffer.is_some());
+            debug_assert!(!self.webrtc_framing || read_write.outgoing_buffer.is_some());
 
             if substream.inner.is_none() {
                 if Some(substream_id) == self.ping_substream.as_ref() {
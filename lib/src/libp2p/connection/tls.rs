@@ -0,0 +1,727 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Certificates of the libp2p TLS handshake.
+//!
+//! The libp2p TLS handshake, used in particular by QUIC connections, is a regular TLS 1.3
+//! handshake during which both sides present a self-signed X.509 certificate. This certificate
+//! contains an extension holding the libp2p public key of the node and a signature, made with
+//! this libp2p key, of the public key of the certificate. This is what makes it possible to
+//! authenticate the `PeerId` of the remote.
+//!
+//! See <https://github.com/libp2p/specs/blob/master/tls/tls.md>.
+//!
+//! This module doesn't implement TLS itself. It only generates the certificate of the local node
+//! and verifies the certificates of remotes, and is meant to be plugged into a TLS library.
+//!
+//! # Usage
+//!
+//! Use [`generate_certificate`] to generate the certificate and private key to present to
+//! remotes, and [`verify_certificate`] in order to verify the certificate presented by a remote.
+//! The [`VerifiedCertificate`] returned by [`verify_certificate`] can then be used to check the
+//! signatures of the TLS handshake through [`VerifiedCertificate::verify_signature`].
+//!
+//! The ALPN protocol negotiated during the handshake must be [`ALPN_PROTOCOL`].
+//!
+
+use super::super::peer_id::{FromProtobufEncodingError, PeerId, PublicKey};
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Name of the ALPN protocol that must be negotiated during the TLS handshake.
+pub const ALPN_PROTOCOL: &[u8] = b"libp2p";
+
+/// Certificate generated by [`generate_certificate`].
+#[derive(Clone)]
+pub struct GeneratedCertificate {
+    /// DER encoding of the X.509 certificate.
+    pub certificate_der: Vec<u8>,
+    /// DER encoding of the PKCS#8 structure containing the private key of the certificate.
+    pub private_key_pkcs8_der: Vec<u8>,
+}
+
+/// Generates a self-signed certificate signed with the given libp2p Ed25519 key.
+///
+/// The certificate uses an Ed25519 key whose private key is `certificate_private_key`. This
+/// parameter as well as `serial_number` should be randomly generated.
+pub fn generate_certificate(
+    libp2p_ed25519_private_key: &[u8; 32],
+    certificate_private_key: &[u8; 32],
+    mut serial_number: [u8; 8],
+) -> GeneratedCertificate {
+    let certificate_signing_key = ed25519_zebra::SigningKey::from(*certificate_private_key);
+    let certificate_public_key = ed25519_zebra::VerificationKey::from(&certificate_signing_key);
+
+    let algorithm_identifier = der_sequence(&[&der_element(TAG_OID, ED25519_OID)]);
+
+    let subject_public_key_info = der_sequence(&[
+        &algorithm_identifier,
+        &der_bit_string(certificate_public_key.as_ref()),
+    ]);
+
+    // The libp2p key signs the `SubjectPublicKeyInfo` structure of the certificate.
+    let signed_key = {
+        let libp2p_signing_key = ed25519_zebra::SigningKey::from(*libp2p_ed25519_private_key);
+        let libp2p_public_key = ed25519_zebra::VerificationKey::from(&libp2p_signing_key);
+
+        let mut message =
+            Vec::with_capacity(SIGNATURE_PREFIX.len() + subject_public_key_info.len());
+        message.extend_from_slice(SIGNATURE_PREFIX);
+        message.extend_from_slice(&subject_public_key_info);
+        let signature = <[u8; 64]>::from(libp2p_signing_key.sign(&message));
+
+        der_sequence(&[
+            &der_element(
+                TAG_OCTET_STRING,
+                &PublicKey::Ed25519(libp2p_public_key.into()).to_protobuf_encoding(),
+            ),
+            &der_element(TAG_OCTET_STRING, &signature),
+        ])
+    };
+
+    // The serial number must be a strictly positive integer whose encoding is minimal.
+    serial_number[0] = (serial_number[0] & 0x7f) | 0x40;
+
+    // The content of the name doesn't matter, but RFC 5280 requires it to be non-empty.
+    let name = der_sequence(&[&der_element(
+        TAG_SET,
+        &der_sequence(&[
+            &der_element(TAG_OID, COMMON_NAME_OID),
+            &der_element(TAG_UTF8_STRING, b"libp2p"),
+        ]),
+    )]);
+
+    // Similar to other libp2p implementations, the certificate is valid from 1975 until the
+    // year 4096, which avoids having to know the current time.
+    let validity = der_sequence(&[
+        &der_element(TAG_UTC_TIME, b"750101000000Z"),
+        &der_element(TAG_GENERALIZED_TIME, b"40960101000000Z"),
+    ]);
+
+    let extensions = der_element(
+        TAG_EXTENSIONS,
+        &der_sequence(&[&der_sequence(&[
+            &der_element(TAG_OID, LIBP2P_EXTENSION_OID),
+            &der_element(TAG_BOOLEAN, &[0xff]),
+            &der_element(TAG_OCTET_STRING, &signed_key),
+        ])]),
+    );
+
+    let tbs_certificate = der_sequence(&[
+        &der_element(TAG_VERSION, &der_element(TAG_INTEGER, &[2])),
+        &der_element(TAG_INTEGER, &serial_number),
+        &algorithm_identifier,
+        &name,
+        &validity,
+        &name,
+        &subject_public_key_info,
+        &extensions,
+    ]);
+
+    let certificate_signature = <[u8; 64]>::from(certificate_signing_key.sign(&tbs_certificate));
+
+    GeneratedCertificate {
+        certificate_der: der_sequence(&[
+            &tbs_certificate,
+            &algorithm_identifier,
+            &der_bit_string(&certificate_signature),
+        ]),
+        // See RFC 8410.
+        private_key_pkcs8_der: der_sequence(&[
+            &der_element(TAG_INTEGER, &[0]),
+            &algorithm_identifier,
+            &der_element(
+                TAG_OCTET_STRING,
+                &der_element(TAG_OCTET_STRING, certificate_private_key),
+            ),
+        ]),
+    }
+}
+
+/// Verifies a certificate presented by a remote during the TLS handshake.
+///
+/// `now_from_unix_epoch` is used to check the validity period of the certificate.
+pub fn verify_certificate(
+    certificate_der: &[u8],
+    now_from_unix_epoch: Duration,
+) -> Result<VerifiedCertificate, VerifyError> {
+    let certificate = decode_certificate(certificate_der).ok_or(VerifyError::InvalidDer)?;
+
+    // Check the validity period.
+    {
+        let now = i64::try_from(now_from_unix_epoch.as_secs()).unwrap_or(i64::MAX);
+        let not_before = decode_time(certificate.not_before).ok_or(VerifyError::InvalidDer)?;
+        let not_after = decode_time(certificate.not_after).ok_or(VerifyError::InvalidDer)?;
+        if now < not_before || now > not_after {
+            return Err(VerifyError::OutsideValidityPeriod);
+        }
+    }
+
+    // Find the libp2p extension. Any other critical extension results in an error, as we don't
+    // know how to interpret it.
+    let mut signed_key = None;
+    let mut extensions = certificate.extensions;
+    while !extensions.is_empty() {
+        let (extension, rest) =
+            decode_element(extensions, TAG_SEQUENCE).ok_or(VerifyError::InvalidDer)?;
+        extensions = rest;
+
+        let (oid, extension) = decode_element(extension, TAG_OID).ok_or(VerifyError::InvalidDer)?;
+        let (critical, extension) = match decode_element(extension, TAG_BOOLEAN) {
+            Some((value, rest)) => (value != [0], rest),
+            None => (false, extension),
+        };
+        let (value, rest) =
+            decode_element(extension, TAG_OCTET_STRING).ok_or(VerifyError::InvalidDer)?;
+        if !rest.is_empty() {
+            return Err(VerifyError::InvalidDer);
+        }
+
+        if oid == LIBP2P_EXTENSION_OID {
+            if signed_key.is_some() {
+                return Err(VerifyError::InvalidDer);
+            }
+            signed_key = Some(value);
+        } else if critical {
+            return Err(VerifyError::UnsupportedCriticalExtension);
+        }
+    }
+
+    // Check the signature of the libp2p key.
+    let peer_id = {
+        let signed_key = signed_key.ok_or(VerifyError::MissingLibp2pExtension)?;
+        let (signed_key, rest) =
+            decode_element(signed_key, TAG_SEQUENCE).ok_or(VerifyError::InvalidDer)?;
+        if !rest.is_empty() {
+            return Err(VerifyError::InvalidDer);
+        }
+        let (public_key, signed_key) =
+            decode_element(signed_key, TAG_OCTET_STRING).ok_or(VerifyError::InvalidDer)?;
+        let (signature, rest) =
+            decode_element(signed_key, TAG_OCTET_STRING).ok_or(VerifyError::InvalidDer)?;
+        if !rest.is_empty() {
+            return Err(VerifyError::InvalidDer);
+        }
+
+        let public_key = PublicKey::from_protobuf_encoding(public_key)
+            .map_err(VerifyError::InvalidLibp2pPublicKey)?;
+
+        let mut message =
+            Vec::with_capacity(SIGNATURE_PREFIX.len() + certificate.subject_public_key_info.len());
+        message.extend_from_slice(SIGNATURE_PREFIX);
+        message.extend_from_slice(certificate.subject_public_key_info);
+        public_key
+            .verify(&message, signature)
+            .map_err(|_| VerifyError::BadLibp2pSignature)?;
+
+        public_key.into_peer_id()
+    };
+
+    // Check the self-signature of the certificate.
+    let public_key = decode_subject_public_key_info(certificate.subject_public_key_info)?;
+    let signature_scheme = match decode_algorithm_identifier(certificate.signature_algorithm)? {
+        (ED25519_OID, None) => SignatureScheme::Ed25519,
+        (ECDSA_WITH_SHA256_OID, None) => SignatureScheme::EcdsaSecp256r1Sha256,
+        _ => return Err(VerifyError::UnsupportedAlgorithm),
+    };
+    if certificate.tbs_signature_algorithm != certificate.signature_algorithm {
+        return Err(VerifyError::InvalidDer);
+    }
+    public_key
+        .verify(
+            signature_scheme,
+            certificate.tbs_certificate,
+            certificate.signature,
+        )
+        .map_err(|_| VerifyError::BadCertificateSignature)?;
+
+    Ok(VerifiedCertificate {
+        peer_id,
+        public_key,
+    })
+}
+
+/// Certificate successfully verified by [`verify_certificate`].
+pub struct VerifiedCertificate {
+    peer_id: PeerId,
+    public_key: CertificatePublicKey,
+}
+
+impl VerifiedCertificate {
+    /// Returns the identity of the owner of the certificate.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// Returns the identity of the owner of the certificate.
+    pub fn into_peer_id(self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Verifies a signature made using the private key of the certificate, such as the one
+    /// contained in the `CertificateVerify` message of the TLS handshake.
+    pub fn verify_signature(
+        &self,
+        scheme: SignatureScheme,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), SignatureVerifyFailed> {
+        self.public_key.verify(scheme, message, signature)
+    }
+}
+
+/// Signature scheme supported by [`VerifiedCertificate::verify_signature`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// Ed25519 signature. Called `ed25519` in TLS 1.3.
+    Ed25519,
+    /// ECDSA signature using the P-256 curve and SHA-256. Called `ecdsa_secp256r1_sha256` in
+    /// TLS 1.3.
+    EcdsaSecp256r1Sha256,
+}
+
+/// Error potentially returned by [`verify_certificate`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyError {
+    /// Failed to decode the certificate.
+    InvalidDer,
+    /// Current time is outside of the validity period of the certificate.
+    OutsideValidityPeriod,
+    /// Certificate contains a critical extension that isn't supported.
+    UnsupportedCriticalExtension,
+    /// Certificate doesn't contain the libp2p extension.
+    MissingLibp2pExtension,
+    /// Failed to decode the libp2p public key found in the libp2p extension.
+    #[display(fmt = "Invalid libp2p public key: {_0}")]
+    InvalidLibp2pPublicKey(FromProtobufEncodingError),
+    /// Signature of the libp2p extension is invalid.
+    BadLibp2pSignature,
+    /// Public key algorithm or signature algorithm of the certificate isn't supported.
+    UnsupportedAlgorithm,
+    /// Self-signature of the certificate is invalid.
+    BadCertificateSignature,
+}
+
+/// Call to [`VerifiedCertificate::verify_signature`] has failed. No reason is provided for
+/// security reasons.
+#[derive(Debug, derive_more::Display)]
+pub struct SignatureVerifyFailed();
+
+enum CertificatePublicKey {
+    Ed25519(ed25519_zebra::VerificationKey),
+    EcdsaP256(p256::ecdsa::VerifyingKey),
+}
+
+impl CertificatePublicKey {
+    fn verify(
+        &self,
+        scheme: SignatureScheme,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), SignatureVerifyFailed> {
+        match (self, scheme) {
+            (CertificatePublicKey::Ed25519(key), SignatureScheme::Ed25519) => {
+                let signature = ed25519_zebra::Signature::try_from(signature)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                key.verify(&signature, message)
+                    .map_err(|_| SignatureVerifyFailed())
+            }
+            (CertificatePublicKey::EcdsaP256(key), SignatureScheme::EcdsaSecp256r1Sha256) => {
+                use p256::ecdsa::signature::Verifier as _;
+
+                // ECDSA signatures are encoded as a DER structure containing two integers.
+                let (signature, rest) =
+                    decode_element(signature, TAG_SEQUENCE).ok_or(SignatureVerifyFailed())?;
+                let (r, signature) =
+                    decode_element(signature, TAG_INTEGER).ok_or(SignatureVerifyFailed())?;
+                let (s, signature) =
+                    decode_element(signature, TAG_INTEGER).ok_or(SignatureVerifyFailed())?;
+                if !rest.is_empty() || !signature.is_empty() {
+                    return Err(SignatureVerifyFailed());
+                }
+
+                let mut raw_signature = [0; 64];
+                for (integer, out) in [r, s].into_iter().zip(raw_signature.chunks_mut(32)) {
+                    let integer = match integer {
+                        [0, rest @ ..] => rest,
+                        integer => integer,
+                    };
+                    if integer.len() > 32 {
+                        return Err(SignatureVerifyFailed());
+                    }
+                    out[32 - integer.len()..].copy_from_slice(integer);
+                }
+
+                let signature = p256::ecdsa::Signature::from_slice(&raw_signature)
+                    .map_err(|_| SignatureVerifyFailed())?;
+                key.verify(message, &signature)
+                    .map_err(|_| SignatureVerifyFailed())
+            }
+            _ => Err(SignatureVerifyFailed()),
+        }
+    }
+}
+
+/// Prefix of the message signed with the libp2p key.
+const SIGNATURE_PREFIX: &[u8] = b"libp2p-tls-handshake:";
+
+/// Object identifier 1.3.101.112.
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+/// Object identifier 1.2.840.10045.2.1.
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// Object identifier 1.2.840.10045.3.1.7.
+const SECP256R1_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+/// Object identifier 1.2.840.10045.4.3.2.
+const ECDSA_WITH_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// Object identifier 2.5.4.3.
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+/// Object identifier 1.3.6.1.4.1.53594.1.1.
+const LIBP2P_EXTENSION_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xa2, 0x5a, 0x01, 0x01];
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_ISSUER_UNIQUE_ID: u8 = 0x81;
+const TAG_SUBJECT_UNIQUE_ID: u8 = 0x82;
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// Encodes a DER element of the given tag.
+fn der_element(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 4);
+    out.push(tag);
+    if content.len() < 0x80 {
+        out.push(u8::try_from(content.len()).unwrap());
+    } else if content.len() < 0x100 {
+        out.push(0x81);
+        out.push(u8::try_from(content.len()).unwrap());
+    } else {
+        out.push(0x82);
+        out.extend_from_slice(&u16::try_from(content.len()).unwrap().to_be_bytes());
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Encodes a DER sequence containing the given already-encoded elements.
+fn der_sequence(elements: &[&[u8]]) -> Vec<u8> {
+    der_element(TAG_SEQUENCE, &elements.concat())
+}
+
+/// Encodes a DER bit string whose length is a multiple of 8 bits.
+fn der_bit_string(content: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(content.len() + 1);
+    data.push(0);
+    data.extend_from_slice(content);
+    der_element(TAG_BIT_STRING, &data)
+}
+
+/// Decodes the DER element at the start of `data`, which must have the given tag. Returns the
+/// content of the element and the data that follows it.
+fn decode_element(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual_tag, data) = data.split_first()?;
+    if actual_tag != tag {
+        return None;
+    }
+
+    let (&first_length_byte, data) = data.split_first()?;
+    let (length, data) = match first_length_byte {
+        0..=0x7f => (usize::from(first_length_byte), data),
+        0x81..=0x84 => {
+            let num_bytes = usize::from(first_length_byte - 0x80);
+            if data.len() < num_bytes {
+                return None;
+            }
+            let length = data[..num_bytes]
+                .iter()
+                .fold(0usize, |length, byte| (length << 8) | usize::from(*byte));
+            (length, &data[num_bytes..])
+        }
+        _ => return None,
+    };
+
+    if data.len() < length {
+        return None;
+    }
+    Some(data.split_at(length))
+}
+
+/// Same as [`decode_element`], but returns the entire encoded element, including its tag and
+/// length, rather than only its content.
+fn decode_element_raw(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (_, rest) = decode_element(data, tag)?;
+    Some(data.split_at(data.len() - rest.len()))
+}
+
+struct DecodedCertificate<'a> {
+    /// Encoded `TBSCertificate`, including its tag and length. This is what is signed.
+    tbs_certificate: &'a [u8],
+    /// Content of the `signature` field of the `TBSCertificate`.
+    tbs_signature_algorithm: &'a [u8],
+    /// Encoded `notBefore` field, including its tag and length.
+    not_before: &'a [u8],
+    /// Encoded `notAfter` field, including its tag and length.
+    not_after: &'a [u8],
+    /// Encoded `SubjectPublicKeyInfo`, including its tag and length.
+    subject_public_key_info: &'a [u8],
+    /// Content of the `extensions` field, in other words a list of DER-encoded extensions.
+    extensions: &'a [u8],
+    /// Content of the `signatureAlgorithm` field of the certificate.
+    signature_algorithm: &'a [u8],
+    /// Self-signature of the certificate.
+    signature: &'a [u8],
+}
+
+fn decode_certificate(certificate_der: &[u8]) -> Option<DecodedCertificate<'_>> {
+    let (certificate, rest) = decode_element(certificate_der, TAG_SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    let (tbs_certificate, certificate) = decode_element_raw(certificate, TAG_SEQUENCE)?;
+    let (signature_algorithm, certificate) = decode_element(certificate, TAG_SEQUENCE)?;
+    let (signature, certificate) = decode_bit_string(certificate)?;
+    if !certificate.is_empty() {
+        return None;
+    }
+
+    let (tbs, _) = decode_element(tbs_certificate, TAG_SEQUENCE)?;
+    let (version, tbs) = decode_element(tbs, TAG_VERSION)?;
+    // Only version 3 certificates can contain extensions.
+    if version != [TAG_INTEGER, 1, 2] {
+        return None;
+    }
+    let (_serial_number, tbs) = decode_element(tbs, TAG_INTEGER)?;
+    let (tbs_signature_algorithm, tbs) = decode_element(tbs, TAG_SEQUENCE)?;
+    let (_issuer, tbs) = decode_element(tbs, TAG_SEQUENCE)?;
+    let (validity, tbs) = decode_element(tbs, TAG_SEQUENCE)?;
+    let (_subject, tbs) = decode_element(tbs, TAG_SEQUENCE)?;
+    let (subject_public_key_info, mut tbs) = decode_element_raw(tbs, TAG_SEQUENCE)?;
+    for tag in [TAG_ISSUER_UNIQUE_ID, TAG_SUBJECT_UNIQUE_ID] {
+        if let Some((_, rest)) = decode_element(tbs, tag) {
+            tbs = rest;
+        }
+    }
+    let (extensions, tbs) = decode_element(tbs, TAG_EXTENSIONS)?;
+    if !tbs.is_empty() {
+        return None;
+    }
+    let (extensions, rest) = decode_element(extensions, TAG_SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    let (not_before, validity) = decode_element_raw(validity, TAG_UTC_TIME)
+        .or_else(|| decode_element_raw(validity, TAG_GENERALIZED_TIME))?;
+    let (not_after, validity) = decode_element_raw(validity, TAG_UTC_TIME)
+        .or_else(|| decode_element_raw(validity, TAG_GENERALIZED_TIME))?;
+    if !validity.is_empty() {
+        return None;
+    }
+
+    Some(DecodedCertificate {
+        tbs_certificate,
+        tbs_signature_algorithm,
+        not_before,
+        not_after,
+        subject_public_key_info,
+        extensions,
+        signature_algorithm,
+        signature,
+    })
+}
+
+/// Decodes a DER bit string whose length must be a multiple of 8 bits.
+fn decode_bit_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (content, rest) = decode_element(data, TAG_BIT_STRING)?;
+    match content {
+        [0, bits @ ..] => Some((bits, rest)),
+        _ => None,
+    }
+}
+
+/// Decodes the content of an `AlgorithmIdentifier`. Returns the algorithm and, if any, the
+/// object identifier found in the parameters.
+fn decode_algorithm_identifier(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), VerifyError> {
+    let (algorithm, rest) = decode_element(data, TAG_OID).ok_or(VerifyError::InvalidDer)?;
+    if rest.is_empty() {
+        return Ok((algorithm, None));
+    }
+
+    let (parameter, rest) =
+        decode_element(rest, TAG_OID).ok_or(VerifyError::UnsupportedAlgorithm)?;
+    if !rest.is_empty() {
+        return Err(VerifyError::InvalidDer);
+    }
+    Ok((algorithm, Some(parameter)))
+}
+
+fn decode_subject_public_key_info(data: &[u8]) -> Result<CertificatePublicKey, VerifyError> {
+    let (data, _) = decode_element(data, TAG_SEQUENCE).ok_or(VerifyError::InvalidDer)?;
+    let (algorithm, data) = decode_element(data, TAG_SEQUENCE).ok_or(VerifyError::InvalidDer)?;
+    let (public_key, rest) = decode_bit_string(data).ok_or(VerifyError::InvalidDer)?;
+    if !rest.is_empty() {
+        return Err(VerifyError::InvalidDer);
+    }
+
+    match decode_algorithm_identifier(algorithm)? {
+        (ED25519_OID, None) => ed25519_zebra::VerificationKey::try_from(public_key)
+            .map(CertificatePublicKey::Ed25519)
+            .map_err(|_| VerifyError::InvalidDer),
+        (EC_PUBLIC_KEY_OID, Some(SECP256R1_OID)) => {
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                .map(CertificatePublicKey::EcdsaP256)
+                .map_err(|_| VerifyError::InvalidDer)
+        }
+        _ => Err(VerifyError::UnsupportedAlgorithm),
+    }
+}
+
+/// Decodes an encoded `UTCTime` or `GeneralizedTime` into a number of seconds since the UNIX
+/// epoch.
+fn decode_time(encoded: &[u8]) -> Option<i64> {
+    let (digits, year) = if let Some((time, _)) = decode_element(encoded, TAG_UTC_TIME) {
+        let time = time.strip_suffix(b"Z")?;
+        let year = i64::from(decode_digits(time.get(..2)?)?);
+        // As defined in RFC 5280.
+        let year = if year >= 50 { 1900 + year } else { 2000 + year };
+        (time.get(2..)?, year)
+    } else {
+        let (time, _) = decode_element(encoded, TAG_GENERALIZED_TIME)?;
+        let time = time.strip_suffix(b"Z")?;
+        (time.get(4..)?, i64::from(decode_digits(time.get(..4)?)?))
+    };
+
+    if digits.len() != 10 {
+        return None;
+    }
+    let month = decode_digits(&digits[0..2])?;
+    let day = decode_digits(&digits[2..4])?;
+    let hours = i64::from(decode_digits(&digits[4..6])?);
+    let minutes = i64::from(decode_digits(&digits[6..8])?);
+    let seconds = i64::from(decode_digits(&digits[8..10])?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hours >= 24
+        || minutes >= 60
+        || seconds >= 60
+    {
+        return None;
+    }
+
+    // Number of days since the UNIX epoch, using the algorithm described in
+    // <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+    let days = {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * i64::from((month + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    };
+
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+fn decode_digits(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0u32, |value, digit| {
+        if digit.is_ascii_digit() {
+            Some(value * 10 + u32::from(digit - b'0'))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::peer_id::{PeerId, PublicKey};
+    use core::time::Duration;
+
+    const NOW: Duration = Duration::from_secs(1_680_000_000);
+
+    #[test]
+    fn generate_then_verify() {
+        let libp2p_key = [1; 32];
+        let certificate = super::generate_certificate(&libp2p_key, &[2; 32], [3; 8]);
+
+        let verified = super::verify_certificate(&certificate.certificate_der, NOW).unwrap();
+
+        let expected_peer_id = PeerId::from_public_key(&PublicKey::Ed25519(
+            ed25519_zebra::VerificationKey::from(&ed25519_zebra::SigningKey::from(libp2p_key))
+                .into(),
+        ));
+        assert_eq!(*verified.peer_id(), expected_peer_id);
+    }
+
+    #[test]
+    fn handshake_signature() {
+        let certificate = super::generate_certificate(&[1; 32], &[2; 32], [3; 8]);
+        let verified = super::verify_certificate(&certificate.certificate_der, NOW).unwrap();
+
+        let signature = <[u8; 64]>::from(ed25519_zebra::SigningKey::from([2; 32]).sign(b"hello"));
+        assert!(verified
+            .verify_signature(super::SignatureScheme::Ed25519, b"hello", &signature)
+            .is_ok());
+        assert!(verified
+            .verify_signature(super::SignatureScheme::Ed25519, b"hellp", &signature)
+            .is_err());
+        assert!(verified
+            .verify_signature(
+                super::SignatureScheme::EcdsaSecp256r1Sha256,
+                b"hello",
+                &signature
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn tampered_certificate_rejected() {
+        let certificate = super::generate_certificate(&[1; 32], &[2; 32], [3; 8]);
+
+        for n in 0..certificate.certificate_der.len() {
+            let mut tampered = certificate.certificate_der.clone();
+            tampered[n] ^= 0x1;
+            assert!(super::verify_certificate(&tampered, NOW).is_err());
+        }
+    }
+
+    #[test]
+    fn outside_validity_period() {
+        let certificate = super::generate_certificate(&[1; 32], &[2; 32], [3; 8]);
+        assert!(matches!(
+            super::verify_certificate(&certificate.certificate_der, Duration::from_secs(0)),
+            Err(super::VerifyError::OutsideValidityPeriod)
+        ));
+    }
+
+    #[test]
+    fn decode_time() {
+        let encoded = super::der_element(super::TAG_UTC_TIME, b"700101000000Z");
+        assert_eq!(super::decode_time(&encoded), Some(0));
+        let encoded = super::der_element(super::TAG_GENERALIZED_TIME, b"20230328123456Z");
+        assert_eq!(super::decode_time(&encoded), Some(1_680_006_896));
+    }
+}
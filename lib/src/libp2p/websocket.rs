@@ -15,8 +15,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-//! Implementation of a WebSocket client that wraps around an abstract representation of a TCP
-//! socket through the `AsyncRead` and `AsyncWrite` traits.
+//! Implementation of a WebSocket client and server that wraps around an abstract representation
+//! of a TCP socket through the `AsyncRead` and `AsyncWrite` traits.
+//!
+//! Use [`websocket_client_handshake`] on outgoing connections and [`websocket_server_handshake`]
+//! on incoming connections. Both return a [`Connection`] that translates reads and writes into
+//! WebSocket binary frames.
 
 #![cfg(all(feature = "std"))]
 #![cfg_attr(docsrs, doc(cfg(all(feature = "std"))))]
@@ -75,6 +79,37 @@ pub async fn websocket_client_handshake<'a, T: AsyncRead + AsyncWrite + Send + U
     })
 }
 
+/// Negotiates the server side of the WebSocket protocol (including the HTTP-like request) on the
+/// given socket, and returns an object that translates reads and writes into WebSocket binary
+/// frames.
+///
+/// All the WebSocket requests are accepted, no matter the URL being requested.
+pub async fn websocket_server_handshake<T: AsyncRead + AsyncWrite + Send + Unpin + 'static>(
+    tcp_socket: T,
+) -> Result<Connection<T>, io::Error> {
+    let mut server = soketto::handshake::Server::new(tcp_socket);
+
+    let websocket_key = match server.receive_request().await {
+        Ok(req) => req.key(),
+        Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
+    };
+
+    server
+        .send_response(&soketto::handshake::server::Response::Accept {
+            key: websocket_key,
+            protocol: None,
+        })
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    let (sender, receiver) = server.into_builder().finish();
+
+    Ok(Connection {
+        sender: Write::Idle(sender),
+        receiver: Read::Idle(receiver, Vec::with_capacity(1024), 0),
+    })
+}
+
 /// Negotiated WebSocket connection.
 ///
 /// Implements the `AsyncRead` and `AsyncWrite` traits.
@@ -0,0 +1,487 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Authority discovery.
+//!
+//! The authorities of a chain (for example the validators of a relay chain) need to be able to
+//! directly connect to each other. In order to make this possible, each authority regularly
+//! publishes in the Kademlia distributed hash table a record containing the list of addresses it
+//! can be reached at, and looks up the records of the other authorities.
+//!
+//! The key of the record of an authority is derived from its public key, which can be obtained
+//! using [`dht_key`]. The value of the record is built using [`build_authority_record`], then
+//! signed using the `audi` key of the authority (see
+//! [`crate::identity::keystore::KeyNamespace::AuthorityDiscovery`]), then wrapped together with
+//! its signature using [`build_signed_authority_record`].
+//!
+//! The list of authorities can be obtained by calling the [`AUTHORITIES_RUNTIME_FUNCTION_NAME`]
+//! runtime function, whose output is decoded using [`decode_authorities_output`]. The
+//! [`AuthorityDiscovery`] struct holds this list of authorities, and the addresses of each
+//! authority found by verifying their record with [`decode_signed_authority_record`]. It is also
+//! used to verify the records that remotes ask the local node to store, as only the records of
+//! the current authorities should be stored.
+
+use crate::{
+    libp2p::{
+        multiaddr::{Multiaddr, ProtocolRef},
+        peer_id::PeerId,
+    },
+    util::protobuf,
+};
+
+use alloc::{borrow::Cow, vec::Vec};
+use sha2::{Digest as _, Sha256};
+
+/// Name of the runtime function that returns the list of public keys of the authorities
+/// currently participating in the authority discovery. Its output can be decoded using
+/// [`decode_authorities_output`].
+pub const AUTHORITIES_RUNTIME_FUNCTION_NAME: &str = "AuthorityDiscoveryApi_authorities";
+
+/// Decodes the output of a call to [`AUTHORITIES_RUNTIME_FUNCTION_NAME`].
+pub fn decode_authorities_output(
+    scale_encoded: &[u8],
+) -> Result<Vec<[u8; 32]>, DecodeAuthoritiesError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+            nom::multi::many_m_n(
+                num_elems,
+                num_elems,
+                nom::combinator::map(nom::bytes::complete::take(32u32), |b| {
+                    <[u8; 32]>::try_from(b).unwrap()
+                }),
+            )
+        }),
+    );
+
+    match nom::Finish::finish(parser(scale_encoded)) {
+        Ok((_, authorities)) => Ok(authorities),
+        Err(_) => Err(DecodeAuthoritiesError),
+    }
+}
+
+/// Error potentially returned by [`decode_authorities_output`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode the list of authorities")]
+pub struct DecodeAuthoritiesError;
+
+/// Returns the key in the Kademlia distributed hash table of the record of the authority with
+/// the given public key.
+///
+/// The key is the SHA-256 multihash of the public key, in other words the SHA-256 digest of the
+/// public key prefixed with the multihash code of SHA-256 (`0x12`) and the length of the digest
+/// (`0x20`).
+pub fn dht_key(authority_public_key: &[u8; 32]) -> [u8; 34] {
+    let mut out = [0; 34];
+    out[0] = 0x12;
+    out[1] = 0x20;
+    out[2..].copy_from_slice(&Sha256::digest(authority_public_key));
+    out
+}
+
+/// Builds the record containing the addresses the local node can be reached at.
+///
+/// The returned bytes must be signed using the `audi` key of the authority, then passed to
+/// [`build_signed_authority_record`].
+pub fn build_authority_record<'a>(
+    local_peer_id: &PeerId,
+    addresses: impl Iterator<Item = &'a Multiaddr>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    for address in addresses {
+        // Each address in the record must end with the identity of the node.
+        let mut address = address.clone();
+        address.push(ProtocolRef::P2p(Cow::Borrowed(local_peer_id.as_bytes())));
+        for slice in protobuf::bytes_tag_encode(1, address) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+    out
+}
+
+/// Wraps an authority record built using [`build_authority_record`] together with its signature.
+/// The returned value is the one to store in the distributed hash table under the key returned
+/// by [`dht_key`].
+pub fn build_signed_authority_record(authority_record: &[u8], signature: &[u8; 64]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(authority_record.len() + signature.len() + 8);
+    for slice in protobuf::bytes_tag_encode(1, authority_record) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, &signature[..]) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Decodes a record built using [`build_signed_authority_record`] and verifies its signature
+/// against the public key of the authority.
+///
+/// On success, returns the identity of the node of the authority and the addresses it can be
+/// reached at. The returned addresses don't include the `/p2p/` suffix.
+pub fn decode_signed_authority_record(
+    authority_public_key: &[u8; 32],
+    value: &[u8],
+) -> Result<(PeerId, Vec<Multiaddr>), DecodeSignedAuthorityRecordError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] record = 1 => protobuf::bytes_tag_decode,
+            #[required] auth_signature = 2 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    let signed = match nom::Finish::finish(parser(value)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeSignedAuthorityRecordError::ProtobufDecode),
+    };
+
+    let signature_valid = match (
+        schnorrkel::PublicKey::from_bytes(authority_public_key),
+        schnorrkel::Signature::from_bytes(signed.auth_signature),
+    ) {
+        (Ok(public_key), Ok(signature)) => public_key
+            .verify_simple(b"substrate", signed.record, &signature)
+            .is_ok(),
+        _ => false,
+    };
+    if !signature_valid {
+        return Err(DecodeSignedAuthorityRecordError::BadSignature);
+    }
+
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[repeated(max = 32)] addresses = 1 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    let record = match nom::Finish::finish(parser(signed.record)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeSignedAuthorityRecordError::ProtobufDecode),
+    };
+
+    let mut peer_id = None::<PeerId>;
+    let mut addresses = Vec::with_capacity(record.addresses.len());
+
+    for address in record.addresses {
+        let mut address = Multiaddr::try_from(address.to_vec())
+            .map_err(|_| DecodeSignedAuthorityRecordError::BadMultiaddr)?;
+
+        let address_peer_id = match address.iter().last() {
+            Some(ProtocolRef::P2p(bytes)) => PeerId::from_bytes(bytes.into_owned())
+                .map_err(|_| DecodeSignedAuthorityRecordError::BadMultiaddr)?,
+            _ => return Err(DecodeSignedAuthorityRecordError::BadMultiaddr),
+        };
+
+        match &peer_id {
+            Some(peer_id) if *peer_id != address_peer_id => {
+                return Err(DecodeSignedAuthorityRecordError::PeerIdMismatch)
+            }
+            Some(_) => {}
+            None => peer_id = Some(address_peer_id),
+        }
+
+        address.pop();
+        addresses.push(address);
+    }
+
+    let peer_id = peer_id.ok_or(DecodeSignedAuthorityRecordError::NoAddress)?;
+    Ok((peer_id, addresses))
+}
+
+/// Error potentially returned by [`decode_signed_authority_record`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum DecodeSignedAuthorityRecordError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Signature of the record doesn't match the public key of the authority.
+    BadSignature,
+    /// One of the addresses is invalid or doesn't end with a `/p2p/` component.
+    BadMultiaddr,
+    /// The addresses in the record don't all have the same `/p2p/` component.
+    PeerIdMismatch,
+    /// The record doesn't contain any address.
+    NoAddress,
+}
+
+/// Node identity of an authority and addresses it can be reached at.
+type ResolvedAuthority = (PeerId, Vec<Multiaddr>);
+
+/// Collection of the current authorities and of the addresses they can be reached at.
+pub struct AuthorityDiscovery {
+    /// List of authorities, and the node identity and addresses of each authority whose record
+    /// has been found.
+    authorities: hashbrown::HashMap<[u8; 32], Option<ResolvedAuthority>, fnv::FnvBuildHasher>,
+
+    /// For each authority in [`AuthorityDiscovery::authorities`], the value of [`dht_key`].
+    dht_keys: hashbrown::HashMap<[u8; 34], [u8; 32], fnv::FnvBuildHasher>,
+}
+
+impl AuthorityDiscovery {
+    /// Initializes a new empty collection.
+    pub fn new() -> Self {
+        AuthorityDiscovery {
+            authorities: hashbrown::HashMap::with_hasher(Default::default()),
+            dht_keys: hashbrown::HashMap::with_hasher(Default::default()),
+        }
+    }
+
+    /// Updates the list of authorities, for example after a call to
+    /// [`AUTHORITIES_RUNTIME_FUNCTION_NAME`].
+    ///
+    /// The addresses of the authorities that were already known are kept.
+    pub fn set_authorities(&mut self, authorities: impl Iterator<Item = [u8; 32]>) {
+        let mut new_authorities = hashbrown::HashMap::with_capacity_and_hasher(
+            self.authorities.len(),
+            Default::default(),
+        );
+        for authority in authorities {
+            let addresses = self.authorities.remove(&authority).flatten();
+            new_authorities.insert(authority, addresses);
+        }
+        self.authorities = new_authorities;
+
+        self.dht_keys.clear();
+        for authority in self.authorities.keys() {
+            self.dht_keys.insert(dht_key(authority), *authority);
+        }
+    }
+
+    /// Returns `true` if the given public key is in the list of authorities.
+    pub fn is_authority(&self, authority_public_key: &[u8; 32]) -> bool {
+        self.authorities.contains_key(authority_public_key)
+    }
+
+    /// Returns the list of authorities whose record hasn't been found yet.
+    ///
+    /// Use [`dht_key`] to determine the key to look up for each of these authorities.
+    pub fn unresolved_authorities(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.authorities
+            .iter()
+            .filter(|(_, addresses)| addresses.is_none())
+            .map(|(authority, _)| authority)
+    }
+
+    /// Injects a record found in the distributed hash table under the key of the given
+    /// authority.
+    ///
+    /// On success, the addresses of the authority are replaced with the ones in the record.
+    pub fn inject_record(
+        &mut self,
+        authority_public_key: &[u8; 32],
+        value: &[u8],
+    ) -> Result<(), InjectRecordError> {
+        let entry = self
+            .authorities
+            .get_mut(authority_public_key)
+            .ok_or(InjectRecordError::UnknownAuthority)?;
+        *entry = Some(
+            decode_signed_authority_record(authority_public_key, value)
+                .map_err(InjectRecordError::Decode)?,
+        );
+        Ok(())
+    }
+
+    /// Verifies a record that a remote asks to store in the distributed hash table under the
+    /// given key.
+    ///
+    /// Only the records of the current authorities, signed by these authorities, are accepted.
+    /// On success, returns the public key of the authority the record belongs to.
+    pub fn verify_record(
+        &self,
+        dht_key: &[u8],
+        value: &[u8],
+    ) -> Result<&[u8; 32], InjectRecordError> {
+        let authority_public_key = <&[u8; 34]>::try_from(dht_key)
+            .ok()
+            .and_then(|dht_key| self.dht_keys.get(dht_key))
+            .ok_or(InjectRecordError::UnknownAuthority)?;
+        decode_signed_authority_record(authority_public_key, value)
+            .map_err(InjectRecordError::Decode)?;
+        Ok(authority_public_key)
+    }
+
+    /// Returns the node identity and addresses of the given authority, if known.
+    pub fn authority_addresses(
+        &self,
+        authority_public_key: &[u8; 32],
+    ) -> Option<(&PeerId, &[Multiaddr])> {
+        match self.authorities.get(authority_public_key) {
+            Some(Some((peer_id, addresses))) => Some((peer_id, &addresses[..])),
+            _ => None,
+        }
+    }
+
+    /// Returns the list of authorities whose record has been found, with their node identity
+    /// and addresses.
+    pub fn resolved_authorities(&self) -> impl Iterator<Item = (&[u8; 32], &PeerId, &[Multiaddr])> {
+        self.authorities.iter().filter_map(|(authority, resolved)| {
+            let (peer_id, addresses) = resolved.as_ref()?;
+            Some((authority, peer_id, &addresses[..]))
+        })
+    }
+}
+
+impl Default for AuthorityDiscovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error potentially returned by [`AuthorityDiscovery::inject_record`] and
+/// [`AuthorityDiscovery::verify_record`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum InjectRecordError {
+    /// Public key isn't in the list of authorities.
+    UnknownAuthority,
+    /// Failed to decode or verify the record.
+    #[display(fmt = "{_0}")]
+    Decode(DecodeSignedAuthorityRecordError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libp2p::{
+        multiaddr::Multiaddr,
+        peer_id::{PeerId, PublicKey},
+    };
+
+    fn sign(secret: &[u8; 32], message: &[u8]) -> ([u8; 32], [u8; 64]) {
+        let keypair = schnorrkel::MiniSecretKey::from_bytes(secret)
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        let signature = keypair.sign_simple(b"substrate", message);
+        (keypair.public.to_bytes(), signature.to_bytes())
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([1; 32]));
+        let addresses = vec![
+            "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap(),
+            "/dns/example.com/tcp/30333/ws"
+                .parse::<Multiaddr>()
+                .unwrap(),
+        ];
+
+        let record = super::build_authority_record(&peer_id, addresses.iter());
+        let (public_key, signature) = sign(&[5; 32], &record);
+        let signed = super::build_signed_authority_record(&record, &signature);
+
+        assert_eq!(
+            super::decode_signed_authority_record(&public_key, &signed).unwrap(),
+            (peer_id, addresses)
+        );
+
+        let (other_public_key, _) = sign(&[6; 32], &record);
+        assert_eq!(
+            super::decode_signed_authority_record(&other_public_key, &signed),
+            Err(super::DecodeSignedAuthorityRecordError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn authorities_resolution() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([1; 32]));
+        let address = "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap();
+        let record = super::build_authority_record(&peer_id, [&address].into_iter());
+        let (public_key, signature) = sign(&[5; 32], &record);
+        let signed = super::build_signed_authority_record(&record, &signature);
+
+        let mut discovery = super::AuthorityDiscovery::new();
+        assert_eq!(
+            discovery.inject_record(&public_key, &signed),
+            Err(super::InjectRecordError::UnknownAuthority)
+        );
+
+        discovery.set_authorities([public_key, [7; 32]].into_iter());
+        assert_eq!(discovery.unresolved_authorities().count(), 2);
+
+        discovery.inject_record(&public_key, &signed).unwrap();
+        assert_eq!(
+            discovery.unresolved_authorities().collect::<Vec<_>>(),
+            vec![&[7; 32]]
+        );
+        assert_eq!(
+            discovery.authority_addresses(&public_key),
+            Some((&peer_id, &[address][..]))
+        );
+
+        // Authorities that remain in the set keep their addresses.
+        discovery.set_authorities([public_key].into_iter());
+        assert_eq!(discovery.resolved_authorities().count(), 1);
+        discovery.set_authorities([[7; 32]].into_iter());
+        assert_eq!(discovery.resolved_authorities().count(), 0);
+    }
+
+    #[test]
+    fn dht_key_is_multihash() {
+        let key = super::dht_key(&[5; 32]);
+        assert_eq!(&key[..2], &[0x12, 0x20]);
+        assert_eq!(
+            crate::libp2p::multihash::MultihashRef::from_bytes(&key)
+                .unwrap()
+                .hash_algorithm_code(),
+            0x12
+        );
+    }
+
+    #[test]
+    fn verify_put_record() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([1; 32]));
+        let address = "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap();
+        let record = super::build_authority_record(&peer_id, [&address].into_iter());
+        let (public_key, signature) = sign(&[5; 32], &record);
+        let signed = super::build_signed_authority_record(&record, &signature);
+
+        let mut discovery = super::AuthorityDiscovery::new();
+        let key = super::dht_key(&public_key);
+        assert_eq!(
+            discovery.verify_record(&key, &signed),
+            Err(super::InjectRecordError::UnknownAuthority)
+        );
+
+        discovery.set_authorities([public_key].into_iter());
+        assert_eq!(discovery.verify_record(&key, &signed), Ok(&public_key));
+
+        // A record stored under the key of a different authority is refused.
+        let (other_public_key, other_signature) = sign(&[6; 32], &record);
+        discovery.set_authorities([public_key, other_public_key].into_iter());
+        assert_eq!(
+            discovery.verify_record(&super::dht_key(&other_public_key), &signed),
+            Err(super::InjectRecordError::Decode(
+                super::DecodeSignedAuthorityRecordError::BadSignature
+            ))
+        );
+        let other_signed = super::build_signed_authority_record(&record, &other_signature);
+        assert_eq!(
+            discovery.verify_record(&key, &other_signed),
+            Err(super::InjectRecordError::Decode(
+                super::DecodeSignedAuthorityRecordError::BadSignature
+            ))
+        );
+    }
+
+    #[test]
+    fn decode_authorities() {
+        let mut encoded = vec![8];
+        encoded.extend_from_slice(&[1; 32]);
+        encoded.extend_from_slice(&[2; 32]);
+        assert_eq!(
+            super::decode_authorities_output(&encoded).unwrap(),
+            vec![[1; 32], [2; 32]]
+        );
+        assert!(super::decode_authorities_output(&encoded[1..]).is_err());
+    }
+}
@@ -15,23 +15,647 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-// TODO: work in progress
+//! Kademlia distributed hash table.
+//!
+//! The [`Kademlia`] struct holds the state of the Kademlia lookups that are currently in
+//! progress. It doesn't hold any routing table: the k-buckets of the local node (see the
+//! [`kbuckets`] module) are maintained by the API user, typically by the
+//! [`crate::network::service::ChainNetwork`], and the closest nodes of these k-buckets are passed
+//! when starting a lookup.
+//!
+//! A lookup consists in finding the [`ENTRIES_PER_BUCKET`] nodes of the network that are the
+//! closest to a certain key. It is started by calling [`Kademlia::start_lookup`]. The lookup is
+//! performed iteratively: the local node asks the closest nodes it knows about for the nodes they
+//! know are closest to the key, then asks these new nodes, and so on, with up to
+//! [`Config::parallelism`] requests in progress at the same time for each lookup. The lookup is
+//! over once the [`ENTRIES_PER_BUCKET`] closest nodes that have been found have all successfully
+//! answered.
+//!
+//! This module doesn't perform any networking by itself. Use [`Kademlia::next_action`] in order
+//! to know which requests must be sent, and inject the responses back with
+//! [`Kademlia::inject_response`]. The nodes that successfully answer should be inserted in the
+//! k-buckets by the API user.
+//!
+//! In order for the k-buckets to remain up-to-date, the API user should regularly call
+//! [`Kademlia::refresh_keys`] and start a lookup for each of the keys it returns.
+
+use crate::libp2p::{
+    multiaddr::Multiaddr,
+    peer_id::{self, PeerId},
+};
+
+use alloc::vec::Vec;
+use core::{num::NonZeroUsize, ops::Add, time::Duration};
+use rand::{Rng as _, SeedableRng as _};
+use sha2::{Digest as _, Sha256};
 
 pub mod kbuckets;
+pub mod record_store;
+
+/// Number of entries in each k-bucket. Also the number of nodes returned by a lookup and sent
+/// back in response to a find node request.
+pub const ENTRIES_PER_BUCKET: usize = 20;
+
+/// Number of buckets, starting from the one furthest away from the local node, that are
+/// refreshed with a random lookup. See [`Kademlia::refresh_keys`].
+pub const REFRESHED_BUCKETS: u32 = 8;
+
+/// Maximum number of addresses stored for each candidate of a lookup. Additional addresses are
+/// ignored.
+const MAX_ADDRESSES_PER_PEER: usize = 10;
+
+/// Maximum number of random keys generated when searching for a key that belongs to a specific
+/// bucket.
+const MAX_REFRESH_KEY_ATTEMPTS: usize = 4096;
+
+/// Configuration for a [`Kademlia`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Identity of the local node. Never contacted by lookups.
+    pub local_peer_id: PeerId,
+
+    /// Maximum number of requests that can be in progress at the same time for each lookup.
+    /// Typically called `α` in the Kademlia literature.
+    pub parallelism: NonZeroUsize,
+
+    /// Interval between two refreshes of the k-buckets. See [`Kademlia::refresh_keys`].
+    pub refresh_interval: Duration,
+
+    /// Seed used for the randomness of the refresh lookups.
+    pub randomness_seed: [u8; 32],
+}
+
+/// Data structure containing the state of the current Kademlia lookups.
+pub struct Kademlia<TNow> {
+    /// See [`Config::local_peer_id`].
+    local_peer_id: PeerId,
+
+    /// SHA-256 hash of [`Kademlia::local_peer_id`].
+    local_peer_id_hashed: [u8; 32],
+
+    /// List of lookups in progress. Indices are the [`LookupId`]s.
+    lookups: slab::Slab<Lookup>,
+
+    /// For each request in progress, the index within [`Kademlia::lookups`] of the lookup it
+    /// belongs to.
+    requests: hashbrown::HashMap<RequestId, usize, fnv::FnvBuildHasher>,
+
+    /// Identifier to assign to the next request.
+    next_request_id: RequestId,
+
+    /// See [`Config::parallelism`].
+    parallelism: usize,
+
+    /// See [`Config::refresh_interval`].
+    refresh_interval: Duration,
+
+    /// When the next refresh should start. `None` if no refresh has ever been started, in which
+    /// case one should start as soon as possible.
+    next_refresh: Option<TNow>,
+
+    /// Source of randomness for the refresh lookups.
+    randomness: rand_chacha::ChaCha20Rng,
+}
+
+/// Identifier of a lookup started with [`Kademlia::start_lookup`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LookupId(usize);
+
+/// Identifier of a request returned by [`Kademlia::next_action`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequestId(u64);
+
+struct Lookup {
+    /// Key whose closest nodes are searched.
+    key: Vec<u8>,
+    /// SHA-256 hash of [`Lookup::key`].
+    key_hashed: [u8; 32],
+    /// Nodes that have been discovered so far, ordered by increasing distance to the key.
+    candidates: Vec<Candidate>,
+    /// Number of elements of [`Lookup::candidates`] in the [`CandidateState::InProgress`] state.
+    num_in_progress: usize,
+}
+
+struct Candidate {
+    peer_id: PeerId,
+    addresses: Vec<Multiaddr>,
+    /// XOR distance between the SHA-256 hash of the peer id and [`Lookup::key_hashed`].
+    distance: [u8; 32],
+    state: CandidateState,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CandidateState {
+    NotContacted,
+    InProgress(RequestId),
+    Succeeded,
+    Failed,
+}
+
+impl<TNow> Kademlia<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Ord,
+{
+    /// Initializes a new data structure without any lookup in progress.
+    pub fn new(config: Config) -> Self {
+        Kademlia {
+            local_peer_id_hashed: sha256(config.local_peer_id.as_bytes()),
+            local_peer_id: config.local_peer_id,
+            lookups: slab::Slab::new(),
+            requests: hashbrown::HashMap::with_capacity_and_hasher(
+                16,
+                fnv::FnvBuildHasher::default(),
+            ),
+            next_request_id: RequestId(0),
+            parallelism: config.parallelism.get(),
+            refresh_interval: config.refresh_interval,
+            next_refresh: None,
+            randomness: rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed),
+        }
+    }
+
+    /// Returns the identity of the local node that was passed through [`Config::local_peer_id`].
+    pub fn local_peer_id(&self) -> &PeerId {
+        &self.local_peer_id
+    }
+
+    /// Starts a new lookup of the nodes closest to the given key.
+    ///
+    /// The lookup is initially filled with `initial_candidates`, which should be the nodes of the
+    /// k-buckets closest to the key and their addresses. If `initial_candidates` is empty, the
+    /// lookup immediately finishes with an empty result.
+    pub fn start_lookup(
+        &mut self,
+        key: Vec<u8>,
+        initial_candidates: impl IntoIterator<Item = (PeerId, Vec<Multiaddr>)>,
+    ) -> LookupId {
+        let key_hashed = sha256(&key);
+
+        let mut lookup = Lookup {
+            key,
+            key_hashed,
+            candidates: Vec::new(),
+            num_in_progress: 0,
+        };
+        for (peer_id, addresses) in initial_candidates {
+            if peer_id != self.local_peer_id {
+                lookup.insert_candidate(peer_id, addresses);
+            }
+        }
+
+        LookupId(self.lookups.insert(lookup))
+    }
+
+    /// If [`Config::refresh_interval`] has elapsed since the previous refresh, or if no refresh
+    /// has ever happened, schedules the next refresh and returns the list of keys that must be
+    /// passed to [`Kademlia::start_lookup`] in order to refresh the k-buckets. Otherwise, returns
+    /// an empty list.
+    ///
+    /// The first key is the local node itself, which finds the nodes of the buckets closest to
+    /// the local node. The other keys are random keys belonging to each of the
+    /// [`REFRESHED_BUCKETS`] buckets the furthest away from the local node, starting with the
+    /// furthest. Contrary to the closest buckets, these buckets are typically too large to be
+    /// filled by a single lookup.
+    pub fn refresh_keys(&mut self, now: &TNow) -> Vec<Vec<u8>> {
+        if let Some(next_refresh) = &self.next_refresh {
+            if *next_refresh > *now {
+                return Vec::new();
+            }
+        }
+
+        self.next_refresh = Some(now.clone() + self.refresh_interval);
+
+        let mut keys = Vec::with_capacity(1 + usize::try_from(REFRESHED_BUCKETS).unwrap());
+        keys.push(self.local_peer_id.as_bytes().to_vec());
+
+        for bucket_leading_zeros in 0..REFRESHED_BUCKETS {
+            // The hash of a random key has a probability of `1 / 2^(n + 1)` of belonging to the
+            // `n`th furthest bucket. Random keys are generated until one that belongs to the
+            // desired bucket is found. If none is found, the last one is used anyway.
+            let mut key = Vec::new();
+            for _ in 0..MAX_REFRESH_KEY_ATTEMPTS {
+                key = {
+                    let pub_key = self.randomness.sample(rand::distributions::Standard);
+                    PeerId::from_public_key(&peer_id::PublicKey::Ed25519(pub_key)).into_bytes()
+                };
+
+                if leading_zeros(&distance(&self.local_peer_id_hashed, &key))
+                    == bucket_leading_zeros
+                {
+                    break;
+                }
+            }
+            keys.push(key);
+        }
+
+        keys
+    }
+
+    /// Returns when [`Kademlia::refresh_keys`] will next return a non-empty list.
+    ///
+    /// Returns `None` if [`Kademlia::refresh_keys`] has never been called, in which case it
+    /// will return a non-empty list the next time it is called.
+    pub fn next_refresh(&self) -> Option<&TNow> {
+        self.next_refresh.as_ref()
+    }
+
+    /// Returns the next action that must be performed, or `None` if there is nothing to do
+    /// until either [`Kademlia::inject_response`] or [`Kademlia::start_lookup`] is called.
+    pub fn next_action(&mut self) -> Option<Action> {
+        let mut to_contact = None;
+        let mut finished_lookup = None;
+
+        for (lookup_index, lookup) in self.lookups.iter() {
+            let mut num_non_failed = 0;
+            let mut finished = true;
+            let mut next_to_contact = None;
+
+            for (candidate_index, candidate) in lookup.candidates.iter().enumerate() {
+                match candidate.state {
+                    CandidateState::Failed => continue,
+                    CandidateState::Succeeded => {}
+                    CandidateState::InProgress(_) => finished = false,
+                    CandidateState::NotContacted => {
+                        finished = false;
+                        if next_to_contact.is_none() {
+                            next_to_contact = Some(candidate_index);
+                        }
+                    }
+                }
+
+                num_non_failed += 1;
+                if num_non_failed == ENTRIES_PER_BUCKET {
+                    break;
+                }
+            }
+
+            if finished {
+                finished_lookup = Some(lookup_index);
+                break;
+            }
+
+            if let Some(candidate_index) = next_to_contact {
+                if lookup.num_in_progress < self.parallelism {
+                    to_contact = Some((lookup_index, candidate_index));
+                    break;
+                }
+            }
+        }
 
-/// Data structure containing the k-buckets and the state of the current Kademlia queries.
-// TODO: unused
-pub struct Kademlia {}
+        if let Some(lookup_index) = finished_lookup {
+            let lookup = self.lookups.remove(lookup_index);
 
-impl Kademlia {
-    /// Initializes a new empty data structure with empty k-buckets.
-    pub fn new() -> Self {
-        Kademlia {}
+            // Requests that are still in progress with nodes further away than the closest
+            // nodes are ignored.
+            for candidate in &lookup.candidates {
+                if let CandidateState::InProgress(request_id) = candidate.state {
+                    let _was_in = self.requests.remove(&request_id);
+                    debug_assert_eq!(_was_in, Some(lookup_index));
+                }
+            }
+
+            return Some(Action::LookupFinished(LookupFinished {
+                lookup_id: LookupId(lookup_index),
+                key: lookup.key,
+                closest_peers: lookup
+                    .candidates
+                    .into_iter()
+                    .filter(|c| matches!(c.state, CandidateState::Succeeded))
+                    .take(ENTRIES_PER_BUCKET)
+                    .map(|c| (c.peer_id, c.addresses))
+                    .collect(),
+            }));
+        }
+
+        if let Some((lookup_index, candidate_index)) = to_contact {
+            let request_id = self.next_request_id;
+            self.next_request_id.0 += 1;
+
+            let lookup = &mut self.lookups[lookup_index];
+            let candidate = &mut lookup.candidates[candidate_index];
+            candidate.state = CandidateState::InProgress(request_id);
+            lookup.num_in_progress += 1;
+            let _prev_value = self.requests.insert(request_id, lookup_index);
+            debug_assert!(_prev_value.is_none());
+
+            return Some(Action::StartRequest(StartRequest {
+                request_id,
+                lookup_id: LookupId(lookup_index),
+                target: candidate.peer_id.clone(),
+                addresses: candidate.addresses.clone(),
+                key: lookup.key.clone(),
+            }));
+        }
+
+        None
+    }
+
+    /// Injects the response to a request previously returned by [`Kademlia::next_action`].
+    ///
+    /// Pass `Err` if the request has failed, for example because the remote couldn't be reached
+    /// or has sent back an invalid response.
+    ///
+    /// Has no effect if the lookup the request belongs to has already finished.
+    pub fn inject_response(
+        &mut self,
+        request_id: RequestId,
+        response: Result<Vec<(PeerId, Vec<Multiaddr>)>, ()>,
+    ) {
+        let Some(lookup_index) = self.requests.remove(&request_id) else {
+            return;
+        };
+        let lookup = &mut self.lookups[lookup_index];
+
+        let candidate = lookup
+            .candidates
+            .iter_mut()
+            .find(|c| c.state == CandidateState::InProgress(request_id))
+            .unwrap();
+        lookup.num_in_progress -= 1;
+
+        let closer_peers = match response {
+            Ok(closer_peers) => closer_peers,
+            Err(()) => {
+                candidate.state = CandidateState::Failed;
+                return;
+            }
+        };
+
+        candidate.state = CandidateState::Succeeded;
+
+        for (peer_id, addresses) in closer_peers {
+            if peer_id != self.local_peer_id {
+                lookup.insert_candidate(peer_id, addresses);
+            }
+        }
+    }
+}
+
+impl Lookup {
+    /// Inserts a new candidate in the lookup, or adds the given addresses to the existing
+    /// candidate with that [`PeerId`].
+    fn insert_candidate(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        if let Some(candidate) = self.candidates.iter_mut().find(|c| c.peer_id == peer_id) {
+            for address in addresses {
+                if candidate.addresses.len() >= MAX_ADDRESSES_PER_PEER {
+                    break;
+                }
+                if !candidate.addresses.contains(&address) {
+                    candidate.addresses.push(address);
+                }
+            }
+            return;
+        }
+
+        let distance = distance(&self.key_hashed, peer_id.as_bytes());
+        let insert_position = self.candidates.partition_point(|c| c.distance < distance);
+        self.candidates.insert(
+            insert_position,
+            Candidate {
+                peer_id,
+                addresses: addresses.into_iter().take(MAX_ADDRESSES_PER_PEER).collect(),
+                distance,
+                state: CandidateState::NotContacted,
+            },
+        );
+    }
+}
+
+/// Action to perform. See [`Kademlia::next_action`].
+#[derive(Debug)]
+pub enum Action {
+    /// A find node request must be sent to a node.
+    StartRequest(StartRequest),
+    /// A lookup is over.
+    LookupFinished(LookupFinished),
+}
+
+/// See [`Action::StartRequest`].
+#[derive(Debug)]
+pub struct StartRequest {
+    /// Identifier of the request. Must be passed back to [`Kademlia::inject_response`].
+    pub request_id: RequestId,
+    /// Lookup this request belongs to.
+    pub lookup_id: LookupId,
+    /// Node to send the request to. The local node isn't necessarily connected to it.
+    pub target: PeerId,
+    /// Known addresses of [`StartRequest::target`]. Can be empty if the node that has reported
+    /// the target didn't provide any address.
+    pub addresses: Vec<Multiaddr>,
+    /// Key to put in the request. See [`crate::network::protocol::build_find_node_request`].
+    pub key: Vec<u8>,
+}
+
+/// See [`Action::LookupFinished`].
+#[derive(Debug)]
+pub struct LookupFinished {
+    /// Lookup that has finished.
+    pub lookup_id: LookupId,
+    /// Key that was passed to [`Kademlia::start_lookup`].
+    pub key: Vec<u8>,
+    /// Up to [`ENTRIES_PER_BUCKET`] nodes closest to the key that have successfully answered,
+    /// ordered by increasing distance, and their addresses.
+    pub closest_peers: Vec<(PeerId, Vec<Multiaddr>)>,
+}
+
+/// Sorts the given list of nodes by increasing distance to the given key, and only keeps the
+/// [`ENTRIES_PER_BUCKET`] closest ones.
+///
+/// This is the list to send back when a remote sends a find node request.
+pub fn closest_peers<T>(
+    key: &[u8],
+    peers: impl Iterator<Item = (PeerId, T)>,
+) -> impl Iterator<Item = (PeerId, T)> {
+    let key_hashed = sha256(key);
+    let mut list = peers
+        .map(|(peer_id, value)| (distance(&key_hashed, peer_id.as_bytes()), peer_id, value))
+        .collect::<Vec<_>>();
+    list.sort_unstable_by_key(|(distance, _, _)| *distance);
+    list.into_iter()
+        .take(ENTRIES_PER_BUCKET)
+        .map(|(_, peer_id, value)| (peer_id, value))
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Returns the XOR distance between `key_hashed` and the SHA-256 hash of `other`.
+fn distance(key_hashed: &[u8; 32], other: &[u8]) -> [u8; 32] {
+    let mut out = sha256(other);
+    for (out, key) in out.iter_mut().zip(key_hashed.iter()) {
+        *out ^= *key;
     }
+    out
 }
 
-impl Default for Kademlia {
-    fn default() -> Self {
-        Self::new()
+/// Returns the number of leading zero bits of the given distance. The larger the number, the
+/// closer the two keys.
+fn leading_zeros(distance: &[u8; 32]) -> u32 {
+    let mut num = 0;
+    for byte in distance {
+        num += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    num
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        closest_peers, distance, leading_zeros, sha256, Action, Config, Kademlia, REFRESHED_BUCKETS,
+    };
+    use crate::libp2p::{
+        multiaddr::Multiaddr,
+        peer_id::{PeerId, PublicKey},
+    };
+    use core::{num::NonZeroUsize, time::Duration};
+
+    fn new_node(peer_id: PeerId) -> Kademlia<Duration> {
+        Kademlia::new(Config {
+            local_peer_id: peer_id,
+            parallelism: NonZeroUsize::new(3).unwrap(),
+            refresh_interval: Duration::from_secs(60),
+            randomness_seed: [0; 32],
+        })
+    }
+
+    #[test]
+    fn lookup_reaches_target() {
+        // Builds a line of nodes where each node only knows about the next one, then looks up
+        // the last node from the first one.
+        let peer_ids = (0..15u8)
+            .map(|n| PeerId::from_public_key(&PublicKey::Ed25519([n; 32])))
+            .collect::<Vec<_>>();
+        let routing_tables = (0..peer_ids.len())
+            .map(|n| {
+                peer_ids
+                    .get(n + 1)
+                    .map(|peer_id| {
+                        let address = format!("/ip4/127.0.0.1/tcp/{}", n + 1)
+                            .parse::<Multiaddr>()
+                            .unwrap();
+                        (peer_id.clone(), vec![address])
+                    })
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut node = new_node(peer_ids[0].clone());
+        let target = peer_ids.last().unwrap().clone();
+        let lookup_id = node.start_lookup(
+            target.clone().into_bytes(),
+            closest_peers(target.as_bytes(), routing_tables[0].iter().cloned()),
+        );
+
+        let result = loop {
+            match node.next_action() {
+                Some(Action::StartRequest(request)) => {
+                    let responder = peer_ids.iter().position(|p| *p == request.target).unwrap();
+                    assert!(!request.addresses.is_empty());
+                    let response =
+                        closest_peers(&request.key, routing_tables[responder].iter().cloned())
+                            .collect();
+                    node.inject_response(request.request_id, Ok(response));
+                }
+                Some(Action::LookupFinished(finished)) => break finished,
+                None => panic!(),
+            }
+        };
+
+        assert_eq!(result.lookup_id, lookup_id);
+        assert_eq!(result.key, target.as_bytes());
+        assert_eq!(result.closest_peers[0].0, target);
+        assert_eq!(result.closest_peers.len(), peer_ids.len() - 1);
+    }
+
+    #[test]
+    fn lookup_failure() {
+        let mut node = new_node(PeerId::from_public_key(&PublicKey::Ed25519([0; 32])));
+        let remote = PeerId::from_public_key(&PublicKey::Ed25519([1; 32]));
+        let lookup_id = node.start_lookup(
+            b"hello".to_vec(),
+            [(
+                remote.clone(),
+                vec!["/ip4/127.0.0.1/tcp/1".parse::<Multiaddr>().unwrap()],
+            )],
+        );
+
+        let request = match node.next_action() {
+            Some(Action::StartRequest(request)) => request,
+            _ => panic!(),
+        };
+        assert_eq!(request.target, remote);
+        assert_eq!(request.lookup_id, lookup_id);
+        assert!(node.next_action().is_none());
+
+        node.inject_response(request.request_id, Err(()));
+        match node.next_action() {
+            Some(Action::LookupFinished(finished)) => {
+                assert_eq!(finished.lookup_id, lookup_id);
+                assert!(finished.closest_peers.is_empty());
+            }
+            _ => panic!(),
+        }
+        assert!(node.next_action().is_none());
+    }
+
+    #[test]
+    fn empty_lookup_finishes() {
+        let mut node = new_node(PeerId::from_public_key(&PublicKey::Ed25519([0; 32])));
+        let lookup_id = node.start_lookup(b"hello".to_vec(), []);
+
+        match node.next_action() {
+            Some(Action::LookupFinished(finished)) => {
+                assert_eq!(finished.lookup_id, lookup_id);
+                assert!(finished.closest_peers.is_empty());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn local_node_never_contacted() {
+        let local_peer_id = PeerId::from_public_key(&PublicKey::Ed25519([0; 32]));
+        let mut node = new_node(local_peer_id.clone());
+        node.start_lookup(
+            b"hello".to_vec(),
+            [(
+                local_peer_id,
+                vec!["/ip4/127.0.0.1/tcp/1".parse::<Multiaddr>().unwrap()],
+            )],
+        );
+
+        assert!(matches!(
+            node.next_action(),
+            Some(Action::LookupFinished(_))
+        ));
+    }
+
+    #[test]
+    fn refresh_keys() {
+        let local_peer_id = PeerId::from_public_key(&PublicKey::Ed25519([0; 32]));
+        let mut node = new_node(local_peer_id.clone());
+        assert!(node.next_refresh().is_none());
+
+        let keys = node.refresh_keys(&Duration::from_secs(0));
+        assert_eq!(keys.len(), 1 + usize::try_from(REFRESHED_BUCKETS).unwrap());
+        assert_eq!(keys[0], local_peer_id.as_bytes());
+
+        // Each other key belongs to a different bucket, starting with the furthest one.
+        let local_peer_id_hashed = sha256(local_peer_id.as_bytes());
+        for (bucket_leading_zeros, key) in (0..).zip(&keys[1..]) {
+            assert_eq!(
+                leading_zeros(&distance(&local_peer_id_hashed, key)),
+                bucket_leading_zeros
+            );
+        }
+
+        assert_eq!(node.next_refresh(), Some(&Duration::from_secs(60)));
+        assert!(node.refresh_keys(&Duration::from_secs(59)).is_empty());
+        assert!(!node.refresh_keys(&Duration::from_secs(60)).is_empty());
     }
 }
@@ -0,0 +1,205 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage for the records of the Kademlia distributed hash table.
+//!
+//! Each node of the Kademlia network stores the records whose key is close to its own identity.
+//! Other nodes can store records using a put value request and retrieve them using a get value
+//! request. See [`crate::network::protocol::KademliaRequest`].
+//!
+//! The [`RecordStore`] is bounded both in number of records and in size of each record, as the
+//! records are provided by remotes that can't be trusted. Each record expires after a certain
+//! duration, after which it is no longer returned and can be replaced with a new one. Nodes that
+//! want a record to remain available must regularly store it again.
+
+use alloc::vec::Vec;
+use core::{ops::Add, time::Duration};
+
+/// Configuration for a [`RecordStore`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum number of records that can be stored at the same time.
+    pub max_records: usize,
+
+    /// Maximum size, in bytes, of the value of a record.
+    pub max_value_size: usize,
+
+    /// Duration after which a record expires after having been stored.
+    pub record_ttl: Duration,
+}
+
+/// Collection of Kademlia records.
+pub struct RecordStore<TNow> {
+    /// List of records. Values are the value of the record and its expiration.
+    records: hashbrown::HashMap<Vec<u8>, (Vec<u8>, TNow), fnv::FnvBuildHasher>,
+
+    /// See [`Config::max_records`].
+    max_records: usize,
+
+    /// See [`Config::max_value_size`].
+    max_value_size: usize,
+
+    /// See [`Config::record_ttl`].
+    record_ttl: Duration,
+}
+
+impl<TNow> RecordStore<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Ord,
+{
+    /// Initializes a new empty store.
+    pub fn new(config: Config) -> Self {
+        RecordStore {
+            records: hashbrown::HashMap::with_capacity_and_hasher(
+                config.max_records,
+                Default::default(),
+            ),
+            max_records: config.max_records,
+            max_value_size: config.max_value_size,
+            record_ttl: config.record_ttl,
+        }
+    }
+
+    /// Returns the number of records in the store, including the ones that have expired but
+    /// haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns `true` if the store doesn't contain any record.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the value of the record with the given key, if any and if it hasn't expired.
+    pub fn get(&self, now: &TNow, key: &[u8]) -> Option<&[u8]> {
+        match self.records.get(key) {
+            Some((value, expiration)) if *expiration > *now => Some(&value[..]),
+            _ => None,
+        }
+    }
+
+    /// Inserts a record in the store, or replaces the value of an existing record. The
+    /// expiration of the record is reset to [`Config::record_ttl`] from `now`.
+    ///
+    /// If the store is full, the records that have expired are removed in order to make space.
+    pub fn put(&mut self, now: &TNow, key: Vec<u8>, value: Vec<u8>) -> Result<(), PutError> {
+        if value.len() > self.max_value_size {
+            return Err(PutError::ValueTooLarge);
+        }
+
+        if !self.records.contains_key(&key) && self.records.len() >= self.max_records {
+            self.remove_expired(now);
+            if self.records.len() >= self.max_records {
+                return Err(PutError::Full);
+            }
+        }
+
+        let expiration = now.clone() + self.record_ttl;
+        self.records.insert(key, (value, expiration));
+        Ok(())
+    }
+
+    /// Removes the record with the given key. Returns its value if it was in the store, even if
+    /// it has expired.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.records.remove(key).map(|(value, _)| value)
+    }
+
+    /// Removes from the store all the records that have expired.
+    pub fn remove_expired(&mut self, now: &TNow) {
+        self.records.retain(|_, (_, expiration)| *expiration > *now);
+    }
+}
+
+/// Error potentially returned by [`RecordStore::put`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum PutError {
+    /// Value of the record is larger than [`Config::max_value_size`].
+    ValueTooLarge,
+    /// Store already contains [`Config::max_records`] records that haven't expired.
+    Full,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, PutError, RecordStore};
+    use core::time::Duration;
+
+    fn new_store() -> RecordStore<Duration> {
+        RecordStore::new(Config {
+            max_records: 2,
+            max_value_size: 8,
+            record_ttl: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn put_get_expire() {
+        let mut store = new_store();
+        store
+            .put(&Duration::from_secs(0), b"foo".to_vec(), b"bar".to_vec())
+            .unwrap();
+        assert_eq!(
+            store.get(&Duration::from_secs(5), b"foo"),
+            Some(&b"bar"[..])
+        );
+        assert_eq!(store.get(&Duration::from_secs(5), b"baz"), None);
+        assert_eq!(store.get(&Duration::from_secs(10), b"foo"), None);
+
+        // Putting the record again resets its expiration.
+        store
+            .put(&Duration::from_secs(5), b"foo".to_vec(), b"qux".to_vec())
+            .unwrap();
+        assert_eq!(
+            store.get(&Duration::from_secs(12), b"foo"),
+            Some(&b"qux"[..])
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut store = new_store();
+        assert_eq!(
+            store.put(&Duration::from_secs(0), b"foo".to_vec(), vec![0; 9]),
+            Err(PutError::ValueTooLarge)
+        );
+
+        store
+            .put(&Duration::from_secs(0), b"a".to_vec(), Vec::new())
+            .unwrap();
+        store
+            .put(&Duration::from_secs(5), b"b".to_vec(), Vec::new())
+            .unwrap();
+        assert_eq!(
+            store.put(&Duration::from_secs(5), b"c".to_vec(), Vec::new()),
+            Err(PutError::Full)
+        );
+
+        // Replacing an existing record is always possible.
+        store
+            .put(&Duration::from_secs(5), b"b".to_vec(), b"b".to_vec())
+            .unwrap();
+
+        // Once `a` has expired, there is space again.
+        store
+            .put(&Duration::from_secs(10), b"c".to_vec(), Vec::new())
+            .unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&Duration::from_secs(10), b"a"), None);
+    }
+}
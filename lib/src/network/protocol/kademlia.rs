@@ -24,13 +24,20 @@ use alloc::vec::Vec;
 
 // See https://github.com/libp2p/specs/tree/master/kad-dht#rpc-messages for the protobuf format.
 
+/// Value of the `type` field of a message for a request to store a record.
+const MESSAGE_TY_PUT_VALUE: u64 = 0;
+/// Value of the `type` field of a message for a request to find a record.
+const MESSAGE_TY_GET_VALUE: u64 = 1;
+/// Value of the `type` field of a message for a request to find the closest nodes to a key.
+const MESSAGE_TY_FIND_NODE: u64 = 4;
+
 /// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
 /// return the nodes closest to the parameter.
 // TODO: parameter type?
 pub fn build_find_node_request(peer_id: &[u8]) -> Vec<u8> {
     // The capacity is arbitrary but large enough to avoid Vec reallocations.
     let mut out = Vec::with_capacity(64 + peer_id.len());
-    for slice in protobuf::enum_tag_encode(1, 4) {
+    for slice in protobuf::enum_tag_encode(1, MESSAGE_TY_FIND_NODE) {
         out.extend_from_slice(slice.as_ref());
     }
     for slice in protobuf::bytes_tag_encode(2, peer_id) {
@@ -39,6 +46,163 @@ pub fn build_find_node_request(peer_id: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
+/// return the record stored under the given key, if any, and the nodes closest to that key.
+pub fn build_get_value_request(key: &[u8]) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(64 + key.len());
+    for slice in protobuf::enum_tag_encode(1, MESSAGE_TY_GET_VALUE) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, key) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
+/// store a record.
+///
+/// The response to this request is identical to the request itself. In other words, this
+/// function can also be used to build the response to a [`KademliaRequest::PutValue`].
+pub fn build_put_value_request(key: &[u8], value: &[u8]) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(64 + 2 * key.len() + value.len());
+    for slice in protobuf::enum_tag_encode(1, MESSAGE_TY_PUT_VALUE) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, key) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    let record = protobuf::bytes_tag_encode(1, key)
+        .map(either::Left)
+        .chain(protobuf::bytes_tag_encode(2, value).map(either::Right));
+    for slice in protobuf::message_tag_encode(3, record) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Request received on the Kademlia request-response protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KademliaRequest<'a> {
+    /// Remote asks for the nodes closest to the given key.
+    ///
+    /// See [`build_find_node_request`] and [`build_find_node_response`].
+    FindNode {
+        /// Key whose closest nodes are requested.
+        key: &'a [u8],
+    },
+    /// Remote asks for the record stored under the given key, and the nodes closest to it.
+    ///
+    /// See [`build_get_value_request`] and [`build_get_value_response`].
+    GetValue {
+        /// Key of the requested record.
+        key: &'a [u8],
+    },
+    /// Remote asks to store a record.
+    ///
+    /// See [`build_put_value_request`].
+    PutValue {
+        /// Key of the record.
+        key: &'a [u8],
+        /// Value of the record.
+        value: &'a [u8],
+    },
+}
+
+/// Decodes a request received on the Kademlia request-response protocol.
+pub fn decode_kademlia_request(
+    request_bytes: &[u8],
+) -> Result<KademliaRequest<'_>, DecodeKademliaRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] request_ty = 1 => protobuf::enum_tag_decode,
+            #[optional] key = 2 => protobuf::bytes_tag_decode,
+            #[optional] record = 3 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] key = 1 => protobuf::bytes_tag_decode,
+                #[optional] value = 2 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, out)) => out,
+        Err(_) => {
+            return Err(DecodeKademliaRequestError::ProtobufDecode(
+                ProtobufDecodeError,
+            ))
+        }
+    };
+
+    let key = decoded.key.ok_or(DecodeKademliaRequestError::MissingKey)?;
+
+    match decoded.request_ty.unwrap_or(MESSAGE_TY_PUT_VALUE) {
+        MESSAGE_TY_FIND_NODE => Ok(KademliaRequest::FindNode { key }),
+        MESSAGE_TY_GET_VALUE => Ok(KademliaRequest::GetValue { key }),
+        MESSAGE_TY_PUT_VALUE => {
+            let record = decoded
+                .record
+                .ok_or(DecodeKademliaRequestError::MissingRecord)?;
+            if record.key != key {
+                return Err(DecodeKademliaRequestError::RecordKeyMismatch);
+            }
+            Ok(KademliaRequest::PutValue {
+                key,
+                value: record.value.unwrap_or_default(),
+            })
+        }
+        _ => Err(DecodeKademliaRequestError::UnsupportedRequestTy),
+    }
+}
+
+/// Builds the wire message to send back as a response to a [`KademliaRequest::FindNode`].
+///
+/// The `closer_peers` are the peers of the local k-buckets closest to the requested key, and
+/// their addresses.
+pub fn build_find_node_response(
+    closer_peers: &[(peer_id::PeerId, Vec<multiaddr::Multiaddr>)],
+) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(16 + closer_peers.len() * 128);
+    for slice in protobuf::enum_tag_encode(1, MESSAGE_TY_FIND_NODE) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    encode_closer_peers(&mut out, closer_peers);
+    out
+}
+
+/// Builds the wire message to send back as a response to a [`KademliaRequest::GetValue`].
+///
+/// `value` is the value of the record stored locally under `key`, if any. The `closer_peers`
+/// are the peers of the local k-buckets closest to the requested key, and their addresses.
+pub fn build_get_value_response(
+    key: &[u8],
+    value: Option<&[u8]>,
+    closer_peers: &[(peer_id::PeerId, Vec<multiaddr::Multiaddr>)],
+) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(
+        64 + key.len() + value.map_or(0, |v| v.len()) + closer_peers.len() * 128,
+    );
+    for slice in protobuf::enum_tag_encode(1, MESSAGE_TY_GET_VALUE) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, key) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    if let Some(value) = value {
+        let record = protobuf::bytes_tag_encode(1, key)
+            .map(either::Left)
+            .chain(protobuf::bytes_tag_encode(2, value).map(either::Right));
+        for slice in protobuf::message_tag_encode(3, record) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+    encode_closer_peers(&mut out, closer_peers);
+    out
+}
+
 /// Decodes a response to a request built using [`build_find_node_request`].
 // TODO: return a borrow of the response bytes ; we're limited by protobuf library
 pub fn decode_find_node_response(
@@ -55,7 +219,7 @@ pub fn decode_find_node_response(
     );
 
     let closer_peers = match nom::Finish::finish(parser(response_bytes)) {
-        Ok((_, out)) if out.response_ty.unwrap_or(0) == 4 => out.peers,
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == MESSAGE_TY_FIND_NODE => out.peers,
         Ok((_, _)) => return Err(DecodeFindNodeResponseError::BadResponseTy),
         Err(_) => {
             return Err(DecodeFindNodeResponseError::ProtobufDecode(
@@ -64,15 +228,122 @@ pub fn decode_find_node_response(
         }
     };
 
-    let mut result = Vec::with_capacity(closer_peers.len());
-    for peer in closer_peers {
-        let peer_id = peer_id::PeerId::from_bytes(peer.peer_id.to_vec())
-            .map_err(|(err, _)| DecodeFindNodeResponseError::BadPeerId(err))?;
+    decode_closer_peers(closer_peers.into_iter().map(|p| (p.peer_id, p.addrs))).map_err(|err| {
+        match err {
+            DecodePeerError::BadPeerId(err) => DecodeFindNodeResponseError::BadPeerId(err),
+            DecodePeerError::BadMultiaddr(err) => DecodeFindNodeResponseError::BadMultiaddr(err),
+        }
+    })
+}
 
-        let mut multiaddrs = Vec::with_capacity(peer.addrs.len());
-        for addr in peer.addrs {
+/// Decoded response to a request built using [`build_get_value_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetValueResponse {
+    /// Value of the record stored by the remote under the requested key, if any.
+    pub value: Option<Vec<u8>>,
+    /// Nodes that the remote knows are closest to the requested key, and their addresses.
+    pub closer_peers: Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>,
+}
+
+/// Decodes a response to a request built using [`build_get_value_request`].
+///
+/// Returns an error if the response contains a record whose key isn't `requested_key`.
+pub fn decode_get_value_response(
+    requested_key: &[u8],
+    response_bytes: &[u8],
+) -> Result<GetValueResponse, DecodeGetValueResponseError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] response_ty = 1 => protobuf::enum_tag_decode,
+            #[optional] record = 3 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] key = 1 => protobuf::bytes_tag_decode,
+                #[optional] value = 2 => protobuf::bytes_tag_decode,
+            }),
+            #[repeated(max = 1024)] peers = 8 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] peer_id = 1 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] addrs = 2 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == MESSAGE_TY_GET_VALUE => out,
+        Ok((_, _)) => return Err(DecodeGetValueResponseError::BadResponseTy),
+        Err(_) => {
+            return Err(DecodeGetValueResponseError::ProtobufDecode(
+                ProtobufDecodeError,
+            ))
+        }
+    };
+
+    let value = match decoded.record {
+        Some(record) if record.key != requested_key => {
+            return Err(DecodeGetValueResponseError::RecordKeyMismatch)
+        }
+        Some(record) => Some(record.value.unwrap_or_default().to_vec()),
+        None => None,
+    };
+
+    let closer_peers = decode_closer_peers(decoded.peers.into_iter().map(|p| (p.peer_id, p.addrs)))
+        .map_err(|err| match err {
+            DecodePeerError::BadPeerId(err) => DecodeGetValueResponseError::BadPeerId(err),
+            DecodePeerError::BadMultiaddr(err) => DecodeGetValueResponseError::BadMultiaddr(err),
+        })?;
+
+    Ok(GetValueResponse {
+        value,
+        closer_peers,
+    })
+}
+
+/// Decodes a response to a request built using [`build_put_value_request`].
+pub fn decode_put_value_response(response_bytes: &[u8]) -> Result<(), DecodePutValueResponseError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] response_ty = 1 => protobuf::enum_tag_decode,
+        }),
+    );
+
+    match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == MESSAGE_TY_PUT_VALUE => Ok(()),
+        Ok((_, _)) => Err(DecodePutValueResponseError::BadResponseTy),
+        Err(_) => Err(DecodePutValueResponseError::ProtobufDecode(
+            ProtobufDecodeError,
+        )),
+    }
+}
+
+fn encode_closer_peers(
+    out: &mut Vec<u8>,
+    closer_peers: &[(peer_id::PeerId, Vec<multiaddr::Multiaddr>)],
+) {
+    for (peer_id, addrs) in closer_peers {
+        let peer = protobuf::bytes_tag_encode(1, peer_id.as_bytes())
+            .map(either::Left)
+            .chain(
+                addrs
+                    .iter()
+                    .flat_map(|addr| protobuf::bytes_tag_encode(2, addr))
+                    .map(either::Right),
+            );
+        for slice in protobuf::message_tag_encode(8, peer) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+}
+
+fn decode_closer_peers<'a>(
+    peers: impl ExactSizeIterator<Item = (&'a [u8], Vec<&'a [u8]>)>,
+) -> Result<Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>, DecodePeerError> {
+    let mut result = Vec::with_capacity(peers.len());
+    for (peer_id, addrs) in peers {
+        let peer_id = peer_id::PeerId::from_bytes(peer_id.to_vec())
+            .map_err(|(err, _)| DecodePeerError::BadPeerId(err))?;
+
+        let mut multiaddrs = Vec::with_capacity(addrs.len());
+        for addr in addrs {
             let addr = multiaddr::Multiaddr::try_from(addr.to_vec())
-                .map_err(DecodeFindNodeResponseError::BadMultiaddr)?;
+                .map_err(DecodePeerError::BadMultiaddr)?;
             multiaddrs.push(addr);
         }
 
@@ -82,6 +353,28 @@ pub fn decode_find_node_response(
     Ok(result)
 }
 
+/// Error potentially returned by [`decode_closer_peers`].
+enum DecodePeerError {
+    BadPeerId(peer_id::FromBytesError),
+    BadMultiaddr(multiaddr::FromVecError),
+}
+
+/// Error potentially returned by [`decode_kademlia_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeKademliaRequestError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the request: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Request is of a type that isn't supported.
+    UnsupportedRequestTy,
+    /// Request doesn't contain any key.
+    MissingKey,
+    /// Request to store a record doesn't contain any record.
+    MissingRecord,
+    /// Request to store a record contains a record whose key isn't the key of the request.
+    RecordKeyMismatch,
+}
+
 /// Error potentially returned by [`decode_find_node_response`].
 #[derive(Debug, derive_more::Display)]
 pub enum DecodeFindNodeResponseError {
@@ -98,6 +391,118 @@ pub enum DecodeFindNodeResponseError {
     BadMultiaddr(multiaddr::FromVecError),
 }
 
+/// Error potentially returned by [`decode_get_value_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeGetValueResponseError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the response: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Response isn't a response to a get value request.
+    BadResponseTy,
+    /// Response contains a record whose key isn't the requested key.
+    RecordKeyMismatch,
+    /// Error while parsing a [`peer_id::PeerId`] in the response.
+    #[display(fmt = "Invalid PeerId: {_0}")]
+    BadPeerId(peer_id::FromBytesError),
+    /// Error while parsing a [`multiaddr::Multiaddr`] in the response.
+    #[display(fmt = "Invalid multiaddress: {_0}")]
+    BadMultiaddr(multiaddr::FromVecError),
+}
+
+/// Error potentially returned by [`decode_put_value_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodePutValueResponseError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the response: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Response isn't a response to a put value request.
+    BadResponseTy,
+}
+
 /// Error while decoding the Protobuf encoding.
 #[derive(Debug, derive_more::Display)]
 pub struct ProtobufDecodeError;
+
+#[cfg(test)]
+mod tests {
+    use crate::libp2p::{
+        multiaddr::Multiaddr,
+        peer_id::{PeerId, PublicKey},
+    };
+
+    fn closer_peers() -> Vec<(PeerId, Vec<Multiaddr>)> {
+        vec![
+            (
+                PeerId::from_public_key(&PublicKey::Ed25519([1; 32])),
+                vec!["/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap()],
+            ),
+            (
+                PeerId::from_public_key(&PublicKey::Ed25519([2; 32])),
+                Vec::new(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn find_node_round_trip() {
+        let key = [1, 2, 3, 4, 5, 6, 7, 8];
+        let request = super::build_find_node_request(&key);
+        assert_eq!(
+            super::decode_kademlia_request(&request).unwrap(),
+            super::KademliaRequest::FindNode { key: &key }
+        );
+
+        let response = super::build_find_node_response(&closer_peers());
+        assert_eq!(
+            super::decode_find_node_response(&response).unwrap(),
+            closer_peers()
+        );
+    }
+
+    #[test]
+    fn get_value_round_trip() {
+        let key = b"hello";
+        let request = super::build_get_value_request(key);
+        assert_eq!(
+            super::decode_kademlia_request(&request).unwrap(),
+            super::KademliaRequest::GetValue { key }
+        );
+
+        let response = super::build_get_value_response(key, Some(&b"world"[..]), &closer_peers());
+        assert_eq!(
+            super::decode_get_value_response(key, &response).unwrap(),
+            super::GetValueResponse {
+                value: Some(b"world".to_vec()),
+                closer_peers: closer_peers(),
+            }
+        );
+
+        let response = super::build_get_value_response(key, None, &[]);
+        assert_eq!(
+            super::decode_get_value_response(key, &response).unwrap(),
+            super::GetValueResponse {
+                value: None,
+                closer_peers: Vec::new(),
+            }
+        );
+
+        let response = super::build_get_value_response(b"other", Some(&b"world"[..]), &[]);
+        assert!(super::decode_get_value_response(key, &response).is_err());
+    }
+
+    #[test]
+    fn put_value_round_trip() {
+        let request = super::build_put_value_request(b"hello", b"world");
+        assert_eq!(
+            super::decode_kademlia_request(&request).unwrap(),
+            super::KademliaRequest::PutValue {
+                key: b"hello",
+                value: b"world"
+            }
+        );
+        assert!(super::decode_put_value_response(&request).is_ok());
+        assert!(
+            super::decode_put_value_response(&super::build_get_value_request(b"hello")).is_err()
+        );
+    }
+}
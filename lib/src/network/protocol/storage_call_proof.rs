@@ -92,6 +92,102 @@ pub fn build_call_proof_request<'a>(
     )
 }
 
+/// Storage proof request or call proof request received from a remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageOrCallProofRequest<'a> {
+    /// Request for the proof of the storage values of a list of keys.
+    StorageProof {
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// List of storage keys to prove.
+        keys: Vec<&'a [u8]>,
+    },
+    /// Request for the proof of the storage accesses performed by a runtime call.
+    CallProof {
+        /// Hash of the block on top of which to perform the call.
+        block_hash: [u8; 32],
+        /// Name of the runtime function to call.
+        method: &'a str,
+        /// Input to pass to the call.
+        parameter: &'a [u8],
+    },
+}
+
+/// Decodes a storage proof request or a call proof request.
+pub fn decode_storage_or_call_proof_request(
+    request_bytes: &[u8],
+) -> Result<StorageOrCallProofRequest<'_>, DecodeStorageOrCallProofRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] call = 1 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block_hash = 2 => protobuf::bytes_tag_decode,
+                #[required] method = 3 => protobuf::string_tag_decode,
+                #[optional] parameter = 4 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] storage = 2 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block_hash = 2 => protobuf::bytes_tag_decode,
+                #[repeated(max = usize::max_value())] keys = 3 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeStorageOrCallProofRequestError::ProtobufDecode),
+    };
+
+    match (decoded.call, decoded.storage) {
+        (Some(call), None) => Ok(StorageOrCallProofRequest::CallProof {
+            block_hash: <[u8; 32]>::try_from(call.block_hash)
+                .map_err(|_| DecodeStorageOrCallProofRequestError::BadBlockHash)?,
+            method: call.method,
+            parameter: call.parameter.unwrap_or_default(),
+        }),
+        (None, Some(storage)) => Ok(StorageOrCallProofRequest::StorageProof {
+            block_hash: <[u8; 32]>::try_from(storage.block_hash)
+                .map_err(|_| DecodeStorageOrCallProofRequestError::BadBlockHash)?,
+            keys: storage.keys,
+        }),
+        _ => Err(DecodeStorageOrCallProofRequestError::UnsupportedRequestTy),
+    }
+}
+
+/// Error potentially returned by [`decode_storage_or_call_proof_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStorageOrCallProofRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Request is neither a storage proof request nor a call proof request.
+    UnsupportedRequestTy,
+    /// Block hash in the request doesn't have the correct length.
+    BadBlockHash,
+}
+
+/// Builds the bytes corresponding to a response to a storage proof request or a call proof
+/// request.
+///
+/// `proof` must be a SCALE-encoded Merkle proof, or `None` if the request can't be answered.
+pub fn build_storage_or_call_proof_response(
+    ty: StorageOrCallProof,
+    proof: Option<&[u8]>,
+) -> Vec<u8> {
+    let field_num = match ty {
+        StorageOrCallProof::CallProof => 1,
+        StorageOrCallProof::StorageProof => 2,
+    };
+
+    protobuf::message_tag_encode(
+        field_num,
+        proof
+            .into_iter()
+            .flat_map(|proof| protobuf::bytes_tag_encode(2, proof)),
+    )
+    .fold(Vec::new(), |mut a, b| {
+        a.extend_from_slice(b.as_ref());
+        a
+    })
+}
+
 /// Decodes a response to a storage proof request or a call proof request.
 ///
 /// On success, returns a SCALE-encoded Merkle proof, or `None` if the remote couldn't answer
@@ -141,3 +237,68 @@ pub enum StorageOrCallProof {
     StorageProof,
     CallProof,
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn call_proof_request_encode_decode() {
+        let encoded = super::build_call_proof_request(super::CallProofRequestConfig {
+            block_hash: [0x12; 32],
+            method: "Core_version",
+            parameter_vectored: [&[1, 2][..], &[3][..]].into_iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(
+            super::decode_storage_or_call_proof_request(&encoded).unwrap(),
+            super::StorageOrCallProofRequest::CallProof {
+                block_hash: [0x12; 32],
+                method: "Core_version",
+                parameter: &[1, 2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn storage_proof_request_encode_decode() {
+        let encoded = super::build_storage_proof_request(super::StorageProofRequestConfig {
+            block_hash: [0x34; 32],
+            keys: [&b"foo"[..], &b"bar"[..]].into_iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        assert_eq!(
+            super::decode_storage_or_call_proof_request(&encoded).unwrap(),
+            super::StorageOrCallProofRequest::StorageProof {
+                block_hash: [0x34; 32],
+                keys: vec![&b"foo"[..], &b"bar"[..]],
+            }
+        );
+    }
+
+    #[test]
+    fn response_encode_decode() {
+        for ty in [
+            super::StorageOrCallProof::CallProof,
+            super::StorageOrCallProof::StorageProof,
+        ] {
+            let encoded = super::build_storage_or_call_proof_response(ty, Some(&[5, 6, 7]));
+            assert_eq!(
+                super::decode_storage_or_call_proof_response(ty, &encoded).unwrap(),
+                Some(&[5, 6, 7][..])
+            );
+
+            let encoded = super::build_storage_or_call_proof_response(ty, None);
+            assert_eq!(
+                super::decode_storage_or_call_proof_response(ty, &encoded).unwrap(),
+                None
+            );
+        }
+    }
+}
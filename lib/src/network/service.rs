@@ -43,14 +43,15 @@ mod notifications;
 mod requests_responses;
 
 pub use notifications::{
-    EncodedBlockAnnounce, EncodedBlockAnnounceHandshake, EncodedGrandpaCommitMessage, GrandpaState,
-    NotificationsOutErr,
+    EncodedBlockAnnounce, EncodedBlockAnnounceHandshake, EncodedGrandpaCommitMessage,
+    EncodedGrandpaVoteMessage, GrandpaState, NotificationsOutErr,
 };
 
 pub use requests_responses::{
     BlocksRequestError, BlocksRequestResponseEntryError, CallProofRequestError, DiscoveryError,
-    EncodedGrandpaWarpSyncResponse, EncodedMerkleProof, EncodedStateResponse,
-    GrandpaWarpSyncRequestError, KademliaFindNodeError, KademliaOperationId, RequestResult,
+    EncodedGrandpaWarpSyncResponse, EncodedIdentifyResponse, EncodedMerkleProof,
+    EncodedStateResponse, GrandpaWarpSyncRequestError, IdentifyRequestError, KademliaFindNodeError,
+    KademliaGetValueError, KademliaOperationId, KademliaPutValueError, RequestResult,
     StateRequestError, StorageProofRequestError,
 };
 
@@ -119,10 +120,28 @@ pub struct ChainConfig {
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// `true` if incoming storage proof and call proof requests are allowed.
+    ///
+    /// Only set this to `true` if the API user answers the [`Event::StorageProofRequestIn`]
+    /// and [`Event::CallProofRequestIn`] events.
+    pub allow_inbound_storage_and_call_proof_requests: bool,
+
+    /// `true` if incoming Kademlia requests are allowed.
+    ///
+    /// Setting this to `true` advertises the local node as part of the Kademlia DHT. Only do so
+    /// if the API user answers the [`Event::KademliaFindNodeRequestIn`],
+    /// [`Event::KademliaGetValueRequestIn`] and [`Event::KademliaPutValueRequestIn`] events.
+    pub allow_inbound_kademlia_requests: bool,
+
     pub in_slots: u32,
 
     pub out_slots: u32,
 
+    /// If `true`, only reserved peers (see [`ChainNetwork::add_reserved_peer`]) are assigned
+    /// slots. Block announces substreams opened by other peers are refused, and no outbound slot
+    /// is assigned to them.
+    pub reserved_only: bool,
+
     /// Hash of the best block according to the local node.
     pub best_hash: [u8; 32],
     /// Height of the best block according to the local node.
@@ -148,9 +167,17 @@ pub struct ChainNetwork<TNow> {
     /// See [`Config::max_addresses_per_peer`].
     max_addresses_per_peer: NonZeroUsize,
 
-    /// Contains an entry for each peer present in at least one k-bucket of a chain.
+    /// Contains an entry for each peer present in at least one k-bucket of a chain, in the
+    /// reserved peers of at least one chain, or in [`ChainNetwork::dial_requests`].
     kbuckets_peers: hashbrown::HashMap<PeerId, KBucketsPeer, SipHasherBuild>,
 
+    /// For each peer, the number of calls to [`ChainNetwork::add_dial_request`] that haven't
+    /// been balanced with a call to [`ChainNetwork::remove_dial_request`] yet. The peers in this
+    /// list are always marked as desired in the underlying state machine.
+    ///
+    /// Each entry has a corresponding reference in [`ChainNetwork::kbuckets_peers`].
+    dial_requests: hashbrown::HashMap<PeerId, NonZeroUsize, SipHasherBuild>,
+
     /// Tuples of `(peer_id, chain_index)` that have been reported as open to the API user.
     ///
     /// This is a subset of the block announce notification protocol substreams that are open.
@@ -202,6 +229,13 @@ struct Chain<TNow> {
     /// state machine.
     out_peers: hashbrown::HashSet<PeerId, SipHasherBuild>,
 
+    /// List of reserved peers of this chain. These peers are always assigned a slot, and are not
+    /// taken into account when comparing the number of entries in [`Chain::in_peers`] and
+    /// [`Chain::out_peers`] with the maximum number of slots.
+    ///
+    /// Each entry has a corresponding reference in [`ChainNetwork::kbuckets_peers`].
+    reserved_peers: hashbrown::HashSet<PeerId, SipHasherBuild>,
+
     /// Kademlia k-buckets of this chain.
     ///
     /// Used in order to hold the list of peers that are known to be part of this chain.
@@ -214,7 +248,9 @@ struct Chain<TNow> {
 }
 
 struct KBucketsPeer {
-    /// Number of k-buckets containing this peer. Used to know when to remove this entry.
+    /// Number of k-buckets containing this peer, plus number of chains this peer is a reserved
+    /// peer of, plus one if this peer is in [`ChainNetwork::dial_requests`]. Used to know when to
+    /// remove this entry.
     num_references: NonZeroUsize,
 
     /// List of addresses known for this peer, and whether we currently have an outgoing connection
@@ -237,9 +273,14 @@ struct KBucketsPeer {
 enum InRequestTy {
     Identify { observed_addr: multiaddr::Multiaddr },
     Blocks,
+    StorageOrCallProof(protocol::StorageOrCallProof),
+    KademliaFindNode,
+    KademliaGetValue { key: Vec<u8> },
+    KademliaPutValue { key: Vec<u8>, value: Vec<u8> },
 }
 
 enum OutRequestTy {
+    Identify,
     Blocks {
         checked: Option<protocol::BlocksRequestConfig>,
     },
@@ -249,6 +290,10 @@ enum OutRequestTy {
     CallProof,
     KademliaFindNode,
     KademliaDiscoveryFindNode(KademliaOperationId),
+    KademliaGetValue {
+        key: Vec<u8>,
+    },
+    KademliaPutValue,
 }
 
 // Update this when a new notifications protocol is added.
@@ -282,6 +327,10 @@ where
                         usize::try_from(chain.out_slots).unwrap_or(0),
                         SipHasherBuild::new(randomness.gen()),
                     ),
+                    reserved_peers: hashbrown::HashSet::with_capacity_and_hasher(
+                        0,
+                        SipHasherBuild::new(randomness.gen()),
+                    ),
                     chain_config: chain,
                     kbuckets: kademlia::kbuckets::KBuckets::new(
                         local_peer_id.clone(),
@@ -323,6 +372,10 @@ where
                 config.peers_capacity,
                 SipHasherBuild::new(randomness.gen()),
             ),
+            dial_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                SipHasherBuild::new(randomness.gen()),
+            ),
             num_pending_per_peer: hashbrown::HashMap::with_capacity_and_hasher(
                 config.peers_capacity,
                 SipHasherBuild::new(randomness.gen()),
@@ -466,6 +519,31 @@ where
         })
     }
 
+    /// Returns the [`kademlia::ENTRIES_PER_BUCKET`] nodes of the k-buckets of the given chain
+    /// that are the closest to the given key, ordered by increasing distance, and their
+    /// addresses.
+    ///
+    /// This is the list to send back when a remote sends a Kademlia find node request, and the
+    /// list of nodes to start a [`kademlia::Kademlia`] lookup with.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn closest_peers(
+        &self,
+        chain_index: usize,
+        key: &[u8],
+    ) -> Vec<(PeerId, Vec<multiaddr::Multiaddr>)> {
+        kademlia::closest_peers(
+            key,
+            self.discovered_nodes(chain_index)
+                .map(|(peer_id, addresses)| (peer_id.clone(), addresses)),
+        )
+        .map(|(peer_id, addresses)| (peer_id, addresses.cloned().collect()))
+        .collect()
+    }
+
     /// After calling [`ChainNetwork::next_start_connect`], notifies the [`ChainNetwork`] of the
     /// success of the dialing attempt.
     ///
@@ -1010,20 +1088,11 @@ where
                 hashbrown::hash_map::Entry::Vacant(entry) => entry,
             };
 
-            // TODO: O(n)
             let multiaddr: multiaddr::Multiaddr = {
                 let potential = self
-                    .chains
-                    .iter_mut()
-                    .flat_map(|chain| chain.kbuckets.iter_mut_ordered())
-                    .find(|(p, _)| **p == *entry.key())
-                    .and_then(|(peer_id, _)| {
-                        self.kbuckets_peers
-                            .get_mut(peer_id)
-                            .unwrap()
-                            .addresses
-                            .addr_to_pending()
-                    });
+                    .kbuckets_peers
+                    .get_mut(entry.key())
+                    .and_then(|kbuckets_peer| kbuckets_peer.addresses.addr_to_pending());
                 match potential {
                     Some(a) => a.clone(),
                     None => continue,
@@ -1063,24 +1132,34 @@ where
     pub fn slots_to_assign(&'_ self, chain_index: usize) -> impl Iterator<Item = &'_ PeerId> + '_ {
         let chain = &self.chains[chain_index];
 
+        // Reserved peers without a slot are always returned first, as they aren't subject to the
+        // maximum number of slots.
+        let reserved_peers = chain.reserved_peers.iter().filter(|peer_id| {
+            !chain.out_peers.contains(*peer_id) && !chain.in_peers.contains(*peer_id)
+        });
+
         // Check if maximum number of slots is reached.
-        if chain.out_peers.len()
-            >= usize::try_from(chain.chain_config.out_slots).unwrap_or(usize::max_value())
+        if chain.chain_config.reserved_only
+            || chain.num_out_slots_used()
+                >= usize::try_from(chain.chain_config.out_slots).unwrap_or(usize::max_value())
         {
-            return either::Right(iter::empty());
+            return reserved_peers.chain(either::Right(iter::empty()));
         }
 
         // TODO: return in some specific order?
-        either::Left(
+        reserved_peers.chain(either::Left(
             chain
                 .kbuckets
                 .iter_ordered()
                 .map(|(peer_id, _)| peer_id)
                 .filter(|peer_id| {
-                    // Don't assign slots to peers that already have a slot.
-                    !chain.out_peers.contains(*peer_id) && !chain.in_peers.contains(*peer_id)
+                    // Don't assign slots to peers that already have a slot. Reserved peers are
+                    // already part of the list above.
+                    !chain.out_peers.contains(*peer_id)
+                        && !chain.in_peers.contains(*peer_id)
+                        && !chain.reserved_peers.contains(*peer_id)
                 }),
-        )
+        ))
     }
 
     // TODO: docs
@@ -1088,9 +1167,11 @@ where
     pub fn assign_out_slot(&mut self, chain_index: usize, peer_id: PeerId) {
         let chain = &mut self.chains[chain_index];
 
-        // Check if maximum number of slots is reached.
-        if chain.out_peers.len()
-            >= usize::try_from(chain.chain_config.out_slots).unwrap_or(usize::max_value())
+        // Check if maximum number of slots is reached. Reserved peers are always allowed a slot.
+        if !chain.reserved_peers.contains(&peer_id)
+            && (chain.chain_config.reserved_only
+                || chain.num_out_slots_used()
+                    >= usize::try_from(chain.chain_config.out_slots).unwrap_or(usize::max_value()))
         {
             return; // TODO: return error?
         }
@@ -1129,6 +1210,265 @@ where
             }
         }
     }
+
+    /// Adds a peer to the list of reserved peers of the given chain, together with an address
+    /// where it can be reached.
+    ///
+    /// Reserved peers are always assigned a slot, even if the maximum number of slots configured
+    /// with [`ChainConfig::in_slots`] and [`ChainConfig::out_slots`] is reached, and don't count
+    /// towards these limits. Reserved peers whose slot gets unassigned, for example because they
+    /// disconnect, are returned first by [`ChainNetwork::slots_to_assign`].
+    ///
+    /// Returns `true` if an outbound slot has been assigned to the peer as a result of this
+    /// call, in other words if it didn't have any slot yet.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn add_reserved_peer(
+        &mut self,
+        chain_index: usize,
+        peer_id: PeerId,
+        address: multiaddr::Multiaddr,
+    ) -> bool {
+        let max_addresses_per_peer = self.max_addresses_per_peer.get();
+        let kbuckets_peer = if self.chains[chain_index]
+            .reserved_peers
+            .insert(peer_id.clone())
+        {
+            self.kbuckets_peers_add_reference(peer_id.clone())
+        } else {
+            self.kbuckets_peers.get_mut(&peer_id).unwrap()
+        };
+
+        if kbuckets_peer.addresses.len() < max_addresses_per_peer {
+            kbuckets_peer.addresses.insert_discovered(address);
+        }
+
+        // List of addresses must never be empty.
+        debug_assert!(!kbuckets_peer.addresses.is_empty());
+
+        let chain = &self.chains[chain_index];
+        if chain.out_peers.contains(&peer_id) || chain.in_peers.contains(&peer_id) {
+            return false;
+        }
+
+        self.assign_out_slot(chain_index, peer_id);
+        true
+    }
+
+    /// Removes a peer from the list of reserved peers of the given chain.
+    ///
+    /// The slot of the peer, if any, is unassigned and its type returned, similar to
+    /// [`ChainNetwork::unassign_slot`]. Does nothing and returns `None` if the peer wasn't a
+    /// reserved peer.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn remove_reserved_peer(&mut self, chain_index: usize, peer_id: &PeerId) -> Option<SlotTy> {
+        if !self.chains[chain_index].reserved_peers.remove(peer_id) {
+            return None;
+        }
+
+        self.kbuckets_peers_remove_reference(peer_id);
+        self.unassign_slot(chain_index, peer_id)
+    }
+
+    /// Returns the list of reserved peers of the given chain.
+    ///
+    /// See [`ChainNetwork::add_reserved_peer`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn reserved_peers(&'_ self, chain_index: usize) -> impl Iterator<Item = &'_ PeerId> + '_ {
+        self.chains[chain_index].reserved_peers.iter()
+    }
+
+    /// Returns `true` if the given peer is a reserved peer of the given chain.
+    ///
+    /// See [`ChainNetwork::add_reserved_peer`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn is_reserved_peer(&self, chain_index: usize, peer_id: &PeerId) -> bool {
+        self.chains[chain_index].reserved_peers.contains(peer_id)
+    }
+
+    /// Asks for a connection to the given peer to be opened, in order to send requests to it,
+    /// for example a Kademlia find node request with [`ChainNetwork::start_kademlia_find_node`].
+    /// The peer can then be returned by [`ChainNetwork::next_start_connect`].
+    ///
+    /// The given addresses are added to the list of known addresses of this peer. Returns
+    /// `false` and does nothing if no address is known for this peer.
+    ///
+    /// Use [`ChainNetwork::can_start_requests`] to know when the connection is established.
+    /// Each call that returns `true` must later be balanced with a call to
+    /// [`ChainNetwork::remove_dial_request`].
+    pub fn add_dial_request(
+        &mut self,
+        peer_id: PeerId,
+        addresses: impl IntoIterator<Item = multiaddr::Multiaddr>,
+    ) -> bool {
+        let mut addresses = addresses.into_iter().peekable();
+        if addresses.peek().is_none() && !self.kbuckets_peers.contains_key(&peer_id) {
+            return false;
+        }
+
+        let max_addresses_per_peer = self.max_addresses_per_peer.get();
+
+        match self.dial_requests.entry(peer_id.clone()) {
+            hashbrown::hash_map::Entry::Occupied(mut e) => {
+                *e.get_mut() = e.get().checked_add(1).unwrap();
+                let kbuckets_peer = self.kbuckets_peers.get_mut(&peer_id).unwrap();
+                for address in addresses {
+                    if kbuckets_peer.addresses.len() >= max_addresses_per_peer {
+                        break;
+                    }
+                    kbuckets_peer.addresses.insert_discovered(address);
+                }
+            }
+            hashbrown::hash_map::Entry::Vacant(e) => {
+                e.insert(NonZeroUsize::new(1).unwrap());
+                let kbuckets_peer = self.kbuckets_peers_add_reference(peer_id.clone());
+                for address in addresses {
+                    if kbuckets_peer.addresses.len() >= max_addresses_per_peer {
+                        break;
+                    }
+                    kbuckets_peer.addresses.insert_discovered(address);
+                }
+                self.inner.set_peer_desired(&peer_id, true);
+            }
+        }
+
+        // List of addresses must never be empty.
+        debug_assert!(!self.kbuckets_peers[&peer_id].addresses.is_empty());
+        true
+    }
+
+    /// Cancels a previous call to [`ChainNetwork::add_dial_request`].
+    ///
+    /// The connection to the peer, if any, isn't closed.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no dial request for the given peer.
+    ///
+    pub fn remove_dial_request(&mut self, peer_id: &PeerId) {
+        let num_requests = self.dial_requests.get_mut(peer_id).unwrap();
+        if let Some(new_value) = NonZeroUsize::new(num_requests.get() - 1) {
+            *num_requests = new_value;
+            return;
+        }
+
+        self.dial_requests.remove(peer_id).unwrap();
+        self.kbuckets_peers_remove_reference(peer_id);
+        self.inner.set_peer_desired(peer_id, false);
+    }
+
+    /// Increases the number of references to the given peer in [`ChainNetwork::kbuckets_peers`],
+    /// inserting a new entry if necessary, and returns this entry.
+    ///
+    /// If a new entry is inserted, its list of addresses is filled with the addresses of the
+    /// existing connections to this peer and might be empty. It is the responsibility of the
+    /// caller to insert an address in that situation.
+    fn kbuckets_peers_add_reference(&mut self, peer_id: PeerId) -> &mut KBucketsPeer {
+        match self.kbuckets_peers.entry(peer_id) {
+            hashbrown::hash_map::Entry::Occupied(e) => {
+                let e = e.into_mut();
+                e.num_references = e.num_references.checked_add(1).unwrap();
+                e
+            }
+            hashbrown::hash_map::Entry::Vacant(e) => {
+                // The peer was not in the k-buckets, but it is possible that
+                // we already have existing connections to it.
+                let mut addresses =
+                    addresses::Addresses::with_capacity(self.max_addresses_per_peer.get());
+
+                for connection_id in self.inner.established_peer_connections(e.key()) {
+                    let state = self.inner.connection_state(connection_id);
+                    debug_assert!(state.established);
+                    // Because we mark addresses as disconnected when the
+                    // shutdown process starts, we ignore shutting down
+                    // connections.
+                    if state.shutting_down {
+                        continue;
+                    }
+                    if state.outbound {
+                        addresses.insert_discovered(self.inner[connection_id].clone());
+                        addresses.set_connected(&self.inner[connection_id]);
+                    }
+                }
+
+                for connection_id in self.inner.handshaking_peer_connections(e.key()) {
+                    let state = self.inner.connection_state(connection_id);
+                    debug_assert!(!state.established);
+                    debug_assert!(state.outbound);
+                    // Because we mark addresses as disconnected when the
+                    // shutdown process starts, we ignore shutting down
+                    // connections.
+                    if state.shutting_down {
+                        continue;
+                    }
+                    addresses.insert_discovered(self.inner[connection_id].clone());
+                    addresses.set_pending(&self.inner[connection_id]);
+                }
+
+                // TODO: O(n)
+                for (_, (p, addr, _)) in &self.pending_ids {
+                    if p == e.key() {
+                        addresses.insert_discovered(addr.clone());
+                        addresses.set_pending(addr);
+                    }
+                }
+
+                e.insert(KBucketsPeer {
+                    num_references: NonZeroUsize::new(1).unwrap(),
+                    addresses,
+                })
+            }
+        }
+    }
+
+    /// Decreases the number of references to the given peer in [`ChainNetwork::kbuckets_peers`],
+    /// and removes the entry if it reaches zero.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no entry for this peer.
+    ///
+    fn kbuckets_peers_remove_reference(&mut self, peer_id: &PeerId) {
+        let kbuckets_peer = self.kbuckets_peers.get_mut(peer_id).unwrap();
+        if let Some(num_references) = NonZeroUsize::new(kbuckets_peer.num_references.get() - 1) {
+            kbuckets_peer.num_references = num_references;
+        } else {
+            self.kbuckets_peers.remove(peer_id).unwrap();
+        }
+    }
+}
+
+impl<TNow> Chain<TNow> {
+    /// Returns the number of entries in [`Chain::in_peers`] that aren't reserved peers.
+    fn num_in_slots_used(&self) -> usize {
+        self.in_peers
+            .iter()
+            .filter(|peer_id| !self.reserved_peers.contains(*peer_id))
+            .count()
+    }
+
+    /// Returns the number of entries in [`Chain::out_peers`] that aren't reserved peers.
+    fn num_out_slots_used(&self) -> usize {
+        self.out_peers
+            .iter()
+            .filter(|peer_id| !self.reserved_peers.contains(*peer_id))
+            .count()
+    }
 }
 
 /// User must start connecting to the given multiaddress.
@@ -1242,6 +1582,17 @@ pub enum Event {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a GrandPa vote message from the network.
+    ///
+    /// > **Note**: The signature of the vote hasn't been verified.
+    GrandpaVoteMessage {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the vote message relates to.
+        chain_index: usize,
+        message: EncodedGrandpaVoteMessage,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -1276,6 +1627,95 @@ pub enum Event {
         /// Identifier of the request. Necessary to send back the answer.
         request_id: InRequestId,
     },
+    /// A remote has sent a request for the proof of some storage values.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_storage_and_call_proof_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_storage_proof`].
+    StorageProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// List of storage keys to prove.
+        keys: Vec<Vec<u8>>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a request for the proof of the storage accesses of a runtime call.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_storage_and_call_proof_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_call_proof`].
+    CallProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Hash of the block on top of which to perform the call.
+        block_hash: [u8; 32],
+        /// Name of the runtime function to call.
+        method: String,
+        /// Input to pass to the call.
+        parameter: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+
+    /// A remote has sent a Kademlia request for the nodes closest to a key.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_kademlia_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_kademlia_find_node`].
+    KademliaFindNodeRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Key whose closest nodes are requested.
+        key: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a Kademlia request for the record stored under a key.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_kademlia_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_kademlia_get_value`].
+    KademliaGetValueRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Key of the requested record.
+        key: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
+    /// A remote has sent a Kademlia request to store a record.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_kademlia_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_kademlia_put_value`].
+    KademliaPutValueRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_index: usize,
+        /// Key of the record.
+        key: Vec<u8>,
+        /// Value of the record.
+        value: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        request_id: InRequestId,
+    },
 
     RequestInCancel {
         request_id: InRequestId,
@@ -1320,4 +1760,139 @@ pub enum ProtocolError {
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {_0}")]
     BadBlocksRequest(protocol::DecodeBlockRequestError),
+    /// Error while decoding a received storage proof or call proof request.
+    #[display(fmt = "Error while decoding a received storage or call proof request: {_0}")]
+    BadStorageOrCallProofRequest(protocol::DecodeStorageOrCallProofRequestError),
+    /// Error while decoding a received Kademlia request.
+    #[display(fmt = "Error while decoding a received Kademlia request: {_0}")]
+    BadKademliaRequest(protocol::DecodeKademliaRequestError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainConfig, ChainNetwork, Config, SlotTy};
+    use crate::libp2p::{
+        connection,
+        multiaddr::Multiaddr,
+        peer_id::{PeerId, PublicKey},
+    };
+    use crate::network::protocol;
+    use core::{num::NonZeroUsize, time::Duration};
+
+    fn new_network(out_slots: u32, reserved_only: bool) -> ChainNetwork<Duration> {
+        ChainNetwork::new(Config {
+            now: Duration::new(0, 0),
+            connections_capacity: 0,
+            peers_capacity: 0,
+            randomness_seed: [0; 32],
+            chains: vec![ChainConfig {
+                fork_id: None,
+                block_number_bytes: 4,
+                grandpa_protocol_config: None,
+                allow_inbound_block_requests: false,
+                allow_inbound_storage_and_call_proof_requests: false,
+                allow_inbound_kademlia_requests: false,
+                in_slots: 0,
+                out_slots,
+                reserved_only,
+                best_hash: [0; 32],
+                best_number: 0,
+                genesis_hash: [0; 32],
+                role: protocol::Role::Light,
+            }],
+            // The signature of the Noise key is never verified locally.
+            noise_key: connection::UnsignedNoiseKey::random().sign([0; 32], [0; 64]),
+            handshake_timeout: Duration::from_secs(5),
+            max_addresses_per_peer: NonZeroUsize::new(2).unwrap(),
+        })
+    }
+
+    fn peer(n: u8) -> (PeerId, Multiaddr) {
+        (
+            PeerId::from_public_key(&PublicKey::Ed25519([n; 32])),
+            format!("/ip4/127.0.0.1/tcp/{}", 1000 + u16::from(n))
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn reserved_peer_ignores_slots_limit() {
+        let mut network = new_network(0, false);
+        let (peer_id, address) = peer(1);
+
+        assert!(network.add_reserved_peer(0, peer_id.clone(), address.clone()));
+        assert!(network.is_reserved_peer(0, &peer_id));
+        assert_eq!(network.reserved_peers(0).collect::<Vec<_>>(), [&peer_id]);
+
+        // Adding the same peer a second time doesn't assign a second slot.
+        assert!(!network.add_reserved_peer(0, peer_id.clone(), address.clone()));
+
+        let start_connect = network.next_start_connect(|| Duration::new(0, 0)).unwrap();
+        assert_eq!(start_connect.expected_peer_id, peer_id);
+        assert_eq!(start_connect.multiaddr, address);
+
+        assert_eq!(
+            network.remove_reserved_peer(0, &peer_id),
+            Some(SlotTy::Outbound)
+        );
+        assert!(!network.is_reserved_peer(0, &peer_id));
+        assert_eq!(network.remove_reserved_peer(0, &peer_id), None);
+    }
+
+    #[test]
+    fn reserved_peer_returned_first_after_unassign() {
+        let mut network = new_network(1, false);
+        let (reserved_peer_id, reserved_address) = peer(1);
+        let (other_peer_id, other_address) = peer(2);
+
+        network.discover(
+            &Duration::new(0, 0),
+            0,
+            other_peer_id.clone(),
+            [other_address],
+        );
+        assert!(network.add_reserved_peer(0, reserved_peer_id.clone(), reserved_address));
+
+        // The reserved peer doesn't use the only outbound slot.
+        assert_eq!(
+            network.slots_to_assign(0).collect::<Vec<_>>(),
+            [&other_peer_id]
+        );
+        network.assign_out_slot(0, other_peer_id.clone());
+        assert_eq!(network.slots_to_assign(0).count(), 0);
+
+        assert_eq!(
+            network.unassign_slot(0, &reserved_peer_id),
+            Some(SlotTy::Outbound)
+        );
+        assert_eq!(
+            network.slots_to_assign(0).collect::<Vec<_>>(),
+            [&reserved_peer_id]
+        );
+    }
+
+    #[test]
+    fn reserved_only_ignores_other_peers() {
+        let mut network = new_network(4, true);
+        let (reserved_peer_id, reserved_address) = peer(1);
+        let (other_peer_id, other_address) = peer(2);
+
+        network.discover(
+            &Duration::new(0, 0),
+            0,
+            other_peer_id.clone(),
+            [other_address],
+        );
+        assert_eq!(network.slots_to_assign(0).count(), 0);
+
+        // Assigning a slot to a non-reserved peer has no effect.
+        network.assign_out_slot(0, other_peer_id.clone());
+        assert_eq!(network.unassign_slot(0, &other_peer_id), None);
+
+        assert!(network.add_reserved_peer(0, reserved_peer_id.clone(), reserved_address));
+        let start_connect = network.next_start_connect(|| Duration::new(0, 0)).unwrap();
+        assert_eq!(start_connect.expected_peer_id, reserved_peer_id);
+        assert!(network.next_start_connect(|| Duration::new(0, 0)).is_none());
+    }
 }
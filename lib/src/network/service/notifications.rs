@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::finality::grandpa::round_state;
 use crate::libp2p::{
     peers::{self, QueueNotificationError},
     PeerId,
@@ -180,7 +181,9 @@ where
                     // Update the k-buckets to mark the peer as connected.
                     // Note that this is done after having made sure that the handshake
                     // was correct.
-                    // TODO: should we not insert the entry in the k-buckets as well? seems important for incoming connections
+                    // Peers that aren't in the k-buckets, such as peers that have connected to
+                    // the local node, are inserted by `discover` once one of their addresses is
+                    // known, for example through `start_identify_request`.
                     if let Some(mut entry) = self.chains[chain_index]
                         .kbuckets
                         .entry(&peer_id)
@@ -390,12 +393,15 @@ where
             }
 
             // If the peer doesn't already have an outbound slot, check whether we can
-            // allocate an inbound slot for it.
+            // allocate an inbound slot for it. Reserved peers are always allowed a slot, while
+            // other peers are refused if only reserved peers are accepted.
             let has_out_slot = self.chains[chain_index].out_peers.contains(&peer_id);
             if !has_out_slot
-                && self.chains[chain_index].in_peers.len()
-                    >= usize::try_from(self.chains[chain_index].chain_config.in_slots)
-                        .unwrap_or(usize::max_value())
+                && !self.chains[chain_index].reserved_peers.contains(&peer_id)
+                && (self.chains[chain_index].chain_config.reserved_only
+                    || self.chains[chain_index].num_in_slots_used()
+                        >= usize::try_from(self.chains[chain_index].chain_config.in_slots)
+                            .unwrap_or(usize::max_value()))
             {
                 // All in slots are occupied. Refuse the substream.
                 self.inner.in_notification_refuse(substream_id);
@@ -603,6 +609,14 @@ where
                         block_number_bytes,
                     },
                 }),
+                protocol::GrandpaNotificationRef::Vote(_) => Some(Event::GrandpaVoteMessage {
+                    chain_index,
+                    peer_id,
+                    message: EncodedGrandpaVoteMessage {
+                        message: notification,
+                        block_number_bytes,
+                    },
+                }),
                 protocol::GrandpaNotificationRef::Neighbor(n) => {
                     Some(Event::GrandpaNeighborPacket {
                         chain_index,
@@ -868,3 +882,57 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid GrandPa vote message.
+#[derive(Clone)]
+pub struct EncodedGrandpaVoteMessage {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaVoteMessage {
+    /// Returns the decoded version of the vote message.
+    pub fn decode(&self) -> protocol::VoteMessageRef {
+        match protocol::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(protocol::GrandpaNotificationRef::Vote(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the vote in a format that can be passed to
+    /// [`round_state::RoundState::inject_vote`].
+    ///
+    /// Returns `None` if the message is a primary proposal, which isn't a vote.
+    pub fn as_round_state_vote(&self) -> Option<round_state::Vote> {
+        let decoded = self.decode();
+        let (ty, target_hash, target_number) = match decoded.message {
+            protocol::MessageRef::Prevote(vote) => (
+                round_state::VoteTy::Prevote,
+                vote.target_hash,
+                vote.target_number,
+            ),
+            protocol::MessageRef::Precommit(vote) => (
+                round_state::VoteTy::Precommit,
+                vote.target_hash,
+                vote.target_number,
+            ),
+            protocol::MessageRef::PrimaryPropose(_) => return None,
+        };
+
+        Some(round_state::Vote {
+            set_id: decoded.set_id,
+            round_number: decoded.round_number,
+            ty,
+            target_hash,
+            target_number,
+            signature: decoded.signature,
+            authority_public_key: decoded.authority_public_key,
+        })
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaVoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
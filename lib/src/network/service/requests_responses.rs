@@ -25,12 +25,14 @@ use crate::network::{kademlia, protocol};
 
 use super::*;
 
-use alloc::{format, vec::Vec};
+use alloc::{
+    format,
+    vec::{self, Vec},
+};
 use core::{
     fmt,
     hash::Hash,
     iter,
-    num::NonZeroUsize,
     ops::{Add, Sub},
     time::Duration,
 };
@@ -86,8 +88,7 @@ pub(super) fn protocols<'a>(
                 max_size: 1024 * 512,
             },
             max_response_size: 10 * 1024 * 1024,
-            // TODO: make this configurable
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_storage_and_call_proof_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: match &chain.fork_id {
@@ -96,10 +97,11 @@ pub(super) fn protocols<'a>(
                 }
                 None => format!("/{}/kad", hex::encode(chain.genesis_hash)),
             },
-            inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
+            // The maximum request size must be large enough to accept put value requests
+            // containing authority discovery records.
+            inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 8 * 1024 },
             max_response_size: 1024 * 1024,
-            // TODO: `false` here means we don't insert ourselves in the DHT, which is the polite thing to do for as long as Kad isn't implemented
-            inbound_allowed: false,
+            inbound_allowed: chain.allow_inbound_kademlia_requests,
         }))
         .chain(iter::once(peers::ConfigRequestResponse {
             name: match &chain.fork_id {
@@ -143,6 +145,23 @@ where
         response: Result<Vec<u8>, peers::RequestError>,
     ) -> Event {
         match self.out_requests_types.remove(&request_id).unwrap() {
+            (OutRequestTy::Identify, _) => {
+                let response =
+                    response
+                        .map_err(IdentifyRequestError::Request)
+                        .and_then(|payload| {
+                            if let Err(err) = protocol::decode_identify_response(&payload) {
+                                Err(IdentifyRequestError::Decode(err))
+                            } else {
+                                Ok(EncodedIdentifyResponse(payload))
+                            }
+                        });
+
+                Event::RequestResult {
+                    request_id,
+                    response: RequestResult::Identify(response),
+                }
+            }
             (OutRequestTy::Blocks { checked }, chain_index) => {
                 let mut response =
                     response
@@ -279,6 +298,32 @@ where
                     result,
                 }
             }
+            (OutRequestTy::KademliaGetValue { key }, _) => {
+                let response = response
+                    .map_err(KademliaGetValueError::RequestFailed)
+                    .and_then(|payload| {
+                        protocol::decode_get_value_response(&key, &payload)
+                            .map_err(KademliaGetValueError::DecodeError)
+                    });
+
+                Event::RequestResult {
+                    request_id,
+                    response: RequestResult::KademliaGetValue(response),
+                }
+            }
+            (OutRequestTy::KademliaPutValue, _) => {
+                let response = response
+                    .map_err(KademliaPutValueError::RequestFailed)
+                    .and_then(|payload| {
+                        protocol::decode_put_value_response(&payload)
+                            .map_err(KademliaPutValueError::DecodeError)
+                    });
+
+                Event::RequestResult {
+                    request_id,
+                    response: RequestResult::KademliaPutValue(response),
+                }
+            }
         }
     }
 
@@ -310,40 +355,144 @@ where
                     error: ProtocolError::BadIdentifyRequest,
                 }
             }
-        } else if ((protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN)
-            != 0
-        {
-            // Protocols that receive requests are whitelisted, meaning that no other protocol
-            // indices can reach here.
-            unreachable!()
         } else {
             let chain_index =
                 (protocol_index - 1) / requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN;
 
-            match protocol::decode_block_request(
-                self.chains[chain_index].chain_config.block_number_bytes,
-                &request_payload,
-            ) {
-                Ok(config) => {
-                    let _prev_value = self
-                        .in_requests_types
-                        .insert(request_id, InRequestTy::Blocks);
-                    debug_assert!(_prev_value.is_none());
-
-                    Event::BlocksRequestIn {
-                        peer_id,
-                        chain_index,
-                        config,
-                        request_id,
+            match (protocol_index - 1) % requests_responses::REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN {
+                0 => match protocol::decode_block_request(
+                    self.chains[chain_index].chain_config.block_number_bytes,
+                    &request_payload,
+                ) {
+                    Ok(config) => {
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::Blocks);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::BlocksRequestIn {
+                            peer_id,
+                            chain_index,
+                            config,
+                            request_id,
+                        }
                     }
-                }
-                Err(error) => {
-                    self.inner.respond_in_request(request_id, Err(()));
-                    Event::ProtocolError {
-                        peer_id,
-                        error: ProtocolError::BadBlocksRequest(error),
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadBlocksRequest(error),
+                        }
                     }
-                }
+                },
+                1 => match protocol::decode_storage_or_call_proof_request(&request_payload) {
+                    Ok(protocol::StorageOrCallProofRequest::StorageProof { block_hash, keys }) => {
+                        let _prev_value = self.in_requests_types.insert(
+                            request_id,
+                            InRequestTy::StorageOrCallProof(
+                                protocol::StorageOrCallProof::StorageProof,
+                            ),
+                        );
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::StorageProofRequestIn {
+                            peer_id,
+                            chain_index,
+                            block_hash,
+                            keys: keys.into_iter().map(|k| k.to_vec()).collect(),
+                            request_id,
+                        }
+                    }
+                    Ok(protocol::StorageOrCallProofRequest::CallProof {
+                        block_hash,
+                        method,
+                        parameter,
+                    }) => {
+                        let _prev_value = self.in_requests_types.insert(
+                            request_id,
+                            InRequestTy::StorageOrCallProof(
+                                protocol::StorageOrCallProof::CallProof,
+                            ),
+                        );
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::CallProofRequestIn {
+                            peer_id,
+                            chain_index,
+                            block_hash,
+                            method: method.to_owned(),
+                            parameter: parameter.to_vec(),
+                            request_id,
+                        }
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadStorageOrCallProofRequest(error),
+                        }
+                    }
+                },
+                2 => match protocol::decode_kademlia_request(&request_payload) {
+                    Ok(protocol::KademliaRequest::FindNode { key }) => {
+                        let key = key.to_vec();
+                        let _prev_value = self
+                            .in_requests_types
+                            .insert(request_id, InRequestTy::KademliaFindNode);
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::KademliaFindNodeRequestIn {
+                            peer_id,
+                            chain_index,
+                            key,
+                            request_id,
+                        }
+                    }
+                    Ok(protocol::KademliaRequest::GetValue { key }) => {
+                        let key = key.to_vec();
+                        let _prev_value = self.in_requests_types.insert(
+                            request_id,
+                            InRequestTy::KademliaGetValue { key: key.clone() },
+                        );
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::KademliaGetValueRequestIn {
+                            peer_id,
+                            chain_index,
+                            key,
+                            request_id,
+                        }
+                    }
+                    Ok(protocol::KademliaRequest::PutValue { key, value }) => {
+                        let (key, value) = (key.to_vec(), value.to_vec());
+                        let _prev_value = self.in_requests_types.insert(
+                            request_id,
+                            InRequestTy::KademliaPutValue {
+                                key: key.clone(),
+                                value: value.clone(),
+                            },
+                        );
+                        debug_assert!(_prev_value.is_none());
+
+                        Event::KademliaPutValueRequestIn {
+                            peer_id,
+                            chain_index,
+                            key,
+                            value,
+                            request_id,
+                        }
+                    }
+                    Err(error) => {
+                        self.inner.respond_in_request(request_id, Err(()));
+                        Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::BadKademliaRequest(error),
+                        }
+                    }
+                },
+                // Protocols that receive requests are whitelisted, meaning that no other
+                // protocol indices can reach here.
+                _ => unreachable!(),
             }
         }
     }
@@ -619,101 +768,40 @@ where
             return;
         }
 
-        let kbuckets_peer = match kbuckets.entry(&peer_id) {
+        // Peers can already be connected to the chain, for example if they have connected to
+        // the local node before any of their addresses was known.
+        let state = if self.open_chains.contains(&(peer_id.clone(), chain_index)) {
+            kademlia::kbuckets::PeerState::Connected
+        } else {
+            kademlia::kbuckets::PeerState::Disconnected
+        };
+
+        let (is_new_entry, removed_peer_id) = match kbuckets.entry(&peer_id) {
             kademlia::kbuckets::Entry::LocalKey => return, // TODO: return some diagnostic?
             kademlia::kbuckets::Entry::Vacant(entry) => {
-                match entry.insert((), now, kademlia::kbuckets::PeerState::Disconnected) {
+                match entry.insert((), now, state) {
                     Err(kademlia::kbuckets::InsertError::Full) => return, // TODO: return some diagnostic?
-                    Ok((_, removed_entry)) => {
-                        // `removed_entry` is the peer that was removed the k-buckets as the
-                        // result of the new insertion. Purge it from `self.kbuckets_peers`
-                        // if necessary.
-                        if let Some((removed_peer_id, _)) = removed_entry {
-                            match self.kbuckets_peers.entry(removed_peer_id) {
-                                hashbrown::hash_map::Entry::Occupied(e)
-                                    if e.get().num_references.get() == 1 =>
-                                {
-                                    e.remove();
-                                }
-                                hashbrown::hash_map::Entry::Occupied(e) => {
-                                    let num_refs = &mut e.into_mut().num_references;
-                                    *num_refs = NonZeroUsize::new(num_refs.get() - 1).unwrap();
-                                }
-                                hashbrown::hash_map::Entry::Vacant(_) => unreachable!(),
-                            }
-                        }
-
-                        match self.kbuckets_peers.entry(peer_id) {
-                            hashbrown::hash_map::Entry::Occupied(e) => {
-                                let e = e.into_mut();
-                                e.num_references = e.num_references.checked_add(1).unwrap();
-                                e
-                            }
-                            hashbrown::hash_map::Entry::Vacant(e) => {
-                                // The peer was not in the k-buckets, but it is possible that
-                                // we already have existing connections to it.
-                                let mut addresses = addresses::Addresses::with_capacity(
-                                    self.max_addresses_per_peer.get(),
-                                );
-
-                                for connection_id in
-                                    self.inner.established_peer_connections(e.key())
-                                {
-                                    let state = self.inner.connection_state(connection_id);
-                                    debug_assert!(state.established);
-                                    // Because we mark addresses as disconnected when the
-                                    // shutdown process starts, we ignore shutting down
-                                    // connections.
-                                    if state.shutting_down {
-                                        continue;
-                                    }
-                                    if state.outbound {
-                                        addresses
-                                            .insert_discovered(self.inner[connection_id].clone());
-                                        addresses.set_connected(&self.inner[connection_id]);
-                                    }
-                                }
-
-                                for connection_id in
-                                    self.inner.handshaking_peer_connections(e.key())
-                                {
-                                    let state = self.inner.connection_state(connection_id);
-                                    debug_assert!(!state.established);
-                                    debug_assert!(state.outbound);
-                                    // Because we mark addresses as disconnected when the
-                                    // shutdown process starts, we ignore shutting down
-                                    // connections.
-                                    if state.shutting_down {
-                                        continue;
-                                    }
-                                    addresses.insert_discovered(self.inner[connection_id].clone());
-                                    addresses.set_pending(&self.inner[connection_id]);
-                                }
-
-                                // TODO: O(n)
-                                for (_, (p, addr, _)) in &self.pending_ids {
-                                    if p == e.key() {
-                                        addresses.insert_discovered(addr.clone());
-                                        addresses.set_pending(addr);
-                                    }
-                                }
-
-                                e.insert(KBucketsPeer {
-                                    num_references: NonZeroUsize::new(1).unwrap(),
-                                    addresses,
-                                })
-                            }
-                        }
-                    }
+                    Ok((_, removed_entry)) => (true, removed_entry.map(|(peer_id, _)| peer_id)),
                 }
             }
-            kademlia::kbuckets::Entry::Occupied(_) => {
-                self.kbuckets_peers.get_mut(&peer_id).unwrap()
-            }
+            kademlia::kbuckets::Entry::Occupied(_) => (false, None),
+        };
+
+        // `removed_peer_id` is the peer that was removed from the k-buckets as the result of the new
+        // insertion. Purge it from `self.kbuckets_peers` if necessary.
+        if let Some(removed_peer_id) = removed_peer_id {
+            self.kbuckets_peers_remove_reference(&removed_peer_id);
+        }
+
+        let max_addresses_per_peer = self.max_addresses_per_peer.get();
+        let kbuckets_peer = if is_new_entry {
+            self.kbuckets_peers_add_reference(peer_id)
+        } else {
+            self.kbuckets_peers.get_mut(&peer_id).unwrap()
         };
 
         for to_insert in discovered_addrs {
-            if kbuckets_peer.addresses.len() >= self.max_addresses_per_peer.get() {
+            if kbuckets_peer.addresses.len() >= max_addresses_per_peer {
                 continue;
             }
 
@@ -843,6 +931,112 @@ where
         id
     }
 
+    /// Sends a Kademlia "get value" request to a single peer, asking for the record stored under
+    /// the given key and for the nodes closest to that key.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_kademlia_get_value(
+        &mut self,
+        target: &PeerId,
+        now: TNow,
+        chain_index: usize,
+        key: &[u8],
+    ) -> Result<OutRequestId, StartRequestError> {
+        let request_data = protocol::build_get_value_request(key);
+        // The timeout needs to be long enough to potentially download the maximum
+        // response size of 1 MiB. Assuming a 128 kiB/sec connection, that's 8 seconds.
+        let timeout = now + Duration::from_secs(8);
+
+        let id = self.inner.start_request(
+            target,
+            self.protocol_index(chain_index, 2),
+            request_data,
+            timeout,
+        )?;
+
+        let _prev_value = self.out_requests_types.insert(
+            id,
+            (
+                OutRequestTy::KademliaGetValue { key: key.to_vec() },
+                chain_index,
+            ),
+        );
+        debug_assert!(_prev_value.is_none());
+
+        Ok(id)
+    }
+
+    /// Sends a Kademlia "put value" request to a single peer, asking it to store the given
+    /// record.
+    ///
+    /// Returns an error if the record is too large to fit in a request.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_kademlia_put_value(
+        &mut self,
+        target: &PeerId,
+        now: TNow,
+        chain_index: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<OutRequestId, StartRequestError> {
+        let request_data = protocol::build_put_value_request(key, value);
+        let timeout = now + Duration::from_secs(8);
+
+        let id = self.inner.start_request(
+            target,
+            self.protocol_index(chain_index, 2),
+            request_data,
+            timeout,
+        )?;
+
+        let _prev_value = self
+            .out_requests_types
+            .insert(id, (OutRequestTy::KademliaPutValue, chain_index));
+        debug_assert!(_prev_value.is_none());
+
+        Ok(id)
+    }
+
+    /// Sends an identify request to the given peer, asking it for general-purpose information
+    /// about itself, including the addresses it is listening on.
+    ///
+    /// This is notably useful in order to insert in the k-buckets the peers that have connected
+    /// to the local node, as their addresses are otherwise unknown. See
+    /// [`ChainNetwork::discover`].
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no active connection with that peer.
+    ///
+    pub fn start_identify_request(&mut self, target: &PeerId, now: TNow) -> OutRequestId {
+        let id =
+            match self
+                .inner
+                .start_request(target, 0, Vec::new(), now + Duration::from_secs(10))
+            {
+                Ok(id) => id,
+                Err(peers::StartRequestError::RequestTooLarge) => {
+                    // Identify requests are always empty.
+                    unreachable!()
+                }
+            };
+
+        // The identify protocol isn't specific to any chain, and the chain index stored here is
+        // never read.
+        let _prev_value = self
+            .out_requests_types
+            .insert(id, (OutRequestTy::Identify, 0));
+        debug_assert!(_prev_value.is_none());
+
+        id
+    }
+
     /// Returns `true` if if it possible to send requests (i.e. through
     /// [`ChainNetwork::start_grandpa_warp_sync_request`],
     /// [`ChainNetwork::start_blocks_request`], etc.) to the given peer.
@@ -922,6 +1116,124 @@ where
 
         self.inner.respond_in_request(request_id, response);
     }
+
+    /// Queue the response to a storage proof request to send back.
+    ///
+    /// `proof` must be a SCALE-encoded Merkle proof containing the requested keys. Pass `None`
+    /// if the storage of the requested block isn't available locally.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_storage_proof(&mut self, request_id: InRequestId, proof: Option<&[u8]>) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::StorageOrCallProof(protocol::StorageOrCallProof::StorageProof)) => {}
+            _ => panic!(),
+        };
+
+        let response = protocol::build_storage_or_call_proof_response(
+            protocol::StorageOrCallProof::StorageProof,
+            proof,
+        );
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
+
+    /// Queue the response to a call proof request to send back.
+    ///
+    /// `proof` must be a SCALE-encoded Merkle proof containing all the storage items accessed
+    /// by the call. Pass `None` if the call can't be performed, for example because the storage
+    /// of the requested block isn't available locally or because the call has failed.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_call_proof(&mut self, request_id: InRequestId, proof: Option<&[u8]>) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::StorageOrCallProof(protocol::StorageOrCallProof::CallProof)) => {}
+            _ => panic!(),
+        };
+
+        let response = protocol::build_storage_or_call_proof_response(
+            protocol::StorageOrCallProof::CallProof,
+            proof,
+        );
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
+
+    /// Queue the response to a Kademlia find node request to send back.
+    ///
+    /// `closer_peers` should be the nodes of the local k-buckets closest to the requested key,
+    /// and their addresses.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_kademlia_find_node(
+        &mut self,
+        request_id: InRequestId,
+        closer_peers: &[(PeerId, Vec<multiaddr::Multiaddr>)],
+    ) {
+        match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::KademliaFindNode) => {}
+            _ => panic!(),
+        };
+
+        let response = protocol::build_find_node_response(closer_peers);
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
+
+    /// Queue the response to a Kademlia get value request to send back.
+    ///
+    /// `value` should be the value of the record stored locally under the requested key, if
+    /// any. `closer_peers` should be the nodes of the local k-buckets closest to the requested
+    /// key, and their addresses.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_kademlia_get_value(
+        &mut self,
+        request_id: InRequestId,
+        value: Option<&[u8]>,
+        closer_peers: &[(PeerId, Vec<multiaddr::Multiaddr>)],
+    ) {
+        let key = match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::KademliaGetValue { key }) => key,
+            _ => panic!(),
+        };
+
+        let response = protocol::build_get_value_response(&key, value, closer_peers);
+        self.inner.respond_in_request(request_id, Ok(response));
+    }
+
+    /// Queue the response to a Kademlia put value request to send back.
+    ///
+    /// Pass `false` for `accepted` if the record hasn't been stored, for example because it is
+    /// invalid or because the local storage is full.
+    ///
+    /// Has no effect if the connection that sends the request no longer exists.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn respond_kademlia_put_value(&mut self, request_id: InRequestId, accepted: bool) {
+        let (key, value) = match self.in_requests_types.remove(&request_id) {
+            Some(InRequestTy::KademliaPutValue { key, value }) => (key, value),
+            _ => panic!(),
+        };
+
+        // The response to a put value request is identical to the request.
+        let response = if accepted {
+            Ok(protocol::build_put_value_request(&key, &value))
+        } else {
+            Err(())
+        };
+
+        self.inner.respond_in_request(request_id, response);
+    }
 }
 
 /// Response to an outgoing request.
@@ -929,6 +1241,7 @@ where
 /// See [`Event::RequestResult`̀].
 #[derive(Debug)]
 pub enum RequestResult {
+    Identify(Result<EncodedIdentifyResponse, IdentifyRequestError>),
     Blocks(Result<Vec<protocol::BlockData>, BlocksRequestError>),
     GrandpaWarpSync(Result<EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError>),
     State(Result<EncodedStateResponse, StateRequestError>),
@@ -937,6 +1250,8 @@ pub enum RequestResult {
     KademliaFindNode(
         Result<Vec<(peer_id::PeerId, Vec<multiaddr::Multiaddr>)>, KademliaFindNodeError>,
     ),
+    KademliaGetValue(Result<protocol::GetValueResponse, KademliaGetValueError>),
+    KademliaPutValue(Result<(), KademliaPutValueError>),
 }
 
 /// Undecoded but valid block announce.
@@ -1006,6 +1321,29 @@ impl fmt::Debug for EncodedGrandpaWarpSyncResponse {
     }
 }
 
+/// Undecoded but valid identify response.
+#[derive(Clone)]
+pub struct EncodedIdentifyResponse(Vec<u8>);
+
+impl EncodedIdentifyResponse {
+    /// Returns the decoded version of the response.
+    pub fn decode(
+        &self,
+    ) -> protocol::IdentifyResponse<'_, vec::IntoIter<multiaddr::Multiaddr>, vec::IntoIter<&'_ str>>
+    {
+        match protocol::decode_identify_response(&self.0) {
+            Ok(r) => r,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedIdentifyResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
 /// Undecoded but valid state response.
 // TODO: merge with EncodedMerkleProof?
 #[derive(Clone)]
@@ -1048,6 +1386,28 @@ pub enum KademliaFindNodeError {
     DecodeError(protocol::DecodeFindNodeResponseError),
 }
 
+/// Error during [`ChainNetwork::start_kademlia_get_value`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaGetValueError {
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    RequestFailed(peers::RequestError),
+    /// Failed to decode the response.
+    #[display(fmt = "Response decoding error: {_0}")]
+    DecodeError(protocol::DecodeGetValueResponseError),
+}
+
+/// Error during [`ChainNetwork::start_kademlia_put_value`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaPutValueError {
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    RequestFailed(peers::RequestError),
+    /// Failed to decode the response.
+    #[display(fmt = "Response decoding error: {_0}")]
+    DecodeError(protocol::DecodePutValueResponseError),
+}
+
 /// Error returned by [`ChainNetwork::start_blocks_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum BlocksRequestError {
@@ -1136,6 +1496,15 @@ pub enum GrandpaWarpSyncRequestError {
     Decode(protocol::DecodeGrandpaWarpSyncResponseError),
 }
 
+/// Error returned by [`ChainNetwork::start_identify_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum IdentifyRequestError {
+    #[display(fmt = "{_0}")]
+    Request(peers::RequestError),
+    #[display(fmt = "Response decoding error: {_0}")]
+    Decode(protocol::DecodeIdentifyResponseError),
+}
+
 /// Error returned by [`ChainNetwork::start_state_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum StateRequestError {
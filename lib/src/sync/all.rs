@@ -32,7 +32,7 @@
 
 use crate::{
     chain::{blocks_tree, chain_information},
-    executor::{host, storage_diff, vm::ExecHint},
+    executor::{host, runtime_host, storage_diff, vm::ExecHint},
     header,
     sync::{all_forks, optimistic, warp_sync},
     verify,
@@ -2017,6 +2017,14 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             }
         }
     }
+
+    /// Returns the changes made by this block and its non-finalized ancestors to the storage of
+    /// the finalized block.
+    pub fn finalized_storage_diff(&self) -> &'a storage_diff::TrieDiff<TrieEntryVersion> {
+        match &self.inner {
+            BlockStorageInner::Optimistic(inner) => inner.finalized_storage_diff(),
+        }
+    }
 }
 
 /// Outcome of calling [`AllSync::process_one`].
@@ -2453,14 +2461,18 @@ impl<TRq, TSrc, TBl> HeaderBodyVerify<TRq, TSrc, TBl> {
     }
 
     /// Start the verification process.
+    ///
+    /// If `host_functions_profiler` is `Some`, the host functions called by the runtime are
+    /// recorded and returned in [`BlockVerification::Success::host_functions_profiler`].
     pub fn start(
         self,
         now_from_unix_epoch: Duration,
         user_data: TBl,
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
     ) -> BlockVerification<TRq, TSrc, TBl> {
         match self.inner {
             HeaderBodyVerifyInner::Optimistic(verify) => BlockVerification::from_inner(
-                verify.start(now_from_unix_epoch),
+                verify.start(now_from_unix_epoch, host_functions_profiler),
                 self.shared,
                 user_data,
             ),
@@ -2476,6 +2488,9 @@ pub enum BlockVerification<TRq, TSrc, TBl> {
         is_new_best: bool,
         /// State machine yielded back. Use to continue the processing.
         sync: AllSync<TRq, TSrc, TBl>,
+        /// Value that was passed to [`HeaderBodyVerify::start`], containing the host functions
+        /// called by the runtime during the verification.
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
     },
 
     /// Block verification failed.
@@ -2528,7 +2543,11 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
         user_data: TBl,
     ) -> Self {
         match inner {
-            optimistic::BlockVerification::NewBest { sync, .. } => {
+            optimistic::BlockVerification::NewBest {
+                sync,
+                host_functions_profiler,
+                ..
+            } => {
                 // TODO: transition to all_forks
                 BlockVerification::Success {
                     is_new_best: true,
@@ -2536,6 +2555,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                         inner: AllSyncInner::Optimistic { inner: sync },
                         shared,
                     },
+                    host_functions_profiler,
                 }
             }
             optimistic::BlockVerification::Reset { sync, reason, .. } => BlockVerification::Error {
@@ -47,7 +47,7 @@
 
 use crate::{
     chain::{blocks_tree, chain_information},
-    executor::{host, storage_diff},
+    executor::{host, runtime_host, storage_diff},
     header,
     trie::calculate_root,
 };
@@ -832,6 +832,12 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             .best_to_finalized_storage_diff
             .storage_prefix_keys_ordered(prefix, in_finalized_ordered)
     }
+
+    /// Returns the changes made by this block and its non-finalized ancestors to the storage of
+    /// the finalized block.
+    pub fn finalized_storage_diff(&self) -> &'a storage_diff::TrieDiff<TrieEntryVersion> {
+        &self.inner.inner.best_to_finalized_storage_diff
+    }
 }
 
 /// Start the processing of a block verification.
@@ -873,7 +879,15 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
     ///
     /// Must be passed the current UNIX time in order to verify that the block doesn't pretend to
     /// come from the future.
-    pub fn start(mut self, now_from_unix_epoch: Duration) -> BlockVerification<TRq, TSrc, TBl> {
+    ///
+    /// If `host_functions_profiler` is `Some` and the body of the block is verified, the host
+    /// functions called by the runtime are recorded and returned in
+    /// [`BlockVerification::NewBest::host_functions_profiler`].
+    pub fn start(
+        mut self,
+        now_from_unix_epoch: Duration,
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
+    ) -> BlockVerification<TRq, TSrc, TBl> {
         // Extract the block to process. We are guaranteed that a block is available because a
         // `Verify` is built only when that is the case.
         // Be aware that `source_id` might refer to an obsolete source.
@@ -903,6 +917,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                     block_body: block.scale_encoded_extrinsics,
                     block_user_data: Some(block.user_data),
                     source_id,
+                    host_functions_profiler,
                 },
             )
         } else {
@@ -970,6 +985,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                     },
                     new_best_hash,
                     new_best_number,
+                    host_functions_profiler: None,
                 }
             }
         }
@@ -1004,6 +1020,10 @@ pub enum BlockVerification<TRq, TSrc, TBl> {
 
         new_best_number: u64,
         new_best_hash: [u8; 32],
+
+        /// Host functions called by the runtime while verifying the block, if a profiler was
+        /// passed to [`BlockVerify::start`] and the body of the block has been verified.
+        host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
     },
 
     /// Loading a storage value of the finalized block is required in order to continue.
@@ -1035,6 +1055,8 @@ struct BlockVerificationShared<TRq, TSrc, TBl> {
     block_user_data: Option<TBl>,
     /// Source the block has been downloaded from. Might be obsolete.
     source_id: SourceId,
+    /// Profiler to pass to [`blocks_tree::BodyVerifyRuntimeRequired::resume`].
+    host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
 }
 
 impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
@@ -1062,6 +1084,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                         parent_runtime,
                         shared.block_body.iter(),
                         shared.inner.main_trie_root_calculation_cache.take(),
+                        shared.host_functions_profiler.take(),
                     ));
                 }
 
@@ -1072,6 +1095,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     main_trie_root_calculation_cache,
                     parent_runtime,
                     new_runtime,
+                    host_functions_profiler,
                     insert,
                 }) => {
                     // Successfully verified block!
@@ -1134,6 +1158,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                         },
                         new_best_hash,
                         new_best_number,
+                        host_functions_profiler,
                     };
                 }
 
@@ -0,0 +1,604 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate telemetry.
+//!
+//! Substrate/Polkadot nodes can report information about themselves, such as their name or their
+//! best block, to so-called telemetry servers. The telemetry servers then aggregate this
+//! information and display it on a web page, such as <https://telemetry.polkadot.io>.
+//!
+//! Nodes connect to a telemetry server through a WebSocket connection, then send JSON messages
+//! over this connection, one message per WebSocket frame. The first message sent after the
+//! connection has been established must always be a [`TelemetryMessage::SystemConnected`].
+//!
+//! This module doesn't perform any networking. It provides ways to parse the address of a
+//! telemetry server (see [`parse_endpoint`]) and to build the messages to send to it (see
+//! [`TelemetryMessage::encode`]).
+//!
+//! # Verbosity
+//!
+//! Each telemetry server is associated with a verbosity level, typically found in the chain
+//! specification. Each message also has a verbosity level (see [`TelemetryMessage::verbosity`]).
+//! A message must only be sent to a telemetry server if the verbosity of the message is inferior
+//! or equal to the verbosity of the server. See [`TelemetryEndpoint::accepts`].
+//!
+//! # Example
+//!
+//! ```
+//! use smoldot::telemetry;
+//!
+//! let endpoint = telemetry::parse_endpoint("wss://telemetry.polkadot.io/submit/", 0).unwrap();
+//! assert_eq!(endpoint.host, "telemetry.polkadot.io");
+//! assert_eq!(endpoint.port, 443);
+//! assert!(endpoint.secure);
+//!
+//! let message = telemetry::TelemetryMessage::NotifyFinalized(telemetry::NotifyFinalized {
+//!     block_hash: &[0; 32],
+//!     block_number: 12,
+//! });
+//! assert!(endpoint.accepts(&message));
+//! let _json = message.encode(1);
+//! ```
+
+use alloc::{
+    borrow::ToOwned as _,
+    format,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+
+/// Verbosity of the messages containing general information about the node.
+pub const VERBOSITY_SUBSTRATE_INFO: u8 = 0;
+/// Verbosity of the messages containing information about the consensus.
+pub const VERBOSITY_CONSENSUS_INFO: u8 = 1;
+/// Verbosity of the messages containing debugging information about the node.
+pub const VERBOSITY_SUBSTRATE_DEBUG: u8 = 9;
+
+/// Address of a telemetry server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryEndpoint {
+    /// Host name or IP address of the server. IPv6 addresses aren't surrounded with brackets.
+    pub host: String,
+    /// TCP port of the server.
+    pub port: u16,
+    /// Path to pass as part of the WebSocket handshake. Always starts with `/`.
+    pub path: String,
+    /// If `true`, the WebSocket connection must be encrypted with TLS.
+    pub secure: bool,
+    /// Maximum verbosity of the messages to send to this server.
+    pub verbosity: u8,
+}
+
+impl TelemetryEndpoint {
+    /// Returns the value to pass as the `Host` HTTP header during the WebSocket handshake.
+    pub fn host_header(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Returns `true` if the given message should be sent to this server.
+    pub fn accepts(&self, message: &TelemetryMessage) -> bool {
+        message.verbosity() <= self.verbosity
+    }
+
+    /// Returns the address of the server as a multiaddress ending with `/x-parity-ws/<path>` or
+    /// `/x-parity-wss/<path>`, where the path is percent-encoded. The returned value can be
+    /// passed back to [`parse_endpoint`].
+    pub fn to_multiaddr(&self) -> String {
+        let host_protocol = if self.host.contains(':') {
+            "ip6"
+        } else if self.host.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+            "ip4"
+        } else {
+            "dns"
+        };
+
+        let mut path = String::with_capacity(self.path.len() * 3);
+        for byte in self.path.bytes() {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                path.push(char::from(byte));
+            } else {
+                path.push_str(&format!("%{:02X}", byte));
+            }
+        }
+
+        format!(
+            "/{}/{}/tcp/{}/{}/{}",
+            host_protocol,
+            self.host,
+            self.port,
+            if self.secure {
+                "x-parity-wss"
+            } else {
+                "x-parity-ws"
+            },
+            path
+        )
+    }
+}
+
+/// Parses the address of a telemetry server, as found in a chain specification.
+///
+/// Two formats are supported: WebSocket URLs (for example `wss://telemetry.polkadot.io/submit/`)
+/// and multiaddresses ending with `/x-parity-ws/<path>` or `/x-parity-wss/<path>`, where the
+/// path is percent-encoded (for example
+/// `/dns/telemetry.polkadot.io/tcp/443/x-parity-wss/%2Fsubmit%2F`).
+pub fn parse_endpoint(
+    address: &str,
+    verbosity: u8,
+) -> Result<TelemetryEndpoint, ParseEndpointError> {
+    if let Some(rest) = address.strip_prefix("ws://") {
+        parse_url(rest, false, verbosity)
+    } else if let Some(rest) = address.strip_prefix("wss://") {
+        parse_url(rest, true, verbosity)
+    } else if address.starts_with('/') {
+        parse_multiaddr(address, verbosity)
+    } else {
+        Err(ParseEndpointError::UnknownFormat)
+    }
+}
+
+fn parse_url(
+    without_scheme: &str,
+    secure: bool,
+    verbosity: u8,
+) -> Result<TelemetryEndpoint, ParseEndpointError> {
+    let (authority, path) = match without_scheme.find('/') {
+        Some(pos) => (&without_scheme[..pos], &without_scheme[pos..]),
+        None => (without_scheme, "/"),
+    };
+
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        // IPv6 address.
+        let (host, after) = rest
+            .split_once(']')
+            .ok_or(ParseEndpointError::InvalidHost)?;
+        match after.strip_prefix(':') {
+            Some(port) => (host, Some(port)),
+            None if after.is_empty() => (host, None),
+            None => return Err(ParseEndpointError::InvalidHost),
+        }
+    } else {
+        match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(ParseEndpointError::InvalidHost);
+    }
+
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| ParseEndpointError::InvalidPort)?,
+        None if secure => 443,
+        None => 80,
+    };
+
+    Ok(TelemetryEndpoint {
+        host: host.to_owned(),
+        port,
+        path: path.to_owned(),
+        secure,
+        verbosity,
+    })
+}
+
+fn parse_multiaddr(
+    multiaddr: &str,
+    verbosity: u8,
+) -> Result<TelemetryEndpoint, ParseEndpointError> {
+    // Note that the multiaddress is parsed manually rather than with the `multiaddr` module, as
+    // the `x-parity-ws` and `x-parity-wss` protocols are specific to telemetry.
+    let mut components = multiaddr[1..].split('/');
+
+    let host = match (components.next(), components.next()) {
+        (Some("dns" | "dns4" | "dns6" | "ip4" | "ip6"), Some(host)) if !host.is_empty() => {
+            host.to_owned()
+        }
+        _ => return Err(ParseEndpointError::UnknownFormat),
+    };
+
+    let port = match (components.next(), components.next()) {
+        (Some("tcp"), Some(port)) => port.parse().map_err(|_| ParseEndpointError::InvalidPort)?,
+        _ => return Err(ParseEndpointError::UnknownFormat),
+    };
+
+    let secure = match components.next() {
+        Some("x-parity-ws" | "ws") => false,
+        Some("x-parity-wss" | "wss") => true,
+        _ => return Err(ParseEndpointError::UnknownFormat),
+    };
+
+    let path = match components.next() {
+        Some(path) => percent_decode(path).ok_or(ParseEndpointError::InvalidPath)?,
+        None => "/".to_owned(),
+    };
+
+    if components.next().is_some() || !path.starts_with('/') {
+        return Err(ParseEndpointError::InvalidPath);
+    }
+
+    Ok(TelemetryEndpoint {
+        host,
+        port,
+        path,
+        secure,
+        verbosity,
+    })
+}
+
+/// Decodes a percent-encoded string. Returns `None` if the encoding is invalid.
+fn percent_decode(encoded: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hi = char::from(bytes.next()?).to_digit(16)?;
+            let lo = char::from(bytes.next()?).to_digit(16)?;
+            out.push(u8::try_from(hi * 16 + lo).unwrap());
+        } else {
+            out.push(byte);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Error potentially returned by [`parse_endpoint`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ParseEndpointError {
+    /// Address is neither a WebSocket URL nor a supported multiaddress.
+    UnknownFormat,
+    /// Host name of the address is invalid.
+    InvalidHost,
+    /// Port of the address is invalid.
+    InvalidPort,
+    /// Path of the address is invalid.
+    InvalidPath,
+}
+
+/// Message to send to a telemetry server.
+#[derive(Debug, Clone)]
+pub enum TelemetryMessage<'a> {
+    /// Must be the first message sent after the connection has been established.
+    SystemConnected(SystemConnected<'a>),
+    /// Should be sent at a periodic interval.
+    SystemInterval(SystemInterval<'a>),
+    /// A new best block has been imported.
+    BlockImport(BlockImport<'a>),
+    /// A new block has been finalized.
+    NotifyFinalized(NotifyFinalized<'a>),
+}
+
+/// See [`TelemetryMessage::SystemConnected`].
+#[derive(Debug, Clone)]
+pub struct SystemConnected<'a> {
+    /// Name of the chain, as found in the chain specification.
+    pub chain: &'a str,
+    /// Name of the node, as chosen by the user.
+    pub name: &'a str,
+    /// Name of the client implementation.
+    pub implementation: &'a str,
+    /// Version of the client implementation.
+    pub version: &'a str,
+    /// Public key of the validator, if the node is a validator.
+    pub validator: Option<&'a str>,
+    /// Base58-encoded `PeerId` of the node on the peer-to-peer network.
+    pub network_id: &'a str,
+    /// Hash of the genesis block of the chain.
+    pub genesis_hash: &'a [u8; 32],
+    /// Moment when the node was started, in milliseconds since the UNIX epoch.
+    pub startup_time_ms: Option<u64>,
+    /// Operating system the node runs on, for example `linux`.
+    pub target_os: &'a str,
+    /// CPU architecture the node runs on, for example `x86_64`.
+    pub target_arch: &'a str,
+    /// Target environment the node has been compiled for, for example `gnu`.
+    pub target_env: &'a str,
+}
+
+/// See [`TelemetryMessage::SystemInterval`].
+#[derive(Debug, Clone)]
+pub struct SystemInterval<'a> {
+    /// Number of peers the node is connected to.
+    pub num_peers: u64,
+    /// Number of transactions in the transactions pool, if known.
+    pub num_transactions: Option<u64>,
+    /// Average upload bandwidth, in bytes per second, if known.
+    pub bandwidth_upload: Option<f64>,
+    /// Average download bandwidth, in bytes per second, if known.
+    pub bandwidth_download: Option<f64>,
+    /// Hash of the current best block.
+    pub best_block_hash: &'a [u8; 32],
+    /// Height of the current best block.
+    pub best_block_number: u64,
+    /// Hash of the current finalized block.
+    pub finalized_block_hash: &'a [u8; 32],
+    /// Height of the current finalized block.
+    pub finalized_block_number: u64,
+}
+
+/// See [`TelemetryMessage::BlockImport`].
+#[derive(Debug, Clone)]
+pub struct BlockImport<'a> {
+    /// Hash of the new best block.
+    pub block_hash: &'a [u8; 32],
+    /// Height of the new best block.
+    pub block_number: u64,
+}
+
+/// See [`TelemetryMessage::NotifyFinalized`].
+#[derive(Debug, Clone)]
+pub struct NotifyFinalized<'a> {
+    /// Hash of the new finalized block.
+    pub block_hash: &'a [u8; 32],
+    /// Height of the new finalized block.
+    pub block_number: u64,
+}
+
+impl<'a> TelemetryMessage<'a> {
+    /// Returns the verbosity of this message. See the module-level documentation.
+    pub fn verbosity(&self) -> u8 {
+        match self {
+            TelemetryMessage::SystemConnected(_)
+            | TelemetryMessage::SystemInterval(_)
+            | TelemetryMessage::BlockImport(_)
+            | TelemetryMessage::NotifyFinalized(_) => VERBOSITY_SUBSTRATE_INFO,
+        }
+    }
+
+    /// Encodes the message into the JSON document to send to the telemetry server.
+    ///
+    /// Must be passed an identifier of the node within the connection. Since only one node is
+    /// reported per connection in practice, any constant value can be used.
+    pub fn encode(&self, node_id: u64) -> String {
+        let payload = match self {
+            TelemetryMessage::SystemConnected(msg) => SerdePayload::SystemConnected {
+                chain: msg.chain,
+                name: msg.name,
+                implementation: msg.implementation,
+                version: msg.version,
+                validator: msg.validator,
+                network_id: msg.network_id,
+                genesis_hash: hash_to_string(msg.genesis_hash),
+                startup_time: msg.startup_time_ms.map(|t| t.to_string()),
+                target_os: msg.target_os,
+                target_arch: msg.target_arch,
+                target_env: msg.target_env,
+            },
+            TelemetryMessage::SystemInterval(msg) => SerdePayload::SystemInterval {
+                peers: msg.num_peers,
+                txcount: msg.num_transactions,
+                bandwidth_upload: msg.bandwidth_upload,
+                bandwidth_download: msg.bandwidth_download,
+                best: hash_to_string(msg.best_block_hash),
+                height: msg.best_block_number,
+                finalized_hash: hash_to_string(msg.finalized_block_hash),
+                finalized_height: msg.finalized_block_number,
+            },
+            TelemetryMessage::BlockImport(msg) => SerdePayload::BlockImport {
+                best: hash_to_string(msg.block_hash),
+                height: msg.block_number,
+            },
+            TelemetryMessage::NotifyFinalized(msg) => SerdePayload::NotifyFinalized {
+                best: hash_to_string(msg.block_hash),
+                // Substrate reports the height of finalized blocks as a string.
+                height: msg.block_number.to_string(),
+            },
+        };
+
+        serde_json::to_string(&SerdeMessage {
+            id: node_id,
+            payload,
+        })
+        .unwrap()
+    }
+}
+
+fn hash_to_string(hash: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+#[derive(serde::Serialize)]
+struct SerdeMessage<'a> {
+    id: u64,
+    payload: SerdePayload<'a>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "msg")]
+enum SerdePayload<'a> {
+    #[serde(rename = "system.connected")]
+    SystemConnected {
+        chain: &'a str,
+        name: &'a str,
+        implementation: &'a str,
+        version: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        validator: Option<&'a str>,
+        network_id: &'a str,
+        genesis_hash: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        startup_time: Option<String>,
+        target_os: &'a str,
+        target_arch: &'a str,
+        target_env: &'a str,
+    },
+    #[serde(rename = "system.interval")]
+    SystemInterval {
+        peers: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        txcount: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bandwidth_upload: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bandwidth_download: Option<f64>,
+        best: String,
+        height: u64,
+        finalized_hash: String,
+        finalized_height: u64,
+    },
+    #[serde(rename = "block.import")]
+    BlockImport { best: String, height: u64 },
+    #[serde(rename = "notify.finalized")]
+    NotifyFinalized { best: String, height: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_url() {
+        assert_eq!(
+            parse_endpoint("wss://telemetry.polkadot.io/submit/", 0).unwrap(),
+            TelemetryEndpoint {
+                host: "telemetry.polkadot.io".into(),
+                port: 443,
+                path: "/submit/".into(),
+                secure: true,
+                verbosity: 0,
+            }
+        );
+
+        assert_eq!(
+            parse_endpoint("ws://[::1]:8000", 1).unwrap(),
+            TelemetryEndpoint {
+                host: "::1".into(),
+                port: 8000,
+                path: "/".into(),
+                secure: false,
+                verbosity: 1,
+            }
+        );
+
+        assert!(parse_endpoint("http://example.com", 0).is_err());
+        assert!(parse_endpoint("ws://example.com:foo/", 0).is_err());
+    }
+
+    #[test]
+    fn parse_multiaddr() {
+        assert_eq!(
+            parse_endpoint(
+                "/dns/telemetry.polkadot.io/tcp/443/x-parity-wss/%2Fsubmit%2F",
+                0
+            )
+            .unwrap(),
+            TelemetryEndpoint {
+                host: "telemetry.polkadot.io".into(),
+                port: 443,
+                path: "/submit/".into(),
+                secure: true,
+                verbosity: 0,
+            }
+        );
+
+        assert_eq!(
+            parse_endpoint("/ip4/127.0.0.1/tcp/8001/x-parity-ws", 0)
+                .unwrap()
+                .host_header(),
+            "127.0.0.1:8001"
+        );
+
+        assert!(parse_endpoint("/ip4/127.0.0.1/udp/8001/x-parity-ws", 0).is_err());
+        assert!(parse_endpoint("/dns/example.com/tcp/443/x-parity-wss/%2", 0).is_err());
+    }
+
+    #[test]
+    fn to_multiaddr() {
+        let endpoint = parse_endpoint("wss://telemetry.polkadot.io/submit/", 3).unwrap();
+        assert_eq!(
+            endpoint.to_multiaddr(),
+            "/dns/telemetry.polkadot.io/tcp/443/x-parity-wss/%2Fsubmit%2F"
+        );
+
+        for address in [
+            "wss://telemetry.polkadot.io/submit/",
+            "ws://127.0.0.1:8001/",
+            "ws://[::1]:8001/feed?a=b c",
+        ] {
+            let endpoint = parse_endpoint(address, 3).unwrap();
+            assert_eq!(
+                parse_endpoint(&endpoint.to_multiaddr(), 3).unwrap(),
+                endpoint
+            );
+        }
+    }
+
+    #[test]
+    fn encode_messages() {
+        let encoded = TelemetryMessage::NotifyFinalized(NotifyFinalized {
+            block_hash: &[0xab; 32],
+            block_number: 5,
+        })
+        .encode(1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&encoded).unwrap(),
+            serde_json::json!({
+                "id": 1,
+                "payload": {
+                    "msg": "notify.finalized",
+                    "best": format!("0x{}", "ab".repeat(32)),
+                    "height": "5",
+                }
+            })
+        );
+
+        let encoded = TelemetryMessage::SystemInterval(SystemInterval {
+            num_peers: 3,
+            num_transactions: None,
+            bandwidth_upload: Some(1.5),
+            bandwidth_download: None,
+            best_block_hash: &[1; 32],
+            best_block_number: 10,
+            finalized_block_hash: &[2; 32],
+            finalized_block_number: 8,
+        })
+        .encode(7);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&encoded).unwrap(),
+            serde_json::json!({
+                "id": 7,
+                "payload": {
+                    "msg": "system.interval",
+                    "peers": 3,
+                    "bandwidth_upload": 1.5,
+                    "best": format!("0x{}", "01".repeat(32)),
+                    "height": 10,
+                    "finalized_hash": format!("0x{}", "02".repeat(32)),
+                    "finalized_height": 8,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn verbosity_filter() {
+        let message = TelemetryMessage::BlockImport(BlockImport {
+            block_hash: &[0; 32],
+            block_number: 1,
+        });
+
+        let mut endpoint = parse_endpoint("ws://localhost/", VERBOSITY_SUBSTRATE_INFO).unwrap();
+        assert!(endpoint.accepts(&message));
+        endpoint.verbosity = VERBOSITY_SUBSTRATE_DEBUG;
+        assert!(endpoint.accepts(&message));
+    }
+}
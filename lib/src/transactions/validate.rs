@@ -335,7 +335,9 @@ pub fn validate_transaction(
                 main_trie_root_calculation_cache: None,
                 storage_main_trie_changes: storage_diff::TrieDiff::empty(),
                 offchain_storage_changes: storage_diff::TrieDiff::empty(),
+                storage_proof_recorder: None,
                 max_log_level: config.max_log_level,
+                host_functions_profiler: None,
             });
 
             // Information used later, after `Core_initialize_block` is done.
@@ -372,7 +374,9 @@ pub fn validate_transaction(
                 main_trie_root_calculation_cache: None,
                 storage_main_trie_changes: storage_diff::TrieDiff::empty(),
                 offchain_storage_changes: storage_diff::TrieDiff::empty(),
+                storage_proof_recorder: None,
                 max_log_level: config.max_log_level,
+                host_functions_profiler: None,
             });
 
             match vm {
@@ -461,10 +465,12 @@ impl Query {
                         ),
                         storage_main_trie_changes: success.storage_main_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
+                        storage_proof_recorder: None,
                         main_trie_root_calculation_cache: Some(
                             success.main_trie_root_calculation_cache,
                         ),
                         max_log_level: 0,
+                        host_functions_profiler: None,
                     });
 
                     match vm {
@@ -107,6 +107,7 @@ use core::iter;
 mod nibble;
 
 pub mod calculate_root;
+pub mod compact_proof;
 pub mod prefix_proof;
 pub mod proof_decode;
 pub mod proof_encode;
@@ -0,0 +1,351 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of compact trie proofs.
+//!
+//! A compact proof is a variant of a trie proof (see the [`super::proof_decode`] module) that
+//! omits all the information that can be deduced from the rest of the proof. It is, for example,
+//! the format of the proofs found in responses to state requests.
+//!
+//! # Details
+//!
+//! A compact proof is, like a regular proof, a SCALE-encoded list of node values. These node
+//! values are ordered in the order of a depth-first traversal of the trie. Within a node value,
+//! the Merkle value of a child that is itself part of the proof is omitted and replaced with an
+//! empty Merkle value. The child can be found in the entries that follow.
+//!
+//! If the storage value of a node is hashed and the un-hashed storage value is included in the
+//! proof, then the node value is prefixed with the byte `1` and contains an empty storage value,
+//! and the entry that follows the node value contains the un-hashed storage value.
+//!
+//! A compact proof can contain multiple tries one after the other. The first one is typically
+//! the main trie, and the others child tries.
+//!
+//! # Usage
+//!
+//! This module provides the [`decode`] function that converts a compact proof into regular
+//! proofs that can then be passed to [`super::proof_decode::decode_and_verify_proof`].
+
+use super::trie_node;
+
+use alloc::vec::Vec;
+
+/// Byte found at the start of a node value whose storage value is found in the next entry.
+const ESCAPE_HEADER: u8 = 1;
+
+/// Trie found in a compact proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedTrie {
+    /// Hash of the root node of the trie.
+    ///
+    /// > **Note**: A compact proof is always valid, but describes the trie whose root is this
+    /// >           hash. It is the responsibility of the API user to compare this hash with the
+    /// >           one they expect.
+    pub trie_root_hash: [u8; 32],
+
+    /// SCALE-encoded list of node values and storage values that make up the trie, in the format
+    /// expected by [`super::proof_decode::decode_and_verify_proof`].
+    pub proof: Vec<u8>,
+}
+
+/// Decodes a compact proof into the list of tries it contains.
+pub fn decode(compact_proof: &[u8]) -> Result<Vec<DecodedTrie>, Error> {
+    // A compact proof is a SCALE-encoded `Vec<Vec<u8>>`.
+    let (_, entries) = nom::combinator::all_consuming(nom::combinator::flat_map(
+        crate::util::nom_scale_compact_usize,
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+    ))(compact_proof)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)?;
+
+    let mut entries = entries.into_iter();
+    let mut tries = Vec::new();
+
+    while entries.len() != 0 {
+        let mut proof_entries = Vec::new();
+        let trie_root_hash = decode_trie(&mut entries, &mut proof_entries)?;
+
+        // The same node value or storage value can be found multiple times in a trie, but
+        // regular proofs must not contain duplicates.
+        proof_entries.sort_unstable();
+        proof_entries.dedup();
+
+        let mut proof = Vec::with_capacity(
+            proof_entries
+                .iter()
+                .map(|entry| entry.len() + 4)
+                .sum::<usize>()
+                + 4,
+        );
+        proof.extend_from_slice(
+            crate::util::encode_scale_compact_usize(proof_entries.len()).as_ref(),
+        );
+        for entry in proof_entries {
+            proof.extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+            proof.extend_from_slice(&entry);
+        }
+
+        tries.push(DecodedTrie {
+            trie_root_hash,
+            proof,
+        });
+    }
+
+    Ok(tries)
+}
+
+/// Node whose children are being decoded.
+struct StackEntry<'a> {
+    /// Decoded node value, as found in the compact proof.
+    decoded: trie_node::Decoded<'a, trie_node::DecodedPartialKey<'a>, &'a [u8]>,
+    /// Storage value found in the entry following the node value, if any.
+    detached_storage_value: Option<&'a [u8]>,
+    /// Hashes of the children whose Merkle value is omitted from the node value.
+    omitted_children: [Option<[u8; 32]>; 16],
+    /// Index of the next child to examine.
+    next_child: usize,
+}
+
+/// Decodes one trie from `entries`, pushes the node values and storage values to
+/// `proof_entries`, and returns the hash of its root node.
+fn decode_trie<'a>(
+    entries: &mut impl Iterator<Item = &'a [u8]>,
+    proof_entries: &mut Vec<Vec<u8>>,
+) -> Result<[u8; 32], Error> {
+    let mut stack = Vec::<StackEntry>::new();
+
+    loop {
+        let entry = entries.next().ok_or(Error::MissingNode)?;
+
+        let (escaped, node_value) = match entry.split_first() {
+            Some((&ESCAPE_HEADER, node_value)) => (true, node_value),
+            _ => (false, entry),
+        };
+
+        let decoded = trie_node::decode(node_value).map_err(Error::InvalidNodeValue)?;
+
+        let detached_storage_value = if escaped {
+            if !matches!(decoded.storage_value, trie_node::StorageValue::Unhashed(v) if v.is_empty())
+            {
+                return Err(Error::InvalidEscapedNode);
+            }
+            Some(entries.next().ok_or(Error::MissingStorageValue)?)
+        } else {
+            None
+        };
+
+        let mut current = StackEntry {
+            decoded,
+            detached_storage_value,
+            omitted_children: [None; 16],
+            next_child: 0,
+        };
+
+        loop {
+            // Find the next child whose Merkle value is omitted. If there is one, it is the next
+            // entry of the proof.
+            while current.next_child < 16
+                && !matches!(current.decoded.children[current.next_child], Some(c) if c.is_empty())
+            {
+                current.next_child += 1;
+            }
+            if current.next_child < 16 {
+                stack.push(current);
+                break;
+            }
+
+            // All the children of `current` are known. Rebuild its actual node value.
+            let storage_value_hash = current.detached_storage_value.map(blake2_hash);
+            let mut children = current.decoded.children;
+            for (child, omitted) in children.iter_mut().zip(current.omitted_children.iter()) {
+                if let Some(omitted) = omitted {
+                    *child = Some(&omitted[..]);
+                }
+            }
+            let node_value = trie_node::encode_to_vec(trie_node::Decoded {
+                partial_key: current.decoded.partial_key.clone(),
+                children,
+                storage_value: match &storage_value_hash {
+                    Some(hash) => trie_node::StorageValue::Hashed(hash),
+                    None => current.decoded.storage_value,
+                },
+            })
+            .map_err(|_| Error::InvalidFormat)?;
+
+            let node_hash = blake2_hash(&node_value);
+            proof_entries.push(node_value);
+            if let Some(value) = current.detached_storage_value {
+                proof_entries.push(value.to_vec());
+            }
+
+            // Children that are part of the proof are always referred to by their hash, as
+            // otherwise they would be inlined in their parent.
+            match stack.pop() {
+                Some(mut parent) => {
+                    parent.omitted_children[parent.next_child] = Some(node_hash);
+                    parent.next_child += 1;
+                    current = parent;
+                }
+                None => return Ok(node_hash),
+            }
+        }
+    }
+}
+
+fn blake2_hash(data: &[u8]) -> [u8; 32] {
+    <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes()).unwrap()
+}
+
+/// Possible error returned by [`decode`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// Proof is in an invalid format.
+    InvalidFormat,
+    /// One of the node values in the proof has an invalid format.
+    #[display(fmt = "A node of the proof has an invalid format: {_0}")]
+    InvalidNodeValue(trie_node::Error),
+    /// A node value whose storage value is supposed to be found in the next entry has an
+    /// invalid format.
+    InvalidEscapedNode,
+    /// The proof ends while the storage value of a node is supposed to be found in the next
+    /// entry.
+    MissingStorageValue,
+    /// The proof ends while some nodes are supposed to be found in the next entries.
+    MissingNode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{calculate_root, nibble, proof_decode, trie_node, TrieEntryVersion};
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn basic_works() {
+        // Trie made of a branch node at `0xa` with two children at `0xaa` and `0xab`. The value
+        // at `0xaa` uses version 1 of the trie, meaning that it is hashed.
+        let value_aa = [1u8; 40];
+        let value_ab = [2u8; 40];
+
+        let mut storage = BTreeMap::new();
+        storage.insert(vec![0xaa], (value_aa.to_vec(), TrieEntryVersion::V1));
+        storage.insert(vec![0xab], (value_ab.to_vec(), TrieEntryVersion::V0));
+
+        let expected_root = {
+            let mut calculation = calculate_root::root_merkle_value(None);
+            loop {
+                match calculation {
+                    calculate_root::RootMerkleValueCalculation::Finished { hash, .. } => {
+                        break hash
+                    }
+                    calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                        calculation = keys.inject(storage.keys().map(|k| k.iter().cloned()));
+                    }
+                    calculate_root::RootMerkleValueCalculation::StorageValue(value_request) => {
+                        let key = value_request.key().collect::<Vec<u8>>();
+                        calculation =
+                            value_request.inject(storage.get(&key).map(|(val, v)| (val, *v)));
+                    }
+                }
+            }
+        };
+
+        let encode = |partial_key: &[u8],
+                      children: [Option<&[u8]>; 16],
+                      storage_value: trie_node::StorageValue| {
+            trie_node::encode_to_vec(trie_node::Decoded {
+                partial_key: partial_key
+                    .iter()
+                    .map(|n| nibble::Nibble::try_from(*n).unwrap())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                children,
+                storage_value,
+            })
+            .unwrap()
+        };
+
+        let mut root_children = [None; 16];
+        root_children[0xa] = Some(&[][..]);
+        root_children[0xb] = Some(&[][..]);
+
+        let mut compact_entries = Vec::new();
+        compact_entries.push(encode(&[0xa], root_children, trie_node::StorageValue::None));
+        compact_entries.push({
+            let mut node = vec![super::ESCAPE_HEADER];
+            node.extend(encode(
+                &[],
+                [None; 16],
+                trie_node::StorageValue::Unhashed(&[]),
+            ));
+            node
+        });
+        compact_entries.push(value_aa.to_vec());
+        compact_entries.push(encode(
+            &[],
+            [None; 16],
+            trie_node::StorageValue::Unhashed(&value_ab),
+        ));
+
+        let mut compact_proof = crate::util::encode_scale_compact_usize(compact_entries.len())
+            .as_ref()
+            .to_vec();
+        for entry in &compact_entries {
+            compact_proof
+                .extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+            compact_proof.extend_from_slice(entry);
+        }
+
+        let decoded = super::decode(&compact_proof).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].trie_root_hash, expected_root);
+
+        let proof = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &expected_root,
+            proof: &decoded[0].proof,
+        })
+        .unwrap();
+        assert_eq!(
+            proof.storage_value(&[0xaa]),
+            Some(Some((&value_aa[..], TrieEntryVersion::V1)))
+        );
+        assert_eq!(
+            proof.storage_value(&[0xab]),
+            Some(Some((&value_ab[..], TrieEntryVersion::V0)))
+        );
+    }
+
+    #[test]
+    fn missing_node() {
+        let mut root_children = [None; 16];
+        root_children[0] = Some(&[][..]);
+        let root = trie_node::encode_to_vec(trie_node::Decoded {
+            partial_key: core::iter::empty(),
+            children: root_children,
+            storage_value: trie_node::StorageValue::None,
+        })
+        .unwrap();
+
+        let mut compact_proof = vec![4];
+        compact_proof
+            .extend_from_slice(crate::util::encode_scale_compact_usize(root.len()).as_ref());
+        compact_proof.extend_from_slice(&root);
+
+        assert!(matches!(
+            super::decode(&compact_proof),
+            Err(super::Error::MissingNode)
+        ));
+    }
+}
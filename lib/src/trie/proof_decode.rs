@@ -85,11 +85,7 @@ where
     // the function actually take less time than if it was a legitimate proof.
     let merkle_values = {
         // TODO: don't use a Vec?
-        let (_, decoded_proof) = nom::combinator::all_consuming(nom::combinator::flat_map(
-            crate::util::nom_scale_compact_usize,
-            |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
-        ))(config.proof.as_ref())
-        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)?;
+        let decoded_proof = decode_proof_entries(config.proof.as_ref())?;
 
         let merkle_values = decoded_proof
             .iter()
@@ -429,6 +425,108 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         })
     }
 
+    /// Returns the entries of [`DecodedTrieProof::iter_runtime_context_ordered`] whose key is
+    /// superior or equal to `start_key`, stopping before the first key the proof doesn't
+    /// contain enough information about.
+    ///
+    /// In other words, the trie is guaranteed to not contain any storage value between
+    /// `start_key` and the last returned key other than the ones that are returned. The returned
+    /// storage values are never [`StorageValue::HashKnownValueMissing`].
+    ///
+    /// The returned `bool` is `true` if the proof also guarantees that the trie doesn't contain
+    /// any storage value after the last returned key.
+    ///
+    /// This is typically used in order to verify the responses to a paginated download of the
+    /// content of a trie.
+    ///
+    /// # Panic
+    ///
+    /// See [`DecodedTrieProof::iter_runtime_context_ordered`].
+    ///
+    pub fn iter_runtime_context_ordered_contiguous(
+        &'_ self,
+        start_key: &[u8],
+    ) -> (Vec<(Vec<u8>, StorageValue<'_>)>, bool) {
+        // An empty proof doesn't contain any information.
+        if self.entries.is_empty() {
+            return (Vec::new(), false);
+        }
+
+        let start_key = nibble::bytes_to_nibbles(start_key.iter().copied()).collect::<Vec<_>>();
+
+        // Find the lowest key, superior or equal to `start_key`, that the proof doesn't contain
+        // any information about. Since the proof is guaranteed to contain the root node, this is
+        // either the key of a storage value that is missing from the proof, or the first key of
+        // the sub-trie of a child that is missing from the proof.
+        let mut unknown_start: Option<Vec<nibble::Nibble>> = None;
+        for (key, entry) in self.iter_ordered() {
+            if matches!(
+                entry.trie_node_info.storage_value,
+                StorageValue::HashKnownValueMissing(_)
+            ) && key >= &start_key[..]
+            {
+                unknown_start = Some(match unknown_start {
+                    Some(current) if current[..] <= *key => current,
+                    _ => key.to_vec(),
+                });
+            }
+
+            for child in entry
+                .trie_node_info
+                .children
+                .unfold_append_to_key(key.to_vec())
+            {
+                let first_descendant = self
+                    .entries
+                    .range::<[nibble::Nibble], _>((
+                        ops::Bound::Included(&child[..]),
+                        ops::Bound::Unbounded,
+                    ))
+                    .next();
+                if matches!(first_descendant, Some((k, _)) if k.starts_with(&child)) {
+                    continue;
+                }
+
+                // All the keys that start with `child` are unknown. If `start_key` starts with
+                // `child`, the unknown range starts at `start_key`.
+                let unknown = if start_key.starts_with(&child) {
+                    start_key.clone()
+                } else if child > start_key {
+                    child
+                } else {
+                    continue;
+                };
+
+                unknown_start = Some(match unknown_start {
+                    Some(current) if current <= unknown => current,
+                    _ => unknown,
+                });
+            }
+        }
+
+        let entries = self
+            .iter_ordered()
+            .filter(|(key, _)| *key >= &start_key[..])
+            .take_while(|(key, _)| match &unknown_start {
+                Some(unknown) => *key < &unknown[..],
+                None => true,
+            })
+            .filter_map(|(key, entry)| {
+                let value = entry.trie_node_info.storage_value;
+
+                if key.len() % 2 != 0 {
+                    assert!(matches!(value, StorageValue::None));
+                    return None;
+                }
+
+                let key = nibble::nibbles_to_bytes_suffix_extend(key.iter().copied()).collect();
+                Some((key, value))
+            })
+            .collect();
+
+        (entries, unknown_start.is_none())
+    }
+
     /// Returns a list of all elements of the proof, ordered by key in lexicographic order.
     ///
     /// The iterator includes branch nodes.
@@ -696,7 +794,23 @@ impl<'a> fmt::Debug for StorageValue<'a> {
     }
 }
 
-/// Possible error returned by [`decode_and_verify_proof`].
+/// Decodes the list of entries of a SCALE-encoded Merkle proof, without verifying anything
+/// about these entries.
+///
+/// This is useful in order to convert a proof to a format that consists in a list of trie node
+/// values, such as the one returned by the `state_getReadProof` JSON-RPC function.
+///
+/// Returns [`Error::InvalidFormat`] if the proof isn't a valid SCALE-encoded list of entries.
+pub fn decode_proof_entries(proof: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let (_, entries) = nom::combinator::all_consuming(nom::combinator::flat_map(
+        crate::util::nom_scale_compact_usize,
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+    ))(proof)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)?;
+    Ok(entries)
+}
+
+/// Possible error returned by [`decode_and_verify_proof`] and [`decode_proof_entries`].
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum Error {
     /// Proof is in an invalid format.
@@ -809,6 +923,17 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn decode_proof_entries_works() {
+        assert!(super::decode_proof_entries(&[0]).unwrap().is_empty());
+        assert_eq!(
+            super::decode_proof_entries(&[8, 4, 1, 8, 2, 3]).unwrap(),
+            vec![&[1][..], &[2, 3][..]]
+        );
+        assert!(super::decode_proof_entries(&[8, 4, 1]).is_err());
+        assert!(super::decode_proof_entries(&[4, 4, 1, 0]).is_err());
+    }
+
     #[test]
     fn basic_works() {
         // Key/value taken from the Polkadot genesis block.
@@ -983,6 +1108,85 @@ mod tests {
         assert_eq!(obtained.unwrap().0, &[80, 82, 127, 41, 119, 1, 0, 0][..]);
     }
 
+    #[test]
+    fn iter_runtime_context_ordered_contiguous_stops_at_unknown() {
+        // Values are longer than 32 bytes so that the nodes aren't inlined in their parent.
+        let entries = [
+            (&b"a"[..], [1; 40]),
+            (&b"b"[..], [2; 40]),
+            (&b"c"[..], [3; 40]),
+        ];
+
+        let build = |keys_to_prove: &[(&[u8], bool)]| {
+            let builder = super::super::proof_encode::build_from_ordered_trie_entries(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k, v, super::super::TrieEntryVersion::V1)),
+                keys_to_prove.iter().copied(),
+            );
+            (builder.trie_root_hash().unwrap(), builder.build_to_vec())
+        };
+
+        let keys = |(entries, complete): (Vec<(Vec<u8>, super::StorageValue)>, bool)| {
+            let keys = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    assert!(matches!(value, super::StorageValue::Known { .. }));
+                    key
+                })
+                .collect::<Vec<_>>();
+            (keys, complete)
+        };
+
+        // Only `a` is in the proof. The sub-tries of `b` and `c` are unknown.
+        let (root, proof) = build(&[(b"a", false)]);
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &root,
+            proof: &proof,
+        })
+        .unwrap();
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"")),
+            (vec![b"a".to_vec()], false)
+        );
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"b")),
+            (vec![], false)
+        );
+        // The root node proves that no key starts with `d` or more.
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"d")),
+            (vec![], true)
+        );
+
+        // All the entries are in the proof.
+        let (root, proof) = build(&[(b"a", false), (b"b", false), (b"c", false)]);
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &root,
+            proof: &proof,
+        })
+        .unwrap();
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"")),
+            (vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()], true)
+        );
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"b")),
+            (vec![b"b".to_vec(), b"c".to_vec()], true)
+        );
+
+        // An empty proof doesn't contain any information.
+        let decoded = super::decode_and_verify_proof(super::Config {
+            trie_root_hash: &root,
+            proof: &[0],
+        })
+        .unwrap();
+        assert_eq!(
+            keys(decoded.iter_runtime_context_ordered_contiguous(b"")),
+            (vec![], false)
+        );
+    }
+
     #[test]
     fn very_small_root_node_decodes() {
         // Checks that a proof with one root node whose length is < 32 bytes properly verifies.
@@ -15,9 +15,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::{nibble, trie_node, trie_structure};
+use super::{nibble, trie_node, trie_structure, TrieEntryVersion};
 
-use alloc::{borrow::ToOwned as _, vec::Vec};
+use alloc::{
+    borrow::ToOwned as _,
+    vec::{self, Vec},
+};
 use core::{array, iter};
 
 pub use super::nibble::Nibble;
@@ -439,6 +442,473 @@ impl Default for ProofBuilder {
     }
 }
 
+/// Builds a [`ProofBuilder`] containing the node values necessary to prove the given keys, given
+/// the list of all the entries of the trie.
+///
+/// For each key in `keys_to_prove`, the proof contains the node values of all the ancestors of
+/// this key, plus the node value of the node found at this key or, if there isn't any, of the
+/// node that was found in its place while traversing the trie. This makes it possible for the
+/// verifier to determine the storage value of the key or the absence of storage value.
+///
+/// Each key in `keys_to_prove` is accompanied with a `bool`. If `true`, the proof additionally
+/// includes the children of the node found while traversing the trie and the children of its
+/// parent. This is necessary in order for the verifier to be able to calculate the new trie root
+/// after this key has been modified, as removing a node can merge its parent with a sibling or
+/// the node with its child.
+///
+/// If the same key is found multiple times in `trie_entries`, only the last value is used.
+///
+/// The returned [`ProofBuilder`] doesn't have any missing node value, and there is no need to
+/// call [`ProofBuilder::make_coherent`].
+pub fn build_from_trie_entries(
+    trie_entries: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>, TrieEntryVersion)>,
+    keys_to_prove: impl Iterator<Item = (impl AsRef<[u8]>, bool)>,
+) -> ProofBuilder {
+    // Build the structure of the trie.
+    let mut trie = trie_structure::TrieStructure::<TrieEntriesNode>::new();
+    for (key, value, version) in trie_entries {
+        let value = value.as_ref();
+        let node_info = TrieEntriesNode {
+            storage_value_hash: match version {
+                TrieEntryVersion::V1 if value.len() >= 33 => Some(blake2_hash(value)),
+                _ => None,
+            },
+            storage_value: Some(value.to_vec()),
+            merkle_value: None,
+        };
+
+        match trie.node(nibble::bytes_to_nibbles(key.as_ref().iter().copied())) {
+            trie_structure::Entry::Vacant(entry) => {
+                entry
+                    .insert_storage_value()
+                    .insert(node_info, TrieEntriesNode::default());
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Branch(entry)) => {
+                *entry.insert_storage_value().user_data() = node_info;
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Storage(mut entry)) => {
+                *entry.user_data() = node_info;
+            }
+        }
+    }
+
+    // Calculate the Merkle value of every node of the trie. We use the same order of iteration
+    // as in [`ProofBuilder::make_coherent`], which guarantees that children are always
+    // traversed before their parent.
+    if let Some(mut node) = trie.root_node() {
+        node = loop {
+            match node.into_first_child() {
+                Ok(c) => node = c,
+                Err(c) => break c,
+            }
+        };
+
+        loop {
+            let is_root_node = node.is_root_node();
+            // `calculate_merkle_value` can only fail if the node has no children and no storage
+            // value, which can't happen in a trie structure.
+            let merkle_value = with_trie_entries_node_decoded(&mut node, |decoded| {
+                trie_node::calculate_merkle_value(decoded, is_root_node)
+            })
+            .unwrap();
+            node.user_data().merkle_value = Some(merkle_value);
+
+            match node.into_next_sibling() {
+                Err(n) => match n.into_parent() {
+                    Some(p) => node = p,
+                    None => break, // Finished.
+                },
+                Ok(mut sibling) => {
+                    node = loop {
+                        match sibling.into_first_child() {
+                            Ok(c) => sibling = c,
+                            Err(c) => break c,
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    // Indices of the nodes to include in the proof. Nodes are de-duplicated, as multiple keys
+    // typically share ancestors.
+    let mut nodes_to_include =
+        hashbrown::HashSet::<_, fnv::FnvBuildHasher>::with_hasher(Default::default());
+
+    for (key, include_children) in keys_to_prove {
+        let key = nibble::bytes_to_nibbles(key.as_ref().iter().copied()).collect::<Vec<_>>();
+
+        let Some(mut node) = trie.root_node() else {
+            break;
+        };
+        let mut depth = 0;
+
+        // Traverse the trie towards `key`, including every node on the way.
+        loop {
+            nodes_to_include.insert(node.node_index());
+
+            let partial_key_matches = {
+                let partial_key = node.partial_key();
+                let partial_key_len = partial_key.len();
+                let matches = key.len() >= depth + partial_key_len
+                    && itertools::equal(
+                        partial_key,
+                        key[depth..depth + partial_key_len].iter().copied(),
+                    );
+                depth += partial_key_len;
+                matches
+            };
+
+            // Either the node is the one at `key`, or `key` doesn't exist in the trie and the
+            // node found in its place proves its absence.
+            if !partial_key_matches || depth == key.len() {
+                break;
+            }
+
+            match node.into_child(key[depth]) {
+                Ok(child) => {
+                    depth += 1;
+                    node = child;
+                }
+                Err(n) => {
+                    node = n;
+                    break;
+                }
+            }
+        }
+
+        if include_children {
+            let node_index = node.node_index();
+            let parent_index = node.parent().map(|p| p.node_index());
+            for node_index in iter::once(node_index).chain(parent_index) {
+                let mut node = trie.node_by_index(node_index).unwrap();
+                for child_nibble in nibble::all_nibbles() {
+                    if let Some(child) = node.child(child_nibble) {
+                        nodes_to_include.insert(child.node_index());
+                    }
+                }
+            }
+        }
+    }
+
+    // Put the node values of the nodes to include in the proof builder.
+    let mut proof_builder = ProofBuilder::with_nodes_capacity(nodes_to_include.len());
+    for node_index in nodes_to_include {
+        let mut node = trie.node_by_index(node_index).unwrap();
+        let node_value =
+            with_trie_entries_node_decoded(&mut node, trie_node::encode_to_vec).unwrap();
+        let full_key = node.full_key().collect::<Vec<_>>();
+        let user_data = node.user_data();
+        let unhashed_storage_value = if user_data.storage_value_hash.is_some() {
+            user_data.storage_value.as_deref()
+        } else {
+            None
+        };
+        proof_builder.set_node_value(&full_key, &node_value, unhashed_storage_value);
+    }
+
+    debug_assert_eq!(proof_builder.missing_node_values().count(), 0);
+    proof_builder
+}
+
+/// Similar to [`build_from_trie_entries`], except that `trie_entries` must be ordered by
+/// ascending key.
+///
+/// The trie is visited in a single pass over `trie_entries`. Only the nodes that are ancestors
+/// of the entry being visited are kept in memory, which makes this function suitable for tries
+/// that are too large to entirely fit in memory, such as the storage of a block stored in a
+/// database.
+///
+/// # Panic
+///
+/// Panics if the keys of `trie_entries` aren't strictly increasing.
+///
+pub fn build_from_ordered_trie_entries(
+    trie_entries: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>, TrieEntryVersion)>,
+    keys_to_prove: impl Iterator<Item = (impl AsRef<[u8]>, bool)>,
+) -> ProofBuilder {
+    let keys_to_prove = keys_to_prove
+        .map(|(key, include_children)| {
+            let key = nibble::bytes_to_nibbles(key.as_ref().iter().copied()).collect::<Vec<_>>();
+            (key, include_children)
+        })
+        .collect::<Vec<_>>();
+
+    // The node values of the children of a node are only known before the node itself is
+    // finalized. If any key requires including children, the node values of the children of
+    // the nodes in the stack are kept until it is known whether they must be included.
+    let keep_children_node_values = keys_to_prove
+        .iter()
+        .any(|(_, include_children)| *include_children);
+
+    let mut proof_builder = ProofBuilder::new();
+
+    // Nodes that are ancestors of `current_key` (or `current_key` itself) and whose Merkle
+    // value can't be calculated yet, ordered from the root to the deepest. The full key of each
+    // node is always a prefix of `current_key`.
+    let mut stack = Vec::<OrderedEntriesNode>::with_capacity(32);
+    // Key of the latest entry yielded by `trie_entries`.
+    let mut current_key = Vec::<Nibble>::new();
+
+    for (key, value, version) in trie_entries {
+        let key = nibble::bytes_to_nibbles(key.as_ref().iter().copied()).collect::<Vec<_>>();
+        assert!(
+            stack.is_empty() || key > current_key,
+            "trie entries not ordered"
+        );
+
+        // All the nodes of the stack that aren't a prefix of `key` have no more descendants
+        // left to visit, and can be finalized. If `key` and `current_key` diverge below a
+        // node, a branch node is inserted at the point of divergence.
+        let common_prefix_len = key
+            .iter()
+            .zip(current_key.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while stack
+            .last()
+            .map_or(false, |node| node.depth > common_prefix_len)
+        {
+            let node = stack.pop().unwrap();
+            let parent_depth = match stack.last() {
+                Some(parent) if parent.depth >= common_prefix_len => parent.depth,
+                _ => {
+                    stack.push(OrderedEntriesNode::new(common_prefix_len, None, None));
+                    common_prefix_len
+                }
+            };
+            finalize_ordered_entries_node(
+                node,
+                Some(parent_depth),
+                &current_key,
+                &keys_to_prove,
+                keep_children_node_values,
+                &mut stack,
+                &mut proof_builder,
+            );
+        }
+
+        let value = value.as_ref();
+        stack.push(OrderedEntriesNode::new(
+            key.len(),
+            Some(value.to_vec()),
+            match version {
+                TrieEntryVersion::V1 if value.len() >= 33 => Some(blake2_hash(value)),
+                _ => None,
+            },
+        ));
+        current_key = key;
+    }
+
+    // Finalize the nodes remaining in the stack, the last one being the root node.
+    while let Some(node) = stack.pop() {
+        let parent_depth = stack.last().map(|parent| parent.depth);
+        finalize_ordered_entries_node(
+            node,
+            parent_depth,
+            &current_key,
+            &keys_to_prove,
+            keep_children_node_values,
+            &mut stack,
+            &mut proof_builder,
+        );
+    }
+
+    debug_assert_eq!(proof_builder.missing_node_values().count(), 0);
+    proof_builder
+}
+
+/// Node of the trie built by [`build_from_ordered_trie_entries`].
+struct OrderedEntriesNode {
+    /// Number of nibbles of the full key of the node.
+    depth: usize,
+    /// Storage value of the node, if any.
+    storage_value: Option<Vec<u8>>,
+    /// Hash of [`OrderedEntriesNode::storage_value`] if the storage value must be hashed in the
+    /// node value.
+    storage_value_hash: Option<[u8; 32]>,
+    /// Merkle values of the children of the node that have already been finalized.
+    children: [Option<trie_node::MerkleValueOutput>; 16],
+    /// Node values of the children of the node that have already been finalized. Always empty
+    /// if no key to prove requires including children.
+    children_node_values: Vec<OrderedEntriesChildNodeValue>,
+    /// If `true`, the children of the node must be included in the proof.
+    include_children: bool,
+}
+
+impl OrderedEntriesNode {
+    fn new(
+        depth: usize,
+        storage_value: Option<Vec<u8>>,
+        storage_value_hash: Option<[u8; 32]>,
+    ) -> Self {
+        OrderedEntriesNode {
+            depth,
+            storage_value,
+            storage_value_hash,
+            children: Default::default(),
+            children_node_values: Vec::new(),
+            include_children: false,
+        }
+    }
+}
+
+/// Node value of a child of a [`OrderedEntriesNode`], to pass to
+/// [`ProofBuilder::set_node_value`] if the child must be included in the proof.
+struct OrderedEntriesChildNodeValue {
+    /// Full key of the child.
+    key: Vec<Nibble>,
+    /// Node value of the child.
+    node_value: Vec<u8>,
+    /// Storage value of the child if it is hashed in the node value.
+    unhashed_storage_value: Option<Vec<u8>>,
+}
+
+/// Calculates the Merkle value of `node` and stores it in its parent, which must be the last
+/// element of `stack`. `parent_depth` must be `None` if `node` is the root node.
+///
+/// If `node` is part of the path towards one of the keys in `keys_to_prove`, its node value is
+/// also added to `proof_builder`.
+fn finalize_ordered_entries_node(
+    node: OrderedEntriesNode,
+    parent_depth: Option<usize>,
+    current_key: &[Nibble],
+    keys_to_prove: &[(Vec<Nibble>, bool)],
+    keep_children_node_values: bool,
+    stack: &mut [OrderedEntriesNode],
+    proof_builder: &mut ProofBuilder,
+) {
+    let decoded = trie_node::Decoded {
+        partial_key: current_key[parent_depth.map_or(0, |d| d + 1)..node.depth]
+            .iter()
+            .copied(),
+        children: array::from_fn(|nibble| node.children[nibble].as_ref()),
+        storage_value: match (&node.storage_value_hash, &node.storage_value) {
+            (Some(hash), _) => trie_node::StorageValue::Hashed(hash),
+            (None, Some(value)) => trie_node::StorageValue::Unhashed(value),
+            (None, None) => trie_node::StorageValue::None,
+        },
+    };
+
+    // The traversal from the root towards a key reaches the node if the key goes through the
+    // parent of the node then through the child slot of the parent in which the node is
+    // located.
+    let reached_by = |key: &[Nibble]| match parent_depth {
+        None => true,
+        Some(parent_depth) => {
+            key.len() > parent_depth && key[..=parent_depth] == current_key[..=parent_depth]
+        }
+    };
+
+    // The traversal towards a key stops at the node if the node is at this key, if the key
+    // diverges from the key of the node, or if the node has no child in the direction of
+    // the key.
+    let is_found_by = |key: &[Nibble]| {
+        reached_by(key)
+            && (key.len() <= node.depth
+                || key[..node.depth] != current_key[..node.depth]
+                || node.children[usize::from(u8::from(key[node.depth]))].is_none())
+    };
+
+    let in_proof = keys_to_prove.iter().any(|(key, _)| reached_by(key));
+
+    // If the traversal towards a key stops at this node, the children of this node and the
+    // children of its parent (including this node) must be included.
+    let is_found_with_children = keys_to_prove
+        .iter()
+        .any(|(key, include_children)| *include_children && is_found_by(key));
+
+    if node.include_children || is_found_with_children {
+        for child in &node.children_node_values {
+            proof_builder.set_node_value(
+                &child.key,
+                &child.node_value,
+                child.unhashed_storage_value.as_deref(),
+            );
+        }
+    }
+
+    let unhashed_storage_value = if node.storage_value_hash.is_some() {
+        node.storage_value.as_deref()
+    } else {
+        None
+    };
+
+    if in_proof || (keep_children_node_values && parent_depth.is_some()) {
+        // `encode_to_vec` can only fail if the node has no children and no storage value,
+        // which can't happen in a trie.
+        let node_value = trie_node::encode_to_vec(decoded.clone()).unwrap();
+        if in_proof {
+            proof_builder.set_node_value(
+                &current_key[..node.depth],
+                &node_value,
+                unhashed_storage_value,
+            );
+        }
+        if keep_children_node_values && parent_depth.is_some() {
+            let parent = stack.last_mut().unwrap();
+            parent
+                .children_node_values
+                .push(OrderedEntriesChildNodeValue {
+                    key: current_key[..node.depth].to_vec(),
+                    node_value,
+                    unhashed_storage_value: unhashed_storage_value.map(|v| v.to_vec()),
+                });
+            parent.include_children |= is_found_with_children;
+        }
+    }
+
+    let merkle_value = trie_node::calculate_merkle_value(decoded, parent_depth.is_none()).unwrap();
+    if let Some(parent_depth) = parent_depth {
+        let parent = stack.last_mut().unwrap();
+        debug_assert_eq!(parent.depth, parent_depth);
+        parent.children[usize::from(u8::from(current_key[parent_depth]))] = Some(merkle_value);
+    }
+}
+
+/// Node of the trie built by [`build_from_trie_entries`].
+#[derive(Default)]
+struct TrieEntriesNode {
+    /// Storage value of the node, if any.
+    storage_value: Option<Vec<u8>>,
+    /// Hash of [`TrieEntriesNode::storage_value`] if the storage value must be hashed in the
+    /// node value.
+    storage_value_hash: Option<[u8; 32]>,
+    /// Merkle value of the node. `None` if not calculated yet.
+    merkle_value: Option<trie_node::MerkleValueOutput>,
+}
+
+/// Calls `f` with the decoded node value of the given node.
+///
+/// # Panic
+///
+/// Panics if the Merkle value of any of the children of the node isn't known.
+///
+fn with_trie_entries_node_decoded<T>(
+    node: &mut trie_structure::NodeAccess<TrieEntriesNode>,
+    f: impl FnOnce(trie_node::Decoded<vec::IntoIter<Nibble>, trie_node::MerkleValueOutput>) -> T,
+) -> T {
+    let children = array::from_fn(|nibble| {
+        let nibble = nibble::Nibble::try_from(u8::try_from(nibble).unwrap()).unwrap();
+        node.child_user_data(nibble)
+            .map(|child| child.merkle_value.clone().unwrap())
+    });
+    let partial_key = node.partial_key().collect::<Vec<_>>().into_iter();
+
+    let user_data = node.user_data();
+    let storage_value = match (&user_data.storage_value_hash, &user_data.storage_value) {
+        (Some(hash), _) => trie_node::StorageValue::Hashed(hash),
+        (None, Some(value)) => trie_node::StorageValue::Unhashed(value),
+        (None, None) => trie_node::StorageValue::None,
+    };
+
+    f(trie_node::Decoded {
+        partial_key,
+        children,
+        storage_value,
+    })
+}
+
 fn blake2_hash(data: &[u8]) -> [u8; 32] {
     <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes()).unwrap()
 }
@@ -611,6 +1081,116 @@ mod tests {
         }
     }
 
+    #[test]
+    fn build_from_trie_entries_works() {
+        let entries = [
+            (&b"foo"[..], &b"bar"[..]),
+            (
+                &b"fool"[..],
+                &b"a storage value longer than thirty-two bytes"[..],
+            ),
+            (&b"fez"[..], &b"baz"[..]),
+            (&b"abc"[..], &b"def"[..]),
+        ];
+
+        let trie_root_hash = {
+            let mut calculation = super::super::calculate_root::root_merkle_value(None);
+            loop {
+                match calculation {
+                    super::super::calculate_root::RootMerkleValueCalculation::Finished {
+                        hash,
+                        ..
+                    } => break hash,
+                    super::super::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                        calculation = keys.inject(entries.iter().map(|(k, _)| k.iter().copied()));
+                    }
+                    super::super::calculate_root::RootMerkleValueCalculation::StorageValue(
+                        value_request,
+                    ) => {
+                        let key = value_request.key().collect::<Vec<u8>>();
+                        let value = entries.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+                        calculation = value_request
+                            .inject(value.map(|v| (v, super::super::TrieEntryVersion::V1)));
+                    }
+                }
+            }
+        };
+
+        let proof_builder = super::build_from_trie_entries(
+            entries
+                .iter()
+                .map(|(k, v)| (k, v, super::super::TrieEntryVersion::V1)),
+            [(&b"fool"[..], false), (&b"abd"[..], false)].into_iter(),
+        );
+        assert_eq!(proof_builder.trie_root_hash(), Some(trie_root_hash));
+
+        let decoded = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: proof_builder.build_to_vec(),
+        })
+        .unwrap();
+
+        assert_eq!(
+            decoded.storage_value(b"fool").unwrap().unwrap().0,
+            &b"a storage value longer than thirty-two bytes"[..]
+        );
+        assert_eq!(decoded.storage_value(b"foo").unwrap().unwrap().0, b"bar");
+        assert!(decoded.storage_value(b"abd").unwrap().is_none());
+    }
+
+    #[test]
+    fn build_from_ordered_trie_entries_matches_unordered() {
+        for _ in 0..500 {
+            // Generate a random trie. Keys are short and made of few different bytes in order
+            // to generate lots of branch nodes.
+            let mut entries = alloc::collections::BTreeMap::new();
+            for _ in 0..Uniform::new_inclusive(0, 32).sample(&mut rand::thread_rng()) {
+                let key = (0..Uniform::new_inclusive(0, 4).sample(&mut rand::thread_rng()))
+                    .map(|_| Uniform::new_inclusive(0, 2).sample(&mut rand::thread_rng()) * 0x11)
+                    .collect::<Vec<u8>>();
+                let value = (0..Uniform::new_inclusive(0, 40).sample(&mut rand::thread_rng()))
+                    .map(|_| Uniform::new_inclusive(0, 255).sample(&mut rand::thread_rng()))
+                    .collect::<Vec<u8>>();
+                entries.insert(key, value);
+            }
+
+            // Prove a mix of keys that are and aren't in the trie.
+            let keys_to_prove = (0..Uniform::new_inclusive(0, 4).sample(&mut rand::thread_rng()))
+                .map(|_| {
+                    let key = (0..Uniform::new_inclusive(0, 5).sample(&mut rand::thread_rng()))
+                        .map(|_| {
+                            Uniform::new_inclusive(0, 3).sample(&mut rand::thread_rng()) * 0x11
+                        })
+                        .collect::<Vec<u8>>();
+                    (key, rand::random::<bool>())
+                })
+                .collect::<Vec<_>>();
+
+            let unordered = super::build_from_trie_entries(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k, v, super::super::TrieEntryVersion::V1)),
+                keys_to_prove.iter().map(|(k, c)| (k, *c)),
+            );
+            let ordered = super::build_from_ordered_trie_entries(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k, v, super::super::TrieEntryVersion::V1)),
+                keys_to_prove.iter().map(|(k, c)| (k, *c)),
+            );
+
+            assert_eq!(unordered.trie_root_hash(), ordered.trie_root_hash());
+
+            let unordered = unordered.build_to_vec();
+            let ordered = ordered.build_to_vec();
+            let mut unordered = proof_decode::decode_proof_entries(&unordered).unwrap();
+            let mut ordered = proof_decode::decode_proof_entries(&ordered).unwrap();
+            unordered.sort_unstable();
+            ordered.sort_unstable();
+            assert_eq!(unordered, ordered);
+        }
+    }
+
     #[test]
     fn identical_nodes_deduplicated() {
         let mut proof_builder = super::ProofBuilder::new();
@@ -78,6 +78,10 @@ pub struct Config<'a, TBody> {
     /// >           "off", `1` for "error", `2` for "warn", `3` for "info", `4` for "debug",
     /// >           and `5` for "trace".
     pub max_log_level: u32,
+
+    /// If `Some`, the host functions called by the runtime while verifying the block are
+    /// recorded. The profiler is then returned in [`Success::host_functions_profiler`].
+    pub host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
 }
 
 /// Extra items of [`Config`] that are dependant on the consensus engine of the chain.
@@ -137,6 +141,10 @@ pub struct Success {
 
     /// Concatenation of all the log messages printed by the runtime.
     pub logs: String,
+
+    /// Value passed through [`Config::host_functions_profiler`], containing the host functions
+    /// called during the verification.
+    pub host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
 }
 
 /// Extra items in [`Success`] relevant to the consensus engine.
@@ -372,7 +380,9 @@ pub fn verify(
             main_trie_root_calculation_cache: config.main_trie_root_calculation_cache,
             storage_main_trie_changes: Default::default(),
             offchain_storage_changes: Default::default(),
+            storage_proof_recorder: None,
             max_log_level: config.max_log_level,
+            host_functions_profiler: config.host_functions_profiler,
         });
 
         match vm {
@@ -452,7 +462,9 @@ impl VerifyInner {
                             ),
                             storage_main_trie_changes: success.storage_main_trie_changes,
                             offchain_storage_changes: success.offchain_storage_changes,
+                            storage_proof_recorder: None,
                             max_log_level: 0,
+                            host_functions_profiler: success.host_functions_profiler,
                         });
 
                         match vm {
@@ -524,6 +536,7 @@ impl VerifyInner {
                                 state_trie_version: success.state_trie_version,
                                 main_trie_root_calculation_cache: success
                                     .main_trie_root_calculation_cache,
+                                host_functions_profiler: success.host_functions_profiler,
                             });
                         }
                     }
@@ -537,6 +550,7 @@ impl VerifyInner {
                         offchain_storage_changes: success.offchain_storage_changes,
                         main_trie_root_calculation_cache: success.main_trie_root_calculation_cache,
                         logs: success.logs,
+                        host_functions_profiler: success.host_functions_profiler,
                     }));
                 }
                 runtime_host::RuntimeHostVm::StorageGet(inner) => {
@@ -668,6 +682,7 @@ pub struct RuntimeCompilation {
     logs: String,
     heap_pages: vm::HeapPages,
     consensus_success: SuccessConsensus,
+    host_functions_profiler: Option<runtime_host::HostFunctionsProfiler>,
 }
 
 impl RuntimeCompilation {
@@ -686,6 +701,7 @@ impl RuntimeCompilation {
             module: code,
             heap_pages: self.heap_pages,
             exec_hint: vm::ExecHint::CompileAheadOfTime,
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
         }) {
             Ok(vm) => vm,
@@ -706,6 +722,7 @@ impl RuntimeCompilation {
             offchain_storage_changes: self.offchain_storage_changes,
             main_trie_root_calculation_cache: self.main_trie_root_calculation_cache,
             logs: self.logs,
+            host_functions_profiler: self.host_functions_profiler,
         }))
     }
 }
@@ -54,6 +54,7 @@ fn main() {
             // If `true`, the chain will not be able to handle JSON-RPC requests. This can be used
             // to save up some resources.
             disable_json_rpc: false,
+            telemetry_node_name: None,
 
             // This field is necessary only if adding a parachain.
             potential_relay_chains: iter::empty(),
@@ -0,0 +1,324 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background authority discovery service.
+//!
+//! The [`AuthorityDiscoveryService`] spawns a background task that regularly determines the list
+//! of authorities of the chain by calling the runtime of the finalized block, then requests the
+//! records of the authorities whose addresses aren't known yet from the peers the network service
+//! is connected to. See the [`smoldot::network::authority_discovery`] module for more
+//! information.
+//!
+//! The addresses found are passed to [`network_service::NetworkService::discover`], alongside
+//! with the nodes closer to the records that the peers report. The authorities aren't otherwise
+//! dialed or treated differently from the other nodes of the network.
+//!
+//! Contrary to a full node, the light client never publishes any record.
+
+use crate::{network_service, platform::Platform, runtime_service};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::{
+    iter,
+    num::{NonZeroU32, NonZeroUsize},
+    time::Duration,
+};
+use futures::prelude::*;
+use smoldot::{
+    executor::{host, read_only_runtime_host},
+    header,
+    network::{authority_discovery, protocol},
+};
+
+/// Delay between the start of the service and the first round of discovery. Gives the network
+/// service some time to connect to peers.
+const FIRST_ROUND_DELAY: Duration = Duration::from_secs(30);
+
+/// Delay between two rounds of discovery.
+const ROUND_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of peers a record is requested from.
+const MAX_TARGETS: usize = 8;
+
+/// Configuration for an [`AuthorityDiscoveryService`].
+pub struct Config<TPlat: Platform> {
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Closure that spawns background tasks.
+    pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
+
+    /// Service responsible for performing runtime calls.
+    pub runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+
+    /// Access to the network, and index of the chain to discover the authorities of from the
+    /// point of view of the network service.
+    pub network_service: (Arc<network_service::NetworkService<TPlat>>, usize),
+}
+
+/// See [the module-level documentation](..).
+pub struct AuthorityDiscoveryService {
+    /// Handle connected to the background task. Makes it possible to abort it.
+    abort_handle: future::AbortHandle,
+}
+
+impl AuthorityDiscoveryService {
+    /// Builds a new service.
+    pub fn new<TPlat: Platform>(mut config: Config<TPlat>) -> Self {
+        let log_target = format!("authority-discovery-{}", config.log_name);
+
+        let (task, abort_handle) = future::abortable(background_task(
+            log_target.clone(),
+            config.runtime_service,
+            config.network_service.0,
+            config.network_service.1,
+        ));
+        (config.tasks_executor)(log_target, Box::pin(task.map(|_| ())));
+
+        AuthorityDiscoveryService { abort_handle }
+    }
+}
+
+impl Drop for AuthorityDiscoveryService {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+/// Background task of the service. Never ends.
+async fn background_task<TPlat: Platform>(
+    log_target: String,
+    runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+    network_service: Arc<network_service::NetworkService<TPlat>>,
+    chain_index: usize,
+) {
+    let mut authority_discovery = authority_discovery::AuthorityDiscovery::new();
+    let mut next_round = TPlat::sleep(FIRST_ROUND_DELAY);
+
+    loop {
+        next_round.await;
+        next_round = TPlat::sleep(ROUND_INTERVAL);
+
+        match finalized_block_authorities(&runtime_service).await {
+            Ok(authorities) => authority_discovery.set_authorities(authorities.into_iter()),
+            Err(err) => {
+                // Chains that don't use authority discovery don't have the runtime function.
+                log::debug!(target: &log_target, "Authorities <= Error({})", err);
+                continue;
+            }
+        }
+
+        let unresolved = authority_discovery
+            .unresolved_authorities()
+            .copied()
+            .collect::<Vec<_>>();
+        for authority in unresolved {
+            let key = authority_discovery::dht_key(&authority);
+            let targets = network_service
+                .peers_list()
+                .await
+                .take(MAX_TARGETS)
+                .collect::<Vec<_>>();
+            let responses = future::join_all(targets.into_iter().map(|target| {
+                network_service
+                    .clone()
+                    .kademlia_get_value_request(chain_index, target, &key)
+            }))
+            .await;
+
+            let mut values = Vec::new();
+            for response in responses {
+                let Ok(protocol::GetValueResponse {
+                    value,
+                    closer_peers,
+                }) = response
+                else {
+                    continue;
+                };
+
+                network_service
+                    .discover(&TPlat::now(), chain_index, closer_peers, false)
+                    .await;
+                values.extend(value);
+            }
+
+            for value in values {
+                match authority_discovery.inject_record(&authority, &value) {
+                    Ok(()) => {
+                        let (peer_id, addresses) =
+                            authority_discovery.authority_addresses(&authority).unwrap();
+                        log::debug!(
+                            target: &log_target,
+                            "Authorities => Resolved(peer_id={}, num_addresses={})",
+                            peer_id,
+                            addresses.len()
+                        );
+                        network_service
+                            .discover(
+                                &TPlat::now(),
+                                chain_index,
+                                iter::once((peer_id.clone(), addresses.to_vec())),
+                                false,
+                            )
+                            .await;
+                        break;
+                    }
+                    Err(err) => {
+                        log::debug!(target: &log_target, "Authorities => BadRecord({})", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Calls [`authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME`] on the current finalized
+/// block of the runtime service.
+async fn finalized_block_authorities<TPlat: Platform>(
+    runtime_service: &runtime_service::RuntimeService<TPlat>,
+) -> Result<Vec<[u8; 32]>, AuthoritiesError> {
+    let subscription = runtime_service
+        .subscribe_all("authority-discovery", 16, NonZeroUsize::new(32).unwrap())
+        .await;
+    let finalized_block_hash =
+        header::hash_from_scale_encoded_header(&subscription.finalized_block_scale_encoded_header);
+
+    let runtime_lock = match runtime_service
+        .pinned_block_runtime_lock(subscription.new_blocks.id(), &finalized_block_hash)
+        .await
+    {
+        Ok(l) => l,
+        Err(runtime_service::PinnedBlockRuntimeLockError::ObsoleteSubscription) => {
+            return Err(AuthoritiesError::ObsoleteSubscription)
+        }
+    };
+
+    let output = runtime_lock
+        .run(
+            authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME,
+            iter::empty::<&[u8]>(),
+            3,
+            Duration::from_secs(10),
+            NonZeroU32::new(1).unwrap(),
+            |runtime_call_lock, virtual_machine| {
+                let mut runtime_call =
+                    match read_only_runtime_host::run(read_only_runtime_host::Config {
+                        virtual_machine,
+                        function_to_call: authority_discovery::AUTHORITIES_RUNTIME_FUNCTION_NAME,
+                        parameter: iter::empty::<&[u8]>(),
+                        max_log_level: 0,
+                    }) {
+                        Ok(vm) => vm,
+                        Err((err, prototype)) => {
+                            return (
+                                prototype,
+                                Err(runtime_service::RunAttemptError::Other(
+                                    AuthoritiesError::StartError(err),
+                                )),
+                            )
+                        }
+                    };
+
+                loop {
+                    match runtime_call {
+                        read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                            let output = authority_discovery::decode_authorities_output(
+                                success.virtual_machine.value().as_ref(),
+                            )
+                            .map_err(|err| {
+                                runtime_service::RunAttemptError::Other(
+                                    AuthoritiesError::InvalidRuntimeOutput(err),
+                                )
+                            });
+                            return (success.virtual_machine.into_prototype(), output);
+                        }
+                        read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                            return (
+                                error.prototype,
+                                Err(runtime_service::RunAttemptError::Other(
+                                    AuthoritiesError::ReadOnlyRuntime(error.detail),
+                                )),
+                            )
+                        }
+                        read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                            let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
+                            let storage_value = match storage_value {
+                                Ok(v) => v.map(|(v, _)| v),
+                                Err(_) => {
+                                    let key = get.key().as_ref().to_vec();
+                                    return (
+                                        read_only_runtime_host::RuntimeHostVm::StorageGet(get)
+                                            .into_prototype(),
+                                        Err(runtime_service::RunAttemptError::MissingStorageValue(
+                                            key,
+                                        )),
+                                    );
+                                }
+                            };
+                            runtime_call = get.inject_value(storage_value.map(iter::once));
+                        }
+                        read_only_runtime_host::RuntimeHostVm::NextKey(nk) => {
+                            return (
+                                read_only_runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
+                                Err(runtime_service::RunAttemptError::Other(
+                                    AuthoritiesError::NextKeyForbidden,
+                                )),
+                            );
+                        }
+                        read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                            runtime_call =
+                                storage_root.resume(runtime_call_lock.block_storage_root());
+                        }
+                        read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                            runtime_call = sig.verify_and_resume();
+                        }
+                    }
+                }
+            },
+        )
+        .await;
+
+    match output {
+        Ok(authorities) => Ok(authorities),
+        Err(runtime_service::RunError::Call(error)) => Err(AuthoritiesError::Call(error)),
+        Err(runtime_service::RunError::Other(error)) => Err(error),
+    }
+}
+
+/// Error that can happen when fetching the list of authorities.
+#[derive(Debug, derive_more::Display)]
+enum AuthoritiesError {
+    /// Error while performing call request over the network.
+    #[display(fmt = "Error while performing call request over the network: {_0}")]
+    Call(runtime_service::RuntimeCallError),
+    /// Error while starting virtual machine to verify call proof.
+    #[display(fmt = "Error while starting virtual machine to verify call proof: {_0}")]
+    StartError(host::StartErr),
+    /// Error during the execution of the virtual machine to verify call proof.
+    #[display(fmt = "Error during the call proof verification: {_0}")]
+    ReadOnlyRuntime(read_only_runtime_host::ErrorDetail),
+    /// Error while decoding the output of the call.
+    #[display(fmt = "Error while decoding the output of the call: {_0}")]
+    InvalidRuntimeOutput(authority_discovery::DecodeAuthoritiesError),
+    /// Fetching following keys is not supported by call proofs.
+    NextKeyForbidden,
+    /// Runtime service subscription is no longer valid.
+    ObsoleteSubscription,
+}
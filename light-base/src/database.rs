@@ -25,6 +25,18 @@
 //! This database doesn't contain just the state of the finalized block, but also other
 //! information. See [`DatabaseContent`].
 //!
+//! The database can optionally contain the runtime code of the finalized block, alongside with
+//! a Merkle proof of this code and the header of the block this proof has been generated
+//! against. This makes it possible to skip downloading the runtime when the light client
+//! restarts. The proof is the one the runtime service has obtained when downloading the runtime,
+//! and encoding a database never performs any networking request.
+//!
+//! The block the proof has been generated against is the finalized block or one of its
+//! ancestors. Because the database doesn't contain the headers of the blocks between this block
+//! and the finalized block, the proof can't be verified against the finalized block itself.
+//! The runtime code is thus exactly as trustworthy as the rest of the database, whose content
+//! (notably the finalized block) is trusted as well.
+//!
 //! This module provides the function to encode and decode this so-called database.
 
 use alloc::{
@@ -37,10 +49,12 @@ use core::cmp;
 use smoldot::{
     chain,
     database::finalized_serialize,
+    header,
     libp2p::{multiaddr, PeerId},
+    trie::proof_decode,
 };
 
-use crate::{network_service, platform, sync_service};
+use crate::{network_service, platform, runtime_service, sync_service};
 
 /// A decoded database.
 pub struct DatabaseContent {
@@ -51,48 +65,117 @@ pub struct DatabaseContent {
     /// List of nodes that were known to be part of the peer-to-peer network when the database
     /// was encoded.
     pub known_nodes: Vec<(PeerId, Vec<multiaddr::Multiaddr>)>,
+    /// Runtime of the finalized block of [`DatabaseContent::chain_information`], if it was
+    /// included in the database. Its content has been verified against the state root of the
+    /// block whose header is in [`DatabaseContentRuntimeCodeHint::proof_block_header`].
+    pub runtime_code_hint: Option<DatabaseContentRuntimeCodeHint>,
+}
+
+/// See [`DatabaseContent::runtime_code_hint`].
+pub struct DatabaseContentRuntimeCodeHint {
+    /// Storage value of the `:code` key.
+    pub code: Vec<u8>,
+    /// Storage value of the `:heappages` key, or `None` if there is no entry in the storage.
+    pub heap_pages: Option<Vec<u8>>,
+    /// SCALE-encoded header of the block the proof has been generated against. Its number is
+    /// inferior or equal to the one of the finalized block.
+    pub proof_block_header: Vec<u8>,
+    /// Merkle proof of the `:code` and `:heappages` keys.
+    pub proof: Vec<u8>,
 }
 
 /// Serializes the finalized state of the chain, using the given services.
 ///
 /// The returned string is guaranteed to not exceed `max_size` bytes. A truncated or invalid
 /// database is intentionally returned if `max_size` is too low to fit all the information.
+///
+/// The runtime code of the finalized block is included if the runtime service holds a proof of
+/// it. No networking request is performed.
 pub async fn encode_database<TPlat: platform::Platform>(
     network_service: &network_service::NetworkService<TPlat>,
     sync_service: &sync_service::SyncService<TPlat>,
+    runtime_service: &runtime_service::RuntimeService<TPlat>,
     genesis_block_hash: &[u8; 32],
     max_size: usize,
+) -> String {
+    let chain_information = match sync_service.serialize_chain_information().await {
+        Some(ci) => ci,
+        None => {
+            // If the chain information can't be obtained, we just return a dummy value that
+            // will intentionally fail to decode if passed back.
+            let dummy_message = "<unknown>";
+            return if dummy_message.len() > max_size {
+                String::new()
+            } else {
+                dummy_message.to_owned()
+            };
+        }
+    };
+
+    // The runtime service might lag behind the sync service. Its runtime is only included if
+    // it is the one of the finalized block of `chain_information`.
+    let finalized_block_hash = chain_information
+        .as_ref()
+        .finalized_block_header
+        .hash(sync_service.block_number_bytes());
+    let runtime_code_proof = runtime_service
+        .finalized_runtime_code_proof()
+        .await
+        .filter(|proof| proof.finalized_block_hash == finalized_block_hash);
+
+    let known_nodes = network_service
+        .discovered_nodes(0) // TODO: hacky chain_index
+        .await
+        .map(|(peer_id, addrs)| (peer_id, addrs.collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+
+    encode_database_content(
+        genesis_block_hash,
+        &chain_information,
+        sync_service.block_number_bytes(),
+        &known_nodes,
+        runtime_code_proof.as_ref().map(|proof| {
+            (
+                &proof.proof_block_scale_encoded_header[..],
+                &proof.proof[..],
+            )
+        }),
+        max_size,
+    )
+}
+
+/// Serializes the given information. See [`encode_database`].
+///
+/// `runtime_code_proof` contains the SCALE-encoded header of a block and a Merkle proof of the
+/// `:code` and `:heappages` keys against the state root of this block.
+fn encode_database_content(
+    genesis_block_hash: &[u8; 32],
+    chain_information: &chain::chain_information::ValidChainInformation,
+    block_number_bytes: usize,
+    known_nodes: &[(PeerId, Vec<multiaddr::Multiaddr>)],
+    runtime_code_proof: Option<(&[u8], &[u8])>,
+    max_size: usize,
 ) -> String {
     // Craft the structure containing all the data that we would like to include.
     let mut database_draft = SerdeDatabase {
         genesis_hash: hex::encode(genesis_block_hash),
-        chain: match sync_service.serialize_chain_information().await {
-            Some(ci) => {
-                let encoded =
-                    finalized_serialize::encode_chain(&ci, sync_service.block_number_bytes());
-                serde_json::from_str(&encoded).unwrap()
-            }
-            None => {
-                // If the chain information can't be obtained, we just return a dummy value that
-                // will intentionally fail to decode if passed back.
-                let dummy_message = "<unknown>";
-                return if dummy_message.len() > max_size {
-                    String::new()
-                } else {
-                    dummy_message.to_owned()
-                };
-            }
+        chain: {
+            let encoded = finalized_serialize::encode_chain(chain_information, block_number_bytes);
+            serde_json::from_str(&encoded).unwrap()
         },
-        nodes: network_service
-            .discovered_nodes(0) // TODO: hacky chain_index
-            .await
+        nodes: known_nodes
+            .iter()
             .map(|(peer_id, addrs)| {
                 (
                     peer_id.to_base58(),
-                    addrs.map(|a| a.to_string()).collect::<Vec<_>>(),
+                    addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
                 )
             })
             .collect(),
+        runtime_code: runtime_code_proof.map(|(block_header, proof)| SerdeRuntimeCode {
+            block_header: hex::encode(block_header),
+            proof: hex::encode(proof),
+        }),
     };
 
     // Cap the database length to the maximum size.
@@ -103,6 +186,14 @@ pub async fn encode_database<TPlat: platform::Platform>(
             return serialized;
         }
 
+        // Try to reduce the size of the database.
+
+        // The runtime code is by far the largest item and is removed first.
+        if database_draft.runtime_code.is_some() {
+            database_draft.runtime_code = None;
+            continue;
+        }
+
         if database_draft.nodes.is_empty() {
             // Can't shrink the database anymore. Return the string `"<too-large>"` which will
             // fail to decode but will indicate what is wrong.
@@ -114,8 +205,6 @@ pub async fn encode_database<TPlat: platform::Platform>(
             };
         }
 
-        // Try to reduce the size of the database.
-
         // Remove half of the nodes.
         // Which nodes are removed doesn't really matter.
         let mut nodes_to_remove = cmp::max(1, database_draft.nodes.len() / 2);
@@ -165,10 +254,50 @@ pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<Datab
         })
         .collect::<Vec<_>>();
 
+    // The runtime code is verified against the state root of the block the proof has been
+    // generated against, which can't be more recent than the finalized block. A runtime code
+    // that fails to verify is ignored rather than making the entire database invalid, as the
+    // rest of the database remains usable.
+    let runtime_code_hint = decoded.runtime_code.as_ref().and_then(|runtime_code| {
+        let proof_block_header = hex::decode(&runtime_code.block_header).ok()?;
+        let proof = hex::decode(&runtime_code.proof).ok()?;
+
+        let finalized_block_header = &chain_information.as_ref().finalized_block_header;
+        let decoded_header = header::decode(&proof_block_header, block_number_bytes).ok()?;
+        if decoded_header.number > finalized_block_header.number
+            || (decoded_header.number == finalized_block_header.number
+                && header::hash_from_scale_encoded_header(&proof_block_header)
+                    != finalized_block_header.hash(block_number_bytes))
+        {
+            return None;
+        }
+
+        let decoded_proof = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            proof: &proof[..],
+            trie_root_hash: decoded_header.state_root,
+        })
+        .ok()?;
+
+        let (code, _) = decoded_proof.storage_value(b":code")??;
+        let heap_pages = decoded_proof
+            .storage_value(b":heappages")?
+            .map(|(value, _)| value.to_vec());
+        let code = code.to_vec();
+        drop(decoded_proof);
+
+        Some(DatabaseContentRuntimeCodeHint {
+            code,
+            heap_pages,
+            proof_block_header,
+            proof,
+        })
+    });
+
     Ok(DatabaseContent {
         genesis_block_hash,
         chain_information,
         known_nodes,
+        runtime_code_hint,
     })
 }
 
@@ -179,4 +308,179 @@ struct SerdeDatabase {
     genesis_hash: String,
     chain: Box<serde_json::value::RawValue>,
     nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    /// Runtime code of the finalized block, if known.
+    #[serde(
+        rename = "runtimeCode",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    runtime_code: Option<SerdeRuntimeCode>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeRuntimeCode {
+    /// Hexadecimal-encoded SCALE-encoded header of the block the proof has been generated
+    /// against. Has no `0x` prefix.
+    #[serde(rename = "blockHeader")]
+    block_header: String,
+    /// Hexadecimal-encoded Merkle proof of the `:code` and `:heappages` keys of the storage of
+    /// the block. Has no `0x` prefix.
+    proof: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use smoldot::{chain_spec, header, libp2p::peer_id, trie};
+
+    fn test_chain() -> (
+        smoldot::chain::chain_information::ValidChainInformation,
+        usize,
+        Vec<u8>,
+        Vec<u8>,
+        Option<Vec<u8>>,
+    ) {
+        let chain_spec = chain_spec::ChainSpec::from_json_bytes(
+            &include_bytes!("../../demo-chain-specs/substrate-node-template.json")[..],
+        )
+        .unwrap();
+        let block_number_bytes = usize::from(chain_spec.block_number_bytes());
+        let (chain_information, _) = chain_spec.as_chain_information().unwrap();
+
+        let genesis_storage = match chain_spec.genesis_storage() {
+            chain_spec::GenesisStorage::Items(items) => items,
+            chain_spec::GenesisStorage::TrieRootHash(_) => unreachable!(),
+        };
+        let code = genesis_storage.value(b":code").unwrap().to_vec();
+        let heap_pages = genesis_storage.value(b":heappages").map(|v| v.to_vec());
+
+        let mut entries = genesis_storage.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        let proof = trie::proof_encode::build_from_ordered_trie_entries(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, value, trie::TrieEntryVersion::V0)),
+            [(&b":code"[..], false), (&b":heappages"[..], false)].into_iter(),
+        )
+        .build_to_vec();
+
+        (
+            chain_information,
+            block_number_bytes,
+            proof,
+            code,
+            heap_pages,
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let (chain_information, block_number_bytes, proof, code, heap_pages) = test_chain();
+        let finalized_header = chain_information
+            .as_ref()
+            .finalized_block_header
+            .scale_encoding_vec(block_number_bytes);
+        let peer_id = peer_id::PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+        let address = "/ip4/127.0.0.1/tcp/30333".parse().unwrap();
+
+        let encoded = super::encode_database_content(
+            &[0xaa; 32],
+            &chain_information,
+            block_number_bytes,
+            &[(peer_id.clone(), vec![address])],
+            Some((&finalized_header, &proof)),
+            usize::MAX,
+        );
+        let decoded = super::decode_database(&encoded, block_number_bytes).unwrap();
+
+        assert_eq!(decoded.genesis_block_hash, [0xaa; 32]);
+        assert_eq!(
+            decoded
+                .chain_information
+                .as_ref()
+                .finalized_block_header
+                .hash(block_number_bytes),
+            header::hash_from_scale_encoded_header(&finalized_header)
+        );
+        assert_eq!(decoded.known_nodes.len(), 1);
+        assert_eq!(decoded.known_nodes[0].0, peer_id);
+        assert_eq!(decoded.known_nodes[0].1.len(), 1);
+
+        let hint = decoded.runtime_code_hint.unwrap();
+        assert_eq!(hint.code, code);
+        assert_eq!(hint.heap_pages, heap_pages);
+        assert_eq!(hint.proof_block_header, finalized_header);
+        assert_eq!(hint.proof, proof);
+    }
+
+    #[test]
+    fn proof_not_matching_header_ignored() {
+        let (chain_information, block_number_bytes, proof, _, _) = test_chain();
+        let mut header = header::Header::from(chain_information.as_ref().finalized_block_header);
+        header.state_root = [0; 32];
+        let header = header.scale_encoding_vec(block_number_bytes);
+
+        let encoded = super::encode_database_content(
+            &[0xaa; 32],
+            &chain_information,
+            block_number_bytes,
+            &[],
+            Some((&header, &proof)),
+            usize::MAX,
+        );
+        let decoded = super::decode_database(&encoded, block_number_bytes).unwrap();
+        assert!(decoded.runtime_code_hint.is_none());
+    }
+
+    #[test]
+    fn proof_block_after_finalized_ignored() {
+        let (chain_information, block_number_bytes, proof, _, _) = test_chain();
+        let mut header = header::Header::from(chain_information.as_ref().finalized_block_header);
+        header.number += 1;
+        let header = header.scale_encoding_vec(block_number_bytes);
+
+        let encoded = super::encode_database_content(
+            &[0xaa; 32],
+            &chain_information,
+            block_number_bytes,
+            &[],
+            Some((&header, &proof)),
+            usize::MAX,
+        );
+        let decoded = super::decode_database(&encoded, block_number_bytes).unwrap();
+        assert!(decoded.runtime_code_hint.is_none());
+    }
+
+    #[test]
+    fn runtime_code_removed_first_when_too_large() {
+        let (chain_information, block_number_bytes, proof, _, _) = test_chain();
+        let finalized_header = chain_information
+            .as_ref()
+            .finalized_block_header
+            .scale_encoding_vec(block_number_bytes);
+        let peer_id = peer_id::PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+        let nodes = [(peer_id, vec!["/ip4/127.0.0.1/tcp/30333".parse().unwrap()])];
+
+        let without_code = super::encode_database_content(
+            &[0xaa; 32],
+            &chain_information,
+            block_number_bytes,
+            &nodes,
+            None,
+            usize::MAX,
+        );
+
+        let encoded = super::encode_database_content(
+            &[0xaa; 32],
+            &chain_information,
+            block_number_bytes,
+            &nodes,
+            Some((&finalized_header, &proof)),
+            without_code.len(),
+        );
+        assert_eq!(encoded, without_code);
+
+        let decoded = super::decode_database(&encoded, block_number_bytes).unwrap();
+        assert!(decoded.runtime_code_hint.is_none());
+        assert_eq!(decoded.known_nodes.len(), 1);
+    }
 }
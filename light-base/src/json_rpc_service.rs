@@ -43,7 +43,7 @@ use crate::{
     network_service, platform::Platform, runtime_service, sync_service, transactions_service,
 };
 
-use alloc::{boxed::Box, format, string::String, sync::Arc};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
 use core::num::NonZeroU32;
 use futures::prelude::*;
 use smoldot::{
@@ -144,16 +144,44 @@ impl Frontend {
     /// if the requests take a long time to process or if [`Frontend::next_json_rpc_response`]
     /// isn't called often enough. Use [`HandleRpcError::into_json_rpc_error`] to build the
     /// JSON-RPC response to immediately send back to the user.
+    ///
+    /// The request can also be a batch of requests, in which case a single response containing
+    /// the responses to all the elements of the batch will later be generated. Notifications
+    /// within a batch are ignored. A batch that contains more requests than
+    /// [`Config::max_pending_requests`] is always refused.
     pub fn queue_rpc_request(&self, json_rpc_request: String) -> Result<(), HandleRpcError> {
         // If the request isn't even a valid JSON-RPC request, we can't even send back a response.
         // We have no choice but to immediately refuse the request.
-        if let Err(error) = json_rpc::parse::parse_call(&json_rpc_request) {
-            log::warn!(
-                target: &self.log_target,
-                "Refused malformed JSON-RPC request: {}", error
-            );
-            return Err(HandleRpcError::MalformedJsonRpc(error));
-        }
+        let batch = match json_rpc::parse::parse_request(&json_rpc_request) {
+            Ok(json_rpc::parse::Request::Single(_)) => None,
+            Ok(json_rpc::parse::Request::Batch(elements)) => {
+                // The same applies if any element of the batch is invalid.
+                let mut requests = Vec::with_capacity(elements.len());
+                for element in elements {
+                    match json_rpc::parse::parse_call(element) {
+                        Ok(json_rpc::parse::Call { id_json: None, .. }) => {}
+                        Ok(json_rpc::parse::Call {
+                            id_json: Some(_), ..
+                        }) => requests.push(element.to_owned()),
+                        Err(error) => {
+                            log::warn!(
+                                target: &self.log_target,
+                                "Refused JSON-RPC batch containing a malformed request: {}", error
+                            );
+                            return Err(HandleRpcError::MalformedJsonRpc(error));
+                        }
+                    }
+                }
+                Some(requests)
+            }
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Refused malformed JSON-RPC request: {}", error
+                );
+                return Err(HandleRpcError::MalformedJsonRpc(error));
+            }
+        };
 
         // Logging the request before it is queued.
         log::debug!(
@@ -165,21 +193,48 @@ impl Frontend {
             )
         );
 
+        let Some(batch) = batch else {
+            return match self
+                .requests_subscriptions
+                .try_queue_client_request(&self.client_id, json_rpc_request)
+            {
+                Ok(()) => Ok(()),
+                Err(err) => {
+                    log::warn!(
+                        target: &self.log_target,
+                        "Request denied due to JSON-RPC service being overloaded. This will likely \
+                        cause the JSON-RPC client to malfunction."
+                    );
+
+                    Err(HandleRpcError::Overloaded {
+                        json_rpc_request: err.request,
+                    })
+                }
+            };
+        };
+
         match self
             .requests_subscriptions
-            .try_queue_client_request(&self.client_id, json_rpc_request)
+            .try_queue_client_batch(&self.client_id, batch)
         {
             Ok(()) => Ok(()),
-            Err(err) => {
+            Err(requests_subscriptions::TryQueueClientBatchError::TooLarge { requests }) => {
                 log::warn!(
                     target: &self.log_target,
-                    "Request denied due to JSON-RPC service being overloaded. This will likely \
+                    "Batch of {} requests denied due to exceeding the maximum number of pending \
+                    requests.", requests.len()
+                );
+
+                Err(HandleRpcError::Overloaded { json_rpc_request })
+            }
+            Err(requests_subscriptions::TryQueueClientBatchError::QueueFull { .. }) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Batch denied due to JSON-RPC service being overloaded. This will likely \
                     cause the JSON-RPC client to malfunction."
                 );
 
-                Err(HandleRpcError::Overloaded {
-                    json_rpc_request: err.request,
-                })
+                Err(HandleRpcError::Overloaded { json_rpc_request })
             }
         }
     }
@@ -342,22 +397,34 @@ impl HandleRpcError {
     /// Builds the JSON-RPC error string corresponding to this error.
     ///
     /// Returns `None` if the JSON-RPC requests isn't valid JSON-RPC or if the call was a
-    /// notification.
+    /// notification or a batch of notifications.
     pub fn into_json_rpc_error(self) -> Option<String> {
         let json_rpc_request = match self {
             HandleRpcError::Overloaded { json_rpc_request } => json_rpc_request,
             HandleRpcError::MalformedJsonRpc(_) => return None,
         };
 
-        match json_rpc::parse::parse_call(&json_rpc_request) {
-            Ok(json_rpc::parse::Call {
-                id_json: Some(id), ..
-            }) => Some(json_rpc::parse::build_error_response(
-                id,
-                json_rpc::parse::ErrorResponse::ServerError(-32000, "Too busy"),
-                None,
-            )),
-            Ok(json_rpc::parse::Call { id_json: None, .. }) | Err(_) => None,
+        match json_rpc::parse::parse_request(&json_rpc_request) {
+            Ok(json_rpc::parse::Request::Single(call)) => too_busy_error(call),
+            Ok(json_rpc::parse::Request::Batch(elements)) => {
+                json_rpc::parse::build_batch_response(elements.into_iter().filter_map(|element| {
+                    json_rpc::parse::parse_call(element)
+                        .ok()
+                        .and_then(too_busy_error)
+                }))
+            }
+            Err(_) => None,
         }
     }
 }
+
+/// Builds the JSON-RPC error indicating that the JSON-RPC service is too busy, or `None` if the
+/// call is a notification.
+fn too_busy_error(call: json_rpc::parse::Call) -> Option<String> {
+    let id = call.id_json?;
+    Some(json_rpc::parse::build_error_response(
+        id,
+        json_rpc::parse::ErrorResponse::ServerError(-32000, "Too busy"),
+        None,
+    ))
+}
@@ -28,13 +28,16 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter,
+    iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     ops,
     sync::atomic,
     time::Duration,
 };
-use futures::{lock::Mutex, prelude::*};
+use futures::{
+    lock::{Mutex, MutexGuard},
+    prelude::*,
+};
 use hashbrown::HashMap;
 use smoldot::{
     chain::fork_tree,
@@ -47,6 +50,7 @@ use smoldot::{
 
 mod chain_head;
 mod getters;
+mod network;
 mod state_chain;
 mod transactions;
 
@@ -144,6 +148,9 @@ pub(super) enum SubscriptionMessage {
     StopIfChainHeadFollow {
         stop_request_id: (String, requests_subscriptions::RequestId),
     },
+    StopIfNetworkEvents {
+        stop_request_id: (String, requests_subscriptions::RequestId),
+    },
     ChainHeadFollowUnpin {
         hash: methods::HashHexString,
         unpin_request_id: (String, requests_subscriptions::RequestId),
@@ -452,6 +459,7 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::childstate_getStorage { .. }
             | methods::MethodCall::childstate_getStorageHash { .. }
             | methods::MethodCall::childstate_getStorageSize { .. }
+            | methods::MethodCall::grandpa_proveFinality { .. }
             | methods::MethodCall::grandpa_roundState { .. }
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
@@ -646,14 +654,39 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::state_queryStorage {
+                keys,
+                from_block,
+                to_block,
+            } => {
+                self.state_query_storage(
+                    (request_id, &state_machine_request_id),
+                    keys,
+                    from_block,
+                    to_block,
+                )
+                .await;
+            }
             methods::MethodCall::state_queryStorageAt { keys, at } => {
                 self.state_query_storage_at((request_id, &state_machine_request_id), keys, at)
                     .await;
             }
+            methods::MethodCall::grandpa_proveFinality { block_number } => {
+                self.grandpa_prove_finality((request_id, &state_machine_request_id), block_number)
+                    .await;
+            }
+            methods::MethodCall::grandpa_roundState {} => {
+                self.grandpa_round_state((request_id, &state_machine_request_id))
+                    .await;
+            }
             methods::MethodCall::state_getMetadata { hash } => {
                 self.state_get_metadata((request_id, &state_machine_request_id), hash)
                     .await;
             }
+            methods::MethodCall::state_getReadProof { keys, at } => {
+                self.state_get_read_proof((request_id, &state_machine_request_id), keys, at)
+                    .await;
+            }
             methods::MethodCall::state_getStorage { key, hash } => {
                 self.state_get_storage((request_id, &state_machine_request_id), key, hash)
                     .await;
@@ -691,6 +724,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.account_next_index((request_id, &state_machine_request_id), account)
                     .await;
             }
+            methods::MethodCall::system_dryRun { extrinsic, hash } => {
+                self.system_dry_run((request_id, &state_machine_request_id), extrinsic, hash)
+                    .await;
+            }
             methods::MethodCall::system_chain {} => {
                 self.system_chain((request_id, &state_machine_request_id))
                     .await;
@@ -715,6 +752,10 @@ impl<TPlat: Platform> Background<TPlat> {
                 self.system_name((request_id, &state_machine_request_id))
                     .await;
             }
+            methods::MethodCall::system_networkState {} => {
+                self.system_network_state((request_id, &state_machine_request_id))
+                    .await;
+            }
             methods::MethodCall::system_nodeRoles {} => {
                 self.system_node_roles((request_id, &state_machine_request_id))
                     .await;
@@ -884,6 +925,17 @@ impl<TPlat: Platform> Background<TPlat> {
                 )
                 .await;
             }
+            methods::MethodCall::network_unstable_subscribeEvents {} => {
+                self.network_unstable_subscribe_events((request_id, &state_machine_request_id))
+                    .await;
+            }
+            methods::MethodCall::network_unstable_unsubscribeEvents { subscription } => {
+                self.network_unstable_unsubscribe_events(
+                    (request_id, &state_machine_request_id),
+                    &subscription,
+                )
+                .await;
+            }
 
             _method @ (methods::MethodCall::account_nextIndex { .. }
             | methods::MethodCall::author_hasKey { .. }
@@ -896,20 +948,13 @@ impl<TPlat: Platform> Background<TPlat> {
             | methods::MethodCall::childstate_getStorage { .. }
             | methods::MethodCall::childstate_getStorageHash { .. }
             | methods::MethodCall::childstate_getStorageSize { .. }
-            | methods::MethodCall::grandpa_roundState { .. }
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
             | methods::MethodCall::state_getPairs { .. }
-            | methods::MethodCall::state_getReadProof { .. }
             | methods::MethodCall::state_getStorageHash { .. }
             | methods::MethodCall::state_getStorageSize { .. }
-            | methods::MethodCall::state_queryStorage { .. }
             | methods::MethodCall::system_addReservedPeer { .. }
-            | methods::MethodCall::system_dryRun { .. }
-            | methods::MethodCall::system_networkState { .. }
-            | methods::MethodCall::system_removeReservedPeer { .. }
-            | methods::MethodCall::network_unstable_subscribeEvents { .. }
-            | methods::MethodCall::network_unstable_unsubscribeEvents { .. }) => {
+            | methods::MethodCall::system_removeReservedPeer { .. }) => {
                 // TODO: implement the ones that make sense to implement ^
                 log::error!(target: &self.log_target, "JSON-RPC call not supported yet: {:?}", _method);
                 self.requests_subscriptions
@@ -981,6 +1026,80 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Obtains the SCALE-encoded header of the given block, either from the cache of recent
+    /// blocks or, if not found, from the peer-to-peer network.
+    ///
+    /// Returns `Err` if and only if the network request failed.
+    async fn header_query(&self, hash: &[u8; 32]) -> Result<Vec<u8>, ()> {
+        let mut cache_lock = self.cache.lock().await;
+        if let Some(header) = cache_lock.recent_pinned_blocks.get(hash) {
+            Ok(header.clone())
+        } else {
+            // Header isn't known locally. We need to ask the network.
+            // First, try to determine the block number by looking into the cache.
+            // The request can be fulfilled no matter whether it is found, but knowing it will
+            // lead to a better selection of peers, and thus increase the chances of the
+            // requests succeeding.
+            let block_number =
+                if let Some(future) = cache_lock.block_state_root_hashes_numbers.get_mut(hash) {
+                    let _ = future.now_or_never();
+
+                    match future {
+                        future::MaybeDone::Done(Ok((_, num))) => Some(*num),
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+
+            // Release the lock as we're going to start a long asynchronous operation.
+            drop::<MutexGuard<_>>(cache_lock);
+
+            // Actual network query.
+            let result = if let Some(block_number) = block_number {
+                self.sync_service
+                    .clone()
+                    .block_query(
+                        block_number,
+                        *hash,
+                        protocol::BlocksRequestFields {
+                            header: true,
+                            body: false,
+                            justifications: false,
+                        },
+                        3,
+                        Duration::from_secs(8),
+                        NonZeroU32::new(1).unwrap(),
+                    )
+                    .await
+            } else {
+                self.sync_service
+                    .clone()
+                    .block_query_unknown_number(
+                        *hash,
+                        protocol::BlocksRequestFields {
+                            header: true,
+                            body: false,
+                            justifications: false,
+                        },
+                        3,
+                        Duration::from_secs(8),
+                        NonZeroU32::new(1).unwrap(),
+                    )
+                    .await
+            };
+
+            // The `block_query` method guarantees that the header is present and valid.
+            if let Ok(block) = result {
+                let header = block.header.unwrap();
+                debug_assert_eq!(header::hash_from_scale_encoded_header(&header), *hash);
+                Ok(header)
+            } else {
+                Err(())
+            }
+        }
+    }
+
     /// Obtain the state trie root hash and number of the given block, and make sure to put it
     /// in cache.
     async fn state_trie_root_hash(
@@ -1080,6 +1199,28 @@ impl<TPlat: Platform> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        let (values, _) = self
+            .storage_query_with_proof(
+                keys,
+                hash,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await?;
+        Ok(values)
+    }
+
+    /// Similar to [`Background::storage_query`], but additionally returns the SCALE-encoded
+    /// Merkle proof that the storage values have been extracted from.
+    async fn storage_query_with_proof(
+        &self,
+        keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        hash: &[u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<(Vec<Option<Vec<u8>>>, Vec<u8>), StorageQueryError> {
         let (state_trie_root_hash, block_number) = self
             .state_trie_root_hash(hash)
             .await
@@ -1088,7 +1229,7 @@ impl<TPlat: Platform> Background<TPlat> {
         let result = self
             .sync_service
             .clone()
-            .storage_query(
+            .storage_query_with_proof(
                 block_number,
                 hash,
                 &state_trie_root_hash,
@@ -1259,21 +1400,12 @@ impl<TPlat: Platform> Background<TPlat> {
         // then performing the actual call. The first step is the longest and most difficult.
         let precall = self.runtime_lock(block_hash).await?;
 
-        let (runtime_call_lock, virtual_machine) = precall
-            .start(
-                function_to_call,
-                call_parameters.clone(),
-                total_attempts,
-                timeout_per_request,
-                max_parallel,
-            )
-            .await
-            .unwrap(); // TODO: don't unwrap
-
         // Check that the runtime version is correct.
         let runtime_api_version = if let Some((api_name, version_range)) = runtime_api_check {
-            let version = virtual_machine
-                .runtime_version()
+            let version = precall
+                .specification()
+                .map_err(runtime_service::RuntimeCallError::InvalidRuntime)
+                .map_err(RuntimeCallError::Call)?
                 .decode()
                 .apis
                 .find_version(api_name);
@@ -1286,47 +1418,153 @@ impl<TPlat: Platform> Background<TPlat> {
             None
         };
 
-        // Now that we have obtained the virtual machine, we can perform the call.
-        // This is a CPU-only operation that executes the virtual machine.
-        // The virtual machine might access the storage.
-        // TODO: finish doc
+        let call_parameters = call_parameters.fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let output = precall
+            .run(
+                function_to_call,
+                iter::once(&call_parameters),
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+                |runtime_call_lock, virtual_machine| {
+                    run_runtime_calls(
+                        runtime_call_lock,
+                        virtual_machine,
+                        &[(function_to_call, &call_parameters)],
+                    )
+                },
+            )
+            .await
+            .map_err(RuntimeCallError::from_run_error)?;
+
+        Ok((output, runtime_api_version))
+    }
+
+    /// Applies the given extrinsic on top of a child of the given block, and returns the
+    /// SCALE-encoded `ApplyExtrinsicResult` returned by the runtime.
+    ///
+    /// The runtime function `Core_initialize_block` is first called with a header whose parent
+    /// is the given block and whose other fields are dummy values, then
+    /// `BlockBuilder_apply_extrinsic` is called on top of the storage modified by the former.
+    async fn runtime_dry_run(
+        self: &Arc<Self>,
+        block_hash: &[u8; 32],
+        scale_encoded_extrinsic: &[u8],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<Vec<u8>, RuntimeCallError> {
+        let (_, block_number) = self
+            .state_trie_root_hash(block_hash)
+            .await
+            .map_err(RuntimeCallError::FindStorageRootHashError)?;
+
+        let initialize_block_parameter = header::HeaderRef {
+            parent_hash: block_hash,
+            number: block_number + 1,
+            extrinsics_root: &[0; 32],
+            state_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(self.sync_service.block_number_bytes());
+
+        let calls = [
+            ("Core_initialize_block", &initialize_block_parameter[..]),
+            ("BlockBuilder_apply_extrinsic", scale_encoded_extrinsic),
+        ];
+
+        let precall = self.runtime_lock(block_hash).await?;
+
+        precall
+            .run_multiple(
+                calls
+                    .iter()
+                    .map(|(function_to_call, parameter)| (*function_to_call, parameter.to_vec())),
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+                |runtime_call_lock, virtual_machine| {
+                    run_runtime_calls(runtime_call_lock, virtual_machine, &calls)
+                },
+            )
+            .await
+            .map_err(RuntimeCallError::from_run_error)
+    }
+}
 
+/// Executes the given runtime calls one after the other against the call proofs of the given
+/// [`runtime_service::RuntimeCallLock`], each on top of the storage changes performed by the
+/// previous ones, and returns the output of the last call.
+///
+/// Meant to be used as the closure passed to [`runtime_service::RuntimeLock::run`].
+fn run_runtime_calls<TPlat: Platform>(
+    runtime_call_lock: &runtime_service::RuntimeCallLock<'_, TPlat>,
+    mut virtual_machine: host::HostVmPrototype,
+    calls: &[(&str, &[u8])],
+) -> (
+    host::HostVmPrototype,
+    Result<Vec<u8>, runtime_service::RunAttemptError<RuntimeCallError>>,
+) {
+    // Storage changes performed by the previous calls.
+    let mut storage_main_trie_changes = Default::default();
+
+    for (call_index, (function_to_call, parameter)) in calls.iter().enumerate() {
         let mut runtime_call = match runtime_host::run(runtime_host::Config {
             virtual_machine,
             function_to_call,
-            parameter: call_parameters,
+            parameter: iter::once(parameter),
             main_trie_root_calculation_cache: None,
-            storage_main_trie_changes: Default::default(),
+            storage_main_trie_changes: mem::take(&mut storage_main_trie_changes),
             offchain_storage_changes: Default::default(),
+            storage_proof_recorder: None,
             max_log_level: 0,
+            host_functions_profiler: None,
         }) {
             Ok(vm) => vm,
             Err((err, prototype)) => {
-                runtime_call_lock.unlock(prototype);
-                return Err(RuntimeCallError::StartError(err));
+                return (
+                    prototype,
+                    Err(runtime_service::RunAttemptError::Other(
+                        RuntimeCallError::StartError(err),
+                    )),
+                )
             }
         };
 
         loop {
             match runtime_call {
                 runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                    let output = success.virtual_machine.value().as_ref().to_vec();
-                    runtime_call_lock.unlock(success.virtual_machine.into_prototype());
-                    break Ok((output, runtime_api_version));
+                    if call_index == calls.len() - 1 {
+                        let output = success.virtual_machine.value().as_ref().to_vec();
+                        return (success.virtual_machine.into_prototype(), Ok(output));
+                    }
+
+                    storage_main_trie_changes = success.storage_main_trie_changes;
+                    virtual_machine = success.virtual_machine.into_prototype();
+                    break;
                 }
                 runtime_host::RuntimeHostVm::Finished(Err(error)) => {
-                    runtime_call_lock.unlock(error.prototype);
-                    break Err(RuntimeCallError::RuntimeError(error.detail));
+                    return (
+                        error.prototype,
+                        Err(runtime_service::RunAttemptError::Other(
+                            RuntimeCallError::RuntimeError(error.detail),
+                        )),
+                    )
                 }
                 runtime_host::RuntimeHostVm::StorageGet(get) => {
                     let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
                     let storage_value = match storage_value {
                         Ok(v) => v,
-                        Err(err) => {
-                            runtime_call_lock.unlock(
+                        Err(_) => {
+                            let key = get.key().as_ref().to_vec();
+                            return (
                                 runtime_host::RuntimeHostVm::StorageGet(get).into_prototype(),
+                                Err(runtime_service::RunAttemptError::MissingStorageValue(key)),
                             );
-                            break Err(RuntimeCallError::Call(err));
                         }
                     };
                     runtime_call =
@@ -1334,22 +1572,31 @@ impl<TPlat: Platform> Background<TPlat> {
                 }
                 runtime_host::RuntimeHostVm::NextKey(nk) => {
                     // TODO:
-                    runtime_call_lock
-                        .unlock(runtime_host::RuntimeHostVm::NextKey(nk).into_prototype());
-                    break Err(RuntimeCallError::NextKeyForbidden);
+                    return (
+                        runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
+                        Err(runtime_service::RunAttemptError::Other(
+                            RuntimeCallError::NextKeyForbidden,
+                        )),
+                    );
                 }
                 runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
                     runtime_call = sig.verify_and_resume();
                 }
                 runtime_host::RuntimeHostVm::PrefixKeys(pk) => {
                     // TODO:
-                    runtime_call_lock
-                        .unlock(runtime_host::RuntimeHostVm::PrefixKeys(pk).into_prototype());
-                    break Err(RuntimeCallError::PrefixKeysForbidden);
+                    return (
+                        runtime_host::RuntimeHostVm::PrefixKeys(pk).into_prototype(),
+                        Err(runtime_service::RunAttemptError::Other(
+                            RuntimeCallError::PrefixKeysForbidden,
+                        )),
+                    );
                 }
             }
         }
     }
+
+    // The last call always returns.
+    unreachable!()
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -1382,6 +1629,17 @@ enum RuntimeCallError {
     },
 }
 
+impl RuntimeCallError {
+    /// Converts the error returned by [`runtime_service::RuntimeLock::run`] when the closure
+    /// reports errors of type [`RuntimeCallError`].
+    fn from_run_error(error: runtime_service::RunError<RuntimeCallError>) -> Self {
+        match error {
+            runtime_service::RunError::Call(error) => RuntimeCallError::Call(error),
+            runtime_service::RunError::Other(error) => error,
+        }
+    }
+}
+
 /// Error potentially returned by [`Background::state_trie_root_hash`].
 #[derive(Debug, derive_more::Display, Clone)]
 enum StateTrieRootHashError {
@@ -17,7 +17,7 @@
 
 //! All JSON-RPC method handlers that related to the `chainHead` API.
 
-use super::{Background, FollowSubscription, SubscriptionMessage};
+use super::{Background, FollowSubscription, RuntimeCallError, SubscriptionMessage};
 
 use crate::{platform::Platform, runtime_service, sync_service};
 
@@ -37,8 +37,7 @@ use futures::prelude::*;
 use hashbrown::HashMap;
 use smoldot::{
     chain::fork_tree,
-    executor::{self, runtime_host},
-    header,
+    executor, header,
     json_rpc::{self, methods, requests_subscriptions},
     network::protocol,
 };
@@ -141,7 +140,7 @@ impl<TPlat: Platform> Background<TPlat> {
                     .await;
 
                 let pre_runtime_call = if let Some(pre_runtime_call) = &pre_runtime_call {
-                    let call_future = pre_runtime_call.start(
+                    let call_future = pre_runtime_call.run(
                         &function_to_call,
                         iter::once(&call_parameters.0),
                         cmp::min(10, network_config.total_attempts),
@@ -150,6 +149,13 @@ impl<TPlat: Platform> Background<TPlat> {
                             network_config.timeout_ms,
                         ))),
                         NonZeroU32::new(network_config.max_parallel.clamp(1, 5)).unwrap(),
+                        |runtime_call_lock, virtual_machine| {
+                            super::run_runtime_calls(
+                                runtime_call_lock,
+                                virtual_machine,
+                                &[(&function_to_call, &call_parameters.0)],
+                            )
+                        },
                     );
                     futures::pin_mut!(call_future);
 
@@ -166,9 +172,7 @@ impl<TPlat: Platform> Background<TPlat> {
                         match outcome {
                             either::Left(outcome) => break Some(outcome),
                             either::Right((
-                                SubscriptionMessage::StopIfChainHeadCall {
-                                    stop_request_id,
-                                },
+                                SubscriptionMessage::StopIfChainHeadCall { stop_request_id },
                                 confirmation_sender,
                             )) => {
                                 me.requests_subscriptions
@@ -193,143 +197,38 @@ impl<TPlat: Platform> Background<TPlat> {
                 };
 
                 let final_notif = match pre_runtime_call {
-                    Some(Ok((runtime_call_lock, virtual_machine))) => {
-                        match runtime_host::run(runtime_host::Config {
-                            virtual_machine,
-                            function_to_call: &function_to_call,
-                            parameter: iter::once(&call_parameters.0),
-                            main_trie_root_calculation_cache: None,
-                            offchain_storage_changes: Default::default(),
-                            storage_main_trie_changes: Default::default(),
-                            max_log_level: 0,
-                        }) {
-                            Err((error, prototype)) => {
-                                runtime_call_lock.unlock(prototype);
-                                methods::ServerToClient::chainHead_unstable_callEvent {
-                                    subscription: (&subscription_id).into(),
-                                    result: methods::ChainHeadCallEvent::Error {
-                                        error: error.to_string().into(),
-                                    },
-                                }
-                                .to_json_call_object_parameters(None)
-                            }
-                            Ok(mut runtime_call) => {
-                                loop {
-                                    match runtime_call {
-                                        runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                                            let output =
-                                                success.virtual_machine.value().as_ref().to_owned();
-                                            runtime_call_lock
-                                                .unlock(success.virtual_machine.into_prototype());
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Done {
-                                                        output: methods::HexString(output),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::Finished(Err(error)) => {
-                                            runtime_call_lock.unlock(error.prototype);
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Error {
-                                                        error: error.detail.to_string().into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::StorageGet(get) => {
-                                            // TODO: what if the remote lied to us?
-                                            let storage_value =
-                                                runtime_call_lock.storage_entry(get.key().as_ref());
-                                            let storage_value = match storage_value {
-                                                Ok(v) => v,
-                                                Err(error) => {
-                                                    runtime_call_lock.unlock(
-                                                        runtime_host::RuntimeHostVm::StorageGet(
-                                                            get,
-                                                        )
-                                                        .into_prototype(),
-                                                    );
-                                                    break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                            subscription: (&subscription_id).into(),
-                                                            result: methods::ChainHeadCallEvent::Inaccessible {
-                                                                error: error.to_string().into(),
-                                                            },
-                                                        }
-                                                        .to_json_call_object_parameters(None);
-                                                }
-                                            };
-                                            runtime_call = get.inject_value(
-                                                storage_value
-                                                    .map(|(val, vers)| (iter::once(val), vers)),
-                                            );
-                                        }
-                                        runtime_host::RuntimeHostVm::NextKey(nk) => {
-                                            // TODO: implement somehow
-                                            runtime_call_lock.unlock(
-                                                runtime_host::RuntimeHostVm::NextKey(nk)
-                                                    .into_prototype(),
-                                            );
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting next key not implemented".into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::PrefixKeys(nk) => {
-                                            // TODO: implement somehow
-                                            runtime_call_lock.unlock(
-                                                runtime_host::RuntimeHostVm::PrefixKeys(nk)
-                                                    .into_prototype(),
-                                            );
-                                            break methods::ServerToClient::chainHead_unstable_callEvent {
-                                                    subscription: (&subscription_id).into(),
-                                                    result: methods::ChainHeadCallEvent::Inaccessible {
-                                                        error: "getting prefix keys not implemented".into(),
-                                                    },
-                                                }
-                                                .to_json_call_object_parameters(None);
-                                        }
-                                        runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
-                                            runtime_call = sig.verify_and_resume();
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Some(Err(runtime_service::RuntimeCallError::InvalidRuntime(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: error.to_string().into(),
-                            },
-                        }
-                        .to_json_call_object_parameters(None)
+                    Some(Ok(output)) => methods::ServerToClient::chainHead_unstable_callEvent {
+                        subscription: (&subscription_id).into(),
+                        result: methods::ChainHeadCallEvent::Done {
+                            output: methods::HexString(output),
+                        },
                     }
-                    Some(Err(runtime_service::RuntimeCallError::StorageRetrieval(error))) => {
+                    .to_json_call_object_parameters(None),
+                    Some(Err(runtime_service::RunError::Other(
+                        RuntimeCallError::NextKeyForbidden,
+                    ))) => {
+                        // TODO: implement somehow
                         methods::ServerToClient::chainHead_unstable_callEvent {
                             subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: error.to_string().into(),
+                            result: methods::ChainHeadCallEvent::Inaccessible {
+                                error: "getting next key not implemented".into(),
                             },
                         }
                         .to_json_call_object_parameters(None)
                     }
-                    Some(Err(runtime_service::RuntimeCallError::MissingProofEntry)) => {
+                    Some(Err(runtime_service::RunError::Other(
+                        RuntimeCallError::PrefixKeysForbidden,
+                    ))) => {
+                        // TODO: implement somehow
                         methods::ServerToClient::chainHead_unstable_callEvent {
                             subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: "incomplete call proof".into(),
+                            result: methods::ChainHeadCallEvent::Inaccessible {
+                                error: "getting prefix keys not implemented".into(),
                             },
                         }
                         .to_json_call_object_parameters(None)
                     }
-                    Some(Err(runtime_service::RuntimeCallError::CallProof(error))) => {
+                    Some(Err(runtime_service::RunError::Other(error))) => {
                         methods::ServerToClient::chainHead_unstable_callEvent {
                             subscription: (&subscription_id).into(),
                             result: methods::ChainHeadCallEvent::Error {
@@ -338,15 +237,42 @@ impl<TPlat: Platform> Background<TPlat> {
                         }
                         .to_json_call_object_parameters(None)
                     }
-                    Some(Err(runtime_service::RuntimeCallError::StorageQuery(error))) => {
-                        methods::ServerToClient::chainHead_unstable_callEvent {
-                            subscription: (&subscription_id).into(),
-                            result: methods::ChainHeadCallEvent::Error {
-                                error: format!("failed to fetch call proof: {error}").into(),
-                            },
-                        }
-                        .to_json_call_object_parameters(None)
+                    Some(Err(runtime_service::RunError::Call(
+                        runtime_service::RuntimeCallError::InvalidRuntime(error),
+                    ))) => methods::ServerToClient::chainHead_unstable_callEvent {
+                        subscription: (&subscription_id).into(),
+                        result: methods::ChainHeadCallEvent::Error {
+                            error: error.to_string().into(),
+                        },
                     }
+                    .to_json_call_object_parameters(None),
+                    Some(Err(runtime_service::RunError::Call(
+                        runtime_service::RuntimeCallError::MissingProofEntry,
+                    ))) => methods::ServerToClient::chainHead_unstable_callEvent {
+                        subscription: (&subscription_id).into(),
+                        result: methods::ChainHeadCallEvent::Error {
+                            error: "incomplete call proof".into(),
+                        },
+                    }
+                    .to_json_call_object_parameters(None),
+                    Some(Err(runtime_service::RunError::Call(
+                        runtime_service::RuntimeCallError::CallProof(error),
+                    ))) => methods::ServerToClient::chainHead_unstable_callEvent {
+                        subscription: (&subscription_id).into(),
+                        result: methods::ChainHeadCallEvent::Error {
+                            error: error.to_string().into(),
+                        },
+                    }
+                    .to_json_call_object_parameters(None),
+                    Some(Err(runtime_service::RunError::Call(
+                        runtime_service::RuntimeCallError::StorageQuery(error),
+                    ))) => methods::ServerToClient::chainHead_unstable_callEvent {
+                        subscription: (&subscription_id).into(),
+                        result: methods::ChainHeadCallEvent::Error {
+                            error: format!("failed to fetch call proof: {error}").into(),
+                        },
+                    }
+                    .to_json_call_object_parameters(None),
                     None => methods::ServerToClient::chainHead_unstable_callEvent {
                         subscription: (&subscription_id).into(),
                         result: methods::ChainHeadCallEvent::Disjoint {},
@@ -355,8 +281,7 @@ impl<TPlat: Platform> Background<TPlat> {
                 };
 
                 me.requests_subscriptions
-                    .push_notification(&request_id.1,
-                        &subscription_id, final_notif)
+                    .push_notification(&request_id.1, &subscription_id, final_notif)
                     .await;
             }
         });
@@ -1702,6 +1627,7 @@ impl<TPlat: Platform> Background<TPlat> {
         let response = crate::database::encode_database(
             &self.network_service.0,
             &self.sync_service,
+            &self.runtime_service,
             &self.genesis_block_hash,
             usize::try_from(max_size_bytes.unwrap_or(u64::max_value()))
                 .unwrap_or(usize::max_value()),
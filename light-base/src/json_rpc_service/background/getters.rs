@@ -23,7 +23,7 @@ use alloc::{borrow::Cow, format, string::ToString as _, sync::Arc, vec::Vec};
 use core::num::NonZeroUsize;
 use smoldot::{
     header,
-    json_rpc::{methods, requests_subscriptions},
+    json_rpc::{self, methods, requests_subscriptions},
     network::protocol,
 };
 
@@ -223,6 +223,59 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::grandpa_roundState`].
+    ///
+    /// > **Note**: Full nodes don't necessarily gossip GrandPa votes to light clients, in which
+    /// >           case no vote is ever reported.
+    pub(super) async fn grandpa_round_state(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        let (network_service, chain_index) = &self.network_service;
+
+        let response = match network_service.grandpa_round_state(*chain_index).await {
+            Some(round_state) => {
+                methods::Response::grandpa_roundState(methods::GrandpaRoundStates {
+                    set_id: round_state.set_id(),
+                    best: methods::GrandpaRoundState {
+                        round: round_state.round_number(),
+                        total_weight: round_state.total_weight(),
+                        threshold_weight: round_state.threshold_weight(),
+                        prevotes: methods::GrandpaRoundVotes {
+                            current_weight: round_state.prevotes_weight(),
+                            missing: round_state
+                                .missing_prevotes()
+                                .map(|public_key| methods::HexString(public_key.to_vec()))
+                                .collect(),
+                        },
+                        precommits: methods::GrandpaRoundVotes {
+                            current_weight: round_state.precommits_weight(),
+                            missing: round_state
+                                .missing_precommits()
+                                .map(|public_key| methods::HexString(public_key.to_vec()))
+                                .collect(),
+                        },
+                    },
+                    // Only the latest round is tracked.
+                    background: Vec::new(),
+                })
+                .to_json_response(request_id.0)
+            }
+            None => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    "GrandPa authorities aren't known",
+                ),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::system_name`].
     pub(super) async fn system_name(
         self: &Arc<Self>,
@@ -237,6 +290,38 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::system_networkState`].
+    pub(super) async fn system_network_state(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        let (network_service, chain_index) = &self.network_service;
+
+        let response = methods::Response::system_networkState(methods::NetworkState {
+            peer_id: self.peer_id_base58.clone(),
+            // The light client never listens for incoming connections.
+            listened_addresses: Vec::new(),
+            connected_peers: network_service
+                .peers_list()
+                .await
+                .map(|peer_id| peer_id.to_string())
+                .collect(),
+            k_buckets: network_service
+                .discovered_nodes(*chain_index)
+                .await
+                .map(|(peer_id, addresses)| methods::NetworkStateNode {
+                    peer_id: peer_id.to_string(),
+                    addresses: addresses.map(|addr| addr.to_string()).collect(),
+                })
+                .collect(),
+        })
+        .to_json_response(request_id.0);
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::system_nodeRoles`].
     pub(super) async fn system_node_roles(
         self: &Arc<Self>,
@@ -0,0 +1,171 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! All JSON-RPC method handlers related to the `network_unstable_*` family of functions.
+
+use super::{Background, Platform, SubscriptionMessage};
+
+use alloc::{borrow::ToOwned as _, sync::Arc};
+use futures::prelude::*;
+use smoldot::json_rpc::{self, methods, requests_subscriptions};
+
+impl<TPlat: Platform> Background<TPlat> {
+    /// Handles a call to [`methods::MethodCall::network_unstable_subscribeEvents`].
+    pub(super) async fn network_unstable_subscribe_events(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+    ) {
+        let (subscription_id, mut messages_rx, subscription_start) = match self
+            .requests_subscriptions
+            .start_subscription(request_id.1, 1)
+            .await
+        {
+            Ok(v) => v,
+            Err(requests_subscriptions::StartSubscriptionError::LimitReached) => {
+                self.requests_subscriptions
+                    .respond(
+                        request_id.1,
+                        json_rpc::parse::build_error_response(
+                            request_id.0,
+                            json_rpc::parse::ErrorResponse::ServerError(
+                                -32000,
+                                "Too many active subscriptions",
+                            ),
+                            None,
+                        ),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        let mut network_events = self.network_service.0.subscribe_network_events().await;
+        let mut filter = json_rpc::network_events::ChainFilter::new(self.network_service.1);
+
+        subscription_start.start({
+            let me = self.clone();
+            let request_id = (request_id.0.to_owned(), request_id.1.clone());
+
+            async move {
+                me.requests_subscriptions
+                    .respond(
+                        &request_id.1,
+                        methods::Response::network_unstable_subscribeEvents(
+                            (&subscription_id).into(),
+                        )
+                        .to_json_response(&request_id.0),
+                    )
+                    .await;
+
+                loop {
+                    let message = {
+                        let next_event = network_events.next();
+                        let next_message = messages_rx.next();
+                        futures::pin_mut!(next_event, next_message);
+                        match future::select(next_event, next_message).await {
+                            future::Either::Left((v, _)) => either::Left(v),
+                            future::Either::Right((v, _)) => either::Right(v),
+                        }
+                    };
+
+                    match message {
+                        either::Left(None) => {
+                            // The network service has shut down.
+                            break;
+                        }
+                        either::Left(Some(event)) => {
+                            let when = u64::try_from(TPlat::now_from_unix_epoch().as_millis())
+                                .unwrap_or(u64::max_value());
+
+                            for event in filter.inject_event(event, when) {
+                                // This function call will fail if the queue of notifications to
+                                // the user has too many elements in it. Events are meant for
+                                // diagnostic purposes, and we handle this situation by simply not
+                                // sending the notification.
+                                let _ = me
+                                    .requests_subscriptions
+                                    .try_push_notification(
+                                        &request_id.1,
+                                        &subscription_id,
+                                        methods::ServerToClient::network_unstable_event {
+                                            subscription: (&subscription_id).into(),
+                                            result: event,
+                                        }
+                                        .to_json_call_object_parameters(None),
+                                    )
+                                    .await;
+                            }
+                        }
+                        either::Right((
+                            SubscriptionMessage::StopIfNetworkEvents { stop_request_id },
+                            confirmation_sender,
+                        )) => {
+                            me.requests_subscriptions
+                                .respond(
+                                    &stop_request_id.1,
+                                    methods::Response::network_unstable_unsubscribeEvents(())
+                                        .to_json_response(&stop_request_id.0),
+                                )
+                                .await;
+
+                            confirmation_sender.send();
+                            break;
+                        }
+                        either::Right(_) => {
+                            // Any other message.
+                            // Silently discard the confirmation sender.
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Handles a call to [`methods::MethodCall::network_unstable_unsubscribeEvents`].
+    pub(super) async fn network_unstable_unsubscribe_events(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        subscription: &str,
+    ) {
+        // Stopping the subscription is done by sending a message to it.
+        // The task dedicated to this subscription will receive the message, send a response to
+        // the JSON-RPC client, then shut down.
+        let stop_message_received = self
+            .requests_subscriptions
+            .subscription_send(
+                request_id.1,
+                subscription,
+                SubscriptionMessage::StopIfNetworkEvents {
+                    stop_request_id: (request_id.0.to_owned(), request_id.1.clone()),
+                },
+            )
+            .await;
+
+        // Send back a response manually if the task doesn't exist, or has discarded the message,
+        // which could happen for example because there was already a stop message earlier in its
+        // queue or because it was the wrong type of subscription.
+        if stop_message_received.is_err() {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    methods::Response::network_unstable_unsubscribeEvents(())
+                        .to_json_response(request_id.0),
+                )
+                .await;
+        }
+    }
+}
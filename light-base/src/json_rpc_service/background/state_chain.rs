@@ -19,7 +19,7 @@
 
 use super::{Background, Platform, SubscriptionMessage};
 
-use crate::runtime_service;
+use crate::{runtime_service, sync_service};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -30,16 +30,18 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter,
+    cmp, iter,
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
-use futures::{lock::MutexGuard, prelude::*};
+use futures::prelude::*;
 use smoldot::{
+    finality::{self, grandpa::finality_proof},
     header,
     informant::HashDisplay,
     json_rpc::{self, methods, requests_subscriptions},
     network::protocol,
+    trie,
 };
 
 mod sub_utils;
@@ -247,78 +249,8 @@ impl<TPlat: Platform> Background<TPlat> {
             ),
         };
 
-        // Try to look in the cache of recent blocks. If not found, ask the peer-to-peer network.
         // `header` is `Err` if and only if the network request failed.
-        let scale_encoded_header = {
-            let mut cache_lock = self.cache.lock().await;
-            if let Some(header) = cache_lock.recent_pinned_blocks.get(&hash) {
-                Ok(header.clone())
-            } else {
-                // Header isn't known locally. We need to ask the network.
-                // First, try to determine the block number by looking into the cache.
-                // The request can be fulfilled no matter whether it is found, but knowing it will
-                // lead to a better selection of peers, and thus increase the chances of the
-                // requests succeeding.
-                let block_number = if let Some(future) =
-                    cache_lock.block_state_root_hashes_numbers.get_mut(&hash)
-                {
-                    let _ = future.now_or_never();
-
-                    match future {
-                        future::MaybeDone::Done(Ok((_, num))) => Some(*num),
-                        _ => None,
-                    }
-                } else {
-                    None
-                };
-
-                // Release the lock as we're going to start a long asynchronous operation.
-                drop::<MutexGuard<_>>(cache_lock);
-
-                // Actual network query.
-                let result = if let Some(block_number) = block_number {
-                    self.sync_service
-                        .clone()
-                        .block_query(
-                            block_number,
-                            hash,
-                            protocol::BlocksRequestFields {
-                                header: true,
-                                body: false,
-                                justifications: false,
-                            },
-                            3,
-                            Duration::from_secs(8),
-                            NonZeroU32::new(1).unwrap(),
-                        )
-                        .await
-                } else {
-                    self.sync_service
-                        .clone()
-                        .block_query_unknown_number(
-                            hash,
-                            protocol::BlocksRequestFields {
-                                header: true,
-                                body: false,
-                                justifications: false,
-                            },
-                            3,
-                            Duration::from_secs(8),
-                            NonZeroU32::new(1).unwrap(),
-                        )
-                        .await
-                };
-
-                // The `block_query` method guarantees that the header is present and valid.
-                if let Ok(block) = result {
-                    let header = block.header.unwrap();
-                    debug_assert_eq!(header::hash_from_scale_encoded_header(&header), hash);
-                    Ok(header)
-                } else {
-                    Err(())
-                }
-            }
-        };
+        let scale_encoded_header = self.header_query(&hash).await;
 
         // Build the JSON-RPC response.
         let response = match scale_encoded_header {
@@ -967,6 +899,46 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::system_dryRun`].
+    pub(super) async fn system_dry_run(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        extrinsic: methods::HexString,
+        hash: Option<methods::HashHexString>,
+    ) {
+        let block_hash = if let Some(hash) = hash {
+            hash.0
+        } else {
+            header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            )
+        };
+
+        let result = self
+            .runtime_dry_run(
+                &block_hash,
+                &extrinsic.0,
+                3,
+                Duration::from_secs(10),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await;
+
+        let response = match result {
+            Ok(data) => methods::Response::system_dryRun(methods::HexString(data))
+                .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::state_getKeys`].
     pub(super) async fn state_get_keys(
         self: &Arc<Self>,
@@ -1226,6 +1198,54 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::state_getReadProof`].
+    pub(super) async fn state_get_read_proof(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        keys: Vec<methods::HexString>,
+        at: Option<methods::HashHexString>,
+    ) {
+        let at = match at {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        let response = self
+            .storage_query_with_proof(
+                keys.iter().map(|k| &k.0),
+                &at,
+                3,
+                Duration::from_secs(12),
+                NonZeroU32::new(1).unwrap(),
+            )
+            .await;
+
+        // The proof has already been verified by the sync service, and its decoding can thus
+        // never fail.
+        let response = match response {
+            Ok((_, proof)) => methods::Response::state_getReadProof(methods::ReadProof {
+                at: methods::HashHexString(at),
+                proof: trie::proof_decode::decode_proof_entries(&proof)
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| methods::HexString(entry.to_vec()))
+                    .collect(),
+            })
+            .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error.to_string()),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::state_getStorage`].
     pub(super) async fn state_get_storage(
         self: &Arc<Self>,
@@ -1264,6 +1284,369 @@ impl<TPlat: Platform> Background<TPlat> {
             .await;
     }
 
+    /// Handles a call to [`methods::MethodCall::state_queryStorage`].
+    ///
+    /// The storage values of every block of the range are downloaded, except for the blocks
+    /// whose state trie root hash is the same as the one of their parent.
+    pub(super) async fn state_query_storage(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        keys: Vec<methods::HexString>,
+        from_block: methods::HashHexString,
+        to_block: Option<methods::HashHexString>,
+    ) {
+        // Maximum number of blocks in the range. Each block can potentially require a network
+        // request, so this limit is necessary in order to avoid a single JSON-RPC request
+        // monopolizing the networking.
+        const MAX_BLOCKS: usize = 256;
+        // Maximum number of storage proof requests performed at the same time.
+        const MAX_PARALLEL_QUERIES: usize = 8;
+
+        let to_block = match to_block {
+            Some(h) => h.0,
+            None => header::hash_from_scale_encoded_header(
+                sub_utils::subscribe_best(&self.runtime_service).await.0,
+            ),
+        };
+
+        // Walk the chain backwards from `to_block` until `from_block` is reached, in order to
+        // build the list of blocks of the range. Each entry contains the hash, number, and
+        // state trie root hash of a block.
+        let mut blocks = Vec::new();
+        let mut current = to_block;
+        let walk_result = loop {
+            if blocks.len() >= MAX_BLOCKS {
+                break Err(format!("Block range can't exceed {MAX_BLOCKS} blocks"));
+            }
+
+            let Ok(scale_encoded_header) = self.header_query(&current).await else {
+                break Err("Failed to retrieve block header".to_owned());
+            };
+            let decoded = match header::decode(
+                &scale_encoded_header,
+                self.sync_service.block_number_bytes(),
+            ) {
+                Ok(h) => h,
+                Err(error) => break Err(format!("Failed to decode header: {error}")),
+            };
+
+            blocks.push((current, decoded.number, *decoded.state_root));
+
+            if current == from_block.0 {
+                break Ok(());
+            }
+            if decoded.number == 0 {
+                break Err("fromBlock isn't an ancestor of toBlock".to_owned());
+            }
+            current = *decoded.parent_hash;
+        };
+
+        if let Err(error) = walk_result {
+            self.requests_subscriptions
+                .respond(
+                    request_id.1,
+                    json_rpc::parse::build_error_response(
+                        request_id.0,
+                        json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                        None,
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        // Download the storage proofs of the blocks of the range, from the oldest to the newest.
+        // Blocks whose state trie root hash is the same as the one of their parent have an
+        // identical storage, and no request is performed for them.
+        let mut queries = stream::iter(blocks.into_iter().rev().scan(
+            None,
+            |previous_state_root, (block_hash, block_number, state_root)| {
+                let unchanged = *previous_state_root == Some(state_root);
+                *previous_state_root = Some(state_root);
+                Some((block_hash, block_number, state_root, unchanged))
+            },
+        ))
+        .map(|(block_hash, block_number, state_root, unchanged)| {
+            let sync_service = self.sync_service.clone();
+            let keys = &keys;
+            async move {
+                if unchanged {
+                    return Ok::<_, sync_service::StorageQueryError>((block_hash, None));
+                }
+
+                let values = sync_service
+                    .storage_query(
+                        block_number,
+                        &block_hash,
+                        &state_root,
+                        keys.iter().map(|k| &k.0),
+                        3,
+                        Duration::from_secs(12),
+                        NonZeroU32::new(1).unwrap(),
+                    )
+                    .await?;
+
+                Ok((block_hash, Some(values)))
+            }
+        })
+        .buffered(MAX_PARALLEL_QUERIES);
+
+        // Only report the keys whose value differs from the one in the previous block. All the
+        // keys are reported for the first block.
+        let mut out = Vec::new();
+        let mut previous: Option<Vec<_>> = None;
+        while let Some(result) = queries.next().await {
+            let (block_hash, values) = match result {
+                Ok(v) => v,
+                Err(error) => {
+                    self.requests_subscriptions
+                        .respond(
+                            request_id.1,
+                            json_rpc::parse::build_error_response(
+                                request_id.0,
+                                json_rpc::parse::ErrorResponse::ServerError(
+                                    -32000,
+                                    &error.to_string(),
+                                ),
+                                None,
+                            ),
+                        )
+                        .await;
+                    return;
+                }
+            };
+
+            let Some(values) = values else {
+                continue;
+            };
+
+            let changes = keys
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| match &previous {
+                    Some(prev_values) => prev_values[*index] != values[*index],
+                    None => true,
+                })
+                .map(|(index, key)| (key.clone(), values[index].clone().map(methods::HexString)))
+                .collect::<Vec<_>>();
+
+            if !changes.is_empty() {
+                out.push(methods::StorageChangeSet {
+                    block: methods::HashHexString(block_hash),
+                    changes,
+                });
+            }
+
+            previous = Some(values);
+        }
+
+        self.requests_subscriptions
+            .respond(
+                request_id.1,
+                methods::Response::state_queryStorage(out).to_json_response(request_id.0),
+            )
+            .await;
+    }
+
+    /// Handles a call to [`methods::MethodCall::grandpa_proveFinality`].
+    ///
+    /// The justification is found by performing GrandPa warp sync requests starting from the
+    /// genesis block, and is the justification of the first warp sync fragment whose block is
+    /// the requested block or one of its descendants. Since warp sync fragments only contain the
+    /// headers of the blocks where the list of authorities changes, the headers between the
+    /// requested block and the justified block are then downloaded with blocks requests.
+    ///
+    /// > **Note**: The signatures of the justification aren't verified, as doing so would
+    /// >           require verifying the entire warp sync proof starting from the genesis block.
+    /// >           The consistency of the justification and of the headers is, however, checked.
+    pub(super) async fn grandpa_prove_finality(
+        self: &Arc<Self>,
+        request_id: (&str, &requests_subscriptions::RequestId),
+        block_number: u64,
+    ) {
+        // Maximum number of warp sync requests to perform, in order to avoid a single JSON-RPC
+        // request monopolizing the networking.
+        const MAX_REQUESTS: usize = 16;
+        // Maximum number of headers that the proof can contain, for the same reason.
+        const MAX_HEADERS: u64 = 1024;
+        // Maximum number of blocks that full nodes accept to return in a single response.
+        const MAX_BLOCKS_PER_REQUEST: u64 = 128;
+
+        let block_number_bytes = self.sync_service.block_number_bytes();
+        let (network_service, chain_index) = &self.network_service;
+
+        let mut peers = self
+            .sync_service
+            .syncing_peers()
+            .await
+            .into_iter()
+            .map(|(peer_id, ..)| peer_id);
+        let mut target = peers.next();
+        let mut begin_hash = self.genesis_block_hash;
+        let mut num_requests = 0;
+
+        let result = 'requests: loop {
+            let Some(peer_id) = target.clone() else {
+                break Err("No peer could provide a finality proof".to_owned());
+            };
+            if num_requests >= MAX_REQUESTS {
+                break Err("Too many network requests necessary".to_owned());
+            }
+            num_requests += 1;
+
+            let response = match network_service
+                .clone()
+                .grandpa_warp_sync_request(
+                    peer_id.clone(),
+                    *chain_index,
+                    begin_hash,
+                    Duration::from_secs(12),
+                )
+                .await
+            {
+                Ok(response) => response,
+                Err(_) => {
+                    target = peers.next();
+                    continue;
+                }
+            };
+
+            let decoded = response.decode();
+            for fragment in &decoded.fragments {
+                let justified_hash =
+                    header::hash_from_scale_encoded_header(fragment.scale_encoded_header);
+                let (Ok(justified_header), Ok(justification)) = (
+                    header::decode(fragment.scale_encoded_header, block_number_bytes),
+                    finality::justification::decode::decode_grandpa(
+                        fragment.scale_encoded_justification,
+                        block_number_bytes,
+                    ),
+                ) else {
+                    target = peers.next();
+                    continue 'requests;
+                };
+                if *justification.target_hash != justified_hash
+                    || justification.target_number != justified_header.number
+                {
+                    target = peers.next();
+                    continue 'requests;
+                }
+
+                if justified_header.number < block_number {
+                    continue;
+                }
+
+                if justified_header.number - block_number > MAX_HEADERS {
+                    break 'requests Err(
+                        "Too many blocks between the requested block and the closest \
+                        justification"
+                            .to_owned(),
+                    );
+                }
+
+                // Download the headers of the blocks between the requested block (exclusive)
+                // and the justified block (inclusive), in descending order.
+                let mut scale_encoded_headers = Vec::with_capacity(
+                    usize::try_from(justified_header.number - block_number).unwrap(),
+                );
+                let mut expected_hash = justified_hash;
+                let mut expected_number = justified_header.number;
+                while expected_number > block_number {
+                    if num_requests >= MAX_REQUESTS {
+                        break 'requests Err("Too many network requests necessary".to_owned());
+                    }
+                    num_requests += 1;
+
+                    let desired_count =
+                        cmp::min(expected_number - block_number, MAX_BLOCKS_PER_REQUEST);
+                    let blocks = network_service
+                        .clone()
+                        .blocks_request(
+                            peer_id.clone(),
+                            *chain_index,
+                            protocol::BlocksRequestConfig {
+                                start: protocol::BlocksRequestConfigStart::Hash(expected_hash),
+                                desired_count: NonZeroU32::new(
+                                    u32::try_from(desired_count).unwrap(),
+                                )
+                                .unwrap(),
+                                direction: protocol::BlocksRequestDirection::Descending,
+                                fields: protocol::BlocksRequestFields {
+                                    header: true,
+                                    body: false,
+                                    justifications: false,
+                                },
+                            },
+                            Duration::from_secs(12),
+                        )
+                        .await
+                        .unwrap_or_default();
+
+                    // Blocks requests responses are untrusted. Make sure that the headers
+                    // are those of the chain leading to the justified block.
+                    let num_headers_before = scale_encoded_headers.len();
+                    for block in blocks
+                        .into_iter()
+                        .take(usize::try_from(desired_count).unwrap())
+                    {
+                        let Some(scale_encoded_header) = block.header else {
+                            break;
+                        };
+                        if header::hash_from_scale_encoded_header(&scale_encoded_header)
+                            != expected_hash
+                        {
+                            break;
+                        }
+                        let Ok(decoded) = header::decode(&scale_encoded_header, block_number_bytes)
+                        else {
+                            break;
+                        };
+                        if decoded.number != expected_number {
+                            break;
+                        }
+                        expected_hash = *decoded.parent_hash;
+                        expected_number -= 1;
+                        scale_encoded_headers.push(scale_encoded_header);
+                    }
+
+                    if scale_encoded_headers.len() == num_headers_before {
+                        break 'requests Err(
+                            "Failed to download the headers of the finality proof".to_owned(),
+                        );
+                    }
+                }
+
+                break 'requests Ok(Some(finality_proof::encode(
+                    &justified_hash,
+                    fragment.scale_encoded_justification,
+                    scale_encoded_headers.iter().rev().map(|h| &h[..]),
+                )));
+            }
+
+            match decoded.fragments.last() {
+                Some(fragment) if !decoded.is_finished => {
+                    begin_hash =
+                        header::hash_from_scale_encoded_header(fragment.scale_encoded_header);
+                }
+                _ => break Ok(None),
+            }
+        };
+
+        let response = match result {
+            Ok(proof) => methods::Response::grandpa_proveFinality(proof.map(methods::HexString))
+                .to_json_response(request_id.0),
+            Err(error) => json_rpc::parse::build_error_response(
+                request_id.0,
+                json_rpc::parse::ErrorResponse::ServerError(-32000, &error),
+                None,
+            ),
+        };
+
+        self.requests_subscriptions
+            .respond(request_id.1, response)
+            .await;
+    }
+
     /// Handles a call to [`methods::MethodCall::state_queryStorageAt`].
     pub(super) async fn state_query_storage_at(
         self: &Arc<Self>,
@@ -64,7 +64,7 @@
 //!
 // TODO: talk about the fact that a randomness environment is assumed?
 
-#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![cfg_attr(not(any(test, feature = "std", feature = "tokio")), no_std)]
 #![recursion_limit = "512"]
 #![deny(rustdoc::broken_intra_doc_links)]
 // TODO: the `unused_crate_dependencies` lint is disabled because of dev-dependencies, see <https://github.com/rust-lang/rust/issues/95513>
@@ -82,13 +82,16 @@ use smoldot::{
     chain_spec, header,
     informant::HashDisplay,
     libp2p::{connection, multiaddr, peer_id},
+    telemetry,
 };
 
+mod authority_discovery_service;
 mod database;
 mod json_rpc_service;
 mod network_service;
 mod runtime_service;
 mod sync_service;
+mod telemetry_service;
 mod transactions_service;
 mod util;
 
@@ -149,6 +152,11 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
     /// If `false`, then no JSON-RPC service is started for this chain. This saves up a lot of
     /// resources, but will cause all JSON-RPC requests targeting this chain to fail.
     pub disable_json_rpc: bool,
+
+    /// If `Some`, information about the chain and the node is reported to the telemetry servers
+    /// found in the chain specification, under the given node name. If `None`, no telemetry
+    /// server is ever contacted.
+    pub telemetry_node_name: Option<&'a str>,
 }
 
 /// Chain registered in a [`Client`].
@@ -203,6 +211,10 @@ struct PublicApiChain<TChain> {
     /// [`AddChainConfig::disable_json_rpc`] was `true` when adding the chain.
     json_rpc_frontend: Option<json_rpc_service::Frontend>,
 
+    /// Handle that aborts the task reporting to the telemetry servers. `None` iff
+    /// [`AddChainConfig::telemetry_node_name`] was `None` when adding the chain.
+    telemetry_service: Option<future::AbortHandle>,
+
     /// Dummy channel. Nothing is ever sent on it, but the receiving side is stored in the
     /// [`JsonRpcResponses`] in order to detect when the chain has been removed.
     _public_api_chain_destroyed_tx: oneshot::Sender<()>,
@@ -249,6 +261,7 @@ struct ChainServices<TPlat: platform::Platform> {
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
     transactions_service: Arc<transactions_service::TransactionsService<TPlat>>,
+    authority_discovery_service: Arc<authority_discovery_service::AuthorityDiscoveryService>,
     // TODO: can be grabbed from the sync service instead
     block_number_bytes: usize,
 }
@@ -261,6 +274,7 @@ impl<TPlat: platform::Platform> Clone for ChainServices<TPlat> {
             sync_service: self.sync_service.clone(),
             runtime_service: self.runtime_service.clone(),
             transactions_service: self.transactions_service.clone(),
+            authority_discovery_service: self.authority_discovery_service.clone(),
             block_number_bytes: self.block_number_bytes,
         }
     }
@@ -346,7 +360,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
         // known as a checkpoint) is present in the chain spec, it is possible to start syncing at
         // the finalized block it describes.
         // TODO: clean up that block
-        let (chain_information, genesis_block_header, checkpoint_nodes) = {
+        let (chain_information, genesis_block_header, checkpoint_nodes, runtime_code_hint) = {
             match (
                 chain_spec.as_chain_information().map(|(ci, _)| ci), // TODO: don't just throw away the runtime
                 chain_spec.light_sync_state().map(|s| {
@@ -383,6 +397,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                         database_content.chain_information,
                         genesis_header.into(),
                         database_content.known_nodes,
+                        database_content.runtime_code_hint,
                     )
                 }
 
@@ -418,10 +433,16 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             database_content.chain_information,
                             genesis_header,
                             database_content.known_nodes,
+                            database_content.runtime_code_hint,
                         )
                     } else if let Some(Ok(checkpoint)) = checkpoint {
                         // Database is incorrect.
-                        (checkpoint, genesis_header, database_content.known_nodes)
+                        (
+                            checkpoint,
+                            genesis_header,
+                            database_content.known_nodes,
+                            None,
+                        )
                     } else {
                         // TODO: we can in theory support chain specs that have neither a checkpoint nor the genesis storage, but it's complicated
                         // TODO: is this relevant for parachains?
@@ -448,7 +469,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                         digest: header::DigestRef::empty().into(),
                     };
 
-                    (checkpoint, genesis_header, Default::default())
+                    (checkpoint, genesis_header, Default::default(), None)
                 }
 
                 (Err(err), _, _) => return Err(AddChainError::InvalidGenesisStorage(err)),
@@ -459,13 +480,13 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
 
                 (Ok(genesis_ci), Some(Ok(checkpoint)), _) => {
                     let genesis_header = genesis_ci.as_ref().finalized_block_header.clone();
-                    (checkpoint, genesis_header.into(), Default::default())
+                    (checkpoint, genesis_header.into(), Default::default(), None)
                 }
 
                 (Ok(genesis_ci), None, _) => {
                     let genesis_header =
                         header::Header::from(genesis_ci.as_ref().finalized_block_header.clone());
-                    (genesis_ci, genesis_header, Default::default())
+                    (genesis_ci, genesis_header, Default::default(), None)
                 }
             }
         };
@@ -632,7 +653,17 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             Entry::Vacant(entry) => {
                 // Key used by the networking. Represents the identity of the node on the
                 // peer-to-peer network.
-                let network_noise_key = connection::NoiseKey::new(&rand::random());
+                let (network_noise_key, network_tls_certificate) = {
+                    let private_key: [u8; 32] = rand::random();
+                    (
+                        connection::NoiseKey::new(&private_key),
+                        connection::tls::generate_certificate(
+                            &private_key,
+                            &rand::random(),
+                            rand::random(),
+                        ),
+                    )
+                };
 
                 // Spawn a background task that initializes the services of the new chain and
                 // yields a `ChainServices`.
@@ -671,11 +702,13 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
                             log_name.clone(),
                             spawn_new_task,
                             chain_information,
+                            runtime_code_hint,
                             genesis_block_header
                                 .scale_encoding_vec(chain_spec.block_number_bytes().into()),
                             chain_spec,
                             relay_chain.as_ref().map(|(r, _)| r),
                             network_noise_key,
+                            network_tls_certificate,
                         )
                         .await;
 
@@ -806,6 +839,70 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             .boxed()
         });
 
+        // Telemetry service initialization. Like the JSON-RPC service, this is done every time
+        // `add_chain` is called, as the node name is specific to this call.
+        let telemetry_service = if let Some(node_name) = config.telemetry_node_name {
+            // Clone `running_chain_init`.
+            let mut running_chain_init = match services_init {
+                future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+                future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+                future::MaybeDone::Gone => unreachable!(),
+            };
+
+            let endpoints = chain_spec
+                .telemetry_endpoints_with_verbosity()
+                .filter_map(|(address, verbosity)| {
+                    match telemetry::parse_endpoint(address, verbosity) {
+                        Ok(endpoint) => Some(endpoint),
+                        Err(err) => {
+                            log::warn!(
+                                target: "smoldot",
+                                "Failed to parse telemetry endpoint {} of {}: {}",
+                                address, log_name, err
+                            );
+                            None
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let spawn_new_task = self.spawn_new_task.clone();
+            let log_name = log_name.clone();
+            let chain_name = chain_spec.name().to_owned();
+            let node_name = node_name.to_owned();
+            let system_name = self.system_name.clone();
+            let system_version = self.system_version.clone();
+            let startup_time = TPlat::now_from_unix_epoch();
+
+            let (task, abort_handle) = future::abortable(async move {
+                // Wait for the chain to finish initializing before starting to report.
+                (&mut running_chain_init).await;
+                let running_chain = Pin::new(&mut running_chain_init).take_output().unwrap();
+
+                telemetry_service::run(telemetry_service::Config {
+                    log_name,
+                    tasks_executor: Box::new(move |name, task| spawn_new_task(name, task)),
+                    endpoints,
+                    sync_service: running_chain.sync_service,
+                    block_number_bytes: running_chain.block_number_bytes,
+                    chain_name,
+                    node_name,
+                    system_name,
+                    system_version,
+                    network_identity: running_chain.network_identity,
+                    genesis_block_hash,
+                    startup_time,
+                })
+                .await
+            });
+
+            (self.spawn_new_task)("telemetry-service".to_owned(), task.map(|_| ()).boxed());
+
+            Some(abort_handle)
+        } else {
+            None
+        };
+
         // JSON-RPC service initialization. This is done every time `add_chain` is called, even
         // if a similar chain already existed.
         let json_rpc_frontend = if !config.disable_json_rpc {
@@ -862,6 +959,7 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
             key: new_chain_key,
             chain_spec_chain_id,
             json_rpc_frontend: json_rpc_frontend.clone(),
+            telemetry_service,
             _public_api_chain_destroyed_tx: public_api_chain_destroyed_tx,
         });
         Ok(AddChainSuccess {
@@ -889,6 +987,10 @@ impl<TPlat: platform::Platform, TChain> Client<TPlat, TChain> {
     pub fn remove_chain(&mut self, id: ChainId) -> TChain {
         let removed_chain = self.public_api_chains.remove(id.0);
 
+        if let Some(telemetry_service) = &removed_chain.telemetry_service {
+            telemetry_service.abort();
+        }
+
         let running_chain = self.chains_by_key.get_mut(&removed_chain.key).unwrap();
         if running_chain.num_references.get() == 1 {
             log::info!(target: "smoldot", "Shutting down chain {}", running_chain.log_name);
@@ -998,10 +1100,12 @@ async fn start_services<TPlat: platform::Platform>(
         dyn Fn(String, Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
     >,
     chain_information: chain::chain_information::ValidChainInformation,
+    runtime_code_hint: Option<database::DatabaseContentRuntimeCodeHint>,
     genesis_block_scale_encoded_header: Vec<u8>,
     chain_spec: chain_spec::ChainSpec,
     relay_chain: Option<&ChainServices<TPlat>>,
     network_noise_key: connection::NoiseKey,
+    network_tls_certificate: connection::tls::GeneratedCertificate,
 ) -> ChainServices<TPlat> {
     // Since `network_noise_key` is moved out below, use it to build the network identity ahead
     // of the network service starting.
@@ -1017,6 +1121,7 @@ async fn start_services<TPlat: platform::Platform>(
             }),
             num_events_receivers: 1, // Configures the length of `network_event_receivers`
             noise_key: network_noise_key,
+            tls_certificate: network_tls_certificate,
             chains: vec![network_service::ConfigChain {
                 log_name: log_name.clone(),
                 has_grandpa_protocol: matches!(
@@ -1040,6 +1145,18 @@ async fn start_services<TPlat: platform::Platform>(
         })
         .await;
 
+    // The runtime code hint, if any, always corresponds to the finalized block of
+    // `chain_information`.
+    let runtime_code_hint = runtime_code_hint.map(|hint| runtime_service::ConfigRuntimeCodeHint {
+        block_hash: chain_information
+            .as_ref()
+            .finalized_block_header
+            .hash(chain_spec.block_number_bytes().into()),
+        code: hint.code,
+        heap_pages: hint.heap_pages,
+        code_proof: Some((hint.proof_block_header, hint.proof)),
+    });
+
     let (sync_service, runtime_service) = if let Some(relay_chain) = relay_chain {
         // Chain is a parachain.
 
@@ -1077,6 +1194,7 @@ async fn start_services<TPlat: platform::Platform>(
                 }),
                 sync_service: sync_service.clone(),
                 genesis_block_scale_encoded_header,
+                runtime_code_hint,
             })
             .await,
         );
@@ -1115,6 +1233,7 @@ async fn start_services<TPlat: platform::Platform>(
                 }),
                 sync_service: sync_service.clone(),
                 genesis_block_scale_encoded_header,
+                runtime_code_hint,
             })
             .await,
         );
@@ -1128,8 +1247,11 @@ async fn start_services<TPlat: platform::Platform>(
     // transaction will be submitted, the service itself is pretty low cost.
     let transactions_service = Arc::new(
         transactions_service::TransactionsService::new(transactions_service::Config {
-            log_name,
-            tasks_executor: Box::new(move |name, fut| spawn_new_task(name, fut)),
+            log_name: log_name.clone(),
+            tasks_executor: Box::new({
+                let spawn_new_task = spawn_new_task.clone();
+                move |name, fut| spawn_new_task(name, fut)
+            }),
             sync_service: sync_service.clone(),
             runtime_service: runtime_service.clone(),
             network_service: (network_service.clone(), 0),
@@ -1140,12 +1262,25 @@ async fn start_services<TPlat: platform::Platform>(
         .await,
     );
 
+    // The authority discovery service looks up the addresses of the authorities of the chain,
+    // for chains that support it.
+    let authority_discovery_service =
+        Arc::new(authority_discovery_service::AuthorityDiscoveryService::new(
+            authority_discovery_service::Config {
+                log_name,
+                tasks_executor: Box::new(move |name, fut| spawn_new_task(name, fut)),
+                runtime_service: runtime_service.clone(),
+                network_service: (network_service.clone(), 0),
+            },
+        ));
+
     ChainServices {
         network_service,
         network_identity,
         runtime_service,
         sync_service,
         transactions_service,
+        authority_discovery_service,
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
     }
 }
@@ -35,6 +35,9 @@
 //! An important part of the API is the list of channel receivers of [`Event`] returned by
 //! [`NetworkService::new`]. These channels inform the foreground about updates to the network
 //! connectivity.
+//!
+//! Additionally, [`NetworkService::subscribe_network_events`] can be used to receive low-level
+//! [`NetworkEvent`]s about connections, substreams and slots, for diagnostic purposes.
 
 use crate::platform::Platform;
 
@@ -53,6 +56,7 @@ use futures::{
 use hashbrown::{hash_map, HashMap, HashSet};
 use itertools::Itertools as _;
 use smoldot::{
+    finality::grandpa::round_state,
     header,
     informant::{BytesDisplay, HashDisplay},
     libp2p::{connection, multiaddr::Multiaddr, peer_id::PeerId, peers},
@@ -71,6 +75,11 @@ pub struct Config {
     /// Key to use for the encryption layer of all the connections. Gives the node its identity.
     pub noise_key: connection::NoiseKey,
 
+    /// Certificate presented to the remote during the TLS handshake of QUIC connections. Must
+    /// have been generated with [`connection::tls::generate_certificate`] from the same private
+    /// key as [`Config::noise_key`].
+    pub tls_certificate: connection::tls::GeneratedCertificate,
+
     /// Number of event receivers returned by [`NetworkService::new`].
     pub num_events_receivers: usize,
 
@@ -129,6 +138,9 @@ struct Shared<TPlat: Platform> {
     /// purposes.
     log_chain_names: Vec<String>,
 
+    /// See [`Config::tls_certificate`].
+    tls_certificate: connection::tls::GeneratedCertificate,
+
     /// Event to notify when the background task needs to be waken up.
     ///
     /// Waking up this event guarantees a full loop of the background task. In other words,
@@ -192,8 +204,38 @@ struct SharedGuarded<TPlat: Platform> {
         fnv::FnvBuildHasher,
     >,
 
+    kademlia_get_value_requests: HashMap<
+        service::OutRequestId,
+        oneshot::Sender<Result<protocol::GetValueResponse, service::KademliaGetValueError>>,
+        fnv::FnvBuildHasher,
+    >,
+
     kademlia_discovery_operations:
         HashMap<service::KademliaOperationId, usize, fnv::FnvBuildHasher>,
+
+    /// Senders of the subscriptions created with [`NetworkService::subscribe_network_events`].
+    network_events_senders: Vec<mpsc::Sender<NetworkEvent>>,
+
+    /// Identifier that the next connection or substream reported through a [`NetworkEvent`]
+    /// will have.
+    network_events_next_id: u32,
+
+    /// For each peer we're connected to, identifier of the connection as reported through
+    /// [`NetworkEvent`]s.
+    network_events_connections: HashMap<PeerId, u32, fnv::FnvBuildHasher>,
+
+    /// For each outgoing request in progress, identifier of its substream as reported through
+    /// [`NetworkEvent`]s.
+    network_events_requests: HashMap<service::OutRequestId, u32, fnv::FnvBuildHasher>,
+
+    /// For each peer and chain combination with a block announces substream, identifier of
+    /// this substream as reported through [`NetworkEvent`]s.
+    network_events_chain_substreams: HashMap<(PeerId, usize), u32, fnv::FnvBuildHasher>,
+
+    /// For each chain, state of the current GrandPa round as gathered from the votes received
+    /// from the network. `None` if the authorities of the chain haven't been provided through
+    /// [`NetworkService::set_grandpa_authorities`] yet.
+    grandpa_round_states: Vec<Option<round_state::RoundState>>,
 }
 
 impl<TPlat: Platform> NetworkService<TPlat> {
@@ -215,6 +257,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             chains.push(service::ChainConfig {
                 in_slots: 3,
                 out_slots: 4,
+                reserved_only: false,
                 grandpa_protocol_config: if chain.has_grandpa_protocol {
                     // TODO: dummy values
                     Some(service::GrandpaState {
@@ -232,6 +275,8 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 genesis_hash: chain.genesis_block_hash,
                 role: protocol::Role::Light,
                 allow_inbound_block_requests: false,
+                allow_inbound_storage_and_call_proof_requests: false,
+                allow_inbound_kademlia_requests: false,
             });
 
             log_chain_names.push(chain.log_name);
@@ -267,12 +312,29 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 ),
                 storage_proof_requests: HashMap::with_capacity_and_hasher(8, Default::default()),
                 call_proof_requests: HashMap::with_capacity_and_hasher(8, Default::default()),
+                kademlia_get_value_requests: HashMap::with_capacity_and_hasher(
+                    8,
+                    Default::default(),
+                ),
                 kademlia_discovery_operations: HashMap::with_capacity_and_hasher(
                     2,
                     Default::default(),
                 ),
+                network_events_senders: Vec::new(),
+                network_events_next_id: 0,
+                network_events_connections: HashMap::with_capacity_and_hasher(
+                    32,
+                    Default::default(),
+                ),
+                network_events_requests: HashMap::with_capacity_and_hasher(8, Default::default()),
+                network_events_chain_substreams: HashMap::with_capacity_and_hasher(
+                    32,
+                    Default::default(),
+                ),
+                grandpa_round_states: (0..num_chains).map(|_| None).collect(),
             }),
             log_chain_names,
+            tls_certificate: config.tls_certificate,
             wake_up_main_background_task: event_listener::Event::new(),
         });
 
@@ -422,6 +484,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
 
             let (tx, rx) = oneshot::channel();
             guarded.blocks_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "blocks");
             rx
         };
 
@@ -509,6 +572,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
 
             let (tx, rx) = oneshot::channel();
             guarded.grandpa_warp_sync_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "grandpa-warp-sync");
             rx
         };
 
@@ -578,6 +642,43 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             .set_local_grandpa_state(chain_index, grandpa_state)
     }
 
+    /// Sets the GrandPa authorities of the given chain, against which the votes gossiped on the
+    /// network are verified.
+    ///
+    /// Has no effect if the authorities of the given set id have already been provided.
+    pub async fn set_grandpa_authorities<'a>(
+        &self,
+        chain_index: usize,
+        set_id: u64,
+        authorities: impl Iterator<Item = header::GrandpaAuthorityRef<'a>>,
+    ) {
+        let mut guarded = self.shared.guarded.lock().await;
+        let guarded = &mut *guarded;
+
+        if guarded.grandpa_round_states[chain_index]
+            .as_ref()
+            .map_or(false, |state| state.set_id() == set_id)
+        {
+            return;
+        }
+
+        guarded.grandpa_round_states[chain_index] =
+            Some(round_state::RoundState::new(round_state::Config {
+                block_number_bytes: guarded.network.block_number_bytes(chain_index),
+                set_id,
+                authorities,
+            }));
+    }
+
+    /// Returns the state of the current GrandPa round of the given chain, as gathered from the
+    /// network.
+    ///
+    /// Returns `None` if [`NetworkService::set_grandpa_authorities`] hasn't been called yet for
+    /// this chain.
+    pub async fn grandpa_round_state(&self, chain_index: usize) -> Option<round_state::RoundState> {
+        self.shared.guarded.lock().await.grandpa_round_states[chain_index].clone()
+    }
+
     /// Sends a storage proof request to the given peer.
     // TODO: more docs
     pub async fn storage_proof_request(
@@ -622,6 +723,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
 
             let (tx, rx) = oneshot::channel();
             guarded.storage_proof_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "storage-proof");
             rx
         };
 
@@ -697,6 +799,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
 
             let (tx, rx) = oneshot::channel();
             guarded.call_proof_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "call-proof");
             rx
         };
 
@@ -727,6 +830,78 @@ impl<TPlat: Platform> NetworkService<TPlat> {
         result.map_err(CallProofRequestError::Request)
     }
 
+    /// Sends a Kademlia get value request to the given peer, in order to find the record stored
+    /// under the given key in the distributed hash table of the given chain.
+    pub async fn kademlia_get_value_request(
+        self: Arc<Self>,
+        chain_index: usize,
+        target: PeerId, // TODO: takes by value because of futures longevity issue
+        key: &[u8],
+    ) -> Result<protocol::GetValueResponse, KademliaGetValueRequestError> {
+        let rx = {
+            let mut guarded = self.shared.guarded.lock().await;
+
+            // The call to `start_kademlia_get_value` below panics if we have no active
+            // connection.
+            if !guarded.network.can_start_requests(&target) {
+                return Err(KademliaGetValueRequestError::NoConnection);
+            }
+
+            log::debug!(
+                target: "network",
+                "Connection({}) <= KademliaGetValueRequest({}, {})",
+                target,
+                self.shared.log_chain_names[chain_index],
+                hex::encode(key)
+            );
+
+            let request_id = match guarded.network.start_kademlia_get_value(
+                &target,
+                TPlat::now(),
+                chain_index,
+                key,
+            ) {
+                Ok(r) => r,
+                Err(service::StartRequestError::RequestTooLarge) => {
+                    return Err(KademliaGetValueRequestError::RequestTooLarge)
+                }
+            };
+
+            self.shared.wake_up_main_background_task.notify(1);
+
+            let (tx, rx) = oneshot::channel();
+            guarded.kademlia_get_value_requests.insert(request_id, tx);
+            guarded.report_request_start(&target, chain_index, request_id, "kademlia-get-value");
+            rx
+        };
+
+        let result = rx.await.unwrap();
+
+        match &result {
+            Ok(response) => {
+                log::debug!(
+                    target: "network",
+                    "Connection({}) => KademliaGetValueRequest({}, found: {:?}, num_closer_peers: {})",
+                    target,
+                    self.shared.log_chain_names[chain_index],
+                    response.value.is_some(),
+                    response.closer_peers.len()
+                );
+            }
+            Err(err) => {
+                log::debug!(
+                    target: "network",
+                    "Connection({}) => KademliaGetValueRequest({}, {})",
+                    target,
+                    self.shared.log_chain_names[chain_index],
+                    err
+                );
+            }
+        }
+
+        result.map_err(KademliaGetValueRequestError::Request)
+    }
+
     /// Announces transaction to the peers we are connected to.
     ///
     /// Returns a list of peers that we have sent the transaction to. Can return an empty `Vec`
@@ -844,6 +1019,41 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             .into_iter()
     }
 
+    /// Closes the slot of the given peer for the given chain, and prevents this peer from being
+    /// assigned a slot again for a certain amount of time.
+    ///
+    /// This should be called when the peer has misbehaved, for example by sending an invalid
+    /// proof. The `reason` is used for logging purposes.
+    pub async fn ban_peer(&self, chain_index: usize, peer_id: PeerId, reason: &str) {
+        let mut guarded = self.shared.guarded.lock().await;
+
+        log::debug!(
+            target: "network",
+            "Slots({}) ∌ {} (reason={})",
+            self.shared.log_chain_names[chain_index],
+            peer_id,
+            reason
+        );
+
+        guarded.unassign_slot_and_ban(chain_index, peer_id);
+        self.shared.wake_up_main_background_task.notify(1);
+    }
+
+    /// Returns a channel on which low-level [`NetworkEvent`]s are sent, for diagnostic purposes.
+    ///
+    /// Events that happened before this function is called aren't reported. Events are silently
+    /// discarded if the receiver doesn't process them quickly enough.
+    pub async fn subscribe_network_events(&self) -> mpsc::Receiver<NetworkEvent> {
+        let (tx, rx) = mpsc::channel(64);
+        self.shared
+            .guarded
+            .lock()
+            .await
+            .network_events_senders
+            .push(tx);
+        rx
+    }
+
     /// Returns an iterator to the list of [`PeerId`]s that we have an established connection
     /// with.
     pub async fn peers_list(&self) -> impl Iterator<Item = PeerId> {
@@ -894,6 +1104,8 @@ pub enum Event {
     },
 }
 
+pub use smoldot::json_rpc::network_events::NetworkEvent;
+
 /// Error returned by [`NetworkService::blocks_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum BlocksRequestError {
@@ -950,6 +1162,18 @@ impl CallProofRequestError {
     }
 }
 
+/// Error returned by [`NetworkService::kademlia_get_value_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum KademliaGetValueRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Request is too large and can't be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::KademliaGetValueError),
+}
+
 /// Error returned by [`NetworkService::send_block_announce`].
 #[derive(Debug, derive_more::Display)]
 pub enum QueueNotificationError {
@@ -1005,12 +1229,24 @@ async fn update_round<TPlat: Platform>(
             match inner_event {
                 service::Event::Connected(peer_id) => {
                     log::debug!(target: "network", "Connected({})", peer_id);
+                    let connection_id = guarded.next_network_event_id();
+                    guarded
+                        .network_events_connections
+                        .insert(peer_id.clone(), connection_id);
+                    guarded.report_network_event(NetworkEvent::Connected {
+                        connection_id,
+                        peer_id,
+                    });
                 }
                 service::Event::Disconnected {
                     peer_id,
                     chain_indices,
                 } => {
                     log::debug!(target: "network", "Disconnected({})", peer_id);
+                    if let Some(connection_id) = guarded.network_events_connections.remove(&peer_id)
+                    {
+                        guarded.report_network_event(NetworkEvent::Disconnected { connection_id });
+                    }
                     if !chain_indices.is_empty() {
                         // TODO: properly implement when multiple chains
                         if chain_indices.len() == 1 {
@@ -1065,6 +1301,22 @@ async fn update_round<TPlat: Platform>(
                         best_number,
                         HashDisplay(&best_hash)
                     );
+                    if let Some(connection_id) =
+                        guarded.network_events_connections.get(&peer_id).copied()
+                    {
+                        let substream_id = guarded.next_network_event_id();
+                        guarded
+                            .network_events_chain_substreams
+                            .insert((peer_id.clone(), chain_index), substream_id);
+                        guarded.report_network_event(NetworkEvent::SubstreamOpen {
+                            connection_id,
+                            substream_id,
+                            chain_index,
+                            protocol_name: "block-announces",
+                        });
+                        guarded
+                            .report_network_event(NetworkEvent::SubstreamAccept { substream_id });
+                    }
                     break Event::Connected {
                         peer_id,
                         chain_index,
@@ -1095,6 +1347,11 @@ async fn update_round<TPlat: Platform>(
                         &shared.log_chain_names[chain_index],
                         peer_id
                     );
+                    guarded.report_network_event(NetworkEvent::SlotUnassign {
+                        chain_index,
+                        peer_id: peer_id.clone(),
+                        slot_ty: unassigned_slot_ty,
+                    });
                     guarded.unassign_slot_and_ban(chain_index, peer_id);
                     shared.wake_up_main_background_task.notify(1);
                 }
@@ -1119,6 +1376,20 @@ async fn update_round<TPlat: Platform>(
                         &shared.log_chain_names[chain_index],
                         peer_id
                     );
+                    if let Some(substream_id) = guarded
+                        .network_events_chain_substreams
+                        .remove(&(peer_id.clone(), chain_index))
+                    {
+                        guarded.report_network_event(NetworkEvent::SubstreamStop {
+                            substream_id,
+                            reason: "closed".into(),
+                        });
+                    }
+                    guarded.report_network_event(NetworkEvent::SlotUnassign {
+                        chain_index,
+                        peer_id: peer_id.clone(),
+                        slot_ty: unassigned_slot_ty,
+                    });
                     guarded.unassign_slot_and_ban(chain_index, peer_id.clone());
                     shared.wake_up_main_background_task.notify(1);
                     break Event::Disconnected {
@@ -1130,6 +1401,10 @@ async fn update_round<TPlat: Platform>(
                     request_id,
                     response: service::RequestResult::Blocks(response),
                 } => {
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
                     let _ = guarded
                         .blocks_requests
                         .remove(&request_id)
@@ -1140,6 +1415,10 @@ async fn update_round<TPlat: Platform>(
                     request_id,
                     response: service::RequestResult::GrandpaWarpSync(response),
                 } => {
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
                     let _ = guarded
                         .grandpa_warp_sync_requests
                         .remove(&request_id)
@@ -1150,6 +1429,10 @@ async fn update_round<TPlat: Platform>(
                     request_id,
                     response: service::RequestResult::StorageProof(response),
                 } => {
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
                     let _ = guarded
                         .storage_proof_requests
                         .remove(&request_id)
@@ -1160,12 +1443,30 @@ async fn update_round<TPlat: Platform>(
                     request_id,
                     response: service::RequestResult::CallProof(response),
                 } => {
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
                     let _ = guarded
                         .call_proof_requests
                         .remove(&request_id)
                         .unwrap()
                         .send(response);
                 }
+                service::Event::RequestResult {
+                    request_id,
+                    response: service::RequestResult::KademliaGetValue(response),
+                } => {
+                    guarded.report_request_end(
+                        request_id,
+                        response.as_ref().err().map(|err| err.to_string()),
+                    );
+                    let _ = guarded
+                        .kademlia_get_value_requests
+                        .remove(&request_id)
+                        .unwrap()
+                        .send(response);
+                }
                 service::Event::RequestResult { .. } => {
                     // We never start any other kind of requests.
                     unreachable!()
@@ -1234,6 +1535,11 @@ async fn update_round<TPlat: Platform>(
                         &shared.log_chain_names[chain_index],
                         peer_id
                     );
+                    guarded.report_network_event(NetworkEvent::SlotAssign {
+                        chain_index,
+                        peer_id,
+                        slot_ty: service::SlotTy::Inbound,
+                    });
                 }
                 service::Event::IdentifyRequestIn {
                     peer_id,
@@ -1246,7 +1552,12 @@ async fn update_round<TPlat: Platform>(
                     );
                     guarded.network.respond_identify(request_id, "smoldot");
                 }
-                service::Event::BlocksRequestIn { .. } => unreachable!(),
+                service::Event::BlocksRequestIn { .. }
+                | service::Event::StorageProofRequestIn { .. }
+                | service::Event::CallProofRequestIn { .. } => unreachable!(),
+                service::Event::KademliaFindNodeRequestIn { .. }
+                | service::Event::KademliaGetValueRequestIn { .. }
+                | service::Event::KademliaPutValueRequestIn { .. } => unreachable!(),
                 service::Event::RequestInCancel { .. } => {
                     // All incoming requests are immediately answered.
                     unreachable!()
@@ -1284,6 +1595,37 @@ async fn update_round<TPlat: Platform>(
                         message,
                     };
                 }
+                service::Event::GrandpaVoteMessage {
+                    chain_index,
+                    peer_id,
+                    message,
+                } => {
+                    // Note that the round number reported by neighbor packets isn't taken into
+                    // account, as it could be set to any value by a malicious peer. Only the
+                    // votes, whose signatures are verified, make the round advance.
+                    let (vote, round_state) = match (
+                        message.as_round_state_vote(),
+                        &mut guarded.grandpa_round_states[chain_index],
+                    ) {
+                        (Some(vote), Some(round_state)) => (vote, round_state),
+                        _ => continue,
+                    };
+
+                    let result = round_state.inject_vote(vote.clone());
+                    log::debug!(
+                        target: "network",
+                        "Connection({}, {}) => GrandpaVoteMessage(round_number={}, set_id={}, target_hash={}, outcome={})",
+                        peer_id,
+                        &shared.log_chain_names[chain_index],
+                        vote.round_number,
+                        vote.set_id,
+                        HashDisplay(vote.target_hash),
+                        match result {
+                            Ok(()) => "ok".to_string(),
+                            Err(error) => error.to_string(),
+                        }
+                    );
+                }
                 service::Event::ProtocolError { peer_id, error } => {
                     // TODO: handle properly?
                     log::warn!(
@@ -1353,7 +1695,14 @@ async fn update_round<TPlat: Platform>(
                 &shared.log_chain_names[chain_index],
                 peer_id
             );
-            guarded.network.assign_out_slot(chain_index, peer_id);
+            guarded
+                .network
+                .assign_out_slot(chain_index, peer_id.clone());
+            guarded.report_network_event(NetworkEvent::SlotAssign {
+                chain_index,
+                peer_id,
+                slot_ty: service::SlotTy::Outbound,
+            });
         }
     }
 
@@ -1410,7 +1759,13 @@ async fn update_round<TPlat: Platform>(
 
 impl<TPlat: Platform> SharedGuarded<TPlat> {
     fn unassign_slot_and_ban(&mut self, chain_index: usize, peer_id: PeerId) {
-        self.network.unassign_slot(chain_index, &peer_id);
+        if let Some(slot_ty) = self.network.unassign_slot(chain_index, &peer_id) {
+            self.report_network_event(NetworkEvent::SlotUnassign {
+                chain_index,
+                peer_id: peer_id.clone(),
+                slot_ty,
+            });
+        }
 
         let new_expiration = TPlat::now() + Duration::from_secs(20); // TODO: arbitrary constant
         match self.slots_assign_backoff.entry((peer_id, chain_index)) {
@@ -1423,4 +1778,63 @@ impl<TPlat: Platform> SharedGuarded<TPlat> {
             }
         }
     }
+    /// Sends the given event to the subscriptions created with
+    /// [`NetworkService::subscribe_network_events`].
+    fn report_network_event(&mut self, event: NetworkEvent) {
+        // Closed subscriptions are cleaned up here.
+        self.network_events_senders
+            .retain(|sender| !sender.is_closed());
+
+        for sender in &mut self.network_events_senders {
+            // Subscriptions that don't process their events quickly enough miss them.
+            let _ = sender.try_send(event.clone());
+        }
+    }
+
+    /// Returns a new identifier for a connection or substream reported through a
+    /// [`NetworkEvent`].
+    fn next_network_event_id(&mut self) -> u32 {
+        let id = self.network_events_next_id;
+        self.network_events_next_id = self.network_events_next_id.wrapping_add(1);
+        id
+    }
+
+    /// Reports through a [`NetworkEvent`] that the given outgoing request has been started.
+    fn report_request_start(
+        &mut self,
+        target: &PeerId,
+        chain_index: usize,
+        request_id: service::OutRequestId,
+        protocol_name: &'static str,
+    ) {
+        let Some(connection_id) = self.network_events_connections.get(target).copied() else {
+            return;
+        };
+
+        let substream_id = self.next_network_event_id();
+        self.network_events_requests
+            .insert(request_id, substream_id);
+        self.report_network_event(NetworkEvent::SubstreamOpen {
+            connection_id,
+            substream_id,
+            chain_index,
+            protocol_name,
+        });
+    }
+
+    /// Reports through a [`NetworkEvent`] that the given outgoing request has finished, either
+    /// successfully if `error` is `None`, or with the given error.
+    fn report_request_end(&mut self, request_id: service::OutRequestId, error: Option<String>) {
+        let Some(substream_id) = self.network_events_requests.remove(&request_id) else {
+            return;
+        };
+
+        self.report_network_event(match error {
+            None => NetworkEvent::SubstreamAccept { substream_id },
+            Some(reason) => NetworkEvent::SubstreamStop {
+                substream_id,
+                reason,
+            },
+        });
+    }
 }
@@ -46,7 +46,10 @@ pub(super) async fn connection_task<TPlat: Platform>(
             start_connect.id, start_connect.expected_peer_id,
             start_connect.multiaddr
         );
-        TPlat::connect(&start_connect.multiaddr.to_string())
+        TPlat::connect(
+            &start_connect.multiaddr.to_string(),
+            &shared.tls_certificate,
+        )
     };
 
     let socket = {
@@ -146,7 +149,17 @@ pub(super) async fn connection_task<TPlat: Platform>(
                     remote_tls_certificate_multihash,
                 },
             );
-            (id, either::Right((connection, task)))
+            (id, either::Right((connection, task, true)))
+        }
+        PlatformConnection::MultiStreamQuic {
+            connection,
+            remote_peer_id,
+        } => {
+            let (id, task) = guarded.network.pending_outcome_ok_multi_stream(
+                start_connect.id,
+                service::MultiStreamHandshakeKind::Quic { remote_peer_id },
+            );
+            (id, either::Right((connection, task, false)))
         }
     };
     log::debug!(
@@ -177,9 +190,10 @@ pub(super) async fn connection_task<TPlat: Platform>(
             )
             .await
         }
-        either::Right((socket, task)) => {
-            webrtc_multi_stream_connection_task::<TPlat>(
+        either::Right((socket, task, is_webrtc)) => {
+            multi_stream_connection_task::<TPlat>(
                 socket,
+                is_webrtc,
                 shared.clone(),
                 connection_id,
                 task,
@@ -392,13 +406,15 @@ async fn single_stream_connection_task<TPlat: Platform>(
 
 /// Asynchronous task managing a specific multi-stream connection after it's been open.
 ///
-/// > **Note**: This function is specific to WebRTC in the sense that it checks whether the reading
-/// >           and writing sides of substreams never close, and adjusts the size of the write
-/// >           buffer to not go over the frame size limit of WebRTC. It can easily be made more
-/// >           general-purpose.
+/// If `is_webrtc` is `true`, this function checks whether the reading and writing sides of
+/// substreams never close, as required by WebRTC.
+///
+/// > **Note**: The size of the write buffer is adjusted to not go over the frame size limit of
+/// >           WebRTC, no matter the kind of connection.
 // TODO: a lot of logging disappeared
-async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
+async fn multi_stream_connection_task<TPlat: Platform>(
     mut connection: TPlat::Connection,
+    is_webrtc: bool,
     shared: Arc<Shared<TPlat>>,
     connection_id: service::ConnectionId,
     mut connection_task: service::MultiStreamConnectionTask<TPlat::Instant, usize>,
@@ -479,8 +495,9 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
                 let writable_bytes = cmp::min(TPlat::writable_bytes(substream), write_buffer.len());
 
                 let incoming_buffer = match TPlat::read_buffer(substream) {
-                    ReadBuffer::Open(buf) => buf,
-                    ReadBuffer::Closed => panic!(), // Forbidden for WebRTC.
+                    ReadBuffer::Open(buf) => Some(buf),
+                    ReadBuffer::Closed if is_webrtc => panic!(), // Forbidden for WebRTC.
+                    ReadBuffer::Closed => None,
                     ReadBuffer::Reset => {
                         // Inform the connection task. The substream is now considered dead.
                         connection_task.reset_substream(&substream_id);
@@ -491,7 +508,7 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
 
                 let mut read_write = ReadWrite {
                     now: now.clone(),
-                    incoming_buffer: Some(incoming_buffer),
+                    incoming_buffer,
                     outgoing_buffer: if *write_side_was_open {
                         Some((&mut write_buffer[..writable_bytes], &mut []))
                     } else {
@@ -502,7 +519,7 @@ async fn webrtc_multi_stream_connection_task<TPlat: Platform>(
                     wake_up_after,
                 };
 
-                debug_assert!(read_write.outgoing_buffer.is_some());
+                debug_assert!(!is_webrtc || read_write.outgoing_buffer.is_some());
 
                 let substream_fate =
                     connection_task.substream_read_write(&substream_id, &mut read_write);
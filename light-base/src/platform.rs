@@ -18,8 +18,12 @@
 use alloc::{string::String, vec::Vec};
 use core::{ops, str, time::Duration};
 use futures::prelude::*;
+use smoldot::libp2p::{connection::tls, PeerId};
 
 pub mod async_std;
+pub mod tokio;
+
+mod with_buffers;
 
 /// Access to a platform's capabilities.
 pub trait Platform: Send + 'static {
@@ -87,7 +91,20 @@ pub trait Platform: Send + 'static {
     ///
     /// The multiaddress is passed as a string. If the string can't be parsed, an error should be
     /// returned where [`ConnectError::is_bad_addr`] is `true`.
-    fn connect(url: &str) -> Self::ConnectFuture;
+    ///
+    /// The address can also designate a telemetry server, in which case it ends with
+    /// `/x-parity-ws/<path>` or `/x-parity-wss/<path>` where the path is percent-encoded (see
+    /// [`smoldot::telemetry::parse_endpoint`]). The implementation must then open a WebSocket
+    /// connection to the given path and return it as a
+    /// [`PlatformConnection::SingleStreamMultistreamSelectNoiseYamux`]. The data passed to each
+    /// call to [`Platform::send`] on this connection should be sent as a separate WebSocket
+    /// message. Platforms that don't support this can return an error where
+    /// [`ConnectError::is_bad_addr`] is `true`.
+    ///
+    /// `tls_certificate` is the certificate of the local node, generated from its libp2p
+    /// identity. It must be presented to the remote during the TLS handshake of QUIC
+    /// connections, and can be ignored by platforms that don't support QUIC.
+    fn connect(url: &str, tls_certificate: &tls::GeneratedCertificate) -> Self::ConnectFuture;
 
     /// Queues the opening of an additional outbound substream.
     ///
@@ -206,6 +223,18 @@ pub enum PlatformConnection<TStream, TConnection> {
         /// Multihash encoding of the TLS certificate used by the remote node at the DTLS layer.
         remote_tls_certificate_multihash: Vec<u8>,
     },
+    /// The connection is made of multiple substreams, each corresponding to a bidirectional QUIC
+    /// stream. The encryption and multiplexing are handled externally. The remote must have been
+    /// authenticated using the libp2p TLS handshake (see
+    /// [`smoldot::libp2p::connection::tls`]). Contrary to WebRTC, the reading and writing sides
+    /// of substreams can be closed.
+    MultiStreamQuic {
+        /// Object representing the QUIC connection.
+        connection: TConnection,
+        /// Identity of the remote, as found in the certificate it has presented during the TLS
+        /// handshake.
+        remote_peer_id: PeerId,
+    },
 }
 
 /// Direction in which a substream has been opened. See [`Platform::next_substream`].
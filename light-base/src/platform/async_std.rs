@@ -18,28 +18,43 @@
 #![cfg(feature = "std")]
 #![cfg_attr(docsrs, doc(cfg(feature = "std")))]
 
-use super::{ConnectError, Platform, PlatformConnection, PlatformSubstreamDirection, ReadBuffer};
+use super::{
+    with_buffers::WithBuffers, ConnectError, Platform, PlatformConnection,
+    PlatformSubstreamDirection, ReadBuffer,
+};
 
-use alloc::collections::VecDeque;
-use core::{ops, pin::Pin, str, task::Poll, time::Duration};
+use core::{str, time::Duration};
 use futures::prelude::*;
-use smoldot::libp2p::{
-    multiaddr::{Multiaddr, ProtocolRef},
-    websocket,
+use smoldot::{
+    libp2p::{
+        connection::tls,
+        multiaddr::{Multiaddr, ProtocolRef},
+        websocket,
+    },
+    telemetry,
 };
-use std::{
-    io::IoSlice,
-    net::{IpAddr, SocketAddr},
+use std::net::{IpAddr, SocketAddr};
+
+#[cfg(feature = "quic")]
+use {
+    alloc::sync::Arc,
+    core::task::Poll,
+    futures::stream::FuturesUnordered,
+    smoldot::libp2p::async_std_connection::quic,
+    std::net::{Ipv4Addr, Ipv6Addr},
 };
 
 /// Implementation of the [`Platform`] trait that uses the `async-std` library and provides TCP
-/// and WebSocket connections.
+/// and WebSocket connections, plus QUIC connections if the `quic` feature is enabled.
 pub struct AsyncStdTcpWebSocket;
 
 impl Platform for AsyncStdTcpWebSocket {
     type Delay = future::BoxFuture<'static, ()>;
     type Yield = future::Ready<()>;
     type Instant = std::time::Instant;
+    #[cfg(feature = "quic")]
+    type Connection = Connection;
+    #[cfg(not(feature = "quic"))]
     type Connection = std::convert::Infallible;
     type Stream = Stream;
     type ConnectFuture = future::BoxFuture<
@@ -47,6 +62,10 @@ impl Platform for AsyncStdTcpWebSocket {
         Result<PlatformConnection<Self::Stream, Self::Connection>, ConnectError>,
     >;
     type StreamUpdateFuture<'a> = future::BoxFuture<'a, ()>;
+    #[cfg(feature = "quic")]
+    type NextSubstreamFuture<'a> =
+        future::BoxFuture<'a, Option<(Self::Stream, PlatformSubstreamDirection)>>;
+    #[cfg(not(feature = "quic"))]
     type NextSubstreamFuture<'a> =
         future::Pending<Option<(Self::Stream, PlatformSubstreamDirection)>>;
 
@@ -73,12 +92,25 @@ impl Platform for AsyncStdTcpWebSocket {
         future::ready(())
     }
 
-    fn connect(multiaddr: &str) -> Self::ConnectFuture {
+    fn connect(
+        multiaddr: &str,
+        tls_certificate: &tls::GeneratedCertificate,
+    ) -> Self::ConnectFuture {
         // We simply copy the address to own it. We could be more zero-cost here, but doing so
         // would considerably complicate the implementation.
         let multiaddr = multiaddr.to_owned();
+        #[cfg(feature = "quic")]
+        let tls_certificate = tls_certificate.clone();
+        #[cfg(not(feature = "quic"))]
+        let _ = tls_certificate;
 
         Box::pin(async move {
+            // Telemetry servers use the non-standard `x-parity-ws` protocol, which the
+            // `multiaddr` module doesn't support.
+            if multiaddr.contains("/x-parity-ws") {
+                return connect_telemetry(&multiaddr).await;
+            }
+
             let addr = multiaddr.parse::<Multiaddr>().map_err(|_| ConnectError {
                 is_bad_addr: true,
                 message: "Failed to parse address".to_string(),
@@ -102,6 +134,29 @@ impl Platform for AsyncStdTcpWebSocket {
                 });
             }
 
+            // QUIC connections are handled separately, as they don't go through TCP.
+            #[cfg(feature = "quic")]
+            match (&proto1, &proto2, &proto3) {
+                (ProtocolRef::Ip4(ip), ProtocolRef::Udp(port), Some(ProtocolRef::QuicV1)) => {
+                    let addr = SocketAddr::new(IpAddr::V4((*ip).into()), *port);
+                    return connect_quic(either::Left(addr), &tls_certificate).await;
+                }
+                (ProtocolRef::Ip6(ip), ProtocolRef::Udp(port), Some(ProtocolRef::QuicV1)) => {
+                    let addr = SocketAddr::new(IpAddr::V6((*ip).into()), *port);
+                    return connect_quic(either::Left(addr), &tls_certificate).await;
+                }
+                // TODO: we don't care about the differences between Dns, Dns4, and Dns6
+                (
+                    ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+                    ProtocolRef::Udp(port),
+                    Some(ProtocolRef::QuicV1),
+                ) => {
+                    return connect_quic(either::Right((addr.to_string(), *port)), &tls_certificate)
+                        .await
+                }
+                _ => {}
+            }
+
             // TODO: doesn't support WebSocket secure connections
 
             // Ensure ahead of time that the multiaddress is supported.
@@ -179,234 +234,274 @@ impl Platform for AsyncStdTcpWebSocket {
                 }
             };
 
+            #[cfg(feature = "quic")]
+            let socket = future::Either::Left(socket);
+
             Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
                 Stream {
-                    socket,
-                    buffers: Some((
-                        StreamReadBuffer::Open {
-                            buffer: vec![0; 16384],
-                            cursor: 0..0,
-                        },
-                        StreamWriteBuffer::Open {
-                            buffer: VecDeque::with_capacity(16384),
-                            must_close: false,
-                            must_flush: false,
-                        },
-                    )),
+                    inner: WithBuffers::new(socket),
                 },
             ))
         })
     }
 
+    #[cfg(not(feature = "quic"))]
     fn open_out_substream(c: &mut Self::Connection) {
         // This function can only be called with so-called "multi-stream" connections. We never
         // open such connection.
         match *c {}
     }
 
+    #[cfg(not(feature = "quic"))]
     fn next_substream(c: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
         // This function can only be called with so-called "multi-stream" connections. We never
         // open such connection.
         match *c {}
     }
 
-    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
-        Box::pin(future::poll_fn(|cx| {
-            let Some((read_buffer, write_buffer)) = stream.buffers.as_mut() else { return Poll::Pending };
-
-            // Whether the future returned by `update_stream` should return `Ready` or `Pending`.
-            let mut update_stream_future_ready = false;
-
-            if let StreamReadBuffer::Open {
-                buffer: ref mut buf,
-                ref mut cursor,
-            } = read_buffer
-            {
-                // When reading data from the socket, `poll_read` might return "EOF". In that
-                // situation, we transition to the `Closed` state, which would discard the data
-                // currently in the buffer. For this reason, we only try to read if there is no
-                // data left in the buffer.
-                if cursor.start == cursor.end {
-                    if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_read(cx, buf) {
-                        update_stream_future_ready = true;
-                        match result {
-                            Err(_) => {
-                                // End the stream.
-                                stream.buffers = None;
-                                return Poll::Ready(());
-                            }
-                            Ok(0) => {
-                                // EOF.
-                                *read_buffer = StreamReadBuffer::Closed;
-                            }
-                            Ok(bytes) => {
-                                *cursor = 0..bytes;
-                            }
-                        }
-                    }
-                }
-            }
-
-            if let StreamWriteBuffer::Open {
-                buffer: ref mut buf,
-                must_flush,
-                must_close,
-            } = write_buffer
-            {
-                while !buf.is_empty() {
-                    let write_queue_slices = buf.as_slices();
-                    if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_write_vectored(
-                        cx,
-                        &[
-                            IoSlice::new(write_queue_slices.0),
-                            IoSlice::new(write_queue_slices.1),
-                        ],
-                    ) {
-                        if !*must_close {
-                            // In the situation where the API user wants to close the writing
-                            // side, simply sending the buffered data isn't enough to justify
-                            // making the future ready.
-                            update_stream_future_ready = true;
-                        }
-
-                        match result {
-                            Err(_) => {
-                                // End the stream.
-                                stream.buffers = None;
-                                return Poll::Ready(());
-                            }
-                            Ok(bytes) => {
-                                *must_flush = true;
-                                for _ in 0..bytes {
-                                    buf.pop_front();
-                                }
-                            }
-                        }
-                    } else {
-                        break;
-                    }
-                }
+    #[cfg(feature = "quic")]
+    fn open_out_substream(c: &mut Self::Connection) {
+        let connection = c.connection.clone();
+        c.substreams
+            .get_mut()
+            .pending_outbound
+            .push(Box::pin(async move { connection.open_bi().await }));
+    }
 
-                if buf.is_empty() && *must_close {
-                    if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_close(cx) {
-                        update_stream_future_ready = true;
-                        match result {
-                            Err(_) => {
-                                // End the stream.
-                                stream.buffers = None;
-                                return Poll::Ready(());
-                            }
-                            Ok(()) => {
-                                *write_buffer = StreamWriteBuffer::Closed;
-                            }
-                        }
-                    }
-                } else if *must_flush {
-                    if let Poll::Ready(result) = Pin::new(&mut stream.socket).poll_flush(cx) {
-                        update_stream_future_ready = true;
-                        match result {
-                            Err(_) => {
-                                // End the stream.
-                                stream.buffers = None;
-                                return Poll::Ready(());
-                            }
-                            Ok(()) => {
-                                *must_flush = false;
-                            }
-                        }
-                    }
-                }
+    #[cfg(feature = "quic")]
+    fn next_substream(c: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
+        Box::pin(future::poll_fn(move |cx| {
+            let substreams = c.substreams.get_mut();
+
+            if let Poll::Ready(Some(result)) = substreams.pending_outbound.poll_next_unpin(cx) {
+                // Failing to open a stream means that the connection is dead.
+                let Ok((send, recv)) = result else {
+                    return Poll::Ready(None);
+                };
+                return Poll::Ready(Some((
+                    Stream {
+                        inner: WithBuffers::new(future::Either::Right(quic::QuicStream::new(
+                            send, recv,
+                        ))),
+                    },
+                    PlatformSubstreamDirection::Outbound,
+                )));
             }
 
-            if update_stream_future_ready {
-                Poll::Ready(())
-            } else {
-                Poll::Pending
+            if let Poll::Ready(result) = substreams.inbound.poll_unpin(cx) {
+                // Failing to accept a stream means that the connection is dead.
+                let Ok((send, recv)) = result else {
+                    return Poll::Ready(None);
+                };
+                substreams.inbound = accept_bi(&c.connection);
+                return Poll::Ready(Some((
+                    Stream {
+                        inner: WithBuffers::new(future::Either::Right(quic::QuicStream::new(
+                            send, recv,
+                        ))),
+                    },
+                    PlatformSubstreamDirection::Inbound,
+                )));
             }
+
+            Poll::Pending
         }))
     }
 
+    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
+        Box::pin(future::poll_fn(|cx| stream.inner.poll_update(cx)))
+    }
+
     fn read_buffer(stream: &mut Self::Stream) -> ReadBuffer {
-        match stream.buffers.as_ref().map(|(r, _)| r) {
-            None => ReadBuffer::Reset,
-            Some(StreamReadBuffer::Closed) => ReadBuffer::Closed,
-            Some(StreamReadBuffer::Open { buffer, cursor }) => {
-                ReadBuffer::Open(&buffer[cursor.clone()])
-            }
-        }
+        stream.inner.read_buffer()
     }
 
     fn advance_read_cursor(stream: &mut Self::Stream, extra_bytes: usize) {
-        let Some(StreamReadBuffer::Open { ref mut cursor, .. }) =
-            stream.buffers.as_mut().map(|(r, _)| r)
-        else {
-            assert_eq!(extra_bytes, 0);
-            return
-        };
-
-        assert!(cursor.start + extra_bytes <= cursor.end);
-        cursor.start += extra_bytes;
+        stream.inner.advance_read_cursor(extra_bytes)
     }
 
     fn writable_bytes(stream: &mut Self::Stream) -> usize {
-        let Some(StreamWriteBuffer::Open { ref mut buffer, must_close: false, ..}) =
-            stream.buffers.as_mut().map(|(_, w)| w) else { return 0 };
-        buffer.capacity() - buffer.len()
+        stream.inner.writable_bytes()
     }
 
     fn send(stream: &mut Self::Stream, data: &[u8]) {
-        debug_assert!(!data.is_empty());
-
-        // Because `writable_bytes` returns 0 if the writing side is closed, and because `data`
-        // must always have a size inferior or equal to `writable_bytes`, we know for sure that
-        // the writing side isn't closed.
-        let Some(StreamWriteBuffer::Open { ref mut buffer, .. } )=
-            stream.buffers.as_mut().map(|(_, w)| w) else { panic!() };
-        buffer.reserve(data.len());
-        buffer.extend(data.iter().copied());
+        stream.inner.send(data)
     }
 
     fn close_send(stream: &mut Self::Stream) {
-        // It is not illegal to call this on an already-reset stream.
-        let Some((_, write_buffer)) = stream.buffers.as_mut() else { return };
-
-        match write_buffer {
-            StreamWriteBuffer::Open {
-                must_close: must_close @ false,
-                ..
-            } => *must_close = true,
-            _ => {
-                // However, it is illegal to call this on a stream that was already close
-                // attempted.
-                panic!()
-            }
-        }
+        stream.inner.close_send()
     }
 }
 
 /// Implementation detail of [`AsyncStdTcpWebSocket`].
-pub struct Stream {
-    socket: TcpOrWs,
-    /// Read and write buffers of the connection, or `None` if the socket has been reset.
-    buffers: Option<(StreamReadBuffer, StreamWriteBuffer)>,
+#[cfg(feature = "quic")]
+pub struct Connection {
+    /// QUIC endpoint the connection goes through. Each connection has its own endpoint.
+    _endpoint: quinn::Endpoint,
+    connection: quinn::Connection,
+    /// Wrapped within a `Mutex` only in order for `Connection` to implement `Sync`. Never
+    /// locked, as it is only ever accessed through `&mut`.
+    substreams: parking_lot::Mutex<ConnectionSubstreams>,
 }
 
-enum StreamReadBuffer {
-    Open {
-        buffer: Vec<u8>,
-        cursor: ops::Range<usize>,
-    },
-    Closed,
+#[cfg(feature = "quic")]
+struct ConnectionSubstreams {
+    /// Outbound substreams that are being opened.
+    pending_outbound: FuturesUnordered<OpenStreamFuture>,
+    /// Future that is ready when the remote opens a new substream.
+    inbound: OpenStreamFuture,
 }
 
-enum StreamWriteBuffer {
-    Open {
-        buffer: VecDeque<u8>,
-        must_flush: bool,
-        must_close: bool,
-    },
-    Closed,
+#[cfg(feature = "quic")]
+type OpenStreamFuture = future::BoxFuture<
+    'static,
+    Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>,
+>;
+
+/// Returns a future that accepts the next bidirectional stream opened by the remote.
+#[cfg(feature = "quic")]
+fn accept_bi(connection: &quinn::Connection) -> OpenStreamFuture {
+    let connection = connection.clone();
+    Box::pin(async move { connection.accept_bi().await })
+}
+
+/// Connects to the given QUIC address.
+#[cfg(feature = "quic")]
+async fn connect_quic(
+    target: either::Either<SocketAddr, (String, u16)>,
+    tls_certificate: &tls::GeneratedCertificate,
+) -> Result<PlatformConnection<Stream, Connection>, ConnectError> {
+    let target = match target {
+        either::Left(addr) => addr,
+        either::Right((dns, port)) => {
+            async_std::net::ToSocketAddrs::to_socket_addrs(&(&dns[..], port))
+                .await
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or_else(|| ConnectError {
+                    is_bad_addr: false,
+                    message: format!("Failed to resolve {dns}"),
+                })?
+        }
+    };
+
+    let socket = std::net::UdpSocket::bind(if target.is_ipv4() {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    } else {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+    })
+    .map_err(|err| ConnectError {
+        is_bad_addr: false,
+        message: format!("Failed to bind UDP socket: {err}"),
+    })?;
+
+    let client_config = quic::client_config(tls_certificate).map_err(|err| ConnectError {
+        is_bad_addr: false,
+        message: format!("Failed to build TLS configuration: {err}"),
+    })?;
+
+    let mut endpoint = quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        None,
+        socket,
+        Arc::new(quinn::AsyncStdRuntime),
+    )
+    .map_err(|err| ConnectError {
+        is_bad_addr: false,
+        message: format!("Failed to create QUIC endpoint: {err}"),
+    })?;
+    endpoint.set_default_client_config(client_config);
+
+    // The server name is irrelevant, as it isn't verified.
+    let connection = endpoint
+        .connect(target, "libp2p")
+        .map_err(|err| ConnectError {
+            is_bad_addr: true,
+            message: format!("Failed to reach peer: {err}"),
+        })?
+        .await
+        .map_err(|err| ConnectError {
+            is_bad_addr: false,
+            message: format!("Failed to reach peer: {err}"),
+        })?;
+
+    let remote_peer_id = quic::remote_peer_id(&connection).ok_or_else(|| ConnectError {
+        is_bad_addr: false,
+        message: "Invalid remote certificate".to_string(),
+    })?;
+
+    Ok(PlatformConnection::MultiStreamQuic {
+        connection: Connection {
+            _endpoint: endpoint,
+            substreams: parking_lot::Mutex::new(ConnectionSubstreams {
+                pending_outbound: FuturesUnordered::new(),
+                inbound: accept_bi(&connection),
+            }),
+            connection,
+        },
+        remote_peer_id,
+    })
+}
+
+/// Opens a WebSocket connection to a telemetry server, whose address is in the format of
+/// [`smoldot::telemetry::parse_endpoint`].
+async fn connect_telemetry(
+    address: &str,
+) -> Result<PlatformConnection<Stream, <AsyncStdTcpWebSocket as Platform>::Connection>, ConnectError>
+{
+    let endpoint = telemetry::parse_endpoint(address, 0).map_err(|err| ConnectError {
+        is_bad_addr: true,
+        message: format!("Failed to parse address: {err}"),
+    })?;
+
+    // TODO: doesn't support WebSocket secure connections
+    if endpoint.secure {
+        return Err(ConnectError {
+            is_bad_addr: true,
+            message: "WebSocket secure connections aren't supported".to_string(),
+        });
+    }
+
+    let tcp_socket = async_std::net::TcpStream::connect((&endpoint.host[..], endpoint.port))
+        .await
+        .map_err(|err| ConnectError {
+            is_bad_addr: false,
+            message: format!("Failed to reach peer: {err}"),
+        })?;
+    let _ = tcp_socket.set_nodelay(true);
+
+    let socket: TcpOrWs = future::Either::Right(
+        websocket::websocket_client_handshake(websocket::Config {
+            tcp_socket,
+            host: &endpoint.host_header(),
+            url: &endpoint.path,
+        })
+        .await
+        .map_err(|err| ConnectError {
+            message: format!("Failed to negotiate WebSocket: {err}"),
+            is_bad_addr: false,
+        })?,
+    );
+
+    #[cfg(feature = "quic")]
+    let socket = future::Either::Left(socket);
+
+    Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
+        Stream {
+            inner: WithBuffers::new(socket),
+        },
+    ))
+}
+
+/// Implementation detail of [`AsyncStdTcpWebSocket`].
+pub struct Stream {
+    inner: WithBuffers<StreamSocket>,
 }
 
+#[cfg(feature = "quic")]
+type StreamSocket = future::Either<TcpOrWs, quic::QuicStream>;
+#[cfg(not(feature = "quic"))]
+type StreamSocket = TcpOrWs;
+
 type TcpOrWs =
     future::Either<async_std::net::TcpStream, websocket::Connection<async_std::net::TcpStream>>;
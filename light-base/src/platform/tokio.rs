@@ -0,0 +1,298 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(feature = "tokio")]
+#![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+
+use super::{
+    with_buffers::WithBuffers, ConnectError, Platform, PlatformConnection,
+    PlatformSubstreamDirection, ReadBuffer,
+};
+
+use core::time::Duration;
+use futures::prelude::*;
+use smoldot::{
+    libp2p::{
+        connection::tls,
+        multiaddr::{Multiaddr, ProtocolRef},
+        websocket,
+    },
+    telemetry,
+};
+use std::net::{IpAddr, SocketAddr};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt as _};
+
+/// Implementation of the [`Platform`] trait that uses the `tokio` library and provides TCP
+/// and WebSocket connections.
+///
+/// The futures returned by the functions of this trait must be polled from within the context
+/// of a tokio runtime with the time and I/O drivers enabled.
+pub struct TokioTcpWebSocket;
+
+impl Platform for TokioTcpWebSocket {
+    type Delay = future::BoxFuture<'static, ()>;
+    type Yield = future::BoxFuture<'static, ()>;
+    type Instant = std::time::Instant;
+    type Connection = std::convert::Infallible;
+    type Stream = Stream;
+    type ConnectFuture = future::BoxFuture<
+        'static,
+        Result<PlatformConnection<Self::Stream, Self::Connection>, ConnectError>,
+    >;
+    type StreamUpdateFuture<'a> = future::BoxFuture<'a, ()>;
+    type NextSubstreamFuture<'a> =
+        future::Pending<Option<(Self::Stream, PlatformSubstreamDirection)>>;
+
+    fn now_from_unix_epoch() -> Duration {
+        // Intentionally panic if the time is configured earlier than the UNIX EPOCH.
+        std::time::UNIX_EPOCH.elapsed().unwrap()
+    }
+
+    fn now() -> Self::Instant {
+        std::time::Instant::now()
+    }
+
+    fn sleep(duration: Duration) -> Self::Delay {
+        // Creating a tokio timer panics if done outside of the context of a runtime. The timer
+        // is consequently only created when the future is first polled.
+        Box::pin(async move { tokio::time::sleep(duration).await })
+    }
+
+    fn sleep_until(when: Self::Instant) -> Self::Delay {
+        let when = tokio::time::Instant::from_std(when);
+        Box::pin(async move { tokio::time::sleep_until(when).await })
+    }
+
+    fn yield_after_cpu_intensive() -> Self::Yield {
+        // Yielding gives the tokio scheduler the possibility to run other tasks on this thread.
+        Box::pin(tokio::task::yield_now())
+    }
+
+    fn connect(multiaddr: &str, _: &tls::GeneratedCertificate) -> Self::ConnectFuture {
+        // We simply copy the address to own it. We could be more zero-cost here, but doing so
+        // would considerably complicate the implementation.
+        let multiaddr = multiaddr.to_owned();
+
+        Box::pin(async move {
+            // Telemetry servers use the non-standard `x-parity-ws` protocol, which the
+            // `multiaddr` module doesn't support.
+            if multiaddr.contains("/x-parity-ws") {
+                return connect_telemetry(&multiaddr).await;
+            }
+
+            let addr = multiaddr.parse::<Multiaddr>().map_err(|_| ConnectError {
+                is_bad_addr: true,
+                message: "Failed to parse address".to_string(),
+            })?;
+
+            let mut iter = addr.iter().fuse();
+            let proto1 = iter.next().ok_or(ConnectError {
+                is_bad_addr: true,
+                message: "Unknown protocols combination".to_string(),
+            })?;
+            let proto2 = iter.next().ok_or(ConnectError {
+                is_bad_addr: true,
+                message: "Unknown protocols combination".to_string(),
+            })?;
+            let proto3 = iter.next();
+
+            if iter.next().is_some() {
+                return Err(ConnectError {
+                    is_bad_addr: true,
+                    message: "Unknown protocols combination".to_string(),
+                });
+            }
+
+            // TODO: doesn't support WebSocket secure connections
+
+            // Ensure ahead of time that the multiaddress is supported.
+            let (addr, host_if_websocket) = match (&proto1, &proto2, &proto3) {
+                (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), None) => (
+                    either::Left(SocketAddr::new(IpAddr::V4((*ip).into()), *port)),
+                    None,
+                ),
+                (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), None) => (
+                    either::Left(SocketAddr::new(IpAddr::V6((*ip).into()), *port)),
+                    None,
+                ),
+                (ProtocolRef::Ip4(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
+                    let addr = SocketAddr::new(IpAddr::V4((*ip).into()), *port);
+                    (either::Left(addr), Some(addr.to_string()))
+                }
+                (ProtocolRef::Ip6(ip), ProtocolRef::Tcp(port), Some(ProtocolRef::Ws)) => {
+                    let addr = SocketAddr::new(IpAddr::V6((*ip).into()), *port);
+                    (either::Left(addr), Some(addr.to_string()))
+                }
+
+                // TODO: we don't care about the differences between Dns, Dns4, and Dns6
+                (
+                    ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+                    ProtocolRef::Tcp(port),
+                    None,
+                ) => (either::Right((addr.to_string(), *port)), None),
+                (
+                    ProtocolRef::Dns(addr) | ProtocolRef::Dns4(addr) | ProtocolRef::Dns6(addr),
+                    ProtocolRef::Tcp(port),
+                    Some(ProtocolRef::Ws),
+                ) => (
+                    either::Right((addr.to_string(), *port)),
+                    Some(format!("{}:{}", addr, *port)),
+                ),
+
+                _ => {
+                    return Err(ConnectError {
+                        is_bad_addr: true,
+                        message: "Unknown protocols combination".to_string(),
+                    })
+                }
+            };
+
+            let tcp_socket = match addr {
+                either::Left(socket_addr) => tokio::net::TcpStream::connect(socket_addr).await,
+                either::Right((dns, port)) => {
+                    tokio::net::TcpStream::connect((&dns[..], port)).await
+                }
+            };
+
+            if let Ok(tcp_socket) = &tcp_socket {
+                let _ = tcp_socket.set_nodelay(true);
+            }
+
+            // The `tokio` I/O traits are converted to the `futures` ones, which the WebSocket
+            // implementation and the buffers use.
+            let socket: TcpOrWs = match (tcp_socket, host_if_websocket) {
+                (Ok(tcp_socket), Some(host)) => future::Either::Right(
+                    websocket::websocket_client_handshake(websocket::Config {
+                        tcp_socket: tcp_socket.compat(),
+                        host: &host,
+                        url: "/",
+                    })
+                    .await
+                    .map_err(|err| ConnectError {
+                        message: format!("Failed to negotiate WebSocket: {err}"),
+                        is_bad_addr: false,
+                    })?,
+                ),
+                (Ok(tcp_socket), None) => future::Either::Left(tcp_socket.compat()),
+                (Err(err), _) => {
+                    return Err(ConnectError {
+                        is_bad_addr: false,
+                        message: format!("Failed to reach peer: {err}"),
+                    })
+                }
+            };
+
+            Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
+                Stream {
+                    inner: WithBuffers::new(socket),
+                },
+            ))
+        })
+    }
+
+    fn open_out_substream(c: &mut Self::Connection) {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn next_substream(c: &'_ mut Self::Connection) -> Self::NextSubstreamFuture<'_> {
+        // This function can only be called with so-called "multi-stream" connections. We never
+        // open such connection.
+        match *c {}
+    }
+
+    fn update_stream(stream: &'_ mut Self::Stream) -> Self::StreamUpdateFuture<'_> {
+        Box::pin(future::poll_fn(|cx| stream.inner.poll_update(cx)))
+    }
+
+    fn read_buffer(stream: &mut Self::Stream) -> ReadBuffer {
+        stream.inner.read_buffer()
+    }
+
+    fn advance_read_cursor(stream: &mut Self::Stream, extra_bytes: usize) {
+        stream.inner.advance_read_cursor(extra_bytes)
+    }
+
+    fn writable_bytes(stream: &mut Self::Stream) -> usize {
+        stream.inner.writable_bytes()
+    }
+
+    fn send(stream: &mut Self::Stream, data: &[u8]) {
+        stream.inner.send(data)
+    }
+
+    fn close_send(stream: &mut Self::Stream) {
+        stream.inner.close_send()
+    }
+}
+
+/// Opens a WebSocket connection to a telemetry server, whose address is in the format of
+/// [`smoldot::telemetry::parse_endpoint`].
+async fn connect_telemetry(
+    address: &str,
+) -> Result<PlatformConnection<Stream, <TokioTcpWebSocket as Platform>::Connection>, ConnectError> {
+    let endpoint = telemetry::parse_endpoint(address, 0).map_err(|err| ConnectError {
+        is_bad_addr: true,
+        message: format!("Failed to parse address: {err}"),
+    })?;
+
+    // TODO: doesn't support WebSocket secure connections
+    if endpoint.secure {
+        return Err(ConnectError {
+            is_bad_addr: true,
+            message: "WebSocket secure connections aren't supported".to_string(),
+        });
+    }
+
+    let tcp_socket = tokio::net::TcpStream::connect((&endpoint.host[..], endpoint.port))
+        .await
+        .map_err(|err| ConnectError {
+            is_bad_addr: false,
+            message: format!("Failed to reach peer: {err}"),
+        })?;
+    let _ = tcp_socket.set_nodelay(true);
+
+    let socket: TcpOrWs = future::Either::Right(
+        websocket::websocket_client_handshake(websocket::Config {
+            tcp_socket: tcp_socket.compat(),
+            host: &endpoint.host_header(),
+            url: &endpoint.path,
+        })
+        .await
+        .map_err(|err| ConnectError {
+            message: format!("Failed to negotiate WebSocket: {err}"),
+            is_bad_addr: false,
+        })?,
+    );
+
+    Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(
+        Stream {
+            inner: WithBuffers::new(socket),
+        },
+    ))
+}
+
+/// Implementation detail of [`TokioTcpWebSocket`].
+pub struct Stream {
+    inner: WithBuffers<TcpOrWs>,
+}
+
+type TcpOrWs = future::Either<
+    Compat<tokio::net::TcpStream>,
+    websocket::Connection<Compat<tokio::net::TcpStream>>,
+>;
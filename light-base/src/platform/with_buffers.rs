@@ -0,0 +1,271 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Read and write buffers on top of a socket, shared between the implementations of the
+//! [`Platform`](super::Platform) trait that use the `futures` I/O traits.
+
+#![cfg(any(feature = "std", feature = "tokio"))]
+
+use super::ReadBuffer;
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::{
+    ops,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::prelude::*;
+use std::io::IoSlice;
+
+/// Socket and its read and write buffers.
+///
+/// The methods of this struct implement the stream-related functions of
+/// [`Platform`](super::Platform).
+pub(super) struct WithBuffers<T> {
+    socket: T,
+    /// Read and write buffers of the connection, or `None` if the socket has been reset.
+    buffers: Option<(StreamReadBuffer, StreamWriteBuffer)>,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> WithBuffers<T> {
+    /// Initializes a new [`WithBuffers`] around the given socket.
+    pub(super) fn new(socket: T) -> Self {
+        WithBuffers {
+            socket,
+            buffers: Some((
+                StreamReadBuffer::Open {
+                    buffer: vec![0; 16384],
+                    cursor: 0..0,
+                },
+                StreamWriteBuffer::Open {
+                    buffer: VecDeque::with_capacity(16384),
+                    must_close: false,
+                    must_flush: false,
+                },
+            )),
+        }
+    }
+
+    /// See [`Platform::update_stream`](super::Platform::update_stream).
+    ///
+    /// Returns `Ready` when the future returned by `update_stream` should be ready.
+    pub(super) fn poll_update(&mut self, cx: &mut Context) -> Poll<()> {
+        let Some((read_buffer, write_buffer)) = self.buffers.as_mut() else {
+            return Poll::Pending;
+        };
+
+        // Whether the future returned by `update_stream` should return `Ready` or `Pending`.
+        let mut update_stream_future_ready = false;
+
+        if let StreamReadBuffer::Open {
+            buffer: ref mut buf,
+            ref mut cursor,
+        } = read_buffer
+        {
+            // When reading data from the socket, `poll_read` might return "EOF". In that
+            // situation, we transition to the `Closed` state, which would discard the data
+            // currently in the buffer. For this reason, we only try to read if there is no
+            // data left in the buffer.
+            if cursor.start == cursor.end {
+                if let Poll::Ready(result) = Pin::new(&mut self.socket).poll_read(cx, buf) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            self.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(0) => {
+                            // EOF.
+                            *read_buffer = StreamReadBuffer::Closed;
+                        }
+                        Ok(bytes) => {
+                            *cursor = 0..bytes;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let StreamWriteBuffer::Open {
+            buffer: ref mut buf,
+            must_flush,
+            must_close,
+        } = write_buffer
+        {
+            while !buf.is_empty() {
+                let write_queue_slices = buf.as_slices();
+                if let Poll::Ready(result) = Pin::new(&mut self.socket).poll_write_vectored(
+                    cx,
+                    &[
+                        IoSlice::new(write_queue_slices.0),
+                        IoSlice::new(write_queue_slices.1),
+                    ],
+                ) {
+                    if !*must_close {
+                        // In the situation where the API user wants to close the writing
+                        // side, simply sending the buffered data isn't enough to justify
+                        // making the future ready.
+                        update_stream_future_ready = true;
+                    }
+
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            self.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(bytes) => {
+                            *must_flush = true;
+                            for _ in 0..bytes {
+                                buf.pop_front();
+                            }
+                        }
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            if buf.is_empty() && *must_close {
+                if let Poll::Ready(result) = Pin::new(&mut self.socket).poll_close(cx) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            self.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(()) => {
+                            *write_buffer = StreamWriteBuffer::Closed;
+                        }
+                    }
+                }
+            } else if *must_flush {
+                if let Poll::Ready(result) = Pin::new(&mut self.socket).poll_flush(cx) {
+                    update_stream_future_ready = true;
+                    match result {
+                        Err(_) => {
+                            // End the stream.
+                            self.buffers = None;
+                            return Poll::Ready(());
+                        }
+                        Ok(()) => {
+                            *must_flush = false;
+                        }
+                    }
+                }
+            }
+        }
+
+        if update_stream_future_ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// See [`Platform::read_buffer`](super::Platform::read_buffer).
+    pub(super) fn read_buffer(&mut self) -> ReadBuffer<'_> {
+        match self.buffers.as_ref().map(|(r, _)| r) {
+            None => ReadBuffer::Reset,
+            Some(StreamReadBuffer::Closed) => ReadBuffer::Closed,
+            Some(StreamReadBuffer::Open { buffer, cursor }) => {
+                ReadBuffer::Open(&buffer[cursor.clone()])
+            }
+        }
+    }
+
+    /// See [`Platform::advance_read_cursor`](super::Platform::advance_read_cursor).
+    pub(super) fn advance_read_cursor(&mut self, extra_bytes: usize) {
+        let Some(StreamReadBuffer::Open { ref mut cursor, .. }) =
+            self.buffers.as_mut().map(|(r, _)| r)
+        else {
+            assert_eq!(extra_bytes, 0);
+            return;
+        };
+
+        assert!(cursor.start + extra_bytes <= cursor.end);
+        cursor.start += extra_bytes;
+    }
+
+    /// See [`Platform::writable_bytes`](super::Platform::writable_bytes).
+    pub(super) fn writable_bytes(&mut self) -> usize {
+        let Some(StreamWriteBuffer::Open {
+            ref mut buffer,
+            must_close: false,
+            ..
+        }) = self.buffers.as_mut().map(|(_, w)| w)
+        else {
+            return 0;
+        };
+        buffer.capacity() - buffer.len()
+    }
+
+    /// See [`Platform::send`](super::Platform::send).
+    pub(super) fn send(&mut self, data: &[u8]) {
+        debug_assert!(!data.is_empty());
+
+        // Because `writable_bytes` returns 0 if the writing side is closed, and because `data`
+        // must always have a size inferior or equal to `writable_bytes`, we know for sure that
+        // the writing side isn't closed.
+        let Some(StreamWriteBuffer::Open { ref mut buffer, .. }) =
+            self.buffers.as_mut().map(|(_, w)| w)
+        else {
+            panic!()
+        };
+        buffer.reserve(data.len());
+        buffer.extend(data.iter().copied());
+    }
+
+    /// See [`Platform::close_send`](super::Platform::close_send).
+    pub(super) fn close_send(&mut self) {
+        // It is not illegal to call this on an already-reset stream.
+        let Some((_, write_buffer)) = self.buffers.as_mut() else {
+            return;
+        };
+
+        match write_buffer {
+            StreamWriteBuffer::Open {
+                must_close: must_close @ false,
+                ..
+            } => *must_close = true,
+            _ => {
+                // However, it is illegal to call this on a stream that was already close
+                // attempted.
+                panic!()
+            }
+        }
+    }
+}
+
+enum StreamReadBuffer {
+    Open {
+        buffer: Vec<u8>,
+        cursor: ops::Range<usize>,
+    },
+    Closed,
+}
+
+enum StreamWriteBuffer {
+    Open {
+        buffer: VecDeque<u8>,
+        must_flush: bool,
+        must_close: bool,
+    },
+    Closed,
+}
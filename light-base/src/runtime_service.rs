@@ -54,7 +54,7 @@
 //! large, the subscription is force-killed by the [`RuntimeService`].
 //!
 
-use crate::{platform::Platform, sync_service};
+use crate::{network_service, platform::Platform, sync_service};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -67,7 +67,7 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter, mem,
+    fmt, iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
     time::Duration,
@@ -76,12 +76,14 @@ use futures::{
     channel::mpsc,
     lock::{Mutex, MutexGuard},
     prelude::*,
+    stream,
 };
 use itertools::Itertools as _;
 use smoldot::{
     chain::async_tree,
     executor, header,
     informant::{BytesDisplay, HashDisplay},
+    libp2p::PeerId,
     network::protocol,
     trie::{self, proof_decode, TrieEntryVersion},
 };
@@ -102,6 +104,41 @@ pub struct Config<TPlat: Platform> {
 
     /// Header of the genesis block of the chain, in SCALE encoding.
     pub genesis_block_scale_encoded_header: Vec<u8>,
+
+    /// Runtime code of a block, known ahead of time, for example because it was stored in a
+    /// database. If the sync service reports this block as the finalized block when the runtime
+    /// service starts, then this runtime is used rather than downloaded from the network.
+    pub runtime_code_hint: Option<ConfigRuntimeCodeHint>,
+}
+
+/// See [`Config::runtime_code_hint`].
+pub struct ConfigRuntimeCodeHint {
+    /// Hash of the block whose runtime is provided.
+    pub block_hash: [u8; 32],
+    /// Storage value of the `:code` key of this block.
+    ///
+    /// > **Note**: This value is trusted. It is the responsibility of the user to verify that
+    /// >           it matches the storage of the block.
+    pub code: Vec<u8>,
+    /// Storage value of the `:heappages` key of this block, or `None` if there is no entry in
+    /// the storage.
+    pub heap_pages: Option<Vec<u8>>,
+    /// SCALE-encoded header of a block and Merkle proof of the `:code` and `:heappages` keys
+    /// against the state root of this block, if any. Returned again by
+    /// [`RuntimeService::finalized_runtime_code_proof`] as long as this runtime is in use.
+    pub code_proof: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// See [`RuntimeService::finalized_runtime_code_proof`].
+pub struct RuntimeCodeProof {
+    /// Hash of the finalized block whose runtime the proof is about.
+    pub finalized_block_hash: [u8; 32],
+    /// SCALE-encoded header of the block the proof has been generated against. This is either
+    /// the finalized block or one of its ancestors using the same runtime.
+    pub proof_block_scale_encoded_header: Vec<u8>,
+    /// Merkle proof of the `:code` and `:heappages` keys against the state root of
+    /// [`RuntimeCodeProof::proof_block_scale_encoded_header`].
+    pub proof: Vec<u8>,
 }
 
 /// Identifies a runtime currently pinned within a [`RuntimeService`].
@@ -168,8 +205,9 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
         (config.tasks_executor)(log_target.clone(), {
             let sync_service = config.sync_service.clone();
             let guarded = guarded.clone();
+            let runtime_code_hint = config.runtime_code_hint;
             let (abortable, abort) = future::abortable(async move {
-                run_background(log_target, sync_service, guarded).await;
+                run_background(log_target, sync_service, guarded, runtime_code_hint).await;
             });
             background_task_abort = abort;
             abortable.map(|_| ()).boxed()
@@ -535,6 +573,7 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
             let runtime = Arc::new(Runtime {
                 heap_pages: storage_heap_pages,
                 runtime_code: storage_code,
+                code_proof: None,
                 runtime,
             });
             guarded.runtimes.insert(Arc::downgrade(&runtime));
@@ -556,6 +595,33 @@ impl<TPlat: Platform> RuntimeService<TPlat> {
         drop(id);
     }
 
+    /// Returns a Merkle proof of the runtime code of the current finalized block, as it was
+    /// obtained when this runtime was downloaded.
+    ///
+    /// Returns `None` if the runtime of the finalized block isn't known yet, or if no proof is
+    /// available for it. This is notably the case for runtimes passed through
+    /// [`RuntimeService::compile_and_pin_runtime`].
+    ///
+    /// This function doesn't perform any networking request.
+    pub async fn finalized_runtime_code_proof(&self) -> Option<RuntimeCodeProof> {
+        let guarded = self.guarded.lock().await;
+        match &guarded.tree {
+            GuardedInner::FinalizedBlockRuntimeKnown {
+                tree,
+                finalized_block,
+                ..
+            } => {
+                let (header, proof) = tree.finalized_async_user_data().code_proof.as_ref()?;
+                Some(RuntimeCodeProof {
+                    finalized_block_hash: finalized_block.hash,
+                    proof_block_scale_encoded_header: header.clone(),
+                    proof: proof.clone(),
+                })
+            }
+            GuardedInner::FinalizedBlockRuntimeUnknown { .. } => None,
+        }
+    }
+
     /// Returns true if it is believed that we are near the head of the chain.
     ///
     /// The way this method is implemented is opaque and cannot be relied on. The return value
@@ -769,98 +835,335 @@ impl<TPlat: Platform> RuntimeLock<TPlat> {
         }
     }
 
-    pub async fn start<'b>(
+    /// Performs a runtime call.
+    ///
+    /// A call proof is requested from the peers that are assumed to know the block, until a
+    /// valid call proof is obtained. At most `max_parallel` peers are queried at the same time,
+    /// and at most `total_attempts` peers are queried in total. Peers that send back an invalid
+    /// proof are banned.
+    ///
+    /// `execute` is then called with the virtual machine of the runtime, and must perform the
+    /// call using the storage entries found with [`RuntimeCallLock::storage_entry`] and
+    /// [`RuntimeCallLock::storage_prefix_keys_ordered`]. It must give back the virtual machine
+    /// alongside with the outcome of the call.
+    ///
+    /// If `execute` reports that an entry is missing from the call proof, the peer that has
+    /// sent the proof is banned, a call proof is requested from the next peers, and `execute` is
+    /// called again.
+    pub async fn run<'b, T, E>(
         &'b self,
         method: &'b str,
         parameter_vectored: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
         total_attempts: u32,
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
-    ) -> Result<(RuntimeCallLock<'b>, executor::host::HostVmPrototype), RuntimeCallError> {
-        // TODO: DRY :-/ this whole thing is messy
+        execute: impl FnMut(
+            &RuntimeCallLock<'b, TPlat>,
+            executor::host::HostVmPrototype,
+        ) -> (
+            executor::host::HostVmPrototype,
+            Result<T, RunAttemptError<E>>,
+        ),
+    ) -> Result<T, RunError<E>> {
+        let call_parameters = parameter_vectored.fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        self.run_multiple(
+            iter::once((method, call_parameters)),
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+            execute,
+        )
+        .await
+    }
+
+    /// Similar to [`RuntimeLock::run`], but for multiple runtime calls performed one after the
+    /// other, such as `Core_initialize_block` followed with another function.
+    ///
+    /// Each element of `calls` is the name of the runtime function and its concatenated
+    /// parameters. One call proof per element is requested from the same peer, and
+    /// [`RuntimeCallLock::storage_entry`] looks for the key in all of these proofs.
+    ///
+    /// Because these call proofs are generated by the peer against the storage of the block,
+    /// the proof of a call can legitimately miss storage values that the call only reads as a
+    /// consequence of the storage changes performed by the calls before it. When `execute`
+    /// reports with [`RunAttemptError::MissingStorageValue`] that a storage value is missing,
+    /// this value is downloaded separately and `execute` is called again.
+    pub async fn run_multiple<'b, T, E>(
+        &'b self,
+        calls: impl Iterator<Item = (&'b str, Vec<u8>)>,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+        mut execute: impl FnMut(
+            &RuntimeCallLock<'b, TPlat>,
+            executor::host::HostVmPrototype,
+        ) -> (
+            executor::host::HostVmPrototype,
+            Result<T, RunAttemptError<E>>,
+        ),
+    ) -> Result<T, RunError<E>> {
+        let (mut runtime_call_lock, mut virtual_machine) = self
+            .start(calls, total_attempts, timeout_per_request, max_parallel)
+            .await
+            .map_err(RunError::Call)?;
+
+        loop {
+            let (vm, outcome) = execute(&runtime_call_lock, virtual_machine);
+            virtual_machine = vm;
+
+            let retry = match outcome {
+                Ok(output) => {
+                    runtime_call_lock.unlock(virtual_machine);
+                    return Ok(output);
+                }
+                Err(RunAttemptError::Other(error)) => {
+                    runtime_call_lock.unlock(virtual_machine);
+                    return Err(RunError::Other(error));
+                }
+                Err(RunAttemptError::MissingStorageValue(key))
+                    if runtime_call_lock.call_proof_fetch.calls.len() >= 2 =>
+                {
+                    runtime_call_lock.download_storage_value(&key).await
+                }
+                Err(
+                    RunAttemptError::MissingStorageValue(_) | RunAttemptError::MissingProofEntry,
+                ) => runtime_call_lock.retry_with_new_proof().await,
+            };
+
+            if let Err(error) = retry {
+                runtime_call_lock.unlock(virtual_machine);
+                return Err(RunError::Call(error));
+            }
+        }
+    }
+
+    /// Requests the call proofs of the given calls and locks the virtual machine of the runtime.
+    async fn start<'b>(
+        &'b self,
+        calls: impl Iterator<Item = (&'b str, Vec<u8>)>,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> Result<(RuntimeCallLock<'b, TPlat>, executor::host::HostVmPrototype), RuntimeCallError>
+    {
+        // Make sure that the runtime is valid before performing any network request.
+        let runtime = match self.runtime.runtime.as_ref() {
+            Ok(r) => r,
+            Err(err) => return Err(RuntimeCallError::InvalidRuntime(err.clone())),
+        };
+
+        // TODO: better peers selection ; don't just take the first
+        let mut call_proof_fetch = CallProofFetch {
+            calls: calls.collect(),
+            remaining_peers: self
+                .sync_service
+                .peers_assumed_know_blocks(self.block_number, &self.hash)
+                .await
+                .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
+                .collect::<Vec<_>>()
+                .into_iter(),
+            total_attempts,
+            timeout_per_request,
+            max_parallel,
+            errors: Vec::new(),
+        };
 
         // Perform the call proof request.
         // Note that `guarded` is not locked.
-        // TODO: there's no way to verify that the call proof is actually correct; we have to ban the peer and restart the whole call process if it turns out that it's not
-        // TODO: also, an empty proof will be reported as an error right now, which is weird
-        let call_proof = self
-            .sync_service
-            .clone()
-            .call_proof_query(
-                self.block_number,
-                protocol::CallProofRequestConfig {
-                    block_hash: self.hash,
-                    method,
-                    parameter_vectored: parameter_vectored.clone(),
-                },
-                total_attempts,
-                timeout_per_request,
-                max_parallel,
-            )
+        let (call_proof_sender, call_proofs) = self
+            .fetch_call_proof(&mut call_proof_fetch)
             .await
-            .map_err(RuntimeCallError::CallProof);
-
-        let call_proof = call_proof.and_then(|call_proof| {
-            proof_decode::decode_and_verify_proof(proof_decode::Config {
-                proof: call_proof.decode().to_owned(), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
-                trie_root_hash: &self.block_state_root_hash,
-            })
-            .map_err(RuntimeCallError::StorageRetrieval)
-        });
+            .map_err(RuntimeCallError::CallProof)?;
 
-        let (guarded, virtual_machine) = match self.runtime.runtime.as_ref() {
-            Ok(r) => {
-                let mut lock = r.virtual_machine.lock().await;
-                let vm = lock.take().unwrap();
-                (lock, vm)
-            }
-            Err(err) => {
-                return Err(RuntimeCallError::InvalidRuntime(err.clone()));
-            }
+        let (guarded, virtual_machine) = {
+            let mut lock = runtime.virtual_machine.lock().await;
+            let vm = lock.take().unwrap();
+            (lock, vm)
         };
 
         let lock = RuntimeCallLock {
             guarded,
-            block_state_root_hash: self.block_state_root_hash,
-            call_proof,
+            runtime_lock: self,
+            call_proof_fetch,
+            call_proof_sender,
+            call_proofs,
         };
 
         Ok((lock, virtual_machine))
     }
+
+    /// Queries the peers remaining in the given [`CallProofFetch`], at most
+    /// [`CallProofFetch::max_parallel`] at a time, until one of them sends back a valid call
+    /// proof for each of the calls.
+    ///
+    /// The requests still in progress when a valid call proof is received are cancelled.
+    async fn fetch_call_proof(
+        &self,
+        call_proof_fetch: &mut CallProofFetch<'_>,
+    ) -> Result<(PeerId, Vec<proof_decode::DecodedTrieProof<Vec<u8>>>), CallProofError> {
+        let mut in_progress = stream::FuturesUnordered::new();
+
+        loop {
+            while in_progress.len()
+                < usize::try_from(call_proof_fetch.max_parallel.get()).unwrap_or(usize::max_value())
+            {
+                let target = match call_proof_fetch.remaining_peers.next() {
+                    Some(t) => t,
+                    None => break,
+                };
+
+                in_progress.push(self.fetch_call_proof_from_peer(
+                    target,
+                    &call_proof_fetch.calls,
+                    call_proof_fetch.timeout_per_request,
+                ));
+            }
+
+            match in_progress.next().await {
+                Some((target, Ok(call_proofs))) => return Ok((target, call_proofs)),
+                Some((target, Err(error))) => call_proof_fetch.errors.push((target, error)),
+                None => {
+                    return Err(CallProofError {
+                        errors: call_proof_fetch.errors.clone(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Requests from the given peer a call proof for each of the given calls.
+    async fn fetch_call_proof_from_peer(
+        &self,
+        target: PeerId,
+        calls: &[(&str, Vec<u8>)],
+        timeout_per_request: Duration,
+    ) -> (
+        PeerId,
+        Result<Vec<proof_decode::DecodedTrieProof<Vec<u8>>>, CallProofErrorDetail>,
+    ) {
+        let mut call_proofs = Vec::with_capacity(calls.len());
+
+        for (method, call_parameters) in calls {
+            let result = self
+                .sync_service
+                .clone()
+                .call_proof_request(
+                    target.clone(),
+                    protocol::CallProofRequestConfig {
+                        block_hash: self.hash,
+                        method,
+                        parameter_vectored: iter::once(call_parameters),
+                    },
+                    timeout_per_request,
+                )
+                .await;
+
+            let error = match result {
+                // Substrate responds with an empty proof to requests about blocks it doesn't
+                // know. This isn't considered as a misbehavior.
+                Ok(proof) if proof.decode().is_empty() => CallProofErrorDetail::EmptyProof,
+                Ok(proof) => {
+                    match proof_decode::decode_and_verify_proof(proof_decode::Config {
+                        proof: proof.decode().to_owned(), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
+                        trie_root_hash: &self.block_state_root_hash,
+                    }) {
+                        Ok(decoded) => {
+                            call_proofs.push(decoded);
+                            continue;
+                        }
+                        Err(err) => {
+                            self.sync_service
+                                .ban_peer(target.clone(), "invalid-call-proof")
+                                .await;
+                            CallProofErrorDetail::InvalidProof(err)
+                        }
+                    }
+                }
+                Err(err) => CallProofErrorDetail::Network(err),
+            };
+
+            return (target, Err(error));
+        }
+
+        (target, Ok(call_proofs))
+    }
 }
 
-/// See [`RuntimeService::pinned_block_runtime_lock`].
+/// Error returned by the closure passed to [`RuntimeLock::run`] and
+/// [`RuntimeLock::run_multiple`].
+#[derive(Debug, Clone)]
+pub enum RunAttemptError<E> {
+    /// The storage value of the given key is missing from the call proofs.
+    MissingStorageValue(Vec<u8>),
+    /// An entry other than a storage value, such as the list of keys that start with a certain
+    /// prefix, is missing from the call proofs.
+    MissingProofEntry,
+    /// Error specific to the call being performed. The call isn't attempted again.
+    Other(E),
+}
+
+/// Error potentially returned by [`RuntimeLock::run`] and [`RuntimeLock::run_multiple`].
+#[derive(Debug, Clone)]
+pub enum RunError<E> {
+    /// Failed to obtain the information necessary to perform the call.
+    Call(RuntimeCallError),
+    /// Error returned by the closure passed by the API user.
+    Other(E),
+}
+
+/// State of the call proof requests of a [`RuntimeCallLock`].
+struct CallProofFetch<'a> {
+    /// Names of the runtime functions being called, and their concatenated parameters.
+    calls: Vec<(&'a str, Vec<u8>)>,
+    /// Peers that haven't been queried yet and that can be queried in case of failure.
+    remaining_peers: vec::IntoIter<PeerId>,
+    /// Maximum number of peers to query for the call proofs, and for each storage value
+    /// downloaded separately.
+    total_attempts: u32,
+    /// Timeout of each individual request.
+    timeout_per_request: Duration,
+    /// Maximum number of requests in progress at the same time.
+    max_parallel: NonZeroU32,
+    /// Errors that happened so far, one per peer.
+    errors: Vec<(PeerId, CallProofErrorDetail)>,
+}
+
+/// See [`RuntimeLock::run`].
 #[must_use]
-pub struct RuntimeCallLock<'a> {
+pub struct RuntimeCallLock<'a, TPlat: Platform> {
     guarded: MutexGuard<'a, Option<executor::host::HostVmPrototype>>,
-    block_state_root_hash: [u8; 32],
-    call_proof: Result<trie::proof_decode::DecodedTrieProof<Vec<u8>>, RuntimeCallError>,
+    runtime_lock: &'a RuntimeLock<TPlat>,
+    call_proof_fetch: CallProofFetch<'a>,
+    /// Peer that has sent [`RuntimeCallLock::call_proofs`].
+    call_proof_sender: PeerId,
+    /// One call proof per element of [`CallProofFetch::calls`], followed with the storage
+    /// proofs downloaded with [`RuntimeCallLock::download_storage_value`].
+    call_proofs: Vec<proof_decode::DecodedTrieProof<Vec<u8>>>,
 }
 
-impl<'a> RuntimeCallLock<'a> {
+impl<'a, TPlat: Platform> RuntimeCallLock<'a, TPlat> {
     /// Returns the storage root of the block the call is being made against.
     pub fn block_storage_root(&self) -> &[u8; 32] {
-        &self.block_state_root_hash
+        &self.runtime_lock.block_state_root_hash
     }
 
     /// Finds the given key in the call proof and returns the associated storage value.
     ///
-    /// Returns an error if the key couldn't be found in the proof, meaning that the proof is
-    /// invalid.
-    // TODO: if proof is invalid, we should give the option to fetch another call proof
+    /// Returns an error if the key couldn't be found in the proof. In that situation, the
+    /// closure passed to [`RuntimeLock::run`] should return
+    /// [`RunAttemptError::MissingStorageValue`].
     pub fn storage_entry(
         &self,
         requested_key: &[u8],
     ) -> Result<Option<(&[u8], TrieEntryVersion)>, RuntimeCallError> {
-        let call_proof = match &self.call_proof {
-            Ok(p) => p,
-            Err(err) => return Err(err.clone()),
-        };
-
-        match call_proof.storage_value(requested_key) {
-            Some(v) => Ok(v),
-            None => Err(RuntimeCallError::MissingProofEntry),
-        }
+        self.call_proofs
+            .iter()
+            .find_map(|call_proof| call_proof.storage_value(requested_key))
+            .ok_or(RuntimeCallError::MissingProofEntry)
     }
 
     /// Finds in the call proof the list of keys that match a certain prefix.
@@ -869,20 +1172,33 @@ impl<'a> RuntimeCallLock<'a> {
     /// is invalid.
     ///
     /// The keys returned are ordered lexicographically.
-    // TODO: if proof is invalid, we should give the option to fetch another call proof
+    ///
+    /// If the proof turns out to be invalid, the closure passed to [`RuntimeLock::run`] should
+    /// return [`RunAttemptError::MissingProofEntry`].
     pub fn storage_prefix_keys_ordered(
         &'_ self,
         prefix: &[u8],
     ) -> Result<impl Iterator<Item = impl AsRef<[u8]> + '_>, RuntimeCallError> {
+        // Each call proof is tried one after the other.
+        let mut error = RuntimeCallError::MissingProofEntry;
+        for call_proof in &self.call_proofs {
+            match Self::prefix_keys_ordered_in_proof(call_proof, prefix) {
+                Ok(keys) => return Ok(keys),
+                Err(err) => error = err,
+            }
+        }
+        Err(error)
+    }
+
+    /// Implementation of [`RuntimeCallLock::storage_prefix_keys_ordered`] for a single proof.
+    fn prefix_keys_ordered_in_proof(
+        call_proof: &proof_decode::DecodedTrieProof<Vec<u8>>,
+        prefix: &[u8],
+    ) -> Result<vec::IntoIter<Vec<u8>>, RuntimeCallError> {
         // TODO: this could be a function in the proof_decode module
         let mut to_find = vec![trie::bytes_to_nibbles(prefix.iter().copied()).collect::<Vec<_>>()];
         let mut output = Vec::new();
 
-        let call_proof = match &self.call_proof {
-            Ok(p) => p,
-            Err(err) => return Err(err.clone()),
-        };
-
         for key in mem::take(&mut to_find) {
             let node_info = call_proof
                 .trie_node_info(&key)
@@ -915,16 +1231,78 @@ impl<'a> RuntimeCallLock<'a> {
         Ok(output.into_iter())
     }
 
+    /// Reports that the call proof currently in use is missing an entry necessary to the call.
+    ///
+    /// The peer that has sent this call proof is banned, and a new call proof is requested from
+    /// the next peers, if any attempt remains.
+    ///
+    /// On success, the runtime call must be restarted from the beginning, as the previous
+    /// storage accesses were performed against a different proof. On failure, the error
+    /// contains the list of all the peers that have been queried, and
+    /// [`RuntimeCallLock::unlock`] must then be called.
+    async fn retry_with_new_proof(&mut self) -> Result<(), RuntimeCallError> {
+        self.runtime_lock
+            .sync_service
+            .ban_peer(self.call_proof_sender.clone(), "missing-call-proof-entry")
+            .await;
+        self.call_proof_fetch.errors.push((
+            self.call_proof_sender.clone(),
+            CallProofErrorDetail::MissingProofEntry,
+        ));
+
+        let (call_proof_sender, call_proofs) = self
+            .runtime_lock
+            .fetch_call_proof(&mut self.call_proof_fetch)
+            .await
+            .map_err(RuntimeCallError::CallProof)?;
+
+        self.call_proof_sender = call_proof_sender;
+        self.call_proofs = call_proofs;
+        Ok(())
+    }
+
+    /// Downloads a storage proof of the given key and adds it to the proofs that
+    /// [`RuntimeCallLock::storage_entry`] looks into.
+    ///
+    /// On success, the runtime call must be restarted from the beginning. On failure,
+    /// [`RuntimeCallLock::unlock`] must then be called.
+    async fn download_storage_value(&mut self, key: &[u8]) -> Result<(), RuntimeCallError> {
+        let (_, proof) = self
+            .runtime_lock
+            .sync_service
+            .clone()
+            .storage_query_with_proof(
+                self.runtime_lock.block_number,
+                &self.runtime_lock.hash,
+                &self.runtime_lock.block_state_root_hash,
+                iter::once(key),
+                self.call_proof_fetch.total_attempts,
+                self.call_proof_fetch.timeout_per_request,
+                self.call_proof_fetch.max_parallel,
+            )
+            .await
+            .map_err(RuntimeCallError::StorageQuery)?;
+
+        // The proof has already been verified by the sync service.
+        let proof = proof_decode::decode_and_verify_proof(proof_decode::Config {
+            proof,
+            trie_root_hash: &self.runtime_lock.block_state_root_hash,
+        })
+        .unwrap();
+        self.call_proofs.push(proof);
+        Ok(())
+    }
+
     /// End the runtime call.
     ///
     /// This method **must** be called.
-    pub fn unlock(mut self, vm: executor::host::HostVmPrototype) {
+    fn unlock(mut self, vm: executor::host::HostVmPrototype) {
         debug_assert!(self.guarded.is_none());
         *self.guarded = Some(vm);
     }
 }
 
-impl<'a> Drop for RuntimeCallLock<'a> {
+impl<'a, TPlat: Platform> Drop for RuntimeCallLock<'a, TPlat> {
     fn drop(&mut self) {
         if self.guarded.is_none() {
             // The [`RuntimeCallLock`] has been destroyed without being properly unlocked.
@@ -940,15 +1318,11 @@ pub enum RuntimeCallError {
     /// Runtime of the block isn't valid.
     #[display(fmt = "Runtime of the block isn't valid: {_0}")]
     InvalidRuntime(RuntimeError),
-    /// Error while retrieving the storage item from other nodes.
-    // TODO: change error type?
-    #[display(fmt = "Error in call proof: {_0}")]
-    StorageRetrieval(proof_decode::Error),
     /// One or more entries are missing from the call proof.
     MissingProofEntry,
-    /// Error while retrieving the call proof from the network.
+    /// Failed to obtain a valid call proof from the network.
     #[display(fmt = "Error when retrieving the call proof: {_0}")]
-    CallProof(sync_service::CallProofQueryError),
+    CallProof(CallProofError),
     /// Error while querying the storage of the block.
     #[display(fmt = "Error while querying block storage: {_0}")]
     StorageQuery(sync_service::StorageQueryError),
@@ -960,7 +1334,6 @@ impl RuntimeCallError {
     pub fn is_network_problem(&self) -> bool {
         match self {
             RuntimeCallError::InvalidRuntime(_) => false,
-            RuntimeCallError::StorageRetrieval(_) => false,
             RuntimeCallError::MissingProofEntry => false,
             RuntimeCallError::CallProof(err) => err.is_network_problem(),
             RuntimeCallError::StorageQuery(err) => err.is_network_problem(),
@@ -968,6 +1341,60 @@ impl RuntimeCallError {
     }
 }
 
+/// See [`RuntimeCallError::CallProof`].
+#[derive(Debug, Clone)]
+pub struct CallProofError {
+    /// Contains one error per peer that has been queried, in the order in which they have been
+    /// queried. If this list is empty, then we aren't connected to any node that is assumed to
+    /// know the block.
+    pub errors: Vec<(PeerId, CallProofErrorDetail)>,
+}
+
+impl CallProofError {
+    /// Returns `true` if this is caused by networking issues, as opposed to a consensus-related
+    /// issue.
+    pub fn is_network_problem(&self) -> bool {
+        self.errors.iter().all(|(_, err)| match err {
+            CallProofErrorDetail::Network(err) => err.is_network_problem(),
+            CallProofErrorDetail::EmptyProof => true,
+            CallProofErrorDetail::InvalidProof(_) | CallProofErrorDetail::MissingProofEntry => {
+                false
+            }
+        })
+    }
+}
+
+impl fmt::Display for CallProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.errors.is_empty() {
+            write!(f, "No node available for call proof query")
+        } else {
+            write!(f, "Call proof query errors:")?;
+            for (peer_id, err) in &self.errors {
+                write!(f, "\n- {peer_id}: {err}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// See [`CallProofError`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum CallProofErrorDetail {
+    /// Error during the network request.
+    #[display(fmt = "{_0}")]
+    Network(network_service::CallProofRequestError),
+    /// The peer has sent back an empty proof, which typically indicates that it doesn't know
+    /// the block.
+    EmptyProof,
+    /// The proof couldn't be verified against the state root of the block. The peer has been
+    /// banned.
+    #[display(fmt = "{_0}")]
+    InvalidProof(proof_decode::Error),
+    /// The proof is missing an entry necessary to the call. The peer has been banned.
+    MissingProofEntry,
+}
+
 /// Error when analyzing the runtime.
 #[derive(Debug, derive_more::Display, Clone)]
 pub enum RuntimeError {
@@ -1095,6 +1522,7 @@ async fn run_background<TPlat: Platform>(
     log_target: String,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     guarded: Arc<Mutex<Guarded<TPlat>>>,
+    mut runtime_code_hint: Option<ConfigRuntimeCodeHint>,
 ) {
     loop {
         // The buffer size should be large enough so that, if the CPU is busy, it doesn't
@@ -1109,6 +1537,50 @@ async fn run_background<TPlat: Platform>(
             ))
         );
 
+        let finalized_block_hash = header::hash_from_scale_encoded_header(
+            &subscription.finalized_block_scale_encoded_header,
+        );
+
+        // Build the runtime of the finalized block, if it is known. The sync service might
+        // provide it, otherwise the hint passed through the configuration is used if it matches
+        // the finalized block. The hint is only ever used at most once.
+        let finalized_block_runtime =
+            if let Some(finalized_block_runtime) = subscription.finalized_block_runtime {
+                Some(Runtime {
+                    runtime_code: finalized_block_runtime.storage_code,
+                    heap_pages: finalized_block_runtime.storage_heap_pages,
+                    code_proof: finalized_block_runtime.storage_code_proof.map(|proof| {
+                        (
+                            subscription.finalized_block_scale_encoded_header.clone(),
+                            proof,
+                        )
+                    }),
+                    runtime: Ok(SuccessfulRuntime {
+                        runtime_spec: finalized_block_runtime
+                            .virtual_machine
+                            .runtime_version()
+                            .clone(),
+                        virtual_machine: Mutex::new(Some(finalized_block_runtime.virtual_machine)),
+                    }),
+                })
+            } else if let Some(hint) = runtime_code_hint
+                .take()
+                .filter(|hint| hint.block_hash == finalized_block_hash)
+            {
+                let runtime_code = Some(hint.code);
+                let heap_pages = hint.heap_pages;
+                let runtime =
+                    SuccessfulRuntime::from_storage::<TPlat>(&runtime_code, &heap_pages).await;
+                Some(Runtime {
+                    runtime_code,
+                    heap_pages,
+                    code_proof: hint.code_proof,
+                    runtime,
+                })
+            } else {
+                None
+            };
+
         // Update the state of `guarded` with what we just grabbed.
         //
         // Note that the content of `guarded` is reset unconditionally.
@@ -1132,30 +1604,16 @@ async fn run_background<TPlat: Platform>(
             lock.runtimes = slab::Slab::with_capacity(2); // TODO: hardcoded capacity
 
             // TODO: DRY below
-            if let Some(finalized_block_runtime) = subscription.finalized_block_runtime {
-                let finalized_block_hash = header::hash_from_scale_encoded_header(
-                    &subscription.finalized_block_scale_encoded_header,
-                );
-
+            if let Some(finalized_block_runtime) = finalized_block_runtime {
                 let storage_code_len = u64::try_from(
                     finalized_block_runtime
-                        .storage_code
+                        .runtime_code
                         .as_ref()
                         .map_or(0, |v| v.len()),
                 )
                 .unwrap();
 
-                let runtime = Arc::new(Runtime {
-                    runtime_code: finalized_block_runtime.storage_code,
-                    heap_pages: finalized_block_runtime.storage_heap_pages,
-                    runtime: Ok(SuccessfulRuntime {
-                        runtime_spec: finalized_block_runtime
-                            .virtual_machine
-                            .runtime_version()
-                            .clone(),
-                        virtual_machine: Mutex::new(Some(finalized_block_runtime.virtual_machine)),
-                    }),
-                });
+                let runtime = Arc::new(finalized_block_runtime);
 
                 match &runtime.runtime {
                     Ok(runtime) => {
@@ -1252,9 +1710,7 @@ async fn run_background<TPlat: Platform>(
                         });
                         let node_index = tree.input_insert_block(
                             Block {
-                                hash: header::hash_from_scale_encoded_header(
-                                    &subscription.finalized_block_scale_encoded_header,
-                                ),
+                                hash: finalized_block_hash,
                                 scale_encoded_header: subscription
                                     .finalized_block_scale_encoded_header,
                             },
@@ -1416,7 +1872,7 @@ async fn run_background<TPlat: Platform>(
                     }.format_with(", ", |block, fmt| fmt(&HashDisplay(&block.hash))).to_string();
 
                     match download_result {
-                        Ok((storage_code, storage_heap_pages)) => {
+                        Ok((storage_code, storage_heap_pages, code_proof)) => {
                             log::debug!(
                                 target: &log_target,
                                 "Worker <= SuccessfulDownload(blocks=[{}])",
@@ -1427,7 +1883,7 @@ async fn run_background<TPlat: Platform>(
                             guarded.best_near_head_of_chain = true;
                             drop(guarded);
 
-                            background.runtime_download_finished(async_op_id, storage_code, storage_heap_pages).await;
+                            background.runtime_download_finished(async_op_id, storage_code, storage_heap_pages, code_proof).await;
                         }
                         Err(error) => {
                             log::debug!(
@@ -1497,14 +1953,18 @@ struct Background<TPlat: Platform> {
     blocks_stream: Pin<Box<dyn Stream<Item = sync_service::Notification> + Send>>,
 
     /// List of runtimes currently being downloaded from the network.
-    /// For each item, the download id, storage value of `:code`, and storage value of
-    /// `:heappages`.
+    /// For each item, the download id, storage value of `:code`, storage value of
+    /// `:heappages`, and SCALE-encoded header of the block alongside with the Merkle proof of
+    /// these two values.
     runtime_downloads: stream::FuturesUnordered<
         future::BoxFuture<
             'static,
             (
                 async_tree::AsyncOpId,
-                Result<(Option<Vec<u8>>, Option<Vec<u8>>), RuntimeDownloadError>,
+                Result<
+                    (Option<Vec<u8>>, Option<Vec<u8>>, (Vec<u8>, Vec<u8>)),
+                    RuntimeDownloadError,
+                >,
             ),
         >,
     >,
@@ -1520,6 +1980,7 @@ impl<TPlat: Platform> Background<TPlat> {
         async_op_id: async_tree::AsyncOpId,
         storage_code: Option<Vec<u8>>,
         storage_heap_pages: Option<Vec<u8>>,
+        code_proof: (Vec<u8>, Vec<u8>),
     ) {
         let mut guarded = self.guarded.lock().await;
 
@@ -1561,6 +2022,7 @@ impl<TPlat: Platform> Background<TPlat> {
             let runtime = Arc::new(Runtime {
                 heap_pages: storage_heap_pages,
                 runtime_code: storage_code,
+                code_proof: Some(code_proof),
                 runtime,
             });
 
@@ -1886,10 +2348,12 @@ impl<TPlat: Platform> Background<TPlat> {
                         let block_hash = download_params.block_user_data.hash;
                         let state_root = *decoded_header.state_root;
                         let block_number = decoded_header.number;
+                        let scale_encoded_header =
+                            download_params.block_user_data.scale_encoded_header.clone();
 
                         Box::pin(async move {
                             let result = sync_service
-                                .storage_query(
+                                .storage_query_with_proof(
                                     block_number,
                                     &block_hash,
                                     &state_root,
@@ -1901,10 +2365,10 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .await;
 
                             let result = match result {
-                                Ok(mut c) => {
+                                Ok((mut c, proof)) => {
                                     let heap_pages = c.pop().unwrap();
                                     let code = c.pop().unwrap();
-                                    Ok((code, heap_pages))
+                                    Ok((code, heap_pages, (scale_encoded_header, proof)))
                                 }
                                 Err(error) => Err(RuntimeDownloadError::StorageQuery(error)),
                             };
@@ -1994,6 +2458,12 @@ struct Runtime {
     /// build.
     // TODO: consider storing hash instead
     heap_pages: Option<Vec<u8>>,
+
+    /// SCALE-encoded header of a block and Merkle proof of [`Runtime::runtime_code`] and
+    /// [`Runtime::heap_pages`] against the state root of this block.
+    ///
+    /// `None` if the runtime hasn't been obtained alongside with a proof.
+    code_proof: Option<(Vec<u8>, Vec<u8>)>,
 }
 
 struct SuccessfulRuntime {
@@ -2029,6 +2499,7 @@ impl SuccessfulRuntime {
             module,
             heap_pages,
             exec_hint,
+            resource_limits: Default::default(),
             allow_unresolved_imports: false,
         }) {
             Ok(vm) => {
@@ -2047,6 +2518,7 @@ impl SuccessfulRuntime {
                     module,
                     heap_pages,
                     exec_hint,
+                    resource_limits: Default::default(),
                     allow_unresolved_imports: true,
                 }) {
                     Ok(vm) => {
@@ -401,8 +401,37 @@ impl<TPlat: Platform> SyncService<TPlat> {
         requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
         total_attempts: u32,
         timeout_per_request: Duration,
-        _max_parallel: NonZeroU32,
+        max_parallel: NonZeroU32,
     ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        let (values, _) = self
+            .storage_query_with_proof(
+                block_number,
+                block_hash,
+                storage_trie_root,
+                requested_keys,
+                total_attempts,
+                timeout_per_request,
+                max_parallel,
+            )
+            .await?;
+        Ok(values)
+    }
+
+    /// Similar to [`SyncService::storage_query`], but additionally returns the SCALE-encoded
+    /// Merkle proof that the storage values have been extracted from.
+    ///
+    /// The proof has been verified against `storage_trie_root` and can be passed to
+    /// [`smoldot::trie::proof_decode::decode_and_verify_proof`] again later.
+    pub async fn storage_query_with_proof(
+        self: Arc<Self>,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        storage_trie_root: &[u8; 32],
+        requested_keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        _max_parallel: NonZeroU32,
+    ) -> Result<(Vec<Option<Vec<u8>>>, Vec<u8>), StorageQueryError> {
         let mut outcome_errors =
             Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
 
@@ -445,11 +474,11 @@ impl<TPlat: Platform> SyncService<TPlat> {
                         );
                     }
                     debug_assert_eq!(result.len(), result.capacity());
-                    Ok(result)
+                    Ok((result, outcome.decode().to_vec()))
                 });
 
             match result {
-                Ok(values) => return Ok(values),
+                Ok(values_and_proof) => return Ok(values_and_proof),
                 Err(err) => {
                     outcome_errors.push(err);
                 }
@@ -541,59 +570,35 @@ impl<TPlat: Platform> SyncService<TPlat> {
         }
     }
 
-    // TODO: documentation
-    // TODO: there's no proof that the call proof is actually correct
-    pub async fn call_proof_query<'a>(
+    /// Sends a call proof request to the given peer.
+    ///
+    /// The proof isn't verified. It is the responsibility of the caller to verify it, and to
+    /// call [`SyncService::ban_peer`] if the peer has misbehaved.
+    ///
+    /// See also [`network_service::NetworkService::call_proof_request`].
+    pub async fn call_proof_request<'a>(
         self: Arc<Self>,
-        block_number: u64,
+        target: PeerId,
         config: protocol::CallProofRequestConfig<
             'a,
             impl Iterator<Item = impl AsRef<[u8]>> + Clone,
         >,
-        total_attempts: u32,
-        timeout_per_request: Duration,
-        _max_parallel: NonZeroU32,
-    ) -> Result<network_service::EncodedMerkleProof, CallProofQueryError> {
-        let mut outcome_errors =
-            Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
-
-        // TODO: better peers selection ; don't just take the first
-        // TODO: handle max_parallel
-        for target in self
-            .peers_assumed_know_blocks(block_number, &config.block_hash)
+        timeout: Duration,
+    ) -> Result<network_service::EncodedMerkleProof, network_service::CallProofRequestError> {
+        self.network_service
+            .clone()
+            .call_proof_request(self.network_chain_index, target, config, timeout)
             .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
-        {
-            let result = self
-                .network_service
-                .clone()
-                .call_proof_request(
-                    self.network_chain_index,
-                    target,
-                    config.clone(),
-                    timeout_per_request,
-                )
-                .await;
-
-            match result {
-                Ok(value) if !value.decode().is_empty() => return Ok(value),
-                // TODO: this check of emptiness is a bit of a hack; it is necessary because Substrate responds to requests about blocks it doesn't know with an empty proof
-                Ok(_) => outcome_errors.push(network_service::CallProofRequestError::Request(
-                    service::CallProofRequestError::Request(
-                        smoldot::libp2p::peers::RequestError::Substream(
-                            smoldot::libp2p::connection::established::RequestError::SubstreamClosed,
-                        ),
-                    ),
-                )),
-                Err(err) => {
-                    outcome_errors.push(err);
-                }
-            }
-        }
+    }
 
-        Err(CallProofQueryError {
-            errors: outcome_errors,
-        })
+    /// Bans the given peer from the chain this sync service is responsible for, for example
+    /// because it has sent back an invalid proof.
+    ///
+    /// See also [`network_service::NetworkService::ban_peer`].
+    pub async fn ban_peer(&self, peer_id: PeerId, reason: &str) {
+        self.network_service
+            .ban_peer(self.network_chain_index, peer_id, reason)
+            .await
     }
 }
 
@@ -658,36 +663,6 @@ pub enum StorageQueryErrorDetail {
     MissingProofEntry,
 }
 
-/// Error that can happen when calling [`SyncService::call_proof_query`].
-#[derive(Debug, Clone)]
-pub struct CallProofQueryError {
-    /// Contains one error per peer that has been contacted. If this list is empty, then we
-    /// aren't connected to any node.
-    pub errors: Vec<network_service::CallProofRequestError>,
-}
-
-impl CallProofQueryError {
-    /// Returns `true` if this is caused by networking issues, as opposed to a consensus-related
-    /// issue.
-    pub fn is_network_problem(&self) -> bool {
-        self.errors.iter().all(|err| err.is_network_problem())
-    }
-}
-
-impl fmt::Display for CallProofQueryError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.errors.is_empty() {
-            write!(f, "No node available for call proof query")
-        } else {
-            write!(f, "Call proof query errors:")?;
-            for err in &self.errors {
-                write!(f, "\n- {err}")?;
-            }
-            Ok(())
-        }
-    }
-}
-
 /// Return value of [`SyncService::subscribe_all`].
 pub struct SubscribeAll {
     /// SCALE-encoded header of the finalized block at the time of the subscription.
@@ -725,6 +700,10 @@ pub struct FinalizedBlockRuntime {
 
     /// Storage value at the `:heappages` key.
     pub storage_heap_pages: Option<Vec<u8>>,
+
+    /// Merkle proof of the `:code` and `:heappages` keys against the state root of the
+    /// finalized block, if known.
+    pub storage_code_proof: Option<Vec<u8>>,
 }
 
 /// Notification about a new block or a new finalized block.
@@ -1104,8 +1104,9 @@ async fn parahead<TPlat: Platform>(
         }
     };
 
-    let (runtime_call_lock, virtual_machine) = precall
-        .start(
+    // TODO: move the logic below in the `para` module
+    let output = precall
+        .run(
             para::PERSISTED_VALIDATION_FUNCTION_NAME,
             para::persisted_validation_data_parameters(
                 parachain_id,
@@ -1114,66 +1115,84 @@ async fn parahead<TPlat: Platform>(
             6,
             Duration::from_secs(10),
             NonZeroU32::new(2).unwrap(),
-        )
-        .await
-        .map_err(ParaheadError::Call)?;
-
-    // TODO: move the logic below in the `para` module
-
-    let mut runtime_call = match read_only_runtime_host::run(read_only_runtime_host::Config {
-        virtual_machine,
-        function_to_call: para::PERSISTED_VALIDATION_FUNCTION_NAME,
-        parameter: para::persisted_validation_data_parameters(
-            parachain_id,
-            para::OccupiedCoreAssumption::TimedOut,
-        ),
-        max_log_level: 0,
-    }) {
-        Ok(vm) => vm,
-        Err((err, prototype)) => {
-            runtime_call_lock.unlock(prototype);
-            return Err(ParaheadError::StartError(err));
-        }
-    };
+            |runtime_call_lock, virtual_machine| {
+                let mut runtime_call =
+                    match read_only_runtime_host::run(read_only_runtime_host::Config {
+                        virtual_machine,
+                        function_to_call: para::PERSISTED_VALIDATION_FUNCTION_NAME,
+                        parameter: para::persisted_validation_data_parameters(
+                            parachain_id,
+                            para::OccupiedCoreAssumption::TimedOut,
+                        ),
+                        max_log_level: 0,
+                    }) {
+                        Ok(vm) => vm,
+                        Err((err, prototype)) => {
+                            return (
+                                prototype,
+                                Err(runtime_service::RunAttemptError::Other(
+                                    ParaheadError::StartError(err),
+                                )),
+                            )
+                        }
+                    };
 
-    let output = loop {
-        match runtime_call {
-            read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                let output = success.virtual_machine.value().as_ref().to_owned();
-                runtime_call_lock.unlock(success.virtual_machine.into_prototype());
-                break output;
-            }
-            read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
-                runtime_call_lock.unlock(error.prototype);
-                return Err(ParaheadError::ReadOnlyRuntime(error.detail));
-            }
-            read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
-                let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
-                let storage_value = match storage_value {
-                    Ok(v) => v.map(|(v, _)| v),
-                    Err(err) => {
-                        runtime_call_lock.unlock(
-                            read_only_runtime_host::RuntimeHostVm::StorageGet(get).into_prototype(),
-                        );
-                        return Err(ParaheadError::Call(err));
+                loop {
+                    match runtime_call {
+                        read_only_runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                            let output = success.virtual_machine.value().as_ref().to_owned();
+                            return (success.virtual_machine.into_prototype(), Ok(output));
+                        }
+                        read_only_runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                            return (
+                                error.prototype,
+                                Err(runtime_service::RunAttemptError::Other(
+                                    ParaheadError::ReadOnlyRuntime(error.detail),
+                                )),
+                            )
+                        }
+                        read_only_runtime_host::RuntimeHostVm::StorageGet(get) => {
+                            let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
+                            let storage_value = match storage_value {
+                                Ok(v) => v.map(|(v, _)| v),
+                                Err(_) => {
+                                    let key = get.key().as_ref().to_vec();
+                                    return (
+                                        read_only_runtime_host::RuntimeHostVm::StorageGet(get)
+                                            .into_prototype(),
+                                        Err(runtime_service::RunAttemptError::MissingStorageValue(
+                                            key,
+                                        )),
+                                    );
+                                }
+                            };
+                            runtime_call = get.inject_value(storage_value.map(iter::once));
+                        }
+                        read_only_runtime_host::RuntimeHostVm::NextKey(nk) => {
+                            // TODO:
+                            return (
+                                read_only_runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
+                                Err(runtime_service::RunAttemptError::Other(
+                                    ParaheadError::NextKeyForbidden,
+                                )),
+                            );
+                        }
+                        read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
+                            runtime_call =
+                                storage_root.resume(runtime_call_lock.block_storage_root());
+                        }
+                        read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                            runtime_call = sig.verify_and_resume();
+                        }
                     }
-                };
-                runtime_call = get.inject_value(storage_value.map(iter::once));
-            }
-            read_only_runtime_host::RuntimeHostVm::NextKey(nk) => {
-                // TODO:
-                runtime_call_lock
-                    .unlock(read_only_runtime_host::RuntimeHostVm::NextKey(nk).into_prototype());
-                return Err(ParaheadError::NextKeyForbidden);
-            }
-            read_only_runtime_host::RuntimeHostVm::StorageRoot(storage_root) => {
-                runtime_call = storage_root.resume(runtime_call_lock.block_storage_root());
-            }
-            read_only_runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
-                runtime_call = sig.verify_and_resume();
-            }
-        }
-    };
+                }
+            },
+        )
+        .await
+        .map_err(|error| match error {
+            runtime_service::RunError::Call(error) => ParaheadError::Call(error),
+            runtime_service::RunError::Other(error) => error,
+        })?;
 
     // Try decode the result of the runtime call.
     // If this fails, it indicates an incompatibility between smoldot and the relay chain.
@@ -33,6 +33,7 @@ use smoldot::{
     libp2p,
     network::{self, protocol},
     sync::all,
+    trie::proof_decode,
 };
 
 /// Starts a sync service background task to synchronize a standalone chain (relay chain or not).
@@ -78,6 +79,7 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
         network_up_to_date_best: true,
         network_up_to_date_finalized: true,
         known_finalized_runtime: None,
+        latest_storage_proof: None,
         pending_block_requests: stream::FuturesUnordered::new(),
         pending_grandpa_requests: stream::FuturesUnordered::new(),
         pending_storage_requests: stream::FuturesUnordered::new(),
@@ -159,20 +161,24 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
         if !task.network_up_to_date_finalized {
             // If the chain uses GrandPa, the networking has to be kept up-to-date with the
             // state of finalization for other peers to send back relevant gossip messages.
-            // (code style) `grandpa_set_id` is extracted first in order to avoid borrowing
+            // (code style) `grandpa_authorities` is extracted first in order to avoid borrowing
             // checker issues.
-            let grandpa_set_id =
+            let grandpa_authorities =
                 if let chain::chain_information::ChainInformationFinalityRef::Grandpa {
                     after_finalized_block_authorities_set_id,
+                    finalized_triggered_authorities,
                     ..
                 } = task.sync.as_chain_information().as_ref().finality
                 {
-                    Some(after_finalized_block_authorities_set_id)
+                    Some((
+                        after_finalized_block_authorities_set_id,
+                        finalized_triggered_authorities.to_vec(),
+                    ))
                 } else {
                     None
                 };
 
-            if let Some(set_id) = grandpa_set_id {
+            if let Some((set_id, authorities)) = grandpa_authorities {
                 let commit_finalized_height = task.sync.finalized_block_header().number;
                 task.network_service
                     .set_local_grandpa_state(
@@ -184,6 +190,16 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                         },
                     )
                     .await;
+
+                // The authorities are also necessary in order to verify the GrandPa votes
+                // gossiped by the other nodes.
+                task.network_service
+                    .set_grandpa_authorities(
+                        network_chain_index,
+                        set_id,
+                        authorities.iter().map(header::GrandpaAuthorityRef::from),
+                    )
+                    .await;
             }
 
             task.network_up_to_date_finalized = true;
@@ -279,6 +295,12 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 // A storage request has been finished.
                 // `result` is an error if the request got cancelled by the sync state machine.
                 if let Ok(result) = result {
+                    // The proof is kept in order to later be able to provide it alongside with
+                    // the runtime of the finalized block.
+                    if let Ok(proof) = &result {
+                        task.latest_storage_proof = Some(proof.clone());
+                    }
+
                     // Inject the result of the request into the sync state machine.
                     task.sync.storage_get_response(request_id, result).1
                 } else {
@@ -375,6 +397,13 @@ struct Task<TPlat: Platform> {
     /// If `Some`, contains the runtime of the current finalized block.
     known_finalized_runtime: Option<FinalizedBlockRuntime>,
 
+    /// Latest storage proof successfully downloaded on behalf of [`Task::sync`].
+    ///
+    /// The only storage proofs that the syncing state machine requests are the ones of the
+    /// runtime code of the block targeted by the warp syncing. This proof is kept in order to
+    /// be attached to [`Task::known_finalized_runtime`] once the warp syncing finishes.
+    latest_storage_proof: Option<Vec<u8>>,
+
     /// For each networking peer, the index of the corresponding peer within the [`Task::sync`].
     // TODO: use SipHasher
     peers_source_id_map: HashMap<libp2p::PeerId, all::SourceId, fnv::FnvBuildHasher>,
@@ -670,11 +699,38 @@ impl<TPlat: Platform> Task<TPlat> {
                 self.warp_sync_taking_long_time_warning =
                     future::Either::Right(future::pending()).fuse();
 
+                // The latest storage proof is normally the one the runtime has been obtained
+                // from. Since nothing strictly guarantees this, the proof is verified against
+                // the finalized block and discarded if it doesn't match.
+                let storage_code_proof = self.latest_storage_proof.take().filter(|proof| {
+                    let decoded =
+                        match proof_decode::decode_and_verify_proof(proof_decode::Config {
+                            proof: &proof[..],
+                            trie_root_hash: finalized_header.state_root,
+                        }) {
+                            Ok(d) => d,
+                            Err(_) => return false,
+                        };
+
+                    match (
+                        decoded.storage_value(b":code"),
+                        decoded.storage_value(b":heappages"),
+                    ) {
+                        (Some(code), Some(heap_pages)) => {
+                            code.map(|(v, _)| v) == finalized_storage_code.as_deref()
+                                && heap_pages.map(|(v, _)| v)
+                                    == finalized_storage_heap_pages.as_deref()
+                        }
+                        _ => false,
+                    }
+                });
+
                 debug_assert!(self.known_finalized_runtime.is_none());
                 self.known_finalized_runtime = Some(FinalizedBlockRuntime {
                     virtual_machine: finalized_block_runtime,
                     storage_code: finalized_storage_code,
                     storage_heap_pages: finalized_storage_heap_pages,
+                    storage_code_proof,
                 });
 
                 self.network_up_to_date_finalized = false;
@@ -0,0 +1,436 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Telemetry integration.
+//!
+//! The [`run`] function connects to the telemetry servers passed through the configuration and
+//! regularly reports to them the state of the chain as seen by the [`sync_service::SyncService`].
+//! See the [`smoldot::telemetry`] module for more information.
+//!
+//! Each telemetry server is handled by a separate background task that connects to it through
+//! [`Platform::connect`]. In case of disconnection, the task tries to reconnect after a delay.
+//! Messages that are generated while the connection to a server isn't established, or while the
+//! connection is too slow, are silently discarded.
+
+use crate::{
+    platform::{Platform, PlatformConnection, ReadBuffer},
+    sync_service,
+};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::time::Duration;
+use futures::{channel::mpsc, prelude::*};
+use hashbrown::HashMap;
+use smoldot::{
+    header,
+    libp2p::{connection, peer_id::PeerId},
+    telemetry,
+};
+
+/// Identifier of the node passed when encoding telemetry messages. Since only one node is
+/// reported per connection, the value doesn't matter.
+const NODE_ID: u64 = 1;
+
+/// Delay between two reports of the state of the chain.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Configuration for [`run`].
+pub struct Config<TPlat: Platform> {
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Closure that spawns background tasks.
+    pub tasks_executor: Box<dyn FnMut(String, future::BoxFuture<'static, ()>) + Send>,
+
+    /// List of telemetry servers to report to.
+    pub endpoints: Vec<telemetry::TelemetryEndpoint>,
+
+    /// Service whose state is reported.
+    pub sync_service: Arc<sync_service::SyncService<TPlat>>,
+
+    /// Number of bytes of the block number in the networking protocol and in block headers.
+    pub block_number_bytes: usize,
+
+    /// Name of the chain, as found in the chain specification.
+    pub chain_name: String,
+
+    /// Name of the node reported to the telemetry servers.
+    pub node_name: String,
+
+    /// Name of the client, reported to the telemetry servers as the implementation.
+    pub system_name: String,
+
+    /// Version of the client.
+    pub system_version: String,
+
+    /// Network identity of the node.
+    pub network_identity: PeerId,
+
+    /// Hash of the genesis block of the chain.
+    pub genesis_block_hash: [u8; 32],
+
+    /// Time when the chain has been added, as returned by [`Platform::now_from_unix_epoch`].
+    pub startup_time: Duration,
+}
+
+/// Reports the state of the chain to the telemetry servers. Never ends.
+///
+/// The background tasks connected to the telemetry servers end when the future returned by this
+/// function is destroyed.
+pub async fn run<TPlat: Platform>(mut config: Config<TPlat>) {
+    let log_target = format!("telemetry-{}", config.log_name);
+
+    let system_connected =
+        telemetry::TelemetryMessage::SystemConnected(telemetry::SystemConnected {
+            chain: &config.chain_name,
+            name: &config.node_name,
+            implementation: &config.system_name,
+            version: &config.system_version,
+            validator: None,
+            network_id: &config.network_identity.to_string(),
+            genesis_hash: &config.genesis_block_hash,
+            startup_time_ms: u64::try_from(config.startup_time.as_millis()).ok(),
+            target_os: target_os(),
+            target_arch: target_arch(),
+            target_env: target_env(),
+        })
+        .encode(NODE_ID);
+
+    let mut endpoints = Vec::with_capacity(config.endpoints.len());
+    for endpoint in config.endpoints {
+        // The size of the channel is arbitrary. Messages are discarded if it is full.
+        let (tx, rx) = mpsc::channel(16);
+        (config.tasks_executor)(
+            log_target.clone(),
+            Box::pin(run_endpoint::<TPlat>(
+                log_target.clone(),
+                endpoint.clone(),
+                system_connected.clone(),
+                rx,
+            )),
+        );
+        endpoints.push((endpoint, tx));
+    }
+
+    // Sends the given message to all the telemetry servers whose verbosity is high enough.
+    let mut send = |message: telemetry::TelemetryMessage| {
+        let mut encoded = None;
+        for (endpoint, sender) in &mut endpoints {
+            if !endpoint.accepts(&message) {
+                continue;
+            }
+
+            let encoded = encoded.get_or_insert_with(|| message.encode(NODE_ID));
+            let _ = sender.try_send(encoded.clone());
+        }
+    };
+
+    let mut reported_best = None;
+    let mut reported_finalized = None;
+
+    // Outer loop. Subscribes to the sync service. Entered again if the subscription is closed.
+    loop {
+        let mut subscription = config.sync_service.subscribe_all(32, false).await;
+
+        let mut finalized = (
+            header::hash_from_scale_encoded_header(
+                &subscription.finalized_block_scale_encoded_header,
+            ),
+            header::decode(
+                &subscription.finalized_block_scale_encoded_header,
+                config.block_number_bytes,
+            )
+            .unwrap()
+            .number,
+        );
+        let mut best = finalized;
+
+        // Numbers of the non-finalized blocks, indexed by hash.
+        let mut non_finalized_blocks =
+            HashMap::<[u8; 32], u64, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+                subscription.non_finalized_blocks_ancestry_order.len(),
+                Default::default(),
+            );
+        for block in subscription.non_finalized_blocks_ancestry_order {
+            let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+            let number = header::decode(&block.scale_encoded_header, config.block_number_bytes)
+                .unwrap()
+                .number;
+            non_finalized_blocks.insert(hash, number);
+            if block.is_new_best {
+                best = (hash, number);
+            }
+        }
+
+        let mut next_report = TPlat::sleep(Duration::new(0, 0));
+
+        // Inner loop. Process incoming events.
+        loop {
+            match future::select(subscription.new_blocks.next(), &mut next_report).await {
+                future::Either::Left((None, _)) => break,
+                future::Either::Left((Some(sync_service::Notification::Block(block)), _)) => {
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    let number =
+                        header::decode(&block.scale_encoded_header, config.block_number_bytes)
+                            .unwrap()
+                            .number;
+                    non_finalized_blocks.insert(hash, number);
+                    if block.is_new_best {
+                        best = (hash, number);
+                    }
+                }
+                future::Either::Left((
+                    Some(sync_service::Notification::BestBlockChanged { hash }),
+                    _,
+                )) => {
+                    best = if hash == finalized.0 {
+                        finalized
+                    } else {
+                        (hash, *non_finalized_blocks.get(&hash).unwrap())
+                    };
+                }
+                future::Either::Left((
+                    Some(sync_service::Notification::Finalized {
+                        hash,
+                        best_block_hash,
+                    }),
+                    _,
+                )) => {
+                    finalized = (hash, *non_finalized_blocks.get(&hash).unwrap());
+                    best = if best_block_hash == finalized.0 {
+                        finalized
+                    } else {
+                        (
+                            best_block_hash,
+                            *non_finalized_blocks.get(&best_block_hash).unwrap(),
+                        )
+                    };
+                    // Blocks that aren't descendants of the new finalized block and whose number
+                    // is above it are only removed once the finalized block reaches their number.
+                    non_finalized_blocks.retain(|_, number| *number > finalized.1);
+                }
+                future::Either::Right(((), _)) => {
+                    next_report = TPlat::sleep(REPORT_INTERVAL);
+
+                    let num_peers = config.sync_service.syncing_peers().await.len();
+                    send(telemetry::TelemetryMessage::SystemInterval(
+                        telemetry::SystemInterval {
+                            num_peers: u64::try_from(num_peers).unwrap_or(u64::MAX),
+                            num_transactions: None,
+                            bandwidth_upload: None,
+                            bandwidth_download: None,
+                            best_block_hash: &best.0,
+                            best_block_number: best.1,
+                            finalized_block_hash: &finalized.0,
+                            finalized_block_number: finalized.1,
+                        },
+                    ));
+
+                    // Blocks are reported to the telemetry servers only at each tick rather than
+                    // every time they change, in order to limit the bandwidth usage while
+                    // syncing.
+                    if reported_best != Some(best.0) {
+                        reported_best = Some(best.0);
+                        send(telemetry::TelemetryMessage::BlockImport(
+                            telemetry::BlockImport {
+                                block_hash: &best.0,
+                                block_number: best.1,
+                            },
+                        ));
+                    }
+                    if reported_finalized != Some(finalized.0) {
+                        reported_finalized = Some(finalized.0);
+                        send(telemetry::TelemetryMessage::NotifyFinalized(
+                            telemetry::NotifyFinalized {
+                                block_hash: &finalized.0,
+                                block_number: finalized.1,
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Background task connected to a specific telemetry server. Ends when the future returned by
+/// [`run`] is destroyed.
+async fn run_endpoint<TPlat: Platform>(
+    log_target: String,
+    endpoint: telemetry::TelemetryEndpoint,
+    system_connected: String,
+    mut messages: mpsc::Receiver<String>,
+) {
+    let address = endpoint.to_multiaddr();
+
+    // The certificate is only used by QUIC connections and is irrelevant here. A random one is
+    // generated in order to satisfy the API of the platform.
+    let tls_certificate = {
+        let private_key: [u8; 32] = rand::random();
+        connection::tls::generate_certificate(&private_key, &rand::random(), rand::random())
+    };
+
+    loop {
+        let connect = TPlat::connect(&address, &tls_certificate);
+        let timeout = TPlat::sleep(Duration::from_secs(20));
+
+        match future::select(connect, timeout).await {
+            future::Either::Left((
+                Ok(PlatformConnection::SingleStreamMultistreamSelectNoiseYamux(mut stream)),
+                _,
+            )) => {
+                log::debug!(target: &log_target, "Connected to {}", address);
+
+                // Discard the messages that have been queued while disconnected, as they are
+                // out of date.
+                while let Ok(Some(_)) = messages.try_next() {}
+
+                let mut next_message = Some(system_connected.clone());
+                loop {
+                    // Data sent by the server is ignored.
+                    match TPlat::read_buffer(&mut stream) {
+                        ReadBuffer::Open(buffer) => {
+                            let len = buffer.len();
+                            if len != 0 {
+                                TPlat::advance_read_cursor(&mut stream, len);
+                            }
+                        }
+                        ReadBuffer::Closed | ReadBuffer::Reset => {
+                            log::debug!(target: &log_target, "Disconnected from {}", address);
+                            break;
+                        }
+                    }
+
+                    // Each call to `send` translates into one WebSocket message. The message is
+                    // kept until enough space is available in the write buffer.
+                    if let Some(message) = next_message.as_ref() {
+                        if TPlat::writable_bytes(&mut stream) >= message.len() {
+                            TPlat::send(&mut stream, message.as_bytes());
+                            next_message = None;
+                        }
+                    }
+
+                    if next_message.is_some() {
+                        TPlat::update_stream(&mut stream).await;
+                        continue;
+                    }
+
+                    match future::select(messages.next(), TPlat::update_stream(&mut stream)).await {
+                        future::Either::Left((Some(message), _)) => next_message = Some(message),
+                        future::Either::Left((None, _)) => return,
+                        future::Either::Right(((), _)) => {}
+                    }
+                }
+            }
+            future::Either::Left((Ok(_), _)) => {
+                // Telemetry servers are always reached through a single-stream connection.
+                log::debug!(
+                    target: &log_target,
+                    "Unexpected type of connection to {}",
+                    address
+                );
+            }
+            future::Either::Left((Err(err), _)) if err.is_bad_addr => {
+                // The platform doesn't support connecting to this server. There is no point in
+                // trying again.
+                log::debug!(
+                    target: &log_target,
+                    "Unsupported telemetry address {}: {}",
+                    address,
+                    err.message
+                );
+                return;
+            }
+            future::Either::Left((Err(err), _)) => {
+                log::debug!(
+                    target: &log_target,
+                    "Failed to connect to {}: {}",
+                    address,
+                    err.message
+                );
+            }
+            future::Either::Right(((), _)) => {
+                log::debug!(target: &log_target, "Timeout when connecting to {}", address);
+            }
+        }
+
+        // Wait a bit before trying to reconnect, while still discarding the messages that are
+        // being sent. The task ends if the service has been destroyed.
+        let mut reconnect = TPlat::sleep(Duration::from_secs(10));
+        loop {
+            match future::select(&mut reconnect, messages.next()).await {
+                future::Either::Left(((), _)) => break,
+                future::Either::Right((Some(_), _)) => {}
+                future::Either::Right((None, _)) => return,
+            }
+        }
+    }
+}
+
+/// Returns the name of the operating system the client is compiled for, in the same format as
+/// `std::env::consts::OS`, or an empty string if unknown.
+fn target_os() -> &'static str {
+    if cfg!(target_os = "linux") {
+        "linux"
+    } else if cfg!(target_os = "macos") {
+        "macos"
+    } else if cfg!(target_os = "windows") {
+        "windows"
+    } else if cfg!(target_os = "android") {
+        "android"
+    } else if cfg!(target_os = "ios") {
+        "ios"
+    } else if cfg!(target_os = "freebsd") {
+        "freebsd"
+    } else {
+        ""
+    }
+}
+
+/// Returns the name of the CPU architecture the client is compiled for, in the same format as
+/// `std::env::consts::ARCH`, or an empty string if unknown.
+fn target_arch() -> &'static str {
+    if cfg!(target_arch = "x86_64") {
+        "x86_64"
+    } else if cfg!(target_arch = "x86") {
+        "x86"
+    } else if cfg!(target_arch = "aarch64") {
+        "aarch64"
+    } else if cfg!(target_arch = "arm") {
+        "arm"
+    } else if cfg!(target_arch = "wasm32") {
+        "wasm32"
+    } else {
+        ""
+    }
+}
+
+/// Returns the environment the client is compiled for, or an empty string if unknown.
+fn target_env() -> &'static str {
+    if cfg!(target_env = "gnu") {
+        "gnu"
+    } else if cfg!(target_env = "musl") {
+        "musl"
+    } else if cfg!(target_env = "msvc") {
+        "msvc"
+    } else {
+        ""
+    }
+}
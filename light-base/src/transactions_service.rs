@@ -1141,8 +1141,8 @@ async fn validate_transaction<TPlat: Platform>(
     );
 
     let block_hash = *runtime_lock.block_hash();
-    let (runtime_call_lock, runtime) = runtime_lock
-        .start(
+    let outcome = runtime_lock
+        .run(
             validate::VALIDATION_FUNCTION_NAME,
             // TODO: don't hardcode v3 but determine parameters dynamically from the runtime
             validate::validate_transaction_runtime_parameters_v3(
@@ -1150,90 +1150,99 @@ async fn validate_transaction<TPlat: Platform>(
                 source,
                 &block_hash,
             ),
-            1,
+            3,
             Duration::from_secs(8),
             NonZeroU32::new(1).unwrap(),
-        )
-        .await
-        .map_err(ValidateTransactionError::Call)
-        .map_err(InvalidOrError::ValidateError)
-        .map_err(ValidationError::InvalidOrError)?;
-
-    let mut validation_in_progress = validate::validate_transaction(validate::Config {
-        runtime,
-        scale_encoded_header: block_scale_encoded_header,
-        block_number_bytes: relay_chain_sync.block_number_bytes(),
-        scale_encoded_transaction: iter::once(scale_encoded_transaction),
-        source,
-        max_log_level: 0,
-    });
-
-    loop {
-        match validation_in_progress {
-            validate::Query::Finished {
-                result: Ok(Ok(success)),
-                virtual_machine,
-            } => {
-                runtime_call_lock.unlock(virtual_machine);
-                break Ok(success);
-            }
-            validate::Query::Finished {
-                result: Ok(Err(invalid)),
-                virtual_machine,
-            } => {
-                runtime_call_lock.unlock(virtual_machine);
-                break Err(ValidationError::InvalidOrError(InvalidOrError::Invalid(
-                    invalid,
-                )));
-            }
-            validate::Query::Finished {
-                result: Err(error),
-                virtual_machine,
-            } => {
-                runtime_call_lock.unlock(virtual_machine);
-                break Err(ValidationError::InvalidOrError(
-                    InvalidOrError::ValidateError(ValidateTransactionError::Validation(error)),
-                ));
-            }
-            validate::Query::StorageGet(get) => {
-                let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
-                let storage_value = match storage_value {
-                    Ok(v) => v,
-                    Err(err) => {
-                        runtime_call_lock.unlock(validate::Query::StorageGet(get).into_prototype());
-                        return Err(ValidationError::InvalidOrError(
-                            InvalidOrError::ValidateError(ValidateTransactionError::Call(err)),
-                        ));
-                    }
-                };
-                validation_in_progress =
-                    get.inject_value(storage_value.map(|(val, vers)| (iter::once(val), vers)));
-            }
-            validate::Query::NextKey(nk) => {
-                // TODO:
-                runtime_call_lock.unlock(validate::Query::NextKey(nk).into_prototype());
-                break Err(ValidationError::InvalidOrError(
-                    InvalidOrError::ValidateError(ValidateTransactionError::NextKeyForbidden),
-                ));
-            }
-            validate::Query::PrefixKeys(prefix) => {
-                // TODO: lots of allocations because I couldn't figure how to make this annoying borrow checker happy
-                let rq_prefix = prefix.prefix().as_ref().to_owned();
-                let result = runtime_call_lock
-                    .storage_prefix_keys_ordered(&rq_prefix)
-                    .map(|i| i.map(|v| v.as_ref().to_owned()).collect::<Vec<_>>());
-                match result {
-                    Ok(v) => validation_in_progress = prefix.inject_keys_ordered(v.into_iter()),
-                    Err(err) => {
-                        runtime_call_lock
-                            .unlock(validate::Query::PrefixKeys(prefix).into_prototype());
-                        return Err(ValidationError::InvalidOrError(
-                            InvalidOrError::ValidateError(ValidateTransactionError::Call(err)),
-                        ));
+            |runtime_call_lock, runtime| {
+                let mut validation_in_progress = validate::validate_transaction(validate::Config {
+                    runtime,
+                    scale_encoded_header: block_scale_encoded_header,
+                    block_number_bytes: relay_chain_sync.block_number_bytes(),
+                    scale_encoded_transaction: iter::once(scale_encoded_transaction.clone()),
+                    source,
+                    max_log_level: 0,
+                });
+
+                loop {
+                    match validation_in_progress {
+                        validate::Query::Finished {
+                            result: Ok(result),
+                            virtual_machine,
+                        } => return (virtual_machine, Ok(result)),
+                        validate::Query::Finished {
+                            result: Err(error),
+                            virtual_machine,
+                        } => {
+                            return (
+                                virtual_machine,
+                                Err(runtime_service::RunAttemptError::Other(
+                                    ValidateTransactionError::Validation(error),
+                                )),
+                            )
+                        }
+                        validate::Query::StorageGet(get) => {
+                            let storage_value = runtime_call_lock.storage_entry(get.key().as_ref());
+                            let storage_value = match storage_value {
+                                Ok(v) => v,
+                                Err(_) => {
+                                    let key = get.key().as_ref().to_vec();
+                                    return (
+                                        validate::Query::StorageGet(get).into_prototype(),
+                                        Err(runtime_service::RunAttemptError::MissingStorageValue(
+                                            key,
+                                        )),
+                                    );
+                                }
+                            };
+                            validation_in_progress = get.inject_value(
+                                storage_value.map(|(val, vers)| (iter::once(val), vers)),
+                            );
+                        }
+                        validate::Query::NextKey(nk) => {
+                            // TODO:
+                            return (
+                                validate::Query::NextKey(nk).into_prototype(),
+                                Err(runtime_service::RunAttemptError::Other(
+                                    ValidateTransactionError::NextKeyForbidden,
+                                )),
+                            );
+                        }
+                        validate::Query::PrefixKeys(prefix) => {
+                            // TODO: lots of allocations because I couldn't figure how to make this annoying borrow checker happy
+                            let rq_prefix = prefix.prefix().as_ref().to_owned();
+                            let result = runtime_call_lock
+                                .storage_prefix_keys_ordered(&rq_prefix)
+                                .map(|i| i.map(|v| v.as_ref().to_owned()).collect::<Vec<_>>());
+                            match result {
+                                Ok(v) => {
+                                    validation_in_progress =
+                                        prefix.inject_keys_ordered(v.into_iter())
+                                }
+                                Err(_) => {
+                                    return (
+                                        validate::Query::PrefixKeys(prefix).into_prototype(),
+                                        Err(runtime_service::RunAttemptError::MissingProofEntry),
+                                    )
+                                }
+                            }
+                        }
                     }
                 }
-            }
-        }
+            },
+        )
+        .await;
+
+    match outcome {
+        Ok(Ok(success)) => Ok(success),
+        Ok(Err(invalid)) => Err(ValidationError::InvalidOrError(InvalidOrError::Invalid(
+            invalid,
+        ))),
+        Err(runtime_service::RunError::Call(error)) => Err(ValidationError::InvalidOrError(
+            InvalidOrError::ValidateError(ValidateTransactionError::Call(error)),
+        )),
+        Err(runtime_service::RunError::Other(error)) => Err(ValidationError::InvalidOrError(
+            InvalidOrError::ValidateError(error),
+        )),
     }
 }
 
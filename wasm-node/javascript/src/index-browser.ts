@@ -87,12 +87,16 @@ export function start(options?: ClientOptions): Client {
  function connect(config: ConnectionConfig, forbidWs: boolean, forbidNonLocalWs: boolean, forbidWss: boolean, forbidWebRTC: boolean): Connection {
   // Attempt to parse the multiaddress.
   // TODO: remove support for `/wss` in a long time (https://github.com/paritytech/smoldot/issues/1940)
-  const wsParsed = config.address.match(/^\/(ip4|ip6|dns4|dns6|dns)\/(.*?)\/tcp\/(.*?)\/(ws|wss|tls\/ws)$/);
+  const wsParsed = config.address.match(/^\/(ip4|ip6|dns4|dns6|dns)\/(.*?)\/tcp\/(.*?)\/(ws|wss|tls\/ws|x-parity-ws\/[^\/]*|x-parity-wss\/[^\/]*)$/);
 
   const webRTCParsed = config.address.match(/^\/(ip4|ip6)\/(.*?)\/udp\/(.*?)\/webrtc-direct\/certhash\/(.*?)$/);
 
   if (wsParsed != null) {
-      const proto = (wsParsed[4] == 'ws') ? 'ws' : 'wss';
+      // Addresses of telemetry servers use the `x-parity-ws` or `x-parity-wss` protocol,
+      // followed with the percent-encoded path to connect to.
+      const telemetryParsed = wsParsed[4].match(/^(x-parity-ws|x-parity-wss)\/(.*)$/);
+      const proto = (wsParsed[4] == 'ws' || (telemetryParsed != null && telemetryParsed[1] == 'x-parity-ws')) ? 'ws' : 'wss';
+      const path = (telemetryParsed != null) ? decodeURIComponent(telemetryParsed[2]) : '';
       if (
           (proto == 'ws' && forbidWs) ||
           (proto == 'ws' && wsParsed[2] != 'localhost' && wsParsed[2] != '127.0.0.1' && forbidNonLocalWs) ||
@@ -102,8 +106,8 @@ export function start(options?: ClientOptions): Client {
       }
 
       const url = (wsParsed[1] == 'ip6') ?
-          (proto + "://[" + wsParsed[2] + "]:" + wsParsed[3]) :
-          (proto + "://" + wsParsed[2] + ":" + wsParsed[3]);
+          (proto + "://[" + wsParsed[2] + "]:" + wsParsed[3] + path) :
+          (proto + "://" + wsParsed[2] + ":" + wsParsed[3] + path);
 
       const connection = new WebSocket(url);
       connection.binaryType = 'arraybuffer';
@@ -253,6 +253,7 @@ fn add_chain(
             specification: str::from_utf8(&chain_spec).unwrap(),
             database_content: str::from_utf8(&database_content).unwrap(),
             disable_json_rpc: json_rpc_running == 0,
+            telemetry_node_name: None,
             potential_relay_chains: potential_relay_chains.into_iter(),
         }) {
         Ok(c) => c,
@@ -17,7 +17,7 @@
 
 use crate::{bindings, timers::Delay};
 
-use smoldot::libp2p::multihash;
+use smoldot::libp2p::{connection::tls, multihash};
 use smoldot_light::platform::{ConnectError, PlatformSubstreamDirection};
 
 use core::{mem, pin, slice, str, task, time::Duration};
@@ -100,7 +100,7 @@ impl smoldot_light::platform::Platform for Platform {
         }
     }
 
-    fn connect(url: &str) -> Self::ConnectFuture {
+    fn connect(url: &str, _: &tls::GeneratedCertificate) -> Self::ConnectFuture {
         let mut lock = STATE.try_lock().unwrap();
 
         let connection_id = lock.next_connection_id;